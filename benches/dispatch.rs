@@ -0,0 +1,135 @@
+//! Dispatch overhead of the handler registry
+//!
+//! Run with `cargo bench --bench dispatch`. Measures:
+//!
+//! - sequential dispatch of a delivery to a no-op handler, with handlers
+//!   registered for many other event types
+//! - concurrent dispatch from several tasks
+//! - how long a runtime registration takes while slow deliveries are in
+//!   flight
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use octocrab::models::webhook_events::WebhookEvent;
+use octofer::dispatch::Dispatcher;
+use octofer::Context;
+
+const DISPATCHES: u32 = 100_000;
+const TASKS: u32 = 8;
+const SLOW_DELIVERIES: usize = 64;
+const SLOW_HANDLER: Duration = Duration::from_millis(50);
+
+fn ping() -> WebhookEvent {
+    WebhookEvent::try_from_header_and_body(
+        "ping",
+        r#"{"zen": "Keep it logically awesome.", "hook_id": 1}"#,
+    )
+    .expect("valid ping payload")
+}
+
+async fn noop(_context: Context, _extra: Arc<()>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+async fn dispatcher() -> Dispatcher {
+    let dispatcher = Dispatcher::new(None);
+    for event in [
+        "issues",
+        "issue_comment",
+        "pull_request",
+        "push",
+        "check_run",
+        "star",
+    ] {
+        for _ in 0..8 {
+            dispatcher.on(event, noop, Arc::new(())).await;
+        }
+    }
+    dispatcher.on("ping", noop, Arc::new(())).await;
+    dispatcher
+}
+
+async fn sequential() -> Duration {
+    let dispatcher = dispatcher().await;
+    let event = ping();
+    let started = Instant::now();
+    for _ in 0..DISPATCHES {
+        dispatcher
+            .dispatch(dispatcher.context(event.clone()))
+            .await
+            .unwrap();
+    }
+    started.elapsed() / DISPATCHES
+}
+
+async fn concurrent() -> Duration {
+    let dispatcher = dispatcher().await;
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let dispatcher = dispatcher.clone();
+            tokio::spawn(async move {
+                let event = ping();
+                for _ in 0..DISPATCHES / TASKS {
+                    dispatcher
+                        .dispatch(dispatcher.context(event.clone()))
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    started.elapsed() / DISPATCHES
+}
+
+async fn registration_under_load() -> Duration {
+    let dispatcher = Dispatcher::new(None);
+    dispatcher
+        .on(
+            "ping",
+            |_context: Context, _extra: Arc<()>| async {
+                tokio::time::sleep(SLOW_HANDLER).await;
+                Ok(())
+            },
+            Arc::new(()),
+        )
+        .await;
+
+    let deliveries: Vec<_> = (0..SLOW_DELIVERIES)
+        .map(|_| {
+            let dispatcher = dispatcher.clone();
+            tokio::spawn(async move { dispatcher.dispatch(dispatcher.context(ping())).await })
+        })
+        .collect();
+    // Let the deliveries reach their handler
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    let started = Instant::now();
+    dispatcher.on("star", noop, Arc::new(())).await;
+    let registration = started.elapsed();
+
+    for delivery in deliveries {
+        delivery.await.unwrap().unwrap();
+    }
+    registration
+}
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 8)]
+async fn main() {
+    println!(
+        "sequential dispatch:        {:?} per delivery",
+        sequential().await
+    );
+    println!(
+        "concurrent dispatch ({TASKS} tasks): {:?} per delivery",
+        concurrent().await
+    );
+    println!(
+        "registration while {SLOW_DELIVERIES} deliveries run: {:?}",
+        registration_under_load().await
+    );
+}
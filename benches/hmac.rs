@@ -0,0 +1,52 @@
+//! Webhook signature verification throughput of the HMAC backends
+//!
+//! Run with `cargo bench --bench hmac`, and with `--features ring` to include
+//! the `ring` backend. Each backend verifies signed payloads of 1KB, 100KB
+//! and 5MB, the size of large `push` deliveries.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use octofer::github::middlewares::HmacBackend;
+use octofer::webhook::forward::sign_payload;
+
+const SECRET: &str = "benchmark-secret";
+const SIZES: [(&str, usize); 3] = [
+    ("1KB", 1024),
+    ("100KB", 100 * 1024),
+    ("5MB", 5 * 1024 * 1024),
+];
+/// Bytes verified per backend and payload size
+const BYTES_PER_RUN: usize = 256 * 1024 * 1024;
+
+fn payload(size: usize) -> Vec<u8> {
+    // JSON-like bytes, the content does not change the cost of the HMAC
+    br#"{"ref":"refs/heads/main","commits":[]}"#.iter().copied().cycle().take(size).collect()
+}
+
+fn verify(backend: HmacBackend, payload: &[u8], signature: &str) -> Duration {
+    let iterations = (BYTES_PER_RUN / payload.len()).max(1);
+    let started = Instant::now();
+    for _ in 0..iterations {
+        backend
+            .verify(black_box(signature), black_box(payload), SECRET)
+            .unwrap();
+    }
+    started.elapsed() / iterations as u32
+}
+
+fn main() {
+    for (name, size) in SIZES {
+        let payload = payload(size);
+        let signature = sign_payload(&payload, SECRET);
+        for &backend in HmacBackend::AVAILABLE {
+            let elapsed = verify(backend, &payload, &signature);
+            let throughput = size as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+            println!(
+                "{name:>5} {:<12} {:?} per delivery, {throughput:.0} MiB/s",
+                format!("{backend:?}"),
+                elapsed
+            );
+        }
+    }
+}
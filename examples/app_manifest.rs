@@ -0,0 +1,51 @@
+//! Example app printing the GitHub App manifest its handlers need
+//!
+//! Run with `manifest` to print the `default_events` and
+//! `default_permissions` to register the app with, instead of starting the
+//! server:
+//!
+//! ```bash
+//! cargo run --example app_manifest -- manifest
+//! ```
+
+use octofer::prelude::*;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("manifest") {
+        let mut app = Octofer::new_default();
+        register(&mut app).await;
+        let manifest = app.required_manifest().await;
+        for unresolved in &manifest.unresolved {
+            eprintln!("warning: unknown requirement {}", unresolved);
+        }
+        println!("{}", serde_json::to_string_pretty(&manifest)?);
+        return Ok(());
+    }
+
+    let config = Config::from_env()?;
+    config.init_logging();
+    let mut app = Octofer::new(config).await?;
+    register(&mut app).await;
+    app.start().await?;
+    Ok(())
+}
+
+async fn register(app: &mut Octofer) {
+    app.on_issue(
+        |context: Context, _extra: Arc<()>| async move {
+            context.queue_comment("triage", "Thanks, we'll take a look!");
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await
+    .requires_permissions(&["queue_comment"]);
+
+    app.on_pull_request(
+        |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+        Arc::new(()),
+    )
+    .await
+    .requires_permissions(&["changed_files", "create_check_run"]);
+}
@@ -0,0 +1,89 @@
+//! Example bot approving patch-level Dependabot bumps
+//!
+//! Dependabot titles its pull requests `Bump serde from 1.0.1 to 1.0.2`,
+//! optionally followed by `in /path`. The bot parses the version delta from
+//! the title, approves bumps that only change the patch version, and asks a
+//! maintainer team to review the others.
+
+use octofer::prelude::*;
+
+/// Login of Dependabot
+const DEPENDABOT: &str = "dependabot[bot]";
+
+/// Team asked to review bumps that are not patch-level
+const REVIEWERS_TEAM: &str = "maintainers";
+
+/// Parse the versions of a `Bump <dependency> from <old> to <new>` title
+fn parse_bump(title: &str) -> Option<(&str, &str)> {
+    let rest = title.strip_prefix("Bump ")?;
+    let (_, versions) = rest.split_once(" from ")?;
+    let (from, to) = versions.split_once(" to ")?;
+    let to = to.split_whitespace().next()?;
+    Some((from.trim(), to))
+}
+
+/// Split a version into its numeric components, ignoring a `v` prefix
+fn components(version: &str) -> Option<Vec<u64>> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect()
+}
+
+/// Whether going from `from` to `to` only changes the patch version
+fn is_patch_bump(from: &str, to: &str) -> bool {
+    match (components(from), components(to)) {
+        (Some(from), Some(to)) if from.len() == 3 && to.len() == 3 => {
+            from[..2] == to[..2] && to[2] > from[2]
+        }
+        _ => false,
+    }
+}
+
+async fn review_bump(context: Context, _extra: Arc<()>) -> anyhow::Result<()> {
+    let Some(WebhookEventPayload::PullRequest(payload)) =
+        context.event().as_ref().map(|e| &e.specific)
+    else {
+        return Ok(());
+    };
+    if context.payload()["action"] != "opened" {
+        return Ok(());
+    }
+    let pull_request = &payload.pull_request;
+    if pull_request.user.as_ref().map(|user| user.login.as_str()) != Some(DEPENDABOT) {
+        return Ok(());
+    }
+    let Some((from, to)) = pull_request.title.as_deref().and_then(parse_bump) else {
+        return Ok(());
+    };
+
+    if is_patch_bump(from, to) {
+        context
+            .approve(Some(&format!("Patch-level bump from {from} to {to}")))
+            .await?;
+        println!("Approved #{}", payload.number);
+    } else {
+        context.request_reviewers(&[], &[REVIEWERS_TEAM]).await?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().unwrap_or_default();
+    config.init_logging();
+
+    let mut app = Octofer::new(config)
+        .await
+        .unwrap_or_else(|_| Octofer::new_default());
+
+    app.on_pull_request(review_bump, Arc::new(()))
+        .await
+        .named("dependabot-auto-approve")
+        // Dependabot is a bot; make sure bot senders are not filtered out
+        .include_bots(true);
+
+    app.start().await?;
+    Ok(())
+}
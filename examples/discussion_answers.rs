@@ -0,0 +1,39 @@
+//! Example bot that marks accepted answers in GitHub Discussions
+//!
+//! When the author of a discussion replies `/answer` to a comment, the bot
+//! marks that comment as the discussion's answer.
+
+use octofer::prelude::*;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().unwrap_or_default();
+    config.init_logging();
+
+    let mut app = Octofer::new(config)
+        .await
+        .unwrap_or_else(|_| Octofer::new_default());
+
+    app.on_discussion_comment(
+        |context: Context, _extra: Arc<()>| async move {
+            let (Some(discussion), Some(comment)) =
+                (context.discussion(), context.discussion_comment())
+            else {
+                return Ok(());
+            };
+
+            let is_op = comment.user.login == discussion.user.login;
+            if is_op && comment.parent_id.is_some() && comment.body.trim() == "/answer" {
+                context.mark_comment_as_answer().await?;
+                println!("Marked answer on discussion #{}", discussion.number);
+            }
+
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await;
+
+    app.start().await?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Example triage bot for duplicate issues
+//!
+//! - When an issue is opened, the bot searches for similar issues by title and
+//!   lists them in a comment.
+//! - When a collaborator with write access comments `/duplicate #123`, the bot
+//!   closes the issue as a duplicate of #123.
+
+use octofer::helpers::issues::SearchRateLimited;
+use octofer::prelude::*;
+
+/// Parse the issue number of a `/duplicate #123` command
+fn parse_duplicate_command(body: &str) -> Option<u64> {
+    let rest = body.trim().strip_prefix("/duplicate")?;
+    rest.trim().trim_start_matches('#').parse().ok()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().unwrap_or_default();
+    config.init_logging();
+
+    let mut app = Octofer::new(config)
+        .await
+        .unwrap_or_else(|_| Octofer::new_default());
+
+    app.on_issue(
+        |context: Context, _extra: Arc<()>| async move {
+            let Some(WebhookEventPayload::Issues(payload)) =
+                context.event().as_ref().map(|e| &e.specific)
+            else {
+                return Ok(());
+            };
+            if context.payload()["action"] != "opened" {
+                return Ok(());
+            }
+
+            let similar = match context.search_similar_issues(&payload.issue.title, 3).await {
+                Ok(similar) => similar,
+                // Searching is best effort; don't fail the delivery
+                Err(e) if e.is::<SearchRateLimited>() => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if similar.is_empty() {
+                return Ok(());
+            }
+
+            let list = similar
+                .iter()
+                .map(|issue| format!("- #{} {}", issue.number, issue.title))
+                .collect::<Vec<_>>()
+                .join("\n");
+            context
+                .upsert_comment(
+                    "similar-issues",
+                    &format!("This issue might be a duplicate of:\n\n{list}"),
+                )
+                .await?;
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await;
+
+    app.on_issue_comment(
+        |context: Context, _extra: Arc<()>| async move {
+            let Some(WebhookEventPayload::IssueComment(payload)) =
+                context.event().as_ref().map(|e| &e.specific)
+            else {
+                return Ok(());
+            };
+            let Some(original) = payload
+                .comment
+                .body
+                .as_deref()
+                .and_then(parse_duplicate_command)
+            else {
+                return Ok(());
+            };
+
+            if !context.sender_can_write().await? {
+                println!("Ignoring /duplicate from a user without write access");
+                return Ok(());
+            }
+
+            context.close_as_duplicate(original, None).await?;
+            println!(
+                "Closed #{} as duplicate of #{}",
+                payload.issue.number, original
+            );
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await;
+
+    app.start().await?;
+    Ok(())
+}
@@ -2,8 +2,7 @@
 //!
 //! This example shows how to register handlers for different types of GitHub webhook events.
 
-use octofer::{Config, Context, Octofer};
-use std::sync::Arc;
+use octofer::prelude::*;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -0,0 +1,73 @@
+//! Example CI bot reporting the same check for pull requests and merge queues
+//!
+//! Required status checks must pass both on the pull request and on the merge
+//! group GitHub creates when the pull request enters the merge queue. The same
+//! handler is registered for `pull_request` and `merge_group` events; the
+//! check run lands on the right commit in both cases because
+//! `Context::create_check_run` uses `Context::head_sha`.
+
+use octofer::helpers::checks::CheckRunOptions;
+use octofer::octocrab::models::webhook_events::payload::MergeGroupWebhookEventAction;
+use octofer::octocrab::params::checks::{CheckRunConclusion, CheckRunStatus};
+use octofer::prelude::*;
+
+/// Name of the required check, identical for pull requests and merge groups
+const CHECK_NAME: &str = "octofer / lint";
+
+async fn report_check(context: Context, _extra: Arc<()>) -> anyhow::Result<()> {
+    // merge_group events are also sent when a group is destroyed
+    if let Some(WebhookEventPayload::MergeGroup(payload)) =
+        context.event().as_ref().map(|e| &e.specific)
+    {
+        if payload.action == MergeGroupWebhookEventAction::Destroyed {
+            return Ok(());
+        }
+    }
+
+    let summary = match context.merge_group() {
+        Some(group) => format!(
+            "Merge queue run for {} (pull request #{})",
+            group.base_ref,
+            group
+                .pull_request_number()
+                .map_or_else(|| "?".to_string(), |n| n.to_string())
+        ),
+        None => "Pull request run".to_string(),
+    };
+
+    let check_run = context
+        .create_check_run(
+            CHECK_NAME,
+            CheckRunOptions {
+                status: Some(CheckRunStatus::Completed),
+                conclusion: Some(CheckRunConclusion::Success),
+                title: Some("Lint passed".to_string()),
+                summary: Some(summary),
+                ..Default::default()
+            },
+        )
+        .await?;
+    println!(
+        "Reported '{}' on {} (merge queue: {})",
+        CHECK_NAME,
+        check_run.head_sha,
+        context.is_merge_queue_event()
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().unwrap_or_default();
+    config.init_logging();
+
+    let mut app = Octofer::new(config)
+        .await
+        .unwrap_or_else(|_| Octofer::new_default());
+
+    app.on_pull_request(report_check, Arc::new(())).await;
+    app.on_merge_group(report_check, Arc::new(())).await;
+
+    app.start().await?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! Skeleton of a bot provisioning access from organization membership
+//!
+//! Members added to or removed from the organization or its teams are
+//! granted or revoked access in an external system. Since deliveries can be
+//! missed, a nightly job lists the actual members and reconciles them with
+//! the members known to the external system.
+
+use octofer::helpers::membership::{MemberAction, OrgRoleFilter};
+use octofer::prelude::*;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// Organization whose members are provisioned
+const ORGANIZATION: &str = "my-org";
+
+/// Members known to the external system; a real bot would query it
+fn provisioned_members() -> BTreeSet<String> {
+    BTreeSet::new()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().unwrap_or_default();
+    config.init_logging();
+
+    let mut app = Octofer::new(config)
+        .await
+        .unwrap_or_else(|_| Octofer::new_default());
+
+    app.on_organization(
+        |context: Context, _extra: Arc<()>| async move {
+            if let Some(change) = context.org_member_change() {
+                match change.action {
+                    MemberAction::Added => println!(
+                        "grant {} access to {} for user {}",
+                        change.role, change.organization, change.login
+                    ),
+                    MemberAction::Removed => println!(
+                        "revoke access to {} for user {}",
+                        change.organization, change.login
+                    ),
+                }
+            }
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await;
+
+    app.on_membership(
+        |context: Context, _extra: Arc<()>| async move {
+            if let Some(change) = context.team_membership_change() {
+                let verb = match change.action {
+                    MemberAction::Added => "grant",
+                    MemberAction::Removed => "revoke",
+                };
+                println!(
+                    "{} team {} for user {}",
+                    verb, change.team_slug, change.login
+                );
+            }
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await;
+
+    if let Some(client) = app.dispatcher().github_client().cloned() {
+        tokio::spawn(async move {
+            let mut nightly = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+            loop {
+                nightly.tick().await;
+                let actual: BTreeSet<String> = match client
+                    .list_org_members(ORGANIZATION, OrgRoleFilter::All)
+                    .await
+                {
+                    Ok(members) => members.into_iter().collect(),
+                    Err(e) => {
+                        eprintln!("reconcile failed: {e}");
+                        continue;
+                    }
+                };
+                let expected = provisioned_members();
+                for login in actual.difference(&expected) {
+                    println!("grant access to {ORGANIZATION} for user {login}");
+                }
+                for login in expected.difference(&actual) {
+                    println!("revoke access to {ORGANIZATION} for user {login}");
+                }
+            }
+        });
+    }
+
+    app.start().await?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! Skeleton of a self-hosted runner autoscaler
+//!
+//! GitHub sends a `workflow_job` event when a job is queued, when a runner
+//! picks it up and when it completes. The autoscaler picks a runner pool from
+//! the job's labels, scales it up for queued jobs and down for completed ones.
+//! The handlers run in the high-priority lane, since jobs wait for a runner
+//! until the pool is scaled up. The number of jobs waiting for each pool is
+//! app state shared by the handlers.
+
+use std::collections::HashMap;
+
+use octofer::github::models::WorkflowJob;
+use octofer::prelude::*;
+
+/// Number of jobs waiting for a runner, by pool
+type QueuedJobs = HashMap<String, u64>;
+
+/// Runner pool serving a job: the first label besides the default ones
+fn pool(job: &WorkflowJob) -> &str {
+    job.labels
+        .iter()
+        .map(String::as_str)
+        .find(|label| !matches!(*label, "self-hosted" | "linux" | "x64"))
+        .unwrap_or("default")
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().unwrap_or_default();
+    config.init_logging();
+
+    let mut app = Octofer::new(config)
+        .await
+        .unwrap_or_else(|_| Octofer::new_default());
+    app.state_mut(QueuedJobs::new());
+
+    app.on_workflow_job_queued(
+        |context: Context, _extra: Arc<()>| async move {
+            if let Some(job) = context.workflow_job() {
+                let queued = context.state_mut::<QueuedJobs>()?;
+                let mut queued = queued.lock().await;
+                let waiting = queued.entry(pool(&job).to_string()).or_default();
+                *waiting += 1;
+                println!(
+                    "scale up pool {} for job {} ({} waiting)",
+                    pool(&job),
+                    job.id,
+                    waiting
+                );
+            }
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await
+    .high_priority();
+
+    app.on_workflow_job_in_progress(
+        |context: Context, _extra: Arc<()>| async move {
+            if let Some(job) = context.workflow_job() {
+                let queued = context.state_mut::<QueuedJobs>()?;
+                if let Some(waiting) = queued.lock().await.get_mut(pool(&job)) {
+                    *waiting = waiting.saturating_sub(1);
+                }
+                println!(
+                    "job {} picked up by {}",
+                    job.id,
+                    job.runner_name.as_deref().unwrap_or("unknown runner")
+                );
+            }
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await;
+
+    app.on_workflow_job_completed(
+        |context: Context, _extra: Arc<()>| async move {
+            if let Some(job) = context.workflow_job() {
+                println!(
+                    "scale down pool {} after job {} ({})",
+                    pool(&job),
+                    job.id,
+                    job.conclusion.as_deref().unwrap_or("unknown")
+                );
+            }
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await
+    .high_priority();
+
+    app.start().await?;
+    Ok(())
+}
@@ -1,23 +1,20 @@
 use anyhow::Result;
-use octofer::{
-    Octofer,
-    github::webhook_events::WebhookEventPayload,
-    octocrab::{self, params::issues},
-};
+use octofer::octocrab::{self, params::issues};
+use octofer::prelude::*;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let config = octofer::Config::from_env()?;
+    let config = Config::from_env()?;
     let mut app = Octofer::new(config).await?;
 
-    app.on_issue(handler, std::sync::Arc::new(())).await;
+    app.on_issue(handler, Arc::new(())).await;
 
     app.start().await?;
 
     Ok(())
 }
 
-async fn handler(ctx: octofer::Context, e: std::sync::Arc<()>) -> Result<()> {
+async fn handler(ctx: Context, _extra: Arc<()>) -> Result<()> {
     let event = if let Some(e) = &ctx.event {
         e
     } else {
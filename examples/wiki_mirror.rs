@@ -0,0 +1,34 @@
+//! Example bot that mirrors wiki pages
+//!
+//! On each `gollum` event, the bot fetches the source of the created or
+//! edited pages and prints it, where a real mirror would publish it to
+//! another documentation site.
+
+use octofer::prelude::*;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().unwrap_or_default();
+    config.init_logging();
+
+    let mut app = Octofer::new(config)
+        .await
+        .unwrap_or_else(|_| Octofer::new_default());
+
+    app.on_gollum(
+        |context: Context, _extra: Arc<()>| async move {
+            for page in context.wiki_pages() {
+                let source = context.fetch_wiki_page(&page.page_name).await?;
+                println!("--- {} ({:?}, {})", page.title, page.action, page.sha);
+                println!("{}", source);
+            }
+
+            Ok(())
+        },
+        Arc::new(()),
+    )
+    .await;
+
+    app.start().await?;
+    Ok(())
+}
@@ -0,0 +1,653 @@
+//! Archiving of webhook deliveries for replay and debugging
+//!
+//! When archiving is enabled, every verified delivery is written to a
+//! [`DeliveryArchive`] once it was processed: the GitHub headers, the raw
+//! payload, when it was received and completed, and its outcome. Deliveries
+//! are handed to a background task through a bounded channel of
+//! [`ARCHIVE_QUEUE_CAPACITY`] entries, so archiving never delays responses;
+//! when the sink cannot keep up, deliveries are dropped with a warning.
+//!
+//! [`JsonlArchive`] writes one JSON object per line to a file per day, and
+//! is enabled with the `OCTOFER_ARCHIVE_PATH` environment variable. Other
+//! sinks, e.g. S3, implement [`DeliveryArchive`] and are registered with
+//! [`Octofer::archive_deliveries`](crate::Octofer::archive_deliveries).
+//!
+//! Archived deliveries can be read back with [`read_archive`] and replayed
+//! through a [`Dispatcher`] with [`ArchivedDelivery::replay`].
+//!
+//! During development, [`FixtureRecorder`] writes every delivery as a
+//! [redacted](redact) test fixture instead, to be loaded with
+//! `octofer::testing::fixtures`. It is enabled with the
+//! `OCTOFER_RECORD_FIXTURES_DIR` environment variable, and only when the
+//! server binds to a loopback address unless `OCTOFER_RECORD_FIXTURES_FORCE`
+//! is set.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::archive::{ArchivedDelivery, DeliveryArchive};
+//! use octofer::Octofer;
+//!
+//! struct StdoutArchive;
+//!
+//! impl DeliveryArchive for StdoutArchive {
+//!     async fn store(&self, delivery: &ArchivedDelivery) -> anyhow::Result<()> {
+//!         println!("{}", serde_json::to_string(delivery)?);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # async fn example(app: Octofer) {
+//! app.archive_deliveries(StdoutArchive);
+//! # }
+//! ```
+//!
+//! Replaying an archive offline:
+//!
+//! ```rust,no_run
+//! use octofer::archive::read_archive;
+//! use octofer::dispatch::Dispatcher;
+//!
+//! # async fn example(dispatcher: Dispatcher) -> anyhow::Result<()> {
+//! for delivery in read_archive("archive/deliveries-2024-05-01.jsonl")? {
+//!     let report = delivery.replay(&dispatcher).await?;
+//!     println!("{}: {} handler(s)", report.event, report.handlers);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+use tracing::{debug, error, warn};
+
+use crate::dispatch::{DispatchReport, Dispatcher};
+use crate::tasks::{TaskOptions, TaskShutdown, TaskSupervisor};
+
+/// Maximum number of deliveries waiting to be archived
+pub const ARCHIVE_QUEUE_CAPACITY: usize = 1024;
+
+/// Request headers stored with archived deliveries
+///
+/// The signature is left out: it cannot be checked without the secret, and
+/// replayed deliveries are not verified.
+pub const ARCHIVED_HEADERS: &[&str] = &[
+    "X-GitHub-Event",
+    "X-GitHub-Delivery",
+    "X-GitHub-Hook-ID",
+    "X-GitHub-Hook-Installation-Target-ID",
+    "X-GitHub-Hook-Installation-Target-Type",
+    "User-Agent",
+];
+
+/// Payload fields whose values are replaced by [`redact`]
+///
+/// A field matches if its name is one of these, or ends with `_` followed by
+/// one of them (e.g. `access_token`).
+pub const REDACTED_FIELDS: &[&str] = &["email", "key", "password", "secret", "token"];
+
+/// Value redacted fields and email addresses are replaced with
+pub const REDACTED: &str = "<redacted>";
+
+/// File of a [`FixtureRecorder`] listing the recorded fixtures, one JSON
+/// object per line
+pub const FIXTURE_INDEX_FILE: &str = "index.jsonl";
+
+/// A processed webhook delivery, as written to a [`DeliveryArchive`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedDelivery {
+    /// Unique ID of the delivery (`X-GitHub-Delivery` header), if sent
+    pub delivery_id: Option<String>,
+    /// Event type of the delivery
+    pub event: String,
+    /// The [`ARCHIVED_HEADERS`] sent with the delivery, by lowercase name
+    pub headers: BTreeMap<String, String>,
+    /// Payload of the delivery (a string if the body is not JSON)
+    pub payload: Value,
+    /// When the delivery was received
+    pub received_at: DateTime<Utc>,
+    /// When the response was sent
+    pub completed_at: DateTime<Utc>,
+    /// HTTP status returned to GitHub
+    pub status: u16,
+    /// Errors of the handlers that failed
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+impl ArchivedDelivery {
+    /// Archive the `headers` and raw `body` of a delivery received at `received_at`
+    ///
+    /// `completed_at` is set to now.
+    pub fn new(
+        headers: &HeaderMap,
+        body: &[u8],
+        received_at: DateTime<Utc>,
+        status: u16,
+        errors: Vec<String>,
+    ) -> Self {
+        let headers: BTreeMap<String, String> = ARCHIVED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(*name)?.to_str().ok()?;
+                Some((name.to_lowercase(), value.to_string()))
+            })
+            .collect();
+        let payload = serde_json::from_slice(body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()));
+
+        Self {
+            delivery_id: headers.get("x-github-delivery").cloned(),
+            event: headers.get("x-github-event").cloned().unwrap_or_default(),
+            headers,
+            payload,
+            received_at,
+            completed_at: Utc::now(),
+            status,
+            errors,
+        }
+    }
+
+    /// Rebuild the request headers of the delivery
+    pub fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Run the handlers of `dispatcher` for the delivery again
+    ///
+    /// The signature is not verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the delivery cannot be parsed or a handler fails.
+    pub async fn replay(&self, dispatcher: &Dispatcher) -> Result<DispatchReport> {
+        let body = serde_json::to_vec(&self.payload)?;
+        let context = dispatcher.parse(&self.header_map(), &body)?;
+        debug!(
+            "Replaying delivery {} ({})",
+            self.delivery_id.as_deref().unwrap_or("without ID"),
+            self.event
+        );
+        dispatcher.dispatch(context).await
+    }
+}
+
+/// Sink for archived deliveries
+///
+/// Implementations may use `async fn`:
+///
+/// ```rust
+/// use octofer::archive::{ArchivedDelivery, DeliveryArchive};
+///
+/// struct Discard;
+///
+/// impl DeliveryArchive for Discard {
+///     async fn store(&self, _delivery: &ArchivedDelivery) -> anyhow::Result<()> {
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait DeliveryArchive: Send + Sync + 'static {
+    /// Store a delivery
+    ///
+    /// Deliveries are stored one at a time, in the order they completed.
+    /// Errors are logged and the delivery is skipped.
+    fn store(&self, delivery: &ArchivedDelivery) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Archive writing deliveries as JSON lines, to one file per day
+///
+/// Deliveries go to `deliveries-YYYY-MM-DD.jsonl` in the archive directory,
+/// by the UTC date they were received.
+pub struct JsonlArchive {
+    dir: PathBuf,
+    file: Mutex<Option<(NaiveDate, File)>>,
+}
+
+impl JsonlArchive {
+    /// Create an archive writing to `dir`, which is created if needed
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Path of the file deliveries received on `date` are written to
+    pub fn path_for(&self, date: NaiveDate) -> PathBuf {
+        self.dir
+            .join(format!("deliveries-{}.jsonl", date.format("%Y-%m-%d")))
+    }
+}
+
+impl DeliveryArchive for JsonlArchive {
+    async fn store(&self, delivery: &ArchivedDelivery) -> Result<()> {
+        let mut line = serde_json::to_vec(delivery)?;
+        line.push(b'\n');
+
+        let date = delivery.received_at.date_naive();
+        let mut file = self.file.lock().await;
+        if file.as_ref().is_none_or(|(opened, _)| *opened != date) {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let path = self.path_for(date);
+            let opened = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .map_err(|e| anyhow!("Failed to open archive {}: {}", path.display(), e))?;
+            *file = Some((date, opened));
+        }
+
+        let (_, file) = file.as_mut().expect("archive file was just opened");
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Archive writing every delivery as a redacted test fixture
+///
+/// Each delivery goes to its own `{event}.{action}.{delivery_id}.json` file
+/// in the fixture directory (`{event}.{delivery_id}.json` for events without
+/// an action), with its payload passed through [`redact`]. Every fixture is
+/// also listed in [`FIXTURE_INDEX_FILE`].
+///
+/// Meant for development only: payloads still contain logins, repository
+/// names and issue contents, so review fixtures before committing them.
+pub struct FixtureRecorder {
+    dir: PathBuf,
+    index: Mutex<()>,
+}
+
+impl FixtureRecorder {
+    /// Create a recorder writing to `dir`, which is created if needed
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            index: Mutex::new(()),
+        }
+    }
+
+    /// Name of the fixture file `delivery` is written to
+    pub fn file_name(delivery: &ArchivedDelivery) -> String {
+        let delivery_id = delivery
+            .delivery_id
+            .clone()
+            .unwrap_or_else(|| delivery.received_at.timestamp_millis().to_string());
+        let parts = [
+            Some(delivery.event.as_str()),
+            delivery.payload["action"].as_str(),
+            Some(delivery_id.as_str()),
+        ];
+        let stem: Vec<String> = parts.into_iter().flatten().map(file_name_part).collect();
+        format!("{}.json", stem.join("."))
+    }
+}
+
+/// Keep the characters of `part` that are safe in a file name
+fn file_name_part(part: &str) -> String {
+    part.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+impl DeliveryArchive for FixtureRecorder {
+    async fn store(&self, delivery: &ArchivedDelivery) -> Result<()> {
+        let mut fixture = delivery.clone();
+        redact(&mut fixture.payload);
+        fixture.errors.clear();
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let file_name = Self::file_name(&fixture);
+        let path = self.dir.join(&file_name);
+        tokio::fs::write(&path, serde_json::to_vec_pretty(&fixture)?)
+            .await
+            .map_err(|e| anyhow!("Failed to write fixture {}: {}", path.display(), e))?;
+
+        let mut line = serde_json::to_vec(&serde_json::json!({
+            "file": file_name,
+            "event": fixture.event,
+            "action": fixture.payload["action"],
+            "delivery_id": fixture.delivery_id,
+            "received_at": fixture.received_at,
+        }))?;
+        line.push(b'\n');
+
+        let _index = self.index.lock().await;
+        let mut index = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(FIXTURE_INDEX_FILE))
+            .await?;
+        index.write_all(&line).await?;
+        index.flush().await?;
+        debug!("Recorded fixture {}", path.display());
+        Ok(())
+    }
+}
+
+/// Replace secrets and email addresses in a payload by [`REDACTED`]
+///
+/// Non-null values of [`REDACTED_FIELDS`] are replaced, as are strings
+/// anywhere in the payload that are an email address. Addresses within
+/// longer text, e.g. an issue body, are kept.
+pub fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if !value.is_null() && is_redacted_field(name) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        Value::String(string) if is_email(string) => *string = REDACTED.to_string(),
+        _ => {}
+    }
+}
+
+fn is_redacted_field(name: &str) -> bool {
+    let name = name.to_lowercase();
+    REDACTED_FIELDS.iter().any(|field| {
+        name == *field
+            || name
+                .strip_suffix(field)
+                .is_some_and(|prefix| prefix.ends_with('_'))
+    })
+}
+
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !value.contains(char::is_whitespace)
+}
+
+/// Handle to the background task writing deliveries to an archive
+///
+/// Cloning is cheap; clones share the same archive. Archiving is disabled
+/// until an archive is [set](DeliveryArchiver::set).
+#[derive(Clone)]
+pub struct DeliveryArchiver {
+    sender: Arc<RwLock<Option<mpsc::Sender<ArchivedDelivery>>>>,
+    tasks: TaskSupervisor,
+    task_name: &'static str,
+}
+
+impl Default for DeliveryArchiver {
+    fn default() -> Self {
+        Self::supervised(TaskSupervisor::default(), "delivery-archive")
+    }
+}
+
+impl DeliveryArchiver {
+    /// Create an archiver whose writer runs as the task `task_name` of
+    /// `tasks`
+    pub fn supervised(tasks: TaskSupervisor, task_name: &'static str) -> Self {
+        Self {
+            sender: Arc::default(),
+            tasks,
+            task_name,
+        }
+    }
+
+    /// Write deliveries to `archive` from now on, replacing the previous archive
+    ///
+    /// The writer is a [supervised task](crate::tasks), restarted if storing
+    /// a delivery panics; the deliveries queued for the previous archive are
+    /// still written to it. Must be called within a Tokio runtime.
+    pub fn set<A: DeliveryArchive>(&self, archive: A) {
+        let (sender, receiver) = mpsc::channel::<ArchivedDelivery>(ARCHIVE_QUEUE_CAPACITY);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let archive = Arc::new(archive);
+        self.tasks
+            .spawn(self.task_name, TaskOptions::default(), move |shutdown| {
+                write_deliveries(receiver.clone(), archive.clone(), shutdown)
+            });
+
+        *self.sender.write().expect("archive lock poisoned") = Some(sender);
+    }
+
+    /// Whether an archive is set
+    pub fn is_enabled(&self) -> bool {
+        self.sender.read().expect("archive lock poisoned").is_some()
+    }
+
+    /// Queue `delivery` for archiving, dropping it if the queue is full
+    ///
+    /// Returns whether the delivery was queued.
+    pub fn archive(&self, delivery: ArchivedDelivery) -> bool {
+        let Some(sender) = self.sender.read().expect("archive lock poisoned").clone() else {
+            return false;
+        };
+
+        match sender.try_send(delivery) {
+            Ok(()) => true,
+            Err(TrySendError::Full(delivery)) => {
+                warn!(
+                    "Archive queue full, dropping delivery {}",
+                    delivery.delivery_id.as_deref().unwrap_or("without ID")
+                );
+                false
+            }
+            Err(TrySendError::Closed(_)) => {
+                warn!("Archive writer stopped, delivery not archived");
+                false
+            }
+        }
+    }
+}
+
+/// Write the deliveries of `receiver` to `archive`
+///
+/// Returns once the archive was replaced or the app shuts down, and the
+/// queued deliveries were written.
+async fn write_deliveries<A: DeliveryArchive>(
+    receiver: Arc<Mutex<mpsc::Receiver<ArchivedDelivery>>>,
+    archive: Arc<A>,
+    mut shutdown: TaskShutdown,
+) -> Result<()> {
+    let mut receiver = receiver.lock().await;
+    let mut closed = false;
+    loop {
+        let delivery = tokio::select! {
+            delivery = receiver.recv() => delivery,
+            _ = shutdown.cancelled(), if !closed => {
+                receiver.close();
+                closed = true;
+                continue;
+            }
+        };
+        let Some(delivery) = delivery else {
+            return Ok(());
+        };
+        if let Err(e) = archive.store(&delivery).await {
+            error!(
+                "Failed to archive delivery {}: {:?}",
+                delivery.delivery_id.as_deref().unwrap_or("without ID"),
+                e
+            );
+        }
+    }
+}
+
+/// Read the deliveries of an archive file written by [`JsonlArchive`]
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or a line is not an archived
+/// delivery.
+pub fn read_archive(path: impl AsRef<Path>) -> Result<Vec<ArchivedDelivery>> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow!("Failed to open archive {}: {}", path.display(), e))?;
+
+    std::io::BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(number, line)| {
+            serde_json::from_str(&line?).map_err(|e| {
+                anyhow!(
+                    "Invalid delivery on line {} of {}: {}",
+                    number + 1,
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Context;
+    use crate::testing::issues_payload;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn delivery(id: &str) -> ArchivedDelivery {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-GitHub-Event", "issues".parse().unwrap());
+        headers.insert("X-GitHub-Delivery", id.parse().unwrap());
+        headers.insert("X-Hub-Signature-256", "sha256=abc".parse().unwrap());
+        let body = serde_json::to_vec(&issues_payload("opened", 3)).unwrap();
+
+        ArchivedDelivery::new(&headers, &body, Utc::now(), 200, Vec::new())
+    }
+
+    #[tokio::test]
+    async fn test_archive_and_replay() {
+        let dir = std::env::temp_dir().join(format!("octofer-archive-{}", std::process::id()));
+        let archive = JsonlArchive::new(&dir);
+        for id in ["d-1", "d-2"] {
+            archive.store(&delivery(id)).await.unwrap();
+        }
+
+        let path = archive.path_for(Utc::now().date_naive());
+        let deliveries = read_archive(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[1].delivery_id.as_deref(), Some("d-2"));
+        assert!(!deliveries[0].headers.contains_key("x-hub-signature-256"));
+
+        let dispatcher = Dispatcher::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+        dispatcher
+            .on(
+                "issues",
+                |context: Context, calls: Arc<AtomicUsize>| async move {
+                    assert_eq!(context.require_issue_number()?, 3);
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                },
+                calls.clone(),
+            )
+            .await;
+        for delivery in &deliveries {
+            delivery.replay(&dispatcher).await.unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_fixtures_are_redacted_and_loadable() {
+        let dir = std::env::temp_dir().join(format!("octofer-fixtures-{}", std::process::id()));
+        let recorder = FixtureRecorder::new(&dir);
+
+        let mut payload = issues_payload("opened", 3);
+        payload["sender"]["email"] = "octocat@github.com".into();
+        payload["issue"]["body"] = "Ping me at octocat@example.org".into();
+        payload["issue"]["user"]["notification_email"] = "octocat@example.org".into();
+        payload["hook"] =
+            serde_json::json!({ "config": { "secret": "hunter2", "url": "https://example.org" } });
+        let mut delivery = delivery("d-1");
+        delivery.payload = payload;
+        recorder.store(&delivery).await.unwrap();
+
+        let path = dir.join("issues.opened.d-1.json");
+        let fixture = crate::testing::fixtures::load(&path).unwrap();
+        let index = std::fs::read_to_string(dir.join(FIXTURE_INDEX_FILE)).unwrap();
+        let event = crate::testing::MockWebhookEvent::from_fixture(path.to_str().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(fixture.payload["sender"]["email"], REDACTED);
+        assert_eq!(
+            fixture.payload["issue"]["user"]["notification_email"],
+            REDACTED
+        );
+        assert_eq!(fixture.payload["hook"]["config"]["secret"], REDACTED);
+        assert_eq!(
+            fixture.payload["hook"]["config"]["url"],
+            "https://example.org"
+        );
+        // Only whole strings are recognized as email addresses
+        assert_eq!(
+            fixture.payload["issue"]["body"],
+            "Ping me at octocat@example.org"
+        );
+        assert_eq!(fixture.payload["issue"]["number"], 3);
+
+        let entry: Value = serde_json::from_str(index.trim()).unwrap();
+        assert_eq!(entry["file"], "issues.opened.d-1.json");
+        assert_eq!(entry["action"], "opened");
+        assert!(matches!(
+            event.unwrap().specific,
+            octocrab::models::webhook_events::WebhookEventPayload::Issues(_)
+        ));
+    }
+
+    struct StuckArchive;
+
+    impl DeliveryArchive for StuckArchive {
+        async fn store(&self, _delivery: &ArchivedDelivery) -> Result<()> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_archiver_drops_deliveries_on_overflow() {
+        let archiver = DeliveryArchiver::default();
+        assert!(!archiver.is_enabled());
+        assert!(!archiver.archive(delivery("ignored")));
+
+        archiver.set(StuckArchive);
+        let dropped = (0..ARCHIVE_QUEUE_CAPACITY + 10)
+            .filter(|i| !archiver.archive(delivery(&format!("d-{i}"))))
+            .count();
+        // The writer may have taken one delivery off the queue already
+        assert!((9..=10).contains(&dropped));
+    }
+}
@@ -0,0 +1,583 @@
+//! Backfilling existing issues and pull requests
+//!
+//! Webhooks only cover what happens after the app is installed. To apply the
+//! same logic to the open issues or pull requests a repository already has,
+//! [`Octofer::backfill`] pages through them with the installation client and
+//! runs a handler for each item, oldest first. Every item gets a synthesized
+//! `issues` or `pull_request` event with the `opened` action, built from the
+//! item as the API returns it, so handlers written for webhooks mostly work
+//! unchanged; [`Context::is_backfill`] tells them apart.
+//!
+//! Backfills are resumable: after each item, the number of the last handled
+//! item is saved to a [`BackfillStore`] under a key of the installation,
+//! repository and [`BackfillKind`]. A later backfill with the same store skips
+//! the items up to it, so an interrupted backfill continues where it left
+//! off. [`Octofer::backfill`] keeps cursors in the app's
+//! [store](crate::storage), in the [`STORE_NAMESPACE`] namespace. A failing handler stops the backfill before its item, which is
+//! retried first on the next run.
+//!
+//! Before each page, the installation's remaining API quota is checked and
+//! the backfill pauses until it resets if fewer than
+//! [`RATE_LIMIT_RESERVE`](crate::github::batch::RATE_LIMIT_RESERVE) requests
+//! are left. With [`BackfillOptions::dry_run`], items are listed but the
+//! handler does not run and no cursor is saved.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::backfill::BackfillKind;
+//! use octofer::{Context, Octofer};
+//!
+//! async fn label(context: Context) -> anyhow::Result<()> {
+//!     if context.is_backfill() {
+//!         println!("Backfilling #{}", context.payload()["Issues"]["issue"]["number"]);
+//!     }
+//!     Ok(())
+//! }
+//!
+//! # async fn example(app: Octofer) -> anyhow::Result<()> {
+//! let summary = app
+//!     .backfill(12345, "octocat/hello-world", BackfillKind::Issues, label)
+//!     .await?;
+//! println!("{} issue(s) backfilled", summary.processed.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use octocrab::models::webhook_events::WebhookEvent;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::github::batch::wait_for_quota;
+use crate::github::GitHubClient;
+use crate::helpers::path_segment;
+use crate::storage::Namespace;
+use crate::templates::Templates;
+use crate::{Context, Octofer};
+
+/// Items requested per page
+const ITEMS_PER_PAGE: usize = 100;
+
+/// Namespace of the cursors in the app's [store](crate::storage)
+pub const STORE_NAMESPACE: &str = "backfill";
+
+/// Items a backfill iterates over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackfillKind {
+    /// Open issues, without pull requests, as `issues.opened` events
+    Issues,
+    /// Open pull requests, as `pull_request.opened` events
+    PullRequests,
+}
+
+impl BackfillKind {
+    /// Name of the event synthesized for each item
+    pub fn event(&self) -> &'static str {
+        match self {
+            Self::Issues => "issues",
+            Self::PullRequests => "pull_request",
+        }
+    }
+
+    /// API route listing the items, below the repository's route
+    fn route(&self) -> &'static str {
+        match self {
+            Self::Issues => "issues",
+            Self::PullRequests => "pulls",
+        }
+    }
+}
+
+impl fmt::Display for BackfillKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Issues => write!(f, "issues"),
+            Self::PullRequests => write!(f, "pull_requests"),
+        }
+    }
+}
+
+/// Storage of the cursors of backfills: the number of the last item handled,
+/// by backfill key
+///
+/// Implement it to resume backfills across restarts, e.g. in a database.
+pub trait BackfillStore: Send + Sync {
+    /// Get the cursor of backfill `key`, if any
+    fn load(&self, key: &str) -> BoxFuture<'_, Result<Option<u64>>>;
+
+    /// Set the cursor of backfill `key` to `number`
+    fn save(&self, key: &str, number: u64) -> BoxFuture<'_, Result<()>>;
+}
+
+impl BackfillStore for Namespace {
+    fn load(&self, key: &str) -> BoxFuture<'_, Result<Option<u64>>> {
+        let key = key.to_string();
+        Box::pin(async move { self.get_json(&key).await })
+    }
+
+    fn save(&self, key: &str, number: u64) -> BoxFuture<'_, Result<()>> {
+        let key = key.to_string();
+        Box::pin(async move { self.put_json(&key, &number, None).await })
+    }
+}
+
+/// [`BackfillStore`] keeping cursors in memory
+///
+/// Cursors are lost on restart, but survive failed backfills.
+#[derive(Debug, Default)]
+pub struct InMemoryBackfillStore {
+    cursors: Mutex<HashMap<String, u64>>,
+}
+
+impl InMemoryBackfillStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BackfillStore for InMemoryBackfillStore {
+    fn load(&self, key: &str) -> BoxFuture<'_, Result<Option<u64>>> {
+        let cursor = self.cursors.lock().unwrap().get(key).copied();
+        Box::pin(async move { Ok(cursor) })
+    }
+
+    fn save(&self, key: &str, number: u64) -> BoxFuture<'_, Result<()>> {
+        self.cursors.lock().unwrap().insert(key.to_string(), number);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Options of a backfill
+#[derive(Clone)]
+pub struct BackfillOptions {
+    /// Storage of the cursors
+    pub store: Arc<dyn BackfillStore>,
+    /// List the items without running the handler or saving cursors
+    pub dry_run: bool,
+}
+
+impl BackfillOptions {
+    /// Create options resuming from the cursors of `store`
+    pub fn new(store: Arc<dyn BackfillStore>) -> Self {
+        Self {
+            store,
+            dry_run: false,
+        }
+    }
+
+    /// List the items without running the handler or saving cursors
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+}
+
+impl Default for BackfillOptions {
+    fn default() -> Self {
+        Self::new(Arc::new(InMemoryBackfillStore::new()))
+    }
+}
+
+impl fmt::Debug for BackfillOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackfillOptions")
+            .field("dry_run", &self.dry_run)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Outcome of a completed backfill
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackfillSummary {
+    /// Numbers of the items handled, or that would have been in a dry run
+    pub processed: Vec<u64>,
+    /// Cursor the backfill resumed from, if an earlier one was interrupted
+    pub resumed_after: Option<u64>,
+    /// Items skipped because an earlier backfill handled them
+    pub skipped: usize,
+    /// Whether this was a dry run
+    pub dry_run: bool,
+}
+
+impl Octofer {
+    /// Run `handler` for every open issue or pull request of `repository`
+    /// (`owner/name`), keeping cursors in the app's store
+    ///
+    /// A later backfill of the same repository and kind resumes after the
+    /// last item handled, until the store is cleared.
+    /// See the [`backfill`](crate::backfill) module and
+    /// [`Octofer::backfill_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the app has no GitHub client, listing the items
+    /// fails, or the handler fails for an item.
+    pub async fn backfill<F, Fut>(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        kind: BackfillKind,
+        handler: F,
+    ) -> Result<BackfillSummary>
+    where
+        F: Fn(Context) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        self.backfill_with(
+            installation_id,
+            repository,
+            kind,
+            &BackfillOptions::new(Arc::new(Namespace::new(self.store(), STORE_NAMESPACE))),
+            handler,
+        )
+        .await
+    }
+
+    /// Run `handler` for every open issue or pull request of `repository`
+    /// (`owner/name`) with `options`
+    ///
+    /// Items are handled one at a time, oldest first, skipping those up to
+    /// the cursor in `options.store`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the app has no GitHub client, listing the items
+    /// or saving a cursor fails, or the handler fails for an item; the
+    /// cursor then points at the item before it.
+    pub async fn backfill_with<F, Fut>(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        kind: BackfillKind,
+        options: &BackfillOptions,
+        handler: F,
+    ) -> Result<BackfillSummary>
+    where
+        F: Fn(Context) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let github_client = self
+            .server
+            .github_client()
+            .cloned()
+            .ok_or_else(|| anyhow!("Backfilling requires a GitHub client"))?;
+        let templates = self.server.dispatcher().templates();
+        let state = self.server.dispatcher().state().clone();
+        let repo_config_cache = self.server.dispatcher().repo_config_cache().clone();
+        let permission_cache = self.server.dispatcher().permission_cache().clone();
+        backfill(
+            github_client,
+            templates,
+            installation_id,
+            repository,
+            kind,
+            options,
+            |mut context: Context| {
+                context.app_state = state.clone();
+                context.repo_config_cache = repo_config_cache.clone();
+                context.permission_cache = permission_cache.clone();
+                handler(context)
+            },
+        )
+        .await
+    }
+}
+
+/// Run `handler` for every open item of `repository`, see
+/// [`Octofer::backfill_with`]
+async fn backfill<F, Fut>(
+    github_client: Arc<GitHubClient>,
+    templates: Templates,
+    installation_id: u64,
+    repository: &str,
+    kind: BackfillKind,
+    options: &BackfillOptions,
+    handler: F,
+) -> Result<BackfillSummary>
+where
+    F: Fn(Context) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let (owner, repo) = repository
+        .split_once('/')
+        .ok_or_else(|| anyhow!("Invalid repository '{}', expected owner/name", repository))?;
+    let client = github_client.installation_client(installation_id).await?;
+    let repo_route = format!("/repos/{}/{}", path_segment(owner), path_segment(repo));
+    let repository: Value = client
+        .get(&repo_route, None::<&()>)
+        .await
+        .map_err(|e| anyhow!("Failed to get repository {}/{}: {}", owner, repo, e))?;
+
+    let key = format!("{}:{}/{}:{}", installation_id, owner, repo, kind);
+    let cursor = options.store.load(&key).await?;
+    let mut summary = BackfillSummary {
+        resumed_after: cursor,
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+    if let Some(cursor) = cursor {
+        info!("Resuming backfill {} after #{}", key, cursor);
+    }
+
+    let route = format!("{}/{}", repo_route, kind.route());
+    for page in 1u32.. {
+        wait_for_quota(&client, installation_id).await;
+        let query = json!({
+            "state": "open",
+            "sort": "created",
+            "direction": "asc",
+            "per_page": ITEMS_PER_PAGE,
+            "page": page,
+        });
+        let items: Vec<Value> = client
+            .get(&route, Some(&query))
+            .await
+            .map_err(|e| anyhow!("Failed to list {} of {}/{}: {}", kind, owner, repo, e))?;
+        let count = items.len();
+
+        for item in items {
+            // The issues API lists pull requests too
+            if kind == BackfillKind::Issues && item.get("pull_request").is_some() {
+                continue;
+            }
+            let number = item["number"]
+                .as_u64()
+                .ok_or_else(|| anyhow!("Item of {} without a number", route))?;
+            if cursor.is_some_and(|cursor| number <= cursor) {
+                summary.skipped += 1;
+                continue;
+            }
+            if options.dry_run {
+                debug!("Would backfill {} #{}", kind, number);
+                summary.processed.push(number);
+                continue;
+            }
+
+            let event = synthesize_event(kind, number, item, &repository, installation_id)?;
+            let mut context = Context::with_github_client(
+                Some(event),
+                Some(installation_id),
+                Some(github_client.clone()),
+            );
+            context.templates = templates.clone();
+            context.backfill = true;
+
+            handler(context)
+                .await
+                .map_err(|e| e.context(format!("Backfill of #{} failed", number)))?;
+            options.store.save(&key, number).await?;
+            summary.processed.push(number);
+        }
+
+        if count < ITEMS_PER_PAGE {
+            break;
+        }
+    }
+
+    info!(
+        "Backfill {} done: {} item(s){}, {} skipped",
+        key,
+        summary.processed.len(),
+        if options.dry_run { " (dry run)" } else { "" },
+        summary.skipped
+    );
+    Ok(summary)
+}
+
+/// Build the `opened` event of `item` of `repository`
+fn synthesize_event(
+    kind: BackfillKind,
+    number: u64,
+    item: Value,
+    repository: &Value,
+    installation_id: u64,
+) -> Result<WebhookEvent> {
+    let sender = item["user"].clone();
+    let mut payload = json!({
+        "action": "opened",
+        "repository": repository,
+        "sender": sender,
+        // The node ID is unknown, and unused by the installation client
+        "installation": { "id": installation_id, "node_id": "" },
+    });
+    match kind {
+        BackfillKind::Issues => payload["issue"] = item,
+        BackfillKind::PullRequests => {
+            payload["number"] = json!(number);
+            payload["pull_request"] = item;
+        }
+    }
+
+    let body = serde_json::to_vec(&payload)?;
+    WebhookEvent::try_from_header_and_body(kind.event(), &body)
+        .map_err(|e| anyhow!("Failed to synthesize event for #{}: {}", number, e))
+}
+
+impl Context {
+    /// Whether the context was synthesized by a [backfill](crate::backfill)
+    /// rather than received as a webhook delivery
+    pub fn is_backfill(&self) -> bool {
+        self.backfill
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issue, pull_request, repository, MockGitHub, TEST_INSTALLATION_ID};
+    use axum::{extract::Query, routing::get, Json, Router};
+    use octocrab::models::webhook_events::WebhookEventPayload;
+
+    /// Three pages of issues #1 to #250, every tenth being a pull request
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/repos/octofer/app",
+                get(|| async { Json(repository("octofer", "app")) }),
+            )
+            .route(
+                "/repos/octofer/app/issues",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    let page: u64 = query["page"].parse().unwrap();
+                    let first = (page - 1) * 100 + 1;
+                    let issues: Vec<Value> = (first..(first + 100).min(251))
+                        .map(|number| {
+                            let mut issue = issue("octofer", "app", number);
+                            if number % 10 == 0 {
+                                issue["pull_request"] = json!({ "url": "" });
+                            }
+                            issue
+                        })
+                        .collect();
+                    Json(issues)
+                }),
+            )
+            .route(
+                "/repos/octofer/app/pulls",
+                get(|| async { Json(vec![pull_request("octofer", "app", 10, "abc")]) }),
+            )
+    }
+
+    async fn run<F, Fut>(
+        mock: &MockGitHub,
+        kind: BackfillKind,
+        options: &BackfillOptions,
+        handler: F,
+    ) -> Result<BackfillSummary>
+    where
+        F: Fn(Context) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        backfill(
+            Arc::new(mock.client()),
+            Templates::builtin(),
+            TEST_INSTALLATION_ID,
+            "octofer/app",
+            kind,
+            options,
+            handler,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_backfill_resumes() {
+        let mock = MockGitHub::start(routes()).await;
+        let options = BackfillOptions::default();
+
+        // Interrupted at #151
+        let seen = Mutex::new(Vec::new());
+        let err = run(&mock, BackfillKind::Issues, &options, |context: Context| {
+            let seen = &seen;
+            async move {
+                assert!(context.is_backfill());
+                let Some(WebhookEventPayload::Issues(payload)) =
+                    context.event().as_ref().map(|e| &e.specific)
+                else {
+                    panic!("not an issues event");
+                };
+                if payload.issue.number == 151 {
+                    return Err(anyhow!("interrupted"));
+                }
+                seen.lock().unwrap().push(payload.issue.number);
+                Ok(())
+            }
+        })
+        .await
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("#151"));
+        assert_eq!(seen.lock().unwrap().len(), 135);
+
+        let summary = run(
+            &mock,
+            BackfillKind::Issues,
+            &options,
+            |_context: Context| async { Ok(()) },
+        )
+        .await
+        .unwrap();
+        assert_eq!(summary.resumed_after, Some(149));
+        assert_eq!(summary.skipped, 135);
+        assert_eq!(summary.processed.first(), Some(&151));
+        assert_eq!(summary.processed.len(), 90);
+
+        let pages: Vec<_> = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.path == "/repos/octofer/app/issues")
+            .map(|r| r.query.unwrap())
+            .collect();
+        assert_eq!(pages.len(), 5);
+        assert_eq!(
+            pages[4],
+            "direction=asc&page=3&per_page=100&sort=created&state=open"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backfill_dry_run_and_pull_requests() {
+        let mock = MockGitHub::start(routes()).await;
+        let options = BackfillOptions::default().dry_run();
+
+        let summary = run(
+            &mock,
+            BackfillKind::Issues,
+            &options,
+            |_context: Context| async { panic!("dry runs do not run the handler") },
+        )
+        .await
+        .unwrap();
+        assert!(summary.dry_run);
+        assert_eq!(summary.processed.len(), 225);
+        let key = format!("{TEST_INSTALLATION_ID}:octofer/app:issues");
+        assert_eq!(options.store.load(&key).await.unwrap(), None);
+
+        let numbers = Mutex::new(Vec::new());
+        run(
+            &mock,
+            BackfillKind::PullRequests,
+            &BackfillOptions::default(),
+            |context: Context| {
+                let numbers = &numbers;
+                async move {
+                    numbers
+                        .lock()
+                        .unwrap()
+                        .push(context.require_issue_number()?);
+                    Ok(())
+                }
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(*numbers.lock().unwrap(), [10]);
+    }
+}
@@ -16,7 +16,8 @@
 //!
 //! Event handlers are functions that process GitHub webhook events. They receive a
 //! [`Context`] containing event information and can optionally receive additional
-//! data via the `extra` parameter.
+//! data via the `extra` parameter. Data shared by several handlers is stored as
+//! app [`state`](crate::state) instead, returned by [`Context::state`].
 //!
 //! # Examples
 //!
@@ -50,9 +51,29 @@
 
 use octocrab::models::webhook_events::WebhookEvent;
 
-use crate::{github::GitHubClient, webhook::WebhookEventKind};
-use crate::{SerdeToString, UNDEFINED_EVENT_KIND};
-use std::sync::Arc;
+use crate::github::{
+    layers::{ApiBudget, AuditTrail, DryRun},
+    models::InstallationAccess,
+    GitHubClient,
+};
+use crate::groups::GroupFilter;
+use crate::helpers::comments::CommentQueue;
+use crate::helpers::compare::ComparisonCache;
+use crate::helpers::contents::CommitRefCache;
+use crate::helpers::edits::EditChanges;
+use crate::helpers::files::ChangedFilesCache;
+use crate::helpers::notes::ReportNotes;
+use crate::helpers::permissions::PermissionCache;
+use crate::helpers::pull_requests::PullRequestCache;
+use crate::helpers::repo_config::RepoConfigCache;
+use crate::sequence::OutOfOrderHint;
+use crate::state::AppState;
+use crate::templates::Templates;
+use crate::webhook::{WebhookEventKind, WebhookSource};
+use crate::UNDEFINED_EVENT_KIND;
+use std::fmt;
+use std::panic::Location;
+use std::sync::{Arc, RwLock};
 
 /// Context passed to event handlers containing event information and utilities
 ///
@@ -65,6 +86,12 @@ use std::sync::Arc;
 /// - `event` - The complete webhook event from GitHub (if available)
 /// - `installation_id` - The GitHub App installation ID (if available)
 /// - `github_client` - An authenticated GitHub API client (if available)
+/// - `api_budget` - The GitHub API request budget of this handler invocation (if enabled)
+/// - `audit_trail` - Recorder of the GitHub API writes of this handler invocation (if set)
+/// - `dry_run` - Interceptor of the GitHub API writes of this handler invocation (in dry-run mode)
+/// - `delivery_id` - The unique ID of the delivery (if sent)
+/// - `out_of_order` - Set if the delivery is older than one already seen (if tracked)
+/// - `installation_access` - Repository selection and permissions of the installation (if known)
 ///
 /// # Examples
 ///
@@ -112,10 +139,63 @@ use std::sync::Arc;
 pub struct Context {
     /// Event payload data from GitHub webhook
     pub event: Option<WebhookEvent>,
+    /// Payload as GitHub sent it, including the fields octocrab does not
+    /// model, if the context was parsed from a delivery
+    pub raw_payload: Option<Arc<serde_json::Value>>,
     /// Installation ID for GitHub App authentication
     pub installation_id: Option<u64>,
     /// GitHub client for API operations (if available)
     pub github_client: Option<Arc<GitHubClient>>,
+    /// API request budget shared with the installation clients handed out
+    pub api_budget: Option<Arc<ApiBudget>>,
+    /// Audit trail shared with the installation clients handed out
+    pub audit_trail: Option<Arc<AuditTrail>>,
+    /// Dry-run recorder shared with the installation clients handed out, if
+    /// the handler runs in dry-run mode
+    pub dry_run: Option<Arc<DryRun>>,
+    /// Unique ID of the delivery (`X-GitHub-Delivery` header), if sent
+    pub delivery_id: Option<String>,
+    /// Set by the dispatcher if the delivery arrived out of order
+    pub out_of_order: Option<OutOfOrderHint>,
+    /// Repository selection and permissions of the installation, if known
+    pub installation_access: Option<InstallationAccess>,
+    /// Comment sections queued for the delivery, shared by all its handlers
+    pub comment_queue: CommentQueue,
+    /// Notes added to the delivery's report, see [`Context::note`]
+    pub report_notes: ReportNotes,
+    /// Pull requests associated with the delivery, shared by all its handlers
+    pub pull_request_cache: PullRequestCache,
+    /// Files changed by the delivery's pull request, shared by all its handlers
+    pub changed_files_cache: ChangedFilesCache,
+    /// Commit comparisons and push files of the delivery, shared by all its
+    /// handlers
+    pub comparison_cache: ComparisonCache,
+    /// Refs resolved to commit SHAs for the delivery, shared by all its
+    /// handlers
+    pub commit_ref_cache: CommitRefCache,
+    /// Repository configuration files, shared by all deliveries of the
+    /// dispatcher
+    pub repo_config_cache: RepoConfigCache,
+    /// Repository permissions of senders, shared by all deliveries of the
+    /// dispatcher
+    pub permission_cache: PermissionCache,
+    /// Changed files matching the handler's file patterns, if checked
+    pub matched_files: Option<Arc<Vec<String>>>,
+    /// Identifier of the action requested on a check run, which octocrab
+    /// leaves out of `check_run` payloads
+    pub requested_action: Option<String>,
+    /// Previous body and title of an edited pull request, which octocrab
+    /// leaves out of `pull_request` payloads
+    pub pull_request_changes: Option<EditChanges>,
+    /// Webhook the delivery was received on
+    pub source: WebhookSource,
+    /// Templates of the app, rendered with [`Context::render`]
+    pub templates: Templates,
+    /// State of the app, see [`Context::state`]
+    pub app_state: AppState,
+    /// Whether the context was synthesized by a
+    /// [backfill](crate::backfill), see [`Context::is_backfill`]
+    pub backfill: bool,
 }
 
 impl Context {
@@ -144,7 +224,29 @@ impl Context {
         Self {
             event,
             installation_id,
+            raw_payload: None,
             github_client: None,
+            api_budget: None,
+            audit_trail: None,
+            dry_run: None,
+            delivery_id: None,
+            out_of_order: None,
+            installation_access: None,
+            comment_queue: CommentQueue::default(),
+            report_notes: ReportNotes::default(),
+            pull_request_cache: PullRequestCache::default(),
+            changed_files_cache: ChangedFilesCache::default(),
+            comparison_cache: ComparisonCache::default(),
+            commit_ref_cache: CommitRefCache::default(),
+            repo_config_cache: RepoConfigCache::default(),
+            permission_cache: PermissionCache::default(),
+            matched_files: None,
+            requested_action: None,
+            pull_request_changes: None,
+            source: WebhookSource::App,
+            templates: Templates::builtin(),
+            app_state: AppState::default(),
+            backfill: false,
         }
     }
 
@@ -181,10 +283,75 @@ impl Context {
         Self {
             event,
             installation_id,
+            raw_payload: None,
             github_client,
+            api_budget: None,
+            audit_trail: None,
+            dry_run: None,
+            delivery_id: None,
+            out_of_order: None,
+            installation_access: None,
+            comment_queue: CommentQueue::default(),
+            report_notes: ReportNotes::default(),
+            pull_request_cache: PullRequestCache::default(),
+            changed_files_cache: ChangedFilesCache::default(),
+            comparison_cache: ComparisonCache::default(),
+            commit_ref_cache: CommitRefCache::default(),
+            repo_config_cache: RepoConfigCache::default(),
+            permission_cache: PermissionCache::default(),
+            matched_files: None,
+            requested_action: None,
+            pull_request_changes: None,
+            source: WebhookSource::App,
+            templates: Templates::builtin(),
+            app_state: AppState::default(),
+            backfill: false,
         }
     }
 
+    /// Attach an API request budget to this context
+    ///
+    /// Installation clients obtained through [`Context::installation_client`]
+    /// count their requests against the budget. This is done by the framework
+    /// for every handler invocation, based on [`DispatchConfig`](crate::config::DispatchConfig)
+    /// and the handler's [`HandlerRegistration`] options.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use octofer::{Context, github::layers::ApiBudget};
+    /// use std::sync::Arc;
+    ///
+    /// let context = Context::new(None, None).with_api_budget(Some(Arc::new(ApiBudget::new(10))));
+    /// assert_eq!(context.api_budget().map(|b| b.limit()), Some(10));
+    /// ```
+    pub fn with_api_budget(mut self, api_budget: Option<Arc<ApiBudget>>) -> Self {
+        self.api_budget = api_budget;
+        self
+    }
+
+    /// Attach an audit trail to this context
+    ///
+    /// Installation clients obtained through [`Context::installation_client`]
+    /// record their writes in the trail. This is done by the framework for
+    /// every handler invocation, see the [`audit`](crate::github::layers::audit)
+    /// module.
+    pub fn with_audit_trail(mut self, audit_trail: Option<Arc<AuditTrail>>) -> Self {
+        self.audit_trail = audit_trail;
+        self
+    }
+
+    /// Run this context's requests in dry-run mode
+    ///
+    /// Installation clients obtained through [`Context::installation_client`]
+    /// record their writes in `dry_run` instead of sending them. This is done
+    /// by the framework for handlers in dry-run mode, see the
+    /// [`dry_run`](crate::github::layers::dry_run) module.
+    pub fn with_dry_run(mut self, dry_run: Option<Arc<DryRun>>) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
     /// Get the event type as a string
     ///
     /// Returns the type of webhook event (e.g., "issues", "pull_request", "issue_comment").
@@ -207,11 +374,17 @@ impl Context {
     /// ```
     pub fn kind(&self) -> WebhookEventKind {
         match &self.event {
-            Some(e) => e.kind.to_string(),
-            None => UNDEFINED_EVENT_KIND.to_string(),
+            Some(e) => WebhookEventKind::from(&e.kind),
+            None => WebhookEventKind::new(UNDEFINED_EVENT_KIND),
         }
     }
 
+    /// Get the event type
+    #[deprecated(note = "use `Context::kind`")]
+    pub fn event_type(&self) -> WebhookEventKind {
+        self.kind()
+    }
+
     /// Get the event payload
     ///
     /// Returns a reference to the complete webhook event if available.
@@ -334,6 +507,48 @@ impl Context {
         self.github_client.as_ref()
     }
 
+    /// Get the API request budget of this handler invocation
+    ///
+    /// Returns `None` if the budget is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::Context;
+    ///
+    /// async fn handler(context: Context) -> anyhow::Result<()> {
+    ///     if let Some(budget) = context.api_budget() {
+    ///         println!("{} API requests left", budget.remaining());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn api_budget(&self) -> Option<&Arc<ApiBudget>> {
+        self.api_budget.as_ref()
+    }
+
+    /// Get the audit trail of this handler invocation
+    ///
+    /// Returns `None` outside of handlers run by the framework.
+    pub fn audit_trail(&self) -> Option<&Arc<AuditTrail>> {
+        self.audit_trail.as_ref()
+    }
+
+    /// Get the dry-run recorder of this handler invocation
+    ///
+    /// Returns `None` unless the handler runs in dry-run mode.
+    pub fn dry_run(&self) -> Option<&Arc<DryRun>> {
+        self.dry_run.as_ref()
+    }
+
+    /// Get the unique ID of the delivery (`X-GitHub-Delivery` header)
+    ///
+    /// Returns `None` if GitHub did not send one, e.g. for replayed or
+    /// hand-crafted deliveries.
+    pub fn delivery_id(&self) -> Option<&str> {
+        self.delivery_id.as_deref()
+    }
+
     /// Get an authenticated installation client for the current installation
     ///
     /// This is a convenience method that returns an Octocrab client authenticated
@@ -341,11 +556,18 @@ impl Context {
     /// used for repository-specific operations that require installation-level
     /// permissions.
     ///
+    /// Requests made through the client count against the context's
+    /// [API budget](Context::api_budget); once it is exhausted they fail with
+    /// [`BudgetExceeded`](crate::github::layers::BudgetExceeded). Its writes are
+    /// recorded in the context's [audit trail](Context::audit_trail), or
+    /// intercepted in [dry-run mode](Context::dry_run).
+    ///
     /// # Returns
     ///
     /// Returns `Ok(Some(client))` if both a GitHub client and installation ID are
-    /// available, `Ok(None)` if either is missing, or `Err` if there's an error
-    /// creating the installation client.
+    /// available, `Ok(None)` if either is missing, or a
+    /// [`github::Error`](crate::github::Error) if the installation client
+    /// cannot be created. In handlers, `?` converts it into an [`anyhow::Error`].
     ///
     /// # Examples
     ///
@@ -366,15 +588,94 @@ impl Context {
     ///     Ok(())
     /// }
     /// ```
-    pub async fn installation_client(&self) -> anyhow::Result<Option<octocrab::Octocrab>> {
+    pub async fn installation_client(
+        &self,
+    ) -> Result<Option<octocrab::Octocrab>, crate::github::Error> {
         match (&self.github_client, self.installation_id) {
             (Some(client), Some(installation_id)) => {
-                let octocrab_client = client.installation_client(installation_id).await?;
+                let octocrab_client = client
+                    .handler_installation_client(
+                        installation_id,
+                        self.api_budget.clone(),
+                        self.audit_trail.clone(),
+                        self.dry_run.clone(),
+                    )
+                    .await?;
                 Ok(Some(octocrab_client))
             }
             _ => Ok(None),
         }
     }
+
+    /// Check whether the event's installation is known to be suspended
+    ///
+    /// Installations are marked as suspended when GitHub refuses to create a
+    /// token for them, or when an `installation.suspend` event is received.
+    /// While suspended, [`Context::installation_client`] fails with
+    /// [`Error::Suspended`](crate::github::Error::Suspended), so
+    /// handlers can use this to return early.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::Context;
+    ///
+    /// async fn handler(context: Context) -> anyhow::Result<()> {
+    ///     if context.installation_suspended() {
+    ///         return Ok(());
+    ///     }
+    ///     // ... call the GitHub API
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn installation_suspended(&self) -> bool {
+        match (&self.github_client, self.installation_id) {
+            (Some(client), Some(installation_id)) => client.is_suspended(installation_id),
+            _ => false,
+        }
+    }
+
+    /// Get the webhook the delivery was received on
+    ///
+    /// [`WebhookSource::App`] unless the delivery was sent to a
+    /// [plain webhook](crate::webhook::plain), which has no installation.
+    pub fn source(&self) -> &WebhookSource {
+        &self.source
+    }
+
+    /// Get the out-of-order hint of this delivery
+    ///
+    /// Returns `Some` if a newer delivery for the same issue or pull request
+    /// was already dispatched. Always `None` unless
+    /// [`DispatchConfig::sequence_tracking`](crate::config::DispatchConfig::sequence_tracking)
+    /// is enabled. See the [`sequence`](crate::sequence) module for details.
+    pub fn out_of_order_hint(&self) -> Option<OutOfOrderHint> {
+        self.out_of_order
+    }
+
+    /// Check whether the event's sender is a bot account
+    ///
+    /// This includes the app's own bot user, see [`Context::sent_by_self`].
+    pub fn sent_by_bot(&self) -> bool {
+        self.event
+            .as_ref()
+            .and_then(|event| event.sender.as_ref())
+            .is_some_and(|sender| sender.r#type == "Bot")
+    }
+
+    /// Check whether the event was caused by this app
+    ///
+    /// The sender is compared with the app's [bot user](GitHubClient::app_login).
+    /// Always `false` if the app's slug is not known.
+    pub fn sent_by_self(&self) -> bool {
+        let Some(app_login) = self.github_client.as_ref().and_then(|c| c.app_login()) else {
+            return false;
+        };
+        self.event
+            .as_ref()
+            .and_then(|event| event.sender.as_ref())
+            .is_some_and(|sender| sender.login.eq_ignore_ascii_case(&app_login))
+    }
 }
 
 /// Type alias for event handler functions
@@ -411,6 +712,535 @@ pub type EventHandlerFn = Box<
         + Sync,
 >;
 
+/// How a handler's failure affects its delivery
+///
+/// Set with [`HandlerRegistration::error_policy`]. Failures are reported in
+/// the delivery report under every policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Log the failure and treat the handler as successful
+    Ignore,
+    /// Fail the delivery, skipping the remaining handlers
+    #[default]
+    FailDelivery,
+    /// Keep running the remaining handlers, and fail the delivery only if
+    /// none of them succeeded
+    Isolate,
+}
+
+/// Which draft pull requests a handler is skipped for
+///
+/// Set with [`HandlerRegistration::skip_drafts`] and
+/// [`HandlerRegistration::skip_associated_drafts`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DraftFilter {
+    /// Skip events whose payload is about a draft pull request, see
+    /// [`Context::is_draft_pr`]
+    PullRequest,
+    /// Also skip `check_run`, `check_suite` and `workflow_run` events whose
+    /// open associated pull requests are all drafts, see
+    /// [`Context::associated_pull_requests_are_drafts`]
+    AssociatedPullRequests,
+}
+
+/// Per-registration options of an event handler
+///
+/// Options are set through the [`HandlerRegistration`] returned when a handler
+/// is registered.
+#[derive(Clone, Debug, Default)]
+pub struct HandlerOptions {
+    /// API budget override (`None` uses the server-wide default)
+    pub api_budget: Option<usize>,
+    /// Whether the handler runs before the other handlers of its event
+    pub high_priority: bool,
+    /// Order of the handler within its lane; higher runs first
+    pub priority: i32,
+    /// Name shown in logs and delivery reports (`None` uses the handler's
+    /// [source](HandlerSource))
+    pub name: Option<String>,
+    /// Whether the handler skips events sent by bot accounts
+    pub exclude_bots: bool,
+    /// Whether the handler skips events that are not
+    /// [trusted](Context::is_trusted)
+    pub trusted_only: bool,
+    /// Patterns of the files a pull request must change for the handler to
+    /// run (`None` runs it regardless)
+    pub changed_files: Option<Vec<String>>,
+    /// Feature flag that must be enabled for the handler to run (`None`
+    /// runs it regardless)
+    pub flag: Option<String>,
+    /// How a failure of the handler affects the delivery
+    pub error_policy: ErrorPolicy,
+    /// Share of the deliveries of its event the handler runs for (`None`
+    /// runs it for all), see the [`sampling`](crate::sampling) module
+    pub sample_rate: Option<f64>,
+    /// Whether the handler runs for deliveries of
+    /// [stale](crate::staleness) events
+    pub allow_stale: bool,
+    /// Whether the handler's GitHub API writes are intercepted instead of
+    /// sent, see the [`dry_run`](crate::github::layers::dry_run) module
+    pub dry_run: bool,
+    /// Permissions and helpers the handler declared it uses, see the
+    /// [`manifest`](crate::manifest) module
+    pub required_permissions: Vec<String>,
+    /// Draft pull requests the handler is skipped for (`None` runs it
+    /// regardless)
+    pub skip_drafts: Option<DraftFilter>,
+}
+
+/// Where an event handler was registered from
+///
+/// Captured when a handler is registered, so logs and delivery reports can
+/// point at the offending closure even if it was not
+/// [named](HandlerRegistration::named).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HandlerSource {
+    /// Type name of the handler function, e.g. `my_app::main::{{closure}}`
+    pub type_name: &'static str,
+    /// Location of the registration call
+    pub location: &'static Location<'static>,
+}
+
+impl HandlerSource {
+    /// Describe handler type `F`, registered by the caller
+    #[track_caller]
+    pub fn of<F>() -> Self {
+        Self {
+            type_name: std::any::type_name::<F>(),
+            location: Location::caller(),
+        }
+    }
+}
+
+impl fmt::Display for HandlerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}:{})",
+            self.type_name,
+            self.location.file(),
+            self.location.line()
+        )
+    }
+}
+
+/// An event handler together with its registration options
+pub struct RegisteredHandler {
+    /// The boxed handler function
+    pub handler: EventHandlerFn,
+    /// Options shared with the [`HandlerRegistration`] handle
+    pub options: Arc<RwLock<HandlerOptions>>,
+    /// Filters of the handler group the handler was registered in, if any
+    pub group: Option<Arc<GroupFilter>>,
+    /// Where the handler was registered from, if known
+    pub source: Option<HandlerSource>,
+}
+
+impl RegisteredHandler {
+    /// Wrap a handler function with default options
+    pub fn new(handler: EventHandlerFn) -> Self {
+        Self {
+            handler,
+            options: Arc::new(RwLock::new(HandlerOptions::default())),
+            group: None,
+            source: None,
+        }
+    }
+
+    /// Record where the handler was registered from
+    pub fn with_source(mut self, source: HandlerSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Restrict the handler to the events matching a group's filters
+    pub fn with_group(mut self, group: Option<Arc<GroupFilter>>) -> Self {
+        self.group = group;
+        self
+    }
+
+    /// Get a snapshot of the handler's options
+    pub fn options(&self) -> HandlerOptions {
+        self.options
+            .read()
+            .map(|options| options.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the name of the handler
+    ///
+    /// Falls back to the handler's [source](HandlerSource) if it was not
+    /// [named](HandlerRegistration::named).
+    pub fn name(&self) -> String {
+        match (self.options().name, &self.source) {
+            (Some(name), _) => name,
+            (None, Some(source)) => source.to_string(),
+            (None, None) => "unnamed handler".to_string(),
+        }
+    }
+}
+
+/// Handle returned when registering an event handler
+///
+/// The handle can be used to tune how the handler is dispatched. It may be
+/// dropped right away if the defaults are fine.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use octofer::{Context, Octofer};
+/// use std::sync::Arc;
+///
+/// # async fn example(mut app: Octofer) {
+/// // This handler legitimately performs many API calls
+/// app.on_push(
+///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+///     Arc::new(()),
+/// )
+/// .await
+/// .named("bulk-importer")
+/// .api_budget(1_000);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct HandlerRegistration {
+    options: Arc<RwLock<HandlerOptions>>,
+}
+
+impl HandlerRegistration {
+    /// Create a handle for a registered handler
+    pub fn new(handler: &RegisteredHandler) -> Self {
+        Self {
+            options: handler.options.clone(),
+        }
+    }
+
+    /// Create a handle for a handler that was not registered
+    ///
+    /// Tuning it has no effect.
+    pub(crate) fn detached() -> Self {
+        Self {
+            options: Arc::default(),
+        }
+    }
+
+    /// Override the API budget for this handler
+    ///
+    /// Every invocation of the handler may perform up to `limit` GitHub API
+    /// requests through [`Context::installation_client`].
+    pub fn api_budget(self, limit: usize) -> Self {
+        self.update(|options| options.api_budget = Some(limit))
+    }
+
+    /// Lift the API budget for this handler
+    ///
+    /// Requests are still counted and reported in the delivery summary log.
+    pub fn unlimited_api_budget(self) -> Self {
+        self.api_budget(usize::MAX)
+    }
+
+    /// Run this handler in the high-priority lane
+    ///
+    /// High-priority handlers run before the other handlers registered for
+    /// the same event, in registration order, so latency-sensitive work such
+    /// as scaling runners is not delayed by slower handlers.
+    pub fn high_priority(self) -> Self {
+        self.update(|options| options.high_priority = true)
+    }
+
+    /// Set the priority of this handler among the handlers of its event
+    ///
+    /// Handlers with a higher priority run first; handlers with the same
+    /// priority, by default 0, run in registration order. Priorities order
+    /// handlers within their lane, so [high-priority](Self::high_priority)
+    /// handlers still run before all others. Handlers of a delivery always
+    /// run one after the other, so a handler can rely on those with a higher
+    /// priority having completed, e.g. a guard that fails the delivery before
+    /// the handler acting on it runs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_pull_request(
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("deploy-preview");
+    /// app.on_pull_request(
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("policy-guard")
+    /// .priority(100);
+    /// # }
+    /// ```
+    pub fn priority(self, priority: i32) -> Self {
+        self.update(|options| options.priority = priority)
+    }
+
+    /// Name this handler in logs and delivery reports
+    pub fn named(self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        self.update(|options| options.name = Some(name))
+    }
+
+    /// Run, or skip, this handler for events sent by bot accounts
+    ///
+    /// Handlers run for bot senders by default. Events caused by the app
+    /// itself are skipped for all handlers unless
+    /// [`DispatchConfig::ignore_self`](crate::config::DispatchConfig::ignore_self)
+    /// is disabled.
+    pub fn include_bots(self, include: bool) -> Self {
+        self.update(|options| options.exclude_bots = !include)
+    }
+
+    /// Skip this handler for events from forks or senders without write access
+    ///
+    /// See [`Context::is_trusted`]. Skipped deliveries still succeed. If
+    /// trust cannot be established, e.g. because the sender's permission
+    /// cannot be looked up, the handler is skipped too.
+    pub fn trusted_only(self) -> Self {
+        self.update(|options| options.trusted_only = true)
+    }
+
+    /// Run this handler for pull requests only if they change a file matching
+    /// one of `patterns`
+    ///
+    /// Applies to `pull_request` deliveries with the `opened`,
+    /// `synchronize` and `reopened` actions; the handler runs unfiltered for
+    /// other deliveries. Before running the handler, the dispatcher fetches
+    /// the pull request's files with the installation client, see the
+    /// [`files`](crate::helpers::files) module for the requests made and the
+    /// pattern syntax. The matching files are available through
+    /// [`Context::matched_files`].
+    ///
+    /// The handler also runs, unfiltered, if no installation client is
+    /// available or the files cannot be fetched, and if more files changed
+    /// than were fetched and none of those matched.
+    pub fn when_files_changed(self, patterns: &[&str]) -> Self {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect();
+        self.update(|options| options.changed_files = Some(patterns))
+    }
+
+    /// Run this handler only for deliveries `flag` is enabled for
+    ///
+    /// The flag is evaluated before the handler runs, by the provider set
+    /// with [`Octofer::set_feature_flags`](crate::Octofer::set_feature_flags)
+    /// or, without one, by
+    /// [`DispatchConfig::feature_flags`](crate::config::DispatchConfig::feature_flags).
+    /// Skipped deliveries still succeed. See the [`flags`](crate::flags)
+    /// module.
+    pub fn behind_flag(self, flag: impl Into<String>) -> Self {
+        let flag = flag.into();
+        self.update(|options| options.flag = Some(flag))
+    }
+
+    /// Set how a failure of this handler affects the delivery
+    ///
+    /// By default, a failing handler fails the delivery, so GitHub sees a
+    /// `500` and the remaining handlers are skipped. Best-effort handlers can
+    /// use [`ErrorPolicy::Ignore`] so their failures never fail the delivery,
+    /// or [`ErrorPolicy::Isolate`] so they only do if no other handler
+    /// succeeded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::core::ErrorPolicy;
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_push(
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("metrics")
+    /// .error_policy(ErrorPolicy::Ignore);
+    /// # }
+    /// ```
+    pub fn error_policy(self, policy: ErrorPolicy) -> Self {
+        self.update(|options| options.error_policy = policy)
+    }
+
+    /// Run this handler for a share `rate`, between `0.0` and `1.0`, of the
+    /// deliveries of its event
+    ///
+    /// E.g. `0.1` skips the handler for about 90% of the deliveries. Which
+    /// deliveries are sampled is decided from their delivery ID, so
+    /// redeliveries are decided the same way. Skipped deliveries still
+    /// succeed, and list the handler in their report's
+    /// [`sampled_out`](crate::dispatch::DispatchReport::sampled_out)
+    /// handlers. See the [`sampling`](crate::sampling) module.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_push(
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("push-analytics")
+    /// .sample_rate(0.1);
+    /// # }
+    /// ```
+    pub fn sample_rate(self, rate: f64) -> Self {
+        let rate = crate::sampling::clamp(rate);
+        self.update(|options| options.sample_rate = Some(rate))
+    }
+
+    /// Run this handler for deliveries of events older than
+    /// [`DispatchConfig::max_event_age_secs`](crate::config::DispatchConfig::max_event_age_secs)
+    ///
+    /// Stale deliveries skip every other handler, see the
+    /// [`staleness`](crate::staleness) module. Useful for handlers that must
+    /// see every event however late, e.g. to audit or archive them.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_issue(
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("issue-audit")
+    /// .allow_stale();
+    /// # }
+    /// ```
+    pub fn allow_stale(self) -> Self {
+        self.update(|options| options.allow_stale = true)
+    }
+
+    /// Run this handler in dry-run mode
+    ///
+    /// The handler runs, but its GitHub API writes are recorded in the
+    /// delivery report instead of being sent, see the
+    /// [`dry_run`](crate::github::layers::dry_run) module. Useful to try a
+    /// new behavior on real traffic before enabling it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_issue(
+    ///     |context: Context, _extra: Arc<()>| async move {
+    ///         context.upsert_comment("triage", "Thanks for the report!").await?;
+    ///         Ok(())
+    ///     },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("new-triage")
+    /// .dry_run();
+    /// # }
+    /// ```
+    pub fn dry_run(self) -> Self {
+        self.update(|options| options.dry_run = true)
+    }
+
+    /// Declare the permissions this handler needs beyond its event's
+    ///
+    /// Each requirement is a permission and its level, e.g. `checks:write`,
+    /// or the name of a [`Context`] helper the handler calls, e.g.
+    /// `upsert_comment`, whose permissions are looked up in
+    /// [`HELPER_PERMISSIONS`](crate::manifest::HELPER_PERMISSIONS).
+    /// Declarations only feed
+    /// [`Octofer::required_manifest`](crate::Octofer::required_manifest);
+    /// they are not checked when the handler runs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_pull_request(
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("lint")
+    /// .requires_permissions(&["create_check_run", "contents:read"]);
+    /// # }
+    /// ```
+    pub fn requires_permissions(self, requirements: &[&str]) -> Self {
+        self.update(|options| {
+            options
+                .required_permissions
+                .extend(requirements.iter().map(|r| r.to_string()))
+        })
+    }
+
+    /// Skip this handler while the event's pull request is a draft
+    ///
+    /// Applies to `pull_request`, `pull_request_review`,
+    /// `pull_request_review_comment` and `pull_request_review_thread`
+    /// events, whose payload tells whether the pull request is a draft; the
+    /// handler runs for other events. Skipped deliveries still succeed. To
+    /// react once a draft is marked ready, register a handler with
+    /// [`Octofer::on_pull_request_ready_for_review`](crate::Octofer::on_pull_request_ready_for_review).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_pull_request(
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .named("expensive-ci")
+    /// .skip_drafts();
+    /// # }
+    /// ```
+    pub fn skip_drafts(self) -> Self {
+        self.update(|options| options.skip_drafts = Some(DraftFilter::PullRequest))
+    }
+
+    /// Skip this handler while the event's pull request, or every open pull
+    /// request a check or workflow event is associated with, is a draft
+    ///
+    /// Like [`HandlerRegistration::skip_drafts`], and for `check_run`,
+    /// `check_suite` and `workflow_run` events also looks up the
+    /// [associated pull requests](Context::associated_pull_requests), which
+    /// costs API requests once per delivery. The handler runs if the lookup
+    /// fails or no open pull request is associated.
+    pub fn skip_associated_drafts(self) -> Self {
+        self.update(|options| options.skip_drafts = Some(DraftFilter::AssociatedPullRequests))
+    }
+
+    fn update(self, f: impl FnOnce(&mut HandlerOptions)) -> Self {
+        if let Ok(mut options) = self.options.write() {
+            f(&mut options);
+        }
+        self
+    }
+}
+
 /// Trait for types that can handle GitHub events
 ///
 /// This trait allows types to implement event handling logic. It's used internally
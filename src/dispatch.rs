@@ -0,0 +1,2539 @@
+//! Webhook verification and handler dispatch, independent of any HTTP server
+//!
+//! The [`Dispatcher`] owns the handler registry and turns raw webhook
+//! deliveries (headers and body) into [`Context`]s that are run through the
+//! registered handlers. [`WebhookServer`](crate::webhook::WebhookServer) is a
+//! thin axum wrapper around it, but the dispatcher can be used on its own,
+//! e.g. when webhooks are consumed from a queue or in a serverless function.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{dispatch::Dispatcher, Context};
+//! use octofer::octocrab::models::webhook_events::WebhookEventType;
+//! use octofer::SerdeToString;
+//! use std::sync::Arc;
+//!
+//! # async fn example(headers: octofer::dispatch::HeaderMap, body: Vec<u8>) -> anyhow::Result<()> {
+//! let dispatcher = Dispatcher::new(None);
+//!
+//! dispatcher
+//!     .on(
+//!         WebhookEventType::Issues.to_string(),
+//!         |context: Context, _extra: Arc<()>| async move {
+//!             println!("Issue event: {}", context.kind());
+//!             Ok(())
+//!         },
+//!         Arc::new(()),
+//!     )
+//!     .await;
+//!
+//! // For each message received from the queue
+//! let context = dispatcher.verify_and_parse(&headers, &body, "webhook-secret")?;
+//! let report = dispatcher.dispatch(context).await?;
+//! println!("{} handler(s) ran", report.handlers);
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwap;
+use chrono::Utc;
+use octocrab::models::webhook_events::{
+    payload::InstallationWebhookEventAction, WebhookEvent, WebhookEventPayload,
+};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{collections::HashMap, fmt, sync::Arc};
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
+
+use crate::config::{DispatchConfig, WEBHOOK_HEADER_NAME};
+use crate::core::{
+    Context, DraftFilter, ErrorPolicy, EventHandlerFn, HandlerRegistration, HandlerSource,
+    RegisteredHandler,
+};
+use crate::error_log::ErrorLogLimiter;
+use crate::flags::{FeatureFlags, FlagContext, FlagEvaluation, FlagFailurePolicy};
+use crate::github::{
+    is_suspended,
+    layers::{
+        ApiBudget, AuditSink, AuditTrail, DryRun, DryRunResponses, IntendedAction, TracingAuditSink,
+    },
+    middlewares::{extract_installation_id, verify_hmac_sha256, GITHUB_EVENT_HEADER},
+    models::InstallationAccess,
+    GitHubClient,
+};
+use crate::groups::{GroupFilter, GroupInfo};
+use crate::helpers::checks::requested_action_identifier;
+use crate::helpers::comments::QueuedSectionResult;
+use crate::helpers::edits::pull_request_changes;
+use crate::helpers::notes::ReportNote;
+use crate::helpers::permissions::PermissionCache;
+use crate::helpers::repo_config::RepoConfigCache;
+use crate::manifest::AppManifestRequirements;
+use crate::sampling;
+use crate::sequence::{SequenceKey, SequenceTracker};
+use crate::staleness;
+use crate::state::AppState;
+use crate::storage::{SharedStore, Store};
+use crate::stream::{EventStream, EventStreams, Overflow};
+use crate::templates::Templates;
+use crate::webhook::report::DELIVERY_ID_HEADER;
+use crate::webhook::{WebhookEventKind, WebhookSource};
+
+pub use http::HeaderMap;
+
+/// Handler name of the intended actions posting the queued comment sections
+/// in dry-run mode
+pub const QUEUED_COMMENTS_HANDLER: &str = "queued comments";
+
+/// Handlers by event type, in registration order
+type HandlerMap = HashMap<WebhookEventKind, Arc<[Arc<RegisteredHandler>]>>;
+
+/// Registry of event handlers and the logic to run them for webhook deliveries
+///
+/// Cloning a dispatcher is cheap; clones share the same handler registry and
+/// configuration.
+#[derive(Clone)]
+pub struct Dispatcher {
+    /// Event handlers mapped by event type (e.g., "issues", "pull_request")
+    ///
+    /// Deliveries load a snapshot without locking; registering a handler
+    /// swaps in a new snapshot, which deliveries dispatched afterwards see.
+    handlers: Arc<ArcSwap<HandlerMap>>,
+    /// GitHub client handed to handlers through their context (if available)
+    github_client: Option<Arc<GitHubClient>>,
+    /// Configuration for dispatching events to handlers
+    config: Arc<RwLock<DispatchConfig>>,
+    /// Latest timestamps seen per issue and pull request
+    sequences: Arc<SequenceTracker>,
+    /// Templates handed to handlers through their context
+    templates: Arc<std::sync::RwLock<Templates>>,
+    /// Sink of the audit records of the handlers' GitHub API writes
+    audit_sink: Arc<std::sync::RwLock<Arc<dyn AuditSink>>>,
+    /// Responses synthesized for the writes of handlers in dry-run mode
+    dry_run_responses: Arc<std::sync::RwLock<Arc<DryRunResponses>>>,
+    /// Provider of feature flags (`None` uses the configured static flags)
+    feature_flags: Arc<std::sync::RwLock<Option<Arc<dyn FeatureFlags>>>>,
+    /// Storage of the stateful features
+    store: SharedStore,
+    /// State handed to handlers through their context
+    state: AppState,
+    /// Streams the contexts of the deliveries are published to
+    streams: EventStreams,
+    /// Counts of handler errors, limiting how many are logged
+    error_log: Arc<ErrorLogLimiter>,
+    /// Repository configuration files read by the handlers
+    repo_config_cache: RepoConfigCache,
+    /// Repository permissions of senders looked up by the handlers
+    permission_cache: PermissionCache,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Dispatcher {
+    /// Create a new dispatcher without any registered handlers
+    ///
+    /// # Arguments
+    ///
+    /// * `github_client` - GitHub client made available to handlers, if any
+    pub fn new(github_client: Option<Arc<GitHubClient>>) -> Self {
+        Self {
+            handlers: Arc::new(ArcSwap::from_pointee(HashMap::new())),
+            github_client,
+            config: Arc::new(RwLock::new(DispatchConfig::default())),
+            sequences: Arc::new(SequenceTracker::default()),
+            templates: Arc::new(std::sync::RwLock::new(Templates::builtin())),
+            audit_sink: Arc::new(std::sync::RwLock::new(Arc::new(TracingAuditSink))),
+            dry_run_responses: Arc::new(std::sync::RwLock::new(Arc::new(
+                DryRunResponses::builtin(),
+            ))),
+            feature_flags: Arc::new(std::sync::RwLock::new(None)),
+            store: SharedStore::default(),
+            state: AppState::default(),
+            streams: EventStreams::default(),
+            error_log: Arc::new(ErrorLogLimiter::new()),
+            repo_config_cache: RepoConfigCache::default(),
+            permission_cache: PermissionCache::default(),
+        }
+    }
+
+    /// Get the GitHub client handed to handlers
+    pub fn github_client(&self) -> Option<&Arc<GitHubClient>> {
+        self.github_client.as_ref()
+    }
+
+    /// Set the configuration used when dispatching events to handlers
+    pub async fn set_config(&self, config: DispatchConfig) {
+        self.sequences.set_capacity(config.sequence_cache_size);
+        *self.config.write().await = config;
+    }
+
+    /// Get the configuration used when dispatching events to handlers
+    pub async fn config(&self) -> DispatchConfig {
+        self.config.read().await.clone()
+    }
+
+    /// Set the templates handlers render with [`Context::render`]
+    ///
+    /// Defaults to [`Templates::builtin`].
+    pub fn set_templates(&self, templates: Templates) {
+        *self.templates.write().expect("templates lock poisoned") = templates;
+    }
+
+    /// Get the templates handlers render with [`Context::render`]
+    pub fn templates(&self) -> Templates {
+        self.templates
+            .read()
+            .expect("templates lock poisoned")
+            .clone()
+    }
+
+    /// Set the sink receiving the audit records of the handlers' GitHub API
+    /// writes
+    ///
+    /// Defaults to [`TracingAuditSink`]. See the
+    /// [`audit`](crate::github::layers::audit) module.
+    pub fn set_audit_sink<S: AuditSink>(&self, sink: S) {
+        *self.audit_sink.write().expect("audit sink lock poisoned") = Arc::new(sink);
+    }
+
+    fn audit_sink(&self) -> Arc<dyn AuditSink> {
+        self.audit_sink
+            .read()
+            .expect("audit sink lock poisoned")
+            .clone()
+    }
+
+    /// Set the responses synthesized for the writes of handlers in dry-run
+    /// mode
+    ///
+    /// Defaults to [`DryRunResponses::builtin`]. See the
+    /// [`dry_run`](crate::github::layers::dry_run) module.
+    pub fn set_dry_run_responses(&self, responses: DryRunResponses) {
+        *self
+            .dry_run_responses
+            .write()
+            .expect("dry-run responses lock poisoned") = Arc::new(responses);
+    }
+
+    fn dry_run_responses(&self) -> Arc<DryRunResponses> {
+        self.dry_run_responses
+            .read()
+            .expect("dry-run responses lock poisoned")
+            .clone()
+    }
+
+    /// Set the provider evaluating the flags of handlers registered
+    /// [behind a flag](HandlerRegistration::behind_flag)
+    ///
+    /// Defaults to the [`DispatchConfig::feature_flags`]. See the
+    /// [`flags`](crate::flags) module.
+    pub fn set_feature_flags<F: FeatureFlags>(&self, flags: F) {
+        *self
+            .feature_flags
+            .write()
+            .expect("feature flags lock poisoned") = Some(Arc::new(flags));
+    }
+
+    /// Set the storage of the stateful features
+    ///
+    /// Defaults to an [`InMemoryStore`](crate::storage::InMemoryStore). See
+    /// the [`storage`](crate::storage) module.
+    pub fn set_store<S: Store>(&self, store: S) {
+        self.store.set(Arc::new(store));
+    }
+
+    /// Get the storage of the stateful features
+    ///
+    /// The returned store follows later calls to [`Dispatcher::set_store`].
+    pub fn store(&self) -> Arc<dyn Store> {
+        Arc::new(self.store.clone())
+    }
+
+    /// Get the state handed to handlers through their context
+    ///
+    /// See the [`state`](crate::state) module.
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Get the repository configuration files cached for the handlers
+    pub(crate) fn repo_config_cache(&self) -> &RepoConfigCache {
+        &self.repo_config_cache
+    }
+
+    /// Get the sender permissions cached for the handlers
+    pub(crate) fn permission_cache(&self) -> &PermissionCache {
+        &self.permission_cache
+    }
+
+    /// Open a stream of the contexts of the deliveries dispatched from now
+    /// on, buffering up to `buffer` of them
+    ///
+    /// See the [`stream`](crate::stream) module.
+    pub fn event_stream(&self, buffer: usize, overflow: Overflow) -> EventStream {
+        self.streams.subscribe(buffer, overflow)
+    }
+
+    /// Get the open streams of the contexts of the deliveries
+    pub fn event_streams(&self) -> &EventStreams {
+        &self.streams
+    }
+
+    fn feature_flags(&self) -> Option<Arc<dyn FeatureFlags>> {
+        self.feature_flags
+            .read()
+            .expect("feature flags lock poisoned")
+            .clone()
+    }
+
+    /// Register an event handler for a specific event type
+    ///
+    /// See [`WebhookServer::on`](crate::webhook::WebhookServer::on) for details.
+    ///
+    /// If `event` is not a known event name, see [`normalize_event_name`],
+    /// the error is logged and the handler is not registered. Use
+    /// [`Dispatcher::try_on`] to handle the error, or
+    /// [`Dispatcher::on_unchecked`] for custom event names.
+    #[track_caller]
+    pub fn on<F, Fut, E>(
+        &self,
+        event: impl Into<String>,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let source = HandlerSource::of::<F>();
+        let registration = normalize_event_name(&event.into())
+            .map(|event| self.register(event, None, handler, extra, source));
+        registration_or_log(registration, source)
+    }
+
+    /// Register an event handler, failing if the event name is unknown
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InvalidEventName`] error if `event` is empty or not a
+    /// known event name.
+    #[track_caller]
+    pub fn try_on<F, Fut, E>(
+        &self,
+        event: impl Into<String>,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl Future<Output = Result<HandlerRegistration>>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let source = HandlerSource::of::<F>();
+        let registration = normalize_event_name(&event.into())
+            .map(|event| self.register(event, None, handler, extra, source));
+        async move { Ok(registration?.await) }
+    }
+
+    /// Register an event handler without checking that the event name is known
+    ///
+    /// Use this for event types newer than the ones known to octocrab. The
+    /// name is still trimmed and lowercased.
+    ///
+    /// If `event` is empty, the error is logged and the handler is not
+    /// registered.
+    #[track_caller]
+    pub fn on_unchecked<F, Fut, E>(
+        &self,
+        event: impl Into<String>,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let source = HandlerSource::of::<F>();
+        let registration = normalize_unchecked(&event.into())
+            .map(|event| self.register(event, None, handler, extra, source));
+        registration_or_log(registration, source)
+    }
+
+    /// Register an event handler under an already normalized event name
+    ///
+    /// The handler only runs for events matching `group`, if given. `source`
+    /// is where the handler was registered from, see [`HandlerSource::of`].
+    pub(crate) fn register<F, Fut, E>(
+        &self,
+        event: WebhookEventKind,
+        group: Option<Arc<GroupFilter>>,
+        handler: F,
+        extra: Arc<E>,
+        source: HandlerSource,
+    ) -> impl Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let boxed_handler: EventHandlerFn = Box::new(move |context| {
+            // Clone the extra data for this handler call
+            let extra = extra.clone();
+            Box::pin(handler(context, extra))
+        });
+
+        let registered = Arc::new(
+            RegisteredHandler::new(boxed_handler)
+                .with_group(group)
+                .with_source(source),
+        );
+        let registration = HandlerRegistration::new(&registered);
+
+        let handlers = self.handlers.clone();
+        async move {
+            // Deliveries in flight keep running with the previous snapshot
+            handlers.rcu(|current| {
+                let mut updated = HandlerMap::clone(current);
+                let mut event_handlers = updated
+                    .get(&event)
+                    .map(|handlers| handlers.to_vec())
+                    .unwrap_or_default();
+                event_handlers.push(registered.clone());
+                updated.insert(event.clone(), event_handlers.into());
+                updated
+            });
+
+            registration
+        }
+    }
+
+    /// Number of registered handlers per event type, sorted by event type
+    pub async fn handler_counts(&self) -> Vec<(WebhookEventKind, usize)> {
+        let handlers = self.handlers.load();
+        let mut counts: Vec<_> = handlers
+            .iter()
+            .map(|(event, handlers)| (event.clone(), handlers.len()))
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    /// Names of the registered handlers per event type, sorted by event type
+    ///
+    /// Handlers are listed in the order they run, see
+    /// [`HandlerRegistration::priority`], under their
+    /// [`RegisteredHandler::name`].
+    pub async fn handler_names(&self) -> Vec<(WebhookEventKind, Vec<String>)> {
+        let handlers = self.handlers.load();
+        let mut names: Vec<_> = handlers
+            .iter()
+            .map(|(event, handlers)| {
+                (
+                    event.clone(),
+                    execution_order(handlers)
+                        .map(|(_, handler)| handler.name())
+                        .collect(),
+                )
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Events and permissions the GitHub App needs for the registered
+    /// handlers
+    ///
+    /// See the [`manifest`](crate::manifest) module.
+    pub async fn required_manifest(&self) -> AppManifestRequirements {
+        let handlers = self.handlers.load();
+        let declared: Vec<_> = handlers
+            .iter()
+            .flat_map(|(event, handlers)| {
+                handlers
+                    .iter()
+                    .map(move |handler| (event.as_str(), handler.options().required_permissions))
+            })
+            .collect();
+        AppManifestRequirements::from_handlers(
+            declared
+                .iter()
+                .map(|(event, required)| (*event, required.as_slice())),
+        )
+    }
+
+    /// Log the registered handlers per event type
+    ///
+    /// Called when the webhook server starts, so misconfigurations are
+    /// visible at boot.
+    pub async fn log_handlers(&self) {
+        let names = self.handler_names().await;
+        if names.is_empty() {
+            warn!("No event handlers registered");
+            return;
+        }
+
+        info!("Registered event handlers:");
+        for (event, names) in names {
+            info!(
+                "  {}: {} handler{} — {}",
+                event,
+                names.len(),
+                if names.len() == 1 { "" } else { "s" },
+                names.join(", ")
+            );
+        }
+    }
+
+    /// Verify the HMAC signature of a webhook delivery
+    ///
+    /// The signature is read from the `X-Hub-Signature-256` header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signature header is missing or does not match
+    /// the body.
+    pub fn verify(headers: &HeaderMap, body: &[u8], secret: &str) -> Result<()> {
+        let signature = headers
+            .get(WEBHOOK_HEADER_NAME)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| anyhow!("Missing HMAC signature header: {}", WEBHOOK_HEADER_NAME))?;
+
+        verify_hmac_sha256(signature, body, secret)
+    }
+
+    /// Parse a webhook delivery into a handler [`Context`]
+    ///
+    /// The event type is read from the `X-GitHub-Event` header. The signature
+    /// is **not** verified; use [`Dispatcher::verify_and_parse`] for untrusted
+    /// input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event header is missing or the body is not a
+    /// valid payload for the event type.
+    pub fn parse(&self, headers: &HeaderMap, body: &[u8]) -> Result<Context> {
+        self.parse_from(&WebhookSource::App, headers, body)
+    }
+
+    /// Parse a delivery received on `source` into a handler [`Context`]
+    ///
+    /// Like [`Dispatcher::parse`], but deliveries of a
+    /// [plain webhook](crate::webhook::plain) get no installation ID and no
+    /// installation access, even if their payload mentions an installation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event header is missing or the payload cannot
+    /// be parsed.
+    pub fn parse_from(
+        &self,
+        source: &WebhookSource,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Context> {
+        let event_type = headers
+            .get(GITHUB_EVENT_HEADER)
+            .ok_or_else(|| anyhow!("Missing required header: {}", GITHUB_EVENT_HEADER))?
+            .to_str()
+            .map_err(|e| anyhow!("Invalid header value for {}: {}", GITHUB_EVENT_HEADER, e))?;
+
+        let event = WebhookEvent::try_from_header_and_body(event_type, body)
+            .map_err(|e| anyhow!("Failed to parse webhook event: {}", e))?;
+        let requested_action = requested_action_identifier(&event, body);
+        let pull_request_changes = pull_request_changes(&event, body);
+        let delivery_id = headers
+            .get(DELIVERY_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let payload = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .map(Arc::new);
+
+        if !source.is_app() {
+            let mut context = self.context_for_installation(event, None);
+            context.source = source.clone();
+            context.raw_payload = payload;
+            context.requested_action = requested_action;
+            context.pull_request_changes = pull_request_changes;
+            context.delivery_id = delivery_id;
+            return Ok(context);
+        }
+
+        // octocrab drops most permissions of full installation objects and
+        // does not model the installation of every event, so both are also
+        // read from the raw payload
+        let access = payload
+            .as_ref()
+            .and_then(|payload| InstallationAccess::from_json(&payload["installation"]));
+
+        let installation_id = extract_installation_id(&event, payload.as_deref());
+        let mut context = self.context_for_installation(event, installation_id);
+        if access.is_some() {
+            context.installation_access = access;
+        }
+        context.raw_payload = payload;
+        context.requested_action = requested_action;
+        context.pull_request_changes = pull_request_changes;
+        context.delivery_id = delivery_id;
+        Ok(context)
+    }
+
+    /// Verify a webhook delivery and parse it into a handler [`Context`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if verification or parsing fails.
+    pub fn verify_and_parse(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+        secret: &str,
+    ) -> Result<Context> {
+        Self::verify(headers, body, secret)?;
+        debug!("HMAC signature verified successfully");
+        self.parse(headers, body)
+    }
+
+    /// Build the handler [`Context`] for an already parsed event
+    ///
+    /// The context's [installation access](Context::installation_access) is
+    /// taken from the GitHub client's cache, if present.
+    pub fn context(&self, event: WebhookEvent) -> Context {
+        let installation_id = extract_installation_id(&event, None);
+        self.context_for_installation(event, installation_id)
+    }
+
+    fn context_for_installation(
+        &self,
+        event: WebhookEvent,
+        installation_id: Option<u64>,
+    ) -> Context {
+        let mut context =
+            Context::with_github_client(Some(event), installation_id, self.github_client.clone());
+        context.templates = self.templates();
+        context.app_state = self.state.clone();
+        context.repo_config_cache = self.repo_config_cache.clone();
+        context.permission_cache = self.permission_cache.clone();
+        context.installation_access = self
+            .github_client
+            .as_ref()
+            .zip(installation_id)
+            .and_then(|(client, id)| client.cached_installation(id));
+        context
+    }
+
+    /// Run all handlers registered for the context's event type
+    ///
+    /// Handlers run sequentially, each with its own API budget, ordered by
+    /// their [priority](HandlerRegistration::priority). Dispatch stops at the
+    /// first failing handler.
+    ///
+    /// `installation.suspend` and `installation.unsuspend` events update the
+    /// GitHub client's set of suspended installations before handlers run.
+    ///
+    /// With [`DispatchConfig::ignore_self`] enabled, no handler runs for events
+    /// [caused by the app itself](Context::sent_by_self). Handlers registered
+    /// with [`include_bots(false)`](crate::core::HandlerRegistration::include_bots)
+    /// are skipped for events sent by any bot, and handlers registered with
+    /// [`trusted_only`](crate::core::HandlerRegistration::trusted_only) for
+    /// events that are not [trusted](Context::is_trusted). Handlers registered
+    /// with [`when_files_changed`](crate::core::HandlerRegistration::when_files_changed)
+    /// are skipped for pull requests not changing a matching file, which are
+    /// fetched with the installation client first. Handlers registered
+    /// [`behind_flag`](crate::core::HandlerRegistration::behind_flag) are
+    /// skipped unless their flag is enabled for the delivery, and handlers
+    /// with a [`sample_rate`](crate::core::HandlerRegistration::sample_rate),
+    /// or of an event type sampled by [`DispatchConfig::sample_rates`],
+    /// unless the delivery is in their sample. With
+    /// [`DispatchConfig::max_event_age_secs`] set, deliveries of older events
+    /// only run the handlers registered with
+    /// [`allow_stale`](crate::core::HandlerRegistration::allow_stale).
+    ///
+    /// Handlers registered with
+    /// [`dry_run`](crate::core::HandlerRegistration::dry_run), or every
+    /// handler with [`DispatchConfig::dry_run`] enabled, run with their
+    /// GitHub API writes intercepted and listed in the report's
+    /// [`intended_actions`](DispatchReport::intended_actions).
+    ///
+    /// Deliveries not skipped for every handler are published to the open
+    /// [event streams](Dispatcher::event_stream) before handlers run.
+    ///
+    /// With [`DispatchConfig::sequence_tracking`] enabled, deliveries older
+    /// than one already dispatched for the same issue or pull request get an
+    /// [`out_of_order_hint`](Context::out_of_order_hint).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first handler that failed, according to the
+    /// handlers' [error policies](ErrorPolicy): failures of ignored handlers
+    /// never fail the delivery, and those of isolated handlers only if no
+    /// other handler succeeded. Failures caused by a suspended installation
+    /// are only logged, unless [`DispatchConfig::ignore_suspended`] is
+    /// disabled.
+    pub async fn dispatch(&self, context: Context) -> Result<DispatchReport> {
+        let (report, result) = self.dispatch_with_report(context).await;
+        result.map(|_| report)
+    }
+
+    /// Run all handlers registered for the context's event type, reporting
+    /// on failures too
+    ///
+    /// Same as [`Dispatcher::dispatch`], but the report is returned alongside
+    /// the result, so the handlers that ran before a failure are known.
+    pub async fn dispatch_with_report(&self, context: Context) -> (DispatchReport, Result<()>) {
+        self.dispatch_tracked(context, &AtomicUsize::new(0)).await
+    }
+
+    /// Same as [`Dispatcher::dispatch_with_report`], counting the handlers
+    /// that completed in `completed` as they do
+    pub(crate) async fn dispatch_tracked(
+        &self,
+        mut context: Context,
+        completed: &AtomicUsize,
+    ) -> (DispatchReport, Result<()>) {
+        let started = Instant::now();
+        let kind = context.kind();
+        let (
+            default_budget,
+            ignore_suspended,
+            ignore_self,
+            sequence_tracking,
+            max_pages,
+            error_log_limits,
+            event_sample_rate,
+            max_event_age,
+            dry_run_all,
+        ) = {
+            let config = self.config.read().await;
+            let window = Duration::from_secs(config.error_log_window_secs);
+            (
+                config.api_budget,
+                config.ignore_suspended,
+                config.ignore_self,
+                config.sequence_tracking,
+                config.changed_files_max_pages,
+                config.error_log_burst.map(|burst| (burst, window)),
+                config.sample_rates.rate(kind.as_str()),
+                config.max_event_age_secs.map(Duration::from_secs),
+                config.dry_run,
+            )
+        };
+        // Resolved once the first handler behind a flag is reached
+        let mut flags = None;
+        let mut report = DispatchReport {
+            event: kind.clone(),
+            ..Default::default()
+        };
+
+        self.track_suspension(&context).await;
+        self.track_installation_access(&context);
+        self.track_repository_identity(&context);
+        self.track_account_rename(&context);
+        self.track_config_changes(&context);
+
+        if sequence_tracking {
+            if let Some((key, timestamp)) = SequenceKey::of(&context) {
+                context.out_of_order = self.sequences.observe(key, timestamp);
+            }
+            if let Some(hint) = &context.out_of_order {
+                warn!(
+                    "Out-of-order delivery of {} event: updated at {}, already saw {}",
+                    kind, hint.timestamp, hint.latest_seen
+                );
+                report.out_of_order = true;
+            }
+        }
+
+        if ignore_self && context.sent_by_self() {
+            debug!("Skipping {} event caused by the app itself", kind);
+            report.caused_by_self = true;
+            report.duration = started.elapsed();
+            return (report, Ok(()));
+        }
+        if let Some(max_age) = max_event_age {
+            if let Some(age) = staleness::event_age(&context, Utc::now()) {
+                if age > max_age {
+                    warn!(
+                        "Stale delivery of {} event: happened {}s ago, over the limit of {}s",
+                        kind,
+                        age.as_secs(),
+                        max_age.as_secs()
+                    );
+                    report.stale = true;
+                }
+            }
+        }
+        if !report.stale {
+            self.streams.publish(&context);
+        }
+        let sent_by_bot = context.sent_by_bot();
+        // Looked up once the first trusted-only handler is reached
+        let mut trusted = None;
+
+        // Get handlers for this event type; the snapshot is not locked while
+        // they run
+        let Some(event_handlers) = self.handlers.load().get(&kind).cloned() else {
+            info!("No handlers registered for event: {}", kind);
+            report.duration = started.elapsed();
+            return (report, Ok(()));
+        };
+
+        let ordered = execution_order(&event_handlers);
+        let audit_sink = self.audit_sink();
+        let dry_run_responses = self.dry_run_responses();
+
+        let mut result = Ok(());
+        // First failure of an isolated handler, returned if no handler succeeds
+        let mut isolated = None;
+        let mut succeeded = 0;
+        for (index, registered) in ordered {
+            if let Some(group) = &registered.group {
+                if !group.matches(&context) {
+                    debug!("Skipping handler of group '{}'", group.name);
+                    continue;
+                }
+            }
+            if report.stale && !registered.options().allow_stale {
+                debug!(
+                    "Skipping handler '{}' for stale {} event",
+                    registered.name(),
+                    kind
+                );
+                continue;
+            }
+            if sent_by_bot && registered.options().exclude_bots {
+                debug!(
+                    "Skipping handler '{}' for {} event sent by a bot",
+                    registered.name(),
+                    kind
+                );
+                continue;
+            }
+            if registered.options().trusted_only {
+                if trusted.is_none() {
+                    trusted = Some(context.is_trusted().await.unwrap_or_else(|e| {
+                        warn!("Cannot tell whether {} event is trusted: {:#}", kind, e);
+                        false
+                    }));
+                }
+                if trusted == Some(false) {
+                    info!(
+                        "Skipping trusted-only handler '{}' for untrusted {} event",
+                        registered.name(),
+                        kind
+                    );
+                    continue;
+                }
+            }
+
+            if let Some(flag) = registered.options().flag {
+                let (provider, flag_context, policy) = match &flags {
+                    Some(flags) => flags,
+                    None => flags.insert(self.resolve_flags(&context).await),
+                };
+                let evaluation = evaluate_flag(
+                    provider.as_ref(),
+                    registered.name(),
+                    flag,
+                    flag_context,
+                    *policy,
+                )
+                .await;
+                let enabled = evaluation.enabled;
+                if !enabled {
+                    debug!(
+                        "Skipping handler '{}': flag '{}' is disabled",
+                        evaluation.handler, evaluation.flag
+                    );
+                }
+                report.flags.push(evaluation);
+                if !enabled {
+                    continue;
+                }
+            }
+
+            let sample_rate = match (registered.options().sample_rate, event_sample_rate) {
+                (Some(rate), Some(event_rate)) => Some(rate.min(event_rate)),
+                (rate, event_rate) => rate.or(event_rate),
+            };
+            if let Some(rate) = sample_rate {
+                if !sampling::is_sampled(context.delivery_id(), rate) {
+                    debug!(
+                        "Skipping handler '{}': delivery sampled out at rate {}",
+                        registered.name(),
+                        rate
+                    );
+                    report.sampled_out.push(registered.name());
+                    continue;
+                }
+            }
+
+            if let Some(filter) = registered.options().skip_drafts {
+                if is_draft(&context, filter).await {
+                    debug!(
+                        "Skipping handler '{}' for {} event of a draft pull request",
+                        registered.name(),
+                        kind
+                    );
+                    continue;
+                }
+            }
+
+            let mut handler_context = context.clone();
+            if let Some(patterns) = registered.options().changed_files {
+                if !matches_changed_files(&mut handler_context, &patterns, max_pages).await {
+                    debug!(
+                        "Skipping handler '{}': no changed file matches {:?}",
+                        registered.name(),
+                        patterns
+                    );
+                    continue;
+                }
+            }
+
+            let budget = registered
+                .options()
+                .api_budget
+                .or(default_budget)
+                .map(|limit| Arc::new(ApiBudget::new(limit)));
+
+            let name = registered.name();
+            let audit = Arc::new(AuditTrail::new(
+                audit_sink.clone(),
+                context.delivery_id.clone(),
+                name.clone(),
+                context.installation_id,
+            ));
+            let dry_run = (dry_run_all || registered.options().dry_run)
+                .then(|| Arc::new(DryRun::new(name.clone(), dry_run_responses.clone())));
+            let policy = registered.options().error_policy;
+            let handler_started = Instant::now();
+            let handler_result = (registered.handler)(
+                handler_context
+                    .with_api_budget(budget.clone())
+                    .with_audit_trail(Some(audit.clone()))
+                    .with_dry_run(dry_run.clone()),
+            )
+            .await;
+            report.record(&name, budget.as_deref(), &audit);
+            if let Some(dry_run) = dry_run {
+                report.intended_actions.extend(dry_run.actions());
+            }
+            report.notes.extend(context.report_notes.take(&name));
+            report.results.push(HandlerResult {
+                index,
+                name: name.clone(),
+                duration: handler_started.elapsed(),
+                error: handler_result.as_ref().err().map(|e| format!("{:#}", e)),
+                error_policy: policy,
+            });
+            completed.fetch_add(1, Ordering::SeqCst);
+
+            match handler_result {
+                Ok(_) => {
+                    info!("Handler '{}' executed successfully", name);
+                    succeeded += 1;
+                }
+                Err(e) if ignore_suspended && is_suspended(&e) => {
+                    warn!("Skipping delivery: {}", e);
+                    report.installation_suspended = true;
+                    break;
+                }
+                Err(e) if policy == ErrorPolicy::Ignore => {
+                    if self.should_log_error(&kind, &name, &e, error_log_limits) {
+                        warn!("Ignoring failure of handler '{}': {:?}", name, e);
+                    }
+                    succeeded += 1;
+                }
+                Err(e) if policy == ErrorPolicy::Isolate => {
+                    if self.should_log_error(&kind, &name, &e, error_log_limits) {
+                        error!("Isolated handler '{}' failed with error: {:?}", name, e);
+                    }
+                    isolated.get_or_insert(e);
+                }
+                Err(e) => {
+                    if self.should_log_error(&kind, &name, &e, error_log_limits) {
+                        error!("Handler '{}' failed with error: {:?}", name, e);
+                    }
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        if let (Ok(()), Some(e), 0) = (&result, isolated, succeeded) {
+            result = Err(e);
+        }
+
+        // Sections queued by the handlers that ran are posted even if a later
+        // handler failed; a suspended installation cannot post anything
+        if !report.installation_suspended {
+            if context.comment_queue.is_dry_run() {
+                let dry_run = Arc::new(DryRun::new(QUEUED_COMMENTS_HANDLER, dry_run_responses));
+                report.comment_sections = context
+                    .clone()
+                    .with_dry_run(Some(dry_run.clone()))
+                    .flush_comment_queue()
+                    .await;
+                report.intended_actions.extend(dry_run.actions());
+            } else {
+                report.comment_sections = context.flush_comment_queue().await;
+            }
+        }
+
+        report.duration = started.elapsed();
+        report.log();
+        (report, result)
+    }
+
+    /// Count a failure of handler `name` on `event`, returning whether to log
+    /// it in full
+    ///
+    /// Logs the summaries of the errors not logged in the windows that
+    /// ended. `limits` are the burst and window of
+    /// [`DispatchConfig::error_log_burst`], `None` logging every error.
+    fn should_log_error(
+        &self,
+        event: &str,
+        name: &str,
+        error: &anyhow::Error,
+        limits: Option<(usize, Duration)>,
+    ) -> bool {
+        let Some((burst, window)) = limits else {
+            return true;
+        };
+        let (log, summaries) = self.error_log.record(
+            event,
+            name,
+            &format!("{:#}", error),
+            burst,
+            window,
+            Instant::now(),
+        );
+        for summary in summaries {
+            error!("{}", summary);
+        }
+        log
+    }
+
+    /// List the handler groups with at least one registered handler, by name
+    pub async fn groups(&self) -> Vec<GroupInfo> {
+        let handlers = self.handlers.load();
+        let mut groups: Vec<GroupInfo> = Vec::new();
+
+        for group in handlers
+            .values()
+            .flat_map(|handlers| handlers.iter())
+            .filter_map(|h| h.group.as_ref())
+        {
+            match groups.iter_mut().find(|g| g.filter.name == group.name) {
+                Some(info) => info.handlers += 1,
+                None => groups.push(GroupInfo {
+                    filter: GroupFilter::clone(group),
+                    handlers: 1,
+                }),
+            }
+        }
+
+        groups.sort_by(|a, b| a.filter.name.cmp(&b.filter.name));
+        groups
+    }
+
+    /// Get the flag provider, the flag context of the delivery and the
+    /// failure policy
+    async fn resolve_flags(
+        &self,
+        context: &Context,
+    ) -> (Arc<dyn FeatureFlags>, FlagContext, FlagFailurePolicy) {
+        let config = self.config.read().await;
+        let provider = self
+            .feature_flags()
+            .unwrap_or_else(|| Arc::new(config.feature_flags.clone()));
+        (
+            provider,
+            FlagContext::of(context),
+            config.flag_failure_policy,
+        )
+    }
+
+    /// Cache the installation access sent in `installation` and
+    /// `installation_repositories` events, and forget it for deleted
+    /// installations
+    fn track_installation_access(&self, context: &Context) {
+        let (Some(client), Some(event)) = (&self.github_client, context.event()) else {
+            return;
+        };
+
+        match &event.specific {
+            WebhookEventPayload::Installation(payload)
+                if payload.action == InstallationWebhookEventAction::Deleted =>
+            {
+                if let Some(installation_id) = context.installation_id() {
+                    client.forget_installation(installation_id);
+                }
+            }
+            WebhookEventPayload::Installation(_)
+            | WebhookEventPayload::InstallationRepositories(_) => {
+                if let Some(access) = &context.installation_access {
+                    client.cache_installation(access.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Invalidate the caches keyed by the old full name of a repository
+    /// renamed or transferred by a `repository` event
+    fn track_repository_identity(&self, context: &Context) {
+        let Some(change) = context.repo_identity_change() else {
+            return;
+        };
+
+        debug!(
+            "Repository {} is now {}, forgetting cached data",
+            change.old_full_name, change.new_full_name
+        );
+        self.permission_cache
+            .forget_repository(&change.old_full_name);
+        self.repo_config_cache
+            .forget_repository(&change.old_full_name);
+        if let Some(client) = &self.github_client {
+            client.forget_repository(&change.old_full_name);
+        }
+    }
+
+    /// Forget the cached data keyed by the old login of a renamed account
+    fn track_account_rename(&self, context: &Context) {
+        let Some(rename) = context.installation_target_change() else {
+            return;
+        };
+
+        debug!(
+            "Account {} is now {}, forgetting cached data",
+            rename.old_login, rename.new_login
+        );
+        self.permission_cache.forget_owner(&rename.old_login);
+        self.repo_config_cache.forget_owner(&rename.old_login);
+        if let Some(client) = &self.github_client {
+            client.forget_owner(&rename.old_login);
+        }
+    }
+
+    /// Evict the cached repository configuration files changed by a push to
+    /// the default branch
+    fn track_config_changes(&self, context: &Context) {
+        let forgotten = self.repo_config_cache.forget_pushed(context);
+        if forgotten > 0 {
+            debug!("Push changed {} cached configuration files", forgotten);
+        }
+    }
+
+    /// Update the suspended installations for `installation.suspend` and
+    /// `installation.unsuspend` events
+    async fn track_suspension(&self, context: &Context) {
+        let (Some(client), Some(installation_id), Some(event)) = (
+            &self.github_client,
+            context.installation_id(),
+            context.event(),
+        ) else {
+            return;
+        };
+
+        if let WebhookEventPayload::Installation(payload) = &event.specific {
+            match payload.action {
+                InstallationWebhookEventAction::Suspend => {
+                    client.set_suspended(installation_id, true).await
+                }
+                InstallationWebhookEventAction::Unsuspend => {
+                    client.set_suspended(installation_id, false).await
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Error returned when registering a handler for an invalid event name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidEventName {
+    /// The event name is empty
+    Empty,
+    /// The event name is not a known webhook event type
+    Unknown {
+        /// The normalized event name
+        name: String,
+        /// A known event name that is likely meant instead
+        suggestion: Option<String>,
+    },
+}
+
+impl fmt::Display for InvalidEventName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Event name must not be empty"),
+            Self::Unknown {
+                name,
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "Unknown event name '{}' (did you mean '{}'?); use on_unchecked for custom events",
+                name, suggestion
+            ),
+            Self::Unknown { name, .. } => write!(
+                f,
+                "Unknown event name '{}'; use on_unchecked for custom events",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidEventName {}
+
+/// Normalize an event name and check that it is a known webhook event type
+///
+/// Names are trimmed and lowercased, so `" Issues "` becomes `issues`.
+///
+/// # Errors
+///
+/// Returns an [`InvalidEventName`] error if the name is empty or unknown.
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::dispatch::{normalize_event_name, InvalidEventName};
+///
+/// assert_eq!(normalize_event_name("Pull_Request").unwrap(), "pull_request");
+/// assert!(matches!(
+///     normalize_event_name("issue"),
+///     Err(InvalidEventName::Unknown { suggestion: Some(_), .. })
+/// ));
+/// ```
+pub fn normalize_event_name(name: &str) -> Result<WebhookEventKind, InvalidEventName> {
+    let name = normalize_unchecked(name)?;
+    if is_known_event(&name) {
+        return Ok(name);
+    }
+
+    // Catch the common singular/plural mix-ups, e.g. `issue` for `issues`
+    let suggestion = [format!("{}s", name), name.trim_end_matches('s').to_string()]
+        .into_iter()
+        .find(|candidate| *candidate != name && is_known_event(candidate));
+    Err(InvalidEventName::Unknown {
+        name: name.into_string(),
+        suggestion,
+    })
+}
+
+pub(crate) fn normalize_unchecked(name: &str) -> Result<WebhookEventKind, InvalidEventName> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(InvalidEventName::Empty);
+    }
+    Ok(WebhookEventKind::new(name))
+}
+
+/// Complete a registration, or log why the handler registered at `source`
+/// could not be registered
+///
+/// A failed registration yields a [`HandlerRegistration`] of a handler that
+/// never runs, so callers of the infallible `on` methods keep working.
+pub(crate) async fn registration_or_log(
+    registration: Result<impl Future<Output = HandlerRegistration>, InvalidEventName>,
+    source: HandlerSource,
+) -> HandlerRegistration {
+    match registration {
+        Ok(registration) => registration.await,
+        Err(e) => {
+            error!("Handler {} is not registered: {}", source, e);
+            HandlerRegistration::detached()
+        }
+    }
+}
+
+/// Order handlers the way they run, with their registration index
+///
+/// High-priority handlers run first, so slower handlers of the same delivery
+/// cannot delay them. Within each lane, handlers run by descending priority,
+/// then in registration order.
+fn execution_order(
+    handlers: &[Arc<RegisteredHandler>],
+) -> impl Iterator<Item = (usize, &RegisteredHandler)> {
+    let mut ordered: Vec<_> = handlers
+        .iter()
+        .enumerate()
+        .map(|(index, handler)| (index, handler, handler.options()))
+        .collect();
+    ordered.sort_by_key(|(_, _, options)| {
+        (!options.high_priority, std::cmp::Reverse(options.priority))
+    });
+    ordered
+        .into_iter()
+        .map(|(index, handler, _)| (index, handler.as_ref()))
+}
+
+fn is_known_event(name: &str) -> bool {
+    WebhookEventKind::from(name).is_known()
+}
+
+/// Evaluate the `flag` of `handler`, applying `policy` if `flags` fails
+async fn evaluate_flag(
+    flags: &dyn FeatureFlags,
+    handler: String,
+    flag: String,
+    context: &FlagContext,
+    policy: FlagFailurePolicy,
+) -> FlagEvaluation {
+    let (enabled, error) = match flags.enabled(&flag, context).await {
+        Ok(enabled) => (enabled, None),
+        Err(e) => {
+            warn!(
+                "Failed to evaluate flag '{}' of handler '{}', failing {}: {:#}",
+                flag, handler, policy, e
+            );
+            (policy == FlagFailurePolicy::Open, Some(format!("{:#}", e)))
+        }
+    };
+    FlagEvaluation {
+        handler,
+        flag,
+        enabled,
+        error,
+    }
+}
+
+/// Whether a handler skipping drafts by `filter` should skip the delivery
+///
+/// Failed lookups of associated pull requests do not skip it.
+async fn is_draft(context: &Context, filter: DraftFilter) -> bool {
+    match filter {
+        DraftFilter::PullRequest => context.is_draft_pr() == Some(true),
+        DraftFilter::AssociatedPullRequests => {
+            match context.associated_pull_requests_are_drafts().await {
+                Ok(draft) => draft == Some(true),
+                Err(e) => {
+                    warn!(
+                        "Cannot tell whether pull requests are drafts, not skipping: {:#}",
+                        e
+                    );
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Check a handler's changed-file `patterns` against the delivery's pull
+/// request, attaching the matching files to `context`
+///
+/// Returns whether the handler should run: unless the delivery changes a pull
+/// request's files and they could be listed in full without a match.
+async fn matches_changed_files(
+    context: &mut Context,
+    patterns: &[String],
+    max_pages: usize,
+) -> bool {
+    if !context.changes_pull_request_files() {
+        return true;
+    }
+    if !matches!(context.installation_client().await, Ok(Some(_))) {
+        debug!("No installation client to list changed files, not filtering");
+        return true;
+    }
+
+    match context.changed_files(max_pages).await {
+        Ok(files) => {
+            let matched = files.matching(patterns);
+            let run = !matched.is_empty() || files.truncated;
+            context.matched_files = Some(Arc::new(matched));
+            run
+        }
+        Err(e) => {
+            warn!("Failed to list changed files, not filtering: {:#}", e);
+            true
+        }
+    }
+}
+
+/// Statistics of a single webhook delivery
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DispatchReport {
+    /// Event type of the delivery
+    pub event: WebhookEventKind,
+    /// Number of handlers invoked
+    pub handlers: usize,
+    /// GitHub API requests performed by budgeted handlers
+    pub api_calls: usize,
+    /// Number of handlers that hit their API budget
+    pub budgets_exceeded: usize,
+    /// Mutating GitHub API requests performed by handlers, see the
+    /// [`audit`](crate::github::layers::audit) module
+    pub audited_calls: usize,
+    /// Whether dispatch was cut short because the installation is suspended
+    pub installation_suspended: bool,
+    /// Whether the delivery is older than one already seen for the same subject
+    pub out_of_order: bool,
+    /// Whether handlers were skipped because the app itself caused the event
+    pub caused_by_self: bool,
+    /// Whether handlers not allowing it were skipped because the event is
+    /// [stale](crate::staleness)
+    pub stale: bool,
+    /// Results of the handlers that ran, in the order they ran
+    pub results: Vec<HandlerResult>,
+    /// Evaluations of the flags of handlers registered
+    /// [behind a flag](crate::core::HandlerRegistration::behind_flag)
+    pub flags: Vec<FlagEvaluation>,
+    /// Names of the handlers skipped because the delivery was not in their
+    /// [sample](crate::sampling)
+    pub sampled_out: Vec<String>,
+    /// Outcome of the comment sections queued by the handlers
+    pub comment_sections: Vec<QueuedSectionResult>,
+    /// Notes the handlers added with [`Context::note`]
+    pub notes: Vec<ReportNote>,
+    /// Writes intercepted from the handlers in dry-run mode, in the order
+    /// they were sent, see the [`dry_run`](crate::github::layers::dry_run)
+    /// module
+    pub intended_actions: Vec<IntendedAction>,
+    /// Time spent dispatching the delivery
+    pub duration: Duration,
+}
+
+/// Result of a single handler invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerResult {
+    /// Position of the handler among the handlers registered for the event type
+    pub index: usize,
+    /// Name of the handler, see [`RegisteredHandler::name`]
+    pub name: String,
+    /// Time the handler took
+    pub duration: Duration,
+    /// Error the handler failed with, if any
+    pub error: Option<String>,
+    /// Error policy of the handler; failures of ignored handlers did not
+    /// fail the delivery
+    pub error_policy: ErrorPolicy,
+}
+
+impl HandlerResult {
+    /// Whether the handler succeeded
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl DispatchReport {
+    /// Record the invocation of one handler
+    fn record(&mut self, name: &str, budget: Option<&ApiBudget>, audit: &AuditTrail) {
+        self.handlers += 1;
+        self.audited_calls += audit.recorded();
+
+        if let Some(budget) = budget {
+            self.api_calls += budget.used();
+            if budget.is_exceeded() {
+                self.budgets_exceeded += 1;
+                warn!(
+                    "Handler '{}' exceeded its API budget ({}/{} requests)",
+                    name,
+                    budget.used(),
+                    budget.limit()
+                );
+            }
+        }
+    }
+
+    /// Log the post-delivery summary
+    ///
+    /// The number of handlers skipped by [sampling](crate::sampling) is
+    /// logged in the `sampled_out` field, and whether the delivery was
+    /// [stale](crate::staleness) in the `stale` field, so sampled and stale
+    /// deliveries can be counted from the logs. Writes intercepted in
+    /// [dry-run mode](crate::github::layers::dry_run) are counted in the
+    /// `intended_actions` field.
+    fn log(&self) {
+        let sampled_out = self.sampled_out.len();
+        let stale = self.stale;
+        let intended_actions = self.intended_actions.len();
+        if self.budgets_exceeded > 0 {
+            warn!(
+                sampled_out,
+                stale,
+                intended_actions,
+                "Delivery of {} event: {} handler(s), {} API call(s), {} budget(s) exceeded",
+                self.event,
+                self.handlers,
+                self.api_calls,
+                self.budgets_exceeded
+            );
+        } else {
+            info!(
+                sampled_out,
+                stale,
+                intended_actions,
+                "Delivery of {} event: {} handler(s), {} API call(s)",
+                self.event,
+                self.handlers,
+                self.api_calls
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sign, user, webhook_event, MockGitHub, TEST_INSTALLATION_ID};
+    use crate::SerdeToString;
+    use octocrab::models::webhook_events::WebhookEventType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const SECRET: &str = "test-secret";
+
+    fn delivery(event: &str, body: &[u8]) -> HeaderMap {
+        let signature = sign(SECRET, body);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(GITHUB_EVENT_HEADER, event.parse().unwrap());
+        headers.insert(WEBHOOK_HEADER_NAME, signature.parse().unwrap());
+        headers
+    }
+
+    fn ping_body() -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "zen": "Keep it logically awesome.",
+            "hook_id": 1,
+            "installation": { "id": 42, "node_id": "I_42" },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_verify_and_parse() {
+        let dispatcher = Dispatcher::new(None);
+        let body = ping_body();
+        let headers = delivery("ping", &body);
+
+        let context = dispatcher
+            .verify_and_parse(&headers, &body, SECRET)
+            .unwrap();
+        assert_eq!(context.kind(), WebhookEventType::Ping.to_string());
+        assert_eq!(context.installation_id(), Some(42));
+
+        assert!(dispatcher
+            .verify_and_parse(&headers, &body, "wrong-secret")
+            .is_err());
+        assert!(dispatcher
+            .verify_and_parse(&headers, b"{\"tampered\": true}", SECRET)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_runs_registered_handlers() {
+        let dispatcher = Dispatcher::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..2 {
+            dispatcher
+                .on(
+                    WebhookEventType::Ping.to_string(),
+                    |_context: Context, calls: Arc<AtomicUsize>| async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        Ok(())
+                    },
+                    calls.clone(),
+                )
+                .await;
+        }
+
+        let context = dispatcher
+            .parse(&delivery("ping", &ping_body()), &ping_body())
+            .unwrap();
+        let report = dispatcher.dispatch(context).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(report.handlers, 2);
+        assert_eq!(report.api_calls, 0);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_registration_during_delivery() {
+        use tokio::sync::Notify;
+
+        let dispatcher = Dispatcher::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+        // Notified when the handler is entered, and to let it return
+        let gates = Arc::new((Notify::new(), Notify::new()));
+        dispatcher
+            .on(
+                WebhookEventType::Ping.to_string(),
+                |_context: Context, gates: Arc<(Notify, Notify)>| async move {
+                    gates.0.notify_one();
+                    gates.1.notified().await;
+                    Ok(())
+                },
+                gates.clone(),
+            )
+            .await;
+
+        let in_flight = {
+            let dispatcher = dispatcher.clone();
+            let context = dispatcher
+                .parse(&delivery("ping", &ping_body()), &ping_body())
+                .unwrap();
+            tokio::spawn(async move { dispatcher.dispatch_with_report(context).await })
+        };
+        gates.0.notified().await;
+
+        // Registering does not wait for the delivery in flight
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            dispatcher.on(
+                WebhookEventType::Ping.to_string(),
+                |_context: Context, calls: Arc<AtomicUsize>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                },
+                calls.clone(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        // The delivery in flight keeps its handlers, later ones see the new one
+        gates.1.notify_one();
+        let (report, result) = in_flight.await.unwrap();
+        result.unwrap();
+        assert_eq!(report.handlers, 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // Open the gate of the first handler up front this time
+        gates.1.notify_one();
+        let context = dispatcher
+            .parse(&delivery("ping", &ping_body()), &ping_body())
+            .unwrap();
+        let report = dispatcher.dispatch(context).await.unwrap();
+        assert_eq!(report.handlers, 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            dispatcher.handler_counts().await,
+            [(WebhookEventKind::from("ping"), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_events_unknown_to_octocrab_are_routed() {
+        let dispatcher = Dispatcher::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+        dispatcher
+            .on_unchecked(
+                "copilot_usage",
+                |_context: Context, calls: Arc<AtomicUsize>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                },
+                calls.clone(),
+            )
+            .await;
+
+        let context = dispatcher
+            .parse(&delivery("copilot_usage", &ping_body()), &ping_body())
+            .unwrap();
+        assert_eq!(context.kind(), "copilot_usage");
+        dispatcher.dispatch(context).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_stops_at_first_error() {
+        let dispatcher = Dispatcher::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        dispatcher
+            .on(
+                WebhookEventType::Ping.to_string(),
+                |_context: Context, _extra: Arc<()>| async move { Err(anyhow!("boom")) },
+                Arc::new(()),
+            )
+            .await;
+        dispatcher
+            .on(
+                WebhookEventType::Ping.to_string(),
+                |_context: Context, calls: Arc<AtomicUsize>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                },
+                calls.clone(),
+            )
+            .await;
+
+        let context = dispatcher
+            .parse(&delivery("ping", &ping_body()), &ping_body())
+            .unwrap();
+        let err = dispatcher.dispatch(context).await.unwrap_err();
+
+        assert_eq!(err.to_string(), "boom");
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_isolated_failures_fail_only_alone() {
+        let dispatcher = Dispatcher::new(None);
+        let ping = || {
+            dispatcher
+                .parse(&delivery("ping", &ping_body()), &ping_body())
+                .unwrap()
+        };
+        let fail = |_context: Context, _extra: Arc<()>| async { Err(anyhow!("boom")) };
+
+        dispatcher
+            .on("ping", fail, Arc::new(()))
+            .await
+            .error_policy(ErrorPolicy::Isolate);
+        let err = dispatcher.dispatch(ping()).await.unwrap_err();
+        assert_eq!(err.to_string(), "boom");
+
+        dispatcher
+            .on(
+                "ping",
+                |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                Arc::new(()),
+            )
+            .await;
+        let report = dispatcher.dispatch(ping()).await.unwrap();
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(report.results[0].error.as_deref(), Some("boom"));
+        assert_eq!(report.results[0].error_policy, ErrorPolicy::Isolate);
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_handlers_run_first() {
+        let dispatcher = Dispatcher::new(None);
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        for name in ["regular", "urgent"] {
+            let registration = dispatcher
+                .on(
+                    WebhookEventType::Ping.to_string(),
+                    move |_context: Context, order: Arc<std::sync::Mutex<Vec<&str>>>| async move {
+                        order.lock().unwrap().push(name);
+                        Ok(())
+                    },
+                    order.clone(),
+                )
+                .await;
+            if name == "urgent" {
+                registration.high_priority();
+            }
+        }
+
+        let context = dispatcher
+            .parse(&delivery("ping", &ping_body()), &ping_body())
+            .unwrap();
+        let report = dispatcher.dispatch(context).await.unwrap();
+
+        assert_eq!(*order.lock().unwrap(), ["urgent", "regular"]);
+        assert_eq!(report.results[0].index, 1);
+    }
+
+    #[tokio::test]
+    async fn test_handlers_run_by_priority() {
+        let dispatcher = Dispatcher::new(None);
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        for (name, priority) in [("action", 0), ("guard", 10), ("audit", 0), ("cleanup", -5)] {
+            dispatcher
+                .on(
+                    "ping",
+                    move |_context: Context, order: Arc<std::sync::Mutex<Vec<&str>>>| async move {
+                        order.lock().unwrap().push(name);
+                        Ok(())
+                    },
+                    order.clone(),
+                )
+                .await
+                .named(name)
+                .priority(priority);
+        }
+
+        let context = dispatcher
+            .parse(&delivery("ping", &ping_body()), &ping_body())
+            .unwrap();
+        dispatcher.dispatch(context).await.unwrap();
+
+        let expected = ["guard", "action", "audit", "cleanup"];
+        assert_eq!(*order.lock().unwrap(), expected);
+        assert_eq!(dispatcher.handler_names().await[0].1, expected);
+    }
+
+    #[tokio::test]
+    async fn test_events_from_bots_and_the_app_itself() {
+        use crate::testing::{issues_payload, TEST_APP_SLUG};
+
+        let mock = MockGitHub::start(axum::Router::new()).await;
+        let dispatcher = Dispatcher::new(Some(Arc::new(mock.client())));
+        for name in ["any-sender", "humans-only"] {
+            let registration = dispatcher
+                .on(
+                    WebhookEventType::Issues.to_string(),
+                    |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                    Arc::new(()),
+                )
+                .await
+                .named(name);
+            if name == "humans-only" {
+                registration.include_bots(false);
+            }
+        }
+
+        let sent_by = |login: &str, r#type: &str| {
+            let mut payload = issues_payload("opened", 1);
+            payload["sender"] = user(login);
+            payload["sender"]["type"] = r#type.into();
+            dispatcher.context(webhook_event("issues", payload))
+        };
+        let ran = |report: &DispatchReport| -> Vec<String> {
+            report.results.iter().map(|r| r.name.clone()).collect()
+        };
+        let own_login = format!("{}[bot]", TEST_APP_SLUG);
+
+        let context = sent_by(&own_login, "Bot");
+        assert!(context.sent_by_self() && context.sent_by_bot());
+        let report = dispatcher.dispatch(context).await.unwrap();
+        assert!(report.caused_by_self);
+        assert!(report.results.is_empty());
+
+        let context = sent_by("dependabot[bot]", "Bot");
+        assert!(!context.sent_by_self() && context.sent_by_bot());
+        let report = dispatcher.dispatch(context).await.unwrap();
+        assert!(!report.caused_by_self);
+        assert_eq!(ran(&report), ["any-sender"]);
+
+        let context = sent_by("octocat", "User");
+        assert!(!context.sent_by_self() && !context.sent_by_bot());
+        let report = dispatcher.dispatch(context).await.unwrap();
+        assert_eq!(ran(&report), ["any-sender", "humans-only"]);
+
+        dispatcher
+            .set_config(DispatchConfig {
+                ignore_self: false,
+                ..Default::default()
+            })
+            .await;
+        let report = dispatcher
+            .dispatch(sent_by(&own_login, "Bot"))
+            .await
+            .unwrap();
+        assert_eq!(ran(&report), ["any-sender"]);
+
+        // Without a known slug the app cannot recognize itself
+        let context = Dispatcher::new(None).context(webhook_event("issues", {
+            let mut payload = issues_payload("opened", 1);
+            payload["sender"] = user(&own_login);
+            payload
+        }));
+        assert!(!context.sent_by_self());
+    }
+
+    #[tokio::test]
+    async fn test_trusted_only_handlers_skip_forks() {
+        use crate::testing::{pull_request_payload, repository};
+
+        let mock = MockGitHub::start(axum::Router::new().route(
+            "/repos/octofer/app/collaborators/{user}/permission",
+            axum::routing::get(
+                |axum::extract::Path(user): axum::extract::Path<String>| async move {
+                    let permission = if user == "fork-maintainer" {
+                        "admin"
+                    } else {
+                        "read"
+                    };
+                    axum::Json(serde_json::json!({ "permission": permission }))
+                },
+            ),
+        ))
+        .await;
+        let dispatcher = Dispatcher::new(Some(Arc::new(mock.client())));
+        for name in ["anyone", "trusted"] {
+            let registration = dispatcher
+                .on(
+                    WebhookEventType::PullRequest.to_string(),
+                    |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                    Arc::new(()),
+                )
+                .await
+                .named(name);
+            if name == "trusted" {
+                registration.trusted_only();
+            }
+        }
+
+        let pull_request = |head_owner: &str, sender: &str| {
+            let mut payload = pull_request_payload("opened", 3, "abc123");
+            payload["pull_request"]["head"]["repo"] = repository(head_owner, "app");
+            payload["pull_request"]["base"]["repo"] = repository("octofer", "app");
+            payload["sender"] = user(sender);
+            let mut context = dispatcher.context(webhook_event("pull_request", payload));
+            context.installation_id = Some(1);
+            context
+        };
+        let ran = |report: &DispatchReport| -> Vec<String> {
+            report.results.iter().map(|r| r.name.clone()).collect()
+        };
+
+        let report = dispatcher
+            .dispatch(pull_request("octofer", "fork-maintainer"))
+            .await
+            .unwrap();
+        assert_eq!(ran(&report), ["anyone", "trusted"]);
+
+        let report = dispatcher
+            .dispatch(pull_request("contributor", "fork-maintainer"))
+            .await
+            .unwrap();
+        assert_eq!(ran(&report), ["anyone"]);
+
+        let report = dispatcher
+            .dispatch(pull_request("octofer", "fork-reader"))
+            .await
+            .unwrap();
+        assert_eq!(ran(&report), ["anyone"]);
+    }
+
+    #[tokio::test]
+    async fn test_handlers_behind_flags() {
+        use crate::flags::StaticFeatureFlags;
+        use crate::testing::issues_payload;
+
+        struct Failing;
+
+        impl FeatureFlags for Failing {
+            fn enabled<'a>(
+                &'a self,
+                _flag: &'a str,
+                _context: &'a FlagContext,
+            ) -> futures::future::BoxFuture<'a, Result<bool>> {
+                Box::pin(async { Err(anyhow!("flag service unavailable")) })
+            }
+        }
+
+        let dispatcher = Dispatcher::new(None);
+        dispatcher
+            .set_config(DispatchConfig {
+                feature_flags: StaticFeatureFlags::new().enable_for("beta", "octofer/app"),
+                ..Default::default()
+            })
+            .await;
+        for (name, flag) in [
+            ("always", None),
+            ("beta", Some("beta")),
+            ("other", Some("other")),
+        ] {
+            let registration = dispatcher
+                .on(
+                    WebhookEventType::Issues.to_string(),
+                    |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                    Arc::new(()),
+                )
+                .await
+                .named(name);
+            if let Some(flag) = flag {
+                registration.behind_flag(flag);
+            }
+        }
+        let dispatch = || async {
+            let context = dispatcher.context(webhook_event("issues", issues_payload("opened", 1)));
+            let report = dispatcher.dispatch(context).await.unwrap();
+            let ran: Vec<String> = report.results.iter().map(|r| r.name.clone()).collect();
+            (ran, report.flags)
+        };
+
+        let (ran, flags) = dispatch().await;
+        assert_eq!(ran, ["always", "beta"]);
+        assert_eq!(
+            flags,
+            [
+                FlagEvaluation {
+                    handler: "beta".to_string(),
+                    flag: "beta".to_string(),
+                    enabled: true,
+                    error: None,
+                },
+                FlagEvaluation {
+                    handler: "other".to_string(),
+                    flag: "other".to_string(),
+                    enabled: false,
+                    error: None,
+                },
+            ]
+        );
+
+        // Provider errors skip the handlers by default, or run them when
+        // failing open
+        dispatcher.set_feature_flags(Failing);
+        let (ran, flags) = dispatch().await;
+        assert_eq!(ran, ["always"]);
+        assert!(flags.iter().all(|evaluation| !evaluation.enabled
+            && evaluation.error.as_deref() == Some("flag service unavailable")));
+
+        let mut config = dispatcher.config().await;
+        config.flag_failure_policy = FlagFailurePolicy::Open;
+        dispatcher.set_config(config).await;
+        let (ran, flags) = dispatch().await;
+        assert_eq!(ran, ["always", "beta", "other"]);
+        assert!(flags
+            .iter()
+            .all(|evaluation| evaluation.enabled && evaluation.error.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_sampled_handlers() {
+        use crate::sampling::SampleRates;
+        use crate::testing::issues_payload;
+
+        let dispatcher = Dispatcher::new(None);
+        for (name, rate) in [("all", None), ("sampled", Some(0.1))] {
+            let registration = dispatcher
+                .on(
+                    WebhookEventType::Issues.to_string(),
+                    |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                    Arc::new(()),
+                )
+                .await
+                .named(name);
+            if let Some(rate) = rate {
+                registration.sample_rate(rate);
+            }
+        }
+        let dispatcher = &dispatcher;
+        let dispatch = |delivery: usize| async move {
+            let mut context =
+                dispatcher.context(webhook_event("issues", issues_payload("opened", 1)));
+            context.delivery_id = Some(format!("delivery-{}", delivery));
+            let report = dispatcher.dispatch(context).await.unwrap();
+            let ran: Vec<String> = report.results.iter().map(|r| r.name.clone()).collect();
+            (ran, report.sampled_out)
+        };
+
+        let mut sampled = 0;
+        for delivery in 0..1_000 {
+            let (ran, sampled_out) = dispatch(delivery).await;
+            if ran.len() == 2 {
+                sampled += 1;
+                assert!(sampled_out.is_empty());
+            } else {
+                assert_eq!(ran, ["all"]);
+                assert_eq!(sampled_out, ["sampled"]);
+            }
+            // Redeliveries are decided the same way
+            assert_eq!(dispatch(delivery).await.0, ran);
+        }
+        assert!((70..=130).contains(&sampled), "{} sampled", sampled);
+
+        // Event types sampled by the configuration skip every handler
+        dispatcher
+            .set_config(DispatchConfig {
+                sample_rates: SampleRates::new().with("issues", 0.0),
+                ..Default::default()
+            })
+            .await;
+        let (ran, sampled_out) = dispatch(0).await;
+        assert!(ran.is_empty());
+        assert_eq!(sampled_out, ["all", "sampled"]);
+    }
+
+    #[tokio::test]
+    async fn test_stale_deliveries_skip_handlers() {
+        use crate::testing::issues_payload;
+
+        let dispatcher = Dispatcher::new(None);
+        for (name, allow_stale) in [("triage", false), ("audit", true)] {
+            let registration = dispatcher
+                .on(
+                    WebhookEventType::Issues.to_string(),
+                    |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                    Arc::new(()),
+                )
+                .await
+                .named(name);
+            if allow_stale {
+                registration.allow_stale();
+            }
+        }
+        let dispatcher = &dispatcher;
+        let dispatch = |updated_at: String| async move {
+            let mut payload = issues_payload("labeled", 1);
+            payload["issue"]["updated_at"] = serde_json::json!(updated_at);
+            let context = dispatcher.context(webhook_event("issues", payload));
+            let report = dispatcher.dispatch(context).await.unwrap();
+            let ran: Vec<String> = report.results.iter().map(|r| r.name.clone()).collect();
+            (ran, report.stale)
+        };
+        let hours_ago = |hours| (Utc::now() - chrono::Duration::hours(hours)).to_rfc3339();
+
+        // Without a limit, events never go stale
+        assert_eq!(
+            dispatch(hours_ago(48)).await,
+            (vec!["triage".into(), "audit".into()], false)
+        );
+
+        dispatcher
+            .set_config(DispatchConfig {
+                max_event_age_secs: Some(6 * 3600),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(dispatch(hours_ago(1)).await.0, ["triage", "audit"]);
+        assert_eq!(dispatch(hours_ago(7)).await, (vec!["audit".into()], true));
+    }
+
+    #[tokio::test]
+    async fn test_state_is_shared_by_handlers() {
+        use crate::testing::issues_payload;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let dispatcher = Dispatcher::new(None);
+        dispatcher.state().insert(AtomicU64::new(0));
+        dispatcher
+            .state()
+            .insert(tokio::sync::Mutex::new(Vec::<u64>::new()));
+        dispatcher
+            .on(
+                WebhookEventType::Issues.to_string(),
+                |context: Context, _extra: Arc<()>| async move {
+                    context
+                        .state::<AtomicU64>()?
+                        .fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await;
+        dispatcher
+            .on(
+                WebhookEventType::Issues.to_string(),
+                |context: Context, _extra: Arc<()>| async move {
+                    let count = context.state::<AtomicU64>()?.load(Ordering::Relaxed);
+                    context.state_mut::<Vec<u64>>()?.lock().await.push(count);
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await;
+
+        for _ in 0..2 {
+            let context = dispatcher.context(webhook_event("issues", issues_payload("opened", 1)));
+            dispatcher.dispatch(context).await.unwrap();
+        }
+        let seen = dispatcher
+            .state()
+            .get::<tokio::sync::Mutex<Vec<u64>>>()
+            .unwrap();
+        assert_eq!(*seen.lock().await, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_draft_pull_requests_skip_handlers() {
+        use crate::testing::{pull_request_payload, repository};
+        use serde_json::json;
+
+        let mut app = crate::Octofer::new_default();
+        let ran = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            move |_context: Context, ran: Arc<std::sync::Mutex<Vec<&'static str>>>| async move {
+                ran.lock().unwrap().push(name);
+                Ok(())
+            }
+        };
+        app.on_pull_request(record("ci"), ran.clone())
+            .await
+            .skip_drafts();
+        app.on_pull_request(record("all"), ran.clone()).await;
+        app.on_pull_request_ready_for_review(record("ready"), ran.clone())
+            .await;
+        app.on_check_run(record("checks"), ran.clone())
+            .await
+            .skip_drafts();
+
+        let dispatcher = app.dispatcher();
+        let deliver = |event: &str, payload| {
+            let context = dispatcher.context(webhook_event(event, payload));
+            let ran = ran.clone();
+            async move {
+                dispatcher.dispatch(context).await.unwrap();
+                std::mem::take(&mut *ran.lock().unwrap())
+            }
+        };
+        let pull_request = |action: &str, draft: bool| {
+            let mut payload = pull_request_payload(action, 1, "abc123");
+            payload["pull_request"]["draft"] = json!(draft);
+            payload
+        };
+        assert_eq!(
+            deliver("pull_request", pull_request("opened", true)).await,
+            ["all"]
+        );
+        assert_eq!(
+            deliver("pull_request", pull_request("ready_for_review", false)).await,
+            ["ci", "all", "ready"]
+        );
+        assert_eq!(
+            deliver("pull_request", pull_request("synchronize", false)).await,
+            ["ci", "all"]
+        );
+
+        // Without opting in to lookups, check events always run
+        let check_run = json!({
+            "action": "completed",
+            "check_run": { "id": 1, "head_sha": "abc123", "pull_requests": [] },
+            "repository": repository("octofer", "app"),
+            "sender": user("ci-bot"),
+        });
+        assert_eq!(deliver("check_run", check_run).await, ["checks"]);
+    }
+
+    #[tokio::test]
+    async fn test_handlers_filtered_by_changed_files() {
+        use crate::testing::pull_request_payload;
+
+        let mock = MockGitHub::start(axum::Router::new().route(
+            "/repos/octofer/app/pulls/{number}/files",
+            axum::routing::get(
+                |axum::extract::Path(number): axum::extract::Path<u64>| async move {
+                    let filename = if number == 1 {
+                        "docs/intro.md"
+                    } else {
+                        "src/lib.rs"
+                    };
+                    axum::Json(serde_json::json!([{ "filename": filename }]))
+                },
+            ),
+        ))
+        .await;
+        let dispatcher = Dispatcher::new(Some(Arc::new(mock.client())));
+        let matched = Arc::new(std::sync::Mutex::new(Vec::new()));
+        for name in ["docs-check", "docs-lint"] {
+            dispatcher
+                .on(
+                    WebhookEventType::PullRequest.to_string(),
+                    |context: Context, matched: Arc<std::sync::Mutex<Vec<Option<Vec<String>>>>>| async move {
+                        matched
+                            .lock()
+                            .unwrap()
+                            .push(context.matched_files().map(<[String]>::to_vec));
+                        Ok(())
+                    },
+                    matched.clone(),
+                )
+                .await
+                .named(name)
+                .when_files_changed(&["docs/**"]);
+        }
+        let pull_request = |action: &str, number: u64| {
+            let mut context = dispatcher.context(webhook_event(
+                "pull_request",
+                pull_request_payload(action, number, "abc123"),
+            ));
+            context.installation_id = Some(1);
+            context
+        };
+
+        let report = dispatcher
+            .dispatch(pull_request("synchronize", 1))
+            .await
+            .unwrap();
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(
+            *matched.lock().unwrap(),
+            [
+                Some(vec!["docs/intro.md".to_string()]),
+                Some(vec!["docs/intro.md".to_string()])
+            ]
+        );
+        // Both handlers share one listing
+        assert_eq!(mock.requests().len(), 1);
+
+        let report = dispatcher
+            .dispatch(pull_request("opened", 2))
+            .await
+            .unwrap();
+        assert!(report.results.is_empty());
+
+        // Other actions are not filtered
+        matched.lock().unwrap().clear();
+        let report = dispatcher
+            .dispatch(pull_request("closed", 2))
+            .await
+            .unwrap();
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(*matched.lock().unwrap(), [None, None]);
+        assert_eq!(mock.requests().len(), 2);
+
+        // Without an installation client, handlers run unfiltered
+        let mut context = Dispatcher::new(None).context(webhook_event(
+            "pull_request",
+            pull_request_payload("opened", 2, "abc123"),
+        ));
+        assert!(matches_changed_files(&mut context, &["docs/**".to_string()], 1).await);
+        assert!(context.matched_files().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handlers_are_named_after_their_registration() {
+        let dispatcher = Dispatcher::new(None);
+        dispatcher
+            .on(
+                WebhookEventType::Ping.to_string(),
+                |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                Arc::new(()),
+            )
+            .await
+            .named("auto-labeler");
+        let line = line!() + 2;
+        dispatcher
+            .on(
+                WebhookEventType::Ping.to_string(),
+                |_context: Context, _extra: Arc<()>| async { Err(anyhow!("boom")) },
+                Arc::new(()),
+            )
+            .await;
+
+        let names = dispatcher.handler_names().await;
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[0].1[0], "auto-labeler");
+        let unnamed = &names[0].1[1];
+        assert!(unnamed.contains("test_handlers_are_named_after_their_registration"));
+        assert!(unnamed.ends_with(&format!("(src/dispatch.rs:{})", line)));
+
+        let context = dispatcher
+            .parse(&delivery("ping", &ping_body()), &ping_body())
+            .unwrap();
+        let (report, result) = dispatcher.dispatch_with_report(context).await;
+        assert!(result.is_err());
+        assert_eq!(report.results[0].name, "auto-labeler");
+        assert_eq!(&report.results[1].name, unnamed);
+    }
+
+    fn installation_payload(action: &str) -> serde_json::Value {
+        serde_json::json!({
+            "action": action,
+            "installation": {
+                "id": TEST_INSTALLATION_ID,
+                "account": user("octofer-org"),
+                "permissions": {},
+                "events": [],
+            },
+            "repositories": [],
+            "requester": null,
+            "sender": user("octofer-org"),
+        })
+    }
+
+    #[test]
+    fn test_installation_id_for_event_families() {
+        use crate::testing::{issues_payload, repository};
+        use serde_json::json;
+
+        let with_installation = |mut payload: serde_json::Value| {
+            payload["installation"] = json!({ "id": 42, "node_id": "I_42" });
+            payload
+        };
+        let url = "https://api.github.com/orgs/octofer-org";
+        let organization = json!({
+            "login": "octofer-org",
+            "id": 2,
+            "node_id": "O_2",
+            "url": url,
+            "repos_url": format!("{url}/repos"),
+            "events_url": format!("{url}/events"),
+            "hooks_url": format!("{url}/hooks"),
+            "issues_url": format!("{url}/issues"),
+            "members_url": format!("{url}/members{{/member}}"),
+            "public_members_url": format!("{url}/public_members{{/member}}"),
+            "avatar_url": "https://avatars.githubusercontent.com/u/2",
+        });
+        let mut installation = installation_payload("created");
+        installation["installation"]["id"] = json!(42);
+
+        let cases = [
+            ("issues", with_installation(issues_payload("opened", 1))),
+            ("installation", installation),
+            (
+                "organization",
+                with_installation(json!({
+                    "action": "member_added",
+                    "membership": { "state": "active", "role": "member", "user": user("octocat") },
+                    "organization": organization,
+                    "sender": user("octofer-org"),
+                })),
+            ),
+            (
+                "workflow_run",
+                with_installation(json!({
+                    "action": "completed",
+                    "workflow": null,
+                    "workflow_run": { "id": 3, "head_sha": "d00d" },
+                    "repository": repository("octofer", "app"),
+                    "sender": user("ci-bot"),
+                })),
+            ),
+            (
+                "check_suite",
+                with_installation(json!({
+                    "action": "completed",
+                    "check_suite": { "id": 2, "head_sha": "5ca1ab1e" },
+                    "repository": repository("octofer", "app"),
+                    "sender": user("ci-bot"),
+                })),
+            ),
+        ];
+
+        let dispatcher = Dispatcher::new(None);
+        for (event, payload) in cases {
+            let body = serde_json::to_vec(&payload).unwrap();
+            let context = dispatcher.parse(&delivery(event, &body), &body).unwrap();
+            assert_eq!(context.installation_id(), Some(42), "{}", event);
+        }
+
+        let body = serde_json::to_vec(&issues_payload("opened", 1)).unwrap();
+        let context = dispatcher.parse(&delivery("issues", &body), &body).unwrap();
+        assert_eq!(context.installation_id(), None);
+    }
+
+    #[test]
+    fn test_installation_id_falls_back_to_raw_payload() {
+        use crate::testing::issues_payload;
+        use serde_json::json;
+
+        // An event octocrab parsed without its installation
+        let event = webhook_event("issues", issues_payload("opened", 1));
+        let payload = json!({ "installation": { "id": 7 } });
+        assert_eq!(extract_installation_id(&event, Some(&payload)), Some(7));
+        assert_eq!(extract_installation_id(&event, Some(&json!({}))), None);
+        assert_eq!(extract_installation_id(&event, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_suspended_installation_is_acknowledged() {
+        let mock = MockGitHub::start(axum::Router::new()).await;
+        let client = Arc::new(mock.client());
+        let dispatcher = Dispatcher::new(Some(client.clone()));
+
+        dispatcher
+            .on(
+                WebhookEventType::Installation.to_string(),
+                |context: Context, _extra: Arc<()>| async move {
+                    context.installation_client().await?;
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await;
+
+        let suspend = dispatcher.context(webhook_event(
+            "installation",
+            installation_payload("suspend"),
+        ));
+        let report = dispatcher.dispatch(suspend).await.unwrap();
+        assert!(report.installation_suspended);
+        assert!(client.is_suspended(TEST_INSTALLATION_ID));
+
+        dispatcher
+            .set_config(DispatchConfig {
+                ignore_suspended: false,
+                ..Default::default()
+            })
+            .await;
+        let retry = dispatcher.context(webhook_event(
+            "installation",
+            installation_payload("created"),
+        ));
+        assert!(retry.installation_suspended());
+        let err = dispatcher.dispatch(retry).await.unwrap_err();
+        assert!(is_suspended(&err));
+
+        let unsuspend = dispatcher.context(webhook_event(
+            "installation",
+            installation_payload("unsuspend"),
+        ));
+        let report = dispatcher.dispatch(unsuspend).await.unwrap();
+        assert!(!report.installation_suspended);
+        assert!(!client.is_suspended(TEST_INSTALLATION_ID));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_without_handlers() {
+        let dispatcher = Dispatcher::new(None);
+        let report = dispatcher.dispatch(Context::default()).await.unwrap();
+        assert_eq!(report.handlers, 0);
+    }
+
+    #[test]
+    fn test_normalize_event_name() {
+        assert_eq!(normalize_event_name(" Issues ").unwrap(), "issues");
+        assert_eq!(
+            normalize_event_name(&WebhookEventType::IssueComment.to_string()).unwrap(),
+            "issue_comment"
+        );
+        assert_eq!(normalize_event_name("  "), Err(InvalidEventName::Empty));
+        assert_eq!(
+            normalize_event_name("issue"),
+            Err(InvalidEventName::Unknown {
+                name: "issue".to_string(),
+                suggestion: Some("issues".to_string()),
+            })
+        );
+        assert_eq!(
+            normalize_event_name("pull_requests"),
+            Err(InvalidEventName::Unknown {
+                name: "pull_requests".to_string(),
+                suggestion: Some("pull_request".to_string()),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registration_validates_event_names() {
+        let dispatcher = Dispatcher::new(None);
+        let handler = |_context: Context, _extra: Arc<()>| async { Ok(()) };
+
+        let err = dispatcher
+            .try_on("issue", handler, Arc::new(()))
+            .await
+            .unwrap_err();
+        assert!(err.is::<InvalidEventName>());
+
+        dispatcher.on("ISSUES", handler, Arc::new(())).await;
+        dispatcher.on("issues", handler, Arc::new(())).await;
+        dispatcher
+            .on_unchecked("Custom_Event", handler, Arc::new(()))
+            .await;
+
+        assert_eq!(
+            dispatcher.handler_counts().await,
+            [("custom_event".into(), 1), ("issues".into(), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_event_name_is_not_registered() {
+        let dispatcher = Dispatcher::new(None);
+        let handler = |_context: Context, _extra: Arc<()>| async { Ok(()) };
+        dispatcher
+            .on("issue", handler, Arc::new(()))
+            .await
+            .named("typo")
+            .priority(1);
+        dispatcher.on_unchecked(" ", handler, Arc::new(())).await;
+
+        assert!(dispatcher.handler_counts().await.is_empty());
+    }
+}
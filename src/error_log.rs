@@ -0,0 +1,257 @@
+//! Deduplication of repeated handler error logs
+//!
+//! A broken handler failing on every delivery would log the same error
+//! thousands of times. The dispatcher fingerprints handler errors by event
+//! type, handler name and the start of the error message, and logs the first
+//! [`DispatchConfig::error_log_burst`] errors of a fingerprint in full per
+//! window of [`DispatchConfig::error_log_window_secs`]. Later ones are only
+//! counted, and once the window ends, the next handler failure logs a summary
+//! line such as:
+//!
+//! ```text
+//! Handler 'auto-labeler' on issues failed 412 more time(s) in the last 5m: Label not found
+//! ```
+//!
+//! Only the log volume is reduced: every failure is still recorded in the
+//! [`DispatchReport`](crate::dispatch::DispatchReport) of its delivery.
+//! Setting `error_log_burst` to `None` logs every error.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[cfg(doc)]
+use crate::config::DispatchConfig;
+
+/// Characters of an error message included in its fingerprint
+pub const FINGERPRINT_LENGTH: usize = 200;
+
+/// Maximum number of fingerprints tracked at a time; errors of further
+/// fingerprints are logged in full
+pub const MAX_FINGERPRINTS: usize = 1_000;
+
+/// Event type, handler name and truncated message of a handler error
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Fingerprint {
+    event: String,
+    handler: String,
+    message: String,
+}
+
+/// Errors of a fingerprint in the current window
+#[derive(Debug)]
+struct Occurrences {
+    since: Instant,
+    count: usize,
+    first_line: String,
+}
+
+/// Summary of the errors of a fingerprint not logged during a window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuppressedErrors {
+    /// Event type the handler ran for
+    pub event: String,
+    /// Name of the handler
+    pub handler: String,
+    /// Errors not logged
+    pub count: usize,
+    /// Length of the window
+    pub window: Duration,
+    /// First line of the first error of the window
+    pub first_line: String,
+}
+
+impl fmt::Display for SuppressedErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.window.as_secs();
+        let window = if secs >= 60 && secs.is_multiple_of(60) {
+            format!("{}m", secs / 60)
+        } else {
+            format!("{}s", secs)
+        };
+        write!(
+            f,
+            "Handler '{}' on {} failed {} more time(s) in the last {}: {}",
+            self.handler, self.event, self.count, window, self.first_line
+        )
+    }
+}
+
+/// Counter of handler errors by fingerprint, deciding which are logged
+#[derive(Debug, Default)]
+pub struct ErrorLogLimiter {
+    occurrences: Mutex<HashMap<Fingerprint, Occurrences>>,
+}
+
+impl ErrorLogLimiter {
+    /// Create a limiter without any counted errors
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an error of `handler` on `event` at `now`
+    ///
+    /// Returns whether to log the error in full, with at most `burst` per
+    /// fingerprint and `window`, and the summaries of the windows that ended,
+    /// for every fingerprint.
+    pub fn record(
+        &self,
+        event: &str,
+        handler: &str,
+        error: &str,
+        burst: usize,
+        window: Duration,
+        now: Instant,
+    ) -> (bool, Vec<SuppressedErrors>) {
+        let mut occurrences = self.occurrences.lock().unwrap();
+        let mut summaries = Vec::new();
+        occurrences.retain(|fingerprint, errors| {
+            if now.duration_since(errors.since) < window {
+                return true;
+            }
+            if errors.count > burst {
+                summaries.push(SuppressedErrors {
+                    event: fingerprint.event.clone(),
+                    handler: fingerprint.handler.clone(),
+                    count: errors.count - burst,
+                    window,
+                    first_line: errors.first_line.clone(),
+                });
+            }
+            false
+        });
+
+        let fingerprint = Fingerprint {
+            event: event.to_string(),
+            handler: handler.to_string(),
+            message: error.chars().take(FINGERPRINT_LENGTH).collect(),
+        };
+        if !occurrences.contains_key(&fingerprint) && occurrences.len() >= MAX_FINGERPRINTS {
+            return (true, summaries);
+        }
+        let errors = occurrences
+            .entry(fingerprint)
+            .or_insert_with(|| Occurrences {
+                since: now,
+                count: 0,
+                first_line: error.lines().next().unwrap_or_default().to_string(),
+            });
+        errors.count += 1;
+        (errors.count <= burst, summaries)
+    }
+
+    /// Number of fingerprints counted in their current window
+    pub fn len(&self) -> usize {
+        self.occurrences.lock().unwrap().len()
+    }
+
+    /// Whether no fingerprint is counted
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DispatchConfig;
+    use crate::dispatch::Dispatcher;
+    use crate::testing::{issues_payload, webhook_event};
+    use crate::Context;
+    use anyhow::anyhow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context as LayerContext, Layer, SubscriberExt};
+
+    const WINDOW: Duration = Duration::from_secs(300);
+
+    /// Count the error events logged
+    struct ErrorEvents(Arc<AtomicUsize>);
+
+    impl<S: Subscriber> Layer<S> for ErrorEvents {
+        fn on_event(&self, event: &Event<'_>, _context: LayerContext<'_, S>) {
+            if *event.metadata().level() == Level::ERROR {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[test]
+    fn test_windows_end_with_a_summary() {
+        let limiter = ErrorLogLimiter::new();
+        let start = Instant::now();
+        let error = "Label not found\n\nCaused by: 404";
+
+        let record = |handler, error, now| limiter.record("issues", handler, error, 5, WINDOW, now);
+
+        let logged = (0..100)
+            .filter(|_| record("labeler", error, start).0)
+            .count();
+        assert_eq!(logged, 5);
+
+        // Other handlers and errors are counted separately
+        assert!(record("triage", error, start).0);
+        assert!(record("labeler", "Other", start).0);
+        assert_eq!(limiter.len(), 3);
+
+        let (logged, summaries) = record("labeler", error, start + WINDOW);
+        assert!(logged);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            summaries[0].to_string(),
+            "Handler 'labeler' on issues failed 95 more time(s) in the last 5m: Label not found"
+        );
+        assert_eq!(limiter.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_handler_errors_are_not_all_logged() {
+        let errors = Arc::new(AtomicUsize::new(0));
+        let subscriber = tracing_subscriber::registry().with(ErrorEvents(errors.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let dispatcher = Dispatcher::new(None);
+        dispatcher
+            .set_config(DispatchConfig {
+                error_log_burst: Some(3),
+                ..Default::default()
+            })
+            .await;
+        dispatcher
+            .on(
+                "issues",
+                |_context: Context, _extra: Arc<()>| async { Err(anyhow!("Label not found")) },
+                Arc::new(()),
+            )
+            .await;
+
+        for number in 0..100 {
+            let context = Context::new(
+                Some(webhook_event("issues", issues_payload("opened", number))),
+                None,
+            );
+            let (report, result) = dispatcher.dispatch_with_report(context).await;
+            assert!(result.is_err());
+            assert!(report.results[0].error.is_some());
+        }
+        assert_eq!(errors.load(Ordering::SeqCst), 3);
+
+        // Without deduplication, every error is logged
+        dispatcher
+            .set_config(DispatchConfig {
+                error_log_burst: None,
+                ..Default::default()
+            })
+            .await;
+        for number in 0..10 {
+            let context = Context::new(
+                Some(webhook_event("issues", issues_payload("opened", number))),
+                None,
+            );
+            dispatcher.dispatch(context).await.unwrap_err();
+        }
+        assert_eq!(errors.load(Ordering::SeqCst), 13);
+    }
+}
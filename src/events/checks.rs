@@ -5,13 +5,22 @@
 
 use std::sync::Arc;
 
+use octocrab::models::webhook_events::payload::{
+    CheckRunWebhookEventAction, CheckSuiteWebhookEventAction,
+};
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::core::{HandlerRegistration, HandlerSource};
+use crate::{Context, EventKind, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for check run events
-    pub async fn on_check_run<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_check_run<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -19,12 +28,114 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::CheckRun.to_string(), handler, extra)
-            .await;
-        self
+    }
+
+    /// Register a handler for clicks on the check run action button `identifier`
+    ///
+    /// The handler runs for `check_run` events with the `requested_action`
+    /// action whose [identifier](Context::requested_action_identifier) is
+    /// `identifier`, i.e. the `identifier` of an action the app attached to
+    /// one of its check runs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_check_action(
+    ///     "fix-lint",
+    ///     |_context: Context, _extra: Arc<()>| async move {
+    ///         // ... push a commit fixing the lint errors
+    ///         Ok(())
+    ///     },
+    ///     Arc::new(()),
+    /// )
+    /// .await
+    /// .trusted_only();
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn on_check_action<F, Fut, E>(
+        &mut self,
+        identifier: &str,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        // Report the wrapped handler under its own type name
+        let source = HandlerSource::of::<F>();
+        let handler = Arc::new(handler);
+        let identifier = identifier.to_string();
+        self.server.register(
+            EventKind::from(WebhookEventType::CheckRun),
+            move |context: Context, extra: Arc<E>| {
+                let handler = handler.clone();
+                let matches = context.check_run_action()
+                    == Some(CheckRunWebhookEventAction::RequestedAction)
+                    && context.requested_action_identifier() == Some(identifier.as_str());
+                async move {
+                    if matches {
+                        handler(context, extra).await
+                    } else {
+                        Ok(())
+                    }
+                }
+            },
+            extra,
+            source,
+        )
+    }
+
+    /// Register a handler for check suites a user asked to run again
+    ///
+    /// The handler runs for `check_suite` events with the `rerequested`
+    /// action, sent when "Re-run all checks" is clicked for the app's suite.
+    #[track_caller]
+    pub fn on_check_suite_rerequested<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        // Report the wrapped handler under its own type name
+        let source = HandlerSource::of::<F>();
+        let handler = Arc::new(handler);
+        self.server.register(
+            EventKind::from(WebhookEventType::CheckSuite),
+            move |context: Context, extra: Arc<E>| {
+                let handler = handler.clone();
+                let matches =
+                    context.check_suite_action() == Some(CheckSuiteWebhookEventAction::Rerequested);
+                async move {
+                    if matches {
+                        handler(context, extra).await
+                    } else {
+                        Ok(())
+                    }
+                }
+            },
+            extra,
+            source,
+        )
     }
 
     /// Register a handler for check suite events
-    pub async fn on_check_suite<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_check_suite<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -32,151 +143,157 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::CheckSuite.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for code scanning alert events
-    pub async fn on_code_scanning_alert<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_code_scanning_alert<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::CodeScanningAlert.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::CodeScanningAlert.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for secret scanning alert events
-    pub async fn on_secret_scanning_alert<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_secret_scanning_alert<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::SecretScanningAlert.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::SecretScanningAlert.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for secret scanning alert location events
-    pub async fn on_secret_scanning_alert_location<F, Fut, E>(
+    #[track_caller]
+    pub fn on_secret_scanning_alert_location<F, Fut, E>(
         &mut self,
         handler: F,
         extra: Arc<E>,
-    ) -> &Self
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::SecretScanningAlertLocation.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::SecretScanningAlertLocation.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for dependabot alert events
-    pub async fn on_dependabot_alert<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_dependabot_alert<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::DependabotAlert.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::DependabotAlert.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for repository vulnerability alert events
-    pub async fn on_repository_vulnerability_alert<F, Fut, E>(
+    #[track_caller]
+    pub fn on_repository_vulnerability_alert<F, Fut, E>(
         &mut self,
         handler: F,
         extra: Arc<E>,
-    ) -> &Self
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::RepositoryVulnerabilityAlert.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::RepositoryVulnerabilityAlert.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for security advisory events
-    pub async fn on_security_advisory<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_security_advisory<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::SecurityAdvisory.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::SecurityAdvisory.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for repository advisory events
-    pub async fn on_repository_advisory<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_repository_advisory<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::RepositoryAdvisory.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::RepositoryAdvisory.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for security and analysis events
-    pub async fn on_security_and_analysis<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_security_and_analysis<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::SecurityAndAnalysis.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::SecurityAndAnalysis.to_string(),
+            handler,
+            extra,
+        )
     }
 }
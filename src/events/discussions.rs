@@ -7,11 +7,16 @@ use std::sync::Arc;
 
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::{core::HandlerRegistration, Context, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for discussion events
-    pub async fn on_discussion<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_discussion<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -19,24 +24,24 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Discussion.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for discussion comment events
-    pub async fn on_discussion_comment<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_discussion_comment<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::DiscussionComment.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::DiscussionComment.to_string(),
+            handler,
+            extra,
+        )
     }
 }
@@ -7,11 +7,16 @@ use std::sync::Arc;
 
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::{core::HandlerRegistration, Context, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for installation events
-    pub async fn on_installation<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_installation<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -19,87 +24,81 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Installation.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for installation repositories events
-    pub async fn on_installation_repositories<F, Fut, E>(
+    #[track_caller]
+    pub fn on_installation_repositories<F, Fut, E>(
         &mut self,
         handler: F,
         extra: Arc<E>,
-    ) -> &Self
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::InstallationRepositories.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::InstallationRepositories.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for installation target events
-    pub async fn on_installation_target<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_installation_target<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::InstallationTarget.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::InstallationTarget.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for GitHub App authorization events
-    pub async fn on_github_app_authorization<F, Fut, E>(
+    #[track_caller]
+    pub fn on_github_app_authorization<F, Fut, E>(
         &mut self,
         handler: F,
         extra: Arc<E>,
-    ) -> &Self
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::GithubAppAuthorization.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::GithubAppAuthorization.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for personal access token request events
-    pub async fn on_personal_access_token_request<F, Fut, E>(
+    #[track_caller]
+    pub fn on_personal_access_token_request<F, Fut, E>(
         &mut self,
         handler: F,
         extra: Arc<E>,
-    ) -> &Self
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::PersonalAccessTokenRequest.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::PersonalAccessTokenRequest.to_string(),
+            handler,
+            extra,
+        )
     }
 }
@@ -7,7 +7,8 @@ use std::sync::Arc;
 
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::helpers::sub_issues::SUB_ISSUES_EVENT;
+use crate::{core::HandlerRegistration, Context, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for issue comment events
@@ -104,7 +105,12 @@ impl Octofer {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn on_issue_comment<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_issue_comment<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -112,8 +118,6 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::IssueComment.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for issue events
@@ -217,7 +221,12 @@ impl Octofer {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn on_issue<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_issue<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -225,7 +234,49 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Issues.to_string(), handler, extra)
-            .await;
-        self
+    }
+
+    /// Register a handler for sub-issues events
+    ///
+    /// Sub-issues events are sent when a sub-issue is added to or removed
+    /// from an issue. octocrab does not parse them yet, so they are routed by
+    /// their raw event name; use
+    /// [`Context::sub_issues_event`](crate::Context::sub_issues_event) to read
+    /// the payload.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Octofer, Context};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_sub_issues(
+    ///     |context: Context, _extra: Arc<()>| async move {
+    ///         if let Some(event) = context.sub_issues_event() {
+    ///             println!(
+    ///                 "{:?}: #{} under #{}",
+    ///                 event.action, event.sub_issue.number, event.parent_issue.number
+    ///             );
+    ///         }
+    ///         Ok(())
+    ///     },
+    ///     Arc::new(()),
+    /// )
+    /// .await;
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn on_sub_issues<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        self.server.on_unchecked(SUB_ISSUES_EVENT, handler, extra)
     }
 }
@@ -0,0 +1,194 @@
+//! Event kinds independent of octocrab's event enum
+//!
+//! Handlers are registered, and deliveries routed, by [`EventKind`]: the
+//! event name GitHub sends in the `X-GitHub-Event` header. It converts to and
+//! from octocrab's [`WebhookEventType`], but does not depend on the variants
+//! of the octocrab version Octofer is built with, so events octocrab does not
+//! know yet (its `Unknown` variant) route like any other.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+
+use octocrab::models::webhook_events::WebhookEventType;
+use serde::{Deserialize, Serialize};
+
+/// Name of a webhook event, e.g. `pull_request`
+///
+/// Compares equal to the plain event name:
+///
+/// ```rust
+/// use octofer::octocrab::models::webhook_events::WebhookEventType;
+/// use octofer::EventKind;
+///
+/// let kind = EventKind::from(WebhookEventType::IssueComment);
+/// assert_eq!(kind, "issue_comment");
+/// assert_eq!(WebhookEventType::from(kind), WebhookEventType::IssueComment);
+///
+/// // Events octocrab has no variant for keep their name
+/// let kind = EventKind::from(WebhookEventType::Unknown("copilot_usage".to_string()));
+/// assert_eq!(kind, "copilot_usage");
+/// assert!(!kind.is_known());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EventKind(String);
+
+impl EventKind {
+    /// Create an event kind from its name, as is
+    ///
+    /// Use [`normalize_event_name`](crate::dispatch::normalize_event_name) to
+    /// normalize and check names given by users.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// Get the event name
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Check whether octocrab has a variant of [`WebhookEventType`] for the
+    /// event
+    pub fn is_known(&self) -> bool {
+        !matches!(
+            serde_json::from_value(serde_json::Value::String(self.0.clone())),
+            Ok(WebhookEventType::Unknown(_)) | Err(_)
+        )
+    }
+
+    /// Get the event name
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl From<&WebhookEventType> for EventKind {
+    fn from(event_type: &WebhookEventType) -> Self {
+        match event_type {
+            WebhookEventType::Unknown(name) => Self(name.clone()),
+            known => match serde_json::to_value(known) {
+                Ok(serde_json::Value::String(name)) => Self(name),
+                _ => Self::default(),
+            },
+        }
+    }
+}
+
+impl From<WebhookEventType> for EventKind {
+    fn from(event_type: WebhookEventType) -> Self {
+        Self::from(&event_type)
+    }
+}
+
+impl From<EventKind> for WebhookEventType {
+    /// Convert to the matching variant, or `Unknown` with the event name
+    fn from(kind: EventKind) -> Self {
+        serde_json::from_value(serde_json::Value::String(kind.0.clone()))
+            .unwrap_or(WebhookEventType::Unknown(kind.0))
+    }
+}
+
+impl From<String> for EventKind {
+    fn from(name: String) -> Self {
+        Self(name)
+    }
+}
+
+impl From<&str> for EventKind {
+    fn from(name: &str) -> Self {
+        Self(name.to_string())
+    }
+}
+
+impl From<EventKind> for String {
+    fn from(kind: EventKind) -> Self {
+        kind.0
+    }
+}
+
+impl Deref for EventKind {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for EventKind {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for EventKind {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq<str> for EventKind {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for EventKind {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<String> for EventKind {
+    fn eq(&self, other: &String) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<EventKind> for &str {
+    fn eq(&self, other: &EventKind) -> bool {
+        *self == other.0
+    }
+}
+
+impl PartialEq<EventKind> for String {
+    fn eq(&self, other: &EventKind) -> bool {
+        *self == other.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions_with_webhook_event_type() {
+        for (event_type, name) in [
+            (
+                WebhookEventType::PullRequestReviewThread,
+                "pull_request_review_thread",
+            ),
+            (WebhookEventType::Ping, "ping"),
+            (
+                WebhookEventType::Unknown("new_event".to_string()),
+                "new_event",
+            ),
+        ] {
+            let kind = EventKind::from(&event_type);
+            assert_eq!(kind, name);
+            assert_eq!(WebhookEventType::from(kind), event_type);
+        }
+        assert!(EventKind::from("merge_group").is_known());
+        assert!(!EventKind::from("new_event").is_known());
+
+        let json = serde_json::to_value(EventKind::from("push")).unwrap();
+        assert_eq!(json, serde_json::json!("push"));
+        assert_eq!(serde_json::from_value::<EventKind>(json).unwrap(), "push");
+    }
+}
@@ -7,11 +7,16 @@ use std::sync::Arc;
 
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::{core::HandlerRegistration, Context, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for label events
-    pub async fn on_label<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_label<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -19,12 +24,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Label.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for milestone events
-    pub async fn on_milestone<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_milestone<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -32,12 +40,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Milestone.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for watch events (repository stars)
-    pub async fn on_watch<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_watch<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -45,12 +56,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Watch.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for star events
-    pub async fn on_star<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_star<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -58,12 +72,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Star.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for ping events
-    pub async fn on_ping<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_ping<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -71,12 +88,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Ping.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for meta events
-    pub async fn on_meta<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_meta<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -84,12 +104,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Meta.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for page build events
-    pub async fn on_page_build<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_page_build<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -97,12 +120,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::PageBuild.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for schedule events
-    pub async fn on_schedule<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_schedule<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -110,12 +136,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Schedule.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for sponsorship events
-    pub async fn on_sponsorship<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_sponsorship<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -123,29 +152,34 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Sponsorship.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for marketplace purchase events
-    pub async fn on_marketplace_purchase<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_marketplace_purchase<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::MarketplacePurchase.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::MarketplacePurchase.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for merge group events
-    pub async fn on_merge_group<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_merge_group<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -153,7 +187,5 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::MergeGroup.to_string(), handler, extra)
-            .await;
-        self
     }
 }
@@ -9,9 +9,11 @@
 //! ## Issue Events
 //! - [`on_issue()`](../struct.Octofer.html#method.on_issue) - Issue opened, closed, edited, etc.
 //! - [`on_issue_comment()`](../struct.Octofer.html#method.on_issue_comment) - Comments on issues
+//! - [`on_sub_issues()`](../struct.Octofer.html#method.on_sub_issues) - Sub-issues added or removed
 //!
 //! ## Pull Request Events  
 //! - [`on_pull_request()`](../struct.Octofer.html#method.on_pull_request) - PR opened, closed, merged, etc.
+//! - [`on_pull_request_ready_for_review()`](../struct.Octofer.html#method.on_pull_request_ready_for_review) - Draft PR marked ready for review
 //! - [`on_pull_request_review()`](../struct.Octofer.html#method.on_pull_request_review) - PR reviews submitted
 //! - [`on_pull_request_review_comment()`](../struct.Octofer.html#method.on_pull_request_review_comment) - Comments on PR reviews
 //! - [`on_pull_request_review_thread()`](../struct.Octofer.html#method.on_pull_request_review_thread) - PR review threads
@@ -32,12 +34,17 @@
 //! ## Workflow Events
 //! - [`on_workflow_run()`](../struct.Octofer.html#method.on_workflow_run) - Workflow run
 //! - [`on_workflow_job()`](../struct.Octofer.html#method.on_workflow_job) - Workflow job
+//! - [`on_workflow_job_queued()`](../struct.Octofer.html#method.on_workflow_job_queued),
+//!   [`on_workflow_job_in_progress()`](../struct.Octofer.html#method.on_workflow_job_in_progress),
+//!   [`on_workflow_job_completed()`](../struct.Octofer.html#method.on_workflow_job_completed) - Workflow job lifecycle
 //! - [`on_workflow_dispatch()`](../struct.Octofer.html#method.on_workflow_dispatch) - Workflow dispatch
 //! - [`on_status()`](../struct.Octofer.html#method.on_status) - Commit status
 //!
 //! ## Check & Security Events
 //! - [`on_check_run()`](../struct.Octofer.html#method.on_check_run) - Check run
+//! - [`on_check_action()`](../struct.Octofer.html#method.on_check_action) - Check run action button clicked
 //! - [`on_check_suite()`](../struct.Octofer.html#method.on_check_suite) - Check suite
+//! - [`on_check_suite_rerequested()`](../struct.Octofer.html#method.on_check_suite_rerequested) - Check suite re-run requested
 //! - [`on_code_scanning_alert()`](../struct.Octofer.html#method.on_code_scanning_alert) - Code scanning alert
 //! - [`on_secret_scanning_alert()`](../struct.Octofer.html#method.on_secret_scanning_alert) - Secret scanning alert
 //! - [`on_dependabot_alert()`](../struct.Octofer.html#method.on_dependabot_alert) - Dependabot alert
@@ -96,7 +103,8 @@
 //!
 //! Where:
 //! - `context` - Contains the webhook event data and GitHub API client
-//! - `extra` - Additional data you want to pass to the handler
+//! - `extra` - Additional data you want to pass to the handler; data shared by
+//!   several handlers is simpler stored as app [`state`](crate::state)
 //!
 //! # Examples
 //!
@@ -202,12 +210,36 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! ## Handlers Sharing App State
+//!
+//! ```rust,no_run
+//! use octofer::{Octofer, Config, Context};
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut app = Octofer::new(Config::default()).await.unwrap_or_else(|_| Octofer::new_default());
+//! app.state(AtomicU64::new(0));
+//!
+//! app.on_pull_request(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let handled = context.state::<AtomicU64>()?.fetch_add(1, Ordering::Relaxed);
+//!         println!("{} pull request events handled before this one", handled);
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! ).await;
+//! # Ok(())
+//! # }
+//! ```
 
 pub mod checks;
 pub mod deployments;
 pub mod discussions;
 pub mod installations;
 pub mod issues;
+pub mod kind;
 pub mod misc;
 pub mod projects;
 pub mod prs;
@@ -215,3 +247,5 @@ pub mod releases;
 pub mod repository;
 pub mod teams;
 pub mod workflows;
+
+pub use kind::EventKind;
@@ -7,11 +7,16 @@ use std::sync::Arc;
 
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::{core::HandlerRegistration, Context, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for project (classic) events
-    pub async fn on_project<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_project<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -19,12 +24,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Project.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for project card events
-    pub async fn on_project_card<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_project_card<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -32,12 +40,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::ProjectCard.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for project column events
-    pub async fn on_project_column<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_project_column<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -45,12 +56,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::ProjectColumn.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for projects v2 events
-    pub async fn on_projects_v2<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_projects_v2<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -58,12 +72,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::ProjectsV2.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for projects v2 item events
-    pub async fn on_projects_v2_item<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_projects_v2_item<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -71,7 +88,5 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::ProjectsV2Item.to_string(), handler, extra)
-            .await;
-        self
     }
 }
@@ -5,9 +5,11 @@
 
 use std::sync::Arc;
 
+use octocrab::models::webhook_events::payload::PullRequestWebhookEventAction;
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::core::{HandlerRegistration, HandlerSource};
+use crate::{Context, EventKind, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for pull request events
@@ -106,7 +108,12 @@ impl Octofer {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn on_pull_request<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_pull_request<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -114,8 +121,64 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::PullRequest.to_string(), handler, extra)
-            .await;
-        self
+    }
+
+    /// Register a handler for draft pull requests marked as ready for review
+    ///
+    /// The handler runs for `pull_request` events with the
+    /// `ready_for_review` action. Together with
+    /// [`HandlerRegistration::skip_drafts`] on the app's other pull request
+    /// handlers, work is deferred until a pull request leaves the draft
+    /// state.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_pull_request_ready_for_review(
+    ///     |context: Context, _extra: Arc<()>| async move {
+    ///         context.create_check_run("integration", Default::default()).await?;
+    ///         Ok(())
+    ///     },
+    ///     Arc::new(()),
+    /// )
+    /// .await;
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn on_pull_request_ready_for_review<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        // Report the wrapped handler under its own type name
+        let source = HandlerSource::of::<F>();
+        let handler = Arc::new(handler);
+        self.server.register(
+            EventKind::from(WebhookEventType::PullRequest),
+            move |context: Context, extra: Arc<E>| {
+                let handler = handler.clone();
+                let matches = context.pull_request_action()
+                    == Some(PullRequestWebhookEventAction::ReadyForReview);
+                async move {
+                    if matches {
+                        handler(context, extra).await
+                    } else {
+                        Ok(())
+                    }
+                }
+            },
+            extra,
+            source,
+        )
     }
 
     /// Register a handler for pull request review events
@@ -166,20 +229,22 @@ impl Octofer {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn on_pull_request_review<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_pull_request_review<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::PullRequestReview.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::PullRequestReview.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for pull request review comment events
@@ -234,24 +299,22 @@ impl Octofer {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn on_pull_request_review_comment<F, Fut, E>(
+    #[track_caller]
+    pub fn on_pull_request_review_comment<F, Fut, E>(
         &mut self,
         handler: F,
         extra: Arc<E>,
-    ) -> &Self
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::PullRequestReviewComment.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::PullRequestReviewComment.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for pull request review thread events
@@ -303,23 +366,21 @@ impl Octofer {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn on_pull_request_review_thread<F, Fut, E>(
+    #[track_caller]
+    pub fn on_pull_request_review_thread<F, Fut, E>(
         &mut self,
         handler: F,
         extra: Arc<E>,
-    ) -> &Self
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::PullRequestReviewThread.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::PullRequestReviewThread.to_string(),
+            handler,
+            extra,
+        )
     }
 }
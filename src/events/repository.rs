@@ -7,11 +7,18 @@ use std::sync::Arc;
 
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::core::{HandlerRegistration, HandlerSource};
+use crate::helpers::repository::RepoIdentityChange;
+use crate::{Context, EventKind, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for push events
-    pub async fn on_push<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_push<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -19,12 +26,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Push.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for create events (branch/tag created)
-    pub async fn on_create<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_create<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -32,12 +42,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Create.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for delete events (branch/tag deleted)
-    pub async fn on_delete<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_delete<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -45,12 +58,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Delete.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for fork events
-    pub async fn on_fork<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_fork<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -58,12 +74,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Fork.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for commit comment events
-    pub async fn on_commit_comment<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_commit_comment<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -71,12 +90,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::CommitComment.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for gollum events (wiki page updates)
-    pub async fn on_gollum<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_gollum<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -84,12 +106,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Gollum.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for public events (repository made public)
-    pub async fn on_public<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_public<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -97,12 +122,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Public.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for repository events
-    pub async fn on_repository<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_repository<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -110,58 +138,99 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Repository.to_string(), handler, extra)
-            .await;
-        self
+    }
+
+    /// Register a handler for repositories that were renamed or transferred
+    ///
+    /// The handler runs for `repository` events with the `renamed` and
+    /// `transferred` actions, with the old and new full name of the
+    /// repository, so references stored by the app can be updated. See the
+    /// [`repository`](crate::helpers::repository) helpers.
+    #[track_caller]
+    pub fn on_repo_identity_changed<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, RepoIdentityChange, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        // Report the wrapped handler under its own type name
+        let source = HandlerSource::of::<F>();
+        let handler = Arc::new(handler);
+        self.server.register(
+            EventKind::from(WebhookEventType::Repository),
+            move |context: Context, extra: Arc<E>| {
+                let handler = handler.clone();
+                let change = context.repo_identity_change();
+                async move {
+                    match change {
+                        Some(change) => handler(context, change, extra).await,
+                        None => Ok(()),
+                    }
+                }
+            },
+            extra,
+            source,
+        )
     }
 
     /// Register a handler for repository dispatch events
-    pub async fn on_repository_dispatch<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_repository_dispatch<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::RepositoryDispatch.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::RepositoryDispatch.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for repository import events
-    pub async fn on_repository_import<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_repository_import<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::RepositoryImport.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::RepositoryImport.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for branch protection rule events
-    pub async fn on_branch_protection_rule<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_branch_protection_rule<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::BranchProtectionRule.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::BranchProtectionRule.to_string(),
+            handler,
+            extra,
+        )
     }
 }
@@ -5,13 +5,20 @@
 
 use std::sync::Arc;
 
+use octocrab::models::webhook_events::payload::WorkflowJobWebhookEventAction;
 use octocrab::models::webhook_events::WebhookEventType;
 
-use crate::{Context, Octofer, SerdeToString};
+use crate::core::{HandlerRegistration, HandlerSource};
+use crate::{Context, EventKind, Octofer, SerdeToString};
 
 impl Octofer {
     /// Register a handler for workflow run events
-    pub async fn on_workflow_run<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_workflow_run<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -19,12 +26,15 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::WorkflowRun.to_string(), handler, extra)
-            .await;
-        self
     }
 
     /// Register a handler for workflow job events
-    pub async fn on_workflow_job<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_workflow_job<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -32,29 +42,116 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::WorkflowJob.to_string(), handler, extra)
-            .await;
-        self
+    }
+
+    /// Register a handler for workflow jobs that were queued
+    ///
+    /// Use [`HandlerRegistration::high_priority`] for latency-sensitive work
+    /// such as scaling up runners.
+    #[track_caller]
+    pub fn on_workflow_job_queued<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        self.on_workflow_job_action(WorkflowJobWebhookEventAction::Queued, handler, extra)
+    }
+
+    /// Register a handler for workflow jobs that started running on a runner
+    #[track_caller]
+    pub fn on_workflow_job_in_progress<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        self.on_workflow_job_action(WorkflowJobWebhookEventAction::InProgress, handler, extra)
+    }
+
+    /// Register a handler for workflow jobs that completed
+    #[track_caller]
+    pub fn on_workflow_job_completed<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        self.on_workflow_job_action(WorkflowJobWebhookEventAction::Completed, handler, extra)
+    }
+
+    /// Register a `workflow_job` handler that only runs for `action`
+    #[track_caller]
+    fn on_workflow_job_action<F, Fut, E>(
+        &mut self,
+        action: WorkflowJobWebhookEventAction,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        // Report the wrapped handler under its own type name
+        let source = HandlerSource::of::<F>();
+        let handler = Arc::new(handler);
+        self.server.register(
+            EventKind::from(WebhookEventType::WorkflowJob),
+            move |context: Context, extra: Arc<E>| {
+                let handler = handler.clone();
+                let matches = context.workflow_job_action().as_ref() == Some(&action);
+                async move {
+                    if matches {
+                        handler(context, extra).await
+                    } else {
+                        Ok(())
+                    }
+                }
+            },
+            extra,
+            source,
+        )
     }
 
     /// Register a handler for workflow dispatch events
-    pub async fn on_workflow_dispatch<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_workflow_dispatch<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        self.server
-            .on(
-                WebhookEventType::WorkflowDispatch.to_string(),
-                handler,
-                extra,
-            )
-            .await;
-        self
+        self.server.on(
+            WebhookEventType::WorkflowDispatch.to_string(),
+            handler,
+            extra,
+        )
     }
 
     /// Register a handler for status events
-    pub async fn on_status<F, Fut, E>(&mut self, handler: F, extra: Arc<E>) -> &Self
+    #[track_caller]
+    pub fn on_status<F, Fut, E>(
+        &mut self,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -62,7 +159,5 @@ impl Octofer {
     {
         self.server
             .on(WebhookEventType::Status.to_string(), handler, extra)
-            .await;
-        self
     }
 }
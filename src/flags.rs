@@ -0,0 +1,299 @@
+//! Feature flags gating handlers
+//!
+//! Handlers registered with
+//! [`behind_flag`](crate::core::HandlerRegistration::behind_flag) only run
+//! for deliveries their flag is enabled for, e.g. to roll out a behavior
+//! repository by repository. Flags are evaluated before the handler runs by
+//! the app's [`FeatureFlags`] provider, set with
+//! [`Octofer::set_feature_flags`](crate::Octofer::set_feature_flags) to
+//! delegate to a flag service. Without a provider, the
+//! [`StaticFeatureFlags`] of
+//! [`DispatchConfig::feature_flags`](crate::config::DispatchConfig::feature_flags)
+//! decide, loaded from `OCTOFER_FEATURE_FLAGS`:
+//!
+//! ```bash
+//! # new-labeler everywhere, beta-triage for my-org and octofer/app only
+//! export OCTOFER_FEATURE_FLAGS="new-labeler;beta-triage=my-org,octofer/app"
+//! ```
+//!
+//! If the provider fails, the handler runs or is skipped according to
+//! [`DispatchConfig::flag_failure_policy`](crate::config::DispatchConfig::flag_failure_policy).
+//! Every evaluation, failed or not, is listed in the
+//! [`flags`](crate::dispatch::DispatchReport::flags) of the delivery's
+//! report.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use futures::future::BoxFuture;
+//! use octofer::flags::{FeatureFlags, FlagContext};
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! struct Beta;
+//!
+//! impl FeatureFlags for Beta {
+//!     fn enabled<'a>(
+//!         &'a self,
+//!         _flag: &'a str,
+//!         context: &'a FlagContext,
+//!     ) -> BoxFuture<'a, anyhow::Result<bool>> {
+//!         Box::pin(async move { Ok(context.organization.as_deref() == Some("beta-testers")) })
+//!     }
+//! }
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.set_feature_flags(Beta);
+//! app.on_issue(
+//!     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+//!     Arc::new(()),
+//! )
+//! .await
+//! .behind_flag("new-labeler");
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::Context;
+
+/// Target of [`StaticFeatureFlags`] matching every delivery
+pub const EVERYONE: &str = "*";
+
+/// What a flag is evaluated for: the delivery's repository, account,
+/// installation and sender
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagContext {
+    /// Full name of the event's repository (e.g. `octocat/hello-world`)
+    pub repository: Option<String>,
+    /// Organization or user account the event belongs to
+    pub organization: Option<String>,
+    /// Installation the delivery belongs to
+    pub installation_id: Option<u64>,
+    /// Login of the event's sender
+    pub sender: Option<String>,
+}
+
+impl FlagContext {
+    /// Get the flag context of a handler context's delivery
+    pub fn of(context: &Context) -> Self {
+        let event = context.event().as_ref();
+        let repository = event.and_then(|event| event.repository.as_ref());
+        Self {
+            repository: repository.and_then(|repo| repo.full_name.clone()),
+            organization: event
+                .and_then(|event| event.organization.as_ref())
+                .map(|org| org.login.clone())
+                .or_else(|| {
+                    repository
+                        .and_then(|repo| repo.owner.as_ref())
+                        .map(|owner| owner.login.clone())
+                }),
+            installation_id: context.installation_id(),
+            sender: event
+                .and_then(|event| event.sender.as_ref())
+                .map(|sender| sender.login.clone()),
+        }
+    }
+}
+
+/// Provider of feature flags, e.g. a client of a flag service
+pub trait FeatureFlags: Send + Sync + 'static {
+    /// Whether `flag` is enabled for `context`
+    ///
+    /// Errors are handled according to the
+    /// [`FlagFailurePolicy`] of the dispatcher.
+    fn enabled<'a>(
+        &'a self,
+        flag: &'a str,
+        context: &'a FlagContext,
+    ) -> BoxFuture<'a, anyhow::Result<bool>>;
+}
+
+/// Whether handlers run when their flag cannot be evaluated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagFailurePolicy {
+    /// Run the handler, as if the flag were enabled
+    Open,
+    /// Skip the handler, as if the flag were disabled
+    #[default]
+    Closed,
+}
+
+impl FromStr for FlagFailurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "open" => Ok(Self::Open),
+            "closed" => Ok(Self::Closed),
+            other => Err(format!(
+                "Invalid flag failure policy '{}', expected 'open' or 'closed'",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FlagFailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open => write!(f, "open"),
+            Self::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+/// Flags enabled for fixed repositories and accounts
+///
+/// Each flag lists its targets: repositories by full name, organization or
+/// user accounts by login, or [`EVERYONE`]. Targets are compared case
+/// insensitively, and flags without any target are disabled.
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::flags::{FlagContext, StaticFeatureFlags};
+///
+/// let flags = StaticFeatureFlags::parse("new-labeler;beta-triage=my-org,octofer/app");
+/// let context = FlagContext {
+///     repository: Some("octofer/app".to_string()),
+///     organization: Some("octofer".to_string()),
+///     ..Default::default()
+/// };
+/// assert!(flags.is_enabled("new-labeler", &context));
+/// assert!(flags.is_enabled("beta-triage", &context));
+/// assert!(!flags.is_enabled("unknown", &context));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StaticFeatureFlags {
+    /// Targets by flag
+    flags: BTreeMap<String, Vec<String>>,
+}
+
+impl StaticFeatureFlags {
+    /// Create flags that are all disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse flags from `flag[=target,...]` entries separated by `;`
+    ///
+    /// Flags without targets are enabled for [`EVERYONE`]. Empty entries and
+    /// targets are ignored.
+    pub fn parse(spec: &str) -> Self {
+        let mut flags = Self::new();
+        for entry in spec.split(';') {
+            let (flag, targets) = entry.split_once('=').unwrap_or((entry, EVERYONE));
+            let flag = flag.trim();
+            if flag.is_empty() {
+                continue;
+            }
+            for target in targets.split(',').map(str::trim) {
+                if !target.is_empty() {
+                    flags = flags.enable_for(flag, target);
+                }
+            }
+        }
+        flags
+    }
+
+    /// Enable `flag` for every delivery
+    pub fn enable(self, flag: impl Into<String>) -> Self {
+        self.enable_for(flag, EVERYONE)
+    }
+
+    /// Enable `flag` for a repository (`owner/name`) or account (`owner`)
+    pub fn enable_for(mut self, flag: impl Into<String>, target: impl Into<String>) -> Self {
+        self.flags
+            .entry(flag.into())
+            .or_default()
+            .push(target.into());
+        self
+    }
+
+    /// Whether `flag` is enabled for `context`
+    pub fn is_enabled(&self, flag: &str, context: &FlagContext) -> bool {
+        let Some(targets) = self.flags.get(flag) else {
+            return false;
+        };
+        let matches = |value: &Option<String>, target: &str| {
+            value
+                .as_deref()
+                .is_some_and(|value| value.eq_ignore_ascii_case(target))
+        };
+        targets.iter().any(|target| {
+            target == EVERYONE
+                || matches(&context.repository, target)
+                || matches(&context.organization, target)
+        })
+    }
+
+    /// Whether no flag is enabled
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+}
+
+impl FeatureFlags for StaticFeatureFlags {
+    fn enabled<'a>(
+        &'a self,
+        flag: &'a str,
+        context: &'a FlagContext,
+    ) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(async move { Ok(self.is_enabled(flag, context)) })
+    }
+}
+
+/// Evaluation of the flag of a handler for a delivery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagEvaluation {
+    /// Name of the handler, see
+    /// [`RegisteredHandler::name`](crate::core::RegisteredHandler::name)
+    pub handler: String,
+    /// Flag the handler is behind
+    pub flag: String,
+    /// Whether the handler ran, after applying the failure policy
+    pub enabled: bool,
+    /// Error the provider failed with, if any
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_flags() {
+        let flags = StaticFeatureFlags::parse(" new-labeler ; beta=Octofer/App, my-org;;empty=");
+        let context = |repository: &str, organization: &str| FlagContext {
+            repository: Some(repository.to_string()),
+            organization: Some(organization.to_string()),
+            ..Default::default()
+        };
+
+        assert!(flags.is_enabled("new-labeler", &context("a/b", "a")));
+        assert!(flags.is_enabled("beta", &context("octofer/app", "octofer")));
+        assert!(flags.is_enabled("beta", &context("my-org/tools", "my-org")));
+        assert!(!flags.is_enabled("beta", &context("octofer/other", "octofer")));
+        assert!(!flags.is_enabled("empty", &context("a/b", "a")));
+        assert!(!flags.is_enabled("beta", &FlagContext::default()));
+        assert_eq!(
+            flags,
+            StaticFeatureFlags::new()
+                .enable("new-labeler")
+                .enable_for("beta", "Octofer/App")
+                .enable_for("beta", "my-org")
+        );
+
+        assert_eq!("Open".parse(), Ok(FlagFailurePolicy::Open));
+        assert!("ajar".parse::<FlagFailurePolicy>().is_err());
+    }
+}
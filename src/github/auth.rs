@@ -22,6 +22,8 @@
 //! ```
 
 use crate::config::GitHubConfig;
+use crate::github::error::{Error, Result};
+use crate::secrets::PrivateKey;
 
 /// GitHub authentication configuration
 ///
@@ -47,7 +49,7 @@ pub struct GitHubAuth {
     /// GitHub App ID (found in your GitHub App settings)
     pub app_id: u64,
     /// Private key for JWT signing (PEM format as bytes)
-    pub private_key: Vec<u8>,
+    pub private_key: PrivateKey,
 }
 
 impl GitHubAuth {
@@ -111,7 +113,18 @@ impl GitHubAuth {
     /// # Ok::<(), anyhow::Error>(())
     /// ```
     pub fn private_key(&self) -> &[u8] {
-        &self.private_key
+        self.private_key.as_bytes()
+    }
+
+    /// Parse the private key into a key for signing JWTs
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPrivateKey`] if the private key is not a valid
+    /// RSA PEM key.
+    pub fn encoding_key(&self) -> Result<jsonwebtoken::EncodingKey> {
+        jsonwebtoken::EncodingKey::from_rsa_pem(self.private_key.as_bytes())
+            .map_err(Error::InvalidPrivateKey)
     }
 }
 
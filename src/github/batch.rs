@@ -0,0 +1,560 @@
+//! App-wide iteration over installations and repositories
+//!
+//! Scheduled jobs and maintenance tasks often need to run something for every
+//! installation of the app, or every repository the app can access.
+//! [`GitHubClient::for_each_installation`] and
+//! [`GitHubClient::for_each_repository`] run a closure for each of them with
+//! bounded concurrency. A failure for one installation or repository is
+//! recorded in the returned [`IterationSummary`] instead of aborting the run.
+//!
+//! Before running the closure, each lane checks the installation's remaining
+//! core API quota and pauses until the quota resets if fewer than
+//! [`RATE_LIMIT_RESERVE`] requests are left.
+//!
+//! [`GitHubClient::prewarm`] creates the clients of installations ahead of
+//! their first event, e.g. at startup with
+//! [`GitHubConfig::prewarm_installations`](crate::config::GitHubConfig::prewarm_installations).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::GitHubClient;
+//!
+//! # async fn example(client: GitHubClient) -> anyhow::Result<()> {
+//! let summary = client
+//!     .for_each_repository(4, |handle| async move {
+//!         let issues = handle
+//!             .client
+//!             .issues(&handle.owner, &handle.repository.name)
+//!             .list()
+//!             .send()
+//!             .await?;
+//!         println!("{}: {} open issue(s)", handle.repository.name, issues.items.len());
+//!         Ok(())
+//!     })
+//!     .await?;
+//!
+//! for failure in &summary.failures {
+//!     eprintln!("{}", failure);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use octocrab::models::{Installation, InstallationRepositories, Repository};
+use octocrab::Octocrab;
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+
+use crate::config::PrewarmInstallations;
+use crate::github::{is_suspended, GitHubClient};
+
+/// Remaining core API requests below which a lane pauses until the quota resets
+pub const RATE_LIMIT_RESERVE: u64 = 100;
+
+/// Installation tokens requested at the same time when pre-warming
+pub const PREWARM_CONCURRENCY: usize = 4;
+
+/// How often pre-warmed installation tokens are renewed if about to expire
+///
+/// Shorter than the five minutes before expiry at which cached tokens are
+/// renewed, so tokens never expire between two checks.
+pub const PREWARM_REFRESH_INTERVAL: Duration = Duration::from_secs(4 * 60);
+
+/// An installation and a client authenticated as it
+#[derive(Debug, Clone)]
+pub struct InstallationHandle {
+    /// Installation metadata
+    pub installation: Installation,
+    /// Client authenticated as the installation
+    pub client: Octocrab,
+}
+
+impl InstallationHandle {
+    /// ID of the installation
+    pub fn id(&self) -> u64 {
+        self.installation.id.0
+    }
+}
+
+/// A repository, its installation and a client authenticated as it
+#[derive(Debug, Clone)]
+pub struct RepositoryHandle {
+    /// Installation metadata
+    pub installation: Installation,
+    /// Repository metadata
+    pub repository: Repository,
+    /// Login of the repository owner
+    pub owner: String,
+    /// Client authenticated as the installation
+    pub client: Octocrab,
+}
+
+/// Failure of one installation or repository during an iteration
+#[derive(Debug)]
+pub struct IterationFailure {
+    /// ID of the installation
+    pub installation_id: u64,
+    /// Full name of the repository, for per-repository failures
+    pub repository: Option<String>,
+    /// The error
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for IterationFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repository {
+            Some(repository) => write!(
+                f,
+                "Installation {} ({}): {:#}",
+                self.installation_id, repository, self.error
+            ),
+            None => write!(f, "Installation {}: {:#}", self.installation_id, self.error),
+        }
+    }
+}
+
+/// Outcome of an iteration over installations or repositories
+#[derive(Debug, Default)]
+pub struct IterationSummary {
+    /// Number of items the closure completed successfully for
+    pub succeeded: usize,
+    /// Number of items skipped because their installation is suspended
+    pub skipped: usize,
+    /// Items that failed
+    pub failures: Vec<IterationFailure>,
+}
+
+impl IterationSummary {
+    /// Whether no item failed
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        installation_id: u64,
+        repository: Option<String>,
+        result: Result<()>,
+    ) {
+        match result {
+            Ok(()) => self.succeeded += 1,
+            Err(e) if is_suspended(&e) => {
+                debug!("Skipping suspended installation {}", installation_id);
+                self.skipped += 1;
+            }
+            Err(error) => {
+                let failure = IterationFailure {
+                    installation_id,
+                    repository,
+                    error,
+                };
+                warn!("{}", failure);
+                self.failures.push(failure);
+            }
+        }
+    }
+}
+
+impl GitHubClient {
+    /// Run `f` for every installation of the app
+    ///
+    /// At most `concurrency` installations are processed at the same time.
+    /// Errors returned by `f`, or when creating an installation's client, are
+    /// collected in the summary; suspended installations are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the installations cannot be listed.
+    pub async fn for_each_installation<F, Fut>(
+        &self,
+        concurrency: usize,
+        f: F,
+    ) -> Result<IterationSummary>
+    where
+        F: Fn(InstallationHandle) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let installations = self.get_installations().await?;
+        let f = &f;
+
+        let results = stream::iter(installations)
+            .map(|installation| async move {
+                let id = installation.id.0;
+                let result = async {
+                    let client = self.installation_client(id).await?;
+                    wait_for_quota(&client, id).await;
+                    f(InstallationHandle {
+                        installation,
+                        client,
+                    })
+                    .await
+                }
+                .await;
+                (id, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut summary = IterationSummary::default();
+        for (id, result) in results {
+            summary.record(id, None, result);
+        }
+        Ok(summary)
+    }
+
+    /// Run `f` for every repository accessible to any installation of the app
+    ///
+    /// Repositories of all installations are processed together, at most
+    /// `concurrency` at the same time. Errors listing an installation's
+    /// repositories are recorded once for the installation; errors returned by
+    /// `f` are recorded per repository.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the installations cannot be listed.
+    pub async fn for_each_repository<F, Fut>(
+        &self,
+        concurrency: usize,
+        f: F,
+    ) -> Result<IterationSummary>
+    where
+        F: Fn(RepositoryHandle) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let installations = self.get_installations().await?;
+        let concurrency = concurrency.max(1);
+        let mut summary = IterationSummary::default();
+
+        let listings = stream::iter(installations)
+            .map(|installation| async move {
+                let id = installation.id.0;
+                let result = async {
+                    let client = self.installation_client(id).await?;
+                    let repositories = list_repositories(&client).await?;
+                    Ok((client, repositories))
+                }
+                .await;
+                (installation, result)
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut handles = Vec::new();
+        for (installation, result) in listings {
+            match result {
+                Ok((client, repositories)) => {
+                    handles.extend(repositories.into_iter().map(|repository| {
+                        RepositoryHandle {
+                            installation: installation.clone(),
+                            owner: repository
+                                .owner
+                                .as_ref()
+                                .map(|owner| owner.login.clone())
+                                .unwrap_or_else(|| installation.account.login.clone()),
+                            repository,
+                            client: client.clone(),
+                        }
+                    }))
+                }
+                Err(e) => summary.record(installation.id.0, None, Err(e)),
+            }
+        }
+
+        let f = &f;
+        let results = stream::iter(handles)
+            .map(|handle| async move {
+                let id = handle.installation.id.0;
+                let name = format!("{}/{}", handle.owner, handle.repository.name);
+                wait_for_quota(&handle.client, id).await;
+                (id, name, f(handle).await)
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        for (id, name, result) in results {
+            summary.record(id, Some(name), result);
+        }
+        Ok(summary)
+    }
+
+    /// Create and cache the clients of `installation_ids` ahead of their
+    /// first event
+    ///
+    /// At most [`PREWARM_CONCURRENCY`] tokens are requested at the same time.
+    /// Failures are logged and collected in the summary; suspended
+    /// installations are skipped.
+    pub async fn prewarm(&self, installation_ids: &[u64]) -> IterationSummary {
+        let results = stream::iter(installation_ids.iter().copied())
+            .map(|id| async move {
+                let result = self
+                    .installation_client(id)
+                    .await
+                    .map(drop)
+                    .map_err(anyhow::Error::from);
+                (id, result)
+            })
+            .buffer_unordered(PREWARM_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut summary = IterationSummary::default();
+        for (id, result) in results {
+            summary.record(id, None, result);
+        }
+        summary
+    }
+
+    /// Create and cache the clients of `installations`, listing all
+    /// installations of the app for [`PrewarmInstallations::All`]
+    ///
+    /// See [`GitHubClient::prewarm`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the installations cannot be listed.
+    pub async fn prewarm_installations(
+        &self,
+        installations: &PrewarmInstallations,
+    ) -> Result<IterationSummary> {
+        match installations {
+            PrewarmInstallations::All => {
+                let ids: Vec<u64> = self
+                    .get_installations()
+                    .await?
+                    .iter()
+                    .map(|installation| installation.id.0)
+                    .collect();
+                Ok(self.prewarm(&ids).await)
+            }
+            PrewarmInstallations::Ids(ids) => Ok(self.prewarm(ids).await),
+        }
+    }
+
+    /// Keep the tokens of `installations` fresh in a background task
+    ///
+    /// Every [`PREWARM_REFRESH_INTERVAL`] the clients are pre-warmed again,
+    /// which only requests new tokens for those about to expire. The task
+    /// runs until the returned handle is aborted.
+    pub fn keep_warm(self: Arc<Self>, installations: PrewarmInstallations) -> JoinHandle<()> {
+        tokio::spawn(async move { self.refresh_warm(&installations).await })
+    }
+
+    /// Pre-warm the clients of `installations` again every
+    /// [`PREWARM_REFRESH_INTERVAL`], forever
+    pub(crate) async fn refresh_warm(&self, installations: &PrewarmInstallations) {
+        let mut interval = tokio::time::interval(PREWARM_REFRESH_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately, right after startup
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.prewarm_installations(installations).await {
+                warn!("Failed to list installations to keep warm: {}", e);
+            }
+        }
+    }
+}
+
+/// List all repositories accessible to an installation client
+pub(crate) async fn list_repositories(client: &Octocrab) -> Result<Vec<Repository>> {
+    let mut repositories = Vec::new();
+    for page in 1u32.. {
+        let batch: InstallationRepositories = client
+            .get(
+                "/installation/repositories",
+                Some(&json!({ "per_page": 100, "page": page })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to get installation repositories: {}", e))?;
+
+        let done = batch.repositories.len() < 100;
+        repositories.extend(batch.repositories);
+        if done {
+            break;
+        }
+    }
+    Ok(repositories)
+}
+
+/// Pause if the installation's remaining core API quota is low
+///
+/// Querying the rate limit does not count against it. If the rate limit
+/// cannot be determined, no pause is made.
+pub(crate) async fn wait_for_quota(client: &Octocrab, installation_id: u64) {
+    let limits = match client.get::<Value, _, _>("/rate_limit", None::<&()>).await {
+        Ok(limits) => limits,
+        Err(e) => {
+            debug!(
+                "Could not check rate limit of installation {}: {}",
+                installation_id, e
+            );
+            return;
+        }
+    };
+
+    if let Some(pause) = quota_pause(&limits, Utc::now().timestamp()) {
+        warn!(
+            "Installation {} is low on API quota, pausing for {:?}",
+            installation_id, pause
+        );
+        tokio::time::sleep(pause).await;
+    }
+}
+
+/// Time to wait for the quota to reset, if fewer than [`RATE_LIMIT_RESERVE`]
+/// requests remain
+fn quota_pause(limits: &Value, now: i64) -> Option<Duration> {
+    let core = &limits["resources"]["core"];
+    let remaining = core["remaining"].as_u64()?;
+    let reset = core["reset"].as_i64()?;
+
+    (remaining < RATE_LIMIT_RESERVE && reset > now)
+        .then(|| Duration::from_secs((reset - now) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, MockGitHub};
+    use axum::{http::HeaderMap, routing::get, Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn routes() -> Router {
+        Router::new().route(
+            "/installation/repositories",
+            get(|headers: HeaderMap| async move {
+                // Name repositories after the installation's token
+                let token = headers["authorization"].to_str().unwrap().to_string();
+                let owner = token.trim_start_matches("Bearer ghs_").replace('_', "-");
+                Json(json!({
+                    "total_count": 2,
+                    "repositories": [repository(&owner, "api"), repository(&owner, "web")],
+                }))
+            }),
+        )
+    }
+
+    #[test]
+    fn test_quota_pause() {
+        let limits = |remaining: u64| json!({ "resources": { "core": { "remaining": remaining, "reset": 1_060 } } });
+
+        assert_eq!(
+            quota_pause(&limits(10), 1_000),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(quota_pause(&limits(4_000), 1_000), None);
+        assert_eq!(quota_pause(&limits(10), 2_000), None);
+        assert_eq!(quota_pause(&json!({}), 1_000), None);
+    }
+
+    #[tokio::test]
+    async fn test_for_each_installation_collects_failures() {
+        let mock = MockGitHub::start(routes()).await;
+        mock.set_installations(&[1, 2, 3]);
+        let client = mock.client();
+
+        let summary = client
+            .for_each_installation(2, |handle| async move {
+                if handle.id() == 2 {
+                    anyhow::bail!("boom");
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].installation_id, 2);
+        assert_eq!(summary.failures[0].to_string(), "Installation 2: boom");
+    }
+
+    #[tokio::test]
+    async fn test_prewarm_caches_installation_clients() {
+        let mock = MockGitHub::start(Router::new()).await;
+        mock.set_installations(&[1, 2, 3]);
+        let client = mock.client();
+
+        let summary = client
+            .prewarm_installations(&PrewarmInstallations::Ids(vec![1, 2, 99]))
+            .await
+            .unwrap();
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].installation_id, 99);
+        assert_eq!(mock.token_requests(), 2);
+
+        // Only the installation that was not warmed yet needs a token
+        let summary = client
+            .prewarm_installations(&PrewarmInstallations::All)
+            .await
+            .unwrap();
+        assert_eq!(summary.succeeded, 3);
+        assert_eq!(mock.token_requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_for_each_installation_respects_concurrency() {
+        let mock = MockGitHub::start(routes()).await;
+        mock.set_installations(&[1, 2, 3, 4, 5]);
+        let client = mock.client();
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let summary = client
+            .for_each_installation(2, |_handle| {
+                let (active, peak) = (active.clone(), peak.clone());
+                async move {
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(summary.succeeded, 5);
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_for_each_repository_flattens_installations() {
+        let mock = MockGitHub::start(routes()).await;
+        mock.set_installations(&[1, 2]);
+        let client = mock.client();
+
+        let summary = client
+            .for_each_repository(3, |handle| async move {
+                if handle.owner == "test-2" && handle.repository.name == "web" {
+                    anyhow::bail!("no access");
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(summary.succeeded, 3);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(
+            summary.failures[0].repository.as_deref(),
+            Some("test-2/web")
+        );
+    }
+}
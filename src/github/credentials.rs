@@ -0,0 +1,122 @@
+//! Health of the GitHub App's credentials
+//!
+//! Once the app's private key is rotated or revoked, GitHub rejects every
+//! JWT signed with it, and the app can no longer create installation tokens.
+//! [`GitHubClient`](crate::github::GitHubClient) counts token creations
+//! failing with `401 Unauthorized`; after [`DEFAULT_FAILURE_THRESHOLD`] of
+//! them in a row, it considers its credentials unhealthy:
+//!
+//! - `GET /health` responds with `503 Service Unavailable`;
+//! - an error is logged with target [`LOG_TARGET`], e.g. to alert on;
+//! - [`CredentialHealth::total_failures`] counts the rejections.
+//!
+//! The next successful token creation marks the credentials healthy again.
+//! Rotate them without a restart with
+//! [`GitHubClient::reload_credentials`](crate::github::GitHubClient::reload_credentials),
+//! or by changing them in [`Octofer::reload_config`](crate::Octofer::reload_config).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::GitHubClient;
+//!
+//! # fn example(client: GitHubClient) {
+//! let health = client.credential_health();
+//! if !health.is_healthy() {
+//!     println!(
+//!         "{} token creations rejected in a row",
+//!         health.consecutive_failures()
+//!     );
+//! }
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use tracing::{error, info};
+
+/// Number of rejected token creations in a row after which credentials are
+/// considered unhealthy
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+/// Target of the log events about credential health
+pub const LOG_TARGET: &str = "octofer::credentials";
+
+/// Counters of token creations rejected because of the app's credentials
+#[derive(Debug)]
+pub struct CredentialHealth {
+    /// Rejections in a row after which credentials are unhealthy
+    threshold: u32,
+    consecutive: AtomicU32,
+    total: AtomicU64,
+    unhealthy: AtomicBool,
+}
+
+impl CredentialHealth {
+    /// Create healthy counters, turning unhealthy after `threshold`
+    /// rejections in a row
+    ///
+    /// A `threshold` of 0 is treated as 1.
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            consecutive: AtomicU32::new(0),
+            total: AtomicU64::new(0),
+            unhealthy: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether fewer than the threshold of token creations in a row were
+    /// rejected
+    pub fn is_healthy(&self) -> bool {
+        !self.unhealthy.load(Ordering::Relaxed)
+    }
+
+    /// Number of token creations rejected since the last successful one
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive.load(Ordering::Relaxed)
+    }
+
+    /// Number of token creations rejected since the client was created
+    pub fn total_failures(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Count a token creation of app `app_id` rejected with `401`
+    pub(crate) fn record_failure(&self, app_id: u64, message: &str) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        let consecutive = self.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+        if consecutive >= self.threshold && !self.unhealthy.swap(true, Ordering::Relaxed) {
+            error!(
+                target: LOG_TARGET,
+                app_id,
+                consecutive,
+                "GitHub rejected the credentials of app {} {} times in a row ({}); \
+                 check its private key and reload the credentials",
+                app_id,
+                consecutive,
+                message
+            );
+        }
+    }
+
+    /// Record a successful token creation, marking the credentials healthy
+    pub(crate) fn record_success(&self) {
+        self.consecutive.store(0, Ordering::Relaxed);
+        if self.unhealthy.swap(false, Ordering::Relaxed) {
+            info!(target: LOG_TARGET, "GitHub accepts the app's credentials again");
+        }
+    }
+
+    /// Forget the rejections in a row, e.g. after new credentials were loaded
+    pub(crate) fn reset(&self) {
+        self.consecutive.store(0, Ordering::Relaxed);
+        self.unhealthy.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Default for CredentialHealth {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD)
+    }
+}
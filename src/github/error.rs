@@ -0,0 +1,102 @@
+//! Errors of the GitHub client
+//!
+//! [`GitHubClient`](crate::github::GitHubClient) methods fail with [`Error`],
+//! so callers can tell a suspended or unknown installation apart from a failed
+//! request without matching on messages. Handlers keep returning
+//! [`anyhow::Result`]: `?` converts the error, and [`is_suspended`] detects a
+//! suspended installation anywhere in an [`anyhow::Error`]'s chain.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::{Error, GitHubClient};
+//!
+//! # async fn example(client: GitHubClient) -> anyhow::Result<()> {
+//! match client.installation_client(12345).await {
+//!     Ok(installation_client) => {
+//!         installation_client.current().user().await?;
+//!     }
+//!     Err(Error::Suspended(id)) => println!("Installation {} is suspended", id),
+//!     Err(e) => return Err(e.into()),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+/// Result of a GitHub client operation
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Error returned by the GitHub client
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The app has no installation with this ID
+    #[error("Installation with ID {0} not found")]
+    InstallationNotFound(u64),
+    /// The installation is suspended
+    ///
+    /// GitHub refuses to create tokens for suspended installations, so API
+    /// calls on their behalf cannot succeed until it is unsuspended.
+    #[error("Installation {0} is suspended")]
+    Suspended(u64),
+    /// GitHub refused to create an access token for the installation
+    #[error("Failed to create installation token for installation {installation_id}: {source}")]
+    TokenCreation {
+        /// ID of the installation
+        installation_id: u64,
+        /// Error returned by GitHub
+        #[source]
+        source: octocrab::Error,
+    },
+    /// The app's private key is not a valid RSA PEM key
+    #[error("Failed to create encoding key from PEM: {0}")]
+    InvalidPrivateKey(#[source] jsonwebtoken::errors::Error),
+    /// A request to the GitHub API failed
+    #[error("GitHub API request failed: {0}")]
+    Api(#[from] octocrab::Error),
+    /// GitHub responded with something the client did not expect
+    #[error("Unexpected response from GitHub: {0}")]
+    UnexpectedResponse(String),
+    /// GitHub rejected an OAuth code exchange or token refresh, e.g. with
+    /// `bad_verification_code` or `bad_refresh_token`
+    #[error("OAuth request failed with {error}: {description}")]
+    OAuth {
+        /// Error code
+        error: String,
+        /// Human-readable description of the error
+        description: String,
+    },
+    /// The installation's search quota is exhausted, see the
+    /// [`search`](crate::github::search) module
+    #[error(transparent)]
+    SearchBudgetExhausted(#[from] crate::github::search::SearchBudgetExhausted),
+    /// The HTTP client could not be built, e.g. because of an invalid proxy
+    /// or User-Agent
+    #[error("Failed to build GitHub client: {0}")]
+    Client(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    /// Get the ID of the installation the error is about, if any
+    pub fn installation_id(&self) -> Option<u64> {
+        match self {
+            Self::InstallationNotFound(id) | Self::Suspended(id) => Some(*id),
+            Self::TokenCreation {
+                installation_id, ..
+            } => Some(*installation_id),
+            _ => None,
+        }
+    }
+
+    /// Wrap a failure to build the HTTP client
+    pub(crate) fn client(error: anyhow::Error) -> Self {
+        Self::Client(error.into())
+    }
+}
+
+/// Check whether `error` was caused by a suspended installation
+pub fn is_suspended(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| matches!(cause.downcast_ref::<Error>(), Some(Error::Suspended(_))))
+}
@@ -0,0 +1,55 @@
+//! GitHub GraphQL API support
+//!
+//! Some GitHub features, such as Discussions, are only available through the
+//! GraphQL API. Octocrab can send GraphQL requests but does not surface the
+//! `errors` GitHub returns alongside a `200 OK` status; [`query`] does.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::graphql;
+//! use serde_json::json;
+//!
+//! # async fn example(client: octocrab::Octocrab) -> anyhow::Result<()> {
+//! let data = graphql::query(
+//!     &client,
+//!     "query($owner: String!, $name: String!) { repository(owner: $owner, name: $name) { id } }",
+//!     json!({ "owner": "octocat", "name": "hello-world" }),
+//! )
+//! .await?;
+//!
+//! println!("Repository node ID: {}", data["repository"]["id"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::{anyhow, Result};
+use octocrab::Octocrab;
+use serde_json::{json, Value};
+
+/// Send a GraphQL query or mutation and return its `data`
+///
+/// # Errors
+///
+/// Returns an error if the request fails or GitHub reports GraphQL errors.
+pub async fn query(client: &Octocrab, query: &str, variables: Value) -> Result<Value> {
+    let mut response: Value = client
+        .graphql(&json!({ "query": query, "variables": variables }))
+        .await
+        .map_err(|e| anyhow!("GraphQL request failed: {}", e))?;
+
+    if let Some(errors) = response.get("errors").and_then(Value::as_array) {
+        if !errors.is_empty() {
+            let messages: Vec<&str> = errors
+                .iter()
+                .filter_map(|e| e.get("message").and_then(Value::as_str))
+                .collect();
+            return Err(anyhow!("GraphQL errors: {}", messages.join("; ")));
+        }
+    }
+
+    Ok(response
+        .get_mut("data")
+        .map(Value::take)
+        .unwrap_or(Value::Null))
+}
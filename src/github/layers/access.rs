@@ -0,0 +1,554 @@
+//! Hints for `404 Not Found` and `403 Forbidden` responses of installations
+//!
+//! GitHub answers `404` both for resources that do not exist and for
+//! repositories an installation cannot see, and `403` when it lacks a
+//! permission, with little more than "Not Found" or "Resource not accessible
+//! by integration". The [`AccessHintLayer`] adds what is needed to tell these
+//! cases apart to the `message` of such responses of installation clients, so
+//! it shows up in the [`octocrab::Error`] returned to the handler:
+//!
+//! - whether the repository of the request is among the repositories the
+//!   installation can access, and the installation's repository selection,
+//!   looked up once with `GET /installation/repositories` and cached in
+//!   [`AccessHints`] for [`ACCESS_HINT_TTL`]
+//! - the permission the endpoint requires, for the endpoints Octofer's
+//!   helpers use
+//!
+//! For example:
+//!
+//! ```text
+//! 404 from GET /repos/octofer/app/issues: repo 'octofer/app' is not in installation 123's selected repositories (GitHub: Not Found)
+//! ```
+//!
+//! The status and the rest of the response are left unchanged, so matching on
+//! [`octocrab::Error::GitHub`] status codes keeps working. Rate limiting
+//! `403`s get no hint.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http::header::{ACCEPT, AUTHORIZATION, CONTENT_LENGTH, USER_AGENT};
+use http::{Method, Request, Response, StatusCode, Uri};
+use http_body_util::BodyExt;
+use serde::Deserialize;
+use serde_json::Value;
+use tower::{BoxError, Layer, Service, ServiceExt};
+use tracing::debug;
+
+use crate::github::layers::CacheBody;
+use crate::github::models::RepositorySelection;
+use crate::helpers::permissions::Permission;
+
+/// How long the repositories accessible to an installation are reused
+pub const ACCESS_HINT_TTL: Duration = Duration::from_secs(300);
+
+/// Number of repositories requested per page of the lookup
+const REPOSITORIES_PER_PAGE: usize = 100;
+
+/// Repositories an installation can access
+#[derive(Debug)]
+struct AccessibleRepositories {
+    /// Repository selection of the installation
+    selection: Option<RepositorySelection>,
+    /// Lowercase full names of the repositories
+    full_names: HashSet<String>,
+}
+
+/// Page of `GET /installation/repositories`
+#[derive(Deserialize)]
+struct RepositoriesPage {
+    repository_selection: Option<RepositorySelection>,
+    repositories: Vec<RepositoryName>,
+}
+
+#[derive(Deserialize)]
+struct RepositoryName {
+    full_name: String,
+}
+
+/// Results of repository lookups keyed by installation, with their time
+type LookupCache = Mutex<HashMap<u64, (Option<Arc<AccessibleRepositories>>, Instant)>>;
+
+/// Cache of the repositories accessible to each installation, shared by the
+/// clients of a [`Transport`](crate::github::transport::Transport)
+///
+/// Failed lookups are remembered as well, so they are not repeated for every
+/// `404` within [`ACCESS_HINT_TTL`].
+#[derive(Debug, Default)]
+pub struct AccessHints {
+    repositories: LookupCache,
+}
+
+impl AccessHints {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the accessible repositories of an installation, e.g. after its
+    /// repository selection changed
+    pub fn forget(&self, installation_id: u64) {
+        self.repositories.lock().unwrap().remove(&installation_id);
+    }
+
+    /// Get the result of the last lookup for an installation, if still fresh
+    fn get(&self, installation_id: u64) -> Option<Option<Arc<AccessibleRepositories>>> {
+        self.repositories
+            .lock()
+            .unwrap()
+            .get(&installation_id)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < ACCESS_HINT_TTL)
+            .map(|(repositories, _)| repositories.clone())
+    }
+
+    fn insert(&self, installation_id: u64, repositories: Option<Arc<AccessibleRepositories>>) {
+        self.repositories
+            .lock()
+            .unwrap()
+            .insert(installation_id, (repositories, Instant::now()));
+    }
+}
+
+/// Layer adding hints to `404` and `403` responses of an installation client
+///
+/// Without hints or an installation the layer passes responses through.
+#[derive(Debug, Clone)]
+pub struct AccessHintLayer {
+    hints: Option<(Arc<AccessHints>, u64)>,
+    base_uri: Uri,
+}
+
+impl AccessHintLayer {
+    /// Create a layer for a client of `installation_id` sending requests to
+    /// `base_uri`
+    pub fn new(
+        hints: Option<Arc<AccessHints>>,
+        installation_id: Option<u64>,
+        base_uri: Uri,
+    ) -> Self {
+        Self {
+            hints: hints.zip(installation_id),
+            base_uri,
+        }
+    }
+}
+
+impl<S> Layer<S> for AccessHintLayer {
+    type Service = AccessHintService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessHintService {
+            inner,
+            hints: self.hints.clone(),
+            base_path: self.base_uri.path().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+/// Service created by [`AccessHintLayer`]
+#[derive(Debug, Clone)]
+pub struct AccessHintService<S> {
+    inner: S,
+    hints: Option<(Arc<AccessHints>, u64)>,
+    /// Path of the base URI, stripped from request paths
+    base_path: String,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for AccessHintService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<CacheBody>, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Default + Send + 'static,
+{
+    type Response = Response<CacheBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let Some((hints, installation_id)) = self.hints.clone() else {
+            return Box::pin(self.inner.call(req));
+        };
+
+        let method = req.method().clone();
+        let path = req
+            .uri()
+            .path()
+            .strip_prefix(self.base_path.as_str())
+            .unwrap_or(req.uri().path())
+            .to_string();
+        // Lookups reuse the credentials of the failed request
+        let lookup = Lookup {
+            service: self.inner.clone(),
+            uri: req.uri().clone(),
+            base_path: self.base_path.clone(),
+            headers: [AUTHORIZATION, USER_AGENT]
+                .into_iter()
+                .filter_map(|name| Some((name.clone(), req.headers().get(&name)?.clone())))
+                .collect(),
+        };
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?;
+            let status = response.status();
+            let rate_limited = response
+                .headers()
+                .get("x-ratelimit-remaining")
+                .is_some_and(|remaining| remaining == "0");
+            if !matches!(status, StatusCode::NOT_FOUND | StatusCode::FORBIDDEN)
+                || rate_limited
+                || path == "/installation/repositories"
+            {
+                return Ok(response);
+            }
+
+            let repository = repository_of(&path);
+            let accessible = match (&repository, hints.get(installation_id)) {
+                (None, _) => None,
+                (Some(_), Some(cached)) => cached,
+                (Some(_), None) => {
+                    let accessible = match lookup.accessible_repositories().await {
+                        Ok(accessible) => Some(Arc::new(accessible)),
+                        Err(e) => {
+                            debug!(
+                                "Failed to look up repositories of installation {}: {}",
+                                installation_id, e
+                            );
+                            None
+                        }
+                    };
+                    hints.insert(installation_id, accessible.clone());
+                    accessible
+                }
+            };
+            let Some(hint) = hint(
+                status,
+                installation_id,
+                repository.as_deref(),
+                accessible.as_deref(),
+                required_permission(&method, &path),
+            ) else {
+                return Ok(response);
+            };
+
+            let (mut parts, body) = response.into_parts();
+            let body = body.collect().await?.to_bytes();
+            let Some(body) = with_hint(
+                &body,
+                &format!("{} from {} {}: {}", status.as_u16(), method, path, hint),
+            ) else {
+                return Ok(Response::from_parts(parts, full(body)));
+            };
+            parts.headers.remove(CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, full(body)))
+        })
+    }
+}
+
+/// Request of the repositories accessible to an installation
+struct Lookup<S> {
+    service: S,
+    /// URI of the failed request, providing scheme and authority
+    uri: Uri,
+    base_path: String,
+    headers: Vec<(http::HeaderName, http::HeaderValue)>,
+}
+
+impl<S> Lookup<S> {
+    async fn accessible_repositories<ReqBody>(self) -> Result<AccessibleRepositories, BoxError>
+    where
+        S: Service<Request<ReqBody>, Response = Response<CacheBody>, Error = BoxError> + Clone,
+        ReqBody: Default,
+    {
+        let mut selection = None;
+        let mut full_names = HashSet::new();
+        for page in 1usize.. {
+            let mut uri = Uri::builder().path_and_query(format!(
+                "{}/installation/repositories?per_page={}&page={}",
+                self.base_path, REPOSITORIES_PER_PAGE, page
+            ));
+            if let (Some(scheme), Some(authority)) = (self.uri.scheme(), self.uri.authority()) {
+                uri = uri.scheme(scheme.clone()).authority(authority.clone());
+            }
+            let mut request = Request::get(uri.build()?)
+                .header(ACCEPT, "application/vnd.github+json")
+                .body(ReqBody::default())?;
+            request.headers_mut().extend(self.headers.iter().cloned());
+
+            let response = self.service.clone().oneshot(request).await?;
+            if !response.status().is_success() {
+                return Err(format!("GitHub answered {}", response.status()).into());
+            }
+            let body = response.into_body().collect().await?.to_bytes();
+            let page: RepositoriesPage = serde_json::from_slice(&body)?;
+
+            selection = selection.or(page.repository_selection);
+            let done = page.repositories.len() < REPOSITORIES_PER_PAGE;
+            full_names.extend(
+                page.repositories
+                    .into_iter()
+                    .map(|repository| repository.full_name.to_lowercase()),
+            );
+            if done {
+                break;
+            }
+        }
+
+        Ok(AccessibleRepositories {
+            selection,
+            full_names,
+        })
+    }
+}
+
+/// Full name (`owner/name`) of the repository of a `/repos/{owner}/{repo}`
+/// path
+fn repository_of(path: &str) -> Option<String> {
+    let mut segments = path.strip_prefix("/repos/")?.split('/');
+    let owner = segments.next().filter(|owner| !owner.is_empty())?;
+    let repo = segments.next().filter(|repo| !repo.is_empty())?;
+    Some(format!("{}/{}", owner, repo))
+}
+
+/// Permission an installation needs for the endpoint of `method` and `path`
+///
+/// Covers the endpoints used by Octofer's helpers; `None` for others.
+fn required_permission(method: &Method, path: &str) -> Option<(&'static str, Permission)> {
+    let level = if matches!(*method, Method::GET | Method::HEAD) {
+        Permission::Read
+    } else {
+        Permission::Write
+    };
+
+    let name = if let Some(rest) = path.strip_prefix("/repos/") {
+        let resource: Vec<&str> = rest.split('/').skip(2).collect();
+        match resource.as_slice() {
+            [] => "metadata",
+            ["check-runs" | "check-suites", ..] => "checks",
+            ["commits", _, "check-runs" | "check-suites", ..] => "checks",
+            ["statuses", ..] | ["commits", _, "status" | "statuses"] => "statuses",
+            ["issues" | "labels" | "milestones", ..] => "issues",
+            ["pulls", ..] => "pull_requests",
+            ["contents" | "git" | "commits" | "compare" | "dispatches", ..] => "contents",
+            ["actions", ..] => "actions",
+            ["deployments", ..] => "deployments",
+            ["secret-scanning", ..] => "secret_scanning_alerts",
+            ["dependabot", ..] => "vulnerability_alerts",
+            ["collaborators", ..] => "metadata",
+            _ => return None,
+        }
+    } else if let Some(rest) = path.strip_prefix("/orgs/") {
+        match rest.split('/').nth(1)? {
+            "members" | "memberships" | "teams" => "members",
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+    Some((name, level))
+}
+
+/// Explain a `404` or `403` of a request to `repository`, if possible
+fn hint(
+    status: StatusCode,
+    installation_id: u64,
+    repository: Option<&str>,
+    accessible: Option<&AccessibleRepositories>,
+    permission: Option<(&'static str, Permission)>,
+) -> Option<String> {
+    let requires = permission.map(|(name, level)| format!("`{}: {}`", name, level));
+
+    if let (Some(repository), Some(accessible)) = (repository, accessible) {
+        if !accessible.full_names.contains(&repository.to_lowercase()) {
+            return Some(match accessible.selection {
+                Some(RepositorySelection::Selected) => format!(
+                    "repo '{}' is not in installation {}'s selected repositories",
+                    repository, installation_id
+                ),
+                _ => format!(
+                    "repo '{}' does not exist or belongs to another account than installation {}",
+                    repository, installation_id
+                ),
+            });
+        }
+        if status == StatusCode::NOT_FOUND {
+            return Some(format!(
+                "repo '{}' is accessible to installation {}, so the resource does not exist{}",
+                repository,
+                installation_id,
+                requires
+                    .map(|requires| format!(
+                        " or the endpoint's {} permission is missing",
+                        requires
+                    ))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+
+    requires.map(|requires| {
+        format!(
+            "the endpoint requires the {} permission; check that installation {} was granted it",
+            requires, installation_id
+        )
+    })
+}
+
+/// Prefix the `message` of a JSON error body with `hint`
+fn with_hint(body: &[u8], hint: &str) -> Option<Bytes> {
+    let mut error: Value = serde_json::from_slice(body).ok()?;
+    let message = error.get("message")?.as_str()?;
+    error["message"] = Value::String(format!("{} (GitHub: {})", hint, message));
+    serde_json::to_vec(&error).ok().map(Bytes::from)
+}
+
+fn full(body: Bytes) -> CacheBody {
+    http_body_util::Full::new(body)
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockGitHub;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn error(status: StatusCode, message: &'static str) -> (StatusCode, Json<Value>) {
+        (status, Json(json!({ "message": message })))
+    }
+
+    /// Mock of an installation with access to `octofer/app` only, counting
+    /// the lookups of its repositories in `lookups`
+    async fn mock_github(lookups: Arc<AtomicUsize>) -> MockGitHub {
+        MockGitHub::start(
+            Router::new()
+                .route(
+                    "/installation/repositories",
+                    get(move || async move {
+                        lookups.fetch_add(1, Ordering::SeqCst);
+                        Json(json!({
+                            "total_count": 1,
+                            "repository_selection": "selected",
+                            "repositories": [{ "full_name": "octofer/app" }],
+                        }))
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/issues/404",
+                    get(|| async { error(StatusCode::NOT_FOUND, "Not Found") }),
+                )
+                .route(
+                    "/repos/octofer/app/labels",
+                    post(|| async {
+                        error(
+                            StatusCode::FORBIDDEN,
+                            "Resource not accessible by integration",
+                        )
+                    }),
+                )
+                .route(
+                    "/repos/octofer/hidden/issues",
+                    get(|| async { error(StatusCode::NOT_FOUND, "Not Found") }),
+                ),
+        )
+        .await
+    }
+
+    fn message(error: octocrab::Error) -> String {
+        match error {
+            octocrab::Error::GitHub { source, .. } => source.message,
+            other => panic!("unexpected error {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inaccessible_repository_is_explained() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let mock = mock_github(lookups.clone()).await;
+        let client = mock.client().installation_client(1).await.unwrap();
+
+        let error = client
+            .get::<Value, _, ()>("/repos/octofer/hidden/issues", None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            message(error),
+            "404 from GET /repos/octofer/hidden/issues: repo 'octofer/hidden' is not in \
+             installation 1's selected repositories (GitHub: Not Found)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_resource_and_permission_are_explained() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let mock = mock_github(lookups.clone()).await;
+        let client = mock.client().installation_client(1).await.unwrap();
+
+        let error = client
+            .get::<Value, _, ()>("/repos/octofer/app/issues/404", None)
+            .await
+            .unwrap_err();
+        let octocrab::Error::GitHub { source, .. } = &error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(source.status_code, StatusCode::NOT_FOUND);
+        assert!(source.message.contains(
+            "repo 'octofer/app' is accessible to installation 1, so the resource does not exist \
+             or the endpoint's `issues: read` permission is missing"
+        ));
+
+        let error = client
+            .post::<_, Value>("/repos/octofer/app/labels", Some(&json!({ "name": "bug" })))
+            .await
+            .unwrap_err();
+        assert!(message(error).starts_with(
+            "403 from POST /repos/octofer/app/labels: the endpoint requires the `issues: write` \
+             permission"
+        ));
+
+        // The accessible repositories were looked up once
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_required_permissions() {
+        let permission = |method: Method, path: &str| required_permission(&method, path);
+        assert_eq!(
+            permission(Method::POST, "/repos/o/r/check-runs"),
+            Some(("checks", Permission::Write))
+        );
+        assert_eq!(
+            permission(Method::GET, "/repos/o/r/commits/abc/check-runs"),
+            Some(("checks", Permission::Read))
+        );
+        assert_eq!(
+            permission(Method::GET, "/repos/o/r/commits/abc"),
+            Some(("contents", Permission::Read))
+        );
+        assert_eq!(
+            permission(Method::PATCH, "/repos/o/r/dependabot/alerts/3"),
+            Some(("vulnerability_alerts", Permission::Write))
+        );
+        assert_eq!(
+            permission(Method::GET, "/orgs/o/teams/t/members"),
+            Some(("members", Permission::Read))
+        );
+        assert_eq!(permission(Method::GET, "/user"), None);
+    }
+}
@@ -0,0 +1,396 @@
+//! Audit log of the GitHub API writes performed by handlers
+//!
+//! Every handler invocation gets an [`AuditTrail`], shared with the
+//! installation clients it obtains through
+//! [`Context::installation_client`](crate::Context::installation_client),
+//! which includes the clients used by the [`Context`](crate::Context)
+//! helpers. The [`AuditLayer`] of those clients passes an [`AuditRecord`] of
+//! every mutating request (any method but `GET` and `HEAD`) to the app's
+//! [`AuditSink`], once its response is received:
+//!
+//! - [`TracingAuditSink`], the default, logs records as structured events at
+//!   info level with the `octofer::audit` target;
+//! - [`JsonlAuditSink`] appends them to a JSONL file, enabled with
+//!   [`GitHubConfig::audit_log_path`](crate::config::GitHubConfig::audit_log_path).
+//!
+//! GraphQL requests are always `POST`, so queries are recorded as well as
+//! mutations. Requests rejected by the handler's
+//! [API budget](crate::github::layers::budget) never reach GitHub and are not
+//! recorded, and retries of a request are recorded once. Writes of the
+//! framework itself, e.g. posting [queued comments](crate::Context::queue_comment)
+//! after the handlers ran, do not belong to a handler and are not recorded.
+//!
+//! The number of records of a delivery is reported in
+//! [`DeliveryReport::audited_calls`](crate::webhook::report::DeliveryReport::audited_calls).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::layers::{AuditRecord, AuditSink};
+//! use octofer::Octofer;
+//!
+//! struct Stdout;
+//!
+//! impl AuditSink for Stdout {
+//!     fn record(&self, record: &AuditRecord) {
+//!         println!(
+//!             "{} {} {} by {}",
+//!             record.method, record.path, record.status.unwrap_or_default(), record.handler
+//!         );
+//!     }
+//! }
+//!
+//! # fn example(app: Octofer) {
+//! app.set_audit_sink(Stdout);
+//! # }
+//! ```
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use chrono::{DateTime, Utc};
+use http::{Method, Request, Response};
+use serde::Serialize;
+use tower::{BoxError, Layer, Service};
+use tracing::{info, warn};
+
+/// Record of a mutating GitHub API request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditRecord {
+    /// Time the request was sent
+    pub timestamp: DateTime<Utc>,
+    /// HTTP method, e.g. `POST`
+    pub method: String,
+    /// Path of the request, e.g. `/repos/octocat/hello-world/issues/1/comments`
+    pub path: String,
+    /// Response status; `None` if no response was received
+    pub status: Option<u16>,
+    /// ID of the delivery the handler ran for, if sent
+    pub delivery_id: Option<String>,
+    /// Name of the handler, see
+    /// [`RegisteredHandler::name`](crate::dispatch::RegisteredHandler::name)
+    pub handler: String,
+    /// Installation the request was made as
+    pub installation_id: Option<u64>,
+}
+
+/// Destination of the [`AuditRecord`]s of an app
+///
+/// Sinks are called from the handlers' requests, so they should not block
+/// for long.
+pub trait AuditSink: Send + Sync + 'static {
+    /// Store `record`
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Sink logging records as structured tracing events
+///
+/// Events are logged at info level with the `octofer::audit` target, with
+/// one field per record field.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        info!(
+            target: "octofer::audit",
+            method = %record.method,
+            path = %record.path,
+            status = record.status,
+            delivery_id = record.delivery_id.as_deref(),
+            handler = %record.handler,
+            installation_id = record.installation_id,
+            timestamp = %record.timestamp.to_rfc3339(),
+            "GitHub API {} {}",
+            record.method,
+            record.path
+        );
+    }
+}
+
+/// Sink appending records to a JSONL file
+///
+/// The file and its directory are created when the first record is written.
+/// Records that cannot be written are logged as warnings.
+#[derive(Debug)]
+pub struct JsonlAuditSink {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl JsonlAuditSink {
+    /// Create a sink appending to the file at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            file: Mutex::new(None),
+        }
+    }
+
+    fn write(&self, record: &AuditRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if file.is_none() {
+            if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)?;
+            }
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        let file = file.as_mut().expect("audit log was just opened");
+        file.write_all(&line)?;
+        file.flush()
+    }
+}
+
+impl AuditSink for JsonlAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        if let Err(e) = self.write(record) {
+            warn!(
+                "Failed to write audit record to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Recorder of the API writes of one handler invocation
+///
+/// Created by the framework for every handler invocation and shared with
+/// the installation clients handed out by its [`Context`](crate::Context).
+pub struct AuditTrail {
+    sink: Arc<dyn AuditSink>,
+    delivery_id: Option<String>,
+    handler: String,
+    installation_id: Option<u64>,
+    /// Number of requests recorded so far
+    recorded: AtomicUsize,
+}
+
+impl AuditTrail {
+    /// Create a trail passing the records of `handler` to `sink`
+    pub fn new(
+        sink: Arc<dyn AuditSink>,
+        delivery_id: Option<String>,
+        handler: impl Into<String>,
+        installation_id: Option<u64>,
+    ) -> Self {
+        Self {
+            sink,
+            delivery_id,
+            handler: handler.into(),
+            installation_id,
+            recorded: AtomicUsize::new(0),
+        }
+    }
+
+    /// Name of the handler the trail records the requests of
+    pub fn handler(&self) -> &str {
+        &self.handler
+    }
+
+    /// Number of requests recorded so far
+    pub fn recorded(&self) -> usize {
+        self.recorded.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, timestamp: DateTime<Utc>, method: &Method, path: String, status: Option<u16>) {
+        self.recorded.fetch_add(1, Ordering::Relaxed);
+        self.sink.record(&AuditRecord {
+            timestamp,
+            method: method.to_string(),
+            path,
+            status,
+            delivery_id: self.delivery_id.clone(),
+            handler: self.handler.clone(),
+            installation_id: self.installation_id,
+        });
+    }
+}
+
+impl fmt::Debug for AuditTrail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditTrail")
+            .field("delivery_id", &self.delivery_id)
+            .field("handler", &self.handler)
+            .field("installation_id", &self.installation_id)
+            .field("recorded", &self.recorded())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Layer that records mutating requests in an optional [`AuditTrail`]
+///
+/// When no trail is set the layer is a no-op.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLayer {
+    trail: Option<Arc<AuditTrail>>,
+}
+
+impl AuditLayer {
+    /// Create a new audit layer
+    pub fn new(trail: Option<Arc<AuditTrail>>) -> Self {
+        Self { trail }
+    }
+}
+
+impl<S> Layer<S> for AuditLayer {
+    type Service = AuditService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuditService {
+            inner,
+            trail: self.trail.clone(),
+        }
+    }
+}
+
+/// Service created by [`AuditLayer`]
+#[derive(Debug, Clone)]
+pub struct AuditService<S> {
+    inner: S,
+    trail: Option<Arc<AuditTrail>>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AuditService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let trail = match &self.trail {
+            Some(trail) if !matches!(*req.method(), Method::GET | Method::HEAD) => trail.clone(),
+            _ => {
+                let fut = self.inner.call(req);
+                return Box::pin(async move { fut.await.map_err(Into::into) });
+            }
+        };
+
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let timestamp = Utc::now();
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let result = fut.await.map_err(Into::into);
+            let status = result
+                .as_ref()
+                .ok()
+                .map(|response| response.status().as_u16());
+            trail.record(timestamp, &method, path, status);
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::Dispatcher;
+    use crate::testing::{comment, issues_payload, MockGitHub, TEST_INSTALLATION_ID};
+    use axum::{routing::get, Json, Router};
+    use serde_json::Value;
+
+    /// Sink keeping records in memory
+    #[derive(Default)]
+    struct Records(Mutex<Vec<AuditRecord>>);
+
+    impl AuditSink for Arc<Records> {
+        fn record(&self, record: &AuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_writes_are_recorded() {
+        let mock = MockGitHub::start(
+            Router::new().route(
+                "/repos/octofer/app/issues/1/comments",
+                get(|| async { Json(Value::Array(Vec::new())) })
+                    .post(|| async { Json(comment(100, "octofer-test[bot]", "Thanks!")) }),
+            ),
+        )
+        .await;
+        let dispatcher = Dispatcher::new(None);
+        let records = Arc::new(Records::default());
+        dispatcher.set_audit_sink(records.clone());
+        dispatcher
+            .on(
+                "issues",
+                |context: crate::Context, _: Arc<()>| async move {
+                    context.upsert_comment("welcome", "Thanks!").await?;
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await
+            .named("welcome");
+
+        let mut context = mock.context("issues", issues_payload("opened", 1));
+        context.delivery_id = Some("d-1".to_string());
+        let (report, result) = dispatcher.dispatch_with_report(context).await;
+        result.unwrap();
+
+        let records = records.0.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].method, "POST");
+        assert_eq!(records[0].path, "/repos/octofer/app/issues/1/comments");
+        assert_eq!(records[0].status, Some(200));
+        assert_eq!(records[0].delivery_id.as_deref(), Some("d-1"));
+        assert_eq!(records[0].handler, "welcome");
+        assert_eq!(records[0].installation_id, Some(TEST_INSTALLATION_ID));
+        assert_eq!(report.audited_calls, 1);
+    }
+
+    #[test]
+    fn test_jsonl_sink_appends_records() {
+        let dir = std::env::temp_dir().join(format!("octofer-audit-{}", std::process::id()));
+        let path = dir.join("audit/writes.jsonl");
+        let sink = JsonlAuditSink::new(&path);
+        let trail = AuditTrail::new(Arc::new(sink), None, "labeler", Some(7));
+        for status in [Some(201), None] {
+            trail.record(
+                Utc::now(),
+                &Method::DELETE,
+                "/repos/o/r/labels/bug".into(),
+                status,
+            );
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["method"], "DELETE");
+        assert_eq!(lines[0]["status"], 201);
+        assert_eq!(lines[1]["status"], Value::Null);
+        assert_eq!(lines[1]["handler"], "labeler");
+        assert_eq!(trail.recorded(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+//! Per-invocation GitHub API call budget
+//!
+//! A runaway handler (for example an accidental loop of API calls) can burn an
+//! installation's whole rate limit in minutes. An [`ApiBudget`] caps the number
+//! of requests a single handler invocation may perform: the [`BudgetLayer`]
+//! counts every request sent through the client and fails fast with
+//! [`BudgetExceeded`] once the cap is reached.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::layers::BudgetExceeded;
+//! use octofer::Context;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     if let Some(client) = context.installation_client().await? {
+//!         if let Err(e) = client.current().user().await {
+//!             // The budget error is the source of the octocrab service error
+//!             if std::error::Error::source(&e)
+//!                 .map(|s| s.is::<BudgetExceeded>())
+//!                 .unwrap_or(false)
+//!             {
+//!                 println!("Handler exceeded its API budget");
+//!             }
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::Request;
+use tower::{BoxError, Layer, Service};
+
+/// Counter of GitHub API requests allowed for one handler invocation
+///
+/// The budget is shared between the [`Context`](crate::Context) of a handler
+/// invocation and every installation client it hands out, so requests made
+/// through any of those clients count against the same cap.
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::github::layers::ApiBudget;
+///
+/// let budget = ApiBudget::new(2);
+/// assert!(budget.try_acquire().is_ok());
+/// assert!(budget.try_acquire().is_ok());
+/// assert!(budget.try_acquire().is_err());
+/// assert_eq!(budget.used(), 2);
+/// assert_eq!(budget.remaining(), 0);
+/// ```
+#[derive(Debug)]
+pub struct ApiBudget {
+    /// Maximum number of requests allowed
+    limit: usize,
+    /// Number of requests performed so far
+    used: AtomicUsize,
+    /// Number of requests rejected because the budget was exhausted
+    rejected: AtomicUsize,
+}
+
+impl ApiBudget {
+    /// Create a new budget allowing up to `limit` requests
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+            rejected: AtomicUsize::new(0),
+        }
+    }
+
+    /// Maximum number of requests allowed by this budget
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Number of requests performed so far
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests still allowed
+    pub fn remaining(&self) -> usize {
+        self.limit.saturating_sub(self.used())
+    }
+
+    /// Whether at least one request was rejected because the budget ran out
+    pub fn is_exceeded(&self) -> bool {
+        self.rejected.load(Ordering::Relaxed) > 0
+    }
+
+    /// Reserve one request from the budget
+    ///
+    /// Returns `Err(BudgetExceeded)` without consuming anything if the budget
+    /// is already exhausted.
+    pub fn try_acquire(&self) -> Result<(), BudgetExceeded> {
+        let acquired = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < self.limit).then_some(used + 1)
+            });
+
+        match acquired {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(BudgetExceeded { limit: self.limit })
+            }
+        }
+    }
+}
+
+/// Error returned when a handler exceeds its API budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    /// The budget limit that was hit
+    pub limit: usize,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GitHub API budget of {} requests exceeded for this handler invocation",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Layer that enforces an optional [`ApiBudget`] on outgoing requests
+///
+/// When no budget is set the layer is a no-op.
+#[derive(Debug, Clone, Default)]
+pub struct BudgetLayer {
+    budget: Option<Arc<ApiBudget>>,
+}
+
+impl BudgetLayer {
+    /// Create a new budget layer
+    pub fn new(budget: Option<Arc<ApiBudget>>) -> Self {
+        Self { budget }
+    }
+}
+
+impl<S> Layer<S> for BudgetLayer {
+    type Service = BudgetService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BudgetService {
+            inner,
+            budget: self.budget.clone(),
+        }
+    }
+}
+
+/// Service created by [`BudgetLayer`]
+#[derive(Debug, Clone)]
+pub struct BudgetService<S> {
+    inner: S,
+    budget: Option<Arc<ApiBudget>>,
+}
+
+impl<S, B> Service<Request<B>> for BudgetService<S>
+where
+    S: Service<Request<B>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if let Some(budget) = &self.budget {
+            if let Err(e) = budget.try_acquire() {
+                tracing::warn!("{} ({} {})", e, req.method(), req.uri().path());
+                return Box::pin(async move { Err(e.into()) });
+            }
+        }
+
+        let fut = self.inner.call(req);
+        Box::pin(async move { fut.await.map_err(Into::into) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_budget_layer_rejects_after_limit() {
+        let budget = Arc::new(ApiBudget::new(2));
+        let service = BudgetLayer::new(Some(budget.clone())).layer(tower::service_fn(
+            |_req: Request<()>| async { Ok::<_, BoxError>(()) },
+        ));
+
+        for _ in 0..2 {
+            service
+                .clone()
+                .oneshot(Request::new(()))
+                .await
+                .expect("request within budget should succeed");
+        }
+
+        let err = service
+            .clone()
+            .oneshot(Request::new(()))
+            .await
+            .expect_err("request over budget should fail");
+
+        assert!(err.is::<BudgetExceeded>());
+        assert_eq!(budget.used(), 2);
+        assert!(budget.is_exceeded());
+    }
+
+    #[tokio::test]
+    async fn test_budget_layer_without_budget_is_noop() {
+        let service = BudgetLayer::new(None).layer(tower::service_fn(|_req: Request<()>| async {
+            Ok::<_, BoxError>(())
+        }));
+
+        for _ in 0..10 {
+            service.clone().oneshot(Request::new(())).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,617 @@
+//! Dry-run mode intercepting the GitHub API writes of handlers
+//!
+//! To try a new behavior on real traffic without acting on it, handlers can
+//! run in dry-run mode, for the whole app with
+//! [`Octofer::dry_run`](crate::Octofer::dry_run) or per handler with
+//! [`HandlerRegistration::dry_run`](crate::core::HandlerRegistration::dry_run).
+//! Their invocations get a [`DryRun`], shared with the installation clients
+//! they obtain through
+//! [`Context::installation_client`](crate::Context::installation_client),
+//! which includes the clients used by the [`Context`](crate::Context)
+//! helpers. The [`DryRunLayer`] of those clients lets `GET` and `HEAD`
+//! requests and GraphQL queries through, but records every other request as
+//! an [`IntendedAction`] instead of sending it, and answers it with a
+//! synthesized success response:
+//!
+//! - `201 Created` for `POST`, `204 No Content` for `DELETE` and `200 OK`
+//!   otherwise;
+//! - the body of the first [`DryRunResponses`] entry matching the request,
+//!   with the request's string, number and boolean fields of the same name
+//!   and type copied over, so e.g. a created comment has the body that was
+//!   sent. Requests matching no entry get `{}`.
+//!
+//! The built-in responses cover creating and updating issues and comments,
+//! adding labels and GraphQL mutations; other endpoints whose responses
+//! handlers read can be added with [`DryRunResponses::with`].
+//!
+//! Intercepted requests never reach GitHub, so they are not
+//! [audited](crate::github::layers::audit), but count against the handler's
+//! [API budget](crate::github::layers::budget). Comment sections
+//! [queued](crate::Context::queue_comment) by a dry-run handler are not
+//! posted either. Requests sent through clients obtained otherwise, e.g.
+//! from [`Context::github`](crate::Context::github), are not intercepted.
+//!
+//! The intended actions of a delivery are listed in
+//! [`DeliveryReport::intended_actions`](crate::webhook::report::DeliveryReport::intended_actions).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::layers::DryRunResponses;
+//! use octofer::Octofer;
+//! use serde_json::json;
+//!
+//! # async fn example(app: Octofer) {
+//! app.dry_run(true).await;
+//! app.set_dry_run_responses(DryRunResponses::builtin().with(
+//!     "PUT",
+//!     "/repos/{owner}/{repo}/pulls/{number}/merge",
+//!     json!({ "sha": "0000000000000000000000000000000000000000", "merged": true, "message": "" }),
+//! ));
+//! # }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Method, Request, Response, StatusCode, Uri};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tower::{BoxError, Layer, Service};
+use tracing::info;
+
+use crate::github::layers::CacheBody;
+
+/// Mutating GitHub API request intercepted in dry-run mode
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IntendedAction {
+    /// Name of the handler, see
+    /// [`RegisteredHandler::name`](crate::dispatch::RegisteredHandler::name)
+    pub handler: String,
+    /// HTTP method, e.g. `POST`
+    pub method: String,
+    /// Path of the request, e.g. `/repos/octocat/hello-world/issues/1/comments`
+    pub path: String,
+    /// JSON body of the request, if any
+    pub body: Option<Value>,
+}
+
+/// Bodies of the responses synthesized for intercepted requests
+///
+/// Entries are matched in order against the method and path of requests.
+/// Path segments written `{name}` match any segment, e.g.
+/// `/repos/{owner}/{repo}/issues/{number}/comments`.
+#[derive(Debug, Clone)]
+pub struct DryRunResponses {
+    entries: Vec<(Method, Vec<String>, Value)>,
+}
+
+impl DryRunResponses {
+    /// Create a set without any entry, answering every request with `{}`
+    pub fn empty() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Create the built-in set, covering issues, comments, labels and
+    /// GraphQL mutations
+    pub fn builtin() -> Self {
+        let comment = json!({
+            "id": 0,
+            "node_id": "IC_dry_run",
+            "url": "https://api.github.com/dry-run",
+            "html_url": "https://github.com/dry-run",
+            "body": "",
+            "author_association": "NONE",
+            "user": dry_run_user(),
+            "created_at": "1970-01-01T00:00:00Z",
+        });
+        let issue = json!({
+            "id": 0,
+            "node_id": "I_dry_run",
+            "url": "https://api.github.com/dry-run",
+            "repository_url": "https://api.github.com/dry-run",
+            "labels_url": "https://api.github.com/dry-run",
+            "comments_url": "https://api.github.com/dry-run",
+            "events_url": "https://api.github.com/dry-run",
+            "html_url": "https://github.com/dry-run",
+            "number": 0,
+            "state": "open",
+            "title": "",
+            "body": "",
+            "user": dry_run_user(),
+            "labels": [],
+            "assignees": [],
+            "author_association": "NONE",
+            "locked": false,
+            "comments": 0,
+            "created_at": "1970-01-01T00:00:00Z",
+            "updated_at": "1970-01-01T00:00:00Z",
+        });
+
+        Self::empty()
+            .with(
+                "POST",
+                "/repos/{owner}/{repo}/issues/{number}/comments",
+                comment.clone(),
+            )
+            .with(
+                "PATCH",
+                "/repos/{owner}/{repo}/issues/comments/{id}",
+                comment,
+            )
+            .with("POST", "/repos/{owner}/{repo}/issues", issue.clone())
+            .with("PATCH", "/repos/{owner}/{repo}/issues/{number}", issue)
+            .with(
+                "POST",
+                "/repos/{owner}/{repo}/issues/{number}/labels",
+                json!([]),
+            )
+            .with(
+                "PUT",
+                "/repos/{owner}/{repo}/issues/{number}/labels",
+                json!([]),
+            )
+            .with("POST", "/graphql", json!({ "data": {} }))
+    }
+
+    /// Answer requests of `method` to paths matching `pattern` with `body`
+    ///
+    /// The entry takes precedence over the existing ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `method` is not a valid HTTP method.
+    pub fn with(mut self, method: &str, pattern: &str, body: Value) -> Self {
+        let method = Method::from_bytes(method.as_bytes()).expect("invalid HTTP method");
+        self.entries.insert(0, (method, segments(pattern), body));
+        self
+    }
+
+    /// Synthesize the response to a request of `method` to `path` with `body`
+    fn respond(&self, method: &Method, path: &str, body: Option<&Value>) -> (StatusCode, Value) {
+        let status = match *method {
+            Method::POST => StatusCode::CREATED,
+            Method::DELETE => StatusCode::NO_CONTENT,
+            _ => StatusCode::OK,
+        };
+        let path = segments(path);
+        let canned = self.entries.iter().find(|(entry_method, pattern, _)| {
+            entry_method == method
+                && pattern.len() == path.len()
+                && pattern
+                    .iter()
+                    .zip(&path)
+                    .all(|(expected, actual)| expected.starts_with('{') || expected == actual)
+        });
+        let Some((_, _, canned)) = canned else {
+            return (status, json!({}));
+        };
+
+        let mut response = canned.clone();
+        if let (Some(response), Some(Value::Object(sent))) = (response.as_object_mut(), body) {
+            for (name, value) in sent {
+                if let Some(field) = response.get_mut(name) {
+                    if same_scalar_type(field, value) {
+                        *field = value.clone();
+                    }
+                }
+            }
+        }
+        (status, response)
+    }
+}
+
+impl Default for DryRunResponses {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Recorder of the intended writes of one dry-run handler invocation
+///
+/// Created by the framework for every invocation of a handler in dry-run
+/// mode and shared with the installation clients handed out by its
+/// [`Context`](crate::Context).
+pub struct DryRun {
+    handler: String,
+    responses: Arc<DryRunResponses>,
+    actions: Mutex<Vec<IntendedAction>>,
+}
+
+impl DryRun {
+    /// Create a recorder of the writes of `handler`, answered with
+    /// `responses`
+    pub fn new(handler: impl Into<String>, responses: Arc<DryRunResponses>) -> Self {
+        Self {
+            handler: handler.into(),
+            responses,
+            actions: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Name of the handler the recorder intercepts the writes of
+    pub fn handler(&self) -> &str {
+        &self.handler
+    }
+
+    /// Get the requests intercepted so far, in the order they were sent
+    pub fn actions(&self) -> Vec<IntendedAction> {
+        self.actions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Record a request of `method` to `path` and synthesize its response
+    pub(crate) fn intercept(
+        &self,
+        method: &Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Bytes) {
+        info!(
+            "Dry run: handler '{}' would send {} {}",
+            self.handler, method, path
+        );
+        let (status, response) = self.responses.respond(method, path, body.as_ref());
+        self.actions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(IntendedAction {
+                handler: self.handler.clone(),
+                method: method.to_string(),
+                path: path.to_string(),
+                body,
+            });
+
+        let response = if status == StatusCode::NO_CONTENT {
+            Bytes::new()
+        } else {
+            Bytes::from(response.to_string())
+        };
+        (status, response)
+    }
+}
+
+impl fmt::Debug for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DryRun")
+            .field("handler", &self.handler)
+            .field("actions", &self.actions())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Build the response of an intercepted request
+pub(crate) fn synthesized_response<E: 'static>(
+    status: StatusCode,
+    body: Bytes,
+) -> Response<BoxBody<Bytes, E>> {
+    let json = !body.is_empty();
+    let mut response = Response::new(Full::new(body).map_err(|never| match never {}).boxed());
+    *response.status_mut() = status;
+    if json {
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    }
+    response
+}
+
+/// Layer that intercepts mutating requests when a [`DryRun`] is set
+///
+/// When no recorder is set the layer is a no-op.
+#[derive(Debug, Clone)]
+pub struct DryRunLayer {
+    dry_run: Option<Arc<DryRun>>,
+    base_uri: Uri,
+}
+
+impl DryRunLayer {
+    /// Create a layer for a client sending requests to `base_uri`
+    pub fn new(dry_run: Option<Arc<DryRun>>, base_uri: Uri) -> Self {
+        Self { dry_run, base_uri }
+    }
+}
+
+impl<S> Layer<S> for DryRunLayer {
+    type Service = DryRunService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DryRunService {
+            inner,
+            dry_run: self.dry_run.clone(),
+            base_path: self.base_uri.path().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+/// Service created by [`DryRunLayer`]
+#[derive(Debug, Clone)]
+pub struct DryRunService<S> {
+    inner: S,
+    dry_run: Option<Arc<DryRun>>,
+    /// Path of the base URI, stripped from request paths
+    base_path: String,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for DryRunService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<CacheBody>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    ReqBody: http_body::Body + From<Bytes> + Send + 'static,
+    ReqBody::Data: Send,
+    ReqBody::Error: Into<BoxError>,
+{
+    type Response = Response<CacheBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let dry_run = match &self.dry_run {
+            Some(dry_run) if !matches!(*req.method(), Method::GET | Method::HEAD) => {
+                dry_run.clone()
+            }
+            _ => {
+                let fut = self.inner.call(req);
+                return Box::pin(async move { fut.await.map_err(Into::into) });
+            }
+        };
+
+        let path = req
+            .uri()
+            .path()
+            .strip_prefix(self.base_path.as_str())
+            .unwrap_or(req.uri().path())
+            .to_string();
+        // The inner service is ready, the clone may not be
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = body.collect().await.map_err(Into::into)?.to_bytes();
+            let json = serde_json::from_slice::<Value>(&body).ok();
+
+            if path == "/graphql" && !is_mutation(json.as_ref()) {
+                let req = Request::from_parts(parts, ReqBody::from(body));
+                return inner.call(req).await.map_err(Into::into);
+            }
+
+            let (status, response) = dry_run.intercept(&parts.method, &path, json);
+            Ok(synthesized_response(status, response))
+        })
+    }
+}
+
+/// Whether a GraphQL request body is a mutation
+fn is_mutation(body: Option<&Value>) -> bool {
+    let query = body
+        .and_then(|body| body["query"].as_str())
+        .unwrap_or_default();
+    // Skip the comments before the operation
+    let operation = query
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default();
+    operation.starts_with("mutation")
+}
+
+fn segments(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `a` and `b` are both strings, numbers or booleans
+///
+/// Arrays and objects are not copied, since requests often send names where
+/// responses hold objects, e.g. the `labels` of an issue.
+fn same_scalar_type(a: &Value, b: &Value) -> bool {
+    matches!(
+        (a, b),
+        (Value::String(_), Value::String(_))
+            | (Value::Number(_), Value::Number(_))
+            | (Value::Bool(_), Value::Bool(_))
+    )
+}
+
+/// User of the synthesized responses
+fn dry_run_user() -> Value {
+    let url = "https://api.github.com/users/dry-run";
+    json!({
+        "login": "dry-run[bot]",
+        "id": 0,
+        "node_id": "BOT_dry_run",
+        "avatar_url": "https://avatars.githubusercontent.com/u/0",
+        "gravatar_id": "",
+        "url": url,
+        "html_url": "https://github.com/dry-run",
+        "followers_url": format!("{url}/followers"),
+        "following_url": format!("{url}/following"),
+        "gists_url": format!("{url}/gists"),
+        "starred_url": format!("{url}/starred"),
+        "subscriptions_url": format!("{url}/subscriptions"),
+        "organizations_url": format!("{url}/orgs"),
+        "repos_url": format!("{url}/repos"),
+        "events_url": format!("{url}/events"),
+        "received_events_url": format!("{url}/received_events"),
+        "type": "Bot",
+        "site_admin": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::{Dispatcher, QUEUED_COMMENTS_HANDLER};
+    use crate::github::graphql;
+    use crate::github::layers::{AuditRecord, AuditSink};
+    use crate::testing::{issues_payload, MockGitHub};
+    use axum::{routing::get, Json, Router};
+
+    /// Sink keeping records in memory
+    #[derive(Default)]
+    struct Records(Mutex<Vec<AuditRecord>>);
+
+    impl AuditSink for Arc<Records> {
+        fn record(&self, record: &AuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    fn comments_mock() -> Router {
+        Router::new().route(
+            "/repos/octofer/app/issues/1/comments",
+            get(|| async { Json(Value::Array(Vec::new())) })
+                .post(|| async { Json(json!({ "message": "Not expected" })) }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_handler_writes_are_intercepted() {
+        let mock = MockGitHub::start(comments_mock()).await;
+        let dispatcher = Dispatcher::new(None);
+        let records = Arc::new(Records::default());
+        dispatcher.set_audit_sink(records.clone());
+        dispatcher
+            .on(
+                "issues",
+                |context: crate::Context, _: Arc<()>| async move {
+                    let comment = context.upsert_comment("welcome", "Thanks!").await?;
+                    assert!(comment.body.unwrap().starts_with("Thanks!"));
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await
+            .named("welcome")
+            .dry_run();
+
+        let context = mock.context("issues", issues_payload("opened", 1));
+        let (report, result) = dispatcher.dispatch_with_report(context).await;
+        result.unwrap();
+
+        assert!(mock.requests().iter().all(|r| r.method == Method::GET));
+        assert_eq!(report.intended_actions.len(), 1);
+        let action = &report.intended_actions[0];
+        assert_eq!(action.handler, "welcome");
+        assert_eq!(action.method, "POST");
+        assert_eq!(action.path, "/repos/octofer/app/issues/1/comments");
+        assert!(action.body.as_ref().unwrap()["body"]
+            .as_str()
+            .unwrap()
+            .starts_with("Thanks!"));
+        assert!(records.0.lock().unwrap().is_empty());
+        assert_eq!(report.audited_calls, 0);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_app_does_not_post_queued_comments() {
+        let mock = MockGitHub::start(comments_mock()).await;
+        let dispatcher = Dispatcher::new(None);
+        dispatcher
+            .set_config(crate::config::DispatchConfig {
+                dry_run: true,
+                ..Default::default()
+            })
+            .await;
+        dispatcher
+            .on(
+                "issues",
+                |context: crate::Context, _: Arc<()>| async move {
+                    context.queue_comment("coverage", "Coverage: 87%");
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await;
+
+        let context = mock.context("issues", issues_payload("opened", 1));
+        let report = dispatcher.dispatch(context).await.unwrap();
+
+        assert!(mock.requests().iter().all(|r| r.method == Method::GET));
+        assert_eq!(report.comment_sections.len(), 1);
+        assert!(report.comment_sections[0].error.is_none());
+        let handlers: Vec<&str> = report
+            .intended_actions
+            .iter()
+            .map(|action| action.handler.as_str())
+            .collect();
+        assert_eq!(handlers, [QUEUED_COMMENTS_HANDLER]);
+    }
+
+    #[tokio::test]
+    async fn test_graphql_queries_are_sent_in_dry_run() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/graphql",
+            axum::routing::post(|| async {
+                Json(json!({ "data": { "viewer": { "login": "bot" } } }))
+            }),
+        ))
+        .await;
+        let dry_run = Arc::new(DryRun::new("sync", Arc::new(DryRunResponses::builtin())));
+        let context = mock
+            .context("issues", issues_payload("opened", 1))
+            .with_dry_run(Some(dry_run.clone()));
+        let client = context.installation_client().await.unwrap().unwrap();
+
+        let data = graphql::query(&client, "query { viewer { login } }", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(data["viewer"]["login"], "bot");
+        let data = graphql::query(
+            &client,
+            "# Close it\nmutation($id: ID!) { closeIssue(input: { issueId: $id }) { clientMutationId } }",
+            json!({ "id": "I_1" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(data, json!({}));
+
+        assert_eq!(mock.requests().len(), 1);
+        let actions = dry_run.actions();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].path, "/graphql");
+        assert_eq!(actions[0].body.as_ref().unwrap()["variables"]["id"], "I_1");
+    }
+
+    #[test]
+    fn test_responses_copy_fields_of_the_same_type() {
+        let responses = DryRunResponses::builtin().with(
+            "PUT",
+            "/repos/{owner}/{repo}/pulls/{number}/merge",
+            json!({ "merged": true, "message": "" }),
+        );
+
+        let (status, issue) = responses.respond(
+            &Method::PATCH,
+            "/repos/o/r/issues/3",
+            Some(&json!({ "state": "closed", "labels": ["bug"] })),
+        );
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(issue["state"], "closed");
+        assert_eq!(issue["labels"], json!([]));
+
+        let (_, merge) = responses.respond(&Method::PUT, "/repos/o/r/pulls/3/merge", None);
+        assert_eq!(merge["merged"], true);
+        let (status, other) = responses.respond(&Method::DELETE, "/repos/o/r/labels/bug", None);
+        assert_eq!((status, other), (StatusCode::NO_CONTENT, json!({})));
+    }
+}
@@ -0,0 +1,456 @@
+//! Conditional requests with cached ETags
+//!
+//! GitHub answers a `GET` carrying the `ETag` of the current representation in
+//! `If-None-Match` with `304 Not Modified`, and such responses do not count
+//! against the rate limit. The [`EtagLayer`] remembers the `ETag` and body of
+//! successful `GET` responses in a [`ResponseCache`] shared by all clients of
+//! a [`Transport`](crate::github::transport::Transport), sends the `ETag` with
+//! the next request for the same resource, and serves the cached body when
+//! GitHub answers `304`. Handlers see a regular `200` response, with the
+//! headers of the `304` (such as the rate limit headers) on top of the cached
+//! ones.
+//!
+//! Entries are keyed by [`CacheScope`], method, path and query, and `Accept`
+//! header, so installations never see each other's responses. The cache holds
+//! at most a configured number of entries, evicting the oldest, and drops
+//! entries older than its TTL. It is enabled with
+//! [`GitHubConfig::response_cache`](crate::config::GitHubConfig::response_cache).
+//!
+//! # Examples
+//!
+//! ```rust
+//! use octofer::github::layers::ResponseCache;
+//! use std::time::Duration;
+//!
+//! let cache = ResponseCache::new(1_000, Duration::from_secs(300));
+//! let stats = cache.stats();
+//! assert_eq!((stats.hits, stats.misses, stats.entries), (0, 0, 0));
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http::header::{ACCEPT, CONTENT_LENGTH, ETAG, IF_NONE_MATCH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use tower::{BoxError, Layer, Service};
+use tracing::debug;
+
+/// Body of the responses of an [`EtagService`]
+pub type CacheBody = BoxBody<Bytes, BoxError>;
+
+/// Identity a client authenticates as, separating cached responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheScope {
+    /// The GitHub App itself (JWT)
+    App,
+    /// An installation of the app
+    Installation(u64),
+}
+
+/// Key of a cached response: scope, method, path and query, `Accept` header
+type CacheKey = (CacheScope, Method, String, Option<HeaderValue>);
+
+/// A cached `200` response
+#[derive(Debug)]
+struct CachedResponse {
+    etag: HeaderValue,
+    headers: HeaderMap,
+    body: Bytes,
+    stored_at: Instant,
+}
+
+/// Counters of a [`ResponseCache`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResponseCacheStats {
+    /// Requests answered with `304 Not Modified` and served from the cache
+    pub hits: u64,
+    /// Cacheable requests that were not served from the cache
+    pub misses: u64,
+    /// Number of cached responses
+    pub entries: usize,
+}
+
+/// Bounded in-memory cache of `ETag`s and response bodies
+#[derive(Debug)]
+pub struct ResponseCache {
+    max_entries: usize,
+    ttl: Duration,
+    entries: Mutex<HashMap<CacheKey, Arc<CachedResponse>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    /// Create a cache of at most `max_entries` responses, each kept for `ttl`
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        Self {
+            max_entries,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the cache's counters
+    pub fn stats(&self) -> ResponseCacheStats {
+        ResponseCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().len(),
+        }
+    }
+
+    /// Forget all cached responses
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Forget the cached responses of the repository `full_name` (`owner/name`)
+    ///
+    /// Used when a repository is renamed or transferred, since GitHub
+    /// redirects requests for the old name. Returns the number of forgotten
+    /// responses.
+    pub fn forget_repository(&self, full_name: &str) -> usize {
+        let route = format!("/repos/{}", full_name.to_lowercase());
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|(_, _, path, _), _| {
+            let path = path.to_lowercase();
+            let Some((_, rest)) = path.split_once(&route) else {
+                return true;
+            };
+            !(rest.is_empty() || rest.starts_with(['/', '?']))
+        });
+        before - entries.len()
+    }
+
+    /// Forget the cached responses of the account `login`: those of its
+    /// repositories, and of its organization or user routes
+    ///
+    /// Used when the account is renamed. Returns the number of forgotten
+    /// responses.
+    pub fn forget_owner(&self, login: &str) -> usize {
+        let login = login.to_lowercase();
+        let routes = [
+            format!("/repos/{}", login),
+            format!("/orgs/{}", login),
+            format!("/users/{}", login),
+        ];
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|(_, _, path, _), _| {
+            let path = path.to_lowercase();
+            !routes.iter().any(|route| {
+                path.split_once(route.as_str())
+                    .is_some_and(|(_, rest)| rest.is_empty() || rest.starts_with(['/', '?']))
+            })
+        });
+        before - entries.len()
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Arc<CachedResponse>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(cached) if cached.stored_at.elapsed() < self.ttl => Some(cached.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: CacheKey, response: CachedResponse) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            entries.retain(|_, cached| cached.stored_at.elapsed() < self.ttl);
+            while entries.len() >= self.max_entries {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.stored_at)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, Arc::new(response));
+    }
+}
+
+/// Layer that sends conditional requests for responses in a [`ResponseCache`]
+///
+/// Without a cache or scope the layer only boxes response bodies.
+#[derive(Debug, Clone, Default)]
+pub struct EtagLayer {
+    cache: Option<Arc<ResponseCache>>,
+    scope: Option<CacheScope>,
+}
+
+impl EtagLayer {
+    /// Create a layer caching responses of clients authenticated as `scope`
+    pub fn new(cache: Option<Arc<ResponseCache>>, scope: Option<CacheScope>) -> Self {
+        Self { cache, scope }
+    }
+}
+
+impl<S> Layer<S> for EtagLayer {
+    type Service = EtagService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EtagService {
+            inner,
+            cache: self.cache.clone().zip(self.scope),
+        }
+    }
+}
+
+/// Service created by [`EtagLayer`]
+#[derive(Debug, Clone)]
+pub struct EtagService<S> {
+    inner: S,
+    cache: Option<(Arc<ResponseCache>, CacheScope)>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for EtagService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<CacheBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // Requests that are already conditional are left to the caller
+        let cache = self
+            .cache
+            .clone()
+            .filter(|_| req.method() == Method::GET && !req.headers().contains_key(IF_NONE_MATCH));
+        let Some((cache, scope)) = cache else {
+            let fut = self.inner.call(req);
+            return Box::pin(async move { Ok(box_body(fut.await.map_err(Into::into)?)) });
+        };
+
+        let key: CacheKey = (
+            scope,
+            req.method().clone(),
+            req.uri()
+                .path_and_query()
+                .map(|path| path.to_string())
+                .unwrap_or_default(),
+            req.headers().get(ACCEPT).cloned(),
+        );
+        let cached = cache.get(&key);
+        if let Some(cached) = &cached {
+            req.headers_mut().insert(IF_NONE_MATCH, cached.etag.clone());
+        }
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await.map_err(Into::into)?;
+            if let (StatusCode::NOT_MODIFIED, Some(cached)) = (response.status(), &cached) {
+                cache.hits.fetch_add(1, Ordering::Relaxed);
+                debug!("GitHub API {} served from the response cache", key.2);
+                return Ok(cached_response(cached, response.headers()));
+            }
+            cache.misses.fetch_add(1, Ordering::Relaxed);
+
+            let Some(etag) = response
+                .headers()
+                .get(ETAG)
+                .filter(|_| response.status() == StatusCode::OK)
+                .cloned()
+            else {
+                return Ok(box_body(response));
+            };
+            let (parts, body) = response.into_parts();
+            let body = body.collect().await.map_err(Into::into)?.to_bytes();
+            cache.insert(
+                key,
+                CachedResponse {
+                    etag,
+                    headers: parts.headers.clone(),
+                    body: body.clone(),
+                    stored_at: Instant::now(),
+                },
+            );
+            Ok(Response::from_parts(parts, full(body)))
+        })
+    }
+}
+
+/// Build the `200` response for a `304` to a request for `cached`
+fn cached_response(cached: &CachedResponse, not_modified: &HeaderMap) -> Response<CacheBody> {
+    let mut response = Response::new(full(cached.body.clone()));
+    let headers = response.headers_mut();
+    headers.clone_from(&cached.headers);
+    for (name, value) in not_modified {
+        if name != CONTENT_LENGTH && name != TRANSFER_ENCODING {
+            headers.insert(name, value.clone());
+        }
+    }
+    response
+}
+
+fn full(body: Bytes) -> CacheBody {
+    Full::new(body).map_err(|never| match never {}).boxed()
+}
+
+fn box_body<B>(response: Response<B>) -> Response<CacheBody>
+where
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+{
+    response.map(|body| body.map_err(Into::into).boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockGitHub;
+    use axum::{http::HeaderMap as Headers, response::IntoResponse, routing::get, Json, Router};
+    use serde_json::{json, Value};
+
+    fn repository_route() -> Router {
+        Router::new().route(
+            "/repos/octofer/app",
+            get(|headers: Headers| async move {
+                let rate_limit = [("x-ratelimit-remaining", "4999")];
+                if headers.get("if-none-match").and_then(|v| v.to_str().ok()) == Some("\"v1\"") {
+                    return (StatusCode::NOT_MODIFIED, rate_limit).into_response();
+                }
+                (
+                    rate_limit,
+                    [("etag", "\"v1\"")],
+                    Json(json!({ "full_name": "octofer/app" })),
+                )
+                    .into_response()
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_responses_are_served_from_cache() {
+        let cache = Arc::new(ResponseCache::new(10, Duration::from_secs(60)));
+        let mock = MockGitHub::start(repository_route()).await;
+        let transport = mock.transport().with_response_cache(cache.clone());
+        let client = transport
+            .installation_client(1, "token", None, None, None)
+            .unwrap();
+
+        for _ in 0..2 {
+            let response = client._get("/repos/octofer/app").await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["x-ratelimit-remaining"], "4999");
+            let body = client.body_to_string(response).await.unwrap();
+            let repository: Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(repository["full_name"], "octofer/app");
+        }
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].headers["if-none-match"], "\"v1\"");
+        assert_eq!(
+            cache.stats(),
+            ResponseCacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1,
+            }
+        );
+
+        // Other installations do not get the cached ETag
+        let other = transport
+            .installation_client(2, "token", None, None, None)
+            .unwrap();
+        other._get("/repos/octofer/app").await.unwrap();
+        assert!(!mock.requests()[2].headers.contains_key("if-none-match"));
+    }
+
+    #[test]
+    fn test_repository_responses_are_forgotten() {
+        let cache = ResponseCache::new(10, Duration::from_secs(60));
+        for path in [
+            "/repos/octofer/app",
+            "/repos/Octofer/App/issues/1/comments?per_page=100",
+            "/repos/octofer/app-docs",
+        ] {
+            cache.insert(
+                (
+                    CacheScope::Installation(1),
+                    Method::GET,
+                    path.to_string(),
+                    None,
+                ),
+                CachedResponse {
+                    etag: HeaderValue::from_static("\"x\""),
+                    headers: HeaderMap::new(),
+                    body: Bytes::new(),
+                    stored_at: Instant::now(),
+                },
+            );
+        }
+
+        assert_eq!(cache.forget_repository("octofer/app"), 2);
+        assert_eq!(cache.stats().entries, 1);
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let cache = ResponseCache::new(2, Duration::from_secs(60));
+        for path in ["/a", "/b", "/c"] {
+            cache.insert(
+                (CacheScope::App, Method::GET, path.to_string(), None),
+                CachedResponse {
+                    etag: HeaderValue::from_static("\"x\""),
+                    headers: HeaderMap::new(),
+                    body: Bytes::new(),
+                    stored_at: Instant::now(),
+                },
+            );
+        }
+
+        assert_eq!(cache.stats().entries, 2);
+        assert!(cache
+            .get(&(CacheScope::App, Method::GET, "/a".to_string(), None))
+            .is_none());
+        assert!(cache
+            .get(&(CacheScope::App, Method::GET, "/c".to_string(), None))
+            .is_some());
+
+        let expired = ResponseCache::new(2, Duration::ZERO);
+        expired.insert(
+            (CacheScope::App, Method::GET, "/a".to_string(), None),
+            CachedResponse {
+                etag: HeaderValue::from_static("\"x\""),
+                headers: HeaderMap::new(),
+                body: Bytes::new(),
+                stored_at: Instant::now(),
+            },
+        );
+        assert!(expired
+            .get(&(CacheScope::App, Method::GET, "/a".to_string(), None))
+            .is_none());
+    }
+}
@@ -0,0 +1,96 @@
+//! Debug logging of outgoing GitHub API requests
+//!
+//! The [`RequestLogLayer`] logs the method, path, response status and duration
+//! of every request at debug level, making API usage observable without a
+//! network capture. It is enabled with
+//! [`GitHubConfig::log_requests`](crate::config::GitHubConfig::log_requests).
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use http::{Request, Response};
+use tower::{BoxError, Layer, Service};
+use tracing::debug;
+
+/// Layer that logs outgoing requests when enabled
+///
+/// When disabled the layer is a no-op.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestLogLayer {
+    enabled: bool,
+}
+
+impl RequestLogLayer {
+    /// Create a new request logging layer
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl<S> Layer<S> for RequestLogLayer {
+    type Service = RequestLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLogService {
+            inner,
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// Service created by [`RequestLogLayer`]
+#[derive(Debug, Clone)]
+pub struct RequestLogService<S> {
+    inner: S,
+    enabled: bool,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestLogService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if !self.enabled {
+            let fut = self.inner.call(req);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let started = Instant::now();
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let result = fut.await.map_err(Into::into);
+            match &result {
+                Ok(response) => debug!(
+                    "GitHub API {} {} -> {} ({:?})",
+                    method,
+                    path,
+                    response.status(),
+                    started.elapsed()
+                ),
+                Err(e) => debug!(
+                    "GitHub API {} {} failed: {} ({:?})",
+                    method,
+                    path,
+                    e,
+                    started.elapsed()
+                ),
+            }
+            result
+        })
+    }
+}
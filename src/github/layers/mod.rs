@@ -0,0 +1,19 @@
+//! Tower layers applied to the Octocrab clients created by Octofer
+//!
+//! Unlike [`middlewares`](super::middlewares), which process *incoming* webhook
+//! requests, the layers in this module wrap *outgoing* GitHub API requests made
+//! through the clients handed out by [`GitHubClient`](super::GitHubClient).
+
+pub mod access;
+pub mod audit;
+pub mod budget;
+pub mod dry_run;
+pub mod etag;
+pub mod logging;
+
+pub use access::*;
+pub use audit::*;
+pub use budget::*;
+pub use dry_run::*;
+pub use etag::*;
+pub use logging::*;
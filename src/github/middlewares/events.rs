@@ -8,10 +8,13 @@ use axum::{
     response::Response,
 };
 use octocrab::models::webhook_events::WebhookEvent;
+use serde_json::Value;
 use std::sync::Arc;
 use tracing::debug;
 
-const GITHUB_EVENT_HEADER: &str = "X-GitHub-Event";
+use crate::SerdeToString;
+
+pub const GITHUB_EVENT_HEADER: &str = "X-GitHub-Event";
 
 /// Context containing GitHub event information
 pub struct GitHubEventContext {
@@ -41,8 +44,8 @@ pub async fn github_event_middleware(mut req: Request, next: Next) -> Result<Res
     let body = extract_request_body(&mut req).await?;
     let event = parse_webhook_event(&event_type, &body)?;
 
-    let installation_id = event.installation.as_ref().map(|i| i.id().0 as i64);
-    debug!("Extracted installation ID: {:?}", installation_id);
+    let payload = serde_json::from_slice::<Value>(&body).ok();
+    let installation_id = extract_installation_id(&event, payload.as_ref()).map(|id| id as i64);
 
     // Store event context in request extensions
     let context = GitHubEventContext {
@@ -58,6 +61,29 @@ pub async fn github_event_middleware(mut req: Request, next: Next) -> Result<Res
     Ok(next.run(req).await)
 }
 
+/// Get the ID of the installation a delivery was sent for
+///
+/// The typed `installation` field of the event is used if octocrab populated
+/// it; otherwise the ID is read from `payload["installation"]["id"]` of the
+/// raw payload, if given. Deliveries without a resolvable installation are
+/// logged at debug level, since handlers get no installation client for them.
+pub fn extract_installation_id(event: &WebhookEvent, payload: Option<&Value>) -> Option<u64> {
+    let installation_id = event
+        .installation
+        .as_ref()
+        .map(|installation| installation.id().0)
+        .or_else(|| payload?["installation"]["id"].as_u64());
+
+    match installation_id {
+        Some(id) => debug!("Extracted installation ID: {}", id),
+        None => debug!(
+            "{} event has no installation ID; handlers get no installation client",
+            event.kind.to_string()
+        ),
+    }
+    installation_id
+}
+
 /// Extract the GitHub event type from request headers
 fn extract_event_type(req: &Request) -> Result<String, StatusCode> {
     req.headers()
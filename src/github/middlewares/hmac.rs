@@ -1,6 +1,18 @@
 //! HMAC verification middleware for webhook security
+//!
+//! Verification can be disabled for local development, so deliveries can be
+//! sent with `curl` without computing signatures. Since anyone who can reach
+//! the server could then forge deliveries, [`HmacConfig::validate`] only
+//! accepts this on a loopback address; the same goes for the built-in
+//! development secret unless [`HmacConfig::allow_default_secret`] is set.
+//!
+//! Signatures are computed with the RustCrypto `hmac` and `sha2` crates, or
+//! with `ring` when the `ring` feature is enabled, see [`HmacBackend`]. Both
+//! compare signatures in constant time. `cargo bench --bench hmac` compares
+//! the backends on payloads of several sizes.
 
 use crate::config::{WEBHOOK_HEADER_NAME, WEBHOOK_SECRET};
+use crate::secrets::Secret;
 use anyhow::Context;
 use axum::{
     body::Body,
@@ -10,7 +22,10 @@ use axum::{
     response::Response,
 };
 use hmac::Mac;
-use tracing::debug;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, warn};
 
 type HmacSha256 = hmac::Hmac<sha2::Sha256>;
 
@@ -18,17 +33,24 @@ type HmacSha256 = hmac::Hmac<sha2::Sha256>;
 #[derive(Clone, Debug)]
 pub struct HmacConfig {
     /// Secret key for HMAC verification
-    pub secret: String,
+    ///
+    /// Redacted from `Debug` output, see [`Secret`].
+    pub secret: Secret,
     /// Header name containing the HMAC signature
     pub header_name: String,
+    /// Accept deliveries without verifying their signature
+    ///
+    /// Only valid when the server binds to a loopback address.
+    pub disabled: bool,
+    /// Accept the built-in development secret on a non-loopback address
+    pub allow_default_secret: bool,
+    /// Implementation verifying signatures
+    pub backend: HmacBackend,
 }
 
 impl Default for HmacConfig {
     fn default() -> Self {
-        Self {
-            secret: WEBHOOK_SECRET.to_string(),
-            header_name: WEBHOOK_HEADER_NAME.to_string(),
-        }
+        Self::new(WEBHOOK_SECRET.to_string(), WEBHOOK_HEADER_NAME.to_string())
     }
 }
 
@@ -36,18 +58,197 @@ impl HmacConfig {
     /// Create a new HMAC configuration
     pub fn new(secret: String, header_name: String) -> Self {
         Self {
-            secret,
+            secret: secret.into(),
             header_name,
+            disabled: false,
+            allow_default_secret: false,
+            backend: HmacBackend::default(),
+        }
+    }
+
+    /// Check that the configuration is safe for a server bound to `host`
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InsecureHmacConfig`] error if verification is disabled,
+    /// or the built-in development secret is used without
+    /// [`HmacConfig::allow_default_secret`], and `host` is not a loopback
+    /// address.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use octofer::github::middlewares::HmacConfig;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut config = HmacConfig::default();
+    /// config.disabled = true;
+    /// assert!(config.validate(Ipv4Addr::LOCALHOST).is_ok());
+    /// assert!(config.validate(Ipv4Addr::UNSPECIFIED).is_err());
+    /// ```
+    pub fn validate(&self, host: Ipv4Addr) -> Result<(), InsecureHmacConfig> {
+        if host.is_loopback() {
+            return Ok(());
+        }
+        if self.disabled {
+            return Err(InsecureHmacConfig::VerificationDisabled { host });
+        }
+        if self.secret.expose() == WEBHOOK_SECRET && !self.allow_default_secret {
+            return Err(InsecureHmacConfig::DefaultSecret { host });
+        }
+        Ok(())
+    }
+}
+
+/// Implementation computing and comparing HMAC-SHA256 signatures
+///
+/// Defaults to [`HmacBackend::Ring`] when the `ring` feature is enabled, and
+/// [`HmacBackend::RustCrypto`] otherwise. Both accept and reject the same
+/// signatures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HmacBackend {
+    /// The `hmac` and `sha2` crates
+    #[cfg_attr(not(feature = "ring"), default)]
+    RustCrypto,
+    /// `ring::hmac`, available with the `ring` feature
+    #[cfg(feature = "ring")]
+    #[cfg_attr(feature = "ring", default)]
+    Ring,
+}
+
+impl HmacBackend {
+    /// Backends available in this build
+    pub const AVAILABLE: &'static [HmacBackend] = &[
+        HmacBackend::RustCrypto,
+        #[cfg(feature = "ring")]
+        HmacBackend::Ring,
+    ];
+
+    /// Verify a `sha256=<hex>` signature of `payload` made with `secret`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signature is malformed or does not match.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use octofer::github::middlewares::HmacBackend;
+    /// use octofer::webhook::forward::sign_payload;
+    ///
+    /// let signature = sign_payload(b"Hello, World!", "secret");
+    /// for backend in HmacBackend::AVAILABLE {
+    ///     assert!(backend.verify(&signature, b"Hello, World!", "secret").is_ok());
+    ///     assert!(backend.verify(&signature, b"Hello, World?", "secret").is_err());
+    /// }
+    /// ```
+    pub fn verify(self, signature: &str, payload: &[u8], secret: &str) -> anyhow::Result<()> {
+        // GitHub signatures are in the format "sha256=<hex_signature>"
+        let signature_hex = signature
+            .strip_prefix("sha256=")
+            .context("Signature must start with 'sha256='")?;
+
+        let expected_signature =
+            hex::decode(signature_hex).context("Failed to decode hex signature")?;
+
+        let verified = match self {
+            Self::RustCrypto => {
+                let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+                    .map_err(|_| anyhow::anyhow!("Invalid secret key for HMAC"))?;
+                mac.update(payload);
+                mac.verify_slice(&expected_signature).is_ok()
+            }
+            #[cfg(feature = "ring")]
+            Self::Ring => {
+                let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+                ring::hmac::verify(&key, payload, &expected_signature).is_ok()
+            }
+        };
+
+        if verified {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("HMAC signature verification failed"))
         }
     }
 }
 
+/// Error returned when an HMAC configuration would let anyone forge deliveries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsecureHmacConfig {
+    /// Verification is disabled on a non-loopback address
+    VerificationDisabled {
+        /// Address the server binds to
+        host: Ipv4Addr,
+    },
+    /// The built-in development secret is used on a non-loopback address
+    DefaultSecret {
+        /// Address the server binds to
+        host: Ipv4Addr,
+    },
+}
+
+impl fmt::Display for InsecureHmacConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VerificationDisabled { host } => write!(
+                f,
+                "Refusing to disable webhook signature verification on non-loopback \
+                 address {}; bind to 127.0.0.1 or unset OCTOFER_DISABLE_HMAC",
+                host
+            ),
+            Self::DefaultSecret { host } => write!(
+                f,
+                "Refusing to use the default webhook secret on non-loopback address {}; \
+                 set GITHUB_WEBHOOK_SECRET, or OCTOFER_ALLOW_DEFAULT_SECRET=true to \
+                 accept it anyway",
+                host
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InsecureHmacConfig {}
+
+/// HMAC configuration shared between the webhook server and its router
+///
+/// The middleware reads the configuration on every request, so replacing it
+/// with [`SharedHmacConfig::set`] takes effect for the next delivery, e.g.
+/// when rotating the webhook secret without a restart.
+#[derive(Clone, Debug, Default)]
+pub struct SharedHmacConfig(Arc<RwLock<HmacConfig>>);
+
+impl SharedHmacConfig {
+    /// Create a shared configuration
+    pub fn new(config: HmacConfig) -> Self {
+        Self(Arc::new(RwLock::new(config)))
+    }
+
+    /// Get a copy of the current configuration
+    pub fn get(&self) -> HmacConfig {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replace the configuration
+    pub fn set(&self, config: HmacConfig) {
+        *self.0.write().unwrap() = config;
+    }
+}
+
 /// Middleware to verify HMAC signatures on incoming webhook requests
 pub async fn verify_hmac_middleware(
-    State(config): State<HmacConfig>,
+    State(config): State<SharedHmacConfig>,
     req: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
+    let config = config.get();
+    if config.disabled {
+        warn!(
+            "HMAC verification is DISABLED; accepting unsigned delivery to {}",
+            req.uri()
+        );
+        return Ok(next.run(req).await);
+    }
     let (parts, body) = req.into_parts();
 
     // Extract the HMAC signature from request headers
@@ -60,14 +261,18 @@ pub async fn verify_hmac_middleware(
             StatusCode::BAD_REQUEST
         })?;
 
-    // Read the request body
+    // Read the request body. It is verified in place and handed on as the
+    // same `Bytes`, without copying it
     let payload = axum::body::to_bytes(body, usize::MAX).await.map_err(|e| {
         tracing::error!("Failed to read request body: {}", e);
         StatusCode::BAD_REQUEST
     })?;
 
     // Verify the HMAC signature
-    match verify_hmac_sha256(signature, &payload, &config.secret) {
+    match config
+        .backend
+        .verify(signature, &payload, config.secret.expose())
+    {
         Ok(_) => {
             debug!("HMAC signature verified successfully");
             // Reconstruct the request with the original body
@@ -82,25 +287,13 @@ pub async fn verify_hmac_middleware(
     }
 }
 
-/// Verify HMAC-SHA256 signature
-fn verify_hmac_sha256(signature: &str, payload: &[u8], secret: &str) -> anyhow::Result<()> {
-    // GitHub signatures are in the format "sha256=<hex_signature>"
-    let signature_hex = signature
-        .strip_prefix("sha256=")
-        .context("Signature must start with 'sha256='")?;
-
-    let expected_signature =
-        hex::decode(signature_hex).context("Failed to decode hex signature")?;
-
-    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
-        .map_err(|_| anyhow::anyhow!("Invalid secret key for HMAC"))?;
-
-    mac.update(payload);
-
-    match mac.verify_slice(&expected_signature) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(anyhow::anyhow!("HMAC signature verification failed")),
-    }
+/// Verify HMAC-SHA256 signature with the default backend
+pub(crate) fn verify_hmac_sha256(
+    signature: &str,
+    payload: &[u8],
+    secret: &str,
+) -> anyhow::Result<()> {
+    HmacBackend::default().verify(signature, payload, secret)
 }
 
 #[cfg(test)]
@@ -142,4 +335,126 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("decode hex"));
     }
+
+    #[test]
+    fn test_backends_agree_on_test_vectors() {
+        let hi_there_key = "\x0b".repeat(20);
+        let vectors = [
+            // Example from GitHub's webhook documentation
+            (
+                "It's a Secret to Everybody",
+                &b"Hello, World!"[..],
+                "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+                true,
+            ),
+            // RFC 4231 test cases 1 and 2
+            (
+                hi_there_key.as_str(),
+                b"Hi There",
+                "sha256=b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+                true,
+            ),
+            (
+                "Jefe",
+                b"what do ya want for nothing?",
+                "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                true,
+            ),
+            (
+                "Jefe",
+                b"what do ya want for nothing?",
+                "sha256=5BDCC146BF60754E6A042426089575C75A003F089D2739839DEC58B964EC3843",
+                true,
+            ),
+            (
+                "test-secret",
+                b"",
+                "sha256=a41bc6d81d6413576ae0994995e0ad89a416ec97389515c3604f47722122eeeb",
+                true,
+            ),
+            // Wrong secret, tampered payload, flipped bit
+            (
+                "It's a secret to everybody",
+                b"Hello, World!",
+                "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+                false,
+            ),
+            (
+                "It's a Secret to Everybody",
+                b"Hello, World?",
+                "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+                false,
+            ),
+            (
+                "It's a Secret to Everybody",
+                b"Hello, World!",
+                "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e16",
+                false,
+            ),
+            // Truncated, empty and malformed signatures
+            (
+                "It's a Secret to Everybody",
+                b"Hello, World!",
+                "sha256=757107ea0eb2509fc211221cce984b8a",
+                false,
+            ),
+            (
+                "It's a Secret to Everybody",
+                b"Hello, World!",
+                "sha256=",
+                false,
+            ),
+            ("It's a Secret to Everybody", b"Hello, World!", "", false),
+            (
+                "It's a Secret to Everybody",
+                b"Hello, World!",
+                "sha1=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+                false,
+            ),
+        ];
+
+        for (secret, payload, signature, valid) in vectors {
+            for backend in HmacBackend::AVAILABLE {
+                assert_eq!(
+                    backend.verify(signature, payload, secret).is_ok(),
+                    valid,
+                    "{backend:?} on {signature:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_debug_redacts_secret() {
+        let config = HmacConfig::new("s3cr3t".to_string(), WEBHOOK_HEADER_NAME.to_string());
+        assert!(!format!("{:?}", config).contains("s3cr3t"));
+        assert!(!format!("{:?}", SharedHmacConfig::new(config)).contains("s3cr3t"));
+    }
+
+    #[test]
+    fn test_validate_per_host() {
+        let public = Ipv4Addr::new(0, 0, 0, 0);
+        let custom = HmacConfig::new("s3cr3t".to_string(), WEBHOOK_HEADER_NAME.to_string());
+        assert!(custom.validate(public).is_ok());
+
+        let mut disabled = custom.clone();
+        disabled.disabled = true;
+        assert!(disabled.validate(Ipv4Addr::LOCALHOST).is_ok());
+        assert_eq!(
+            disabled.validate(public),
+            Err(InsecureHmacConfig::VerificationDisabled { host: public })
+        );
+        // Allowing the default secret does not allow disabling verification
+        disabled.allow_default_secret = true;
+        assert!(disabled.validate(public).is_err());
+
+        let mut default = HmacConfig::default();
+        assert!(default.validate(Ipv4Addr::LOCALHOST).is_ok());
+        assert_eq!(
+            default.validate(public),
+            Err(InsecureHmacConfig::DefaultSecret { host: public })
+        );
+        default.allow_default_secret = true;
+        assert!(default.validate(public).is_ok());
+    }
 }
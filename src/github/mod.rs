@@ -7,7 +7,15 @@
 //!
 //! - [`GitHubAuth`] - GitHub App authentication configuration
 //! - [`GitHubClient`] - High-level GitHub API client with token management
+//! - [`Error`] - Typed errors returned by the client
+//! - [`batch`] - Iteration over all installations and repositories of the app
+//! - [`graphql`] - GitHub GraphQL API support
+//! - [`redelivery`] - Listing and redelivery of the app's failed webhook deliveries
+//! - [`search`] - Search API requests within the search rate limit
 //! - [`middlewares`] - Request/response middleware for security and event processing
+//! - [`layers`] - Tower layers applied to outgoing GitHub API requests
+//! - [`transport`] - Construction of Octocrab clients with Octofer's service stack
+//! - [`user_auth`] - User-to-server tokens from the OAuth web flow, to act on behalf of users
 //! - [`models`] - GitHub API data models (re-exported from octocrab)
 //!
 //! # Authentication Flow
@@ -66,10 +74,20 @@
 //! ```
 
 pub mod auth;
+pub mod batch;
 pub mod client;
+pub mod credentials;
+pub mod error;
+pub mod graphql;
+pub mod layers;
 pub mod middlewares;
 pub mod models;
+pub mod redelivery;
+pub mod search;
+pub mod transport;
+pub mod user_auth;
 
 pub use auth::*;
 pub use client::*;
+pub use error::{is_suspended, Error};
 pub use models::*;
@@ -13,6 +13,15 @@
 //! - `PullRequest` - Represents a GitHub pull request
 //! - `WebhookEvent` - Represents incoming webhook events
 //!
+//! It also defines typed models for webhook payload parts that octocrab only
+//! exposes as raw JSON, such as [`Discussion`], [`DiscussionComment`] and
+//! [`MergeGroup`], [`WorkflowJob`] and [`WorkflowRun`], and [`InstallationAccess`], which keeps the full permission
+//! map of an installation. [`SubIssuesEvent`] is the payload of `sub_issues`
+//! webhooks, which octocrab does not know yet. [`CommitStatus`] is the commit status of a `status`
+//! webhook, with its state typed like statuses returned by the API.
+//! [`PrRef`] identifies a pull request that check and workflow events only
+//! refer to by branch and commit.
+//!
 //! # Examples
 //!
 //! ```rust,no_run
@@ -39,3 +48,310 @@
 
 // Re-export commonly used octocrab models
 pub use octocrab::models::*;
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::helpers::permissions::Permission;
+
+/// A GitHub Discussion, as sent in `discussion` and `discussion_comment` webhooks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Discussion {
+    /// Database ID of the discussion
+    pub id: u64,
+    /// GraphQL node ID of the discussion
+    pub node_id: String,
+    /// Discussion number within the repository
+    pub number: u64,
+    /// Title of the discussion
+    pub title: String,
+    /// Body of the discussion
+    pub body: Option<String>,
+    /// Author of the discussion
+    pub user: Author,
+    /// Category the discussion belongs to
+    pub category: DiscussionCategory,
+    /// State of the discussion (e.g. `open`, `closed`, `locked`)
+    pub state: String,
+    /// Whether the discussion is locked
+    #[serde(default)]
+    pub locked: bool,
+    /// Number of comments on the discussion
+    #[serde(default)]
+    pub comments: u64,
+    /// Web URL of the discussion
+    pub html_url: String,
+    /// Web URL of the accepted answer, if any
+    pub answer_html_url: Option<String>,
+}
+
+/// The category of a GitHub Discussion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscussionCategory {
+    /// Database ID of the category
+    pub id: u64,
+    /// GraphQL node ID of the category
+    pub node_id: Option<String>,
+    /// Display name of the category
+    pub name: String,
+    /// URL-friendly name of the category
+    pub slug: String,
+    /// Emoji of the category (e.g. `:speech_balloon:`)
+    pub emoji: Option<String>,
+    /// Description of the category
+    pub description: Option<String>,
+    /// Whether discussions in this category can have an accepted answer
+    #[serde(default)]
+    pub is_answerable: bool,
+}
+
+/// A comment on a GitHub Discussion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscussionComment {
+    /// Database ID of the comment
+    pub id: u64,
+    /// GraphQL node ID of the comment
+    pub node_id: String,
+    /// Body of the comment
+    pub body: String,
+    /// Author of the comment
+    pub user: Author,
+    /// Database ID of the comment this one replies to, if it is a reply
+    pub parent_id: Option<u64>,
+    /// Web URL of the comment
+    pub html_url: String,
+}
+
+/// A merge queue group, as sent in `merge_group` webhooks
+///
+/// GitHub tests the group on a temporary branch whose head commit is
+/// `head_sha`; required checks must be reported against that commit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeGroup {
+    /// SHA of the merge group's head commit
+    pub head_sha: String,
+    /// Full ref of the temporary merge queue branch
+    /// (e.g. `refs/heads/gh-readonly-queue/main/pr-42-<sha>`)
+    pub head_ref: String,
+    /// SHA of the commit the group is based on
+    pub base_sha: String,
+    /// Full ref of the branch the group merges into (e.g. `refs/heads/main`)
+    pub base_ref: String,
+}
+
+impl MergeGroup {
+    /// Number of the pull request whose entry created the merge group
+    ///
+    /// Parsed from the `pr-<number>-<sha>` suffix of `head_ref`.
+    pub fn pull_request_number(&self) -> Option<u64> {
+        let name = self.head_ref.rsplit('/').next()?;
+        name.strip_prefix("pr-")?.split('-').next()?.parse().ok()
+    }
+}
+
+/// A GitHub Actions job, as sent in `workflow_job` webhooks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkflowJob {
+    /// ID of the job
+    pub id: u64,
+    /// ID of the workflow run the job belongs to
+    pub run_id: u64,
+    /// Name of the job
+    pub name: String,
+    /// Name of the workflow the job belongs to
+    pub workflow_name: Option<String>,
+    /// SHA of the commit the job runs on
+    pub head_sha: String,
+    /// Status of the job (`queued`, `in_progress`, `completed` or `waiting`)
+    pub status: String,
+    /// Conclusion of the job once completed (e.g. `success`, `failure`)
+    pub conclusion: Option<String>,
+    /// Labels a runner needs to pick up the job (e.g. `self-hosted`, `linux`)
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// ID of the runner the job was assigned to
+    pub runner_id: Option<u64>,
+    /// Name of the runner the job was assigned to
+    pub runner_name: Option<String>,
+    /// ID of the runner group of the runner
+    pub runner_group_id: Option<u64>,
+    /// Name of the runner group of the runner
+    pub runner_group_name: Option<String>,
+    /// Web URL of the job
+    pub html_url: Option<String>,
+}
+
+/// A GitHub Actions workflow run, as sent in `workflow_run` webhooks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    /// ID of the run
+    pub id: u64,
+    /// Name of the workflow
+    pub name: Option<String>,
+    /// ID of the workflow
+    pub workflow_id: u64,
+    /// Number of the run among the runs of its workflow
+    pub run_number: u64,
+    /// Attempt of the run, starting at 1 and increased by re-runs
+    #[serde(default = "first_attempt")]
+    pub run_attempt: u64,
+    /// Event that triggered the run (e.g. `push`, `pull_request`)
+    pub event: String,
+    /// Branch the run runs on
+    pub head_branch: Option<String>,
+    /// SHA of the commit the run runs on
+    pub head_sha: String,
+    /// Status of the run (e.g. `queued`, `in_progress`, `completed`)
+    pub status: Option<String>,
+    /// Conclusion of the run once completed (e.g. `success`, `failure`)
+    pub conclusion: Option<String>,
+    /// Pull requests the run belongs to, empty for runs of forks
+    #[serde(default)]
+    pub pull_requests: Vec<WorkflowRunPullRequest>,
+    /// Web URL of the run
+    pub html_url: Option<String>,
+}
+
+fn first_attempt() -> u64 {
+    1
+}
+
+/// A pull request a workflow run belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowRunPullRequest {
+    /// ID of the pull request
+    pub id: u64,
+    /// Number of the pull request
+    pub number: u64,
+}
+
+/// Action of a `sub_issues` webhook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubIssuesAction {
+    /// A sub-issue was added to the parent issue (sent for the parent)
+    SubIssueAdded,
+    /// A sub-issue was removed from the parent issue (sent for the parent)
+    SubIssueRemoved,
+    /// A parent issue was added to the sub-issue (sent for the sub-issue)
+    ParentIssueAdded,
+    /// A parent issue was removed from the sub-issue (sent for the sub-issue)
+    ParentIssueRemoved,
+}
+
+/// Payload of a `sub_issues` webhook
+///
+/// Linking or unlinking sends the event twice: with a `sub_issue_*` action
+/// about the parent, and with a `parent_issue_*` action about the sub-issue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubIssuesEvent {
+    /// What happened
+    pub action: SubIssuesAction,
+    /// Database ID of the parent issue
+    pub parent_issue_id: u64,
+    /// Parent issue
+    pub parent_issue: issues::Issue,
+    /// Repository of the parent issue, if sent
+    pub parent_issue_repo: Option<Repository>,
+    /// Database ID of the sub-issue
+    pub sub_issue_id: u64,
+    /// Sub-issue
+    pub sub_issue: issues::Issue,
+    /// Repository of the sub-issue, if sent
+    pub sub_issue_repo: Option<Repository>,
+}
+
+/// A commit status, as sent in `status` webhooks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitStatus {
+    /// SHA of the commit the status is set on
+    pub sha: String,
+    /// State of the status
+    pub state: StatusState,
+    /// Label that tells the status apart from others on the commit (e.g.
+    /// `ci/jenkins`)
+    pub context: String,
+    /// Short description of the status
+    pub description: Option<String>,
+    /// URL of the page the status links to
+    pub target_url: Option<String>,
+}
+
+/// A pull request an event is associated with
+///
+/// See [`Context::associated_pull_requests`](crate::Context::associated_pull_requests).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrRef {
+    /// Number of the pull request
+    pub number: u64,
+    /// Name of the pull request's head branch
+    pub head_ref: String,
+    /// SHA of the pull request's head commit
+    pub head_sha: String,
+    /// Name of the branch the pull request merges into
+    pub base_ref: String,
+}
+
+/// Which repositories of its account an installation can access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepositorySelection {
+    /// All repositories, including ones created later
+    All,
+    /// Only the repositories selected when installing the app; API calls for
+    /// other repositories fail with `404 Not Found`
+    Selected,
+}
+
+/// Repository selection and permissions granted to an installation
+///
+/// octocrab's [`Installation`] only knows a handful of permissions, so the
+/// permission map is parsed from the raw installation object instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallationAccess {
+    /// ID of the installation
+    pub id: u64,
+    /// Which repositories the installation can access
+    pub repository_selection: Option<RepositorySelection>,
+    /// Granted permissions by name (e.g. `checks`, `pull_requests`)
+    pub permissions: HashMap<String, Permission>,
+}
+
+impl InstallationAccess {
+    /// Parse a full installation object, as returned by `GET /app/installations/{id}`
+    /// and sent in `installation` webhooks
+    ///
+    /// Returns `None` for the minimal installation objects of other webhooks,
+    /// which only carry the installation ID.
+    pub fn from_json(installation: &Value) -> Option<Self> {
+        let id = installation["id"].as_u64()?;
+        let permissions = installation["permissions"]
+            .as_object()?
+            .iter()
+            .filter_map(|(name, level)| {
+                Some((name.clone(), Permission::from_name(level.as_str()?)?))
+            })
+            .collect();
+        let repository_selection =
+            serde_json::from_value(installation["repository_selection"].clone()).ok();
+
+        Some(Self {
+            id,
+            repository_selection,
+            permissions,
+        })
+    }
+
+    /// Level of the permission `name`, if granted
+    pub fn permission(&self, name: &str) -> Option<Permission> {
+        self.permissions.get(name).copied()
+    }
+
+    /// Whether the permission `name` is granted with at least `level`
+    pub fn has_permission(&self, name: &str, level: Permission) -> bool {
+        self.permission(name)
+            .is_some_and(|granted| granted >= level)
+    }
+}
@@ -0,0 +1,441 @@
+//! Recovery of missed webhook deliveries
+//!
+//! GitHub does not retry deliveries that failed, e.g. while the app was
+//! down, so their events are lost unless they are redelivered. The app's
+//! deliveries are listed by [`GitHubClient::list_hook_deliveries`] and
+//! redelivered one by one with [`GitHubClient::redeliver`], or all failed
+//! deliveries of a window at once with [`GitHubClient::redeliver_failed`].
+//!
+//! [`redeliver_failed`](GitHubClient::redeliver_failed) requests at most
+//! [`RedeliveryOptions::max_redeliveries`] redeliveries, oldest first and
+//! paced to [`RedeliveryOptions::per_minute`], so a long outage does not end
+//! with a burst of deliveries into the app's own endpoint. A delivery is
+//! only redelivered if none of its attempts succeeded, and once per run
+//! even if it failed several times. Redeliveries carry the
+//! `X-GitHub-Delivery` ID of the original delivery, so they are
+//! [sampled](crate::sampling) the same way, and handlers using
+//! [`Context::delivery_scoped_key`](crate::Context::delivery_scoped_key) see
+//! the same key as for an original delivery that failed halfway. A
+//! redelivery requested by another replica that has not been attempted yet
+//! is not listed, so reconciliation should run on a single replica.
+//!
+//! With `OCTOFER_REDELIVER_FAILED_SINCE` set, e.g. to `4h`, the webhook
+//! server redelivers the failed deliveries of the window in the background
+//! once it listens, see
+//! [`GitHubConfig::redelivery`](crate::config::GitHubConfig::redelivery).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::redelivery::RedeliveryOptions;
+//! use octofer::github::GitHubClient;
+//! use std::time::Duration;
+//!
+//! # async fn example(client: GitHubClient) -> anyhow::Result<()> {
+//! let options = RedeliveryOptions {
+//!     dry_run: true,
+//!     ..RedeliveryOptions::new(Duration::from_secs(4 * 3600))
+//! };
+//! let summary = client.redeliver_failed(&options).await?;
+//! for delivery in &summary.pending {
+//!     println!("Would redeliver {} delivery {}", delivery.event, delivery.guid);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use octocrab::Page;
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::github::error::Result;
+use crate::github::GitHubClient;
+
+/// Default maximum number of deliveries redelivered by a run
+pub const DEFAULT_MAX_REDELIVERIES: usize = 500;
+
+/// Default number of redeliveries requested per minute
+pub const DEFAULT_REDELIVERIES_PER_MINUTE: u32 = 60;
+
+/// An attempt to deliver a webhook event to the app
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HookDelivery {
+    /// ID of the attempt, to [redeliver](GitHubClient::redeliver) it
+    pub id: u64,
+    /// ID of the delivery (`X-GitHub-Delivery`), shared by its redeliveries
+    pub guid: String,
+    /// When the attempt was made
+    pub delivered_at: DateTime<Utc>,
+    /// Whether the attempt is a redelivery
+    #[serde(default)]
+    pub redelivery: bool,
+    /// Outcome of the attempt, e.g. `OK` or `Invalid HTTP Response: 503`
+    #[serde(default)]
+    pub status: String,
+    /// HTTP status returned by the app, `0` if it did not respond
+    #[serde(default)]
+    pub status_code: u16,
+    /// Event type
+    pub event: String,
+    /// Action of the event, if it has one
+    #[serde(default)]
+    pub action: Option<String>,
+    /// Installation the event belongs to
+    #[serde(default)]
+    pub installation_id: Option<u64>,
+}
+
+impl HookDelivery {
+    /// Whether the app acknowledged the attempt with a `2xx` status
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status_code)
+    }
+}
+
+/// Options of [`GitHubClient::redeliver_failed`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedeliveryOptions {
+    /// How far back to look for failed deliveries
+    pub since: Duration,
+    /// Maximum number of deliveries redelivered; older ones first
+    #[serde(default = "default_max_redeliveries")]
+    pub max_redeliveries: usize,
+    /// Redeliveries requested per minute
+    #[serde(default = "default_per_minute")]
+    pub per_minute: u32,
+    /// Log the deliveries that would be redelivered without redelivering them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn default_max_redeliveries() -> usize {
+    DEFAULT_MAX_REDELIVERIES
+}
+
+fn default_per_minute() -> u32 {
+    DEFAULT_REDELIVERIES_PER_MINUTE
+}
+
+impl RedeliveryOptions {
+    /// Redeliver the failed deliveries of the last `since`, with the default
+    /// bound and pace
+    pub fn new(since: Duration) -> Self {
+        Self {
+            since,
+            max_redeliveries: DEFAULT_MAX_REDELIVERIES,
+            per_minute: DEFAULT_REDELIVERIES_PER_MINUTE,
+            dry_run: false,
+        }
+    }
+
+    /// Time between two redeliveries
+    fn pace(&self) -> Duration {
+        Duration::from_secs(60) / self.per_minute.max(1)
+    }
+}
+
+/// Outcome of [`GitHubClient::redeliver_failed`]
+#[derive(Debug, Default)]
+pub struct RedeliverySummary {
+    /// Failed deliveries selected for redelivery, oldest first, one attempt
+    /// per delivery
+    pub pending: Vec<HookDelivery>,
+    /// Failed deliveries left out by
+    /// [`max_redeliveries`](RedeliveryOptions::max_redeliveries)
+    pub skipped: usize,
+    /// Number of redeliveries requested; `0` for dry runs
+    pub redelivered: usize,
+    /// Deliveries whose redelivery could not be requested, by GUID
+    pub failures: Vec<(String, String)>,
+}
+
+impl RedeliverySummary {
+    /// Whether every redelivery was requested
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl GitHubClient {
+    /// List the attempts to deliver webhook events to the app since `since`,
+    /// newest first
+    ///
+    /// Pages are followed until an attempt older than `since`. With
+    /// `only_failed`, only attempts the app did not acknowledge with a `2xx`
+    /// status are returned; the delivery may still have been redelivered
+    /// successfully since.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails.
+    pub async fn list_hook_deliveries(
+        &self,
+        since: DateTime<Utc>,
+        only_failed: bool,
+    ) -> Result<Vec<HookDelivery>> {
+        let client = self.app_client();
+        let mut deliveries = Vec::new();
+        let mut page: Page<HookDelivery> = client
+            .get("/app/hook/deliveries", Some(&[("per_page", 100)]))
+            .await?;
+        loop {
+            let items = page.take_items();
+            let done = items.iter().any(|delivery| delivery.delivered_at < since);
+            deliveries.extend(
+                items
+                    .into_iter()
+                    .filter(|delivery| delivery.delivered_at >= since)
+                    .filter(|delivery| !only_failed || !delivery.is_success()),
+            );
+            if done {
+                break;
+            }
+            match client.get_page(&page.next).await? {
+                Some(next) => page = next,
+                None => break,
+            }
+        }
+        Ok(deliveries)
+    }
+
+    /// Ask GitHub to redeliver the delivery attempt `delivery_id`
+    ///
+    /// `delivery_id` is the [`id`](HookDelivery::id) of an attempt, not the
+    /// delivery's GUID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if GitHub refuses the request.
+    pub async fn redeliver(&self, delivery_id: u64) -> Result<()> {
+        let route = format!("/app/hook/deliveries/{}/attempts", delivery_id);
+        let response = self.app_client()._post(route, None::<&()>).await?;
+        octocrab::map_github_error(response).await?;
+        Ok(())
+    }
+
+    /// Redeliver the deliveries of the last `options.since` that never
+    /// succeeded
+    ///
+    /// See the [module documentation](self) for the deliveries selected and
+    /// how redeliveries are paced. A redelivery that cannot be requested is
+    /// recorded in the summary and does not stop the others.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the deliveries cannot be listed.
+    pub async fn redeliver_failed(&self, options: &RedeliveryOptions) -> Result<RedeliverySummary> {
+        let since = chrono::Duration::from_std(options.since)
+            .ok()
+            .and_then(|window| Utc::now().checked_sub_signed(window))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let deliveries = self.list_hook_deliveries(since, false).await?;
+
+        let succeeded: HashSet<&str> = deliveries
+            .iter()
+            .filter(|delivery| delivery.is_success())
+            .map(|delivery| delivery.guid.as_str())
+            .collect();
+        let mut selected = HashSet::new();
+        let mut failed: Vec<HookDelivery> = deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| !succeeded.contains(delivery.guid.as_str()))
+            .filter(|delivery| selected.insert(delivery.guid.as_str()))
+            .cloned()
+            .collect();
+
+        let mut summary = RedeliverySummary {
+            skipped: failed.len().saturating_sub(options.max_redeliveries),
+            ..Default::default()
+        };
+        failed.truncate(options.max_redeliveries);
+        summary.pending = failed;
+        info!(
+            "Found {} failed webhook deliveries since {}, redelivering {}",
+            summary.pending.len() + summary.skipped,
+            since,
+            summary.pending.len()
+        );
+
+        if options.dry_run {
+            for delivery in &summary.pending {
+                info!(
+                    "[dry run] Would redeliver {} delivery {} of {}",
+                    delivery.event, delivery.guid, delivery.delivered_at
+                );
+            }
+            return Ok(summary);
+        }
+
+        let mut pace = tokio::time::interval(options.pace());
+        pace.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        for delivery in &summary.pending {
+            pace.tick().await;
+            match self.redeliver(delivery.id).await {
+                Ok(()) => summary.redelivered += 1,
+                Err(e) => {
+                    warn!("Failed to redeliver delivery {}: {}", delivery.guid, e);
+                    summary
+                        .failures
+                        .push((delivery.guid.clone(), e.to_string()));
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockGitHub;
+    use axum::extract::{Path, Query};
+    use axum::http::header::LINK;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    type Attempts = Arc<Mutex<Vec<(u64, Instant)>>>;
+
+    fn delivery(id: u64, guid: &str, minutes_ago: i64, status_code: u16) -> Value {
+        json!({
+            "id": id,
+            "guid": guid,
+            "delivered_at": Utc::now() - chrono::Duration::minutes(minutes_ago),
+            "redelivery": false,
+            "duration": 0.1,
+            "status": if status_code == 200 { "OK" } else { "Invalid HTTP Response: 503" },
+            "status_code": status_code,
+            "event": "issues",
+            "action": "opened",
+            "installation_id": 1,
+            "repository_id": 1,
+        })
+    }
+
+    /// Two pages of deliveries, newest first, and a redelivery endpoint
+    /// recording when each attempt was redelivered
+    fn routes(attempts: Attempts) -> Router {
+        Router::new()
+            .route(
+                "/app/hook/deliveries",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    let (deliveries, next) = match query.get("cursor").map(String::as_str) {
+                        None => (
+                            vec![
+                                delivery(6, "retried", 10, 200),
+                                delivery(5, "failed-twice", 20, 503),
+                                delivery(4, "ok", 30, 200),
+                            ],
+                            Some("page-2"),
+                        ),
+                        _ => (
+                            vec![
+                                delivery(3, "failed-twice", 40, 0),
+                                delivery(2, "retried", 50, 503),
+                                delivery(1, "failed", 60, 503),
+                                delivery(0, "too-old", 600, 503),
+                            ],
+                            None,
+                        ),
+                    };
+                    let mut response = Json(deliveries).into_response();
+                    if let Some(cursor) = next {
+                        let link = format!(
+                            "</app/hook/deliveries?per_page=100&cursor={cursor}>; rel=\"next\""
+                        );
+                        response.headers_mut().insert(LINK, link.parse().unwrap());
+                    }
+                    response
+                }),
+            )
+            .route(
+                "/app/hook/deliveries/{id}/attempts",
+                post(move |Path(id): Path<u64>| async move {
+                    attempts.lock().unwrap().push((id, Instant::now()));
+                    (StatusCode::ACCEPTED, Json(json!({})))
+                }),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_list_hook_deliveries_follows_cursors() {
+        let mock = MockGitHub::start(routes(Attempts::default())).await;
+        let client = mock.client();
+        let since = Utc::now() - chrono::Duration::hours(2);
+
+        let deliveries = client.list_hook_deliveries(since, false).await.unwrap();
+        let ids: Vec<u64> = deliveries.iter().map(|d| d.id).collect();
+        assert_eq!(ids, [6, 5, 4, 3, 2, 1]);
+
+        let failed = client.list_hook_deliveries(since, true).await.unwrap();
+        let ids: Vec<u64> = failed.iter().map(|d| d.id).collect();
+        assert_eq!(ids, [5, 3, 2, 1]);
+
+        // Pages older than the window are not requested
+        let recent = Utc::now() - chrono::Duration::minutes(25);
+        let deliveries = client.list_hook_deliveries(recent, false).await.unwrap();
+        assert_eq!(deliveries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_redeliveries_are_paced() {
+        let attempts = Attempts::default();
+        let mock = MockGitHub::start(routes(attempts.clone())).await;
+        let client = mock.client();
+        let options = RedeliveryOptions {
+            per_minute: 600,
+            ..RedeliveryOptions::new(Duration::from_secs(7200))
+        };
+
+        let summary = client.redeliver_failed(&options).await.unwrap();
+        assert!(summary.is_success());
+        assert_eq!(summary.redelivered, 2);
+        assert_eq!(summary.skipped, 0);
+
+        // Oldest first, once per delivery that never succeeded
+        let attempts = attempts.lock().unwrap().clone();
+        let ids: Vec<u64> = attempts.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [1, 3]);
+        let gap = attempts[1].1.duration_since(attempts[0].1);
+        assert!(
+            gap >= Duration::from_millis(90),
+            "redelivered {:?} apart",
+            gap
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redeliveries_are_bounded_and_dry_runs_only_list() {
+        let attempts = Attempts::default();
+        let mock = MockGitHub::start(routes(attempts.clone())).await;
+        let client = mock.client();
+        let mut options = RedeliveryOptions {
+            max_redeliveries: 1,
+            dry_run: true,
+            ..RedeliveryOptions::new(Duration::from_secs(7200))
+        };
+
+        let summary = client.redeliver_failed(&options).await.unwrap();
+        let guids: Vec<&str> = summary.pending.iter().map(|d| d.guid.as_str()).collect();
+        assert_eq!(guids, ["failed"]);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.redelivered, 0);
+        assert!(attempts.lock().unwrap().is_empty());
+
+        options.dry_run = false;
+        let summary = client.redeliver_failed(&options).await.unwrap();
+        assert_eq!(summary.redelivered, 1);
+        assert_eq!(attempts.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,433 @@
+//! Search API requests within the search rate limit
+//!
+//! The search API has rate limits of its own, much lower than the rest of
+//! the REST API: 30 requests per minute for issues and 10 for code, per
+//! installation. It also returns at most [`SEARCH_RESULT_CAP`] results per
+//! query, however many pages are requested. [`GitHubClient::search_issues`]
+//! and [`GitHubClient::search_code`] follow pages up to a maximum number of
+//! results within that cap, and track the quota of every installation from
+//! the `X-RateLimit-*` headers of its search responses, reported by
+//! [`GitHubClient::search_quota`].
+//!
+//! Once the quota is exhausted, searches fail with [`SearchBudgetExhausted`]
+//! and the time the quota resets, instead of GitHub's `403 Forbidden`, and
+//! send no request until then. Searches do not count against the core rate
+//! limit, nor the core rate limit against searches.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::search::SearchResource;
+//! use octofer::github::{Error, GitHubClient};
+//!
+//! # async fn example(client: GitHubClient, installation_id: u64) -> anyhow::Result<()> {
+//! match client
+//!     .search_issues(installation_id, "repo:octofer/app is:open label:bug", 200)
+//!     .await
+//! {
+//!     Ok(results) => println!("{} of {} bugs", results.items.len(), results.total_count),
+//!     Err(Error::SearchBudgetExhausted(e)) => println!("Search unavailable: {}", e),
+//!     Err(e) => return Err(e.into()),
+//! }
+//!
+//! if let Some(quota) = client.search_quota(installation_id, SearchResource::Issues) {
+//!     println!("{} searches left until {}", quota.remaining, quota.reset_at);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use http::{HeaderMap, StatusCode};
+use octocrab::models::issues::Issue;
+use octocrab::models::Code;
+use octocrab::Octocrab;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::github::error::{Error, Result};
+use crate::github::GitHubClient;
+
+/// Maximum number of results the search API returns for a query
+pub const SEARCH_RESULT_CAP: usize = 1000;
+
+/// Results requested per page
+const PER_PAGE: usize = 100;
+
+/// Search API with a rate limit of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchResource {
+    /// Issue and pull request search, and the other searches but code
+    Issues,
+    /// Code search
+    Code,
+}
+
+impl SearchResource {
+    fn route(self) -> &'static str {
+        match self {
+            Self::Issues => "/search/issues",
+            Self::Code => "/search/code",
+        }
+    }
+}
+
+/// Search quota of an installation, as last reported by GitHub
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchQuota {
+    /// Requests allowed per window
+    pub limit: u64,
+    /// Requests left in the current window
+    pub remaining: u64,
+    /// When the current window ends
+    pub reset_at: DateTime<Utc>,
+}
+
+impl SearchQuota {
+    /// Whether no request is left at `now`
+    pub fn is_exhausted(&self, now: DateTime<Utc>) -> bool {
+        self.remaining == 0 && self.reset_at > now
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<u64>().ok();
+        Some(Self {
+            limit: header("x-ratelimit-limit")?,
+            remaining: header("x-ratelimit-remaining")?,
+            reset_at: DateTime::from_timestamp(header("x-ratelimit-reset")? as i64, 0)?,
+        })
+    }
+}
+
+/// Error returned when the search quota of an installation is exhausted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchBudgetExhausted {
+    /// When the quota resets, if known
+    pub reset_at: Option<DateTime<Utc>>,
+}
+
+impl fmt::Display for SearchBudgetExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reset_at {
+            Some(reset_at) => write!(f, "Search API rate limit exhausted until {}", reset_at),
+            None => write!(f, "Search API rate limit exhausted"),
+        }
+    }
+}
+
+impl std::error::Error for SearchBudgetExhausted {}
+
+/// A page of search results, or the results of all pages fetched
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SearchResults<T> {
+    /// Number of results matching the query, which may exceed
+    /// [`SEARCH_RESULT_CAP`]
+    #[serde(default)]
+    pub total_count: u64,
+    /// Whether the search timed out before finding every match
+    #[serde(default)]
+    pub incomplete_results: bool,
+    /// Results, best match first
+    pub items: Vec<T>,
+}
+
+/// Search quotas by installation and resource
+#[derive(Debug, Default)]
+pub(crate) struct SearchQuotas {
+    quotas: Mutex<HashMap<(u64, SearchResource), SearchQuota>>,
+}
+
+impl SearchQuotas {
+    fn get(&self, installation_id: u64, resource: SearchResource) -> Option<SearchQuota> {
+        self.quotas
+            .lock()
+            .expect("search quotas lock poisoned")
+            .get(&(installation_id, resource))
+            .copied()
+    }
+
+    fn record(&self, installation_id: u64, resource: SearchResource, quota: SearchQuota) {
+        self.quotas
+            .lock()
+            .expect("search quotas lock poisoned")
+            .insert((installation_id, resource), quota);
+    }
+}
+
+impl GitHubClient {
+    /// Search the issues and pull requests visible to an installation
+    ///
+    /// Follows pages until `max_results` results, capped to
+    /// [`SEARCH_RESULT_CAP`], or the last result. See the
+    /// [module documentation](self) for the rate limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SearchBudgetExhausted`] if the installation's search
+    /// quota is exhausted, or another error if a request fails.
+    pub async fn search_issues(
+        &self,
+        installation_id: u64,
+        query: &str,
+        max_results: usize,
+    ) -> Result<SearchResults<Issue>> {
+        let client = self.installation_client(installation_id).await?;
+        search(
+            &client,
+            self.search_quotas(),
+            installation_id,
+            SearchResource::Issues,
+            query,
+            max_results,
+        )
+        .await
+    }
+
+    /// Search the code of the repositories visible to an installation
+    ///
+    /// Same as [`GitHubClient::search_issues`], within the lower rate limit
+    /// of code search.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SearchBudgetExhausted`] if the installation's code
+    /// search quota is exhausted, or another error if a request fails.
+    pub async fn search_code(
+        &self,
+        installation_id: u64,
+        query: &str,
+        max_results: usize,
+    ) -> Result<SearchResults<Code>> {
+        let client = self.installation_client(installation_id).await?;
+        search(
+            &client,
+            self.search_quotas(),
+            installation_id,
+            SearchResource::Code,
+            query,
+            max_results,
+        )
+        .await
+    }
+
+    /// Get the search quota of an installation, as reported by its last
+    /// search of `resource`
+    ///
+    /// Returns `None` before the installation's first search.
+    pub fn search_quota(
+        &self,
+        installation_id: u64,
+        resource: SearchResource,
+    ) -> Option<SearchQuota> {
+        self.search_quotas().get(installation_id, resource)
+    }
+}
+
+/// Search `resource` for `query` with an installation's `client`, following
+/// pages until `max_results` results
+pub(crate) async fn search<T: DeserializeOwned>(
+    client: &Octocrab,
+    quotas: &SearchQuotas,
+    installation_id: u64,
+    resource: SearchResource,
+    query: &str,
+    max_results: usize,
+) -> Result<SearchResults<T>> {
+    let max_results = max_results.min(SEARCH_RESULT_CAP);
+    let per_page = max_results.clamp(1, PER_PAGE);
+    let mut results = SearchResults {
+        total_count: 0,
+        incomplete_results: false,
+        items: Vec::new(),
+    };
+
+    let mut page = 1;
+    while results.items.len() < max_results {
+        if let Some(quota) = quotas.get(installation_id, resource) {
+            if quota.is_exhausted(Utc::now()) {
+                return Err(Error::SearchBudgetExhausted(SearchBudgetExhausted {
+                    reset_at: Some(quota.reset_at),
+                }));
+            }
+        }
+
+        let uri = format!(
+            "{}?{}",
+            resource.route(),
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("q", query)
+                .append_pair("per_page", &per_page.to_string())
+                .append_pair("page", &page.to_string())
+                .finish()
+        );
+        let response = client._get(uri).await?;
+        let quota = SearchQuota::from_headers(response.headers());
+        if let Some(quota) = quota {
+            quotas.record(installation_id, resource, quota);
+        }
+
+        let status = response.status();
+        if status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok()?.parse::<i64>().ok())
+                .and_then(|secs| Utc::now().checked_add_signed(chrono::Duration::seconds(secs)));
+            let error = match octocrab::map_github_error(response).await {
+                Err(octocrab::Error::GitHub { source, .. })
+                    if status == StatusCode::TOO_MANY_REQUESTS
+                        || quota.is_some_and(|quota| quota.remaining == 0)
+                        || source.message.to_lowercase().contains("rate limit") =>
+                {
+                    let reset_at = retry_after.or(quota.map(|quota| quota.reset_at));
+                    warn!(
+                        "Search API rate limit exhausted for installation {} (resets at {:?})",
+                        installation_id, reset_at
+                    );
+                    return Err(Error::SearchBudgetExhausted(SearchBudgetExhausted {
+                        reset_at,
+                    }));
+                }
+                Err(e) => e,
+                Ok(_) => unreachable!("client error statuses are mapped to errors"),
+            };
+            return Err(error.into());
+        }
+        let response = octocrab::map_github_error(response).await?;
+        let body = client.body_to_string(response).await?;
+        let found: SearchResults<T> = serde_json::from_str(&body)
+            .map_err(|e| Error::UnexpectedResponse(format!("Invalid search results: {}", e)))?;
+
+        let fetched = found.items.len();
+        results.total_count = found.total_count;
+        results.incomplete_results |= found.incomplete_results;
+        let wanted = max_results - results.items.len();
+        results.items.extend(found.items.into_iter().take(wanted));
+        if fetched < per_page
+            || page * per_page >= SEARCH_RESULT_CAP
+            || results.items.len() as u64 >= results.total_count
+        {
+            break;
+        }
+        page += 1;
+    }
+
+    debug!(
+        "Found {} of {} result(s) for search {:?}",
+        results.items.len(),
+        results.total_count,
+        query
+    );
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issue, MockGitHub, TEST_INSTALLATION_ID};
+    use axum::{extract::Query, response::IntoResponse, routing::get, Json, Router};
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct PageQuery {
+        per_page: usize,
+        page: usize,
+    }
+
+    fn quota_headers(remaining: u64) -> [(&'static str, String); 3] {
+        [
+            ("x-ratelimit-limit", "30".to_string()),
+            ("x-ratelimit-remaining", remaining.to_string()),
+            ("x-ratelimit-reset", "4102444800".to_string()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_search_issues_follows_pages_up_to_the_cap() {
+        // 2,500 matches, more than the search API returns
+        let mock = MockGitHub::start(Router::new().route(
+            "/search/issues",
+            get(|Query(query): Query<PageQuery>| async move {
+                let first = (query.page - 1) * query.per_page + 1;
+                let items: Vec<_> = (first..first + query.per_page)
+                    .map(|number| issue("octofer", "app", number as u64))
+                    .collect();
+                (
+                    quota_headers(30 - query.page as u64),
+                    Json(
+                        json!({ "total_count": 2500, "incomplete_results": false, "items": items }),
+                    ),
+                )
+            }),
+        ))
+        .await;
+        let client = mock.client();
+
+        let results = client
+            .search_issues(TEST_INSTALLATION_ID, "is:open", 250)
+            .await
+            .unwrap();
+        assert_eq!(results.total_count, 2500);
+        assert_eq!(results.items.len(), 250);
+        assert_eq!(results.items[249].number, 250);
+        let pages: Vec<_> = mock.requests().iter().map(|r| r.query.clone()).collect();
+        assert_eq!(pages.len(), 3);
+        assert!(pages[2].as_deref().unwrap().contains("page=3"));
+
+        let results = client
+            .search_issues(TEST_INSTALLATION_ID, "is:open", 5000)
+            .await
+            .unwrap();
+        assert_eq!(results.items.len(), SEARCH_RESULT_CAP);
+        assert_eq!(mock.requests().len(), 3 + 10);
+
+        let quota = client
+            .search_quota(TEST_INSTALLATION_ID, SearchResource::Issues)
+            .unwrap();
+        assert_eq!((quota.limit, quota.remaining), (30, 20));
+        assert_eq!(
+            client.search_quota(TEST_INSTALLATION_ID, SearchResource::Code),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_search_budget_is_typed() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/search/code",
+            get(|| async {
+                (
+                    StatusCode::FORBIDDEN,
+                    quota_headers(0),
+                    Json(json!({ "message": "API rate limit exceeded for installation ID 1." })),
+                )
+                    .into_response()
+            }),
+        ))
+        .await;
+        let client = mock.client();
+        let reset_at = DateTime::from_timestamp(4_102_444_800, 0);
+
+        let err = client
+            .search_code(TEST_INSTALLATION_ID, "fn main", 10)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SearchBudgetExhausted(SearchBudgetExhausted { reset_at: at }) if at == reset_at
+        ));
+
+        // No request is sent until the quota resets
+        let err = client
+            .search_code(TEST_INSTALLATION_ID, "fn main", 10)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::SearchBudgetExhausted(_)));
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
@@ -0,0 +1,672 @@
+//! Construction of Octocrab clients with Octofer's service stack
+//!
+//! Octocrab's default builder does not allow adding custom tower layers, so
+//! Octofer assembles the service stack itself. All clients created by a
+//! [`Transport`] share a single underlying HTTP connection pool, which keeps
+//! creating per-delivery clients cheap.
+//!
+//! The stack (outermost first) is:
+//!
+//! 1. Base URI
+//! 2. Extra headers (User-Agent)
+//! 3. Octofer layers ([`RequestLogLayer`], [`BudgetLayer`], [`DryRunLayer`],
+//!    [`AuditLayer`], [`AccessHintLayer`], [`EtagLayer`])
+//! 4. Redirect following (credentials are dropped on cross-origin redirects)
+//! 5. Retries on server errors and rate limiting
+//! 6. The hyper HTTP client, connecting directly or through the configured
+//!    proxy (see [`Transport::with_proxy`]), or the service stack of an
+//!    injected Octocrab client (see [`Transport::with_octocrab`])
+//!
+//! Release asset uploads stream their bodies to a separate host, which
+//! octocrab cannot do, so they use an [`UploadClient`] with a stack of its
+//! own, see [`Transport::upload_client`].
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, USER_AGENT};
+use http::{HeaderName, HeaderValue, Request, Response, Uri};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::{proxy::Tunnel, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::proxy::matcher::Matcher;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use octocrab::{
+    auth::AppAuth,
+    service::middleware::{base_uri::BaseUriLayer, extra_headers::ExtraHeadersLayer},
+    AuthState, Octocrab, OctocrabBuilder,
+};
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tokio::net::TcpStream;
+use tower::retry::{Policy, RetryLayer};
+use tower::util::BoxCloneSyncService;
+use tower::{Service, ServiceBuilder, ServiceExt};
+use tower_http::follow_redirect::FollowRedirectLayer;
+
+use crate::config::ProxyConfig;
+use crate::github::layers::dry_run::synthesized_response;
+use crate::github::layers::{
+    AccessHintLayer, AccessHints, ApiBudget, AuditLayer, AuditTrail, BudgetLayer, CacheScope,
+    DryRun, DryRunLayer, EtagLayer, RequestLogLayer, ResponseCache,
+};
+
+/// Default base URI of the GitHub REST API
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// Number of retries performed on server errors and rate limiting, unless
+/// configured otherwise
+pub const DEFAULT_RETRIES: usize = 20;
+
+/// User-Agent sent with every request unless configured otherwise
+pub const DEFAULT_USER_AGENT: &str = concat!("octofer/", env!("CARGO_PKG_VERSION"));
+
+type Connector = HttpsConnector<ProxyConnector>;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Proxy used by a [`ProxyConnector`]
+struct Proxy {
+    /// Which destinations go through which proxy
+    matcher: Matcher,
+    /// `Proxy-Authorization` header from explicitly configured credentials
+    auth: Option<HeaderValue>,
+}
+
+/// TCP connector that tunnels connections through a proxy when one matches
+/// the destination
+///
+/// The TLS connector wraps this one, so TLS to GitHub is negotiated inside
+/// the tunnel.
+#[derive(Clone)]
+struct ProxyConnector {
+    http: HttpConnector,
+    proxy: Option<Arc<Proxy>>,
+}
+
+impl ProxyConnector {
+    fn new(proxy: Option<Proxy>) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        Self {
+            http,
+            proxy: proxy.map(Arc::new),
+        }
+    }
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let intercept = self
+            .proxy
+            .as_ref()
+            .and_then(|proxy| Some((proxy, proxy.matcher.intercept(&dst)?)));
+        let Some((proxy, intercept)) = intercept else {
+            let connecting = self.http.call(dst);
+            return Box::pin(async move { connecting.await.map_err(Into::into) });
+        };
+
+        let mut tunnel = Tunnel::new(intercept.uri().clone(), self.http.clone());
+        if let Some(auth) = proxy.auth.as_ref().or(intercept.basic_auth()) {
+            tunnel = tunnel.with_auth(auth.clone());
+        }
+        let connecting = tunnel.call(dst);
+        Box::pin(async move { connecting.await.map_err(Into::into) })
+    }
+}
+
+/// Options of the clients built by a [`Transport`]
+///
+/// See [`Transport::with_options`] and
+/// [`GitHubClient::from_octocrab`](crate::github::GitHubClient::from_octocrab).
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Base URI of API requests
+    pub base_uri: Uri,
+    /// User-Agent sent with every request
+    pub user_agent: String,
+    /// Retries of requests failing with a server error, rate limiting or a
+    /// connection error
+    pub retries: usize,
+    /// Whether requests are logged at debug level
+    pub log_requests: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            base_uri: Uri::from_static(GITHUB_API_URL),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            retries: DEFAULT_RETRIES,
+            log_requests: false,
+        }
+    }
+}
+
+/// Retry policy retrying server errors, rate limiting and connection errors
+/// up to a number of times
+#[derive(Clone)]
+struct Retries(usize);
+
+impl<ReqBody, ResBody, E> Policy<Request<ReqBody>, Response<ResBody>, E> for Retries
+where
+    ReqBody: Clone,
+{
+    type Future = std::future::Ready<()>;
+
+    fn retry(
+        &mut self,
+        _request: &mut Request<ReqBody>,
+        result: &mut Result<Response<ResBody>, E>,
+    ) -> Option<Self::Future> {
+        let retryable = match result {
+            Ok(response) => {
+                response.status().is_server_error()
+                    || response.status() == http::StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(_) => true,
+        };
+        if !retryable || self.0 == 0 {
+            return None;
+        }
+        self.0 -= 1;
+        Some(std::future::ready(()))
+    }
+
+    fn clone_request(&mut self, request: &Request<ReqBody>) -> Option<Request<ReqBody>> {
+        if self.0 == 0 {
+            return None;
+        }
+        let mut clone = Request::new(request.body().clone());
+        *clone.method_mut() = request.method().clone();
+        *clone.uri_mut() = request.uri().clone();
+        *clone.version_mut() = request.version();
+        *clone.headers_mut() = request.headers().clone();
+        Some(clone)
+    }
+}
+
+/// Body of an upload sent with an [`UploadClient`], or of its response
+pub type UploadBody = BoxBody<Bytes, std::io::Error>;
+
+/// Client streaming request bodies to GitHub's upload host
+///
+/// Octocrab cannot send streamed request bodies, so release assets are
+/// uploaded with this client, built by [`Transport::upload_client`]. Requests
+/// go through the request log, budget and audit layers, or are intercepted
+/// in [dry-run mode](crate::github::layers::dry_run), but are neither
+/// retried nor redirected, since a streamed body can only be sent once.
+#[derive(Clone)]
+pub struct UploadClient {
+    service: BoxCloneSyncService<Request<UploadBody>, Response<UploadBody>, BoxError>,
+    authorization: HeaderValue,
+}
+
+impl std::fmt::Debug for UploadClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UploadClient").finish_non_exhaustive()
+    }
+}
+
+impl UploadClient {
+    /// Send `request`, authenticated with the client's token
+    ///
+    /// The request's URI is relative to the upload origin.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request cannot be sent, i.e. not for
+    /// unsuccessful responses.
+    pub async fn send(&self, mut request: Request<UploadBody>) -> Result<Response<UploadBody>> {
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, self.authorization.clone());
+        let path = request.uri().path().to_string();
+        self.service
+            .clone()
+            .oneshot(request)
+            .await
+            .map_err(|e| anyhow!("Failed to upload to {}: {}", path, e))
+    }
+}
+
+/// Factory for Octocrab clients sharing one HTTP connection pool
+#[derive(Clone)]
+pub struct Transport {
+    /// TLS-capable connector used to build the shared HTTP client
+    connector: Connector,
+    /// Shared hyper client, created on first use
+    ///
+    /// Octocrab's request body type is not nameable outside of octocrab, so the
+    /// client is stored type-erased and recovered in [`Transport::http`] where
+    /// the body type is inferred from the Octocrab builder bounds.
+    http: Arc<OnceLock<Box<dyn Any + Send + Sync>>>,
+    /// Base URI for API requests
+    base_uri: Uri,
+    /// User-Agent sent with every request
+    user_agent: HeaderValue,
+    /// Whether requests are logged at debug level
+    log_requests: bool,
+    /// Retries of failed requests
+    retries: usize,
+    /// Client whose service stack sends the requests instead of the shared
+    /// HTTP client, if one was injected
+    octocrab: Option<Octocrab>,
+    /// Cache of `ETag`s and responses shared by the clients, if enabled
+    response_cache: Option<Arc<ResponseCache>>,
+    /// Repositories accessible to installations, explaining their `404`s
+    access_hints: Arc<AccessHints>,
+    /// HTTP client of [`UploadClient`]s, created on first use
+    ///
+    /// Uploads stream their bodies, which octocrab's body type cannot, and go
+    /// to a different host, so they use a pool of their own.
+    upload_http: Arc<OnceLock<Client<Connector, UploadBody>>>,
+}
+
+impl std::fmt::Debug for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transport")
+            .field("base_uri", &self.base_uri)
+            .field("user_agent", &self.user_agent)
+            .field("log_requests", &self.log_requests)
+            .field("retries", &self.retries)
+            .field("octocrab", &self.octocrab.is_some())
+            .field("response_cache", &self.response_cache.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transport {
+    /// Create a new transport targeting the public GitHub API
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the native TLS root certificates cannot be loaded.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            connector: connector(None)?,
+            http: Arc::new(OnceLock::new()),
+            base_uri: Uri::from_static(GITHUB_API_URL),
+            user_agent: HeaderValue::from_static(DEFAULT_USER_AGENT),
+            log_requests: false,
+            retries: DEFAULT_RETRIES,
+            octocrab: None,
+            response_cache: None,
+            access_hints: Arc::new(AccessHints::new()),
+            upload_http: Arc::new(OnceLock::new()),
+        })
+    }
+
+    /// Use a different base URI for API requests
+    ///
+    /// This is needed for GitHub Enterprise Server (`https://HOST/api/v3`) or
+    /// when talking to a mock server in tests.
+    pub fn with_base_uri(mut self, base_uri: Uri) -> Self {
+        self.base_uri = base_uri;
+        self
+    }
+
+    /// Get the base URI of requests
+    pub fn base_uri(&self) -> &Uri {
+        &self.base_uri
+    }
+
+    /// Use a custom User-Agent for all requests
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `user_agent` is not a valid header value.
+    pub fn with_user_agent(mut self, user_agent: &str) -> Result<Self> {
+        self.user_agent = HeaderValue::from_str(user_agent)
+            .map_err(|e| anyhow!("Invalid User-Agent '{}': {}", user_agent, e))?;
+        Ok(self)
+    }
+
+    /// Apply the base URI, User-Agent, retries and request logging of
+    /// `options`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the User-Agent is not a valid header value.
+    pub fn with_options(self, options: &ClientOptions) -> Result<Self> {
+        Ok(self
+            .with_base_uri(options.base_uri.clone())
+            .with_user_agent(&options.user_agent)?
+            .with_retries(options.retries)
+            .with_request_logging(options.log_requests))
+    }
+
+    /// Retry requests failing with a server error, rate limiting or a
+    /// connection error up to `retries` times, instead of [`DEFAULT_RETRIES`]
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Send requests through the service stack of `client` instead of the
+    /// shared HTTP client
+    ///
+    /// Octofer's layers, the base URI, User-Agent and retries of the
+    /// transport still apply, while the middleware of `client`, e.g. a
+    /// connector presenting a client certificate, sends the requests. The
+    /// authentication of `client` is not used. Uploads keep using the
+    /// transport's own connections.
+    pub fn with_octocrab(mut self, client: Octocrab) -> Self {
+        self.octocrab = Some(client);
+        self
+    }
+
+    /// Send requests through the proxy described by `config`
+    ///
+    /// Connections to hosts matching `no_proxy` are made directly, as are all
+    /// connections when no proxy is configured. Clients built before this
+    /// call keep their connections.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the proxy URL is invalid or not an `http://` URL,
+    /// the credentials are not valid header characters, or the native TLS
+    /// root certificates cannot be loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use octofer::config::ProxyConfig;
+    /// use octofer::github::transport::Transport;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let transport = Transport::new()?.with_proxy(&ProxyConfig {
+    ///     https_proxy: Some("http://proxy.internal:3128".to_string()),
+    ///     no_proxy: Some("github.internal".to_string()),
+    ///     ..Default::default()
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_proxy(mut self, config: &ProxyConfig) -> Result<Self> {
+        let Some(url) = &config.https_proxy else {
+            return Ok(self);
+        };
+
+        let matcher = Matcher::builder()
+            .all(url.as_str())
+            .no(config.no_proxy.as_deref().unwrap_or_default())
+            .build();
+        let proxy_uri = Matcher::builder()
+            .all(url.as_str())
+            .build()
+            .intercept(&Uri::from_static(GITHUB_API_URL))
+            .map(|intercept| intercept.uri().clone())
+            .ok_or_else(|| anyhow!("Invalid proxy URL '{}'", url))?;
+        if proxy_uri.scheme_str() != Some("http") {
+            return Err(anyhow!(
+                "Unsupported proxy URL '{}': only http:// proxies are supported",
+                url
+            ));
+        }
+
+        let auth = match &config.username {
+            Some(username) => {
+                let credentials = format!(
+                    "{}:{}",
+                    username,
+                    config.password.as_deref().unwrap_or_default()
+                );
+                let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+                let mut value = HeaderValue::from_str(&format!("Basic {}", encoded))
+                    .map_err(|e| anyhow!("Invalid proxy credentials: {}", e))?;
+                value.set_sensitive(true);
+                Some(value)
+            }
+            None => None,
+        };
+
+        self.connector = connector(Some(Proxy { matcher, auth }))?;
+        self.http = Arc::new(OnceLock::new());
+        self.upload_http = Arc::new(OnceLock::new());
+        Ok(self)
+    }
+
+    /// Enable or disable debug logging of every request
+    pub fn with_request_logging(mut self, enabled: bool) -> Self {
+        self.log_requests = enabled;
+        self
+    }
+
+    /// Send conditional requests for responses cached in `cache`
+    ///
+    /// See the [`etag`](crate::github::layers::etag) module. Only clients
+    /// built with [`Transport::app_client`] and
+    /// [`Transport::installation_client`] use the cache.
+    pub fn with_response_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
+    /// Get the response cache of the clients, if enabled
+    pub fn response_cache(&self) -> Option<&Arc<ResponseCache>> {
+        self.response_cache.as_ref()
+    }
+
+    /// Get the cache of the repositories accessible to installations, see the
+    /// [`access`](crate::github::layers::access) module
+    pub fn access_hints(&self) -> &Arc<AccessHints> {
+        &self.access_hints
+    }
+
+    /// Build a client authenticated as the GitHub App (JWT)
+    pub fn app_client(&self, app_id: u64, key: jsonwebtoken::EncodingKey) -> Result<Octocrab> {
+        let auth = AuthState::App(AppAuth {
+            app_id: app_id.into(),
+            key,
+        });
+        self.build(auth, None, None, None, Some(CacheScope::App))
+    }
+
+    /// Build a client sending requests without credentials
+    ///
+    /// Used for endpoints authenticated by their parameters, such as the
+    /// OAuth token exchange.
+    pub fn anonymous_client(&self) -> Result<Octocrab> {
+        self.build(AuthState::None, None, None, None, None)
+    }
+
+    /// Build a client authenticated with an installation or user access token
+    ///
+    /// An optional [`ApiBudget`] caps the number of requests the client may send.
+    /// Responses are not cached, since the token's owner is not known.
+    pub fn token_client(&self, token: &str, budget: Option<Arc<ApiBudget>>) -> Result<Octocrab> {
+        let auth = AuthState::AccessToken {
+            token: token.to_string().into(),
+        };
+        self.build(auth, budget, None, None, None)
+    }
+
+    /// Build a client authenticated with an access token of installation
+    /// `installation_id`
+    ///
+    /// Like [`Transport::token_client`], but responses are cached for the
+    /// installation if a [response cache](Transport::with_response_cache) is set,
+    /// mutating requests are recorded in `audit` if given, or intercepted by
+    /// `dry_run`, and `404` and `403` responses carry
+    /// [access hints](crate::github::layers::access).
+    pub fn installation_client(
+        &self,
+        installation_id: u64,
+        token: &str,
+        budget: Option<Arc<ApiBudget>>,
+        audit: Option<Arc<AuditTrail>>,
+        dry_run: Option<Arc<DryRun>>,
+    ) -> Result<Octocrab> {
+        let auth = AuthState::AccessToken {
+            token: token.to_string().into(),
+        };
+        self.build(
+            auth,
+            budget,
+            audit,
+            dry_run,
+            Some(CacheScope::Installation(installation_id)),
+        )
+    }
+
+    /// Build a client streaming uploads to `origin`, authenticated with an
+    /// access token
+    ///
+    /// GitHub serves uploads from a separate host, `uploads.github.com` or
+    /// `/api/uploads` of a GitHub Enterprise Server, so `origin` replaces the
+    /// base URI and requests use the full path of the upload URL. See
+    /// [`UploadClient`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `token` is not a valid header value.
+    pub fn upload_client(
+        &self,
+        origin: Uri,
+        token: &str,
+        budget: Option<Arc<ApiBudget>>,
+        audit: Option<Arc<AuditTrail>>,
+        dry_run: Option<Arc<DryRun>>,
+    ) -> Result<UploadClient> {
+        let mut authorization = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| anyhow!("Invalid access token: {}", e))?;
+        authorization.set_sensitive(true);
+        let headers: Vec<(HeaderName, HeaderValue)> = vec![(USER_AGENT, self.user_agent.clone())];
+
+        let http = self
+            .upload_http
+            .get_or_init(|| Client::builder(TokioExecutor::new()).build(self.connector.clone()))
+            .clone()
+            .map_response(|response| {
+                response.map(|body| body.map_err(std::io::Error::other).boxed())
+            });
+        // Intercepted uploads never reach GitHub, so they are not audited
+        let audit = audit.filter(|_| dry_run.is_none());
+        let sender = match dry_run {
+            Some(dry_run) => {
+                BoxCloneSyncService::new(tower::service_fn(move |request: Request<UploadBody>| {
+                    let (status, body) =
+                        dry_run.intercept(request.method(), request.uri().path(), None);
+                    async move { Ok::<_, BoxError>(synthesized_response(status, body)) }
+                }))
+            }
+            None => BoxCloneSyncService::new(http.map_err(BoxError::from)),
+        };
+        let service = ServiceBuilder::new()
+            .layer(BaseUriLayer::new(origin))
+            .layer(ExtraHeadersLayer::new(Arc::new(headers)))
+            .layer(RequestLogLayer::new(self.log_requests))
+            .layer(BudgetLayer::new(budget))
+            .layer(AuditLayer::new(audit))
+            .service(sender);
+
+        Ok(UploadClient {
+            service: BoxCloneSyncService::new(service),
+            authorization,
+        })
+    }
+
+    /// Get the shared HTTP client for the request body type `B`
+    fn http<B>(&self) -> Client<Connector, B>
+    where
+        B: http_body::Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.http
+            .get_or_init(|| {
+                Box::new(
+                    Client::builder(TokioExecutor::new()).build::<_, B>(self.connector.clone()),
+                )
+            })
+            .downcast_ref::<Client<Connector, B>>()
+            .expect("transport is only used with a single request body type")
+            .clone()
+    }
+
+    fn build(
+        &self,
+        auth: AuthState,
+        budget: Option<Arc<ApiBudget>>,
+        audit: Option<Arc<AuditTrail>>,
+        dry_run: Option<Arc<DryRun>>,
+        scope: Option<CacheScope>,
+    ) -> Result<Octocrab> {
+        let headers: Vec<(HeaderName, HeaderValue)> = vec![(USER_AGENT, self.user_agent.clone())];
+        let installation_id = match scope {
+            Some(CacheScope::Installation(installation_id)) => Some(installation_id),
+            _ => None,
+        };
+
+        let sender = match &self.octocrab {
+            Some(client) => {
+                let client = client.clone();
+                BoxCloneSyncService::new(tower::service_fn(move |request| {
+                    let client = client.clone();
+                    async move {
+                        client
+                            .send(request)
+                            .await
+                            .map(|response| {
+                                response.map(|body| body.map_err(BoxError::from).boxed())
+                            })
+                            .map_err(BoxError::from)
+                    }
+                }))
+            }
+            None => BoxCloneSyncService::new(
+                self.http()
+                    .map_response(|response| {
+                        response.map(|body| body.map_err(BoxError::from).boxed())
+                    })
+                    .map_err(BoxError::from),
+            ),
+        };
+        let service = ServiceBuilder::new()
+            .layer(BaseUriLayer::new(self.base_uri.clone()))
+            .layer(ExtraHeadersLayer::new(Arc::new(headers)))
+            .layer(RequestLogLayer::new(self.log_requests))
+            .layer(BudgetLayer::new(budget))
+            .layer(DryRunLayer::new(dry_run, self.base_uri.clone()))
+            .layer(AuditLayer::new(audit))
+            .layer(AccessHintLayer::new(
+                Some(self.access_hints.clone()),
+                installation_id,
+                self.base_uri.clone(),
+            ))
+            .layer(EtagLayer::new(self.response_cache.clone(), scope))
+            .layer(FollowRedirectLayer::new())
+            .layer(RetryLayer::new(Retries(self.retries)))
+            .service(sender);
+
+        OctocrabBuilder::new_empty()
+            .with_service(service)
+            .with_auth(auth)
+            .build()
+            .map_err(|e| anyhow!("Failed to build GitHub client: {}", e))
+    }
+}
+
+/// Build the TLS-capable connector, tunneling through `proxy` if given
+fn connector(proxy: Option<Proxy>) -> Result<Connector> {
+    Ok(HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .map_err(|e| anyhow!("Failed to load native TLS roots: {}", e))?
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(ProxyConnector::new(proxy)))
+}
@@ -0,0 +1,475 @@
+//! User-to-server authentication with the OAuth web flow
+//!
+//! Installation tokens act as the app. To act on behalf of a user, e.g. to
+//! create a gist or comment with their identity, the app needs a
+//! user-to-server token from the [web application flow]:
+//!
+//! 1. Redirect the user to [`UserAuth::authorize_url`], with an unguessable
+//!    `state` (see [`generate_state`]) remembered for the user's session.
+//! 2. GitHub redirects back to the app's callback URL with a `code` and the
+//!    `state`; check the state, then trade the code for a [`UserToken`] with
+//!    [`UserAuth::exchange_code`]. The callback endpoint can be served with
+//!    [custom routes](crate::webhook::routes).
+//! 3. Create a client acting as the user with
+//!    [`GitHubClient::user_client`].
+//!
+//! Tokens of apps with [expiring user tokens] enabled expire after 8 hours;
+//! [`UserAuth::refresh`] trades their refresh token for a new token.
+//!
+//! Storing tokens is up to the app: implement [`UserTokenStore`] to keep
+//! them in a database, or use [`InMemoryUserTokenStore`].
+//! [`UserAuth::valid_token`] loads a user's token from a store and refreshes
+//! it when it is about to expire.
+//!
+//! [web application flow]: https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/generating-a-user-access-token-for-a-github-app
+//! [expiring user tokens]: https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/refreshing-user-access-tokens
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::config::OAuthConfig;
+//! use octofer::github::user_auth::{generate_state, UserAuth};
+//! use octofer::github::GitHubClient;
+//!
+//! # async fn example(client: GitHubClient, config: OAuthConfig, code: &str) -> anyhow::Result<()> {
+//! let auth = UserAuth::new(config)?;
+//!
+//! // Redirect the user to GitHub
+//! let state = generate_state();
+//! let url = auth.authorize_url(&state);
+//!
+//! // In the callback, once the state is checked
+//! let token = auth.exchange_code(code).await?;
+//! let user = client.user_client(&token)?.current().user().await?;
+//! println!("Authorized by {}", user.login);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+use http::header::ACCEPT;
+use http::Uri;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::debug;
+
+use crate::config::{OAuthConfig, ProxyConfig};
+use crate::github::error::{Error, Result};
+use crate::github::transport::Transport;
+use crate::secrets::Secret;
+
+/// URL of github.com, which serves the OAuth endpoints
+pub const GITHUB_WEB_URL: &str = "https://github.com";
+
+/// Path of the endpoint codes and refresh tokens are exchanged at
+const ACCESS_TOKEN_PATH: &str = "/login/oauth/access_token";
+
+/// Time before its expiry from which a token is refreshed
+const EXPIRY_BUFFER: Duration = Duration::minutes(5);
+
+/// Access token acting on behalf of a user
+///
+/// The tokens are redacted from `Debug` output, see [`Secret`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserToken {
+    /// The access token
+    pub access_token: Secret,
+    /// Type of the token, `bearer`
+    pub token_type: String,
+    /// Scopes granted to the token; empty for GitHub Apps, whose tokens are
+    /// limited by the app's permissions instead
+    pub scopes: Vec<String>,
+    /// When the access token expires, if the app has expiring tokens enabled
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Token to get a new access token with [`UserAuth::refresh`]
+    pub refresh_token: Option<Secret>,
+    /// When the refresh token expires
+    pub refresh_token_expires_at: Option<DateTime<Utc>>,
+}
+
+impl UserToken {
+    /// Check whether the access token expired or expires within 5 minutes
+    ///
+    /// Tokens without expiry never expire.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| Utc::now() + EXPIRY_BUFFER >= expires_at)
+    }
+
+    /// Check whether the token has a refresh token that did not expire
+    pub fn can_refresh(&self) -> bool {
+        self.refresh_token.is_some()
+            && self
+                .refresh_token_expires_at
+                .is_none_or(|expires_at| Utc::now() < expires_at)
+    }
+}
+
+/// Response of the access token endpoint
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    #[serde(default)]
+    token_type: String,
+    #[serde(default)]
+    scope: String,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+    refresh_token_expires_in: Option<i64>,
+    error: Option<String>,
+    #[serde(default)]
+    error_description: String,
+}
+
+impl TokenResponse {
+    fn into_token(self) -> Result<UserToken> {
+        if let Some(error) = self.error {
+            return Err(Error::OAuth {
+                error,
+                description: self.error_description,
+            });
+        }
+        let access_token = self.access_token.ok_or_else(|| {
+            Error::UnexpectedResponse("OAuth response has no access token".to_string())
+        })?;
+
+        let now = Utc::now();
+        let after = |seconds: i64| now + Duration::seconds(seconds);
+        Ok(UserToken {
+            access_token: access_token.into(),
+            token_type: self.token_type,
+            scopes: self
+                .scope
+                .split(',')
+                .filter(|scope| !scope.is_empty())
+                .map(str::to_string)
+                .collect(),
+            expires_at: self.expires_in.map(after),
+            refresh_token: self.refresh_token.map(Secret::from),
+            refresh_token_expires_at: self.refresh_token_expires_in.map(after),
+        })
+    }
+}
+
+/// Client for the OAuth web flow of a GitHub App
+///
+/// See the [module documentation](self).
+#[derive(Debug)]
+pub struct UserAuth {
+    config: OAuthConfig,
+    /// URL of the GitHub web interface
+    web_url: Uri,
+    /// Client sending the unauthenticated token requests
+    client: Octocrab,
+}
+
+impl UserAuth {
+    /// Create a client for the OAuth flow on github.com
+    ///
+    /// Requests go through the proxy configured in the environment, see
+    /// [`ProxyConfig::from_env`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be built, e.g. because the
+    /// proxy is invalid.
+    pub fn new(config: OAuthConfig) -> Result<Self> {
+        let transport = Transport::new()
+            .and_then(|transport| transport.with_proxy(&ProxyConfig::from_env()))
+            .map_err(Error::client)?
+            .with_base_uri(Uri::from_static(GITHUB_WEB_URL));
+        Self::with_transport(config, transport)
+    }
+
+    /// Create a client for the OAuth flow using a custom [`Transport`]
+    ///
+    /// The transport's base URI is the URL of the GitHub web interface, e.g.
+    /// `https://github.example.com` for GitHub Enterprise Server (not its
+    /// `/api/v3` API URL), or a mock server in tests.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be built.
+    pub fn with_transport(config: OAuthConfig, transport: Transport) -> Result<Self> {
+        Ok(Self {
+            config,
+            web_url: transport.base_uri().clone(),
+            client: transport.anonymous_client().map_err(Error::client)?,
+        })
+    }
+
+    /// Get the URL to send users to for authorizing the app
+    ///
+    /// GitHub redirects them back to the configured redirect URI (or the
+    /// app's first callback URL) with `state` and a code for
+    /// [`UserAuth::exchange_code`]. `state` must be unguessable and checked
+    /// in the callback to prevent cross-site request forgery, see
+    /// [`generate_state`].
+    pub fn authorize_url(&self, state: &str) -> String {
+        let mut url = url::Url::parse(&format!(
+            "{}/login/oauth/authorize",
+            self.web_url.to_string().trim_end_matches('/')
+        ))
+        .expect("the base URI is a valid URL");
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("client_id", &self.config.client_id);
+            if let Some(redirect_uri) = &self.config.redirect_uri {
+                query.append_pair("redirect_uri", redirect_uri);
+            }
+            query.append_pair("state", state);
+        }
+        url.into()
+    }
+
+    /// Trade the code of an authorization callback for a user token
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::OAuth`] if GitHub rejects the code, e.g. with
+    /// `bad_verification_code` for expired or already used codes, and another
+    /// error if the request fails.
+    pub async fn exchange_code(&self, code: &str) -> Result<UserToken> {
+        let mut body = json!({
+            "client_id": self.config.client_id,
+            "client_secret": self.config.client_secret.expose(),
+            "code": code,
+        });
+        if let Some(redirect_uri) = &self.config.redirect_uri {
+            body["redirect_uri"] = json!(redirect_uri);
+        }
+        debug!("Exchanging OAuth code for a user token");
+        self.request_token(&body).await
+    }
+
+    /// Trade the refresh token of an expiring user token for a new token
+    ///
+    /// The old access and refresh tokens stop working; store the new token.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::OAuth`] if the token has no refresh token, or GitHub
+    /// rejects it, e.g. with `bad_refresh_token` for expired refresh tokens,
+    /// and another error if the request fails.
+    pub async fn refresh(&self, token: &UserToken) -> Result<UserToken> {
+        let refresh_token = token.refresh_token.as_ref().ok_or_else(|| Error::OAuth {
+            error: "missing_refresh_token".to_string(),
+            description: "The user token does not expire and cannot be refreshed".to_string(),
+        })?;
+        let body = json!({
+            "client_id": self.config.client_id,
+            "client_secret": self.config.client_secret.expose(),
+            "grant_type": "refresh_token",
+            "refresh_token": refresh_token.expose(),
+        });
+        debug!("Refreshing user token");
+        self.request_token(&body).await
+    }
+
+    /// Get the token of `user_id` from `store`, refreshed if it is about to
+    /// expire
+    ///
+    /// Refreshed tokens are saved to the store. Returns `None` if the store
+    /// has no token for the user, or the token expired and cannot be
+    /// refreshed; the user must then authorize the app again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails, or refreshing the token fails.
+    pub async fn valid_token(
+        &self,
+        store: &dyn UserTokenStore,
+        user_id: u64,
+    ) -> anyhow::Result<Option<UserToken>> {
+        let Some(token) = store.load(user_id).await? else {
+            return Ok(None);
+        };
+        if !token.is_expired() {
+            return Ok(Some(token));
+        }
+        if !token.can_refresh() {
+            debug!("Token of user {} expired and cannot be refreshed", user_id);
+            return Ok(None);
+        }
+
+        let token = self.refresh(&token).await?;
+        store.save(user_id, token.clone()).await?;
+        Ok(Some(token))
+    }
+
+    async fn request_token(&self, body: &serde_json::Value) -> Result<UserToken> {
+        let request = http::Request::post(ACCESS_TOKEN_PATH).header(ACCEPT, "application/json");
+        let request = self.client.build_request(request, Some(body))?;
+        let response = self.client.execute(request).await?;
+        let response = octocrab::map_github_error(response).await?;
+        let text = self.client.body_to_string(response).await?;
+
+        serde_json::from_str::<TokenResponse>(&text)
+            .map_err(|e| Error::UnexpectedResponse(format!("Invalid OAuth response: {}", e)))?
+            .into_token()
+    }
+}
+
+/// Generate a random `state` for [`UserAuth::authorize_url`]
+///
+/// # Panics
+///
+/// Panics if the operating system's random number generator fails.
+pub fn generate_state() -> String {
+    let mut bytes = [0u8; 20];
+    getrandom::getrandom(&mut bytes).expect("the system random number generator is available");
+    hex::encode(bytes)
+}
+
+/// Storage of user tokens, keyed by GitHub user ID
+///
+/// Implement it to persist tokens, e.g. in a database. Tokens are redacted
+/// from serialization, so implementations store
+/// [`Secret::expose`] of the access and refresh tokens themselves.
+pub trait UserTokenStore: Send + Sync {
+    /// Load the token of a user
+    fn load(&self, user_id: u64) -> BoxFuture<'_, anyhow::Result<Option<UserToken>>>;
+
+    /// Save the token of a user, replacing any previous token
+    fn save(&self, user_id: u64, token: UserToken) -> BoxFuture<'_, anyhow::Result<()>>;
+
+    /// Remove the token of a user, e.g. when they revoke the authorization
+    fn remove(&self, user_id: u64) -> BoxFuture<'_, anyhow::Result<()>>;
+}
+
+/// [`UserTokenStore`] keeping tokens in memory
+///
+/// Tokens are lost on restart, so users must authorize the app again.
+#[derive(Debug, Default)]
+pub struct InMemoryUserTokenStore {
+    tokens: RwLock<HashMap<u64, UserToken>>,
+}
+
+impl InMemoryUserTokenStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl UserTokenStore for InMemoryUserTokenStore {
+    fn load(&self, user_id: u64) -> BoxFuture<'_, anyhow::Result<Option<UserToken>>> {
+        let token = self.tokens.read().unwrap().get(&user_id).cloned();
+        Box::pin(async move { Ok(token) })
+    }
+
+    fn save(&self, user_id: u64, token: UserToken) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.tokens.write().unwrap().insert(user_id, token);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove(&self, user_id: u64) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.tokens.write().unwrap().remove(&user_id);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockGitHub;
+    use axum::{routing::post, Json, Router};
+    use serde_json::Value;
+
+    fn config() -> OAuthConfig {
+        OAuthConfig {
+            client_id: "Iv1.client".to_string(),
+            client_secret: "client-secret".into(),
+            redirect_uri: Some("https://bot.example.com/callback".to_string()),
+        }
+    }
+
+    async fn mock() -> (MockGitHub, UserAuth) {
+        let mock = MockGitHub::start(Router::new().route(
+            ACCESS_TOKEN_PATH,
+            post(|Json(body): Json<Value>| async move {
+                let valid = body["code"] == "good-code" || body["refresh_token"] == "refresh-1";
+                if !valid {
+                    return Json(json!({
+                        "error": "bad_verification_code",
+                        "error_description": "The code passed is incorrect or expired.",
+                    }));
+                }
+                Json(json!({
+                    "access_token": "ghu_user",
+                    "token_type": "bearer",
+                    "scope": "",
+                    "expires_in": 28800,
+                    "refresh_token": "ghr_refresh",
+                    "refresh_token_expires_in": 15897600,
+                }))
+            }),
+        ))
+        .await;
+        let auth = UserAuth::with_transport(config(), mock.transport()).unwrap();
+        (mock, auth)
+    }
+
+    #[tokio::test]
+    async fn test_authorize_url() {
+        let transport = Transport::new()
+            .unwrap()
+            .with_base_uri(Uri::from_static(GITHUB_WEB_URL));
+        let auth = UserAuth::with_transport(config(), transport).unwrap();
+        assert_eq!(
+            auth.authorize_url("s t"),
+            "https://github.com/login/oauth/authorize?client_id=Iv1.client\
+             &redirect_uri=https%3A%2F%2Fbot.example.com%2Fcallback&state=s+t"
+        );
+        assert_eq!(generate_state().len(), 40);
+        assert_ne!(generate_state(), generate_state());
+    }
+
+    #[tokio::test]
+    async fn test_exchange_code_and_refresh() {
+        let (mock, auth) = mock().await;
+
+        let token = auth.exchange_code("good-code").await.unwrap();
+        assert_eq!(token.access_token.expose(), "ghu_user");
+        assert!(token.scopes.is_empty());
+        assert!(!token.is_expired());
+        assert!(token.can_refresh());
+        let request = &mock.requests()[0];
+        assert_eq!(request.headers["accept"], "application/json");
+        assert_eq!(request.body["client_secret"], "client-secret");
+        assert_eq!(
+            request.body["redirect_uri"],
+            "https://bot.example.com/callback"
+        );
+
+        let err = auth.exchange_code("used-code").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::OAuth { error, .. } if error == "bad_verification_code"),
+            "{err}"
+        );
+
+        // Expired tokens are refreshed and saved when loaded from a store
+        let store = InMemoryUserTokenStore::new();
+        let expired = UserToken {
+            expires_at: Some(Utc::now()),
+            refresh_token: Some("refresh-1".into()),
+            ..token
+        };
+        assert!(expired.is_expired());
+        store.save(7, expired).await.unwrap();
+        let refreshed = auth.valid_token(&store, 7).await.unwrap().unwrap();
+        assert!(!refreshed.is_expired());
+        assert_eq!(refreshed.refresh_token, Some("ghr_refresh".into()));
+        assert_eq!(store.load(7).await.unwrap(), Some(refreshed.clone()));
+        assert_eq!(mock.requests()[2].body["grant_type"], "refresh_token");
+
+        // Rejected refresh tokens fail
+        assert!(auth.refresh(&refreshed).await.is_err());
+        assert!(auth.valid_token(&store, 8).await.unwrap().is_none());
+    }
+}
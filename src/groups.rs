@@ -0,0 +1,278 @@
+//! Named handler groups with installation-level filters
+//!
+//! A single deployment can serve several teams by registering each team's
+//! handlers in a named group. Filters set on a group apply to every handler
+//! registered through it: the handlers only run for events from the group's
+//! organizations or installations, while handlers outside of any group run
+//! for all events.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! let mut team_a = app.group("team-a").only_organizations(["org-a"]);
+//! team_a
+//!     .on_issue(
+//!         |context: Context, _extra: Arc<()>| async move {
+//!             println!("Team A issue event: {}", context.kind());
+//!             Ok(())
+//!         },
+//!         Arc::new(()),
+//!     )
+//!     .await;
+//! drop(team_a);
+//!
+//! for group in app.groups().await {
+//!     println!("{}: {} handler(s)", group.filter.name, group.handlers);
+//! }
+//! # }
+//! ```
+
+use serde::Serialize;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use crate::{Context, Octofer};
+
+/// Filters of a named handler group
+///
+/// Empty filter lists match every event. When both lists are set, an event
+/// must match both.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct GroupFilter {
+    /// Name of the group
+    pub name: String,
+    /// Organization or user accounts the group's handlers run for
+    pub organizations: Vec<String>,
+    /// Installation IDs the group's handlers run for
+    pub installations: Vec<u64>,
+}
+
+impl GroupFilter {
+    /// Create a filter for `name` that matches every event
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Check whether the group's handlers should run for `context`
+    ///
+    /// The account of an event is its organization, or the owner of its
+    /// repository. Accounts are compared case-insensitively.
+    pub fn matches(&self, context: &Context) -> bool {
+        if !self.installations.is_empty()
+            && !context
+                .installation_id()
+                .is_some_and(|id| self.installations.contains(&id))
+        {
+            return false;
+        }
+
+        if !self.organizations.is_empty() {
+            let account = context.event().as_ref().and_then(|event| {
+                event
+                    .organization
+                    .as_ref()
+                    .map(|org| org.login.as_str())
+                    .or_else(|| {
+                        event
+                            .repository
+                            .as_ref()
+                            .and_then(|repo| repo.owner.as_ref())
+                            .map(|owner| owner.login.as_str())
+                    })
+            });
+            return account.is_some_and(|account| {
+                self.organizations
+                    .iter()
+                    .any(|org| org.eq_ignore_ascii_case(account))
+            });
+        }
+
+        true
+    }
+}
+
+/// A registered handler group and the number of handlers in it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupInfo {
+    /// The group's filters
+    pub filter: GroupFilter,
+    /// Number of handlers registered through the group
+    pub handlers: usize,
+}
+
+/// Scope for registering handlers in a named group
+///
+/// Created by [`Octofer::group`]. Every handler registered through the scope
+/// (it dereferences to [`Octofer`], so all `on_*` methods are available) gets
+/// the group's filters. Filters must be set before registering handlers; the
+/// scope ends when it is dropped.
+pub struct HandlerGroup<'a> {
+    app: &'a mut Octofer,
+    filter: GroupFilter,
+}
+
+impl HandlerGroup<'_> {
+    /// Only run the group's handlers for events from these organizations or users
+    pub fn only_organizations<I, S>(mut self, organizations: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.filter
+            .organizations
+            .extend(organizations.into_iter().map(Into::into));
+        self.apply();
+        self
+    }
+
+    /// Only run the group's handlers for events from these installations
+    pub fn only_installations(mut self, installations: impl IntoIterator<Item = u64>) -> Self {
+        self.filter.installations.extend(installations);
+        self.apply();
+        self
+    }
+
+    /// Get the group's filters
+    pub fn filter(&self) -> &GroupFilter {
+        &self.filter
+    }
+
+    fn apply(&mut self) {
+        self.app
+            .server
+            .set_handler_group(Some(Arc::new(self.filter.clone())));
+    }
+}
+
+impl Deref for HandlerGroup<'_> {
+    type Target = Octofer;
+
+    fn deref(&self) -> &Octofer {
+        self.app
+    }
+}
+
+impl DerefMut for HandlerGroup<'_> {
+    fn deref_mut(&mut self) -> &mut Octofer {
+        self.app
+    }
+}
+
+impl Drop for HandlerGroup<'_> {
+    fn drop(&mut self) {
+        self.app.server.set_handler_group(None);
+    }
+}
+
+impl Octofer {
+    /// Start registering handlers in the named group `name`
+    ///
+    /// See the [`groups`](crate::groups) module for details.
+    pub fn group(&mut self, name: impl Into<String>) -> HandlerGroup<'_> {
+        let mut group = HandlerGroup {
+            app: self,
+            filter: GroupFilter::new(name),
+        };
+        group.apply();
+        group
+    }
+
+    /// List the handler groups with at least one registered handler
+    pub async fn groups(&self) -> Vec<GroupInfo> {
+        self.server.dispatcher().groups().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issues_payload, webhook_event};
+    use std::sync::Mutex;
+
+    fn issue_context(owner: &str, installation_id: u64) -> Context {
+        let mut payload = issues_payload("opened", 1);
+        payload["repository"]["owner"]["login"] = owner.into();
+        Context::new(
+            Some(webhook_event("issues", payload)),
+            Some(installation_id),
+        )
+    }
+
+    async fn record(app: &mut Octofer, calls: &Arc<Mutex<Vec<&'static str>>>, name: &'static str) {
+        app.on_issue(
+            move |_context: Context, calls: Arc<Mutex<Vec<&'static str>>>| async move {
+                calls.lock().unwrap().push(name);
+                Ok(())
+            },
+            calls.clone(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_groups_are_isolated() {
+        let mut app = Octofer::new_default();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        record(
+            &mut app.group("team-a").only_organizations(["Org-A"]),
+            &calls,
+            "a",
+        )
+        .await;
+        record(
+            &mut app.group("team-b").only_organizations(["org-b"]),
+            &calls,
+            "b",
+        )
+        .await;
+        record(&mut app.group("ops").only_installations([7]), &calls, "ops").await;
+        record(&mut app, &calls, "all").await;
+
+        let dispatcher = app.server.dispatcher();
+        for (owner, installation_id) in [("org-a", 1), ("org-b", 7), ("other", 2)] {
+            dispatcher
+                .dispatch(issue_context(owner, installation_id))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["a", "all", "b", "ops", "all", "all"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_groups_are_inspectable() {
+        let mut app = Octofer::new_default();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let mut group = app.group("team-a").only_organizations(["org-a"]);
+            record(&mut group, &calls, "a").await;
+            record(&mut group, &calls, "a").await;
+        }
+        record(&mut app, &calls, "all").await;
+
+        let groups = app.groups().await;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].filter.name, "team-a");
+        assert_eq!(groups[0].filter.organizations, ["org-a"]);
+        assert_eq!(groups[0].handlers, 2);
+
+        // The `on_*` wrappers report the location they were called from
+        let names = app.server.dispatcher().handler_names().await;
+        assert!(names[0]
+            .1
+            .iter()
+            .all(|name| name.contains("src/groups.rs:")));
+    }
+}
@@ -0,0 +1,828 @@
+//! Branch protection helpers
+//!
+//! Requiring an app's check on a branch takes two steps: the check must have
+//! run once in the repository before branch protection can select it, and
+//! the branch's protection must then list it. [`Context::ensure_check_exists`]
+//! creates a neutral check run on the head of the default branch, and
+//! [`Context::require_status_check`] adds the check to the required status
+//! checks of a branch, keeping the rest of its protection as it was.
+//! [`Context::apply_branch_protection`] changes other settings the same way,
+//! from a [`ProtectionSpec`].
+//!
+//! GitHub only accepts protection as a whole, so the current protection is
+//! read, converted to the shape GitHub expects when writing, and written back
+//! with the changes. The helpers fail with [`MissingPermission`] when
+//! GitHub refuses them: creating check runs needs `checks: write`, and
+//! branch protection needs `administration: write`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_installation_repositories(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         context
+//!             .for_each_repository(4, |context| async move {
+//!                 context.ensure_check_exists("octofer / lint").await?;
+//!                 context.require_status_check("main", "octofer / lint").await?;
+//!                 Ok(())
+//!             })
+//!             .await?;
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use anyhow::{anyhow, Result};
+use http::StatusCode;
+use octocrab::models::checks::CheckRun;
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::debug;
+
+use crate::helpers::installation::MissingPermission;
+use crate::helpers::path_segment;
+use crate::helpers::permissions::Permission;
+use crate::Context;
+
+/// Name of the installation permission for branch protection
+pub const ADMINISTRATION_PERMISSION: &str = "administration";
+
+/// Name of the installation permission for check runs
+pub const CHECKS_PERMISSION: &str = "checks";
+
+/// Settings written back as is, from their `enabled` flag
+const FLAGS: [&str; 7] = [
+    "required_linear_history",
+    "allow_force_pushes",
+    "allow_deletions",
+    "block_creations",
+    "required_conversation_resolution",
+    "lock_branch",
+    "allow_fork_syncing",
+];
+
+/// Settings of required pull request reviews written back as is
+const REVIEW_SETTINGS: [&str; 4] = [
+    "dismiss_stale_reviews",
+    "require_code_owner_reviews",
+    "required_approving_review_count",
+    "require_last_push_approval",
+];
+
+impl Context {
+    /// Make sure a check run named `name` exists on the head of the event
+    /// repository's default branch, so branch protection can require it
+    ///
+    /// Creates a completed check run with the `neutral` conclusion, unless
+    /// one with that name already ran on the commit. Returns the check run.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the installation may not create
+    /// check runs, or an error if the event has no repository, no
+    /// installation client is available, or a request fails.
+    pub async fn ensure_check_exists(&self, name: &str) -> Result<CheckRun> {
+        #[derive(Deserialize)]
+        struct CheckRuns {
+            check_runs: Vec<CheckRun>,
+        }
+
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let repo_route = format!("/repos/{}/{}", path_segment(&owner), path_segment(&repo));
+        let branch = match self.repository().and_then(|r| r.default_branch.clone()) {
+            Some(branch) => branch,
+            None => {
+                let repository: Value = get(&client, &repo_route, None).await?;
+                repository["default_branch"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Repository {} has no default branch", repo_route))?
+                    .to_string()
+            }
+        };
+        let head: Value = get(
+            &client,
+            &format!("{}/branches/{}", repo_route, path_segment(&branch)),
+            None,
+        )
+        .await?;
+        let head_sha = head["commit"]["sha"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Branch {} has no head commit", branch))?;
+
+        let existing: CheckRuns = get(
+            &client,
+            &format!("{}/commits/{}/check-runs", repo_route, head_sha),
+            Some(&json!({ "check_name": name, "filter": "latest" })),
+        )
+        .await?;
+        if let Some(check_run) = existing.check_runs.into_iter().next() {
+            return Ok(check_run);
+        }
+
+        debug!(
+            "Creating check run '{}' on {} of {}/{}",
+            name, branch, owner, repo
+        );
+        let body = json!({
+            "name": name,
+            "head_sha": head_sha,
+            "status": "completed",
+            "conclusion": "neutral",
+            "output": {
+                "title": name,
+                "summary": "Created so that branch protection can require this check.",
+            },
+        });
+        client
+            .post(format!("{}/check-runs", repo_route), Some(&body))
+            .await
+            .map_err(|e| {
+                self.permission_error(
+                    e,
+                    CHECKS_PERMISSION,
+                    &format!("create check run '{}'", name),
+                )
+            })
+    }
+
+    /// Require the check `check_name` to pass before merging into `branch`
+    /// of the event's repository
+    ///
+    /// The current protection of the branch, if any, is kept: only the
+    /// check is added to its required status checks. Returns `false`
+    /// without changing anything if the check was already required.
+    ///
+    /// GitHub only lists checks that ran in the repository, see
+    /// [`Context::ensure_check_exists`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the installation may not administer
+    /// the repository, or an error if the event has no repository, no
+    /// installation client is available, or a request fails, e.g. for
+    /// private repositories on plans without branch protection.
+    pub async fn require_status_check(&self, branch: &str, check_name: &str) -> Result<bool> {
+        let spec = ProtectionSpec {
+            required_status_checks: vec![check_name.to_string()],
+            ..Default::default()
+        };
+        self.apply_branch_protection(branch, &spec).await
+    }
+
+    /// Protect `branch` of the event's repository as described by `spec`
+    ///
+    /// Settings `spec` leaves unset keep their current value, and its
+    /// required checks are added to those already required. Returns `false`
+    /// without changing anything if the branch was already protected so.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Context::require_status_check`].
+    pub async fn apply_branch_protection(
+        &self,
+        branch: &str,
+        spec: &ProtectionSpec,
+    ) -> Result<bool> {
+        self.repo_admin()
+            .await?
+            .apply_protection(branch, spec)
+            .await
+    }
+
+    /// Get the event's repository, administered with the installation client
+    pub(crate) async fn repo_admin(&self) -> Result<RepoAdmin> {
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        Ok(RepoAdmin {
+            client,
+            installation_id: self.installation_id.unwrap_or_default(),
+            granted: self
+                .installation_access()
+                .and_then(|access| access.permission(ADMINISTRATION_PERMISSION)),
+            owner,
+            repo,
+        })
+    }
+
+    /// Turn a request GitHub refused into [`MissingPermission`] of
+    /// `permission`, see [`permission_error`]
+    fn permission_error(
+        &self,
+        error: octocrab::Error,
+        permission: &str,
+        what: &str,
+    ) -> anyhow::Error {
+        permission_error(
+            error,
+            self.installation_id.unwrap_or_default(),
+            permission,
+            self.installation_access()
+                .and_then(|access| access.permission(permission)),
+            what,
+        )
+    }
+}
+
+/// Repository whose settings an installation changes
+pub(crate) struct RepoAdmin {
+    /// Client of the installation
+    pub(crate) client: Octocrab,
+    /// ID of the installation
+    pub(crate) installation_id: u64,
+    /// Level of the `administration` permission granted, if known
+    pub(crate) granted: Option<Permission>,
+    /// Login of the repository's owner
+    pub(crate) owner: String,
+    /// Name of the repository
+    pub(crate) repo: String,
+}
+
+impl RepoAdmin {
+    /// API route of the repository
+    pub(crate) fn route(&self) -> String {
+        format!(
+            "/repos/{}/{}",
+            path_segment(&self.owner),
+            path_segment(&self.repo)
+        )
+    }
+
+    /// Turn a request GitHub refused into [`MissingPermission`] of the
+    /// `administration` permission
+    pub(crate) fn error(&self, error: octocrab::Error, what: &str) -> anyhow::Error {
+        permission_error(
+            error,
+            self.installation_id,
+            ADMINISTRATION_PERMISSION,
+            self.granted,
+            what,
+        )
+    }
+
+    /// Protect `branch` as described by `spec`, see
+    /// [`Context::apply_branch_protection`]
+    pub(crate) async fn apply_protection(
+        &self,
+        branch: &str,
+        spec: &ProtectionSpec,
+    ) -> Result<bool> {
+        let route = format!(
+            "{}/branches/{}/protection",
+            self.route(),
+            path_segment(branch)
+        );
+        let what = format!("update the protection of branch {}", branch);
+
+        let current = match self.client.get::<Value, _, _>(&route, None::<&()>).await {
+            Ok(protection) => Some(protection),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == StatusCode::NOT_FOUND
+                    && source.message == "Branch not protected" =>
+            {
+                None
+            }
+            Err(e) => return Err(self.error(e, &what)),
+        };
+        let current = current.as_ref().map(protection_update).unwrap_or_else(|| {
+            json!({
+                "required_status_checks": null,
+                "enforce_admins": null,
+                "required_pull_request_reviews": null,
+                "restrictions": null,
+            })
+        });
+        let mut update = current.clone();
+        spec.merge_into(&mut update);
+        if update == current {
+            return Ok(false);
+        }
+
+        debug!("Updating the protection of {}", route);
+        self.client
+            .put::<Value, _, _>(&route, Some(&update))
+            .await
+            .map_err(|e| self.error(e, &what))?;
+        Ok(true)
+    }
+}
+
+/// Desired protection of a branch, see [`Context::apply_branch_protection`]
+///
+/// Unset settings keep their current value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProtectionSpec {
+    /// Checks required to pass before merging, added to those already
+    /// required
+    pub required_status_checks: Vec<String>,
+    /// Require branches to be up to date with the base before merging
+    pub strict: Option<bool>,
+    /// Apply the protection to administrators too
+    pub enforce_admins: Option<bool>,
+    /// Approving reviews required before merging
+    pub required_approving_review_count: Option<u8>,
+    /// Dismiss approvals when new commits are pushed
+    pub dismiss_stale_reviews: Option<bool>,
+    /// Require a review from the code owners
+    pub require_code_owner_reviews: Option<bool>,
+    /// Refuse merge commits
+    pub required_linear_history: Option<bool>,
+    /// Allow force pushes
+    pub allow_force_pushes: Option<bool>,
+    /// Allow deleting the branch
+    pub allow_deletions: Option<bool>,
+    /// Require review conversations to be resolved before merging
+    pub required_conversation_resolution: Option<bool>,
+}
+
+impl ProtectionSpec {
+    /// Set the settings of the spec in a protection `update`
+    fn merge_into(&self, update: &mut Value) {
+        for check in &self.required_status_checks {
+            add_required_check(update, check);
+        }
+        if let Some(strict) = self.strict {
+            let checks = &mut update["required_status_checks"];
+            if checks.is_null() {
+                *checks = json!({ "strict": false, "checks": [] });
+            }
+            checks["strict"] = json!(strict);
+        }
+        if let Some(enforce_admins) = self.enforce_admins {
+            update["enforce_admins"] = json!(enforce_admins);
+        }
+
+        let reviews = [
+            (
+                "required_approving_review_count",
+                self.required_approving_review_count
+                    .map(|count| json!(count)),
+            ),
+            (
+                "dismiss_stale_reviews",
+                self.dismiss_stale_reviews.map(Value::Bool),
+            ),
+            (
+                "require_code_owner_reviews",
+                self.require_code_owner_reviews.map(Value::Bool),
+            ),
+        ];
+        for (setting, value) in reviews {
+            if let Some(value) = value {
+                let settings = &mut update["required_pull_request_reviews"];
+                if settings.is_null() {
+                    *settings = json!({});
+                }
+                settings[setting] = value;
+            }
+        }
+
+        let flags = [
+            ("required_linear_history", self.required_linear_history),
+            ("allow_force_pushes", self.allow_force_pushes),
+            ("allow_deletions", self.allow_deletions),
+            (
+                "required_conversation_resolution",
+                self.required_conversation_resolution,
+            ),
+        ];
+        for (flag, enabled) in flags {
+            if let Some(enabled) = enabled {
+                update[flag] = json!(enabled);
+            }
+        }
+    }
+}
+
+/// Turn a request GitHub refused into [`MissingPermission`] of
+/// `permission`, with the `granted` level of installation `installation_id`
+///
+/// GitHub also refuses branch protection with `403` for private
+/// repositories on plans without it, which is reported as is.
+fn permission_error(
+    error: octocrab::Error,
+    installation_id: u64,
+    permission: &str,
+    granted: Option<Permission>,
+    what: &str,
+) -> anyhow::Error {
+    match &error {
+        octocrab::Error::GitHub { source, .. }
+            if source.status_code == StatusCode::FORBIDDEN
+                && !source.message.contains("Upgrade to GitHub Pro") =>
+        {
+            MissingPermission {
+                installation_id,
+                permission: permission.to_string(),
+                required: Permission::Write,
+                granted,
+            }
+            .into()
+        }
+        _ => anyhow!("Failed to {}: {}", what, error),
+    }
+}
+
+/// Get `route`, failing with the route in the error
+async fn get<T: serde::de::DeserializeOwned>(
+    client: &Octocrab,
+    route: &str,
+    query: Option<&Value>,
+) -> Result<T> {
+    client
+        .get(route, query)
+        .await
+        .map_err(|e| anyhow!("Failed to get {}: {}", route, e))
+}
+
+/// Convert the protection of a branch, as GitHub returns it, to the body
+/// that writes it back unchanged
+///
+/// Settings read as objects are written as values, and users, teams and
+/// apps as their logins and slugs.
+fn protection_update(protection: &Value) -> Value {
+    let mut update = Map::new();
+
+    let checks = &protection["required_status_checks"];
+    update.insert(
+        "required_status_checks".to_string(),
+        if checks.is_object() {
+            let listed = match checks["checks"].as_array() {
+                Some(listed) => listed
+                    .iter()
+                    .map(|check| match check["app_id"].as_i64() {
+                        Some(app_id) => json!({ "context": check["context"], "app_id": app_id }),
+                        None => json!({ "context": check["context"] }),
+                    })
+                    .collect(),
+                // Protection set before apps could be tied to checks
+                None => checks["contexts"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|context| json!({ "context": context }))
+                    .collect(),
+            };
+            json!({
+                "strict": checks["strict"].as_bool().unwrap_or(false),
+                "checks": Value::Array(listed),
+            })
+        } else {
+            Value::Null
+        },
+    );
+
+    update.insert(
+        "enforce_admins".to_string(),
+        protection["enforce_admins"]["enabled"]
+            .as_bool()
+            .map_or(Value::Null, Value::Bool),
+    );
+
+    let reviews = &protection["required_pull_request_reviews"];
+    update.insert(
+        "required_pull_request_reviews".to_string(),
+        if reviews.is_object() {
+            let mut settings = Map::new();
+            for setting in REVIEW_SETTINGS {
+                if !reviews[setting].is_null() {
+                    settings.insert(setting.to_string(), reviews[setting].clone());
+                }
+            }
+            for allowances in ["dismissal_restrictions", "bypass_pull_request_allowances"] {
+                if reviews[allowances].is_object() {
+                    settings.insert(allowances.to_string(), actors(&reviews[allowances]));
+                }
+            }
+            Value::Object(settings)
+        } else {
+            Value::Null
+        },
+    );
+
+    let restrictions = &protection["restrictions"];
+    update.insert(
+        "restrictions".to_string(),
+        if restrictions.is_object() {
+            actors(restrictions)
+        } else {
+            Value::Null
+        },
+    );
+
+    for flag in FLAGS {
+        if let Some(enabled) = protection[flag]["enabled"].as_bool() {
+            update.insert(flag.to_string(), Value::Bool(enabled));
+        }
+    }
+    Value::Object(update)
+}
+
+/// Users, teams and apps of a restriction, as logins and slugs
+fn actors(restriction: &Value) -> Value {
+    let names = |list: &str, name: &str| -> Vec<Value> {
+        restriction[list]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|actor| actor.get(name).cloned())
+            .collect()
+    };
+    json!({
+        "users": names("users", "login"),
+        "teams": names("teams", "slug"),
+        "apps": names("apps", "slug"),
+    })
+}
+
+/// Add `check_name` to the required status checks of a protection `update`,
+/// returning whether it was missing
+fn add_required_check(update: &mut Value, check_name: &str) -> bool {
+    let checks = &mut update["required_status_checks"];
+    if checks.is_null() {
+        *checks = json!({ "strict": false, "checks": [] });
+    }
+    let listed = checks["checks"]
+        .as_array_mut()
+        .expect("required status checks of an update list their checks");
+    if listed.iter().any(|check| check["context"] == check_name) {
+        return false;
+    }
+    // Without an app ID, GitHub ties the check to the app that last set it
+    listed.push(json!({ "context": check_name }));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issue, repository, user, MockGitHub};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+
+    fn payload() -> Value {
+        json!({
+            "action": "opened",
+            "issue": issue("octofer", "app", 1),
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    fn protection() -> Value {
+        json!({
+            "url": "https://api.github.com/repos/octofer/app/branches/main/protection",
+            "required_status_checks": {
+                "url": "https://api.github.com/repos/octofer/app/branches/main/protection/required_status_checks",
+                "strict": true,
+                "contexts": ["ci"],
+                "contexts_url": "https://api.github.com/repos/octofer/app/branches/main/protection/required_status_checks/contexts",
+                "checks": [{ "context": "ci", "app_id": 15368 }],
+            },
+            "required_pull_request_reviews": {
+                "url": "https://api.github.com/repos/octofer/app/branches/main/protection/required_pull_request_reviews",
+                "dismissal_restrictions": {
+                    "users": [user("maintainer")],
+                    "teams": [{ "id": 1, "slug": "core", "name": "Core" }],
+                    "apps": [],
+                },
+                "dismiss_stale_reviews": true,
+                "require_code_owner_reviews": false,
+                "required_approving_review_count": 2,
+                "require_last_push_approval": false,
+            },
+            "enforce_admins": { "url": "", "enabled": true },
+            "restrictions": {
+                "url": "",
+                "users": [],
+                "teams": [{ "id": 1, "slug": "core", "name": "Core" }],
+                "apps": [{ "id": 2, "slug": "deployer", "name": "Deployer" }],
+            },
+            "required_linear_history": { "enabled": true },
+            "allow_force_pushes": { "enabled": false },
+            "allow_deletions": { "enabled": false },
+            "required_conversation_resolution": { "enabled": true },
+            "required_signatures": { "url": "", "enabled": true },
+        })
+    }
+
+    fn protection_routes(protection: Value) -> Router {
+        Router::new().route(
+            "/repos/octofer/app/branches/main/protection",
+            get(move || async move { Json(protection) })
+                .put(|Json(body): Json<Value>| async move { Json(body) }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_require_status_check_keeps_protection() {
+        let mock = MockGitHub::start(protection_routes(protection())).await;
+        let context = mock.context("issues", payload());
+
+        assert!(context.require_status_check("main", "lint").await.unwrap());
+        let requests = mock.requests();
+        let methods: Vec<_> = requests.iter().map(|r| r.method.to_string()).collect();
+        assert_eq!(methods, ["GET", "PUT"]);
+        assert_eq!(
+            requests[1].body,
+            json!({
+                "required_status_checks": {
+                    "strict": true,
+                    "checks": [{ "context": "ci", "app_id": 15368 }, { "context": "lint" }],
+                },
+                "enforce_admins": true,
+                "required_pull_request_reviews": {
+                    "dismissal_restrictions": {
+                        "users": ["maintainer"],
+                        "teams": ["core"],
+                        "apps": [],
+                    },
+                    "dismiss_stale_reviews": true,
+                    "require_code_owner_reviews": false,
+                    "required_approving_review_count": 2,
+                    "require_last_push_approval": false,
+                },
+                "restrictions": { "users": [], "teams": ["core"], "apps": ["deployer"] },
+                "required_linear_history": true,
+                "allow_force_pushes": false,
+                "allow_deletions": false,
+                "required_conversation_resolution": true,
+            })
+        );
+
+        // Already required: nothing is written
+        assert!(!context.require_status_check("main", "ci").await.unwrap());
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_apply_branch_protection_merges_spec() {
+        let mock = MockGitHub::start(protection_routes(protection())).await;
+        let context = mock.context("issues", payload());
+        let spec = ProtectionSpec {
+            required_approving_review_count: Some(1),
+            allow_force_pushes: Some(false),
+            required_status_checks: vec!["ci".to_string()],
+            ..Default::default()
+        };
+
+        assert!(context
+            .apply_branch_protection("main", &spec)
+            .await
+            .unwrap());
+        let update = &mock.requests()[1].body;
+        assert_eq!(
+            update["required_pull_request_reviews"]["required_approving_review_count"],
+            1
+        );
+        assert_eq!(
+            update["required_pull_request_reviews"]["dismiss_stale_reviews"],
+            true
+        );
+        assert_eq!(
+            update["required_status_checks"]["checks"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(update["required_linear_history"], true);
+
+        // Already protected so: nothing is written
+        let spec = ProtectionSpec {
+            allow_deletions: Some(false),
+            ..Default::default()
+        };
+        assert!(!context
+            .apply_branch_protection("main", &spec)
+            .await
+            .unwrap());
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_require_status_check_on_unprotected_branch() {
+        let mock = MockGitHub::start(
+            Router::new().route(
+                "/repos/octofer/app/branches/main/protection",
+                get(|| async {
+                    (
+                        StatusCode::NOT_FOUND,
+                        Json(json!({ "message": "Branch not protected" })),
+                    )
+                })
+                .put(|Json(body): Json<Value>| async move { Json(body) }),
+            ),
+        )
+        .await;
+        let context = mock.context("issues", payload());
+
+        assert!(context.require_status_check("main", "lint").await.unwrap());
+        assert_eq!(
+            mock.requests()[1].body,
+            json!({
+                "required_status_checks": { "strict": false, "checks": [{ "context": "lint" }] },
+                "enforce_admins": null,
+                "required_pull_request_reviews": null,
+                "restrictions": null,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_administration_permission() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/branches/main/protection",
+            get(|| async {
+                (
+                    StatusCode::FORBIDDEN,
+                    Json(json!({ "message": "Resource not accessible by integration" })),
+                )
+            }),
+        ))
+        .await;
+        let context = mock.context("issues", payload());
+
+        let err = context
+            .require_status_check("main", "lint")
+            .await
+            .unwrap_err();
+        let missing = err.downcast_ref::<MissingPermission>().unwrap();
+        assert_eq!(missing.permission, "administration");
+        assert_eq!(missing.required, Permission::Write);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_check_exists_creates_neutral_run() {
+        let check_run = |name: &Value, head_sha: &Value| {
+            json!({
+                "id": 1,
+                "node_id": "CR_1",
+                "details_url": null,
+                "head_sha": head_sha,
+                "url": "https://api.github.com/repos/octofer/app/check-runs/1",
+                "html_url": null,
+                "conclusion": "neutral",
+                "output": {
+                    "title": null,
+                    "summary": null,
+                    "text": null,
+                    "annotations_count": 0,
+                    "annotations_url": "https://api.github.com/repos/octofer/app/check-runs/1/annotations",
+                },
+                "started_at": null,
+                "completed_at": null,
+                "name": name,
+                "pull_requests": [],
+            })
+        };
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app",
+                    get(|| async {
+                        let mut repository = repository("octofer", "app");
+                        repository["default_branch"] = json!("trunk");
+                        Json(repository)
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/branches/trunk",
+                    get(|| async {
+                        Json(json!({ "name": "trunk", "commit": { "sha": "abc123" } }))
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/commits/abc123/check-runs",
+                    get(|| async { Json(json!({ "total_count": 0, "check_runs": [] })) }),
+                )
+                .route(
+                    "/repos/octofer/app/check-runs",
+                    post(move |Json(body): Json<Value>| async move {
+                        Json(check_run(&body["name"], &body["head_sha"]))
+                    }),
+                ),
+        )
+        .await;
+        let context = mock.context("issues", payload());
+
+        let created = context.ensure_check_exists("lint").await.unwrap();
+        assert_eq!(created.head_sha, "abc123");
+        let requests = mock.requests();
+        assert_eq!(
+            requests[2].query.as_deref(),
+            Some("check_name=lint&filter=latest")
+        );
+        assert_eq!(requests[3].body["status"], "completed");
+        assert_eq!(requests[3].body["conclusion"], "neutral");
+    }
+}
@@ -0,0 +1,420 @@
+//! Check run and merge queue helpers
+//!
+//! CI bots report results as check runs against a commit. For pull requests
+//! that is the head commit of the pull request; for merge queues it is the
+//! head commit of the temporary merge group branch, sent in `merge_group`
+//! webhooks. [`Context::head_sha`] resolves the right commit for both, so one
+//! handler can serve `pull_request` and `merge_group` events alike.
+//!
+//! GitHub does not include a group's position in the queue in webhook
+//! payloads, so it is not exposed here; [`MergeGroup::pull_request_number`]
+//! tells which pull request a group was created for.
+//!
+//! Check runs can offer action buttons, e.g. "Fix it". Clicking one sends a
+//! `check_run` event with the `requested_action` action and the button's
+//! identifier, see [`Context::requested_action_identifier`]; handlers for one
+//! button are registered with
+//! [`Octofer::on_check_action`](crate::Octofer::on_check_action).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::checks::CheckRunOptions;
+//! use octofer::octocrab::params::checks::{CheckRunConclusion, CheckRunStatus};
+//! use octofer::Context;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     let name = if context.is_merge_queue_event() {
+//!         "ci / merge queue"
+//!     } else {
+//!         "ci / pull request"
+//!     };
+//!     context
+//!         .create_check_run(
+//!             name,
+//!             CheckRunOptions {
+//!                 status: Some(CheckRunStatus::Completed),
+//!                 conclusion: Some(CheckRunConclusion::Success),
+//!                 title: Some("All tests passed".to_string()),
+//!                 ..Default::default()
+//!             },
+//!         )
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::{anyhow, Result};
+use octocrab::models::checks::CheckRun;
+use octocrab::models::webhook_events::payload::{
+    CheckRunWebhookEventAction, CheckSuiteWebhookEventAction,
+};
+use octocrab::models::webhook_events::{WebhookEvent, WebhookEventPayload};
+use octocrab::params::checks::{CheckRunConclusion, CheckRunStatus};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::github::models::MergeGroup;
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Prefix of the temporary branches GitHub creates for merge queue groups
+pub const MERGE_QUEUE_BRANCH_PREFIX: &str = "gh-readonly-queue/";
+
+/// Optional fields of a check run created with [`Context::create_check_run`]
+#[derive(Debug, Clone, Default)]
+pub struct CheckRunOptions {
+    /// Status of the check run (GitHub defaults to `queued`)
+    pub status: Option<CheckRunStatus>,
+    /// Conclusion of the check run; implies the `completed` status
+    pub conclusion: Option<CheckRunConclusion>,
+    /// Title of the check run output
+    pub title: Option<String>,
+    /// Summary of the check run output, in Markdown
+    pub summary: Option<String>,
+    /// URL of the CI system's page for the check run
+    pub details_url: Option<String>,
+}
+
+impl Context {
+    /// Get the merge group of a `merge_group` event
+    ///
+    /// Returns `None` for other events.
+    pub fn merge_group(&self) -> Option<MergeGroup> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::MergeGroup(payload) => {
+                parse_payload_part("merge group", &payload.merge_group)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the action of a `check_run` event
+    ///
+    /// Returns `None` for other events.
+    pub fn check_run_action(&self) -> Option<CheckRunWebhookEventAction> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::CheckRun(payload) => Some(payload.action.clone()),
+            _ => None,
+        }
+    }
+
+    /// Get the action of a `check_suite` event
+    ///
+    /// Returns `None` for other events.
+    pub fn check_suite_action(&self) -> Option<CheckSuiteWebhookEventAction> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::CheckSuite(payload) => Some(payload.action.clone()),
+            _ => None,
+        }
+    }
+
+    /// Get the identifier of the action requested on a check run
+    ///
+    /// Set for `check_run` events with the `requested_action` action, sent
+    /// when a user clicks one of the check run's action buttons. Returns
+    /// `None` for other events, and for contexts not parsed from a delivery
+    /// (octocrab does not keep the identifier in [`Context::event`]).
+    pub fn requested_action_identifier(&self) -> Option<&str> {
+        self.requested_action.as_deref()
+    }
+
+    /// Check whether the event was triggered by a merge queue
+    ///
+    /// True for `merge_group` events, and for `check_suite` and `check_run`
+    /// events on a merge queue branch.
+    pub fn is_merge_queue_event(&self) -> bool {
+        let Some(event) = &self.event else {
+            return false;
+        };
+        let head_branch = match &event.specific {
+            WebhookEventPayload::MergeGroup(_) => return true,
+            WebhookEventPayload::CheckSuite(payload) => &payload.check_suite["head_branch"],
+            WebhookEventPayload::CheckRun(payload) => {
+                &payload.check_run["check_suite"]["head_branch"]
+            }
+            _ => return false,
+        };
+        head_branch
+            .as_str()
+            .is_some_and(|branch| branch.starts_with(MERGE_QUEUE_BRANCH_PREFIX))
+    }
+
+    /// Get the SHA of the commit the event is about
+    ///
+    /// This is the commit checks should be reported against: the head of the
+    /// merge group for `merge_group` events, the head of the pull request for
+    /// pull request events, the head of the suite for `check_suite` and
+    /// `check_run` events, the head of the run for `workflow_run` events, the
+    /// pushed commit for `push` events, and the commit the status was set on
+    /// for `status` events. Returns `None` for other events.
+    pub fn head_sha(&self) -> Option<String> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::MergeGroup(_) => self.merge_group().map(|group| group.head_sha),
+            WebhookEventPayload::PullRequest(payload) => {
+                Some(payload.pull_request.head.sha.clone())
+            }
+            WebhookEventPayload::PullRequestReview(payload) => {
+                Some(payload.pull_request.head.sha.clone())
+            }
+            WebhookEventPayload::PullRequestReviewComment(payload) => {
+                Some(payload.pull_request.head.sha.clone())
+            }
+            WebhookEventPayload::CheckSuite(payload) => string(&payload.check_suite["head_sha"]),
+            WebhookEventPayload::CheckRun(payload) => string(&payload.check_run["head_sha"]),
+            WebhookEventPayload::WorkflowRun(payload) => string(&payload.workflow_run["head_sha"]),
+            WebhookEventPayload::Push(payload) if !payload.deleted => Some(payload.after.clone()),
+            WebhookEventPayload::Status(payload) => Some(payload.sha.clone()),
+            _ => None,
+        }
+    }
+
+    /// Create a check run named `name` on the event's [head commit](Context::head_sha)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository or head commit, no
+    /// installation client is available, or the request fails.
+    pub async fn create_check_run(&self, name: &str, options: CheckRunOptions) -> Result<CheckRun> {
+        let (owner, repo) = self.require_repository()?;
+        let head_sha = self
+            .head_sha()
+            .ok_or_else(|| anyhow!("Event {} has no head commit", self.kind()))?;
+        let client = self.require_installation_client().await?;
+
+        let mut body = json!({ "name": name, "head_sha": head_sha });
+        if let Some(status) = options.status {
+            body["status"] = json!(status);
+        }
+        if let Some(conclusion) = options.conclusion {
+            body["conclusion"] = json!(conclusion);
+        }
+        if let Some(details_url) = options.details_url {
+            body["details_url"] = json!(details_url);
+        }
+        if options.title.is_some() || options.summary.is_some() {
+            body["output"] = json!({
+                "title": options.title.unwrap_or_else(|| name.to_string()),
+                "summary": options.summary.unwrap_or_default(),
+            });
+        }
+
+        debug!("Creating check run '{}' on {}", name, head_sha);
+        client
+            .post(
+                format!(
+                    "/repos/{}/{}/check-runs",
+                    path_segment(&owner),
+                    path_segment(&repo)
+                ),
+                Some(&body),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to create check run '{}': {}", name, e))
+    }
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+/// Read the identifier of a `check_run` `requested_action` delivery from its
+/// raw `body`
+pub(crate) fn requested_action_identifier(event: &WebhookEvent, body: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Payload {
+        requested_action: RequestedAction,
+    }
+    #[derive(Deserialize)]
+    struct RequestedAction {
+        identifier: String,
+    }
+
+    match &event.specific {
+        WebhookEventPayload::CheckRun(payload)
+            if payload.action == CheckRunWebhookEventAction::RequestedAction =>
+        {
+            serde_json::from_slice::<Payload>(body)
+                .ok()
+                .map(|payload| payload.requested_action.identifier)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::middlewares::GITHUB_EVENT_HEADER;
+    use crate::testing::{repository, user, webhook_event, MockGitHub};
+    use crate::Octofer;
+    use axum::{routing::post, Json, Router};
+    use http::HeaderMap;
+    use std::sync::{Arc, Mutex};
+
+    fn merge_group_payload() -> Value {
+        json!({
+            "action": "checks_requested",
+            "merge_group": {
+                "head_sha": "abc123",
+                "head_ref": "refs/heads/gh-readonly-queue/main/pr-42-def456",
+                "base_sha": "def456",
+                "base_ref": "refs/heads/main",
+                "head_commit": { "id": "abc123", "message": "Merge pull request #42" },
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    fn check_suite_payload(head_branch: &str) -> Value {
+        json!({
+            "action": "requested",
+            "check_suite": { "head_branch": head_branch, "head_sha": "fff000" },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    #[test]
+    fn test_merge_group_accessors() {
+        let mock_context = |event, payload| Context::new(Some(webhook_event(event, payload)), None);
+
+        let context = mock_context("merge_group", merge_group_payload());
+        let group = context.merge_group().unwrap();
+        assert_eq!(group.base_ref, "refs/heads/main");
+        assert_eq!(group.pull_request_number(), Some(42));
+        assert!(context.is_merge_queue_event());
+        assert_eq!(context.head_sha().as_deref(), Some("abc123"));
+
+        let context = mock_context(
+            "check_suite",
+            check_suite_payload("gh-readonly-queue/main/pr-42-def456"),
+        );
+        assert!(context.merge_group().is_none());
+        assert!(context.is_merge_queue_event());
+        assert_eq!(context.head_sha().as_deref(), Some("fff000"));
+
+        let context = mock_context("check_suite", check_suite_payload("feature"));
+        assert!(!context.is_merge_queue_event());
+    }
+
+    #[tokio::test]
+    async fn test_create_check_run_on_merge_group() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/check-runs",
+            post(|Json(body): Json<Value>| async move {
+                Json(json!({
+                    "id": 1,
+                    "node_id": "CR_1",
+                    "details_url": null,
+                    "head_sha": body["head_sha"],
+                    "url": "https://api.github.com/repos/octofer/app/check-runs/1",
+                    "html_url": null,
+                    "conclusion": body["conclusion"],
+                    "output": {
+                        "title": null,
+                        "summary": null,
+                        "text": null,
+                        "annotations_count": 0,
+                        "annotations_url": "https://api.github.com/repos/octofer/app/check-runs/1/annotations",
+                    },
+                    "started_at": null,
+                    "completed_at": null,
+                    "name": body["name"],
+                    "pull_requests": [],
+                }))
+            }),
+        ))
+        .await;
+        let context = mock.context("merge_group", merge_group_payload());
+
+        let check_run = context
+            .create_check_run(
+                "ci",
+                CheckRunOptions {
+                    conclusion: Some(CheckRunConclusion::Success),
+                    summary: Some("All good".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(check_run.head_sha, "abc123");
+
+        let body = &mock.requests()[0].body;
+        assert_eq!(body["conclusion"], "success");
+        assert_eq!(
+            body["output"],
+            json!({ "title": "ci", "summary": "All good" })
+        );
+        assert!(body.get("status").is_none());
+    }
+
+    fn check_run_delivery(action: &str, identifier: Option<&str>) -> (HeaderMap, Vec<u8>) {
+        let mut payload = json!({
+            "action": action,
+            "check_run": { "id": 4, "name": "lint", "head_sha": "abc123" },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        });
+        if let Some(identifier) = identifier {
+            payload["requested_action"] = json!({ "identifier": identifier });
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert(GITHUB_EVENT_HEADER, "check_run".parse().unwrap());
+        (headers, serde_json::to_vec(&payload).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_check_actions_are_routed_by_identifier() {
+        let mut app = Octofer::new_default();
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        for identifier in ["fix-lint", "rerun-lint"] {
+            app.on_check_action(
+                identifier,
+                move |context: Context, ran: Arc<Mutex<Vec<&str>>>| async move {
+                    assert_eq!(context.requested_action_identifier(), Some(identifier));
+                    ran.lock().unwrap().push(identifier);
+                    Ok(())
+                },
+                ran.clone(),
+            )
+            .await;
+        }
+        app.on_check_suite_rerequested(
+            |_context: Context, ran: Arc<Mutex<Vec<&str>>>| async move {
+                ran.lock().unwrap().push("suite");
+                Ok(())
+            },
+            ran.clone(),
+        )
+        .await;
+
+        let dispatcher = app.dispatcher();
+        let deliver = |(headers, body): (HeaderMap, Vec<u8>)| async move {
+            let context = dispatcher.parse(&headers, &body).unwrap();
+            dispatcher.dispatch(context).await.unwrap();
+        };
+        deliver(check_run_delivery("requested_action", Some("rerun-lint"))).await;
+        deliver(check_run_delivery("requested_action", Some("unknown"))).await;
+        deliver(check_run_delivery("completed", None)).await;
+        assert_eq!(*ran.lock().unwrap(), ["rerun-lint"]);
+
+        let suite = |action: &str| {
+            let payload = json!({
+                "action": action,
+                "check_suite": { "id": 5, "head_sha": "abc123", "head_branch": "main" },
+                "repository": repository("octofer", "app"),
+                "sender": user("octocat"),
+            });
+            let mut headers = HeaderMap::new();
+            headers.insert(GITHUB_EVENT_HEADER, "check_suite".parse().unwrap());
+            (headers, serde_json::to_vec(&payload).unwrap())
+        };
+        deliver(suite("requested")).await;
+        deliver(suite("rerequested")).await;
+        assert_eq!(*ran.lock().unwrap(), ["rerun-lint", "suite"]);
+    }
+}
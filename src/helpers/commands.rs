@@ -0,0 +1,183 @@
+//! Comment command helpers
+//!
+//! Parse commands like `/label bug` from comments. The first line starting
+//! with `/` and a command name is the comment's command; lines of fenced code
+//! blocks and quotes are skipped, so quoting a command in a reply does not
+//! run it again. [`Context::command`] returns the command of an
+//! `issue_comment` event, and for edited comments only if the edit changed
+//! the command, see [`Context::body_meaningfully_changed`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_issue_comment(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let Some(command) = context.command() else {
+//!             return Ok(());
+//!         };
+//!         if command.name == "label" {
+//!             // ... add the labels of command.args
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use octocrab::models::webhook_events::payload::IssueCommentWebhookEventAction;
+use octocrab::models::webhook_events::WebhookEventPayload;
+
+use crate::Context;
+
+/// Command of a comment, e.g. `/label bug`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    /// Lowercased name of the command, without the `/`
+    pub name: String,
+    /// Whitespace-separated arguments following the name
+    pub args: Vec<String>,
+}
+
+impl Command {
+    /// Parse the first command of a comment body
+    ///
+    /// Returns `None` if no line outside code blocks and quotes starts with
+    /// `/` followed by a name of letters, digits, `-` or `_`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use octofer::helpers::commands::Command;
+    ///
+    /// let command = Command::parse("Thanks!\n/Label bug  triage").unwrap();
+    /// assert_eq!(command.name, "label");
+    /// assert_eq!(command.args, ["bug", "triage"]);
+    ///
+    /// assert_eq!(Command::parse("> /label bug\nAgreed"), None);
+    /// assert_eq!(Command::parse("See /docs/setup.md"), None);
+    /// ```
+    pub fn parse(body: &str) -> Option<Self> {
+        let mut fence: Option<&str> = None;
+        for line in body.lines().map(str::trim) {
+            if let Some(open) = fence {
+                if line.starts_with(open) {
+                    fence = None;
+                }
+                continue;
+            }
+            if line.starts_with("```") || line.starts_with("~~~") {
+                fence = Some(&line[..3]);
+                continue;
+            }
+            if let Some(command) = line.strip_prefix('/').and_then(Self::parse_line) {
+                return Some(command);
+            }
+        }
+        None
+    }
+
+    /// Parse a line following the `/` of a command
+    fn parse_line(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let name = words.next()?;
+        let valid = !line.starts_with(char::is_whitespace)
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| Self {
+            name: name.to_lowercase(),
+            args: words.map(str::to_string).collect(),
+        })
+    }
+}
+
+impl Context {
+    /// Get the command of the comment of an `issue_comment` event
+    ///
+    /// Edited comments only return a command if the edit changed it, e.g.
+    /// not for typos fixed in the rest of the comment. Returns `None` for
+    /// deleted comments, other events, and comments without a command.
+    pub fn command(&self) -> Option<Command> {
+        let WebhookEventPayload::IssueComment(payload) = &self.event.as_ref()?.specific else {
+            return None;
+        };
+        match payload.action {
+            IssueCommentWebhookEventAction::Created => {}
+            IssueCommentWebhookEventAction::Edited
+                if self.body_meaningfully_changed(Command::parse) => {}
+            _ => return None,
+        }
+        Command::parse(payload.comment.body.as_deref()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{comment, issue, repository, user, webhook_event};
+    use serde_json::{json, Value};
+
+    fn comment_event(action: &str, body: &str, changes: Value) -> Context {
+        let payload = json!({
+            "action": action,
+            "issue": issue("octofer", "app", 1),
+            "comment": comment(9, "maintainer", body),
+            "changes": changes,
+            "repository": repository("octofer", "app"),
+            "sender": user("maintainer"),
+        });
+        Context::new(Some(webhook_event("issue_comment", payload)), None)
+    }
+
+    #[test]
+    fn test_parse_skips_code_blocks() {
+        let body = "Try:\n```\n/deploy production\n```\n  /deploy staging --force\n/label bug";
+        assert_eq!(
+            Command::parse(body),
+            Some(Command {
+                name: "deploy".to_string(),
+                args: vec!["staging".to_string(), "--force".to_string()],
+            })
+        );
+        assert_eq!(Command::parse("/ deploy"), None);
+        assert_eq!(Command::parse(""), None);
+    }
+
+    #[test]
+    fn test_edited_comments_only_return_changed_commands() {
+        let created = comment_event("created", "/label bug\nThanks", Value::Null);
+        assert_eq!(created.command().unwrap().args, ["bug"]);
+
+        // A typo fixed outside the command
+        let typo = comment_event(
+            "edited",
+            "/label bug\nThanks!",
+            json!({ "body": { "from": "/label bug\nThnaks" } }),
+        );
+        assert_eq!(typo.command(), None);
+
+        let changed = comment_event(
+            "edited",
+            "/label bug enhancement\nThanks",
+            json!({ "body": { "from": "/label bug\nThanks" } }),
+        );
+        assert_eq!(changed.command().unwrap().args, ["bug", "enhancement"]);
+
+        // A command added by the edit
+        let added = comment_event(
+            "edited",
+            "Thanks\n/approve",
+            json!({ "body": { "from": "Thanks" } }),
+        );
+        assert_eq!(added.command().unwrap().name, "approve");
+
+        let deleted = comment_event("deleted", "/label bug", Value::Null);
+        assert_eq!(deleted.command(), None);
+    }
+}
@@ -0,0 +1,538 @@
+//! Comment helpers
+//!
+//! Status-reporting bots (coverage, preview URLs, checklists) should keep one
+//! sticky comment up to date rather than posting a new comment per event.
+//! [`Context::upsert_comment`] finds the app's comment carrying a hidden
+//! marker and edits it, or creates it if there is none.
+//!
+//! When several handlers react to the same delivery, each posting its own
+//! comment floods the conversation. Handlers can instead add a section with
+//! [`Context::queue_comment`]; once all handlers of the delivery ran, the
+//! queued sections are combined into the app's sticky comment with the
+//! [`QUEUED_COMMENT_MARKER`], and the outcome of each section is included in
+//! the [delivery report](crate::webhook::report::DeliveryReport).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_pull_request(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         context
+//!             .upsert_comment("preview", "Preview deployed to https://preview.example.com")
+//!             .await?;
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use octocrab::models::issues::Comment;
+use octocrab::Octocrab;
+use serde_json::json;
+use tracing::{debug, error, warn};
+
+use crate::Context;
+
+/// Marker of the sticky comment queued comment sections are posted in
+///
+/// See [`Context::queue_comment`].
+pub const QUEUED_COMMENT_MARKER: &str = "queued-comments";
+
+/// Comment sections queued by the handlers of one delivery
+///
+/// Cloning is cheap; clones share the same sections, so every handler
+/// context of a delivery adds to the same queue.
+#[derive(Debug, Clone, Default)]
+pub struct CommentQueue {
+    sections: Arc<Mutex<Vec<(String, String)>>>,
+    /// Whether a handler in [dry-run mode](crate::github::layers::dry_run)
+    /// queued a section
+    dry_run: Arc<AtomicBool>,
+}
+
+impl CommentQueue {
+    /// Whether no section is queued
+    pub fn is_empty(&self) -> bool {
+        self.sections.lock().unwrap().is_empty()
+    }
+
+    /// Whether a handler in [dry-run mode](crate::github::layers::dry_run)
+    /// queued a section, so the combined comment is not posted
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.load(Ordering::Relaxed)
+    }
+
+    /// Get the names and contents of the queued sections, in queue order
+    pub fn sections(&self) -> Vec<(String, String)> {
+        self.sections.lock().unwrap().clone()
+    }
+
+    /// Queue `markdown` as section `name`, replacing a section of that name
+    fn push(&self, name: &str, markdown: &str) {
+        let mut sections = self.sections.lock().unwrap();
+        match sections.iter_mut().find(|(section, _)| section == name) {
+            Some((_, content)) => *content = markdown.to_string(),
+            None => sections.push((name.to_string(), markdown.to_string())),
+        }
+    }
+
+    /// Remove and return all queued sections
+    fn take(&self) -> Vec<(String, String)> {
+        std::mem::take(&mut *self.sections.lock().unwrap())
+    }
+}
+
+/// Outcome of posting one comment section queued with [`Context::queue_comment`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedSectionResult {
+    /// Name of the section
+    pub section: String,
+    /// Error posting the section failed with, if any
+    pub error: Option<String>,
+}
+
+impl Context {
+    /// Create or update the app's comment identified by `marker`
+    ///
+    /// The comment is written to the issue or pull request of the event, with
+    /// `<!-- octofer:{marker} -->` appended to `body`. An existing comment is
+    /// only reused if it was authored by the app (identified by the app slug,
+    /// or by being a bot comment if the slug is unknown).
+    ///
+    /// When two deliveries race to create the comment, the duplicates are
+    /// detected after creation: the oldest comment is kept and updated, the
+    /// others are deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue or pull request, no
+    /// installation client is available, or a request fails.
+    pub async fn upsert_comment(&self, marker: &str, body: &str) -> Result<Comment> {
+        let (owner, repo) = self.require_repository()?;
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+
+        let tag = format!("<!-- octofer:{} -->", marker);
+        let body = format!("{}\n\n{}", body, tag);
+        let comments = StickyComments {
+            client: &client,
+            owner: &owner,
+            repo: &repo,
+            number,
+            tag: &tag,
+            app_login: self.github_client.as_ref().and_then(|c| c.app_login()),
+        };
+
+        if let Some(existing) = comments.find().await?.into_iter().next() {
+            return comments.update(existing, &body).await;
+        }
+
+        let created: Comment = client
+            .post(
+                format!("/repos/{}/{}/issues/{}/comments", owner, repo, number),
+                Some(&json!({ "body": body })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to create comment on #{}: {}", number, e))?;
+        debug!("Created comment {} on #{}", created.id, number);
+
+        // Another delivery may have created the comment concurrently. The
+        // oldest comment wins, so all racing deliveries agree on which to keep.
+        let mut found = comments.find().await?;
+        if found.is_empty() {
+            return Ok(created);
+        }
+
+        let kept = found.remove(0);
+        if !found.is_empty() {
+            warn!(
+                "Found {} duplicate '{}' comment(s) on #{}, keeping {}",
+                found.len(),
+                marker,
+                number,
+                kept.id
+            );
+        }
+        for duplicate in found {
+            comments.delete(&duplicate).await?;
+        }
+
+        if kept.id == created.id {
+            Ok(created)
+        } else {
+            comments.update(kept, &body).await
+        }
+    }
+
+    /// Queue `markdown` as section `section` of the delivery's combined comment
+    ///
+    /// Instead of posting a comment per handler, the sections queued by all
+    /// handlers of a delivery are posted together once the handlers ran, in
+    /// the order they were first queued, as the sticky comment with the
+    /// [`QUEUED_COMMENT_MARKER`] (see [`Context::upsert_comment`]). Queuing a
+    /// section again replaces its content. Once a handler in
+    /// [dry-run mode](crate::github::layers::dry_run) queued a section, the
+    /// combined comment is recorded as an intended action instead.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut app: Octofer) {
+    /// app.on_pull_request(
+    ///     |context: Context, _extra: Arc<()>| async move {
+    ///         context.queue_comment("coverage", "Coverage: 87% (+0.4%)");
+    ///         Ok(())
+    ///     },
+    ///     Arc::new(()),
+    /// )
+    /// .await;
+    /// app.on_pull_request(
+    ///     |context: Context, _extra: Arc<()>| async move {
+    ///         context.queue_comment("preview", "Preview: https://preview.example.com");
+    ///         Ok(())
+    ///     },
+    ///     Arc::new(()),
+    /// )
+    /// .await;
+    /// // Both sections end up in a single comment
+    /// # }
+    /// ```
+    pub fn queue_comment(&self, section: &str, markdown: &str) {
+        debug!("Queuing comment section '{}'", section);
+        if self.dry_run.is_some() {
+            self.comment_queue.dry_run.store(true, Ordering::Relaxed);
+        }
+        self.comment_queue.push(section, markdown);
+    }
+
+    /// Post the queued comment sections as one combined comment
+    ///
+    /// Returns the outcome of each section; the queue is emptied either way.
+    pub(crate) async fn flush_comment_queue(&self) -> Vec<QueuedSectionResult> {
+        let sections = self.comment_queue.take();
+        if sections.is_empty() {
+            return Vec::new();
+        }
+
+        let body = sections
+            .iter()
+            .map(|(_, markdown)| markdown.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let error = match self.upsert_comment(QUEUED_COMMENT_MARKER, &body).await {
+            Ok(comment) => {
+                debug!(
+                    "Posted {} queued comment section(s) in comment {}",
+                    sections.len(),
+                    comment.id
+                );
+                None
+            }
+            Err(e) => {
+                error!("Failed to post queued comment sections: {:#}", e);
+                Some(format!("{:#}", e))
+            }
+        };
+
+        sections
+            .into_iter()
+            .map(|(section, _)| QueuedSectionResult {
+                section,
+                error: error.clone(),
+            })
+            .collect()
+    }
+}
+
+/// The app's comments carrying a marker on one issue or pull request
+struct StickyComments<'a> {
+    client: &'a Octocrab,
+    owner: &'a str,
+    repo: &'a str,
+    number: u64,
+    tag: &'a str,
+    app_login: Option<String>,
+}
+
+impl StickyComments<'_> {
+    /// Find the app's comments with the marker, oldest first
+    async fn find(&self) -> Result<Vec<Comment>> {
+        let mut found = Vec::new();
+        for page in 1u32.. {
+            let batch: Vec<Comment> = self
+                .client
+                .get(
+                    format!(
+                        "/repos/{}/{}/issues/{}/comments",
+                        self.owner, self.repo, self.number
+                    ),
+                    Some(&json!({ "per_page": 100, "page": page })),
+                )
+                .await
+                .map_err(|e| anyhow!("Failed to list comments of #{}: {}", self.number, e))?;
+
+            let done = batch.len() < 100;
+            found.extend(batch.into_iter().filter(|c| self.is_sticky(c)));
+            if done {
+                break;
+            }
+        }
+
+        found.sort_by_key(|c| c.id);
+        Ok(found)
+    }
+
+    fn is_sticky(&self, comment: &Comment) -> bool {
+        let authored_by_app = match &self.app_login {
+            Some(login) => comment.user.login == *login,
+            None => comment.user.r#type == "Bot",
+        };
+        authored_by_app
+            && comment
+                .body
+                .as_deref()
+                .is_some_and(|b| b.contains(self.tag))
+    }
+
+    async fn update(&self, comment: Comment, body: &str) -> Result<Comment> {
+        if comment.body.as_deref() == Some(body) {
+            debug!("Comment {} is up to date", comment.id);
+            return Ok(comment);
+        }
+
+        self.client
+            .patch(
+                format!(
+                    "/repos/{}/{}/issues/comments/{}",
+                    self.owner, self.repo, comment.id
+                ),
+                Some(&json!({ "body": body })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to update comment {}: {}", comment.id, e))
+    }
+
+    async fn delete(&self, comment: &Comment) -> Result<()> {
+        let response = self
+            .client
+            ._delete(
+                format!(
+                    "/repos/{}/{}/issues/comments/{}",
+                    self.owner, self.repo, comment.id
+                ),
+                None::<&()>,
+            )
+            .await?;
+        octocrab::map_github_error(response)
+            .await
+            .map_err(|e| anyhow!("Failed to delete comment {}: {}", comment.id, e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::Dispatcher;
+    use crate::testing::{comment, issues_payload, webhook_event, MockGitHub};
+    use axum::{
+        extract::{Path, State},
+        http::StatusCode,
+        routing::{get, patch},
+        Json, Router,
+    };
+    use serde_json::Value;
+
+    type Comments = Arc<Mutex<Vec<Value>>>;
+
+    /// Mock of the issue comment endpoints backed by an in-memory list
+    fn routes(comments: Comments) -> Router {
+        Router::new()
+            .route(
+                "/repos/octofer/app/issues/1/comments",
+                get(|State(comments): State<Comments>| async move {
+                    Json(Value::Array(comments.lock().unwrap().clone()))
+                })
+                .post(
+                    |State(comments): State<Comments>, Json(body): Json<Value>| async move {
+                        let mut comments = comments.lock().unwrap();
+                        let id = 100 + comments.len() as u64;
+                        let created =
+                            comment(id, "octofer-test[bot]", body["body"].as_str().unwrap());
+                        comments.push(created.clone());
+                        Json(created)
+                    },
+                ),
+            )
+            .route(
+                "/repos/octofer/app/issues/comments/{id}",
+                patch(
+                    |State(comments): State<Comments>,
+                     Path(id): Path<u64>,
+                     Json(body): Json<Value>| async move {
+                        let mut comments = comments.lock().unwrap();
+                        let existing = comments.iter_mut().find(|c| c["id"] == id).unwrap();
+                        existing["body"] = body["body"].clone();
+                        Json(existing.clone())
+                    },
+                )
+                .delete(
+                    |State(comments): State<Comments>, Path(id): Path<u64>| async move {
+                        comments.lock().unwrap().retain(|c| c["id"] != id);
+                        StatusCode::NO_CONTENT
+                    },
+                ),
+            )
+            .with_state(comments)
+    }
+
+    fn methods(mock: &MockGitHub) -> Vec<String> {
+        mock.requests()
+            .iter()
+            .map(|r| r.method.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_upsert_creates_then_updates() {
+        let comments = Comments::default();
+        // A user quoting the marker must not be mistaken for the app
+        comments
+            .lock()
+            .unwrap()
+            .push(comment(1, "someone", "<!-- octofer:coverage -->"));
+        let mock = MockGitHub::start(routes(comments.clone())).await;
+        let context = mock.context("issues", issues_payload("opened", 1));
+
+        let created = context
+            .upsert_comment("coverage", "Coverage: 80%")
+            .await
+            .unwrap();
+        assert_eq!(
+            created.body.as_deref(),
+            Some("Coverage: 80%\n\n<!-- octofer:coverage -->")
+        );
+        assert_eq!(methods(&mock), ["GET", "POST", "GET"]);
+
+        let updated = context
+            .upsert_comment("coverage", "Coverage: 85%")
+            .await
+            .unwrap();
+        assert_eq!(updated.id, created.id);
+        assert_eq!(
+            updated.body.as_deref(),
+            Some("Coverage: 85%\n\n<!-- octofer:coverage -->")
+        );
+        assert_eq!(methods(&mock)[3..], ["GET", "PATCH"]);
+        assert_eq!(comments.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_upsert_removes_concurrent_duplicates() {
+        let comments = Comments::default();
+        let mock = MockGitHub::start(routes(comments.clone())).await;
+        let context = mock.context("issues", issues_payload("opened", 1));
+
+        let (first, second) = tokio::join!(
+            context.upsert_comment("preview", "A"),
+            context.upsert_comment("preview", "B"),
+        );
+        first.unwrap();
+        second.unwrap();
+
+        let remaining = comments.lock().unwrap().clone();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0]["id"], 100);
+    }
+
+    #[tokio::test]
+    async fn test_queued_sections_are_posted_as_one_comment() {
+        let comments = Comments::default();
+        let mock = MockGitHub::start(routes(comments.clone())).await;
+        let dispatcher = Dispatcher::new(None);
+        for section in ["coverage", "preview"] {
+            dispatcher
+                .on(
+                    "issues",
+                    move |context: Context, _extra: Arc<()>| async move {
+                        context.queue_comment(section, &format!("{} report", section));
+                        Ok(())
+                    },
+                    Arc::new(()),
+                )
+                .await;
+        }
+
+        let report = dispatcher
+            .dispatch(mock.context("issues", issues_payload("opened", 1)))
+            .await
+            .unwrap();
+        assert_eq!(
+            report.comment_sections,
+            [
+                QueuedSectionResult {
+                    section: "coverage".to_string(),
+                    error: None
+                },
+                QueuedSectionResult {
+                    section: "preview".to_string(),
+                    error: None
+                },
+            ]
+        );
+
+        let posts: Vec<_> = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST")
+            .collect();
+        assert_eq!(posts.len(), 1);
+        let body = posts[0].body["body"].as_str().unwrap();
+        assert!(body.starts_with("coverage report\n\npreview report"));
+        assert!(body.contains(QUEUED_COMMENT_MARKER));
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_is_reported_per_section() {
+        let dispatcher = Dispatcher::new(None);
+        dispatcher
+            .on(
+                "issues",
+                |context: Context, _extra: Arc<()>| async move {
+                    context.queue_comment("a", "A");
+                    context.queue_comment("b", "B");
+                    context.queue_comment("a", "A again");
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await;
+
+        // Without a GitHub client, the sections cannot be posted
+        let context = Context::new(
+            Some(webhook_event("issues", issues_payload("opened", 1))),
+            None,
+        );
+        let report = dispatcher.dispatch(context).await.unwrap();
+        let sections: Vec<_> = report
+            .comment_sections
+            .iter()
+            .map(|s| s.section.as_str())
+            .collect();
+        assert_eq!(sections, ["a", "b"]);
+        assert!(report.comment_sections.iter().all(|s| s.error.is_some()));
+    }
+}
@@ -0,0 +1,456 @@
+//! Comparing commits and the changed files of pushes
+//!
+//! The `commits` of a `push` payload are capped at 20, and their file lists
+//! are omitted for large pushes. [`Context::compare`] compares two commits
+//! with `GET /repos/{owner}/{repo}/compare/{base}...{head}`, following its
+//! pages, and [`Context::push_changed_files`] uses it to list every file
+//! changed by a push:
+//!
+//! - for pushes to existing branches, the files changed between the push's
+//!   `before` and `after` commits;
+//! - for pushes creating a branch, whose `before` is all zeros, every file of
+//!   the `after` commit's tree, as [`FileStatus::Added`];
+//! - for pushes deleting a branch, no files.
+//!
+//! GitHub lists at most 300 files per comparison, see
+//! [`Comparison::files_truncated`]. Results are cached for the delivery and
+//! shared by all its handlers.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_push(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         for file in context.push_changed_files().await? {
+//!             println!("{:?} {}", file.status, file.filename);
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Number of commits per page of the compare API
+const COMMITS_PER_PAGE: usize = 100;
+
+/// Maximum number of pages of a comparison that are fetched
+const MAX_COMPARE_PAGES: usize = 30;
+
+/// Maximum number of files GitHub lists for a comparison
+pub const MAX_COMPARE_FILES: usize = 300;
+
+/// SHA GitHub sends as `before` of pushes creating a branch, and as `after`
+/// of pushes deleting one
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// Comparison of two commits
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Comparison {
+    /// How the head relates to the base: `ahead`, `behind`, `identical` or
+    /// `diverged`
+    pub status: String,
+    /// Number of commits the head has that the base does not
+    pub ahead_by: u64,
+    /// Number of commits the base has that the head does not
+    pub behind_by: u64,
+    /// Number of commits in the comparison
+    pub total_commits: u64,
+    /// SHAs of the commits in the comparison, oldest first
+    pub commits: Vec<String>,
+    /// Files changed between the base and the head
+    pub files: Vec<ChangedFile>,
+    /// Whether more files changed than GitHub lists, see [`MAX_COMPARE_FILES`]
+    pub files_truncated: bool,
+}
+
+/// File changed between two commits
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ChangedFile {
+    /// Path of the file
+    pub filename: String,
+    /// Path of the file before it was renamed or copied
+    #[serde(default)]
+    pub previous_filename: Option<String>,
+    /// How the file changed
+    pub status: FileStatus,
+    /// Number of added lines
+    #[serde(default)]
+    pub additions: u64,
+    /// Number of deleted lines
+    #[serde(default)]
+    pub deletions: u64,
+    /// Number of changed lines
+    #[serde(default)]
+    pub changes: u64,
+}
+
+/// How a file changed between two commits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    /// The file was created
+    Added,
+    /// The file was deleted
+    Removed,
+    /// The file's contents changed
+    Modified,
+    /// The file was moved, see [`ChangedFile::previous_filename`]
+    Renamed,
+    /// The file was copied, see [`ChangedFile::previous_filename`]
+    Copied,
+    /// The file's mode or type changed
+    Changed,
+    /// The file did not change
+    Unchanged,
+    /// Status not known to this version of Octofer
+    #[serde(other)]
+    Other,
+}
+
+/// Comparisons of a delivery by base and head
+type Comparisons = HashMap<(String, String), Arc<OnceCell<Comparison>>>;
+
+/// Comparisons and push files of a delivery, fetched at most once
+///
+/// Shared by the contexts of all handlers of a delivery.
+#[derive(Clone, Debug, Default)]
+pub struct ComparisonCache {
+    comparisons: Arc<Mutex<Comparisons>>,
+    push_files: Arc<OnceCell<Vec<ChangedFile>>>,
+}
+
+impl ComparisonCache {
+    /// Get the cell of the comparison of `base` and `head`
+    fn cell(&self, base: &str, head: &str) -> Arc<OnceCell<Comparison>> {
+        self.comparisons
+            .lock()
+            .expect("comparison cache lock poisoned")
+            .entry((base.to_string(), head.to_string()))
+            .or_default()
+            .clone()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawComparison {
+    status: String,
+    ahead_by: u64,
+    behind_by: u64,
+    total_commits: u64,
+    #[serde(default)]
+    commits: Vec<RawCommit>,
+    #[serde(default)]
+    files: Vec<ChangedFile>,
+}
+
+#[derive(Deserialize)]
+struct RawCommit {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct RawTree {
+    tree: Vec<RawTreeEntry>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct RawTreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+impl Context {
+    /// Compare the commits `base` and `head` of the event's repository
+    ///
+    /// `base` and `head` are commit SHAs or branch names. Follows the pages
+    /// of the comparison to list all its commits, up to 3000. The result is
+    /// cached for the delivery.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, or a request fails.
+    pub async fn compare(&self, base: &str, head: &str) -> Result<Comparison> {
+        self.comparison_cache
+            .cell(base, head)
+            .get_or_try_init(|| self.fetch_comparison(base, head))
+            .await
+            .cloned()
+    }
+
+    /// Get every file changed by the event's push
+    ///
+    /// See the [module documentation](self) for pushes creating or deleting a
+    /// branch. The result is cached for the delivery.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not a push, no installation client is
+    /// available, or a request fails.
+    pub async fn push_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        self.comparison_cache
+            .push_files
+            .get_or_try_init(|| self.fetch_push_changed_files())
+            .await
+            .cloned()
+    }
+
+    async fn fetch_push_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        let (before, after) = match self.event.as_ref().map(|event| &event.specific) {
+            Some(WebhookEventPayload::Push(payload)) => (&payload.before, &payload.after),
+            _ => return Err(anyhow!("Event is not a push")),
+        };
+
+        if after == NULL_SHA {
+            return Ok(Vec::new());
+        }
+        if before == NULL_SHA {
+            let (owner, repo) = self.require_repository()?;
+            let client = self.require_installation_client().await?;
+            return tree_files(&client, &owner, &repo, after).await;
+        }
+        let comparison = self.compare(before, after).await?;
+        if comparison.files_truncated {
+            warn!(
+                "Push {}...{} changes more than {} files, listing the first ones",
+                before, after, MAX_COMPARE_FILES
+            );
+        }
+        Ok(comparison.files)
+    }
+
+    async fn fetch_comparison(&self, base: &str, head: &str) -> Result<Comparison> {
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/compare/{}...{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            path_segment(base),
+            path_segment(head)
+        );
+
+        let mut comparison = Comparison::default();
+        for page in 1..=MAX_COMPARE_PAGES {
+            let raw: RawComparison = client
+                .get(
+                    &route,
+                    Some(&json!({ "per_page": COMMITS_PER_PAGE, "page": page })),
+                )
+                .await
+                .map_err(|e| anyhow!("Failed to compare {}...{}: {}", base, head, e))?;
+            comparison.status = raw.status;
+            comparison.ahead_by = raw.ahead_by;
+            comparison.behind_by = raw.behind_by;
+            comparison.total_commits = raw.total_commits;
+
+            let full = raw.commits.len() == COMMITS_PER_PAGE;
+            comparison
+                .commits
+                .extend(raw.commits.into_iter().map(|commit| commit.sha));
+            for file in raw.files {
+                if !comparison
+                    .files
+                    .iter()
+                    .any(|listed| listed.filename == file.filename)
+                {
+                    comparison.files.push(file);
+                }
+            }
+            if !full || comparison.commits.len() as u64 >= comparison.total_commits {
+                break;
+            }
+        }
+        comparison.files_truncated = comparison.files.len() >= MAX_COMPARE_FILES;
+
+        debug!(
+            "Comparison {}...{}: {} commit(s), {} file(s)",
+            base,
+            head,
+            comparison.commits.len(),
+            comparison.files.len()
+        );
+        Ok(comparison)
+    }
+}
+
+/// List the files of the tree of commit `sha` as added files
+async fn tree_files(
+    client: &Octocrab,
+    owner: &str,
+    repo: &str,
+    sha: &str,
+) -> Result<Vec<ChangedFile>> {
+    let tree: RawTree = client
+        .get(
+            format!(
+                "/repos/{}/{}/git/trees/{}",
+                path_segment(owner),
+                path_segment(repo),
+                path_segment(sha)
+            ),
+            Some(&json!({ "recursive": 1 })),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to list the tree of {}: {}", sha, e))?;
+    if tree.truncated {
+        warn!("Tree of {} is too large to be listed completely", sha);
+    }
+
+    Ok(tree
+        .tree
+        .into_iter()
+        .filter(|entry| entry.kind == "blob")
+        .map(|entry| ChangedFile {
+            filename: entry.path,
+            previous_filename: None,
+            status: FileStatus::Added,
+            additions: 0,
+            deletions: 0,
+            changes: 0,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{push_payload, MockGitHub};
+    use axum::{extract::Query, routing::get, Json, Router};
+    use serde_json::Value;
+
+    fn sha(i: usize) -> String {
+        format!("{:040x}", i + 1)
+    }
+
+    /// Payload of a push of `commits` commits, of which GitHub sent 20
+    fn truncated_push(commits: usize) -> Value {
+        let mut payload = push_payload(&sha(commits));
+        payload["before"] = json!(sha(0));
+        payload["commits"] = (commits - 20..commits)
+            .map(|i| {
+                json!({
+                    "id": sha(i),
+                    "tree_id": sha(i),
+                    "distinct": true,
+                    "message": format!("Commit {i}"),
+                    "timestamp": "2024-01-01T00:00:00Z",
+                    "url": format!("https://github.com/octofer/app/commit/{}", sha(i)),
+                    "author": { "name": "octocat", "email": "octocat@github.com" },
+                    "committer": { "name": "octocat", "email": "octocat@github.com" },
+                })
+            })
+            .collect();
+        payload
+    }
+
+    #[tokio::test]
+    async fn test_truncated_push_is_compared_across_pages() {
+        let route = format!("/repos/octofer/app/compare/{}...{}", sha(0), sha(130));
+        let mock = MockGitHub::start(Router::new().route(
+            &route,
+            get(|Query(query): Query<HashMap<String, String>>| async move {
+                let page: usize = query["page"].parse().unwrap();
+                let commits: Vec<Value> = ((page - 1) * 100..(page * 100).min(130))
+                    .map(|i| json!({ "sha": sha(i + 1) }))
+                    .collect();
+                // GitHub lists the files of the whole comparison on the pages
+                let files = json!([
+                    { "filename": "src/lib.rs", "status": "modified", "additions": 3, "deletions": 1, "changes": 4 },
+                    { "filename": "docs/new.md", "previous_filename": "docs/old.md", "status": "renamed" },
+                    { "filename": "build.rs", "status": "removed", "deletions": 12 },
+                ]);
+                Json(json!({
+                    "status": "ahead",
+                    "ahead_by": 130,
+                    "behind_by": 0,
+                    "total_commits": 130,
+                    "commits": commits,
+                    "files": if page == 1 { files } else { json!([files[0]]) },
+                }))
+            }),
+        ))
+        .await;
+        let context = mock.context("push", truncated_push(130));
+
+        let files = context.push_changed_files().await.unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, ["src/lib.rs", "docs/new.md", "build.rs"]);
+        assert_eq!(files[0].changes, 4);
+        assert_eq!(files[1].status, FileStatus::Renamed);
+        assert_eq!(files[1].previous_filename.as_deref(), Some("docs/old.md"));
+        assert_eq!(files[2].status, FileStatus::Removed);
+
+        let comparison = context.compare(&sha(0), &sha(130)).await.unwrap();
+        assert_eq!(comparison.commits.len(), 130);
+        assert_eq!(comparison.commits[129], sha(130));
+        assert!(!comparison.files_truncated);
+
+        // Both are fetched once per delivery
+        context.push_changed_files().await.unwrap();
+        assert_eq!(mock.requests().len(), 2);
+        assert_eq!(
+            mock.requests()[1].query.as_deref(),
+            Some("page=2&per_page=100")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_branch_lists_the_tree() {
+        let mock = MockGitHub::start(Router::new().route(
+            &format!("/repos/octofer/app/git/trees/{}", sha(1)),
+            get(|| async {
+                Json(json!({
+                    "sha": sha(1),
+                    "truncated": false,
+                    "tree": [
+                        { "path": "README.md", "type": "blob" },
+                        { "path": "src", "type": "tree" },
+                        { "path": "src/main.rs", "type": "blob" },
+                        { "path": "vendor/lib", "type": "commit" },
+                    ],
+                }))
+            }),
+        ))
+        .await;
+        // push_payload creates the branch: its `before` is all zeros
+        let context = mock.context("push", push_payload(&sha(1)));
+
+        let files = context.push_changed_files().await.unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, ["README.md", "src/main.rs"]);
+        assert!(files.iter().all(|f| f.status == FileStatus::Added));
+        assert_eq!(mock.requests()[0].query.as_deref(), Some("recursive=1"));
+
+        // Deleting a branch changes no files
+        let mut payload = push_payload(NULL_SHA);
+        payload["before"] = json!(sha(1));
+        payload["deleted"] = json!(true);
+        let context = mock.context("push", payload);
+        assert!(context.push_changed_files().await.unwrap().is_empty());
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
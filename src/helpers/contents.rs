@@ -0,0 +1,449 @@
+//! File contents at a commit
+//!
+//! [`Context::get_file`] reads a file of the event's repository at a ref,
+//! e.g. `Cargo.toml` at the head of a pull request. The contents of a file at
+//! a commit never change, so they are cached by repository, path and commit
+//! SHA in a bounded [`FileCache`] of the [`GitHubClient`], shared by all
+//! deliveries: reading the same file at the same commit from several
+//! handlers and events costs one `GET /repos/{owner}/{repo}/contents/{path}`
+//! request. Files that do not exist at a commit are cached too.
+//!
+//! Refs other than commit SHAs, e.g. branch names, are resolved to a commit
+//! SHA with `GET /repos/{owner}/{repo}/commits/{ref}` once per delivery, so a
+//! branch moving during the delivery does not change what its handlers read.
+//!
+//! The cache keeps the most recently read files, up to
+//! [`GitHubConfig::file_cache_entries`] (`OCTOFER_FILE_CACHE_ENTRIES`,
+//! default 1000). [`Context::get_file_uncached`] bypasses it, and
+//! [`GitHubClient::file_cache_stats`] counts its hits and misses.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_pull_request(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let Some(head) = context.head_sha() else {
+//!             return Ok(());
+//!         };
+//!         if let Some(manifest) = context.get_file("Cargo.toml", &head).await? {
+//!             println!("{} lines", manifest.lines().count());
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+//!
+//! [`GitHubClient`]: crate::github::GitHubClient
+//! [`GitHubClient::file_cache_stats`]: crate::github::GitHubClient::file_cache_stats
+//! [`GitHubConfig::file_cache_entries`]: crate::config::GitHubConfig::file_cache_entries
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+use crate::helpers::{fetch_optional_file_at, path_segment};
+use crate::Context;
+
+/// Default maximum number of files in a [`FileCache`]
+pub const DEFAULT_FILE_CACHE_ENTRIES: usize = 1000;
+
+/// Repository (`owner/name`, lowercase), path and commit SHA of a file
+type FileKey = (String, String, String);
+
+/// Counters of a [`FileCache`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileCacheStats {
+    /// Reads served from the cache
+    pub hits: u64,
+    /// Reads that were not served from the cache
+    pub misses: u64,
+    /// Number of cached files
+    pub entries: usize,
+}
+
+/// Bounded in-memory cache of file contents by commit, evicting the least
+/// recently read files
+#[derive(Debug)]
+pub struct FileCache {
+    max_entries: usize,
+    files: Mutex<CachedFiles>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct CachedFiles {
+    /// Contents, `None` for files that do not exist, and when they were last
+    /// read
+    entries: HashMap<FileKey, (Option<String>, u64)>,
+    /// Number of reads so far, ordering the entries by recency
+    clock: u64,
+}
+
+impl FileCache {
+    /// Create a cache of at most `max_entries` files; `0` disables it
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            files: Mutex::new(CachedFiles::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the cache's counters
+    pub fn stats(&self) -> FileCacheStats {
+        FileCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.lock().entries.len(),
+        }
+    }
+
+    /// Forget all cached files
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// Get the cached contents of the file `key`
+    ///
+    /// `Some(None)` if the file is known not to exist at the commit.
+    fn get(&self, key: &FileKey) -> Option<Option<String>> {
+        if self.max_entries == 0 {
+            return None;
+        }
+        let mut files = self.lock();
+        files.clock += 1;
+        let clock = files.clock;
+        match files.entries.get_mut(key) {
+            Some((content, last_read)) => {
+                *last_read = clock;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(content.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn insert(&self, key: FileKey, content: Option<String>) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut files = self.lock();
+        if !files.entries.contains_key(&key) && files.entries.len() >= self.max_entries {
+            let least_recent = files
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_read))| *last_read)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recent) = least_recent {
+                files.entries.remove(&least_recent);
+            }
+        }
+        files.clock += 1;
+        let clock = files.clock;
+        files.entries.insert(key, (content, clock));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CachedFiles> {
+        self.files.lock().expect("file cache lock poisoned")
+    }
+}
+
+/// Refs of a delivery resolved to commit SHAs, resolved at most once each
+///
+/// Shared by the contexts of all handlers of a delivery.
+#[derive(Clone, Debug, Default)]
+pub struct CommitRefCache(Arc<Mutex<HashMap<String, Arc<OnceCell<String>>>>>);
+
+impl CommitRefCache {
+    /// Get the cell of the commit SHA of `git_ref`
+    fn cell(&self, git_ref: &str) -> Arc<OnceCell<String>> {
+        self.0
+            .lock()
+            .expect("commit ref cache lock poisoned")
+            .entry(git_ref.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawCommit {
+    sha: String,
+}
+
+/// Whether `git_ref` is a full commit SHA
+fn is_commit_sha(git_ref: &str) -> bool {
+    git_ref.len() == 40 && git_ref.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl Context {
+    /// Get the UTF-8 contents of the file at `path` of the event's repository
+    /// at `git_ref`, or `None` if it does not exist there
+    ///
+    /// `git_ref` is a commit SHA, branch or tag name. It is resolved to a
+    /// commit SHA once per delivery, and the contents are cached by commit
+    /// for all deliveries, see the [module documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, `git_ref` does not exist, or a request fails.
+    pub async fn get_file(&self, path: &str, git_ref: &str) -> Result<Option<String>> {
+        let (owner, repo) = self.require_repository()?;
+        let sha = self.resolve_commit(git_ref).await?;
+        let key = (
+            format!("{}/{}", owner, repo).to_lowercase(),
+            path.to_string(),
+            sha,
+        );
+
+        let cache = self
+            .github_client
+            .as_ref()
+            .map(|github| github.file_cache());
+        if let Some(content) = cache.and_then(|cache| cache.get(&key)) {
+            debug!("Read {} at {} from the file cache", path, key.2);
+            return Ok(content);
+        }
+
+        let content = self.fetch_file_at(path, &key.2).await?;
+        if let Some(cache) = cache {
+            cache.insert(key, content.clone());
+        }
+        Ok(content)
+    }
+
+    /// Get the UTF-8 contents of the file at `path` of the event's repository
+    /// at `git_ref`, or `None` if it does not exist there, without the caches
+    /// of [`Context::get_file`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, or the request fails.
+    pub async fn get_file_uncached(&self, path: &str, git_ref: &str) -> Result<Option<String>> {
+        self.fetch_file_at(path, git_ref).await
+    }
+
+    /// Resolve `git_ref` of the event's repository to a commit SHA
+    ///
+    /// Commit SHAs are returned as they are; other refs are resolved once per
+    /// delivery.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, `git_ref` does not exist, or the request fails.
+    pub async fn resolve_commit(&self, git_ref: &str) -> Result<String> {
+        if is_commit_sha(git_ref) {
+            return Ok(git_ref.to_lowercase());
+        }
+        self.commit_ref_cache
+            .cell(git_ref)
+            .get_or_try_init(|| self.fetch_commit_sha(git_ref))
+            .await
+            .cloned()
+    }
+
+    async fn fetch_commit_sha(&self, git_ref: &str) -> Result<String> {
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/commits/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            path_segment(git_ref)
+        );
+        let commit: RawCommit = client
+            .get(&route, None::<&()>)
+            .await
+            .map_err(|e| anyhow!("Failed to resolve {}: {}", git_ref, e))?;
+        Ok(commit.sha)
+    }
+
+    async fn fetch_file_at(&self, path: &str, git_ref: &str) -> Result<Option<String>> {
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let repo_route = format!("/repos/{}/{}", path_segment(&owner), path_segment(&repo));
+        fetch_optional_file_at(&client, &repo_route, path, Some(git_ref)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pull_request_payload, webhook_event, MockGitHub, TEST_INSTALLATION_ID};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde_json::json;
+
+    const HEAD: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/repos/octofer/app/contents/Cargo.toml",
+                get(|| async {
+                    let content = STANDARD.encode("[package]\nname = \"app\"\n");
+                    Json(json!({ "encoding": "base64", "content": content }))
+                }),
+            )
+            .route(
+                "/repos/octofer/app/contents/build.rs",
+                get(|| async {
+                    (
+                        StatusCode::NOT_FOUND,
+                        Json(json!({ "message": "Not Found" })),
+                    )
+                }),
+            )
+            .route(
+                "/repos/octofer/app/commits/feature",
+                get(|| async { Json(json!({ "sha": HEAD })) }),
+            )
+    }
+
+    fn delivery(github: &Arc<crate::github::GitHubClient>) -> Context {
+        Context::with_github_client(
+            Some(webhook_event(
+                "pull_request",
+                pull_request_payload("synchronize", 1, HEAD),
+            )),
+            Some(TEST_INSTALLATION_ID),
+            Some(github.clone()),
+        )
+    }
+
+    fn calls(mock: &MockGitHub) -> Vec<String> {
+        mock.requests()
+            .into_iter()
+            .map(|r| match r.query {
+                Some(query) => format!("{}?{}", r.path, query),
+                None => r.path,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_same_commit_is_fetched_once_across_deliveries() {
+        let mock = MockGitHub::start(routes()).await;
+        let github = Arc::new(mock.client());
+
+        for _ in 0..2 {
+            let content = delivery(&github)
+                .get_file("Cargo.toml", HEAD)
+                .await
+                .unwrap();
+            assert_eq!(content.as_deref(), Some("[package]\nname = \"app\"\n"));
+        }
+        assert_eq!(
+            calls(&mock),
+            [format!(
+                "/repos/octofer/app/contents/Cargo.toml?ref={}",
+                HEAD
+            )]
+        );
+        assert_eq!(
+            github.file_cache_stats(),
+            FileCacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refs_are_resolved_once_per_delivery() {
+        let mock = MockGitHub::start(routes()).await;
+        let github = Arc::new(mock.client());
+
+        let context = delivery(&github);
+        assert!(context
+            .get_file("Cargo.toml", "feature")
+            .await
+            .unwrap()
+            .is_some());
+        assert!(context
+            .get_file("build.rs", "feature")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(context
+            .get_file("build.rs", "feature")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            calls(&mock),
+            [
+                "/repos/octofer/app/commits/feature".to_string(),
+                format!("/repos/octofer/app/contents/Cargo.toml?ref={}", HEAD),
+                format!("/repos/octofer/app/contents/build.rs?ref={}", HEAD),
+            ]
+        );
+
+        // A new delivery resolves the ref again, but reads the cached file
+        let context = delivery(&github);
+        assert!(context
+            .get_file("Cargo.toml", "feature")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(calls(&mock).len(), 4);
+
+        assert!(context
+            .get_file_uncached("Cargo.toml", "feature")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            calls(&mock).last().unwrap(),
+            "/repos/octofer/app/contents/Cargo.toml?ref=feature"
+        );
+    }
+
+    #[test]
+    fn test_file_cache_evicts_the_least_recently_read_file() {
+        let key = |path: &str| {
+            (
+                "octofer/app".to_string(),
+                path.to_string(),
+                HEAD.to_string(),
+            )
+        };
+        let cache = FileCache::new(2);
+        cache.insert(key("a"), Some("a".to_string()));
+        cache.insert(key("b"), None);
+        assert_eq!(cache.get(&key("a")), Some(Some("a".to_string())));
+
+        cache.insert(key("c"), Some("c".to_string()));
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("a")), Some(Some("a".to_string())));
+        assert_eq!(cache.stats().entries, 2);
+
+        let disabled = FileCache::new(0);
+        disabled.insert(key("a"), Some("a".to_string()));
+        assert_eq!(disabled.get(&key("a")), None);
+        assert_eq!(disabled.stats(), FileCacheStats::default());
+    }
+}
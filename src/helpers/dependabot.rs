@@ -0,0 +1,798 @@
+//! Dependabot alert helpers
+//!
+//! Typed access to the alert of `dependabot_alert` events, dismissing it, and
+//! listing the alerts of repositories, e.g. for a weekly triage job labeling
+//! repositories with critical alerts or dismissing alerts of development-only
+//! dependencies. [`GitHubClient::alerts_summary_for_installation`] counts the
+//! open alerts of every repository of an installation by severity.
+//!
+//! Reading and dismissing alerts requires the `vulnerability_alerts`
+//! permission, named "Dependabot alerts" in the app's settings. Requests
+//! GitHub refuses for lack of it fail with [`MissingPermission`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::dependabot::{DependabotDismissReason, DependencyScope};
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_dependabot_alert(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let Some(alert) = context.dependabot_alert() else {
+//!             return Ok(());
+//!         };
+//!         if alert.state == "open" && alert.scope == Some(DependencyScope::Development) {
+//!             context
+//!                 .dismiss_dependabot_alert(
+//!                     DependabotDismissReason::TolerableRisk,
+//!                     Some("Development-only dependency"),
+//!                 )
+//!                 .await?;
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use futures::stream::{self, StreamExt};
+use http::StatusCode;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::{Octocrab, Page};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::github::batch::list_repositories;
+use crate::github::GitHubClient;
+use crate::helpers::installation::MissingPermission;
+use crate::helpers::permissions::Permission;
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Name of the installation permission for Dependabot alerts
+pub const DEPENDABOT_PERMISSION: &str = "vulnerability_alerts";
+
+/// Alerts fetched per request
+const ALERTS_PER_PAGE: usize = 100;
+
+/// Repositories whose alerts are listed at the same time by
+/// [`GitHubClient::alerts_summary_for_installation`]
+pub const SUMMARY_CONCURRENCY: usize = 4;
+
+/// Severity of the advisory of a Dependabot alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependabotSeverity {
+    /// Low severity
+    Low,
+    /// Medium severity
+    Medium,
+    /// High severity
+    High,
+    /// Critical severity
+    Critical,
+}
+
+impl fmt::Display for DependabotSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+            Self::Critical => "critical",
+        };
+        f.write_str(name)
+    }
+}
+
+/// State of a Dependabot alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependabotAlertState {
+    /// The vulnerable dependency is still used
+    Open,
+    /// The alert was dismissed
+    Dismissed,
+    /// The dependency was upgraded or removed
+    Fixed,
+    /// The alert was dismissed by an auto-triage rule
+    AutoDismissed,
+}
+
+impl fmt::Display for DependabotAlertState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Open => "open",
+            Self::Dismissed => "dismissed",
+            Self::Fixed => "fixed",
+            Self::AutoDismissed => "auto_dismissed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Whether a dependency is needed at runtime or only for development
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyScope {
+    /// Needed only to develop or test the project
+    Development,
+    /// Needed at runtime
+    Runtime,
+}
+
+impl fmt::Display for DependencyScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Development => "development",
+            Self::Runtime => "runtime",
+        })
+    }
+}
+
+/// Reason a Dependabot alert is dismissed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependabotDismissReason {
+    /// A fix has already been started
+    FixStarted,
+    /// The alert is inaccurate or incorrect
+    Inaccurate,
+    /// No bandwidth to fix this
+    NoBandwidth,
+    /// The vulnerable code is not actually used
+    NotUsed,
+    /// The risk is tolerable for this repository
+    TolerableRisk,
+}
+
+impl fmt::Display for DependabotDismissReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::FixStarted => "fix_started",
+            Self::Inaccurate => "inaccurate",
+            Self::NoBandwidth => "no_bandwidth",
+            Self::NotUsed => "not_used",
+            Self::TolerableRisk => "tolerable_risk",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Dependabot alert, as sent in `dependabot_alert` events and listed by the
+/// alert endpoints
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawAlert")]
+pub struct DependabotAlert {
+    /// Number of the alert in its repository
+    pub number: u64,
+    /// `open`, `dismissed`, `fixed` or `auto_dismissed`
+    pub state: String,
+    /// Severity of the advisory
+    pub severity: Option<DependabotSeverity>,
+    /// Name of the vulnerable package
+    pub package: String,
+    /// Ecosystem of the package (e.g. `npm`, `pip`)
+    pub ecosystem: String,
+    /// Path of the manifest declaring the dependency
+    pub manifest_path: String,
+    /// Whether the dependency is needed at runtime, if known
+    pub scope: Option<DependencyScope>,
+    /// GHSA ID of the advisory
+    pub ghsa_id: Option<String>,
+    /// Summary of the advisory
+    pub summary: Option<String>,
+    /// URL of the alert on GitHub
+    pub html_url: Option<String>,
+    /// Why the alert was dismissed, if it is
+    pub dismissed_reason: Option<DependabotDismissReason>,
+}
+
+#[derive(Deserialize)]
+struct RawAlert {
+    number: u64,
+    state: String,
+    #[serde(default)]
+    dependency: Value,
+    #[serde(default)]
+    security_advisory: Value,
+    #[serde(default)]
+    security_vulnerability: Value,
+    #[serde(default)]
+    html_url: Option<String>,
+    #[serde(default)]
+    dismissed_reason: Option<DependabotDismissReason>,
+}
+
+impl From<RawAlert> for DependabotAlert {
+    fn from(raw: RawAlert) -> Self {
+        let string = |value: &Value| value.as_str().map(str::to_string);
+        let severity = [
+            &raw.security_vulnerability["severity"],
+            &raw.security_advisory["severity"],
+        ]
+        .into_iter()
+        .find_map(|severity| serde_json::from_value(severity.clone()).ok());
+
+        Self {
+            number: raw.number,
+            state: raw.state,
+            severity,
+            package: string(&raw.dependency["package"]["name"]).unwrap_or_default(),
+            ecosystem: string(&raw.dependency["package"]["ecosystem"]).unwrap_or_default(),
+            manifest_path: string(&raw.dependency["manifest_path"]).unwrap_or_default(),
+            scope: serde_json::from_value(raw.dependency["scope"].clone()).ok(),
+            ghsa_id: string(&raw.security_advisory["ghsa_id"]),
+            summary: string(&raw.security_advisory["summary"]),
+            html_url: raw.html_url,
+            dismissed_reason: raw.dismissed_reason,
+        }
+    }
+}
+
+/// Alerts to list with [`GitHubClient::list_dependabot_alerts`]
+///
+/// Empty lists match any state or severity.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependabotAlertFilters {
+    /// States of the alerts
+    pub states: Vec<DependabotAlertState>,
+    /// Severities of the alerts
+    pub severities: Vec<DependabotSeverity>,
+    /// Scope of the vulnerable dependencies, if any
+    pub scope: Option<DependencyScope>,
+}
+
+impl DependabotAlertFilters {
+    /// Match open alerts only
+    pub fn open() -> Self {
+        Self {
+            states: vec![DependabotAlertState::Open],
+            ..Self::default()
+        }
+    }
+
+    /// Query parameters of the filters
+    fn query(&self) -> Value {
+        let join = |values: Vec<String>| values.join(",");
+        let mut query = json!({ "per_page": ALERTS_PER_PAGE });
+        if !self.states.is_empty() {
+            query["state"] = json!(join(self.states.iter().map(|s| s.to_string()).collect()));
+        }
+        if !self.severities.is_empty() {
+            query["severity"] = json!(join(
+                self.severities.iter().map(|s| s.to_string()).collect()
+            ));
+        }
+        if let Some(scope) = self.scope {
+            query["scope"] = json!(scope.to_string());
+        }
+        query
+    }
+}
+
+/// Number of alerts per severity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AlertCounts {
+    /// Alerts of critical severity
+    pub critical: u64,
+    /// Alerts of high severity
+    pub high: u64,
+    /// Alerts of medium severity
+    pub medium: u64,
+    /// Alerts of low severity
+    pub low: u64,
+}
+
+impl AlertCounts {
+    /// Total number of alerts
+    pub fn total(&self) -> u64 {
+        self.critical + self.high + self.medium + self.low
+    }
+
+    fn count(&mut self, severity: Option<DependabotSeverity>) {
+        match severity {
+            Some(DependabotSeverity::Critical) => self.critical += 1,
+            Some(DependabotSeverity::High) => self.high += 1,
+            Some(DependabotSeverity::Medium) => self.medium += 1,
+            Some(DependabotSeverity::Low) | None => self.low += 1,
+        }
+    }
+
+    fn add(&mut self, other: &AlertCounts) {
+        self.critical += other.critical;
+        self.high += other.high;
+        self.medium += other.medium;
+        self.low += other.low;
+    }
+}
+
+/// Open Dependabot alerts of a repository by severity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepositoryAlerts {
+    /// Full name (`owner/name`) of the repository
+    pub repository: String,
+    /// Number of open alerts per severity
+    pub counts: AlertCounts,
+}
+
+/// Open Dependabot alerts of the repositories of an installation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependabotSummary {
+    /// Number of open alerts per severity across all repositories
+    pub totals: AlertCounts,
+    /// Repositories with at least one open alert, by full name
+    pub repositories: Vec<RepositoryAlerts>,
+    /// Repositories whose alerts could not be listed, e.g. because
+    /// Dependabot alerts are disabled, with the error
+    pub unavailable: Vec<(String, String)>,
+}
+
+impl DependabotSummary {
+    /// Repositories with at least one critical alert
+    pub fn with_critical(&self) -> impl Iterator<Item = &RepositoryAlerts> {
+        self.repositories.iter().filter(|r| r.counts.critical > 0)
+    }
+}
+
+impl Context {
+    /// Get the alert of a `dependabot_alert` event
+    ///
+    /// Returns `None` for other events.
+    pub fn dependabot_alert(&self) -> Option<DependabotAlert> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::DependabotAlert(payload) => {
+                parse_payload_part("Dependabot alert", &payload.alert)
+            }
+            _ => None,
+        }
+    }
+
+    /// Dismiss the event's Dependabot alert with `reason`
+    ///
+    /// Returns the updated alert.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the installation may not write
+    /// Dependabot alerts, or an error if the event is not a
+    /// `dependabot_alert` event, no installation client is available, or the
+    /// request fails.
+    pub async fn dismiss_dependabot_alert(
+        &self,
+        reason: DependabotDismissReason,
+        comment: Option<&str>,
+    ) -> Result<DependabotAlert> {
+        let alert = self
+            .dependabot_alert()
+            .ok_or_else(|| anyhow!("Event is not a Dependabot alert event"))?;
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/dependabot/alerts/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            alert.number
+        );
+
+        let mut body = json!({ "state": "dismissed", "dismissed_reason": reason });
+        if let Some(comment) = comment {
+            body["dismissed_comment"] = json!(comment);
+        }
+        debug!("Dismissing Dependabot alert {} as {}", route, reason);
+        client.patch(&route, Some(&body)).await.map_err(|e| {
+            permission_error(
+                e,
+                self.installation_id.unwrap_or_default(),
+                self.installation_access()
+                    .and_then(|access| access.permission(DEPENDABOT_PERMISSION)),
+                Permission::Write,
+                &format!("dismiss {}", route),
+            )
+        })
+    }
+}
+
+impl GitHubClient {
+    /// List the Dependabot alerts of repository `owner/repo` matching
+    /// `filters`, following all pages
+    ///
+    /// Uses the client of installation `installation_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the installation may not read
+    /// Dependabot alerts, or an error if a request fails, including for
+    /// repositories with Dependabot alerts disabled.
+    pub async fn list_dependabot_alerts(
+        &self,
+        installation_id: u64,
+        owner: &str,
+        repo: &str,
+        filters: &DependabotAlertFilters,
+    ) -> Result<Vec<DependabotAlert>> {
+        let client = self.installation_client(installation_id).await?;
+        self.dependabot_alerts(&client, installation_id, owner, repo, filters)
+            .await
+    }
+
+    /// Count the open Dependabot alerts of every repository accessible to
+    /// installation `installation_id` by severity
+    ///
+    /// Lists the alerts of up to [`SUMMARY_CONCURRENCY`] repositories at the
+    /// same time. Repositories whose alerts cannot be listed, e.g. because
+    /// Dependabot alerts are disabled for them, are reported in
+    /// [`DependabotSummary::unavailable`] instead of failing the summary.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the installation may not read
+    /// Dependabot alerts, or an error if its repositories cannot be listed.
+    pub async fn alerts_summary_for_installation(
+        &self,
+        installation_id: u64,
+    ) -> Result<DependabotSummary> {
+        let client = self.installation_client(installation_id).await?;
+        let repositories = list_repositories(&client).await?;
+        let filters = DependabotAlertFilters::open();
+
+        let results: Vec<(String, Result<Vec<DependabotAlert>>)> =
+            stream::iter(repositories.into_iter().filter_map(|repository| {
+                let owner = repository.owner?.login;
+                Some((owner, repository.name))
+            }))
+            .map(|(owner, repo)| {
+                let (client, filters) = (&client, &filters);
+                async move {
+                    let alerts = self
+                        .dependabot_alerts(client, installation_id, &owner, &repo, filters)
+                        .await;
+                    (format!("{}/{}", owner, repo), alerts)
+                }
+            })
+            .buffer_unordered(SUMMARY_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut summary = DependabotSummary::default();
+        for (repository, alerts) in results {
+            match alerts {
+                Ok(alerts) if alerts.is_empty() => {}
+                Ok(alerts) => {
+                    let mut counts = AlertCounts::default();
+                    for alert in &alerts {
+                        counts.count(alert.severity);
+                    }
+                    summary.totals.add(&counts);
+                    summary
+                        .repositories
+                        .push(RepositoryAlerts { repository, counts });
+                }
+                Err(e) if e.is::<MissingPermission>() => return Err(e),
+                Err(e) => {
+                    warn!("Skipping Dependabot alerts of {}: {}", repository, e);
+                    summary.unavailable.push((repository, e.to_string()));
+                }
+            }
+        }
+        summary
+            .repositories
+            .sort_by(|a, b| a.repository.cmp(&b.repository));
+        summary.unavailable.sort();
+        Ok(summary)
+    }
+
+    /// List the alerts of a repository with an installation client
+    async fn dependabot_alerts(
+        &self,
+        client: &Octocrab,
+        installation_id: u64,
+        owner: &str,
+        repo: &str,
+        filters: &DependabotAlertFilters,
+    ) -> Result<Vec<DependabotAlert>> {
+        let route = format!(
+            "/repos/{}/{}/dependabot/alerts",
+            path_segment(owner),
+            path_segment(repo)
+        );
+        let error = |e: octocrab::Error| {
+            permission_error(
+                e,
+                installation_id,
+                self.cached_installation(installation_id)
+                    .and_then(|access| access.permission(DEPENDABOT_PERMISSION)),
+                Permission::Read,
+                &format!("list {}", route),
+            )
+        };
+
+        // The alert endpoints paginate with cursors, linked as the next page
+        let mut page: Page<DependabotAlert> = client
+            .get(&route, Some(&filters.query()))
+            .await
+            .map_err(error)?;
+        let mut alerts = page.take_items();
+        while let Some(next) = client.get_page(&page.next).await.map_err(error)? {
+            page = next;
+            alerts.extend(page.take_items());
+        }
+        Ok(alerts)
+    }
+}
+
+/// Turn a refused Dependabot alert request into [`MissingPermission`]
+///
+/// GitHub also refuses requests with `403` for repositories with Dependabot
+/// alerts disabled, which are reported as is.
+fn permission_error(
+    error: octocrab::Error,
+    installation_id: u64,
+    granted: Option<Permission>,
+    required: Permission,
+    what: &str,
+) -> anyhow::Error {
+    match &error {
+        octocrab::Error::GitHub { source, .. }
+            if source.status_code == StatusCode::FORBIDDEN
+                && !source.message.contains("disabled") =>
+        {
+            MissingPermission {
+                installation_id,
+                permission: DEPENDABOT_PERMISSION.to_string(),
+                required,
+                granted,
+            }
+            .into()
+        }
+        _ => anyhow!("Failed to {}: {}", what, error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, webhook_event, MockGitHub};
+    use axum::extract::{Path, Query};
+    use axum::http::header::LINK;
+    use axum::response::IntoResponse;
+    use axum::routing::{get, patch};
+    use axum::{Json, Router};
+    use std::collections::HashMap;
+
+    fn alert(number: u64, severity: &str, scope: &str) -> Value {
+        json!({
+            "number": number,
+            "state": "open",
+            "dependency": {
+                "package": { "ecosystem": "npm", "name": format!("package-{number}") },
+                "manifest_path": "package-lock.json",
+                "scope": scope,
+            },
+            "security_advisory": {
+                "ghsa_id": format!("GHSA-0000-0000-{number:04}"),
+                "summary": "Prototype pollution",
+                "severity": severity,
+            },
+            "security_vulnerability": { "severity": severity },
+            "html_url": format!("https://github.com/octofer/app/security/dependabot/{number}"),
+            "dismissed_reason": null,
+        })
+    }
+
+    fn alert_payload() -> Value {
+        json!({
+            "action": "created",
+            "alert": alert(3, "critical", "development"),
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    #[test]
+    fn test_dependabot_alert() {
+        let context = Context::new(
+            Some(webhook_event("dependabot_alert", alert_payload())),
+            None,
+        );
+        let alert = context.dependabot_alert().unwrap();
+        assert_eq!(alert.number, 3);
+        assert_eq!(alert.state, "open");
+        assert_eq!(alert.severity, Some(DependabotSeverity::Critical));
+        assert_eq!(alert.package, "package-3");
+        assert_eq!(alert.ecosystem, "npm");
+        assert_eq!(alert.manifest_path, "package-lock.json");
+        assert_eq!(alert.scope, Some(DependencyScope::Development));
+        assert_eq!(alert.ghsa_id.as_deref(), Some("GHSA-0000-0000-0003"));
+
+        let context = Context::new(
+            Some(webhook_event(
+                "issues",
+                crate::testing::issues_payload("opened", 1),
+            )),
+            None,
+        );
+        assert!(context.dependabot_alert().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_alerts_with_filters_follows_cursors() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/dependabot/alerts",
+            get(|Query(query): Query<HashMap<String, String>>| async move {
+                let (alerts, next) = match query.get("after").map(String::as_str) {
+                    None => ((1..=100).collect::<Vec<u64>>(), Some("cursor-1")),
+                    Some("cursor-1") => (vec![101, 102], None),
+                    Some(_) => (vec![], None),
+                };
+                let alerts: Vec<Value> = alerts
+                    .into_iter()
+                    .map(|n| alert(n, "high", "runtime"))
+                    .collect();
+                let mut response = Json(alerts).into_response();
+                if let Some(cursor) = next {
+                    let link = format!(
+                        "</repos/octofer/app/dependabot/alerts?per_page=100&after={cursor}>; rel=\"next\""
+                    );
+                    response.headers_mut().insert(LINK, link.parse().unwrap());
+                }
+                response
+            }),
+        ))
+        .await;
+
+        let filters = DependabotAlertFilters {
+            states: vec![DependabotAlertState::Open, DependabotAlertState::Dismissed],
+            severities: vec![DependabotSeverity::Critical, DependabotSeverity::High],
+            scope: Some(DependencyScope::Runtime),
+        };
+        let alerts = mock
+            .client()
+            .list_dependabot_alerts(1, "octofer", "app", &filters)
+            .await
+            .unwrap();
+        assert_eq!(alerts.len(), 102);
+        assert_eq!(alerts[101].number, 102);
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        let query = requests[0].query.as_deref().unwrap();
+        assert!(query.contains("state=open%2Cdismissed"));
+        assert!(query.contains("severity=critical%2Chigh"));
+        assert!(query.contains("scope=runtime"));
+        assert_eq!(
+            requests[1].query.as_deref(),
+            Some("per_page=100&after=cursor-1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dismiss_and_missing_permission() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/dependabot/alerts/3",
+                    patch(|Json(body): Json<Value>| async move {
+                        let mut alert = alert(3, "critical", "development");
+                        alert["state"] = body["state"].clone();
+                        alert["dismissed_reason"] = body["dismissed_reason"].clone();
+                        Json(alert)
+                    }),
+                )
+                .route(
+                    "/repos/octofer/locked/dependabot/alerts",
+                    get(|| async {
+                        (
+                            StatusCode::FORBIDDEN,
+                            Json(json!({ "message": "Resource not accessible by integration" })),
+                        )
+                    }),
+                ),
+        )
+        .await;
+        let context = mock.context("dependabot_alert", alert_payload());
+
+        let alert = context
+            .dismiss_dependabot_alert(DependabotDismissReason::TolerableRisk, Some("Dev only"))
+            .await
+            .unwrap();
+        assert_eq!(alert.state, "dismissed");
+        assert_eq!(
+            alert.dismissed_reason,
+            Some(DependabotDismissReason::TolerableRisk)
+        );
+        assert_eq!(
+            mock.requests()[0].body,
+            json!({
+                "state": "dismissed",
+                "dismissed_reason": "tolerable_risk",
+                "dismissed_comment": "Dev only",
+            })
+        );
+
+        let err = mock
+            .client()
+            .list_dependabot_alerts(1, "octofer", "locked", &DependabotAlertFilters::open())
+            .await
+            .unwrap_err();
+        let missing = err.downcast_ref::<MissingPermission>().unwrap();
+        assert_eq!(missing.permission, DEPENDABOT_PERMISSION);
+        assert_eq!(missing.required, Permission::Read);
+    }
+
+    #[tokio::test]
+    async fn test_alerts_summary_for_installation() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/installation/repositories",
+                    get(|| async {
+                        Json(json!({
+                            "total_count": 3,
+                            "repositories": [
+                                repository("octofer", "api"),
+                                repository("octofer", "web"),
+                                repository("octofer", "docs"),
+                            ],
+                        }))
+                    }),
+                )
+                .route(
+                    "/repos/octofer/{repo}/dependabot/alerts",
+                    get(|Path(repo): Path<String>| async move {
+                        match repo.as_str() {
+                            "api" => Json(json!([
+                                alert(1, "critical", "runtime"),
+                                alert(2, "high", "runtime"),
+                                alert(3, "high", "development"),
+                            ]))
+                            .into_response(),
+                            "web" => Json(json!([alert(1, "low", "runtime")])).into_response(),
+                            _ => (
+                                StatusCode::FORBIDDEN,
+                                Json(json!({
+                                    "message": "Dependabot alerts are disabled for this repository."
+                                })),
+                            )
+                                .into_response(),
+                        }
+                    }),
+                ),
+        )
+        .await;
+
+        let summary = mock
+            .client()
+            .alerts_summary_for_installation(1)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary.totals,
+            AlertCounts {
+                critical: 1,
+                high: 2,
+                medium: 0,
+                low: 1,
+            }
+        );
+        assert_eq!(summary.totals.total(), 4);
+        let repositories: Vec<&str> = summary
+            .repositories
+            .iter()
+            .map(|r| r.repository.as_str())
+            .collect();
+        assert_eq!(repositories, ["octofer/api", "octofer/web"]);
+        let critical: Vec<_> = summary.with_critical().collect();
+        assert_eq!(critical.len(), 1);
+        assert_eq!(summary.unavailable.len(), 1);
+        assert_eq!(summary.unavailable[0].0, "octofer/docs");
+    }
+}
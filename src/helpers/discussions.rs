@@ -0,0 +1,366 @@
+//! Discussion helpers
+//!
+//! Typed accessors for `discussion` and `discussion_comment` webhook payloads,
+//! and helpers to moderate the discussion of an event. Discussions are only
+//! writable through the GraphQL API, so the helpers use
+//! [`graphql::query`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_discussion_comment(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let Some(comment) = context.discussion_comment() else {
+//!             return Ok(());
+//!         };
+//!
+//!         if comment.body.trim() == "/answer" {
+//!             context.mark_comment_as_answer().await?;
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::params::LockReason;
+use serde_json::json;
+use tracing::debug;
+
+use crate::github::graphql;
+use crate::github::models::{Discussion, DiscussionCategory, DiscussionComment};
+use crate::helpers::parse_payload_part;
+use crate::Context;
+
+const MARK_AS_ANSWER: &str = r#"
+mutation($id: ID!) {
+  markDiscussionCommentAsAnswer(input: { id: $id }) {
+    discussion { id }
+  }
+}"#;
+
+const REPLY_TO: &str = r#"
+query($id: ID!) {
+  node(id: $id) {
+    ... on DiscussionComment { replyTo { id } }
+  }
+}"#;
+
+const LABEL_ID: &str = r#"
+query($owner: String!, $name: String!, $label: String!) {
+  repository(owner: $owner, name: $name) {
+    label(name: $label) { id }
+  }
+}"#;
+
+const ADD_LABELS: &str = r#"
+mutation($labelable: ID!, $labels: [ID!]!) {
+  addLabelsToLabelable(input: { labelableId: $labelable, labelIds: $labels }) {
+    clientMutationId
+  }
+}"#;
+
+const LOCK: &str = r#"
+mutation($lockable: ID!, $reason: LockReason) {
+  lockLockable(input: { lockableId: $lockable, lockReason: $reason }) {
+    clientMutationId
+  }
+}"#;
+
+impl Context {
+    /// Get the discussion of a `discussion` or `discussion_comment` event
+    ///
+    /// Returns `None` for other events.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::Context;
+    ///
+    /// async fn handler(context: Context) -> anyhow::Result<()> {
+    ///     if let Some(discussion) = context.discussion() {
+    ///         println!("#{} {} by {}", discussion.number, discussion.title, discussion.user.login);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn discussion(&self) -> Option<Discussion> {
+        let discussion = match &self.event.as_ref()?.specific {
+            WebhookEventPayload::Discussion(payload) => &payload.discussion,
+            WebhookEventPayload::DiscussionComment(payload) => &payload.discussion,
+            _ => return None,
+        };
+        parse_payload_part("discussion", discussion)
+    }
+
+    /// Get the comment of a `discussion_comment` event
+    ///
+    /// Returns `None` for other events.
+    pub fn discussion_comment(&self) -> Option<DiscussionComment> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::DiscussionComment(payload) => {
+                parse_payload_part("discussion comment", &payload.comment)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the category of the event's discussion
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::Context;
+    ///
+    /// async fn handler(context: Context) -> anyhow::Result<()> {
+    ///     if let Some(category) = context.discussion_category() {
+    ///         if category.slug == "q-a" {
+    ///             context.add_discussion_label("question").await?;
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn discussion_category(&self) -> Option<DiscussionCategory> {
+        self.discussion().map(|d| d.category)
+    }
+
+    /// Mark the comment of a `discussion_comment` event as the discussion's answer
+    ///
+    /// GitHub only accepts top-level comments as answers. If the event's comment
+    /// is a reply (e.g. the author replying `/answer` to a suggestion), the
+    /// comment it replies to is marked instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not a `discussion_comment` event, no
+    /// installation client is available, or the GraphQL mutation fails (for
+    /// example because the category is not answerable).
+    pub async fn mark_comment_as_answer(&self) -> Result<()> {
+        let comment = self
+            .discussion_comment()
+            .ok_or_else(|| anyhow!("Event is not a discussion comment event"))?;
+        let client = self.require_installation_client().await?;
+
+        let answer_id = if comment.parent_id.is_some() {
+            let data = graphql::query(&client, REPLY_TO, json!({ "id": comment.node_id })).await?;
+            data["node"]["replyTo"]["id"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("Could not resolve parent of comment {}", comment.id))?
+        } else {
+            comment.node_id
+        };
+
+        debug!("Marking discussion comment {} as answer", answer_id);
+        graphql::query(&client, MARK_AS_ANSWER, json!({ "id": answer_id })).await?;
+        Ok(())
+    }
+
+    /// Add an existing repository label to the event's discussion
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no discussion, the label does not
+    /// exist in the repository, or the GraphQL request fails.
+    pub async fn add_discussion_label(&self, label: &str) -> Result<()> {
+        let discussion = self
+            .discussion()
+            .ok_or_else(|| anyhow!("Event has no discussion"))?;
+        let (owner, name) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+
+        let data = graphql::query(
+            &client,
+            LABEL_ID,
+            json!({ "owner": owner, "name": name, "label": label }),
+        )
+        .await?;
+        let label_id = data["repository"]["label"]["id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Label '{}' not found in {}/{}", label, owner, name))?;
+
+        graphql::query(
+            &client,
+            ADD_LABELS,
+            json!({ "labelable": discussion.node_id, "labels": [label_id] }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Lock the event's discussion, optionally giving a reason
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no discussion or the GraphQL request fails.
+    pub async fn lock_discussion(&self, reason: Option<LockReason>) -> Result<()> {
+        let discussion = self
+            .discussion()
+            .ok_or_else(|| anyhow!("Event has no discussion"))?;
+        let client = self.require_installation_client().await?;
+
+        let reason = reason.map(graphql_lock_reason).transpose()?;
+        graphql::query(
+            &client,
+            LOCK,
+            json!({ "lockable": discussion.node_id, "reason": reason }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// Map a REST lock reason to its GraphQL `LockReason` enum value
+fn graphql_lock_reason(reason: LockReason) -> Result<&'static str> {
+    match reason {
+        LockReason::OffTopic => Ok("OFF_TOPIC"),
+        LockReason::TooHeated => Ok("TOO_HEATED"),
+        LockReason::Resolved => Ok("RESOLVED"),
+        LockReason::Spam => Ok("SPAM"),
+        other => Err(anyhow!("Unsupported lock reason: {:?}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, MockGitHub};
+    use axum::{routing::post, Json, Router};
+    use serde_json::Value;
+
+    fn discussion_json() -> Value {
+        json!({
+            "id": 10,
+            "node_id": "D_10",
+            "number": 3,
+            "title": "How do I configure the bot?",
+            "body": "Help",
+            "user": user("op"),
+            "category": {
+                "id": 5,
+                "node_id": "DIC_5",
+                "name": "Q&A",
+                "slug": "q-a",
+                "emoji": ":pray:",
+                "description": "Ask the community",
+                "is_answerable": true,
+            },
+            "state": "open",
+            "locked": false,
+            "comments": 2,
+            "html_url": "https://github.com/octofer/app/discussions/3",
+            "answer_html_url": null,
+        })
+    }
+
+    fn comment_payload(parent_id: Option<u64>) -> Value {
+        json!({
+            "action": "created",
+            "comment": {
+                "id": 20,
+                "node_id": "DC_20",
+                "body": "/answer",
+                "user": user("op"),
+                "parent_id": parent_id,
+                "html_url": "https://github.com/octofer/app/discussions/3#discussioncomment-20",
+            },
+            "discussion": discussion_json(),
+            "repository": repository("octofer", "app"),
+            "sender": user("op"),
+        })
+    }
+
+    /// Stub GraphQL endpoint answering every query with canned data
+    fn graphql_stub() -> Router {
+        Router::new().route(
+            "/graphql",
+            post(|Json(body): Json<Value>| async move {
+                let query = body["query"].as_str().unwrap_or_default();
+                let data = if query.contains("replyTo") {
+                    json!({ "node": { "replyTo": { "id": "DC_PARENT" } } })
+                } else if query.contains("label(name") {
+                    json!({ "repository": { "label": { "id": "LA_1" } } })
+                } else {
+                    json!({})
+                };
+                Json(json!({ "data": data }))
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_typed_discussion_accessors() {
+        let mock = MockGitHub::start(Router::new()).await;
+        let context = mock.context("discussion_comment", comment_payload(None));
+
+        let discussion = context.discussion().unwrap();
+        assert_eq!(discussion.number, 3);
+        assert_eq!(discussion.user.login, "op");
+        assert_eq!(context.discussion_category().unwrap().slug, "q-a");
+        assert_eq!(context.discussion_comment().unwrap().body, "/answer");
+    }
+
+    #[tokio::test]
+    async fn test_mark_reply_marks_parent_as_answer() {
+        let mock = MockGitHub::start(graphql_stub()).await;
+        let context = mock.context("discussion_comment", comment_payload(Some(19)));
+
+        context.mark_comment_as_answer().await.unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|r| r.method == "POST" && r.path == "/graphql"));
+        assert_eq!(requests[0].headers["authorization"], "Bearer ghs_test");
+        assert_eq!(requests[0].body["variables"]["id"], "DC_20");
+        assert!(requests[1].body["query"]
+            .as_str()
+            .unwrap()
+            .contains("markDiscussionCommentAsAnswer"));
+        assert_eq!(requests[1].body["variables"]["id"], "DC_PARENT");
+    }
+
+    #[tokio::test]
+    async fn test_add_label_and_lock_discussion() {
+        let mock = MockGitHub::start(graphql_stub()).await;
+        let context = mock.context("discussion_comment", comment_payload(None));
+
+        context.add_discussion_label("question").await.unwrap();
+        context
+            .lock_discussion(Some(LockReason::Resolved))
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].body["variables"]["label"], "question");
+        assert_eq!(requests[1].body["variables"]["labelable"], "D_10");
+        assert_eq!(requests[1].body["variables"]["labels"], json!(["LA_1"]));
+        assert_eq!(requests[2].body["variables"]["reason"], "RESOLVED");
+    }
+
+    #[tokio::test]
+    async fn test_graphql_errors_are_surfaced() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/graphql",
+            post(|| async {
+                Json(json!({ "data": null, "errors": [{ "message": "not answerable" }] }))
+            }),
+        ))
+        .await;
+        let context = mock.context("discussion_comment", comment_payload(None));
+
+        let err = context.mark_comment_as_answer().await.unwrap_err();
+        assert!(err.to_string().contains("not answerable"));
+    }
+}
@@ -0,0 +1,243 @@
+//! Edit helpers
+//!
+//! `edited` deliveries of `issue_comment`, `issues` and `pull_request` events
+//! are sent for any change, e.g. a typo fixed in text a handler does not care
+//! about. [`Context::changes`] returns the previous body and title, and
+//! [`Context::body_meaningfully_changed`] compares the relevant part of the
+//! old and new body, e.g. a parsed command, so handlers only act again when
+//! that part changed. [`Context::command`] does so for comment commands.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! /// Checklist items of a body
+//! fn checklist(body: &str) -> Vec<String> {
+//!     body.lines()
+//!         .filter(|line| line.starts_with("- ["))
+//!         .map(str::to_string)
+//!         .collect()
+//! }
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_issue(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if !context.body_meaningfully_changed(checklist) {
+//!             return Ok(());
+//!         }
+//!         // ... sync the checklist
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use octocrab::models::webhook_events::payload::{
+    IssueCommentWebhookEventAction, IssuesWebhookEventAction, PullRequestWebhookEventAction,
+};
+use octocrab::models::webhook_events::{WebhookEvent, WebhookEventPayload};
+use serde::Deserialize;
+
+use crate::helpers::labels::from;
+use crate::Context;
+
+/// Previous values of an edited comment, issue or pull request
+///
+/// Fields are only set for the values that changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct EditChanges {
+    /// Previous body
+    #[serde(default, deserialize_with = "from")]
+    pub body: Option<String>,
+    /// Previous title of an issue or pull request
+    #[serde(default, deserialize_with = "from")]
+    pub title: Option<String>,
+}
+
+impl Context {
+    /// Get the previous body and title of an `edited` `issue_comment`,
+    /// `issues` or `pull_request` event
+    ///
+    /// Returns `None` for other events and actions, and for pull request
+    /// edits whose context was not parsed from a delivery, since octocrab
+    /// leaves the changes out of `pull_request` payloads.
+    pub fn changes(&self) -> Option<EditChanges> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::IssueComment(payload)
+                if payload.action == IssueCommentWebhookEventAction::Edited =>
+            {
+                Some(EditChanges {
+                    body: payload
+                        .changes
+                        .as_ref()
+                        .map(|changes| changes.body.from.clone()),
+                    title: None,
+                })
+            }
+            WebhookEventPayload::Issues(payload)
+                if payload.action == IssuesWebhookEventAction::Edited =>
+            {
+                let changes = payload.changes.as_ref();
+                Some(EditChanges {
+                    body: changes
+                        .and_then(|changes| changes.body.as_ref())
+                        .map(|body| body.from.clone()),
+                    title: changes
+                        .and_then(|changes| changes.title.as_ref())
+                        .map(|title| title.from.clone()),
+                })
+            }
+            WebhookEventPayload::PullRequest(payload)
+                if payload.action == PullRequestWebhookEventAction::Edited =>
+            {
+                self.pull_request_changes.clone()
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the part of the body `extractor` maps it to changed
+    ///
+    /// For `edited` `issue_comment`, `issues` and `pull_request` events, the
+    /// extractions of the previous and new body are compared, and edits
+    /// keeping the body, e.g. of the title, did not change it. Other events
+    /// and actions, and edits whose previous body is unknown, count as
+    /// changed, so handlers can call this for every delivery.
+    pub fn body_meaningfully_changed<T, F>(&self, extractor: F) -> bool
+    where
+        T: PartialEq,
+        F: Fn(&str) -> T,
+    {
+        let Some(body) = self.edited_body() else {
+            return true;
+        };
+        match self.changes() {
+            Some(EditChanges {
+                body: Some(old), ..
+            }) => extractor(&old) != extractor(body),
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    /// Get the current body of an `edited` event with [`Context::changes`]
+    fn edited_body(&self) -> Option<&str> {
+        let body = match &self.event.as_ref()?.specific {
+            WebhookEventPayload::IssueComment(payload)
+                if payload.action == IssueCommentWebhookEventAction::Edited =>
+            {
+                &payload.comment.body
+            }
+            WebhookEventPayload::Issues(payload)
+                if payload.action == IssuesWebhookEventAction::Edited =>
+            {
+                &payload.issue.body
+            }
+            WebhookEventPayload::PullRequest(payload)
+                if payload.action == PullRequestWebhookEventAction::Edited =>
+            {
+                &payload.pull_request.body
+            }
+            _ => return None,
+        };
+        Some(body.as_deref().unwrap_or_default())
+    }
+}
+
+/// Read the changes of an `edited` `pull_request` delivery from its raw
+/// `body`
+pub(crate) fn pull_request_changes(event: &WebhookEvent, body: &[u8]) -> Option<EditChanges> {
+    #[derive(Deserialize)]
+    struct Payload {
+        #[serde(default)]
+        changes: EditChanges,
+    }
+
+    match &event.specific {
+        WebhookEventPayload::PullRequest(payload)
+            if payload.action == PullRequestWebhookEventAction::Edited =>
+        {
+            serde_json::from_slice::<Payload>(body)
+                .ok()
+                .map(|payload| payload.changes)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::Dispatcher;
+    use crate::github::middlewares::GITHUB_EVENT_HEADER;
+    use crate::testing::{issues_payload, pull_request_payload, webhook_event};
+    use axum::http::HeaderMap;
+    use serde_json::json;
+
+    fn first_word(body: &str) -> Option<String> {
+        body.split_whitespace().next().map(str::to_lowercase)
+    }
+
+    #[test]
+    fn test_issue_edits() {
+        let edited = |changes| {
+            let mut payload = issues_payload("edited", 3);
+            payload["issue"]["body"] = json!("Deploy to staging, please");
+            payload["changes"] = changes;
+            Context::new(Some(webhook_event("issues", payload)), None)
+        };
+
+        // A typo fixed after the first word
+        let context = edited(json!({ "body": { "from": "Deploy to stagign, please" } }));
+        assert_eq!(
+            context.changes().unwrap().body.as_deref(),
+            Some("Deploy to stagign, please")
+        );
+        assert!(!context.body_meaningfully_changed(first_word));
+        assert!(context.body_meaningfully_changed(str::to_string));
+
+        let context = edited(json!({ "body": { "from": "Revert staging" } }));
+        assert!(context.body_meaningfully_changed(first_word));
+
+        // Only the title changed
+        let context = edited(json!({ "title": { "from": "Old title" } }));
+        assert_eq!(
+            context.changes(),
+            Some(EditChanges {
+                body: None,
+                title: Some("Old title".to_string()),
+            })
+        );
+        assert!(!context.body_meaningfully_changed(str::to_string));
+
+        let opened = Context::new(
+            Some(webhook_event("issues", issues_payload("opened", 3))),
+            None,
+        );
+        assert_eq!(opened.changes(), None);
+        assert!(opened.body_meaningfully_changed(first_word));
+    }
+
+    #[test]
+    fn test_pull_request_changes_are_read_from_the_delivery() {
+        let mut payload = pull_request_payload("edited", 5, "abc123");
+        payload["pull_request"]["body"] = json!("Fixes #1");
+        payload["changes"] = json!({ "body": { "from": "Fixes #2" } });
+        let body = serde_json::to_vec(&payload).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(GITHUB_EVENT_HEADER, "pull_request".parse().unwrap());
+
+        let context = Dispatcher::new(None).parse(&headers, &body).unwrap();
+        assert_eq!(context.changes().unwrap().body.as_deref(), Some("Fixes #2"));
+        assert!(context.body_meaningfully_changed(str::to_string));
+
+        // Without the delivery, the previous body is unknown
+        let context = Context::new(Some(webhook_event("pull_request", payload)), None);
+        assert_eq!(context.changes(), None);
+        assert!(context.body_meaningfully_changed(str::to_string));
+    }
+}
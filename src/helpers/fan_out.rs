@@ -0,0 +1,305 @@
+//! Running a handler once per repository of an event
+//!
+//! `installation` and `installation_repositories` events carry a list of
+//! repositories rather than a single `repository`, so the repository helpers
+//! of [`Context`] do not work for them. [`Context::for_each_repository`] runs
+//! a closure for each repository in scope, with a derived context whose
+//! event has its `repository` set. Derived contexts share the event's
+//! installation and GitHub client, so their installation clients and helpers
+//! act on the repository as usual.
+//!
+//! Failures are collected in an [`IterationSummary`], like
+//! [`GitHubClient::for_each_repository`](crate::github::GitHubClient::for_each_repository)
+//! does for app-wide iterations.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_installation_repositories(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let summary = context
+//!             .for_each_repository(4, |context| async move {
+//!                 let repository = context.repository().expect("set by for_each_repository");
+//!                 println!("Provisioning {}", repository.name);
+//!                 Ok(())
+//!             })
+//!             .await?;
+//!         if !summary.is_success() {
+//!             anyhow::bail!("{} repositories failed", summary.failures.len());
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::future::Future;
+
+use anyhow::{anyhow, Result};
+use futures::stream::{self, StreamExt};
+use octocrab::models::webhook_events::{
+    EventInstallation, InstallationEventRepository, WebhookEventPayload,
+};
+use octocrab::models::Repository;
+use serde_json::json;
+
+use crate::github::batch::IterationSummary;
+use crate::Context;
+
+impl Context {
+    /// Get the event's repository, if it has one
+    ///
+    /// Set in the contexts passed by [`Context::for_each_repository`].
+    pub fn repository(&self) -> Option<&Repository> {
+        self.event.as_ref()?.repository.as_ref()
+    }
+
+    /// Get the repositories the event is about
+    ///
+    /// These are the repositories of the installation for `installation`
+    /// events, the added repositories for `installation_repositories` events,
+    /// and the event's repository for other events. Repositories of
+    /// installation events only have their ID, names, visibility, owner and
+    /// URLs set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, or the account owning
+    /// the repositories of an installation event is missing.
+    pub fn affected_repositories(&self) -> Result<Vec<Repository>> {
+        let event = self
+            .event
+            .as_ref()
+            .ok_or_else(|| anyhow!("Context has no event"))?;
+        let listed = match &event.specific {
+            WebhookEventPayload::Installation(payload) => {
+                payload.repositories.clone().unwrap_or_default()
+            }
+            WebhookEventPayload::InstallationRepositories(payload) => {
+                payload.repositories_added.clone()
+            }
+            _ => {
+                return event
+                    .repository
+                    .clone()
+                    .map(|repository| vec![repository])
+                    .ok_or_else(|| anyhow!("Event has no repository"));
+            }
+        };
+
+        let Some(EventInstallation::Full(installation)) = &event.installation else {
+            return Err(anyhow!("Event has no installation account"));
+        };
+        let owner = serde_json::to_value(&installation.account)?;
+        listed
+            .into_iter()
+            .map(|repository| to_repository(repository, &owner))
+            .collect()
+    }
+
+    /// Run `f` with a derived context for each repository the event is about
+    ///
+    /// See [`Context::affected_repositories`] for the repositories in scope.
+    /// At most `concurrency` repositories are processed at the same time.
+    /// Errors returned by `f` are collected per repository in the summary.
+    ///
+    /// For events with a single repository, `f` runs once with a clone of the
+    /// context.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the repositories cannot be determined.
+    pub async fn for_each_repository<F, Fut>(
+        &self,
+        concurrency: usize,
+        f: F,
+    ) -> Result<IterationSummary>
+    where
+        F: Fn(Context) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let listed = matches!(
+            self.event.as_ref().map(|event| &event.specific),
+            Some(
+                WebhookEventPayload::Installation(_)
+                    | WebhookEventPayload::InstallationRepositories(_)
+            )
+        );
+        let contexts: Vec<(String, Context)> = self
+            .affected_repositories()?
+            .into_iter()
+            .map(|repository| {
+                let name = repository
+                    .full_name
+                    .clone()
+                    .unwrap_or_else(|| repository.name.clone());
+                let context = if listed {
+                    self.for_repository(repository)
+                } else {
+                    self.clone()
+                };
+                (name, context)
+            })
+            .collect();
+
+        let f = &f;
+        let results = stream::iter(contexts)
+            .map(|(name, context)| async move { (name, f(context).await) })
+            .buffer_unordered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut summary = IterationSummary::default();
+        let installation_id = self.installation_id.unwrap_or_default();
+        for (name, result) in results {
+            summary.record(installation_id, Some(name), result);
+        }
+        Ok(summary)
+    }
+
+    /// Derive a context for one of the repositories of an installation event
+    ///
+    /// Per-delivery caches are not shared, since they belong to the event's
+    /// own resource.
+    fn for_repository(&self, repository: Repository) -> Context {
+        let mut event = self.event.clone();
+        if let Some(event) = &mut event {
+            event.repository = Some(repository);
+        }
+        Context {
+            event,
+            comment_queue: Default::default(),
+            report_notes: Default::default(),
+            pull_request_cache: Default::default(),
+            changed_files_cache: Default::default(),
+            comparison_cache: Default::default(),
+            commit_ref_cache: Default::default(),
+            matched_files: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// Complete a repository of an installation event into a [`Repository`]
+fn to_repository(
+    repository: InstallationEventRepository,
+    owner: &serde_json::Value,
+) -> Result<Repository> {
+    let full_name = repository.full_name;
+    serde_json::from_value(json!({
+        "id": repository.id,
+        "node_id": repository.node_id,
+        "name": repository.name,
+        "full_name": full_name,
+        "private": repository.private,
+        "owner": owner,
+        "url": format!("https://api.github.com/repos/{}", full_name),
+        "html_url": format!("https://github.com/{}", full_name),
+    }))
+    .map_err(|e| anyhow!("Failed to build repository {}: {}", full_name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{push_payload, user, MockGitHub};
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    fn installation_repositories_payload(names: &[&str]) -> Value {
+        let repositories: Vec<Value> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                json!({
+                    "id": i + 1,
+                    "node_id": format!("R_{}", i + 1),
+                    "name": name,
+                    "full_name": format!("octofer-org/{}", name),
+                    "private": i % 2 == 1,
+                })
+            })
+            .collect();
+        json!({
+            "action": "added",
+            "installation": {
+                "id": 1,
+                "account": user("octofer-org"),
+                "permissions": {},
+                "events": [],
+            },
+            "repositories_added": repositories,
+            "repositories_removed": [],
+            "repository_selection": "selected",
+            "requester": null,
+            "sender": user("octocat"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_for_each_repository_derives_contexts() {
+        let mock = MockGitHub::start(axum::Router::new()).await;
+        let context = mock.context(
+            "installation_repositories",
+            installation_repositories_payload(&["api", "web", "docs"]),
+        );
+        let seen = Mutex::new(Vec::new());
+
+        let summary = context
+            .for_each_repository(2, |context| {
+                let seen = &seen;
+                async move {
+                    let (owner, repo) = context.require_repository()?;
+                    assert_eq!(context.repository().unwrap().private, Some(repo == "web"));
+                    assert!(context.installation_client().await?.is_some());
+                    seen.lock().unwrap().push(format!("{}/{}", owner, repo));
+                    if repo == "web" {
+                        anyhow::bail!("boom");
+                    }
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort();
+        assert_eq!(
+            seen,
+            ["octofer-org/api", "octofer-org/docs", "octofer-org/web"]
+        );
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(
+            summary.failures[0].to_string(),
+            "Installation 1 (octofer-org/web): boom"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_for_each_repository_of_single_repository_events() {
+        let mock = MockGitHub::start(axum::Router::new()).await;
+        let context = mock.context("push", push_payload("abc"));
+        let summary = context
+            .for_each_repository(4, |context| async move {
+                context.require_repository().map(|_| ())
+            })
+            .await
+            .unwrap();
+        assert_eq!(summary.succeeded, 1);
+
+        let mut payload = installation_repositories_payload(&["api"]);
+        payload["installation"] = json!({ "id": 1, "node_id": "I_1" });
+        let context = mock.context("installation_repositories", payload);
+        assert!(context
+            .for_each_repository(4, |_| async { Ok(()) })
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Changed files of pull requests
+//!
+//! [`Context::changed_files`] lists the files changed by the event's pull
+//! request, and [`glob_matches`] matches paths against glob patterns. Handlers
+//! registered with
+//! [`when_files_changed`](crate::core::HandlerRegistration::when_files_changed)
+//! only run for `pull_request` deliveries changing a matching file; they find
+//! the matching files in [`Context::matched_files`].
+//!
+//! The file list is fetched during dispatch, with one
+//! `GET /repos/{owner}/{repo}/pulls/{number}/files` request per page of 100
+//! files, up to
+//! [`DispatchConfig::changed_files_max_pages`](crate::config::DispatchConfig::changed_files_max_pages)
+//! pages. It is fetched at most once per delivery and shared by all its
+//! handlers, and does not count against the handlers' API budgets.
+//!
+//! # Patterns
+//!
+//! Patterns are matched against whole paths, segment by segment:
+//!
+//! - `*` matches any characters within a segment (`*.md`, `docs/*.md`);
+//! - `?` matches one character within a segment;
+//! - `**` as a whole segment matches any number of segments (`docs/**`,
+//!   `**/Cargo.toml`).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_pull_request(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         for path in context.matched_files().unwrap_or_default() {
+//!             println!("Checking {}", path);
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await
+//! .named("docs-check")
+//! .when_files_changed(&["docs/**", "*.md"]);
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::payload::PullRequestWebhookEventAction;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Number of files per page of the pull request files API
+const FILES_PER_PAGE: usize = 100;
+
+/// Files changed by a pull request
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangedFiles {
+    /// Paths of the changed files; for renamed files, both the old and the
+    /// new path
+    pub paths: Vec<String>,
+    /// Whether more files changed than were fetched
+    pub truncated: bool,
+}
+
+impl ChangedFiles {
+    /// Get the paths matching any of `patterns`, see [`glob_matches`]
+    pub fn matching<S: AsRef<str>>(&self, patterns: &[S]) -> Vec<String> {
+        self.paths
+            .iter()
+            .filter(|path| {
+                patterns
+                    .iter()
+                    .any(|pattern| glob_matches(pattern.as_ref(), path))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Changed files of a delivery's pull request, fetched at most once
+///
+/// Shared by the contexts of all handlers of a delivery.
+#[derive(Clone, Debug, Default)]
+pub struct ChangedFilesCache(Arc<OnceCell<ChangedFiles>>);
+
+#[derive(Deserialize)]
+struct RawFile {
+    filename: String,
+    previous_filename: Option<String>,
+}
+
+impl Context {
+    /// Get the files changed by the event's pull request
+    ///
+    /// Fetches up to `max_pages` pages of 100 files. The result is cached for
+    /// the delivery, so later calls return it regardless of `max_pages`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about a pull request, no
+    /// installation client is available, or a request fails.
+    pub async fn changed_files(&self, max_pages: usize) -> Result<ChangedFiles> {
+        self.changed_files_cache
+            .0
+            .get_or_try_init(|| self.fetch_changed_files(max_pages))
+            .await
+            .cloned()
+    }
+
+    /// Get the changed files matching the handler's
+    /// [`when_files_changed`](crate::core::HandlerRegistration::when_files_changed)
+    /// patterns
+    ///
+    /// `None` if the handler has no patterns, or they were not checked because
+    /// the delivery is not a `pull_request` `opened`, `synchronize` or
+    /// `reopened` event, or its files could not be fetched.
+    pub fn matched_files(&self) -> Option<&[String]> {
+        self.matched_files.as_deref().map(Vec::as_slice)
+    }
+
+    /// Check whether the event is a pull request delivery that may change the
+    /// pull request's files
+    pub(crate) fn changes_pull_request_files(&self) -> bool {
+        let Some(event) = &self.event else {
+            return false;
+        };
+        matches!(
+            &event.specific,
+            WebhookEventPayload::PullRequest(payload) if matches!(
+                payload.action,
+                PullRequestWebhookEventAction::Opened
+                    | PullRequestWebhookEventAction::Synchronize
+                    | PullRequestWebhookEventAction::Reopened
+            )
+        )
+    }
+
+    async fn fetch_changed_files(&self, max_pages: usize) -> Result<ChangedFiles> {
+        let number = match self.event.as_ref().map(|event| &event.specific) {
+            Some(WebhookEventPayload::PullRequest(payload)) => payload.number,
+            _ => return Err(anyhow!("Event is not about a pull request")),
+        };
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/pulls/{}/files",
+            path_segment(&owner),
+            path_segment(&repo),
+            number
+        );
+
+        let mut files = ChangedFiles::default();
+        for page in 1..=max_pages {
+            let raw: Vec<RawFile> = client
+                .get(
+                    &route,
+                    Some(&json!({ "per_page": FILES_PER_PAGE, "page": page })),
+                )
+                .await
+                .map_err(|e| anyhow!("Failed to list files of #{}: {}", number, e))?;
+            let full = raw.len() == FILES_PER_PAGE;
+            for file in raw {
+                files.paths.extend(file.previous_filename);
+                files.paths.push(file.filename);
+            }
+            if !full {
+                break;
+            }
+            files.truncated = page == max_pages;
+        }
+
+        debug!(
+            "Pull request #{} changes {} file(s){}",
+            number,
+            files.paths.len(),
+            if files.truncated { " or more" } else { "" }
+        );
+        Ok(files)
+    }
+}
+
+/// Check whether `path` matches the glob `pattern`
+///
+/// See the [module documentation](self) for the syntax.
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::helpers::files::glob_matches;
+///
+/// assert!(glob_matches("docs/**", "docs/guide/intro.md"));
+/// assert!(glob_matches("**/*.rs", "src/lib.rs"));
+/// assert!(!glob_matches("*.md", "docs/intro.md"));
+/// ```
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    segments_match(&pattern, &path)
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| segments_match(rest, &path[skip..])),
+        Some((segment, rest)) => path.split_first().is_some_and(|(name, path)| {
+            segment_matches(segment, name) && segments_match(rest, path)
+        }),
+    }
+}
+
+/// Match one segment, with `*` and `?` wildcards
+fn segment_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position after the last `*`, and where in `name` it resumed matching
+    let (mut p, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((after_star, resumed)) => {
+                    p = after_star;
+                    n = resumed + 1;
+                    star = Some((after_star, resumed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pull_request_payload, MockGitHub};
+    use axum::{extract::Query, routing::get, Json, Router};
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("docs/**", "docs/intro.md"));
+        assert!(glob_matches("docs/**", "docs/guide/setup/intro.md"));
+        assert!(!glob_matches("docs/**", "src/docs.rs"));
+        assert!(glob_matches("**/Cargo.toml", "Cargo.toml"));
+        assert!(glob_matches("**/Cargo.toml", "crates/core/Cargo.toml"));
+        assert!(glob_matches("src/**/mod.rs", "src/helpers/mod.rs"));
+        assert!(glob_matches("*.md", "README.md"));
+        assert!(!glob_matches("*.md", "docs/README.md"));
+        assert!(glob_matches("docs/*-guide.md", "docs/setup-guide.md"));
+        assert!(!glob_matches("docs/*-guide.md", "docs/setup-guide.txt"));
+        assert!(glob_matches("v?.json", "v1.json"));
+        assert!(!glob_matches("v?.json", "v10.json"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+    }
+
+    #[tokio::test]
+    async fn test_changed_files_are_paginated_and_bounded() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/pulls/5/files",
+            get(|Query(query): Query<HashMap<String, String>>| async move {
+                let page: usize = query["page"].parse().unwrap();
+                // The rename on the first page makes it a full page too
+                let files: Vec<Value> = (0..FILES_PER_PAGE - usize::from(page == 1))
+                    .map(|i| json!({ "filename": format!("src/{page}/{i}.rs") }))
+                    .chain((page == 1).then(
+                        || json!({ "filename": "docs/new.md", "previous_filename": "docs/old.md" }),
+                    ))
+                    .collect();
+                Json(files)
+            }),
+        ))
+        .await;
+        let context = mock.context(
+            "pull_request",
+            pull_request_payload("synchronize", 5, "abc"),
+        );
+
+        let files = context.changed_files(2).await.unwrap();
+        assert!(files.truncated);
+        assert_eq!(files.paths.len(), 2 * FILES_PER_PAGE + 1);
+        assert_eq!(files.matching(&["docs/**"]), ["docs/old.md", "docs/new.md"]);
+
+        // The list is fetched once per delivery
+        context.changed_files(5).await.unwrap();
+        assert_eq!(mock.requests().len(), 2);
+    }
+}
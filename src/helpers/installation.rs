@@ -0,0 +1,459 @@
+//! Installation access helpers
+//!
+//! An installation only grants the permissions accepted by the account that
+//! installed the app, and may be limited to selected repositories. GitHub
+//! answers requests beyond that with opaque `403 Forbidden` or `404 Not Found`
+//! responses; checking the installation's access first lets handlers fail
+//! with an actionable error instead.
+//!
+//! `installation` and `installation_repositories` webhooks carry the access
+//! of the installation; for other events it is taken from the GitHub client's
+//! cache, or fetched with [`Context::fetch_installation_access`].
+//!
+//! When the account the app is installed on is renamed, GitHub sends an
+//! `installation_target` event with the old and new login, available as an
+//! [`AccountRename`] with [`Context::installation_target_change`]. The
+//! framework's own caches keyed by the old login, the
+//! [sender permissions](Context::sender_permission), the
+//! [repository configuration files](Context::repo_config) and the
+//! [response cache](crate::github::layers::ResponseCache), are invalidated
+//! before the handlers run. Apps storing logins can look up the current one
+//! with [`GitHubClient::resolve_current_login`](crate::github::GitHubClient::resolve_current_login).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::permissions::Permission;
+//! use octofer::Context;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     // Fails with `MissingPermission` if `checks: write` was not granted
+//!     context.require_permission("checks", Permission::Write).await?;
+//!     // ... create a check run
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::WebhookEventPayload;
+
+use crate::github::models::{InstallationAccess, RepositorySelection};
+use crate::helpers::permissions::Permission;
+use crate::Context;
+
+/// Error returned when an installation lacks a permission a handler requires
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingPermission {
+    /// ID of the installation
+    pub installation_id: u64,
+    /// Name of the permission (e.g. `checks`)
+    pub permission: String,
+    /// Level the handler requires
+    pub required: Permission,
+    /// Level the installation was granted, if any
+    pub granted: Option<Permission>,
+}
+
+impl fmt::Display for MissingPermission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Installation {} needs '{}: {}' permission, but was granted {}; \
+             update the app's permissions and accept them for the installation",
+            self.installation_id,
+            self.permission,
+            self.required,
+            match self.granted {
+                Some(granted) => format!("'{}'", granted),
+                None => "none".to_string(),
+            }
+        )
+    }
+}
+
+impl std::error::Error for MissingPermission {}
+
+/// Rename of the account the app is installed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountRename {
+    /// Login before the rename
+    pub old_login: String,
+    /// Login after the rename
+    pub new_login: String,
+    /// Type of the account, `Organization` or `User`
+    pub account_type: String,
+}
+
+impl Context {
+    /// Get the repository selection and permissions of the event's installation
+    ///
+    /// Returns `None` if they were neither sent with the event nor cached;
+    /// use [`Context::fetch_installation_access`] to fetch them.
+    pub fn installation_access(&self) -> Option<&InstallationAccess> {
+        self.installation_access.as_ref()
+    }
+
+    /// Get the rename of the installation's account of an
+    /// `installation_target.renamed` event
+    ///
+    /// Returns `None` for other events.
+    pub fn installation_target_change(&self) -> Option<AccountRename> {
+        let event = self.event.as_ref()?;
+        let WebhookEventPayload::InstallationTarget(payload) = &event.specific else {
+            return None;
+        };
+        if payload.action != "renamed" {
+            return None;
+        }
+
+        Some(AccountRename {
+            old_login: payload.changes["login"]["from"].as_str()?.to_string(),
+            new_login: payload.account["login"].as_str()?.to_string(),
+            account_type: payload.target_type.clone(),
+        })
+    }
+
+    /// Get the permissions granted to the event's installation, if known
+    ///
+    /// See [`Context::installation_access`].
+    pub fn installation_permissions(&self) -> Option<&HashMap<String, Permission>> {
+        self.installation_access().map(|access| &access.permissions)
+    }
+
+    /// Get which repositories the event's installation can access, if known
+    ///
+    /// With [`RepositorySelection::Selected`], API calls for repositories
+    /// outside the selection fail with `404 Not Found`.
+    pub fn repository_selection(&self) -> Option<RepositorySelection> {
+        self.installation_access()?.repository_selection
+    }
+
+    /// Check whether the event's installation is known to have the permission
+    /// `name` with at least `level`
+    ///
+    /// Returns `false` if the installation access is not known; use
+    /// [`Context::require_permission`] to fetch it when needed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::helpers::permissions::Permission;
+    /// use octofer::Context;
+    ///
+    /// async fn handler(context: Context) -> anyhow::Result<()> {
+    ///     if context.has_permission("pull_requests", Permission::Write) {
+    ///         // ... request reviewers
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn has_permission(&self, name: &str, level: Permission) -> bool {
+        self.installation_access()
+            .is_some_and(|access| access.has_permission(name, level))
+    }
+
+    /// Get the repository selection and permissions of the event's installation,
+    /// fetching them if they are not known
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the context has no installation or GitHub client,
+    /// or the request fails.
+    pub async fn fetch_installation_access(&self) -> Result<InstallationAccess> {
+        if let Some(access) = self.installation_access() {
+            return Ok(access.clone());
+        }
+
+        let installation_id = self
+            .installation_id
+            .ok_or_else(|| anyhow!("Event has no installation"))?;
+        let client = self
+            .github_client
+            .as_ref()
+            .ok_or_else(|| anyhow!("No GitHub client available"))?;
+        Ok(client.get_installation(installation_id).await?)
+    }
+
+    /// Fail unless the event's installation has the permission `name` with at
+    /// least `level`
+    ///
+    /// The installation access is fetched if it is not known.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the permission is not granted, or an
+    /// error if the installation access cannot be fetched.
+    pub async fn require_permission(&self, name: &str, level: Permission) -> Result<()> {
+        let access = self.fetch_installation_access().await?;
+        if access.has_permission(name, level) {
+            return Ok(());
+        }
+
+        Err(MissingPermission {
+            installation_id: access.id,
+            permission: name.to_string(),
+            required: level,
+            granted: access.permission(name),
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::{Dispatcher, HeaderMap};
+    use crate::github::layers::ResponseCache;
+    use crate::github::middlewares::GITHUB_EVENT_HEADER;
+    use crate::github::GitHubClient;
+    use crate::testing::{
+        issues_payload, repository, user, webhook_event, MockGitHub, TEST_INSTALLATION_ID,
+    };
+    use crate::Octofer;
+    use axum::{extract::State, routing::get, Json, Router};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn installation() -> Value {
+        json!({
+            "id": TEST_INSTALLATION_ID,
+            "account": user("octofer"),
+            "repository_selection": "selected",
+            "permissions": { "checks": "read", "pull_requests": "write", "metadata": "read" },
+            "events": [],
+        })
+    }
+
+    fn renamed_payload(old_login: &str, new_login: &str) -> Value {
+        json!({
+            "action": "renamed",
+            "account": user(new_login),
+            "changes": { "login": { "from": old_login } },
+            "target_type": "Organization",
+            "installation": { "id": TEST_INSTALLATION_ID, "node_id": "I_1" },
+            "sender": user("octocat"),
+        })
+    }
+
+    fn headers(event: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(GITHUB_EVENT_HEADER, event.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_installation_access_from_webhooks() {
+        let mock = MockGitHub::start(Router::new()).await;
+        let dispatcher = Dispatcher::new(Some(Arc::new(mock.client())));
+
+        let body = json!({
+            "action": "new_permissions_accepted",
+            "installation": installation(),
+            "repositories": [],
+            "sender": user("octocat"),
+        });
+        let context = dispatcher
+            .parse(
+                &headers("installation"),
+                &serde_json::to_vec(&body).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            context.repository_selection(),
+            Some(RepositorySelection::Selected)
+        );
+        assert!(context.has_permission("pull_requests", Permission::Write));
+        assert!(context.has_permission("checks", Permission::Read));
+        assert!(!context.has_permission("checks", Permission::Write));
+        dispatcher.dispatch(context).await.unwrap();
+
+        // Later events only carry the installation ID; the access is cached
+        let mut body = issues_payload("opened", 1);
+        body["installation"] = json!({ "id": TEST_INSTALLATION_ID, "node_id": "I_1" });
+        let context = dispatcher
+            .parse(&headers("issues"), &serde_json::to_vec(&body).unwrap())
+            .unwrap();
+        assert_eq!(context.installation_permissions().map(|p| p.len()), Some(3));
+
+        let err = context
+            .require_permission("checks", Permission::Write)
+            .await
+            .unwrap_err();
+        let missing = err.downcast_ref::<MissingPermission>().unwrap();
+        assert_eq!(missing.granted, Some(Permission::Read));
+        assert!(err.to_string().contains("'checks: write'"));
+    }
+
+    #[tokio::test]
+    async fn test_get_installation_is_cached() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/app/installations/{id}",
+                    get(|State(fetches): State<Arc<AtomicUsize>>| async move {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        Json(installation())
+                    }),
+                )
+                .with_state(fetches.clone()),
+        )
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 1));
+        assert!(context.installation_access().is_none());
+
+        context
+            .require_permission("pull_requests", Permission::Write)
+            .await
+            .unwrap();
+        let access = context.fetch_installation_access().await.unwrap();
+        assert_eq!(access.permission("metadata"), Some(Permission::Read));
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_account_renames_are_routed() {
+        let mut app = Octofer::new_default();
+        let renames = Arc::new(Mutex::new(Vec::new()));
+        app.on_installation_target(
+            |context: Context, renames: Arc<Mutex<Vec<_>>>| async move {
+                renames
+                    .lock()
+                    .unwrap()
+                    .extend(context.installation_target_change());
+                Ok(())
+            },
+            renames.clone(),
+        )
+        .await;
+
+        let dispatcher = app.dispatcher();
+        let context = dispatcher.context(webhook_event(
+            "installation_target",
+            renamed_payload("octofer", "octofer-labs"),
+        ));
+        dispatcher.dispatch(context).await.unwrap();
+
+        assert_eq!(
+            *renames.lock().unwrap(),
+            [AccountRename {
+                old_login: "octofer".to_string(),
+                new_login: "octofer-labs".to_string(),
+                account_type: "Organization".to_string(),
+            }]
+        );
+        let context = Context::new(
+            Some(webhook_event("issues", issues_payload("opened", 1))),
+            None,
+        );
+        assert!(context.installation_target_change().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_account_rename_invalidates_cached_owner_data() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/rename-org/app/collaborators/{user}/permission",
+                    get(|| async { Json(json!({ "permission": "admin", "role_name": "admin" })) }),
+                )
+                .route(
+                    "/repos/rename-org/app/contents/.github/octofer.yml",
+                    get(|| async {
+                        Json(json!({ "type": "file", "content": STANDARD.encode("enabled: true") }))
+                    }),
+                )
+                .route(
+                    "/orgs/rename-org/members",
+                    get(|| async { ([("etag", "\"v1\"")], Json(json!([]))) }),
+                )
+                .route(
+                    "/orgs/rename-org-other/members",
+                    get(|| async { ([("etag", "\"v1\"")], Json(json!([]))) }),
+                ),
+        )
+        .await;
+        let cache = Arc::new(ResponseCache::new(10, Duration::from_secs(60)));
+        let client = Arc::new(
+            GitHubClient::with_transport(
+                mock.auth(),
+                mock.transport().with_response_cache(cache.clone()),
+            )
+            .unwrap(),
+        );
+
+        // Fill the caches for the old login
+        let dispatcher = Dispatcher::new(Some(client.clone()));
+        let mut payload = issues_payload("opened", 1);
+        payload["repository"] = repository("rename-org", "app");
+        let mut context = Context::with_github_client(
+            Some(webhook_event("issues", payload)),
+            Some(TEST_INSTALLATION_ID),
+            Some(client),
+        );
+        context.repo_config_cache = dispatcher.repo_config_cache().clone();
+        context.permission_cache = dispatcher.permission_cache().clone();
+        let fill = || async {
+            context.sender_permission().await.unwrap();
+            context
+                .repo_config::<Value>(".github/octofer.yml")
+                .await
+                .unwrap();
+            let installation_client = context.require_installation_client().await.unwrap();
+            for route in ["/orgs/rename-org/members", "/orgs/rename-org-other/members"] {
+                installation_client._get(route).await.unwrap();
+            }
+        };
+        fill().await;
+        // Only the member lists have an ETag
+        assert_eq!(cache.stats().entries, 2);
+
+        let renamed = dispatcher.context(webhook_event(
+            "installation_target",
+            renamed_payload("rename-org", "rename-org-new"),
+        ));
+        dispatcher.dispatch(renamed).await.unwrap();
+
+        // Only the responses of the renamed account were dropped
+        assert_eq!(cache.stats().entries, 1);
+        fill().await;
+        let fetches = |suffix: &str| {
+            mock.requests()
+                .iter()
+                .filter(|r| r.path.ends_with(suffix))
+                .count()
+        };
+        assert_eq!(fetches("/permission"), 2);
+        assert_eq!(fetches("/contents/.github/octofer.yml"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_current_login() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/app/installations/{id}",
+            get(|| async {
+                let mut installation = installation();
+                installation["account"] = user("octofer-labs");
+                Json(installation)
+            }),
+        ))
+        .await;
+        let client = mock.client();
+
+        assert_eq!(
+            client
+                .resolve_current_login(TEST_INSTALLATION_ID)
+                .await
+                .unwrap(),
+            "octofer-labs"
+        );
+        assert!(client.cached_installation(TEST_INSTALLATION_ID).is_some());
+    }
+}
@@ -0,0 +1,363 @@
+//! Issue form helpers
+//!
+//! Issues created from an [issue form] carry the submitted values in their
+//! body, rendered as markdown: each field becomes a `### Label` heading
+//! followed by its value. [`Context::issue_form_fields`] parses the body of
+//! the event's issue back into an ordered map of field labels to values:
+//!
+//! - inputs, textareas and dropdowns become [`FormValue::Text`], with the
+//!   text as submitted (dropdowns with several selections are separated by
+//!   `, `; textareas rendered as code keep their fences);
+//! - checkboxes become [`FormValue::Checkboxes`], with each option's label
+//!   and whether it was checked;
+//! - optional fields left empty, rendered as `_No response_`, become
+//!   [`FormValue::NoResponse`].
+//!
+//! Headings inside fenced code blocks do not start a new field, and text
+//! before the first heading is ignored.
+//!
+//! [issue form]: https://docs.github.com/en/communities/using-templates-to-encourage-useful-issues-and-pull-requests/syntax-for-issue-forms
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::issue_forms::FormValue;
+//! use octofer::Context;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     if let Some(FormValue::Text(version)) = context.issue_form_field("Version").await? {
+//!         println!("Reported against {}", version);
+//!     }
+//!     let fields = context.issue_form_fields().await?;
+//!     if fields.get("Code of Conduct").and_then(|v| v.is_checked("I agree")) != Some(true) {
+//!         println!("Code of conduct not accepted");
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::{anyhow, Result};
+use indexmap::IndexMap;
+use octocrab::models::issues::Issue;
+use octocrab::models::webhook_events::WebhookEventPayload;
+
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Value rendered for optional fields left empty
+const NO_RESPONSE: &str = "_No response_";
+
+/// Value of an issue form field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormValue {
+    /// Text of an input, textarea or dropdown
+    Text(String),
+    /// Options of a checkboxes field, with whether they are checked
+    Checkboxes(Vec<(String, bool)>),
+    /// Optional field left empty
+    NoResponse,
+}
+
+impl FormValue {
+    /// Get the text of an input, textarea or dropdown
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Check whether the checkbox `label` is checked
+    ///
+    /// `None` if the field is not a checkboxes field or has no such option.
+    pub fn is_checked(&self, label: &str) -> Option<bool> {
+        match self {
+            Self::Checkboxes(options) => options
+                .iter()
+                .find(|(option, _)| option == label)
+                .map(|(_, checked)| *checked),
+            _ => None,
+        }
+    }
+
+    /// Get the labels of the checked options of a checkboxes field
+    pub fn checked(&self) -> Vec<&str> {
+        match self {
+            Self::Checkboxes(options) => options
+                .iter()
+                .filter(|(_, checked)| *checked)
+                .map(|(option, _)| option.as_str())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Context {
+    /// Parse the body of the event's issue as a submitted issue form
+    ///
+    /// Works for `issues` and `issue_comment` events. The body is taken from
+    /// the payload, or fetched with the installation client if the payload's
+    /// issue has none. See the [module documentation](self) for the format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue, or the body must
+    /// be fetched and no installation client is available or the request
+    /// fails.
+    pub async fn issue_form_fields(&self) -> Result<IndexMap<String, FormValue>> {
+        let body = self.issue_body().await?;
+        Ok(parse_issue_form(body.as_deref().unwrap_or_default()))
+    }
+
+    /// Get the value of the issue form field `label` of the event's issue
+    ///
+    /// `None` if the issue body has no such field. See
+    /// [`Context::issue_form_fields`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Context::issue_form_fields`].
+    pub async fn issue_form_field(&self, label: &str) -> Result<Option<FormValue>> {
+        Ok(self.issue_form_fields().await?.shift_remove(label))
+    }
+
+    async fn issue_body(&self) -> Result<Option<String>> {
+        let issue = match self.event.as_ref().map(|event| &event.specific) {
+            Some(WebhookEventPayload::Issues(payload)) => return Ok(payload.issue.body.clone()),
+            Some(WebhookEventPayload::IssueComment(payload)) => &payload.issue,
+            _ => return Err(anyhow!("Event is not about an issue")),
+        };
+        if issue.body.is_some() {
+            return Ok(issue.body.clone());
+        }
+
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let issue: Issue = client
+            .get(
+                format!(
+                    "/repos/{}/{}/issues/{}",
+                    path_segment(&owner),
+                    path_segment(&repo),
+                    issue.number
+                ),
+                None::<&()>,
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to get issue #{}: {}", issue.number, e))?;
+        Ok(issue.body)
+    }
+}
+
+/// Parse an issue body rendered from an issue form into its fields
+///
+/// See the [module documentation](self) for the format.
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::helpers::issue_forms::{parse_issue_form, FormValue};
+///
+/// let fields = parse_issue_form(
+///     "### Version\n\n1.2.3\n\n### Logs\n\n_No response_\n\n### Checks\n\n- [X] Searched existing issues",
+/// );
+/// assert_eq!(fields["Version"], FormValue::Text("1.2.3".to_string()));
+/// assert_eq!(fields["Logs"], FormValue::NoResponse);
+/// assert_eq!(fields["Checks"].is_checked("Searched existing issues"), Some(true));
+/// ```
+pub fn parse_issue_form(body: &str) -> IndexMap<String, FormValue> {
+    let mut fields = IndexMap::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    // Opening fence of the code block the current line is in, if any
+    let mut fence: Option<String> = None;
+
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        match &fence {
+            Some(open) => {
+                if closes_fence(trimmed, open) {
+                    fence = None;
+                }
+            }
+            None => {
+                if let Some(label) = line.strip_prefix("### ") {
+                    fields.extend(current.take().map(|(label, lines)| field(label, &lines)));
+                    current = Some((label.trim().to_string(), Vec::new()));
+                    continue;
+                }
+                fence = opening_fence(trimmed);
+            }
+        }
+        if let Some((_, lines)) = &mut current {
+            lines.push(line);
+        }
+    }
+    fields.extend(current.map(|(label, lines)| field(label, &lines)));
+    fields
+}
+
+fn field(label: String, lines: &[&str]) -> (String, FormValue) {
+    let text = lines.join("\n");
+    let text = text.trim_matches(|c| c == '\n' || c == '\r').trim_end();
+    (label, parse_value(text))
+}
+
+fn parse_value(text: &str) -> FormValue {
+    if text.trim() == NO_RESPONSE {
+        return FormValue::NoResponse;
+    }
+
+    let options: Option<Vec<(String, bool)>> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(checkbox)
+        .collect();
+    match options {
+        Some(options) if !options.is_empty() => FormValue::Checkboxes(options),
+        _ => FormValue::Text(text.to_string()),
+    }
+}
+
+/// Parse a task list item, e.g. `- [X] I agree`
+fn checkbox(line: &str) -> Option<(String, bool)> {
+    let item = line.trim().strip_prefix("- [")?;
+    let (mark, label) = item.split_at_checked(1)?;
+    let label = label.strip_prefix("] ")?.trim().to_string();
+    match mark {
+        "x" | "X" => Some((label, true)),
+        " " => Some((label, false)),
+        _ => None,
+    }
+}
+
+/// Get the fence opened by `line`, e.g. ```` ``` ```` or `~~~~`
+fn opening_fence(line: &str) -> Option<String> {
+    let marker = line.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let fence: String = line.chars().take_while(|&c| c == marker).collect();
+    (fence.len() >= 3).then_some(fence)
+}
+
+/// Check whether `line` closes the code block opened with `fence`
+fn closes_fence(line: &str, fence: &str) -> bool {
+    let marker = fence.chars().next().unwrap_or('`');
+    let line = line.trim_end();
+    line.len() >= fence.len() && line.chars().all(|c| c == marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{comment, issue, repository, user, MockGitHub};
+    use axum::{routing::get, Json, Router};
+    use serde_json::json;
+
+    /// Body of a bug report, as GitHub renders it from an issue form
+    const BUG_REPORT: &str = "### What happened?
+
+The server crashes on startup.
+
+It worked in the previous release.
+
+### Version
+
+1.4.0
+
+### Which platforms are affected?
+
+Linux, macOS
+
+### Relevant log output
+
+```shell
+$ octofer serve
+### not a heading
+panic: missing secret
+```
+
+### Additional context
+
+_No response_
+
+### Code of Conduct
+
+- [X] I agree to follow this project's Code of Conduct
+- [ ] I searched existing issues";
+
+    #[test]
+    fn test_parse_issue_form() {
+        let fields = parse_issue_form(BUG_REPORT);
+        let labels: Vec<&str> = fields.keys().map(String::as_str).collect();
+        assert_eq!(
+            labels,
+            [
+                "What happened?",
+                "Version",
+                "Which platforms are affected?",
+                "Relevant log output",
+                "Additional context",
+                "Code of Conduct",
+            ]
+        );
+
+        assert_eq!(
+            fields["What happened?"].as_text(),
+            Some("The server crashes on startup.\n\nIt worked in the previous release.")
+        );
+        assert_eq!(fields["Version"].as_text(), Some("1.4.0"));
+        assert_eq!(
+            fields["Which platforms are affected?"].as_text(),
+            Some("Linux, macOS")
+        );
+        assert_eq!(
+            fields["Relevant log output"].as_text(),
+            Some("```shell\n$ octofer serve\n### not a heading\npanic: missing secret\n```")
+        );
+        assert_eq!(fields["Additional context"], FormValue::NoResponse);
+
+        let conduct = &fields["Code of Conduct"];
+        assert_eq!(
+            conduct.is_checked("I agree to follow this project's Code of Conduct"),
+            Some(true)
+        );
+        assert_eq!(
+            conduct.is_checked("I searched existing issues"),
+            Some(false)
+        );
+        assert_eq!(conduct.checked().len(), 1);
+
+        // Text before the first field, e.g. from markdown elements, is ignored
+        let fields = parse_issue_form("Thanks for reporting!\r\n\r\n### Version\r\n\r\n2.0\r\n");
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields["Version"].as_text(), Some("2.0"));
+        assert!(parse_issue_form("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_issue_body_is_fetched_for_comments() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/issues/7",
+            get(|| async {
+                let mut issue = issue("octofer", "app", 7);
+                issue["body"] = json!(BUG_REPORT);
+                Json(issue)
+            }),
+        ))
+        .await;
+        let context = mock.context(
+            "issue_comment",
+            json!({
+                "action": "created",
+                "issue": issue("octofer", "app", 7),
+                "comment": comment(1, "octocat", "Any update?"),
+                "repository": repository("octofer", "app"),
+                "sender": user("octocat"),
+            }),
+        );
+
+        let version = context.issue_form_field("Version").await.unwrap();
+        assert_eq!(version, Some(FormValue::Text("1.4.0".to_string())));
+        assert_eq!(context.issue_form_field("Missing").await.unwrap(), None);
+        assert_eq!(mock.requests()[0].path, "/repos/octofer/app/issues/7");
+    }
+}
@@ -0,0 +1,522 @@
+//! Moving issues to discussions and other repositories
+//!
+//! [`Context::convert_issue_to_discussion`] moves the event's issue to a
+//! discussion category of its repository, and [`Context::transfer_issue`]
+//! transfers it to another repository of the same owner. Both work from
+//! `issues` and `issue_comment` events, but not for pull requests.
+//!
+//! GitHub's API cannot convert an issue in place, so the conversion creates a
+//! discussion with the issue's title and body through the GraphQL API, links
+//! it from a comment on the issue and closes the issue as not planned; the
+//! issue's comments stay on the issue. Categories are looked up by name or
+//! slug; the categories of each repository are cached by the
+//! [`GitHubClient`](crate::github::GitHubClient) for an hour, and looked up
+//! again when a name is not found.
+//!
+//! GitHub only transfers issues between repositories of the same account,
+//! to which the installation has access. Transfers to another account fail
+//! with [`TransferAcrossOwners`] without a request.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_issue_comment(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         match context.command() {
+//!             Some(command) if command.name == "move-to-discussions" => {
+//!                 let category = command.args.first().map_or("q-a", String::as_str);
+//!                 context.convert_issue_to_discussion(category).await?;
+//!             }
+//!             Some(command) if command.name == "transfer" && !command.args.is_empty() => {
+//!                 context.transfer_issue(&command.args[0]).await?;
+//!             }
+//!             _ => {}
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use octocrab::models::issues::Issue;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::github::graphql;
+use crate::helpers::issue_state::{close_issue_at, CloseReason};
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// How long the discussion categories of a repository are reused
+const CATEGORY_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+const CATEGORIES: &str = r#"
+query($owner: String!, $name: String!) {
+  repository(owner: $owner, name: $name) {
+    id
+    discussionCategories(first: 100) {
+      nodes { id name slug }
+    }
+  }
+}"#;
+
+const CREATE_DISCUSSION: &str = r#"
+mutation($repository: ID!, $category: ID!, $title: String!, $body: String!) {
+  createDiscussion(input: {
+    repositoryId: $repository, categoryId: $category, title: $title, body: $body
+  }) {
+    discussion { id number url }
+  }
+}"#;
+
+/// A discussion created from an issue
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DiscussionRef {
+    /// GraphQL node ID of the discussion
+    #[serde(rename = "id")]
+    pub node_id: String,
+    /// Number of the discussion in its repository
+    pub number: u64,
+    /// URL of the discussion on GitHub
+    pub url: String,
+}
+
+/// An issue after a transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueRef {
+    /// Repository of the issue, `owner/name`
+    pub repository: String,
+    /// Number of the issue in its repository
+    pub number: u64,
+    /// GraphQL node ID of the issue
+    pub node_id: String,
+    /// URL of the issue on GitHub
+    pub url: String,
+}
+
+/// Error of [`Context::transfer_issue`] for a repository of another account
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Issues can only be transferred to repositories of {from}, not {to}")]
+pub struct TransferAcrossOwners {
+    /// Owner of the issue's repository
+    pub from: String,
+    /// Owner of the target repository
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Category {
+    id: String,
+    name: String,
+    slug: String,
+}
+
+/// Node ID and discussion categories of a repository
+#[derive(Debug)]
+pub(crate) struct RepositoryCategories {
+    repository_id: String,
+    categories: Vec<Category>,
+}
+
+impl RepositoryCategories {
+    /// Find a category by name or slug, ignoring case
+    fn find(&self, category: &str) -> Option<&Category> {
+        self.categories.iter().find(|c| {
+            c.name.eq_ignore_ascii_case(category) || c.slug.eq_ignore_ascii_case(category)
+        })
+    }
+}
+
+/// Discussion categories of repositories, by lowercase `owner/name`
+#[derive(Debug, Default)]
+pub(crate) struct CategoryCache(RwLock<HashMap<String, (Arc<RepositoryCategories>, Instant)>>);
+
+impl CategoryCache {
+    fn get(&self, repository: &str) -> Option<Arc<RepositoryCategories>> {
+        let entries = self.0.read().expect("category cache lock poisoned");
+        entries
+            .get(repository)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < CATEGORY_CACHE_TTL)
+            .map(|(categories, _)| categories.clone())
+    }
+
+    fn insert(&self, repository: String, categories: Arc<RepositoryCategories>) {
+        self.0
+            .write()
+            .expect("category cache lock poisoned")
+            .insert(repository, (categories, Instant::now()));
+    }
+}
+
+impl Context {
+    /// Move the event's issue to a new discussion in the `category` of its
+    /// repository, by name or slug
+    ///
+    /// Creates the discussion with the issue's title and body, comments on
+    /// the issue with a link to it and closes the issue as not planned, see
+    /// the [module documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue, the repository
+    /// has no such category, no installation client is available, or a
+    /// request fails.
+    pub async fn convert_issue_to_discussion(&self, category: &str) -> Result<DiscussionRef> {
+        let issue = self.require_movable_issue()?;
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+
+        let mut categories = self
+            .discussion_categories(&client, &owner, &repo, false)
+            .await?;
+        if categories.find(category).is_none() {
+            categories = self
+                .discussion_categories(&client, &owner, &repo, true)
+                .await?;
+        }
+        let category_id = &categories
+            .find(category)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Discussion category '{}' not found in {}/{}",
+                    category,
+                    owner,
+                    repo
+                )
+            })?
+            .id;
+
+        let body = format!(
+            "{}\n\n_Moved from {}._",
+            issue.body.as_deref().unwrap_or_default(),
+            issue.html_url
+        );
+        let data = graphql::query(
+            &client,
+            CREATE_DISCUSSION,
+            json!({
+                "repository": categories.repository_id,
+                "category": category_id,
+                "title": issue.title,
+                "body": body.trim_start(),
+            }),
+        )
+        .await
+        .map_err(|e| e.context(format!("Failed to move #{} to a discussion", issue.number)))?;
+        let discussion: DiscussionRef =
+            serde_json::from_value(data["createDiscussion"]["discussion"].clone())
+                .map_err(|e| anyhow!("Invalid discussion created for #{}: {}", issue.number, e))?;
+
+        debug!(
+            "Moved #{} to discussion #{}",
+            issue.number, discussion.number
+        );
+        let issue_route = format!(
+            "/repos/{}/{}/issues/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            issue.number
+        );
+        let _: Value = client
+            .post(
+                format!("{}/comments", issue_route),
+                Some(&json!({ "body": format!("Moved to {}.", discussion.url) })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to comment on #{}: {}", issue.number, e))?;
+        close_issue_at(&client, &issue_route, issue.number, CloseReason::NotPlanned).await?;
+        Ok(discussion)
+    }
+
+    /// Transfer the event's issue to `target_repo`, `owner/name` or the name
+    /// of a repository of the same owner
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TransferAcrossOwners`] if `target_repo` belongs to another
+    /// account, or an error if the event is not about an issue, no
+    /// installation client is available, or the transfer fails, e.g. because
+    /// the installation cannot access `target_repo`.
+    pub async fn transfer_issue(&self, target_repo: &str) -> Result<IssueRef> {
+        let issue = self.require_movable_issue()?;
+        let (owner, repo) = self.require_repository()?;
+        let (new_owner, new_name) = target_repo.split_once('/').unwrap_or((&owner, target_repo));
+        if new_name.is_empty() || new_name.contains('/') {
+            return Err(anyhow!(
+                "Invalid repository '{}', expected owner/name",
+                target_repo
+            ));
+        }
+        if !new_owner.eq_ignore_ascii_case(&owner) {
+            return Err(TransferAcrossOwners {
+                from: owner.clone(),
+                to: new_owner.to_string(),
+            }
+            .into());
+        }
+        let client = self.require_installation_client().await?;
+
+        debug!("Transferring #{} to {}/{}", issue.number, owner, new_name);
+        let route = format!(
+            "/repos/{}/{}/issues/{}/transfer",
+            path_segment(&owner),
+            path_segment(&repo),
+            issue.number
+        );
+        let transferred: Issue = client
+            .post(
+                route,
+                Some(&json!({ "new_owner": new_owner, "new_name": new_name })),
+            )
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to transfer #{} to {}/{}: {}",
+                    issue.number,
+                    new_owner,
+                    new_name,
+                    e
+                )
+            })?;
+        Ok(IssueRef {
+            repository: format!("{}/{}", new_owner, new_name),
+            number: transferred.number,
+            node_id: transferred.node_id,
+            url: transferred.html_url.to_string(),
+        })
+    }
+
+    /// Get the issue of an `issues` or `issue_comment` event, if not a pull
+    /// request
+    fn require_movable_issue(&self) -> Result<&Issue> {
+        let issue = match self.event.as_ref().map(|event| &event.specific) {
+            Some(WebhookEventPayload::Issues(payload)) => &payload.issue,
+            Some(WebhookEventPayload::IssueComment(payload)) => &payload.issue,
+            _ => return Err(anyhow!("Event is not about an issue")),
+        };
+        if issue.pull_request.is_some() {
+            return Err(anyhow!("#{} is a pull request", issue.number));
+        }
+        Ok(issue)
+    }
+
+    /// Get the discussion categories of `owner/repo`, from the client's
+    /// cache unless `refresh`
+    async fn discussion_categories(
+        &self,
+        client: &Octocrab,
+        owner: &str,
+        repo: &str,
+        refresh: bool,
+    ) -> Result<Arc<RepositoryCategories>> {
+        let key = format!("{}/{}", owner, repo).to_lowercase();
+        let cache = self
+            .github_client
+            .as_ref()
+            .map(|github| github.category_cache());
+        if let Some(categories) = cache.filter(|_| !refresh).and_then(|cache| cache.get(&key)) {
+            return Ok(categories);
+        }
+
+        let data = graphql::query(client, CATEGORIES, json!({ "owner": owner, "name": repo }))
+            .await
+            .map_err(|e| {
+                e.context(format!(
+                    "Failed to list the discussion categories of {}",
+                    key
+                ))
+            })?;
+        let repository = &data["repository"];
+        let categories = Arc::new(RepositoryCategories {
+            repository_id: repository["id"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Repository {}/{} not found", owner, repo))?
+                .to_string(),
+            categories: serde_json::from_value(repository["discussionCategories"]["nodes"].clone())
+                .unwrap_or_default(),
+        });
+        if let Some(cache) = cache {
+            cache.insert(key, categories.clone());
+        }
+        Ok(categories)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{comment, issue, repository, user, webhook_event, MockGitHub};
+    use crate::testing::{issues_payload, TEST_INSTALLATION_ID};
+    use axum::routing::{patch, post};
+    use axum::{Json, Router};
+
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/graphql",
+                post(|Json(body): Json<Value>| async move {
+                    let query = body["query"].as_str().unwrap_or_default();
+                    let data = if query.contains("discussionCategories") {
+                        json!({ "repository": {
+                            "id": "R_1",
+                            "discussionCategories": { "nodes": [
+                                { "id": "DIC_1", "name": "General", "slug": "general" },
+                                { "id": "DIC_2", "name": "Q&A", "slug": "q-a" },
+                            ] },
+                        } })
+                    } else {
+                        json!({ "createDiscussion": { "discussion": {
+                            "id": "D_7",
+                            "number": 7,
+                            "url": "https://github.com/octofer/app/discussions/7",
+                        } } })
+                    };
+                    Json(json!({ "data": data }))
+                }),
+            )
+            .route(
+                "/repos/octofer/app/issues/1/comments",
+                post(|| async { Json(comment(9, "octofer[bot]", "Moved")) }),
+            )
+            .route(
+                "/repos/octofer/app/issues/1",
+                patch(|| async { Json(issue("octofer", "app", 1)) }),
+            )
+            .route(
+                "/repos/octofer/app/issues/1/transfer",
+                post(|| async { Json(issue("octofer", "support", 12)) }),
+            )
+    }
+
+    fn comment_payload() -> Value {
+        let mut issue = issue("octofer", "app", 1);
+        issue["title"] = json!("How do I configure the bot?");
+        issue["body"] = json!("It is unclear.");
+        json!({
+            "action": "created",
+            "issue": issue,
+            "comment": comment(2, "maintainer", "/move-to-discussions q-a"),
+            "repository": repository("octofer", "app"),
+            "sender": user("maintainer"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_convert_issue_to_discussion() {
+        let mock = MockGitHub::start(routes()).await;
+        let github = Arc::new(mock.client());
+        let context = |event: &str, payload: Value| {
+            Context::with_github_client(
+                Some(webhook_event(event, payload)),
+                Some(TEST_INSTALLATION_ID),
+                Some(github.clone()),
+            )
+        };
+
+        let discussion = context("issue_comment", comment_payload())
+            .convert_issue_to_discussion("Q&A")
+            .await
+            .unwrap();
+        assert_eq!(discussion.number, 7);
+        assert_eq!(discussion.node_id, "D_7");
+
+        let requests = mock.requests();
+        let calls: Vec<_> = requests
+            .iter()
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "POST /graphql",
+                "POST /graphql",
+                "POST /repos/octofer/app/issues/1/comments",
+                "PATCH /repos/octofer/app/issues/1",
+            ]
+        );
+        assert_eq!(
+            requests[1].body["variables"],
+            json!({
+                "repository": "R_1",
+                "category": "DIC_2",
+                "title": "How do I configure the bot?",
+                "body": "It is unclear.\n\n_Moved from https://github.com/octofer/app/issues/1._",
+            })
+        );
+        assert_eq!(
+            requests[2].body["body"],
+            "Moved to https://github.com/octofer/app/discussions/7."
+        );
+        assert_eq!(requests[3].body["state_reason"], "not_planned");
+
+        // The categories are cached, and found by slug
+        context("issues", issues_payload("opened", 1))
+            .convert_issue_to_discussion("general")
+            .await
+            .unwrap();
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 7);
+        assert_eq!(requests[4].body["variables"]["category"], "DIC_1");
+
+        let error = context("issues", issues_payload("opened", 1))
+            .convert_issue_to_discussion("ideas")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Discussion category 'ideas' not found in octofer/app"
+        );
+        // An unknown category looks the categories up again
+        assert_eq!(mock.requests().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_issue() {
+        let mock = MockGitHub::start(routes()).await;
+        let context = mock.context("issue_comment", comment_payload());
+
+        let transferred = context.transfer_issue("support").await.unwrap();
+        assert_eq!(
+            transferred,
+            IssueRef {
+                repository: "octofer/support".to_string(),
+                number: 12,
+                node_id: "I_12".to_string(),
+                url: "https://github.com/octofer/support/issues/12".to_string(),
+            }
+        );
+        let requests = mock.requests();
+        assert_eq!(requests[0].path, "/repos/octofer/app/issues/1/transfer");
+        assert_eq!(
+            requests[0].body,
+            json!({ "new_owner": "octofer", "new_name": "support" })
+        );
+
+        let error = context
+            .transfer_issue("elsewhere/support")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TransferAcrossOwners>(),
+            Some(&TransferAcrossOwners {
+                from: "octofer".to_string(),
+                to: "elsewhere".to_string(),
+            })
+        );
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
@@ -0,0 +1,482 @@
+//! Closing, reopening, locking and unlocking issues
+//!
+//! [`Context::close_issue`], [`Context::reopen_issue`],
+//! [`Context::lock_issue`] and [`Context::unlock_issue`] act on the issue or
+//! pull request of the event, e.g. from `issues` and `issue_comment`
+//! handlers. Closing sends a `state_reason`, which GitHub shows as
+//! "completed" or "not planned"; reopening sends `reopened`. Reasons are
+//! enums, and parse from user input, e.g. a command argument, with
+//! [`FromStr`], failing with [`InvalidReason`].
+//!
+//! GitHub answers some changes with `422 Unprocessable Entity`. The helpers
+//! handle them as follows:
+//!
+//! - locking an issue that is already locked, and unlocking one that is not
+//!   locked, are no-ops: the helpers return `false`;
+//! - closing or reopening an issue GitHub refuses to change, e.g. reopening
+//!   an issue closed by a transfer, fails with [`IssueStateRejected`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::issue_state::{CloseReason, LockReason};
+//! use octofer::Context;
+//!
+//! async fn wontfix(context: Context, reason: &str) -> anyhow::Result<()> {
+//!     let reason: LockReason = reason.parse()?;
+//!     context.close_issue(CloseReason::NotPlanned).await?;
+//!     if !context.lock_issue(reason).await? {
+//!         println!("The issue was already locked");
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use http::StatusCode;
+use octocrab::models::issues::Issue;
+use octocrab::Octocrab;
+use serde::Serialize;
+use serde_json::json;
+use tracing::debug;
+
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Why an issue is closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    /// The issue was resolved
+    Completed,
+    /// The issue will not be worked on, e.g. a duplicate or spam
+    NotPlanned,
+}
+
+impl CloseReason {
+    /// Value of the reason in the API
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Completed => "completed",
+            Self::NotPlanned => "not_planned",
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CloseReason {
+    type Err = InvalidReason;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value
+            .trim()
+            .to_lowercase()
+            .replace([' ', '-'], "_")
+            .as_str()
+        {
+            "completed" => Ok(Self::Completed),
+            "not_planned" => Ok(Self::NotPlanned),
+            _ => Err(InvalidReason {
+                kind: "close reason",
+                value: value.to_string(),
+                expected: &["completed", "not_planned"],
+            }),
+        }
+    }
+}
+
+/// Why an issue's conversation is locked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LockReason {
+    /// The conversation strayed from the issue
+    #[serde(rename = "off-topic")]
+    OffTopic,
+    /// The conversation became heated
+    #[serde(rename = "too heated")]
+    TooHeated,
+    /// The issue was resolved
+    #[serde(rename = "resolved")]
+    Resolved,
+    /// The conversation is spam
+    #[serde(rename = "spam")]
+    Spam,
+}
+
+impl LockReason {
+    /// Value of the reason in the API
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OffTopic => "off-topic",
+            Self::TooHeated => "too heated",
+            Self::Resolved => "resolved",
+            Self::Spam => "spam",
+        }
+    }
+}
+
+impl fmt::Display for LockReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LockReason {
+    type Err = InvalidReason;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value
+            .trim()
+            .to_lowercase()
+            .replace(['_', '-'], " ")
+            .as_str()
+        {
+            "off topic" => Ok(Self::OffTopic),
+            "too heated" => Ok(Self::TooHeated),
+            "resolved" => Ok(Self::Resolved),
+            "spam" => Ok(Self::Spam),
+            _ => Err(InvalidReason {
+                kind: "lock reason",
+                value: value.to_string(),
+                expected: &["off-topic", "too heated", "resolved", "spam"],
+            }),
+        }
+    }
+}
+
+/// Error parsing a [`CloseReason`] or [`LockReason`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidReason {
+    /// Kind of reason, `close reason` or `lock reason`
+    pub kind: &'static str,
+    /// The value that could not be parsed
+    pub value: String,
+    /// The accepted values
+    pub expected: &'static [&'static str],
+}
+
+impl fmt::Display for InvalidReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid {} '{}', expected one of: {}",
+            self.kind,
+            self.value,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for InvalidReason {}
+
+/// Error returned when GitHub refuses to close or reopen an issue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueStateRejected {
+    /// Number of the issue
+    pub number: u64,
+    /// `close` or `reopen`
+    pub action: &'static str,
+    /// Message of GitHub's response
+    pub message: String,
+}
+
+impl fmt::Display for IssueStateRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GitHub refused to {} #{}: {}",
+            self.action, self.number, self.message
+        )
+    }
+}
+
+impl std::error::Error for IssueStateRejected {}
+
+impl Context {
+    /// Close the event's issue or pull request with `reason`
+    ///
+    /// Sends `{"state": "closed", "state_reason": reason}`. Closing an issue
+    /// that is already closed updates its reason.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`IssueStateRejected`] if GitHub refuses the change, or
+    /// with another error if the event is not about an issue or pull
+    /// request, no installation client is available, or the request fails.
+    pub async fn close_issue(&self, reason: CloseReason) -> Result<Issue> {
+        let (client, issue_route, number) = self.issue_state_target().await?;
+        close_issue_at(&client, &issue_route, number, reason).await
+    }
+
+    /// Reopen the event's issue or pull request
+    ///
+    /// Sends `{"state": "open", "state_reason": "reopened"}`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`IssueStateRejected`] if GitHub refuses the change, e.g.
+    /// for a merged pull request, or with another error if the event is not
+    /// about an issue or pull request, no installation client is available,
+    /// or the request fails.
+    pub async fn reopen_issue(&self) -> Result<Issue> {
+        let (client, issue_route, number) = self.issue_state_target().await?;
+        debug!("Reopening #{}", number);
+        let body = json!({ "state": "open", "state_reason": "reopened" });
+        client
+            .patch(issue_route, Some(&body))
+            .await
+            .map_err(|e| state_error(e, number, "reopen"))
+    }
+
+    /// Lock the conversation of the event's issue or pull request with
+    /// `reason`
+    ///
+    /// Returns `false` if it was already locked, see the
+    /// [module documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue or pull request,
+    /// no installation client is available, or the request fails.
+    pub async fn lock_issue(&self, reason: LockReason) -> Result<bool> {
+        let (client, issue_route, number) = self.issue_state_target().await?;
+        lock_issue_at(&client, &issue_route, number, reason).await
+    }
+
+    /// Unlock the conversation of the event's issue or pull request
+    ///
+    /// Returns `false` if it was not locked, see the
+    /// [module documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue or pull request,
+    /// no installation client is available, or the request fails.
+    pub async fn unlock_issue(&self) -> Result<bool> {
+        let (client, issue_route, number) = self.issue_state_target().await?;
+        debug!("Unlocking #{}", number);
+        let response = client
+            ._delete(format!("{}/lock", issue_route), None::<&()>)
+            .await?;
+        let result = octocrab::map_github_error(response).await;
+        changed_unless_unprocessable(result, number, "unlock")
+    }
+
+    /// Get the installation client, the API route and the number of the
+    /// event's issue or pull request
+    async fn issue_state_target(&self) -> Result<(Octocrab, String, u64)> {
+        let (owner, repo) = self.require_repository()?;
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+        let issue_route = format!(
+            "/repos/{}/{}/issues/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            number
+        );
+        Ok((client, issue_route, number))
+    }
+}
+
+/// Close issue `number` at `issue_route` with `reason`
+pub(crate) async fn close_issue_at(
+    client: &Octocrab,
+    issue_route: &str,
+    number: u64,
+    reason: CloseReason,
+) -> Result<Issue> {
+    debug!("Closing #{} as {}", number, reason);
+    let body = json!({ "state": "closed", "state_reason": reason });
+    client
+        .patch(issue_route, Some(&body))
+        .await
+        .map_err(|e| state_error(e, number, "close"))
+}
+
+/// Lock the conversation of issue `number` at `issue_route` with `reason`,
+/// returning `false` if it was already locked
+pub(crate) async fn lock_issue_at(
+    client: &Octocrab,
+    issue_route: &str,
+    number: u64,
+    reason: LockReason,
+) -> Result<bool> {
+    debug!("Locking #{} as {}", number, reason);
+    let response = client
+        ._put(
+            format!("{}/lock", issue_route),
+            Some(&json!({ "lock_reason": reason })),
+        )
+        .await?;
+    let result = octocrab::map_github_error(response).await;
+    changed_unless_unprocessable(result, number, "lock")
+}
+
+/// Map the result of a request to whether it changed the issue, a `422`
+/// meaning it was already in the requested state
+fn changed_unless_unprocessable<T>(
+    result: octocrab::Result<T>,
+    number: u64,
+    action: &str,
+) -> Result<bool> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(octocrab::Error::GitHub { source, .. })
+            if source.status_code == StatusCode::UNPROCESSABLE_ENTITY =>
+        {
+            debug!("#{} needs no {}: {}", number, action, source.message);
+            Ok(false)
+        }
+        Err(e) => Err(anyhow!("Failed to {} #{}: {}", action, number, e)),
+    }
+}
+
+fn state_error(error: octocrab::Error, number: u64, action: &'static str) -> anyhow::Error {
+    match error {
+        octocrab::Error::GitHub { source, .. }
+            if source.status_code == StatusCode::UNPROCESSABLE_ENTITY =>
+        {
+            IssueStateRejected {
+                number,
+                action,
+                message: source.message,
+            }
+            .into()
+        }
+        e => anyhow!("Failed to {} #{}: {}", action, number, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issue, issues_payload, MockGitHub};
+    use axum::extract::Path;
+    use axum::routing::{patch, put};
+    use axum::{Json, Router};
+    use serde_json::Value;
+
+    fn unprocessable(message: &str) -> (StatusCode, Json<Value>) {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "message": message })),
+        )
+    }
+
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/repos/octofer/app/issues/{number}",
+                patch(
+                    |Path(number): Path<u64>, Json(body): Json<Value>| async move {
+                        if number == 2 {
+                            return Err(unprocessable("Issue was transferred"));
+                        }
+                        let mut issue = issue("octofer", "app", number);
+                        issue["state"] = body["state"].clone();
+                        Ok(Json(issue))
+                    },
+                ),
+            )
+            .route(
+                "/repos/octofer/app/issues/{number}/lock",
+                put(|Path(number): Path<u64>| async move {
+                    match number {
+                        2 => Err(unprocessable("Issue is already locked")),
+                        _ => Ok(StatusCode::NO_CONTENT),
+                    }
+                })
+                .delete(|Path(number): Path<u64>| async move {
+                    match number {
+                        2 => Err(unprocessable("Issue is not locked")),
+                        _ => Ok(StatusCode::NO_CONTENT),
+                    }
+                }),
+            )
+    }
+
+    #[test]
+    fn test_parse_reasons() {
+        assert_eq!("completed".parse(), Ok(CloseReason::Completed));
+        assert_eq!("Not planned".parse(), Ok(CloseReason::NotPlanned));
+        assert_eq!("off_topic".parse(), Ok(LockReason::OffTopic));
+        assert_eq!("too-heated".parse(), Ok(LockReason::TooHeated));
+        assert_eq!(
+            "reopened".parse::<CloseReason>().unwrap_err().to_string(),
+            "Invalid close reason 'reopened', expected one of: completed, not_planned"
+        );
+        assert!("abusive".parse::<LockReason>().is_err());
+
+        assert_eq!(
+            serde_json::to_value(LockReason::TooHeated).unwrap(),
+            LockReason::TooHeated.as_str()
+        );
+        assert_eq!(
+            serde_json::to_value(CloseReason::NotPlanned).unwrap(),
+            CloseReason::NotPlanned.as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_and_reopen_send_state_reason() {
+        let mock = MockGitHub::start(routes()).await;
+        let context = mock.context("issues", issues_payload("opened", 1));
+
+        context.close_issue(CloseReason::Completed).await.unwrap();
+        let reopened = context.reopen_issue().await.unwrap();
+        assert_eq!(reopened.state, octocrab::models::IssueState::Open);
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/repos/octofer/app/issues/1");
+        assert_eq!(
+            requests[0].body,
+            json!({ "state": "closed", "state_reason": "completed" })
+        );
+        assert_eq!(
+            requests[1].body,
+            json!({ "state": "open", "state_reason": "reopened" })
+        );
+
+        let transferred = mock.context("issues", issues_payload("opened", 2));
+        let error = transferred.reopen_issue().await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<IssueStateRejected>(),
+            Some(&IssueStateRejected {
+                number: 2,
+                action: "reopen",
+                message: "Issue was transferred".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lock_and_unlock() {
+        let mock = MockGitHub::start(routes()).await;
+        let context = mock.context("issue_comment", {
+            let mut payload = issues_payload("created", 1);
+            payload["comment"] = crate::testing::comment(1, "octocat", "calm down");
+            payload
+        });
+
+        assert!(context.lock_issue(LockReason::TooHeated).await.unwrap());
+        assert!(context.unlock_issue().await.unwrap());
+        let requests = mock.requests();
+        assert_eq!(requests[0].method, http::Method::PUT);
+        assert_eq!(requests[0].path, "/repos/octofer/app/issues/1/lock");
+        assert_eq!(requests[0].body, json!({ "lock_reason": "too heated" }));
+        assert_eq!(requests[1].method, http::Method::DELETE);
+
+        // Already in the requested state
+        let locked = mock.context("issues", issues_payload("opened", 2));
+        assert!(!locked.lock_issue(LockReason::Spam).await.unwrap());
+        assert!(!locked.unlock_issue().await.unwrap());
+    }
+}
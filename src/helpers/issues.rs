@@ -0,0 +1,849 @@
+//! Issue triage helpers
+//!
+//! Find issues similar to the event's issue with the search API, close
+//! issues as duplicates of another one, assign and unassign users, and edit
+//! issues with as few requests as possible with [`Context::edit_issue`].
+//!
+//! The search API has its own, much lower rate limit than the rest of the
+//! REST API. When it is exhausted, [`Context::search_similar_issues`] fails
+//! with [`SearchRateLimited`], which handlers can treat as "no results".
+//!
+//! [`IssueEdit`] combines changes into one `PATCH` of the issue. Adding and
+//! removing labels has no combined form, GitHub only accepts the full set of
+//! labels, so the set is derived from the event's payload when the edited
+//! issue is the event's own; otherwise the builder falls back to the
+//! dedicated label endpoints.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{helpers::issues::SearchRateLimited, Context};
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     match context.search_similar_issues("crash on startup", 5).await {
+//!         Ok(similar) => {
+//!             for issue in similar {
+//!                 println!("#{} {} ({})", issue.number, issue.title, issue.state);
+//!             }
+//!         }
+//!         Err(e) if e.is::<SearchRateLimited>() => println!("Search unavailable: {}", e),
+//!         Err(e) => return Err(e),
+//!     }
+//!     Ok(())
+//! }
+//!
+//! async fn triage(context: Context) -> anyhow::Result<()> {
+//!     context
+//!         .edit_issue()
+//!         .add_labels(["bug", "needs-triage"])
+//!         .assignees(["octocat"])
+//!         .milestone(Some(3))
+//!         .send()
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use octocrab::models::issues::Issue;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::models::IssueState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::github::search::{self, SearchResource, SearchResults};
+use crate::github::Error;
+use crate::helpers::issue_state::{close_issue_at, CloseReason};
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Label applied by [`Context::close_as_duplicate`]
+pub const DUPLICATE_LABEL: &str = "duplicate";
+
+/// Summary of an issue returned by the search API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssueSummary {
+    /// Issue number
+    pub number: u64,
+    /// Issue title
+    pub title: String,
+    /// `open` or `closed`
+    pub state: String,
+    /// URL of the issue on GitHub
+    pub html_url: String,
+}
+
+/// Error returned when the search API rate limit is exhausted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchRateLimited {
+    /// When the search rate limit resets, if known
+    pub reset_at: Option<DateTime<Utc>>,
+}
+
+impl fmt::Display for SearchRateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reset_at {
+            Some(reset_at) => write!(f, "Search API rate limit exceeded until {}", reset_at),
+            None => write!(f, "Search API rate limit exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for SearchRateLimited {}
+
+/// Changes to an issue or pull request, sent by [`IssueEdit::send`]
+///
+/// Created with [`Context::edit_issue`]. Everything but adding and removing
+/// labels is sent in a single `PATCH /repos/{owner}/{repo}/issues/{number}`.
+/// Added and removed labels are folded into that request too when the
+/// resulting set of labels is known: when [`IssueEdit::labels`] is set, or
+/// when the edited issue is the event's own, whose labels are in the payload.
+/// Labels changed by someone else since the event was delivered are then
+/// overwritten. Otherwise each addition costs one
+/// `POST .../labels` request and each removal one `DELETE .../labels/{name}`.
+#[derive(Debug)]
+#[must_use = "changes are only made by `send`"]
+pub struct IssueEdit<'a> {
+    context: &'a Context,
+    number: Option<u64>,
+    title: Option<String>,
+    body: Option<String>,
+    state: Option<IssueState>,
+    milestone: Option<Option<u64>>,
+    assignees: Option<Vec<String>>,
+    labels: Option<Vec<String>>,
+    add_labels: Vec<String>,
+    remove_labels: Vec<String>,
+}
+
+impl IssueEdit<'_> {
+    /// Edit issue or pull request `number` instead of the event's
+    pub fn number(mut self, number: u64) -> Self {
+        self.number = Some(number);
+        self
+    }
+
+    /// Set the title
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Set the body
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Open or close the issue
+    pub fn state(mut self, state: IssueState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Set the milestone, by number, or remove it with `None`
+    pub fn milestone(mut self, milestone: Option<u64>) -> Self {
+        self.milestone = Some(milestone);
+        self
+    }
+
+    /// Replace the assignees
+    pub fn assignees<I, S>(mut self, assignees: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.assignees = Some(assignees.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Replace the labels
+    pub fn labels<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.labels = Some(labels.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Add labels, keeping the existing ones
+    pub fn add_labels<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.add_labels.extend(labels.into_iter().map(Into::into));
+        self
+    }
+
+    /// Remove labels, keeping the other ones
+    pub fn remove_labels<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.remove_labels
+            .extend(labels.into_iter().map(Into::into));
+        self
+    }
+
+    /// Apply the changes and return the updated issue
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no number was given and the event is not about an
+    /// issue or pull request, no installation client is available, or a
+    /// request fails. Removing a label the issue does not have is not an
+    /// error.
+    pub async fn send(self) -> Result<Issue> {
+        let (owner, repo) = self.context.require_repository()?;
+        let event_number = self.context.require_issue_number().ok();
+        let number = match self.number {
+            Some(number) => number,
+            None => self.context.require_issue_number()?,
+        };
+        let client = self.context.require_installation_client().await?;
+        let issue_route = format!(
+            "/repos/{}/{}/issues/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            number
+        );
+
+        let mut body = json!({});
+        if let Some(title) = self.title {
+            body["title"] = json!(title);
+        }
+        if let Some(text) = self.body {
+            body["body"] = json!(text);
+        }
+        if let Some(state) = self.state {
+            body["state"] = json!(state);
+        }
+        if let Some(milestone) = self.milestone {
+            body["milestone"] = json!(milestone);
+        }
+        if let Some(assignees) = self.assignees {
+            body["assignees"] = json!(assignees);
+        }
+
+        let label_changes = !self.add_labels.is_empty() || !self.remove_labels.is_empty();
+        let current_labels = match self.labels {
+            Some(labels) => Some(labels),
+            None if label_changes && Some(number) == event_number => self.context.event_labels(),
+            None => None,
+        };
+        match current_labels {
+            Some(mut labels) => {
+                for label in self.add_labels {
+                    if !labels.contains(&label) {
+                        labels.push(label);
+                    }
+                }
+                labels.retain(|label| !self.remove_labels.contains(label));
+                body["labels"] = json!(labels);
+            }
+            None => {
+                if !self.add_labels.is_empty() {
+                    let _: Value = client
+                        .post(
+                            format!("{}/labels", issue_route),
+                            Some(&json!({ "labels": self.add_labels })),
+                        )
+                        .await
+                        .map_err(|e| anyhow!("Failed to label #{}: {}", number, e))?;
+                }
+                for label in &self.remove_labels {
+                    let removed: Result<Value, _> = client
+                        .delete(
+                            format!("{}/labels/{}", issue_route, path_segment(label)),
+                            None::<&()>,
+                        )
+                        .await;
+                    match removed {
+                        Ok(_) => {}
+                        Err(octocrab::Error::GitHub { source, .. })
+                            if source.status_code == http::StatusCode::NOT_FOUND => {}
+                        Err(e) => {
+                            return Err(anyhow!(
+                                "Failed to remove label '{}' from #{}: {}",
+                                label,
+                                number,
+                                e
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+
+        debug!("Editing #{} with {}", number, body);
+        client
+            .patch(issue_route, Some(&body))
+            .await
+            .map_err(|e| anyhow!("Failed to edit #{}: {}", number, e))
+    }
+}
+
+impl Context {
+    /// Start editing the event's issue or pull request
+    ///
+    /// See [`IssueEdit`] for the requests made by [`IssueEdit::send`].
+    pub fn edit_issue(&self) -> IssueEdit<'_> {
+        IssueEdit {
+            context: self,
+            number: None,
+            title: None,
+            body: None,
+            state: None,
+            milestone: None,
+            assignees: None,
+            labels: None,
+            add_labels: Vec::new(),
+            remove_labels: Vec::new(),
+        }
+    }
+
+    /// Get the names of the labels of the event's issue or pull request
+    fn event_labels(&self) -> Option<Vec<String>> {
+        let labels = match &self.event.as_ref()?.specific {
+            WebhookEventPayload::Issues(payload) => &payload.issue.labels,
+            WebhookEventPayload::IssueComment(payload) => &payload.issue.labels,
+            WebhookEventPayload::PullRequest(payload) => payload.pull_request.labels.as_ref()?,
+            _ => return None,
+        };
+        Some(labels.iter().map(|label| label.name.clone()).collect())
+    }
+
+    /// Search the event's repository for issues matching `query_terms`
+    ///
+    /// Runs `{query_terms} repo:{owner}/{name} is:issue` against the search
+    /// API and returns at most `limit` results, best match first. The event's
+    /// own issue is excluded.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`SearchRateLimited`] if the search rate limit is exhausted,
+    /// or with another error if the event has no repository, no installation
+    /// client is available, or the request fails.
+    pub async fn search_similar_issues(
+        &self,
+        query_terms: &str,
+        limit: usize,
+    ) -> Result<Vec<IssueSummary>> {
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let own_number = self.require_issue_number().ok();
+
+        let query = format!("{} repo:{}/{} is:issue", query_terms, owner, repo);
+        // Fetch one extra result in case the event's own issue is among them
+        let per_page = (limit + 1).min(100);
+        let results: SearchResults<IssueSummary> = match client
+            .get(
+                "/search/issues",
+                Some(&json!({ "q": query, "per_page": per_page })),
+            )
+            .await
+        {
+            Ok(results) => results,
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::TOO_MANY_REQUESTS
+                    || (source.status_code == http::StatusCode::FORBIDDEN
+                        && source.message.to_lowercase().contains("rate limit")) =>
+            {
+                let reset_at = search_reset(&client).await;
+                warn!("Search API rate limit exceeded (resets at {:?})", reset_at);
+                return Err(SearchRateLimited { reset_at }.into());
+            }
+            Err(e) => return Err(anyhow!("Failed to search issues: {}", e)),
+        };
+
+        let similar: Vec<IssueSummary> = results
+            .items
+            .into_iter()
+            .filter(|issue| Some(issue.number) != own_number)
+            .take(limit)
+            .collect();
+        debug!(
+            "Found {} issue(s) similar to {:?}",
+            similar.len(),
+            query_terms
+        );
+        Ok(similar)
+    }
+
+    /// Search the event's repository for issues and pull requests matching
+    /// `query`
+    ///
+    /// Runs `{query} repo:{owner}/{name}` with the installation's search
+    /// quota, following pages until `max_results` results, see
+    /// [`GitHubClient::search_issues`](crate::github::GitHubClient::search_issues).
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`SearchBudgetExhausted`](search::SearchBudgetExhausted) if
+    /// the installation's search quota is exhausted, or with another error if
+    /// the event has no repository, no installation client is available, or a
+    /// request fails.
+    pub async fn search_issues_in_repo(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<SearchResults<Issue>> {
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let (Some(github_client), Some(installation_id)) =
+            (self.github_client.as_ref(), self.installation_id)
+        else {
+            return Err(anyhow!("No installation client available for this event"));
+        };
+
+        let query = format!("{} repo:{}/{}", query, owner, repo);
+        search::search(
+            &client,
+            github_client.search_quotas(),
+            installation_id,
+            SearchResource::Issues,
+            &query,
+            max_results,
+        )
+        .await
+        .map_err(|e| match e {
+            Error::SearchBudgetExhausted(exhausted) => anyhow!(exhausted),
+            e => anyhow!("Failed to search issues: {}", e),
+        })
+    }
+
+    /// Close the event's issue as a duplicate of issue `of`
+    ///
+    /// Posts a `Duplicate of #N` comment (followed by `comment`, if given),
+    /// applies the [`DUPLICATE_LABEL`] label and closes the issue with the
+    /// `not_planned` state reason.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue, no installation
+    /// client is available, or a request fails.
+    pub async fn close_as_duplicate(&self, of: u64, comment: Option<&str>) -> Result<Issue> {
+        let (owner, repo) = self.require_repository()?;
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+        let issue_route = format!(
+            "/repos/{}/{}/issues/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            number
+        );
+
+        let body = match comment {
+            Some(comment) => format!("Duplicate of #{}\n\n{}", of, comment),
+            None => format!("Duplicate of #{}", of),
+        };
+        let _: Value = client
+            .post(
+                format!("{}/comments", issue_route),
+                Some(&json!({ "body": body })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to comment on #{}: {}", number, e))?;
+
+        let _: Value = client
+            .post(
+                format!("{}/labels", issue_route),
+                Some(&json!({ "labels": [DUPLICATE_LABEL] })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to label #{}: {}", number, e))?;
+
+        debug!("Closing #{} as duplicate of #{}", number, of);
+        close_issue_at(&client, &issue_route, number, CloseReason::NotPlanned).await
+    }
+
+    /// Get the logins of the users assigned to the event's issue or pull
+    /// request, as sent in the payload
+    ///
+    /// Returns an empty list for events about neither.
+    pub fn assignees(&self) -> Vec<String> {
+        let Some(event) = &self.event else {
+            return Vec::new();
+        };
+        let assignees = match &event.specific {
+            WebhookEventPayload::Issues(payload) => &payload.issue.assignees,
+            WebhookEventPayload::IssueComment(payload) => &payload.issue.assignees,
+            WebhookEventPayload::PullRequest(payload) => {
+                match payload.pull_request.assignees.as_ref() {
+                    Some(assignees) => assignees,
+                    None => return Vec::new(),
+                }
+            }
+            _ => return Vec::new(),
+        };
+        assignees.iter().map(|user| user.login.clone()).collect()
+    }
+
+    /// Assign `logins` to the event's issue or pull request, keeping its other
+    /// assignees
+    ///
+    /// GitHub silently ignores users who cannot be assigned, e.g. because
+    /// they have no access to the repository; check the assignees of the
+    /// returned issue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue or pull request,
+    /// no installation client is available, or the request fails.
+    pub async fn add_assignees(&self, logins: &[&str]) -> Result<Issue> {
+        let (owner, repo) = self.require_repository()?;
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+
+        debug!("Assigning {:?} to #{}", logins, number);
+        client
+            .post(
+                format!(
+                    "/repos/{}/{}/issues/{}/assignees",
+                    path_segment(&owner),
+                    path_segment(&repo),
+                    number
+                ),
+                Some(&json!({ "assignees": logins })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to assign #{}: {}", number, e))
+    }
+
+    /// Unassign `logins` from the event's issue or pull request
+    ///
+    /// Users who are not assigned are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about an issue or pull request,
+    /// no installation client is available, or the request fails.
+    pub async fn remove_assignees(&self, logins: &[&str]) -> Result<Issue> {
+        let (owner, repo) = self.require_repository()?;
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+
+        debug!("Unassigning {:?} from #{}", logins, number);
+        client
+            .delete(
+                format!(
+                    "/repos/{}/{}/issues/{}/assignees",
+                    path_segment(&owner),
+                    path_segment(&repo),
+                    number
+                ),
+                Some(&json!({ "assignees": logins })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to unassign #{}: {}", number, e))
+    }
+}
+
+/// Look up when the search rate limit resets
+async fn search_reset(client: &octocrab::Octocrab) -> Option<DateTime<Utc>> {
+    let limits: Value = client.get("/rate_limit", None::<&()>).await.ok()?;
+    let reset = limits["resources"]["search"]["reset"].as_i64()?;
+    DateTime::from_timestamp(reset, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::search::SearchBudgetExhausted;
+    use crate::testing::{issue, issues_payload, MockGitHub};
+    use axum::{
+        http::StatusCode,
+        response::IntoResponse,
+        routing::{get, post},
+        Json, Router,
+    };
+
+    fn summary(number: u64) -> Value {
+        json!({
+            "number": number,
+            "title": format!("Issue {number}"),
+            "state": "open",
+            "html_url": format!("https://github.com/octofer/app/issues/{number}"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_search_similar_issues_excludes_own_issue() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/search/issues",
+            get(|| async {
+                Json(json!({
+                    "total_count": 3,
+                    "items": [summary(7), summary(3), summary(9)],
+                }))
+            }),
+        ))
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 7));
+
+        let similar = context.search_similar_issues("crash", 1).await.unwrap();
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].number, 3);
+
+        let query = mock.requests()[0].query.clone().unwrap();
+        let query: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        assert!(query.contains(&(
+            "q".to_string(),
+            "crash repo:octofer/app is:issue".to_string()
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_search_rate_limit_is_typed() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/search/issues",
+                    get(|| async {
+                        (
+                            StatusCode::FORBIDDEN,
+                            Json(json!({ "message": "API rate limit exceeded for installation" })),
+                        )
+                    }),
+                )
+                .route(
+                    "/rate_limit",
+                    get(|| async {
+                        Json(json!({ "resources": { "search": { "reset": 1_700_000_000 } } }))
+                    }),
+                ),
+        )
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 7));
+
+        let err = context.search_similar_issues("crash", 5).await.unwrap_err();
+        let limited = err.downcast_ref::<SearchRateLimited>().unwrap();
+        assert_eq!(limited.reset_at, DateTime::from_timestamp(1_700_000_000, 0));
+    }
+
+    #[tokio::test]
+    async fn test_search_issues_in_repo() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/search/issues",
+            get(|query: axum::extract::Query<Value>| async move {
+                if query["q"].as_str().unwrap().starts_with("is:open") {
+                    let items = [issue("octofer", "app", 3), issue("octofer", "app", 9)];
+                    return Json(json!({ "total_count": 2, "items": items })).into_response();
+                }
+                let reset = [
+                    ("x-ratelimit-limit", "30"),
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1700000000"),
+                ];
+                let limited = json!({ "message": "API rate limit exceeded" });
+                (StatusCode::FORBIDDEN, reset, Json(limited)).into_response()
+            }),
+        ))
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 7));
+
+        let results = context.search_issues_in_repo("is:open", 10).await.unwrap();
+        let numbers: Vec<u64> = results.items.iter().map(|issue| issue.number).collect();
+        assert_eq!(numbers, [3, 9]);
+        assert!(mock.requests()[0]
+            .query
+            .as_deref()
+            .unwrap()
+            .contains("q=is%3Aopen+repo%3Aoctofer%2Fapp"));
+
+        let err = context
+            .search_issues_in_repo("label:bug", 10)
+            .await
+            .unwrap_err();
+        let exhausted = err.downcast_ref::<SearchBudgetExhausted>().unwrap();
+        assert_eq!(
+            exhausted.reset_at,
+            DateTime::from_timestamp(1_700_000_000, 0)
+        );
+    }
+
+    fn label(name: &str) -> Value {
+        json!({
+            "id": 1,
+            "node_id": "LA_1",
+            "url": format!("https://api.github.com/repos/octofer/app/labels/{name}"),
+            "name": name,
+            "color": "ededed",
+            "default": false,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_edit_issue_sends_one_patch() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/issues/7",
+            axum::routing::patch(|| async { Json(issue("octofer", "app", 7)) }),
+        ))
+        .await;
+        let mut payload = issues_payload("opened", 7);
+        payload["issue"]["labels"] = json!([label("bug"), label("wontfix")]);
+        let context = mock.context("issues", payload);
+
+        context
+            .edit_issue()
+            .add_labels(["needs-triage", "bug"])
+            .remove_labels(["wontfix"])
+            .assignees(["octocat"])
+            .milestone(Some(3))
+            .state(IssueState::Open)
+            .title("Crash on startup")
+            .send()
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PATCH");
+        assert_eq!(
+            requests[0].body,
+            json!({
+                "title": "Crash on startup",
+                "state": "open",
+                "milestone": 3,
+                "assignees": ["octocat"],
+                "labels": ["bug", "needs-triage"],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_edit_other_issue_falls_back_for_labels() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/issues/9/labels",
+                    post(|| async { Json(json!([])) }),
+                )
+                .route(
+                    "/repos/octofer/app/issues/9/labels/{name}",
+                    axum::routing::delete(|| async {
+                        (
+                            StatusCode::NOT_FOUND,
+                            Json(json!({ "message": "Label does not exist" })),
+                        )
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/issues/9",
+                    axum::routing::patch(|| async { Json(issue("octofer", "app", 9)) }),
+                ),
+        )
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 7));
+
+        context
+            .edit_issue()
+            .number(9)
+            .add_labels(["bug"])
+            .remove_labels(["needs info"])
+            .milestone(None)
+            .send()
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        let calls: Vec<(&str, &str)> = requests
+            .iter()
+            .map(|r| (r.method.as_str(), r.path.as_str()))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("POST", "/repos/octofer/app/issues/9/labels"),
+                ("DELETE", "/repos/octofer/app/issues/9/labels/needs%20info"),
+                ("PATCH", "/repos/octofer/app/issues/9"),
+            ]
+        );
+        assert_eq!(requests[0].body["labels"], json!(["bug"]));
+        assert_eq!(requests[2].body, json!({ "milestone": null }));
+    }
+
+    #[tokio::test]
+    async fn test_close_as_duplicate() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/issues/7/comments",
+                    post(|| async { Json(json!({})) }),
+                )
+                .route(
+                    "/repos/octofer/app/issues/7/labels",
+                    post(|| async { Json(json!([])) }),
+                )
+                .route(
+                    "/repos/octofer/app/issues/7",
+                    axum::routing::patch(|| async { Json(issue("octofer", "app", 7)) }),
+                ),
+        )
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 7));
+
+        context
+            .close_as_duplicate(3, Some("Please follow the original issue."))
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0].body["body"],
+            "Duplicate of #3\n\nPlease follow the original issue."
+        );
+        assert_eq!(requests[1].body["labels"], json!(["duplicate"]));
+        assert_eq!(requests[2].method, "PATCH");
+        assert_eq!(
+            requests[2].body,
+            json!({ "state": "closed", "state_reason": "not_planned" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_assignees() {
+        let mock = MockGitHub::start(
+            Router::new().route(
+                "/repos/octofer/app/issues/7/assignees",
+                post(|Json(body): Json<Value>| async move {
+                    let mut issue = issue("octofer", "app", 7);
+                    issue["assignees"] = json!(body["assignees"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|login| crate::testing::user(login.as_str().unwrap()))
+                        .collect::<Vec<_>>());
+                    Json(issue)
+                })
+                .delete(|| async { Json(issue("octofer", "app", 7)) }),
+            ),
+        )
+        .await;
+        let mut payload = issues_payload("opened", 7);
+        payload["issue"]["assignees"] = json!([crate::testing::user("octocat")]);
+        let context = mock.context("issues", payload);
+        assert_eq!(context.assignees(), ["octocat"]);
+
+        let issue = context.add_assignees(&["alice", "bob"]).await.unwrap();
+        assert_eq!(issue.assignees.len(), 2);
+        let issue = context.remove_assignees(&["octocat"]).await.unwrap();
+        assert!(issue.assignees.is_empty());
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].body, json!({ "assignees": ["alice", "bob"] }));
+        assert_eq!(requests[1].method, "DELETE");
+        assert_eq!(requests[1].body, json!({ "assignees": ["octocat"] }));
+    }
+}
@@ -0,0 +1,330 @@
+//! Label and milestone helpers
+//!
+//! Typed accessors for the label and milestone of `label`, `milestone`,
+//! `issues` and `pull_request` webhook payloads, and
+//! [`Context::propagate_label_change_to`], which applies the change of a
+//! `label` event to other repositories, e.g. to keep the labels of a family
+//! of repositories in parity.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_label(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if let Some((old, new)) = context.label_renamed() {
+//!             println!("Label '{}' renamed to '{}'", old, new);
+//!         }
+//!         let summary = context
+//!             .propagate_label_change_to(&["app-docs", "app-website"])
+//!             .await?;
+//!         if !summary.is_success() {
+//!             anyhow::bail!("{} repositories failed", summary.failures.len());
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::payload::LabelWebhookEventAction;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::models::{Label, Milestone};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::github::batch::IterationSummary;
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::plugins::label_sync::{self, LabelChange, LabelSpec};
+use crate::Context;
+
+/// Previous values of the label of an `edited` label event
+///
+/// Fields are only set for the values that changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LabelChanges {
+    /// Previous name of the label
+    #[serde(default, deserialize_with = "from")]
+    pub name: Option<String>,
+    /// Previous color of the label
+    #[serde(default, deserialize_with = "from")]
+    pub color: Option<String>,
+    /// Previous description of the label
+    #[serde(default, deserialize_with = "from")]
+    pub description: Option<String>,
+}
+
+/// Deserialize a `{ "from": value }` change
+pub(crate) fn from<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    struct Change {
+        from: Option<String>,
+    }
+    Ok(Option::<Change>::deserialize(deserializer)?.and_then(|change| change.from))
+}
+
+impl Context {
+    /// Get the label of a `label` event, or of an `issues` or `pull_request`
+    /// event with the `labeled` or `unlabeled` action
+    ///
+    /// Returns `None` for other events.
+    pub fn label(&self) -> Option<Label> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::Label(payload) => parse_payload_part("label", &payload.label),
+            WebhookEventPayload::Issues(payload) => payload.label.clone(),
+            WebhookEventPayload::PullRequest(payload) => payload.label.clone(),
+            _ => None,
+        }
+    }
+
+    /// Get the previous values of the label of an `edited` label event
+    ///
+    /// Returns `None` for other events.
+    pub fn label_changes(&self) -> Option<LabelChanges> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::Label(payload) => {
+                parse_payload_part("label changes", payload.changes.as_ref()?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the old and new name of a label renamed by an `edited` label event
+    ///
+    /// Returns `None` for other events, and for edits keeping the name.
+    pub fn label_renamed(&self) -> Option<(String, String)> {
+        let old = self.label_changes()?.name?;
+        let new = self.label()?.name;
+        (old != new).then_some((old, new))
+    }
+
+    /// Get the milestone of a `milestone` event, or of an `issues` or
+    /// `pull_request` event with the `milestoned` or `demilestoned` action
+    ///
+    /// Returns `None` for other events.
+    pub fn milestone(&self) -> Option<Milestone> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::Milestone(payload) => {
+                parse_payload_part("milestone", &payload.milestone)
+            }
+            WebhookEventPayload::Issues(payload) => payload.milestone.clone(),
+            WebhookEventPayload::PullRequest(payload) => payload.milestone.clone(),
+            _ => None,
+        }
+    }
+
+    /// Apply the change of a `label` event to other repositories
+    ///
+    /// A created label is created in each repository, an edited label is
+    /// updated (and renamed) by its previous name, and a deleted label is
+    /// deleted. `repos` are names of repositories of the event's owner, or
+    /// `owner/name` for repositories of other accounts the installation has
+    /// access to.
+    ///
+    /// Failures, e.g. a repository lacking the edited label or already having
+    /// the created one, are collected per repository in the summary; the
+    /// remaining repositories are still updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the event is not a `label` event, has no
+    /// repository, or no installation client is available.
+    pub async fn propagate_label_change_to(&self, repos: &[&str]) -> Result<IterationSummary> {
+        let change = self.label_change()?;
+        let (owner, _) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+
+        let mut summary = IterationSummary::default();
+        let installation_id = self.installation_id.unwrap_or_default();
+        for repo in repos {
+            let (repo_owner, name) = repo.split_once('/').unwrap_or((&owner, repo));
+            let full_name = format!("{}/{}", repo_owner, name);
+            let repo_route = format!("/repos/{}/{}", path_segment(repo_owner), path_segment(name));
+
+            info!("{}: {}", full_name, change);
+            let result = label_sync::apply(&client, &repo_route, &change)
+                .await
+                .map_err(|e| anyhow!("Failed to {} in {}: {}", change, full_name, e));
+            summary.record(installation_id, Some(full_name), result);
+        }
+        Ok(summary)
+    }
+
+    /// Get the change a `label` event made to its repository's labels
+    fn label_change(&self) -> Result<LabelChange> {
+        let action = match self.event.as_ref().map(|event| &event.specific) {
+            Some(WebhookEventPayload::Label(payload)) => &payload.action,
+            _ => return Err(anyhow!("Event is not a label event")),
+        };
+        let label = self
+            .label()
+            .ok_or_else(|| anyhow!("Label event has no label"))?;
+        let spec = LabelSpec {
+            name: label.name,
+            color: label.color,
+            description: label.description,
+            aliases: Vec::new(),
+        };
+
+        match action {
+            LabelWebhookEventAction::Created => Ok(LabelChange::Create(spec)),
+            LabelWebhookEventAction::Edited => match self.label_changes().and_then(|c| c.name) {
+                Some(from) if from != spec.name => Ok(LabelChange::Rename { from, spec }),
+                _ => Ok(LabelChange::Update {
+                    name: spec.name.clone(),
+                    spec,
+                }),
+            },
+            LabelWebhookEventAction::Deleted => Ok(LabelChange::Delete(spec.name)),
+            _ => Err(anyhow!("Unsupported label event action {:?}", action)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issues_payload, repository, user, webhook_event, MockGitHub};
+    use axum::http::StatusCode;
+    use axum::routing::patch;
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+
+    fn label_payload(action: &str, changes: Option<Value>) -> Value {
+        json!({
+            "action": action,
+            "label": {
+                "id": 1,
+                "node_id": "LA_1",
+                "url": "https://api.github.com/repos/octofer/app/labels/bug",
+                "name": "bug",
+                "color": "d73a4a",
+                "description": "Something isn't working",
+                "default": true,
+            },
+            "changes": changes,
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    #[test]
+    fn test_label_accessors() {
+        let context = Context::new(
+            Some(webhook_event("issues", issues_payload("opened", 1))),
+            None,
+        );
+        assert!(context.label().is_none());
+        assert!(context.milestone().is_none());
+        assert!(context.label_renamed().is_none());
+
+        let payload = label_payload(
+            "edited",
+            Some(json!({ "name": { "from": "defect" }, "color": { "from": "ffffff" } })),
+        );
+        let context = Context::new(Some(webhook_event("label", payload)), None);
+        let label = context.label().unwrap();
+        assert_eq!(label.name, "bug");
+        assert_eq!(
+            label.description.as_deref(),
+            Some("Something isn't working")
+        );
+        let changes = context.label_changes().unwrap();
+        assert_eq!(changes.color.as_deref(), Some("ffffff"));
+        assert_eq!(changes.description, None);
+        assert_eq!(
+            context.label_renamed(),
+            Some(("defect".to_string(), "bug".to_string()))
+        );
+
+        let payload = json!({
+            "action": "closed",
+            "milestone": {
+                "url": "https://api.github.com/repos/octofer/app/milestones/1",
+                "html_url": "https://github.com/octofer/app/milestone/1",
+                "id": 1,
+                "node_id": "MI_1",
+                "number": 1,
+                "state": "closed",
+                "title": "v1.0",
+                "created_at": "2024-01-01T00:00:00Z",
+                "due_on": "2024-03-01T00:00:00Z",
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        });
+        let context = Context::new(Some(webhook_event("milestone", payload)), None);
+        let milestone = context.milestone().unwrap();
+        assert_eq!(milestone.title, "v1.0");
+        assert_eq!(milestone.state.as_deref(), Some("closed"));
+        assert!(milestone.due_on.is_some());
+        assert!(context.label().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_label_change_is_propagated_per_repository() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app-docs/labels/{name}",
+                    patch(|Json(body): Json<Value>| async move { Json(body) }),
+                )
+                .route(
+                    "/repos/octofer/app-website/labels/{name}",
+                    patch(|| async {
+                        (
+                            StatusCode::NOT_FOUND,
+                            Json(json!({ "message": "Not Found" })),
+                        )
+                    }),
+                )
+                .route(
+                    "/repos/other/app/labels/{name}",
+                    patch(|Json(body): Json<Value>| async move { Json(body) }),
+                ),
+        )
+        .await;
+        let context = mock.context(
+            "label",
+            label_payload("edited", Some(json!({ "name": { "from": "defect" } }))),
+        );
+
+        let summary = context
+            .propagate_label_change_to(&["app-docs", "app-website", "other/app"])
+            .await
+            .unwrap();
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(
+            summary.failures[0].repository.as_deref(),
+            Some("octofer/app-website")
+        );
+
+        let requests = mock.requests();
+        let paths: Vec<_> = requests.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/repos/octofer/app-docs/labels/defect",
+                "/repos/octofer/app-website/labels/defect",
+                "/repos/other/app/labels/defect",
+            ]
+        );
+        assert_eq!(requests[0].body["new_name"], "bug");
+        assert_eq!(requests[0].body["color"], "d73a4a");
+
+        let context = mock.context("issues", issues_payload("opened", 1));
+        assert!(context
+            .propagate_label_change_to(&["app-docs"])
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,416 @@
+//! Organization and team membership helpers
+//!
+//! Typed accessors for members added to or removed from an organization
+//! (`organization` events) or a team (`membership` events), and listings of
+//! the current members, e.g. for bots provisioning access in external
+//! systems. Reacting to the events keeps those systems in sync as members
+//! change; periodically diffing them against [`GitHubClient::list_org_members`]
+//! and [`GitHubClient::list_team_members`] catches missed deliveries.
+//!
+//! Listing members requires the `members` organization permission.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::membership::MemberAction;
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_organization(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if let Some(change) = context.org_member_change() {
+//!             match change.action {
+//!                 MemberAction::Added => println!("grant {} to {}", change.role, change.login),
+//!                 MemberAction::Removed => println!("revoke access of {}", change.login),
+//!             }
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::payload::{
+    MembershipWebhookEventAction, OrganizationWebhookEventAction,
+};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::github::GitHubClient;
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Number of members requested per page
+const MEMBERS_PER_PAGE: usize = 100;
+
+/// Whether a member was added or removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberAction {
+    /// The user became a member
+    Added,
+    /// The user is no longer a member
+    Removed,
+}
+
+/// Change of an organization's members by an `organization` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberChange {
+    /// Whether the member was added or removed
+    pub action: MemberAction,
+    /// Login of the organization
+    pub organization: String,
+    /// Login of the member
+    pub login: String,
+    /// Role of the member in the organization, e.g. `admin` or `member`
+    pub role: String,
+}
+
+/// Change of a team's members by a `membership` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TeamMembershipChange {
+    /// Whether the member was added or removed
+    pub action: MemberAction,
+    /// Login of the organization of the team
+    pub organization: String,
+    /// Slug of the team, as used in API paths
+    pub team_slug: String,
+    /// Name of the team
+    pub team_name: String,
+    /// Login of the member
+    pub login: String,
+}
+
+/// Members to list by their role in the organization
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrgRoleFilter {
+    /// All members
+    #[default]
+    All,
+    /// Owners of the organization
+    Admin,
+    /// Members who are not owners
+    Member,
+}
+
+impl OrgRoleFilter {
+    /// Value of the `role` query parameter
+    fn as_str(self) -> &'static str {
+        match self {
+            OrgRoleFilter::All => "all",
+            OrgRoleFilter::Admin => "admin",
+            OrgRoleFilter::Member => "member",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Member {
+    login: String,
+}
+
+impl Context {
+    /// Get the member added to or removed from the organization by an
+    /// `organization.member_added` or `organization.member_removed` event
+    ///
+    /// Returns `None` for other events, including invitations.
+    pub fn org_member_change(&self) -> Option<MemberChange> {
+        let event = self.event.as_ref()?;
+        let WebhookEventPayload::Organization(payload) = &event.specific else {
+            return None;
+        };
+        let action = match payload.action {
+            OrganizationWebhookEventAction::MemberAdded => MemberAction::Added,
+            OrganizationWebhookEventAction::MemberRemoved => MemberAction::Removed,
+            _ => return None,
+        };
+        let membership = payload.membership.as_ref()?;
+
+        Some(MemberChange {
+            action,
+            organization: event.organization.as_ref()?.login.clone(),
+            login: membership["user"]["login"].as_str()?.to_string(),
+            role: membership["role"].as_str()?.to_string(),
+        })
+    }
+
+    /// Get the member added to or removed from a team by a `membership` event
+    ///
+    /// Returns `None` for other events.
+    pub fn team_membership_change(&self) -> Option<TeamMembershipChange> {
+        let event = self.event.as_ref()?;
+        let WebhookEventPayload::Membership(payload) = &event.specific else {
+            return None;
+        };
+        let action = match payload.action {
+            MembershipWebhookEventAction::Added => MemberAction::Added,
+            MembershipWebhookEventAction::Removed => MemberAction::Removed,
+            _ => return None,
+        };
+
+        Some(TeamMembershipChange {
+            action,
+            organization: event.organization.as_ref()?.login.clone(),
+            team_slug: payload.team["slug"].as_str()?.to_string(),
+            team_name: payload.team["name"].as_str()?.to_string(),
+            login: payload.member["login"].as_str()?.to_string(),
+        })
+    }
+}
+
+impl GitHubClient {
+    /// List the logins of all members of organization `org` with `role`
+    ///
+    /// Uses the app's installation on the organization and follows all
+    /// pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the app is not installed on the organization or a
+    /// request fails.
+    pub async fn list_org_members(&self, org: &str, role: OrgRoleFilter) -> Result<Vec<String>> {
+        let client = self.org_client(org).await?;
+        list_members(
+            &client,
+            &format!("/orgs/{}/members", path_segment(org)),
+            json!({ "role": role.as_str() }),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to list members of {}: {}", org, e))
+    }
+
+    /// List the logins of all members of team `team_slug` in organization
+    /// `org`, including those of its child teams
+    ///
+    /// Uses the app's installation on the organization and follows all
+    /// pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the app is not installed on the organization, the
+    /// team does not exist or a request fails.
+    pub async fn list_team_members(&self, org: &str, team_slug: &str) -> Result<Vec<String>> {
+        let client = self.org_client(org).await?;
+        list_members(
+            &client,
+            &format!(
+                "/orgs/{}/teams/{}/members",
+                path_segment(org),
+                path_segment(team_slug)
+            ),
+            json!({}),
+        )
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Failed to list members of team {}/{}: {}",
+                org,
+                team_slug,
+                e
+            )
+        })
+    }
+
+    /// Client of the app's installation on organization `org`
+    async fn org_client(&self, org: &str) -> Result<Octocrab> {
+        let installation: Value = self
+            .app_client()
+            .get(
+                format!("/orgs/{}/installation", path_segment(org)),
+                None::<&()>,
+            )
+            .await
+            .map_err(|e| anyhow!("App is not installed on {}: {}", org, e))?;
+        let installation_id = installation["id"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("Not an installation of {}", org))?;
+        Ok(self.installation_client(installation_id).await?)
+    }
+}
+
+/// Collect the logins of all pages of a member listing
+async fn list_members(client: &Octocrab, path: &str, mut query: Value) -> Result<Vec<String>> {
+    let mut logins = Vec::new();
+    for page in 1u32.. {
+        query["per_page"] = json!(MEMBERS_PER_PAGE);
+        query["page"] = json!(page);
+        let members: Vec<Member> = client.get(path, Some(&query)).await?;
+
+        let done = members.len() < MEMBERS_PER_PAGE;
+        logins.extend(members.into_iter().map(|member| member.login));
+        if done {
+            break;
+        }
+    }
+    Ok(logins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{organization, user, webhook_event, MockGitHub};
+    use axum::extract::Query;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use std::collections::HashMap;
+
+    /// Serve `total` members, `prefix-0` to `prefix-{total - 1}`, by page
+    async fn members_page(
+        total: usize,
+        prefix: &str,
+        query: HashMap<String, String>,
+    ) -> Json<Value> {
+        let per_page: usize = query["per_page"].parse().unwrap();
+        let page: usize = query["page"].parse().unwrap();
+        let members: Vec<Value> = ((page - 1) * per_page..(page * per_page).min(total))
+            .map(|i| json!({ "login": format!("{prefix}-{i}") }))
+            .collect();
+        Json(json!(members))
+    }
+
+    async fn mock_members() -> MockGitHub {
+        MockGitHub::start(
+            Router::new()
+                .route(
+                    "/orgs/octofer/installation",
+                    get(|| async { Json(json!({ "id": 1 })) }),
+                )
+                .route(
+                    "/orgs/octofer/members",
+                    get(|Query(query): Query<HashMap<String, String>>| async move {
+                        let prefix = query["role"].clone();
+                        members_page(150, &prefix, query).await
+                    }),
+                )
+                .route(
+                    "/orgs/octofer/teams/{team}/members",
+                    get(|Query(query): Query<HashMap<String, String>>| async move {
+                        members_page(100, "maintainer", query).await
+                    }),
+                ),
+        )
+        .await
+    }
+
+    #[test]
+    fn test_membership_accessors() {
+        let payload = json!({
+            "action": "member_added",
+            "membership": {
+                "url": "https://api.github.com/orgs/octofer/memberships/alice",
+                "state": "active",
+                "role": "admin",
+                "organization_url": "https://api.github.com/orgs/octofer",
+                "user": user("alice"),
+            },
+            "organization": organization("octofer"),
+            "sender": user("octocat"),
+        });
+        let context = Context::new(Some(webhook_event("organization", payload)), None);
+        assert_eq!(
+            context.org_member_change(),
+            Some(MemberChange {
+                action: MemberAction::Added,
+                organization: "octofer".to_string(),
+                login: "alice".to_string(),
+                role: "admin".to_string(),
+            })
+        );
+        assert!(context.team_membership_change().is_none());
+
+        let payload = json!({
+            "action": "removed",
+            "scope": "team",
+            "member": user("bob"),
+            "team": { "id": 1, "slug": "release-managers", "name": "Release managers" },
+            "organization": organization("octofer"),
+            "sender": user("octocat"),
+        });
+        let context = Context::new(Some(webhook_event("membership", payload)), None);
+        assert_eq!(
+            context.team_membership_change(),
+            Some(TeamMembershipChange {
+                action: MemberAction::Removed,
+                organization: "octofer".to_string(),
+                team_slug: "release-managers".to_string(),
+                team_name: "Release managers".to_string(),
+                login: "bob".to_string(),
+            })
+        );
+        assert!(context.org_member_change().is_none());
+
+        let payload = json!({
+            "action": "renamed",
+            "changes": { "login": { "from": "octofer-old" } },
+            "organization": organization("octofer"),
+            "sender": user("octocat"),
+        });
+        let context = Context::new(Some(webhook_event("organization", payload)), None);
+        assert!(context.org_member_change().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_org_members_follows_pages_with_role() {
+        let mock = mock_members().await;
+        let client = mock.client();
+
+        let admins = client
+            .list_org_members("octofer", OrgRoleFilter::Admin)
+            .await
+            .unwrap();
+        assert_eq!(admins.len(), 150);
+        assert_eq!(admins[0], "admin-0");
+        assert_eq!(admins[149], "admin-149");
+
+        let pages: Vec<_> = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.path == "/orgs/octofer/members")
+            .map(|r| r.query.unwrap())
+            .collect();
+        assert_eq!(pages.len(), 2);
+        assert!(pages.iter().all(|query| query.contains("role=admin")));
+
+        let members = client
+            .list_org_members("octofer", OrgRoleFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(members[0], "all-0");
+    }
+
+    #[tokio::test]
+    async fn test_list_team_members_stops_after_last_full_page() {
+        let mock = mock_members().await;
+
+        let members = mock
+            .client()
+            .list_team_members("octofer", "release-managers")
+            .await
+            .unwrap();
+        assert_eq!(members.len(), 100);
+
+        // A full last page needs an empty one to tell it is the last
+        let pages = mock
+            .requests()
+            .iter()
+            .filter(|r| r.path == "/orgs/octofer/teams/release-managers/members")
+            .count();
+        assert_eq!(pages, 2);
+    }
+
+    #[tokio::test]
+    async fn test_list_members_without_installation() {
+        let mock = MockGitHub::start(Router::new()).await;
+        let error = mock
+            .client()
+            .list_org_members("octofer", OrgRoleFilter::All)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not installed on octofer"));
+    }
+}
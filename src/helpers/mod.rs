@@ -0,0 +1,258 @@
+//! Context helpers for common GitHub operations
+//!
+//! This module extends [`Context`] with typed accessors for webhook payloads
+//! and helpers that act on the resource an event is about, using the
+//! installation client of the event. Helpers are grouped by topic, mirroring
+//! the [`events`](crate::events) module.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context as _, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::Context;
+
+pub mod branch_protection;
+pub mod checks;
+pub mod commands;
+pub mod comments;
+pub mod compare;
+pub mod contents;
+pub mod dependabot;
+pub mod discussions;
+pub mod edits;
+pub mod fan_out;
+pub mod files;
+pub mod installation;
+pub mod issue_forms;
+pub mod issue_moves;
+pub mod issue_state;
+pub mod issues;
+pub mod labels;
+pub mod membership;
+pub mod notes;
+pub mod permissions;
+pub mod pull_requests;
+pub mod releases;
+pub mod repo_config;
+pub mod repo_settings;
+pub mod repository;
+pub mod repository_dispatch;
+pub mod reviews;
+pub mod secret_scanning;
+pub mod statuses;
+pub mod sub_issues;
+pub mod templates;
+pub mod trust;
+pub mod workflows;
+
+/// Characters left unescaped in URL path segments
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Percent-encode a value for use as a single URL path segment
+///
+/// Label names, branch names and similar values may contain spaces, slashes or
+/// other characters with a special meaning in URLs.
+pub(crate) fn path_segment(value: &str) -> String {
+    utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
+
+/// Get the UTF-8 contents of the file at `path` in a repository
+///
+/// `repo_route` is the repository's API route, `/repos/{owner}/{repo}`.
+pub(crate) async fn fetch_file(client: &Octocrab, repo_route: &str, path: &str) -> Result<String> {
+    fetch_optional_file(client, repo_route, path)
+        .await?
+        .ok_or_else(|| anyhow!("{} not found", path))
+}
+
+/// Get the UTF-8 contents of the file at `path` in a repository, or `None`
+/// if it does not exist
+pub(crate) async fn fetch_optional_file(
+    client: &Octocrab,
+    repo_route: &str,
+    path: &str,
+) -> Result<Option<String>> {
+    fetch_optional_file_at(client, repo_route, path, None).await
+}
+
+/// Get the UTF-8 contents of the file at `path` in a repository at the ref
+/// `git_ref`, or at the default branch if unset; `None` if it does not exist
+pub(crate) async fn fetch_optional_file_at(
+    client: &Octocrab,
+    repo_route: &str,
+    path: &str,
+    git_ref: Option<&str>,
+) -> Result<Option<String>> {
+    let encoded = path
+        .split('/')
+        .map(path_segment)
+        .collect::<Vec<_>>()
+        .join("/");
+    let query = git_ref.map(|git_ref| [("ref", git_ref)]);
+    let file: Value = match client
+        .get(
+            format!("{}/contents/{}", repo_route, encoded),
+            query.as_ref(),
+        )
+        .await
+    {
+        Ok(file) => file,
+        Err(octocrab::Error::GitHub { source, .. })
+            if source.status_code == http::StatusCode::NOT_FOUND =>
+        {
+            return Ok(None);
+        }
+        Err(e) => return Err(anyhow!("Failed to fetch {}: {}", path, e)),
+    };
+
+    let content = file["content"]
+        .as_str()
+        .ok_or_else(|| anyhow!("{} is not a file", path))?
+        .replace('\n', "");
+    let bytes = STANDARD
+        .decode(content)
+        .with_context(|| format!("Invalid content encoding for {}", path))?;
+
+    String::from_utf8(bytes)
+        .map(Some)
+        .with_context(|| format!("{} is not valid UTF-8", path))
+}
+
+/// Parse a part of a webhook payload that octocrab only exposes as raw JSON
+pub(crate) fn parse_payload_part<T: DeserializeOwned>(what: &str, value: &Value) -> Option<T> {
+    serde_json::from_value(value.clone())
+        .map_err(|e| warn!("Failed to parse {} from payload: {}", what, e))
+        .ok()
+}
+
+/// Values expiring after a TTL, holding at most a number of entries
+///
+/// Expired entries are dropped on lookup and when the cache is full; a full
+/// cache then drops the oldest entries, like the
+/// [`ResponseCache`](crate::github::layers::ResponseCache).
+#[derive(Debug)]
+pub(crate) struct ExpiringCache<K, V> {
+    max_entries: usize,
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (V, Instant)>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> ExpiringCache<K, V> {
+    /// Create a cache of at most `max_entries` values, each kept for `ttl`
+    pub(crate) fn new(max_entries: usize, ttl: Duration) -> Self {
+        Self {
+            max_entries,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the value of `key`, if cached and not expired
+    pub(crate) fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((value, stored_at)) if stored_at.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Cache `value` for `key`, making room if the cache is full
+    pub(crate) fn insert(&self, key: K, value: V) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            entries.retain(|_, (_, stored_at)| stored_at.elapsed() < self.ttl);
+            while entries.len() >= self.max_entries {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, (_, stored_at))| *stored_at)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, (value, Instant::now()));
+    }
+
+    /// Keep only the entries whose key satisfies `keep`, returning how many
+    /// were dropped
+    pub(crate) fn retain(&self, mut keep: impl FnMut(&K) -> bool) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|key, _| keep(key));
+        before - entries.len()
+    }
+
+    /// Number of cached entries, including expired ones not dropped yet
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+impl Context {
+    /// Get the installation client, failing if none is available
+    pub(crate) async fn require_installation_client(&self) -> Result<Octocrab> {
+        self.installation_client()
+            .await?
+            .ok_or_else(|| anyhow!("No installation client available for this event"))
+    }
+
+    /// Get the owner and name of the event's repository
+    pub(crate) fn require_repository(&self) -> Result<(String, String)> {
+        let repository = self
+            .event
+            .as_ref()
+            .and_then(|e| e.repository.as_ref())
+            .ok_or_else(|| anyhow!("Event has no repository"))?;
+        let owner = repository
+            .owner
+            .as_ref()
+            .ok_or_else(|| anyhow!("Repository {} has no owner", repository.name))?;
+
+        Ok((owner.login.clone(), repository.name.clone()))
+    }
+
+    /// Get the number of the issue or pull request the event is about
+    pub(crate) fn require_issue_number(&self) -> Result<u64> {
+        let event = self
+            .event
+            .as_ref()
+            .ok_or_else(|| anyhow!("Context has no event"))?;
+        match &event.specific {
+            WebhookEventPayload::Issues(payload) => Ok(payload.issue.number),
+            WebhookEventPayload::IssueComment(payload) => Ok(payload.issue.number),
+            WebhookEventPayload::PullRequest(payload) => Ok(payload.number),
+            WebhookEventPayload::PullRequestReview(payload) => Ok(payload.pull_request.number),
+            WebhookEventPayload::PullRequestReviewComment(payload) => {
+                Ok(payload.pull_request.number)
+            }
+            WebhookEventPayload::PullRequestReviewThread(payload) => {
+                Ok(payload.pull_request.number)
+            }
+            _ => Err(anyhow!("Event is not about an issue or pull request")),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Report notes
+//!
+//! Handlers can attach structured data to the report of their delivery with
+//! [`Context::note`], e.g. the score and decision of a classifier, to tune it
+//! from the [delivery reports](crate::webhook::report::DeliveryReport)
+//! instead of from logs. Each note is listed with the name of the handler
+//! that added it.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use serde_json::json;
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_issue(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         context.note("triage", json!({ "priority": "high", "score": 0.92 }));
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::Context;
+
+/// Notes added by the handlers of one delivery, not yet in its report
+///
+/// Cloning is cheap; clones share the same notes, so the dispatcher collects
+/// the notes of every handler context of a delivery.
+#[derive(Debug, Clone, Default)]
+pub struct ReportNotes {
+    notes: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+impl ReportNotes {
+    /// Remove the pending notes, attributing them to `handler`
+    pub(crate) fn take(&self, handler: &str) -> Vec<ReportNote> {
+        std::mem::take(&mut *self.notes.lock().unwrap())
+            .into_iter()
+            .map(|(key, value)| ReportNote {
+                handler: handler.to_string(),
+                key,
+                value,
+            })
+            .collect()
+    }
+}
+
+/// Note added to a delivery's report with [`Context::note`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportNote {
+    /// Name of the handler that added the note, see
+    /// [`RegisteredHandler::name`](crate::core::RegisteredHandler::name)
+    pub handler: String,
+    /// Key of the note
+    pub key: String,
+    /// Value of the note
+    pub value: Value,
+}
+
+impl Context {
+    /// Add `value` to the delivery's report as note `key`
+    ///
+    /// Values that cannot be serialized are logged and dropped.
+    pub fn note(&self, key: &str, value: impl Serialize) {
+        match serde_json::to_value(value) {
+            Ok(value) => self
+                .report_notes
+                .notes
+                .lock()
+                .unwrap()
+                .push((key.to_string(), value)),
+            Err(e) => warn!("Dropping report note '{}': {}", key, e),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Permission helpers
+//!
+//! Look up the repository permission of the user who triggered an event, e.g.
+//! to only obey commands from maintainers. Lookups are cached per repository
+//! and user for [`PERMISSION_CACHE_TTL`], in a [`PermissionCache`] shared by
+//! the deliveries of an app.
+//!
+//! # Examples
+//!
+//! Combining a command with a permission check, reacting with 👎 when the
+//! sender may not run it:
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use octofer::octocrab::models::{reactions::ReactionContent, webhook_events::WebhookEventPayload};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_issue_comment(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let Some(WebhookEventPayload::IssueComment(payload)) =
+//!             context.event().as_ref().map(|e| &e.specific)
+//!         else {
+//!             return Ok(());
+//!         };
+//!         if payload.comment.body.as_deref().map(str::trim) != Some("/merge") {
+//!             return Ok(());
+//!         }
+//!
+//!         if !context.sender_can_write().await? {
+//!             let repository = context.event().as_ref().and_then(|e| e.repository.as_ref());
+//!             if let (Some(client), Some(repo)) = (context.installation_client().await?, repository) {
+//!                 let owner = repo.owner.as_ref().map(|o| o.login.as_str()).unwrap_or_default();
+//!                 client
+//!                     .issues(owner, &repo.name)
+//!                     .create_comment_reaction(payload.comment.id, ReactionContent::MinusOne)
+//!                     .await?;
+//!             }
+//!             return Ok(());
+//!         }
+//!
+//!         // ... merge the pull request
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::helpers::{path_segment, ExpiringCache};
+use crate::Context;
+
+/// How long a looked up permission is reused
+pub const PERMISSION_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Most permissions kept in a [`PermissionCache`]
+pub const PERMISSION_CACHE_MAX_ENTRIES: usize = 10_000;
+
+/// Permission level of a user on a repository, from lowest to highest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Permission {
+    /// No access
+    None,
+    /// Read access
+    Read,
+    /// Triage access: manage issues and pull requests without write access
+    Triage,
+    /// Write access
+    Write,
+    /// Maintain access: manage the repository without admin access
+    Maintain,
+    /// Admin access
+    Admin,
+}
+
+impl Permission {
+    /// Parse a permission or role name as returned by the GitHub API
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Self::None),
+            "read" | "pull" => Some(Self::Read),
+            "triage" => Some(Self::Triage),
+            "write" | "push" => Some(Self::Write),
+            "maintain" => Some(Self::Maintain),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    /// Whether the permission grants write access to the repository
+    pub fn can_write(self) -> bool {
+        self >= Self::Write
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::None => "none",
+            Self::Read => "read",
+            Self::Triage => "triage",
+            Self::Write => "write",
+            Self::Maintain => "maintain",
+            Self::Admin => "admin",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Serialize for Permission {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Cache key: installation, repository and user
+type PermissionKey = (Option<u64>, String, String);
+
+/// Permissions looked up by [`Context::sender_permission`]
+///
+/// Shared by the contexts of all deliveries of a dispatcher. Holds at most
+/// [`PERMISSION_CACHE_MAX_ENTRIES`] permissions, each for
+/// [`PERMISSION_CACHE_TTL`]; when full, expired permissions are dropped, then
+/// the oldest.
+#[derive(Clone, Debug)]
+pub struct PermissionCache(Arc<ExpiringCache<PermissionKey, Permission>>);
+
+impl Default for PermissionCache {
+    fn default() -> Self {
+        Self::new(PERMISSION_CACHE_MAX_ENTRIES, PERMISSION_CACHE_TTL)
+    }
+}
+
+impl PermissionCache {
+    fn new(max_entries: usize, ttl: Duration) -> Self {
+        Self(Arc::new(ExpiringCache::new(max_entries, ttl)))
+    }
+
+    /// Forget the cached permissions on the repository `full_name`, of all
+    /// installations and users
+    pub(crate) fn forget_repository(&self, full_name: &str) {
+        let full_name = full_name.to_lowercase();
+        self.0.retain(|(_, repository, _)| *repository != full_name);
+    }
+
+    /// Forget the cached permissions on the repositories of the account
+    /// `login`, of all installations and users
+    pub(crate) fn forget_owner(&self, login: &str) {
+        let prefix = format!("{}/", login.to_lowercase());
+        self.0
+            .retain(|(_, repository, _)| !repository.starts_with(&prefix));
+    }
+}
+
+impl Context {
+    /// Get the repository permission of the event's sender
+    ///
+    /// Calls `GET /repos/{owner}/{repo}/collaborators/{username}/permission`
+    /// with the installation client. Results are cached for
+    /// [`PERMISSION_CACHE_TTL`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository or sender, no
+    /// installation client is available, or the request fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{helpers::permissions::Permission, Context};
+    ///
+    /// async fn handler(context: Context) -> anyhow::Result<()> {
+    ///     if context.sender_permission().await? >= Permission::Maintain {
+    ///         println!("Sender is a maintainer");
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn sender_permission(&self) -> Result<Permission> {
+        let (owner, repo) = self.require_repository()?;
+        let sender = self
+            .event
+            .as_ref()
+            .and_then(|e| e.sender.as_ref())
+            .map(|sender| sender.login.clone())
+            .ok_or_else(|| anyhow!("Event has no sender"))?;
+
+        let key = (
+            self.installation_id,
+            format!("{}/{}", owner, repo).to_lowercase(),
+            sender.to_lowercase(),
+        );
+        if let Some(permission) = self.permission_cache.0.get(&key) {
+            return Ok(permission);
+        }
+
+        let client = self.require_installation_client().await?;
+        let response: Value = client
+            .get(
+                format!(
+                    "/repos/{}/{}/collaborators/{}/permission",
+                    path_segment(&owner),
+                    path_segment(&repo),
+                    path_segment(&sender)
+                ),
+                None::<&()>,
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to get permission of {}: {}", sender, e))?;
+
+        // `role_name` distinguishes triage and maintain, but may be a custom
+        // role; `permission` is always one of the base levels
+        let permission = response["role_name"]
+            .as_str()
+            .and_then(Permission::from_name)
+            .or_else(|| {
+                response["permission"]
+                    .as_str()
+                    .and_then(Permission::from_name)
+            })
+            .ok_or_else(|| anyhow!("Unexpected permission response for {}", sender))?;
+
+        debug!(
+            "{} has {} permission on {}/{}",
+            sender, permission, owner, repo
+        );
+        self.permission_cache.0.insert(key, permission);
+        Ok(permission)
+    }
+
+    /// Check whether the event's sender has at least write permission
+    ///
+    /// See [`Context::sender_permission`].
+    pub async fn sender_can_write(&self) -> Result<bool> {
+        Ok(self.sender_permission().await?.can_write())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, MockGitHub};
+    use axum::{routing::get, Json, Router};
+    use serde_json::json;
+
+    fn star_context(mock: &MockGitHub, repo: &str, sender: &str) -> Context {
+        mock.context(
+            "star",
+            json!({
+                "action": "created",
+                "starred_at": null,
+                "repository": repository("octofer", repo),
+                "sender": user(sender),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_permission_ordering() {
+        assert!(Permission::Admin > Permission::Maintain);
+        assert!(Permission::Maintain.can_write());
+        assert!(!Permission::Triage.can_write());
+        assert_eq!(Permission::from_name("push"), Some(Permission::Write));
+        assert_eq!(Permission::from_name("custom-role"), None);
+    }
+
+    #[tokio::test]
+    async fn test_sender_permission_is_cached() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/perms-cached/collaborators/{user}/permission",
+            get(|| async { Json(json!({ "permission": "write", "role_name": "maintain" })) }),
+        ))
+        .await;
+        let context = star_context(&mock, "perms-cached", "alice");
+
+        assert_eq!(
+            context.sender_permission().await.unwrap(),
+            Permission::Maintain
+        );
+        assert!(context.sender_can_write().await.unwrap());
+        assert_eq!(mock.requests().len(), 1);
+        assert_eq!(
+            mock.requests()[0].path,
+            "/repos/octofer/perms-cached/collaborators/alice/permission"
+        );
+    }
+
+    #[test]
+    fn test_permission_cache_is_bounded() {
+        let key = |user: &str| (Some(1), "octofer/app".to_string(), user.to_string());
+
+        let cache = PermissionCache::new(2, PERMISSION_CACHE_TTL);
+        for user in ["alice", "bob", "carol"] {
+            cache.0.insert(key(user), Permission::Read);
+        }
+        assert_eq!(cache.0.len(), 2);
+        assert_eq!(cache.0.get(&key("alice")), None);
+        assert_eq!(cache.0.get(&key("carol")), Some(Permission::Read));
+
+        let cache = PermissionCache::new(2, Duration::ZERO);
+        for user in ["alice", "bob", "carol"] {
+            cache.0.insert(key(user), Permission::Read);
+        }
+        assert_eq!(cache.0.len(), 1);
+        assert_eq!(cache.0.get(&key("carol")), None);
+        assert_eq!(cache.0.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_custom_role_falls_back_to_permission() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/perms-custom/collaborators/{user}/permission",
+            get(|| async { Json(json!({ "permission": "read", "role_name": "reviewer" })) }),
+        ))
+        .await;
+        let context = star_context(&mock, "perms-custom", "bob");
+
+        assert_eq!(context.sender_permission().await.unwrap(), Permission::Read);
+        assert!(!context.sender_can_write().await.unwrap());
+    }
+}
@@ -0,0 +1,475 @@
+//! Pull request correlation helpers
+//!
+//! `check_run`, `check_suite` and `workflow_run` events are about a commit,
+//! not a pull request. Their payloads list the pull requests of the commit,
+//! but only those whose head branch is in the same repository: for pull
+//! requests from forks the list is empty. [`Context::associated_pull_requests`]
+//! uses the list when it has entries and otherwise asks the API which pull
+//! requests contain the event's [head commit](Context::head_sha).
+//!
+//! The result is cached for the delivery, so several handlers of the same
+//! delivery share one lookup.
+//!
+//! [`Context::is_draft_pr`] tells whether the pull request of a pull request
+//! event is a draft, from the payload. For check and workflow events,
+//! [`Context::associated_pull_requests_are_drafts`] requests the associated
+//! pull requests to find out, also once per delivery.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::Context;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     for pull_request in context.associated_pull_requests().await? {
+//!         println!("Check finished for #{}", pull_request.number);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::payload::PullRequestWebhookEventAction;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+use crate::github::models::PrRef;
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Pull requests associated with a delivery, and whether they are drafts,
+/// looked up at most once
+///
+/// Shared by the contexts of all handlers of a delivery.
+#[derive(Clone, Debug, Default)]
+pub struct PullRequestCache {
+    pull_requests: Arc<OnceCell<Vec<PrRef>>>,
+    drafts: Arc<OnceCell<Option<bool>>>,
+}
+
+impl PullRequestCache {
+    /// Get the cached pull requests, if they were looked up
+    pub fn get(&self) -> Option<&[PrRef]> {
+        self.pull_requests.get().map(Vec::as_slice)
+    }
+}
+
+/// Shape shared by the `pull_requests` entries of check and workflow payloads
+/// and the pull requests returned by the API
+#[derive(Deserialize)]
+struct RawPullRequest {
+    number: u64,
+    head: RawBranch,
+    base: RawBranch,
+}
+
+/// Fields of a pull request telling whether it is an open draft
+#[derive(Deserialize)]
+struct DraftState {
+    state: String,
+    #[serde(default)]
+    draft: bool,
+}
+
+#[derive(Deserialize)]
+struct RawBranch {
+    #[serde(rename = "ref")]
+    name: String,
+    sha: String,
+}
+
+impl From<RawPullRequest> for PrRef {
+    fn from(raw: RawPullRequest) -> Self {
+        Self {
+            number: raw.number,
+            head_ref: raw.head.name,
+            head_sha: raw.head.sha,
+            base_ref: raw.base.name,
+        }
+    }
+}
+
+impl Context {
+    /// Get the pull requests the event is associated with
+    ///
+    /// For pull request events, this is the event's pull request. For
+    /// `check_run`, `check_suite` and `workflow_run` events, it is the
+    /// payload's list of pull requests. Otherwise, and when that list is
+    /// empty, the pull requests containing the event's
+    /// [head commit](Context::head_sha) are requested from the API, which
+    /// includes closed pull requests and pull requests from forks. Events
+    /// without a head commit have no associated pull requests.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a lookup is needed but the event has no
+    /// repository, no installation client is available, or the request fails.
+    pub async fn associated_pull_requests(&self) -> Result<Vec<PrRef>> {
+        self.pull_request_cache
+            .pull_requests
+            .get_or_try_init(|| self.lookup_pull_requests())
+            .await
+            .cloned()
+    }
+
+    /// Get the action of a `pull_request` event
+    ///
+    /// Returns `None` for other events.
+    pub fn pull_request_action(&self) -> Option<PullRequestWebhookEventAction> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::PullRequest(payload) => Some(payload.action.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the event's pull request is a draft
+    ///
+    /// Returns `None` unless the event is a `pull_request`,
+    /// `pull_request_review`, `pull_request_review_comment` or
+    /// `pull_request_review_thread` event.
+    pub fn is_draft_pr(&self) -> Option<bool> {
+        let pull_request = match &self.event.as_ref()?.specific {
+            WebhookEventPayload::PullRequest(payload) => &payload.pull_request,
+            WebhookEventPayload::PullRequestReview(payload) => &payload.pull_request,
+            WebhookEventPayload::PullRequestReviewComment(payload) => &payload.pull_request,
+            WebhookEventPayload::PullRequestReviewThread(payload) => &payload.pull_request,
+            _ => return None,
+        };
+        Some(pull_request.draft.unwrap_or(false))
+    }
+
+    /// Whether the event's pull request, or every open pull request the
+    /// event is [associated](Context::associated_pull_requests) with, is a
+    /// draft
+    ///
+    /// For pull request events, this is [`Context::is_draft_pr`]. Otherwise
+    /// the associated pull requests are requested to tell whether they are
+    /// drafts, once per delivery. Returns `None` if no open pull request is
+    /// associated with the event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a lookup is needed but the event has no
+    /// repository, no installation client is available, or a request fails.
+    pub async fn associated_pull_requests_are_drafts(&self) -> Result<Option<bool>> {
+        if let Some(draft) = self.is_draft_pr() {
+            return Ok(Some(draft));
+        }
+        self.pull_request_cache
+            .drafts
+            .get_or_try_init(|| self.lookup_drafts())
+            .await
+            .copied()
+    }
+
+    async fn lookup_drafts(&self) -> Result<Option<bool>> {
+        let pull_requests = self.associated_pull_requests().await?;
+        if pull_requests.is_empty() {
+            return Ok(None);
+        }
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+
+        let mut drafts = Vec::new();
+        for pull_request in pull_requests {
+            debug!("Looking up whether #{} is a draft", pull_request.number);
+            let state: DraftState = client
+                .get(
+                    format!(
+                        "/repos/{}/{}/pulls/{}",
+                        path_segment(&owner),
+                        path_segment(&repo),
+                        pull_request.number
+                    ),
+                    None::<&()>,
+                )
+                .await
+                .map_err(|e| {
+                    anyhow!("Failed to get pull request #{}: {}", pull_request.number, e)
+                })?;
+            if state.state == "open" {
+                drafts.push(state.draft);
+            }
+        }
+        Ok((!drafts.is_empty()).then(|| drafts.iter().all(|draft| *draft)))
+    }
+
+    async fn lookup_pull_requests(&self) -> Result<Vec<PrRef>> {
+        let Some(event) = &self.event else {
+            return Ok(Vec::new());
+        };
+        let listed = match &event.specific {
+            WebhookEventPayload::PullRequest(payload) => {
+                return Ok(pull_request_ref(&payload.pull_request));
+            }
+            WebhookEventPayload::PullRequestReview(payload) => {
+                return Ok(pull_request_ref(&payload.pull_request));
+            }
+            WebhookEventPayload::PullRequestReviewComment(payload) => {
+                return Ok(pull_request_ref(&payload.pull_request));
+            }
+            WebhookEventPayload::CheckRun(payload) => {
+                payload_pull_requests(&payload.check_run["pull_requests"])
+            }
+            WebhookEventPayload::CheckSuite(payload) => {
+                payload_pull_requests(&payload.check_suite["pull_requests"])
+            }
+            WebhookEventPayload::WorkflowRun(payload) => {
+                payload_pull_requests(&payload.workflow_run["pull_requests"])
+            }
+            _ => Vec::new(),
+        };
+        if !listed.is_empty() {
+            return Ok(listed);
+        }
+
+        let Some(sha) = self.head_sha() else {
+            return Ok(Vec::new());
+        };
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+
+        debug!("Looking up pull requests of commit {}", sha);
+        let pull_requests: Vec<Value> = client
+            .get(
+                format!(
+                    "/repos/{}/{}/commits/{}/pulls",
+                    path_segment(&owner),
+                    path_segment(&repo),
+                    path_segment(&sha)
+                ),
+                None::<&()>,
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to get pull requests of commit {}: {}", sha, e))?;
+        Ok(payload_pull_requests(&Value::Array(pull_requests)))
+    }
+}
+
+fn pull_request_ref(pull_request: &octocrab::models::pulls::PullRequest) -> Vec<PrRef> {
+    vec![PrRef {
+        number: pull_request.number,
+        head_ref: pull_request.head.ref_field.clone(),
+        head_sha: pull_request.head.sha.clone(),
+        base_ref: pull_request.base.ref_field.clone(),
+    }]
+}
+
+fn payload_pull_requests(pull_requests: &Value) -> Vec<PrRef> {
+    pull_requests
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|pull_request| {
+            parse_payload_part::<RawPullRequest>("pull request reference", pull_request)
+        })
+        .map(PrRef::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        pull_request, pull_request_payload, repository, user, webhook_event, MockGitHub,
+    };
+    use axum::{extract::Path, routing::get, Json, Router};
+    use serde_json::json;
+
+    fn pull_request_entry(number: u64, sha: &str) -> Value {
+        json!({
+            "id": number,
+            "number": number,
+            "url": format!("https://api.github.com/repos/octofer/app/pulls/{number}"),
+            "head": { "ref": "feature", "sha": sha, "repo": { "id": 1, "name": "app" } },
+            "base": { "ref": "main", "sha": "base000", "repo": { "id": 1, "name": "app" } },
+        })
+    }
+
+    fn check_run_payload(pull_requests: Value) -> Value {
+        json!({
+            "action": "completed",
+            "check_run": {
+                "id": 1,
+                "head_sha": "c0ffee",
+                "check_suite": { "id": 2, "head_branch": "feature" },
+                "pull_requests": pull_requests,
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("ci-bot"),
+        })
+    }
+
+    fn check_suite_payload() -> Value {
+        json!({
+            "action": "completed",
+            "check_suite": {
+                "id": 2,
+                "head_sha": "5ca1ab1e",
+                "head_branch": "feature",
+                "pull_requests": [pull_request_entry(4, "5ca1ab1e")],
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("ci-bot"),
+        })
+    }
+
+    fn workflow_run_payload() -> Value {
+        json!({
+            "action": "completed",
+            "workflow": null,
+            "workflow_run": {
+                "id": 3,
+                "head_sha": "d00d",
+                "head_branch": "feature",
+                "pull_requests": [pull_request_entry(5, "d00d")],
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("ci-bot"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_head_sha_and_listed_pull_requests() {
+        let mock_context = |event, payload| Context::new(Some(webhook_event(event, payload)), None);
+
+        let cases = [
+            (
+                mock_context(
+                    "check_run",
+                    check_run_payload(json!([pull_request_entry(3, "c0ffee")])),
+                ),
+                "c0ffee",
+                3,
+            ),
+            (
+                mock_context("check_suite", check_suite_payload()),
+                "5ca1ab1e",
+                4,
+            ),
+            (
+                mock_context("workflow_run", workflow_run_payload()),
+                "d00d",
+                5,
+            ),
+            (
+                mock_context(
+                    "pull_request",
+                    pull_request_payload("opened", 6, "beefcafe"),
+                ),
+                "beefcafe",
+                6,
+            ),
+        ];
+        for (context, sha, number) in cases {
+            assert_eq!(context.head_sha().as_deref(), Some(sha));
+            let pull_requests = context.associated_pull_requests().await.unwrap();
+            assert_eq!(pull_requests.len(), 1);
+            assert_eq!(pull_requests[0].number, number);
+            assert_eq!(pull_requests[0].head_sha, sha);
+            assert_eq!(pull_requests[0].base_ref, "main");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fork_pull_requests_are_looked_up_once() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/commits/{sha}/pulls",
+            get(|Path(sha): Path<String>| async move {
+                Json(json!([pull_request("octofer", "app", 9, &sha)]))
+            }),
+        ))
+        .await;
+        let context = mock.context("check_run", check_run_payload(json!([])));
+
+        let pull_requests = context.associated_pull_requests().await.unwrap();
+        assert_eq!(
+            pull_requests,
+            [PrRef {
+                number: 9,
+                head_ref: "feature".to_string(),
+                head_sha: "c0ffee".to_string(),
+                base_ref: "main".to_string(),
+            }]
+        );
+
+        // Another handler of the same delivery reuses the result
+        let other_handler = context.clone();
+        assert_eq!(
+            other_handler.associated_pull_requests().await.unwrap(),
+            pull_requests
+        );
+        assert_eq!(
+            other_handler.pull_request_cache.get().map(<[_]>::len),
+            Some(1)
+        );
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/repos/octofer/app/commits/c0ffee/pulls");
+    }
+
+    #[tokio::test]
+    async fn test_draft_pull_requests() {
+        let draft_payload = |draft: bool| {
+            let mut payload = pull_request_payload("opened", 6, "beefcafe");
+            payload["pull_request"]["draft"] = json!(draft);
+            payload
+        };
+        let context = |event, payload| Context::new(Some(webhook_event(event, payload)), None);
+        assert_eq!(
+            context("pull_request", draft_payload(true)).is_draft_pr(),
+            Some(true)
+        );
+        assert_eq!(
+            context("pull_request", draft_payload(false)).is_draft_pr(),
+            Some(false)
+        );
+        assert_eq!(
+            context("check_suite", check_suite_payload()).is_draft_pr(),
+            None
+        );
+
+        // Check events look up their pull requests, once per delivery
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/pulls/{number}",
+            get(|Path(number): Path<u64>| async move {
+                let mut pull_request = pull_request("octofer", "app", number, "c0ffee");
+                pull_request["draft"] = json!(number == 3);
+                Json(pull_request)
+            }),
+        ))
+        .await;
+        let check_run = |numbers: &[u64]| {
+            let entries: Vec<_> = numbers
+                .iter()
+                .map(|number| pull_request_entry(*number, "c0ffee"))
+                .collect();
+            mock.context("check_run", check_run_payload(json!(entries)))
+        };
+
+        let draft = check_run(&[3]);
+        assert_eq!(
+            draft.associated_pull_requests_are_drafts().await.unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            draft.associated_pull_requests_are_drafts().await.unwrap(),
+            Some(true)
+        );
+        assert_eq!(mock.requests().len(), 1);
+        assert_eq!(mock.requests()[0].path, "/repos/octofer/app/pulls/3");
+
+        // A commit in a draft and a ready pull request is not a draft's
+        let mixed = check_run(&[3, 4]);
+        assert_eq!(
+            mixed.associated_pull_requests_are_drafts().await.unwrap(),
+            Some(false)
+        );
+    }
+}
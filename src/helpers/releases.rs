@@ -0,0 +1,699 @@
+//! Release helpers
+//!
+//! Typed access to the release of `release` events, uploading assets to it,
+//! and building its notes from the pull requests merged since a previous tag.
+//!
+//! GitHub serves asset uploads from a separate host, `uploads.github.com` or
+//! `/api/uploads` of a GitHub Enterprise Server, named by the release's
+//! `upload_url`. [`Context::upload_release_asset`] sends the upload to that
+//! URL as is, instead of resolving it against the API base URI. Large assets
+//! can be streamed with [`Context::upload_release_asset_stream`] rather than
+//! read into memory first.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::releases::changelog;
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_release(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let Some(release) = context.release() else {
+//!             return Ok(());
+//!         };
+//!         let merged = context
+//!             .list_merged_prs_between("v1.0.0", &release.tag_name)
+//!             .await?;
+//!         context.update_release_notes(&changelog(&merged)).await?;
+//!         context
+//!             .upload_release_asset("checksums.txt", "text/plain", "...")
+//!             .await?;
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Method, StatusCode, Uri};
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use octocrab::models::repos::{Asset, Release};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::github::transport::{UploadBody, UploadClient};
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Size of the chunks in which buffered assets are sent
+const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Number of commits whose pull requests are looked up concurrently
+const PR_LOOKUP_CONCURRENCY: usize = 8;
+
+/// Error returned when a release already has an asset with the uploaded name
+///
+/// Delete the existing asset or choose another name to upload it.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Release {release_id} already has an asset named '{name}'")]
+pub struct AssetNameConflict {
+    /// ID of the release
+    pub release_id: u64,
+    /// Name of the asset
+    pub name: String,
+}
+
+/// Pull request merged between two tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedPullRequest {
+    /// Number of the pull request
+    pub number: u64,
+    /// Title of the pull request
+    pub title: String,
+    /// Login of the author, if the account still exists
+    pub author: Option<String>,
+    /// Names of the labels of the pull request
+    pub labels: Vec<String>,
+    /// Web URL of the pull request
+    pub html_url: String,
+    /// SHA of the commit the pull request was merged with
+    pub merge_commit_sha: String,
+}
+
+/// Render `pull_requests` as a Markdown list, one line per pull request
+///
+/// Lines read `- Title (#12) by @login`, in the order of the pull requests.
+pub fn changelog(pull_requests: &[MergedPullRequest]) -> String {
+    pull_requests
+        .iter()
+        .map(|pr| match &pr.author {
+            Some(author) => format!("- {} (#{}) by @{}\n", pr.title, pr.number, author),
+            None => format!("- {} (#{})\n", pr.title, pr.number),
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct RawPullRequest {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    user: Option<RawUser>,
+    #[serde(default)]
+    labels: Vec<RawLabel>,
+    html_url: String,
+    #[serde(default)]
+    merged_at: Option<String>,
+    #[serde(default)]
+    merge_commit_sha: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct RawLabel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct UploadError {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    errors: Vec<Value>,
+}
+
+impl Context {
+    /// Get the release of a `release` event
+    ///
+    /// Returns `None` for other events.
+    pub fn release(&self) -> Option<Release> {
+        match self.event.as_ref().map(|e| &e.specific) {
+            Some(WebhookEventPayload::Release(payload)) => {
+                parse_payload_part("release", &payload.release)
+            }
+            _ => None,
+        }
+    }
+
+    /// Upload `bytes` as asset `name` of the event's release
+    ///
+    /// The asset is sent in chunks of 1 MiB; use
+    /// [`Self::upload_release_asset_stream`] to avoid holding it in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AssetNameConflict`] if the release already has an asset
+    /// named `name`, or an error if the event is not a release event, no
+    /// installation client is available, or the upload fails.
+    pub async fn upload_release_asset(
+        &self,
+        name: &str,
+        content_type: &str,
+        bytes: impl Into<Bytes>,
+    ) -> Result<Asset> {
+        let bytes = bytes.into();
+        let chunks: Vec<std::io::Result<Bytes>> = (0..bytes.len())
+            .step_by(UPLOAD_CHUNK_SIZE)
+            .map(|start| Ok(bytes.slice(start..(start + UPLOAD_CHUNK_SIZE).min(bytes.len()))))
+            .collect();
+        self.upload_release_asset_stream(
+            name,
+            content_type,
+            bytes.len() as u64,
+            futures::stream::iter(chunks),
+        )
+        .await
+    }
+
+    /// Upload the chunks of `stream` as asset `name` of the event's release
+    ///
+    /// GitHub requires the size of the asset up front, so `length` must be
+    /// the total number of bytes of `stream`. The chunks are sent as they are
+    /// produced, e.g. by a `tokio_util::io::ReaderStream` over a file. The
+    /// upload is not retried, since the stream can only be read once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AssetNameConflict`] if the release already has an asset
+    /// named `name`, or an error if the event is not a release event, no
+    /// installation client is available, `stream` fails, or the upload
+    /// fails.
+    pub async fn upload_release_asset_stream<S>(
+        &self,
+        name: &str,
+        content_type: &str,
+        length: u64,
+        stream: S,
+    ) -> Result<Asset>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let release = self
+            .release()
+            .ok_or_else(|| anyhow!("Event is not a release event"))?;
+        let (origin, path) = upload_target(&release.upload_url)?;
+        let client = self.upload_client(origin).await?;
+
+        let body: UploadBody = BodyExt::boxed(StreamBody::new(stream.map_ok(Frame::data)));
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}?name={}", path, path_segment(name)))
+            .header(CONTENT_TYPE, content_type)
+            .header(CONTENT_LENGTH, length)
+            .body(body)
+            .map_err(|e| anyhow!("Invalid upload of asset {}: {}", name, e))?;
+        let response = client.send(request).await?;
+
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| anyhow!("Failed to read upload response of {}: {}", name, e))?
+            .to_bytes();
+        if status.is_success() {
+            let asset: Asset = serde_json::from_slice(&body)
+                .map_err(|e| anyhow!("Invalid upload response of {}: {}", name, e))?;
+            debug!("Uploaded asset {} to release {}", asset.name, release.id);
+            return Ok(asset);
+        }
+
+        let error: UploadError = serde_json::from_slice(&body).unwrap_or(UploadError {
+            message: String::from_utf8_lossy(&body).into_owned(),
+            errors: Vec::new(),
+        });
+        if status == StatusCode::UNPROCESSABLE_ENTITY
+            && error.errors.iter().any(|e| e["code"] == "already_exists")
+        {
+            return Err(AssetNameConflict {
+                release_id: release.id.into_inner(),
+                name: name.to_string(),
+            }
+            .into());
+        }
+        Err(anyhow!(
+            "Failed to upload asset {} to release {}: {} {}",
+            name,
+            release.id,
+            status,
+            error.message
+        ))
+    }
+
+    /// List the pull requests merged between tags `tag_a` and `tag_b` of the
+    /// event's repository
+    ///
+    /// Compares the tags, see [`Self::compare`], and looks up the pull
+    /// requests associated with each commit of the comparison. Only pull
+    /// requests merged with one of these commits are listed, each once, in
+    /// the order they were merged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, or a request fails.
+    pub async fn list_merged_prs_between(
+        &self,
+        tag_a: &str,
+        tag_b: &str,
+    ) -> Result<Vec<MergedPullRequest>> {
+        let comparison = self.compare(tag_a, tag_b).await?;
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let repo_route = format!("/repos/{}/{}", path_segment(&owner), path_segment(&repo));
+
+        let positions: HashMap<&str, usize> = comparison
+            .commits
+            .iter()
+            .enumerate()
+            .map(|(position, sha)| (sha.as_str(), position))
+            .collect();
+        let lookups: Vec<_> = comparison
+            .commits
+            .iter()
+            .map(|sha| {
+                let client = client.clone();
+                let route = format!("{}/commits/{}/pulls", repo_route, sha);
+                async move {
+                    client
+                        .get(&route, Some(&json!({ "per_page": 100 })))
+                        .await
+                        .map_err(|e| anyhow!("Failed to list pull requests of {}: {}", route, e))
+                }
+            })
+            .collect();
+        let associated: Vec<Vec<RawPullRequest>> = futures::stream::iter(lookups)
+            .buffered(PR_LOOKUP_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let mut merged: HashMap<u64, (usize, MergedPullRequest)> = HashMap::new();
+        for pr in associated.into_iter().flatten() {
+            let Some(sha) = pr.merge_commit_sha.filter(|_| pr.merged_at.is_some()) else {
+                continue;
+            };
+            let Some(&position) = positions.get(sha.as_str()) else {
+                continue;
+            };
+            merged.entry(pr.number).or_insert_with(|| {
+                (
+                    position,
+                    MergedPullRequest {
+                        number: pr.number,
+                        title: pr.title,
+                        author: pr.user.map(|user| user.login),
+                        labels: pr.labels.into_iter().map(|label| label.name).collect(),
+                        html_url: pr.html_url,
+                        merge_commit_sha: sha,
+                    },
+                )
+            });
+        }
+
+        let mut merged: Vec<_> = merged.into_values().collect();
+        merged.sort_by_key(|(position, _)| *position);
+        Ok(merged.into_iter().map(|(_, pr)| pr).collect())
+    }
+
+    /// Replace the notes of the event's release with `markdown`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not a release event, no installation
+    /// client is available, or the request fails.
+    pub async fn update_release_notes(&self, markdown: &str) -> Result<Release> {
+        let release = self
+            .release()
+            .ok_or_else(|| anyhow!("Event is not a release event"))?;
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/releases/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            release.id
+        );
+
+        client
+            .patch(&route, Some(&json!({ "body": markdown })))
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to update notes of release {}: {}",
+                    release.tag_name,
+                    e
+                )
+            })
+    }
+
+    /// Get the upload client of the event's installation for `origin`
+    async fn upload_client(&self, origin: Uri) -> Result<UploadClient> {
+        match (&self.github_client, self.installation_id) {
+            (Some(client), Some(installation_id)) => Ok(client
+                .handler_upload_client(
+                    installation_id,
+                    origin,
+                    self.api_budget.clone(),
+                    self.audit_trail.clone(),
+                    self.dry_run.clone(),
+                )
+                .await?),
+            _ => Err(anyhow!("No installation client available for this event")),
+        }
+    }
+}
+
+/// Split a release's `upload_url` into its origin and path
+///
+/// The URL is a template like
+/// `https://uploads.github.com/repos/o/r/releases/1/assets{?name,label}`.
+fn upload_target(upload_url: &str) -> Result<(Uri, String)> {
+    let url = upload_url.split('{').next().unwrap_or_default();
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| anyhow!("Invalid upload URL '{}': {}", upload_url, e))?;
+    let (Some(scheme), Some(authority)) = (uri.scheme(), uri.authority()) else {
+        return Err(anyhow!("Upload URL '{}' is not absolute", upload_url));
+    };
+    let origin = Uri::builder()
+        .scheme(scheme.clone())
+        .authority(authority.clone())
+        .path_and_query("/")
+        .build()
+        .map_err(|e| anyhow!("Invalid upload URL '{}': {}", upload_url, e))?;
+    Ok((origin, uri.path().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, webhook_event, MockGitHub};
+    use axum::extract::{Path, Query};
+    use axum::http::HeaderMap;
+    use axum::response::IntoResponse;
+    use axum::routing::{get, patch, post};
+    use axum::{Json, Router};
+
+    const UPLOAD_PATH: &str = "/api/uploads/repos/octofer/app/releases/7/assets";
+
+    fn release(upload_url: &str) -> Value {
+        json!({
+            "url": "https://api.github.com/repos/octofer/app/releases/7",
+            "html_url": "https://github.com/octofer/app/releases/tag/v1.1.0",
+            "assets_url": "https://api.github.com/repos/octofer/app/releases/7/assets",
+            "upload_url": upload_url,
+            "tarball_url": null,
+            "zipball_url": null,
+            "id": 7,
+            "node_id": "RE_7",
+            "tag_name": "v1.1.0",
+            "target_commitish": "main",
+            "name": "v1.1.0",
+            "body": null,
+            "draft": false,
+            "prerelease": false,
+            "created_at": "2024-01-01T00:00:00Z",
+            "published_at": "2024-01-01T00:00:00Z",
+            "author": null,
+            "assets": [],
+        })
+    }
+
+    fn release_context(mock: &MockGitHub, upload_url: &str) -> Context {
+        mock.context(
+            "release",
+            json!({
+                "action": "published",
+                "release": release(upload_url),
+                "repository": repository("octofer", "app"),
+                "sender": user("octocat"),
+            }),
+        )
+    }
+
+    fn asset(name: &str, size: usize) -> Value {
+        json!({
+            "url": "https://api.github.com/repos/octofer/app/releases/assets/1",
+            "browser_download_url": format!("https://github.com/octofer/app/releases/download/v1.1.0/{name}"),
+            "id": 1,
+            "node_id": "RA_1",
+            "name": name,
+            "label": null,
+            "state": "uploaded",
+            "content_type": "application/gzip",
+            "size": size,
+            "digest": null,
+            "download_count": 0,
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "uploader": null,
+        })
+    }
+
+    /// Start an upload host answering with an asset of the uploaded size, or
+    /// a conflict for assets named `taken.tar.gz`
+    async fn upload_host() -> MockGitHub {
+        MockGitHub::start(
+            Router::new().route(
+                UPLOAD_PATH,
+                post(
+                    |Query(query): Query<HashMap<String, String>>,
+                     headers: HeaderMap,
+                     body: Bytes| async move {
+                        assert_eq!(headers["authorization"], "Bearer ghs_test");
+                        assert_eq!(headers["content-length"], body.len().to_string().as_str());
+                        let name = &query["name"];
+                        if name == "taken.tar.gz" {
+                            let errors = json!([{
+                                "resource": "ReleaseAsset",
+                                "code": "already_exists",
+                                "field": "name",
+                            }]);
+                            return (
+                                StatusCode::UNPROCESSABLE_ENTITY,
+                                Json(json!({ "message": "Validation Failed", "errors": errors })),
+                            )
+                                .into_response();
+                        }
+                        Json(asset(name, body.len())).into_response()
+                    },
+                ),
+            ),
+        )
+        .await
+    }
+
+    #[test]
+    fn test_release_accessor() {
+        let payload = json!({
+            "action": "published",
+            "release": release("https://uploads.github.com/repos/octofer/app/releases/7/assets{?name,label}"),
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        });
+        let context = Context::new(Some(webhook_event("release", payload)), None);
+        let release = context.release().unwrap();
+        assert_eq!(release.tag_name, "v1.1.0");
+        assert_eq!(release.id.into_inner(), 7);
+
+        let context = Context::new(None, None);
+        assert!(context.release().is_none());
+    }
+
+    #[test]
+    fn test_upload_target() {
+        let (origin, path) = upload_target(
+            "https://uploads.github.com/repos/octofer/app/releases/7/assets{?name,label}",
+        )
+        .unwrap();
+        assert_eq!(origin, "https://uploads.github.com/");
+        assert_eq!(path, "/repos/octofer/app/releases/7/assets");
+
+        let (origin, path) = upload_target(
+            "https://ghe.example.com/api/uploads/repos/octofer/app/releases/7/assets{?name,label}",
+        )
+        .unwrap();
+        assert_eq!(origin, "https://ghe.example.com/");
+        assert_eq!(path, UPLOAD_PATH);
+
+        assert!(upload_target("/repos/octofer/app/releases/7/assets").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upload_release_asset_goes_to_upload_host() {
+        let mock = MockGitHub::start(Router::new()).await;
+        let uploads = upload_host().await;
+        let context = release_context(
+            &mock,
+            &format!("{}{}{{?name,label}}", uploads.uri(), UPLOAD_PATH),
+        );
+
+        // Larger than a chunk, so it is sent in several frames
+        let bytes = vec![7u8; UPLOAD_CHUNK_SIZE + 10];
+        let asset = context
+            .upload_release_asset("app v1.1.0.tar.gz", "application/gzip", bytes)
+            .await
+            .unwrap();
+        assert_eq!(asset.name, "app v1.1.0.tar.gz");
+        assert_eq!(asset.size as usize, UPLOAD_CHUNK_SIZE + 10);
+
+        let requests = uploads.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, UPLOAD_PATH);
+        assert_eq!(
+            requests[0].query.as_deref(),
+            Some("name=app%20v1.1.0.tar.gz")
+        );
+        assert_eq!(requests[0].headers["content-type"], "application/gzip");
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upload_client_ignores_api_base_path() {
+        // Like GitHub Enterprise Server, the API lives under /api/v3 while
+        // uploads go to /api/uploads
+        let uploads = upload_host().await;
+        let transport = uploads
+            .transport()
+            .with_base_uri(format!("{}/api/v3", uploads.uri()).parse().unwrap());
+        let (origin, path) =
+            upload_target(&format!("{}{}{{?name,label}}", uploads.uri(), UPLOAD_PATH)).unwrap();
+        let client = transport
+            .upload_client(origin, "ghs_test", None, None, None)
+            .unwrap();
+
+        let body = http_body_util::Full::new(Bytes::from_static(b"asset"))
+            .map_err(|never| match never {})
+            .boxed();
+        let request = http::Request::post(format!("{}?name=asset.txt", path))
+            .header(CONTENT_LENGTH, 5)
+            .body(body)
+            .unwrap();
+        let response = client.send(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(uploads.requests()[0].path, UPLOAD_PATH);
+    }
+
+    #[tokio::test]
+    async fn test_upload_release_asset_name_conflict() {
+        let mock = MockGitHub::start(Router::new()).await;
+        let uploads = upload_host().await;
+        let context = release_context(
+            &mock,
+            &format!("{}{}{{?name,label}}", uploads.uri(), UPLOAD_PATH),
+        );
+
+        let stream = futures::stream::iter(vec![Ok(Bytes::from_static(b"tar"))]);
+        let error = context
+            .upload_release_asset_stream("taken.tar.gz", "application/gzip", 3, stream)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<AssetNameConflict>(),
+            Some(&AssetNameConflict {
+                release_id: 7,
+                name: "taken.tar.gz".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_merged_prs_between_tags() {
+        fn pr(number: u64, merge_commit_sha: &str, merged: bool) -> Value {
+            json!({
+                "number": number,
+                "title": format!("Change {number}"),
+                "user": user("alice"),
+                "labels": [{ "name": "enhancement" }],
+                "html_url": format!("https://github.com/octofer/app/pull/{number}"),
+                "merged_at": merged.then_some("2024-01-01T00:00:00Z"),
+                "merge_commit_sha": merge_commit_sha,
+            })
+        }
+
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/compare/{basehead}",
+                    get(|| async {
+                        Json(json!({
+                            "status": "ahead",
+                            "ahead_by": 3,
+                            "behind_by": 0,
+                            "total_commits": 3,
+                            "commits": [{ "sha": "aaa" }, { "sha": "bbb" }, { "sha": "ccc" }],
+                            "files": [],
+                        }))
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/commits/{sha}/pulls",
+                    get(|Path(sha): Path<String>| async move {
+                        Json(match sha.as_str() {
+                            // Squash-merged, then listed again for its commit
+                            "aaa" => json!([pr(2, "aaa", true)]),
+                            "bbb" => json!([pr(2, "aaa", true), pr(4, "bbb", false)]),
+                            // Merged before, with a commit cherry-picked later
+                            "ccc" => json!([pr(1, "ccc", true), pr(3, "zzz", true)]),
+                            _ => json!([]),
+                        })
+                    }),
+                ),
+        )
+        .await;
+        let context = release_context(&mock, "https://uploads.github.com/");
+
+        let merged = context
+            .list_merged_prs_between("v1.0.0", "v1.1.0")
+            .await
+            .unwrap();
+        let numbers: Vec<u64> = merged.iter().map(|pr| pr.number).collect();
+        assert_eq!(numbers, [2, 1]);
+        assert_eq!(merged[0].author.as_deref(), Some("alice"));
+        assert_eq!(merged[0].labels, ["enhancement"]);
+        assert_eq!(
+            changelog(&merged),
+            "- Change 2 (#2) by @alice\n- Change 1 (#1) by @alice\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_release_notes() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/releases/7",
+            patch(|Json(body): Json<Value>| async move {
+                let mut release = release("https://uploads.github.com/");
+                release["body"] = body["body"].clone();
+                Json(release)
+            }),
+        ))
+        .await;
+        let context = release_context(&mock, "https://uploads.github.com/");
+
+        let release = context
+            .update_release_notes("- Change 1 (#1)")
+            .await
+            .unwrap();
+        assert_eq!(release.body.as_deref(), Some("- Change 1 (#1)"));
+        assert_eq!(
+            mock.requests()[0].body,
+            json!({ "body": "- Change 1 (#1)" })
+        );
+    }
+}
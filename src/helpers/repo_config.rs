@@ -0,0 +1,355 @@
+//! Repository configuration helpers
+//!
+//! Read a YAML configuration file committed to the event's repository, e.g.
+//! `.github/octofer.yml`, from its default branch. Files are cached per
+//! repository and path for [`REPO_CONFIG_CACHE_TTL`], in a [`RepoConfigCache`]
+//! shared by the deliveries of an app, and every path read is watched: a push to the default branch adding, modifying or removing it
+//! evicts the cached file before any handler of the push runs, so the next
+//! read sees the new configuration. Watching is done by the dispatcher
+//! itself, not by a registered handler, and does not affect the app's own
+//! push handlers.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use serde::Deserialize;
+//! use std::sync::Arc;
+//!
+//! #[derive(Deserialize)]
+//! struct Settings {
+//!     greeting: String,
+//! }
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_issue(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if let Some(settings) = context.repo_config::<Settings>(".github/octofer.yml").await? {
+//!             println!("{}", settings.greeting);
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+use crate::helpers::{fetch_optional_file, path_segment, ExpiringCache};
+use crate::Context;
+
+/// How long a read configuration file is reused
+pub const REPO_CONFIG_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Most configuration files kept in a [`RepoConfigCache`]
+pub const REPO_CONFIG_CACHE_MAX_ENTRIES: usize = 1_000;
+
+/// Most commits listed by a push event; longer pushes may change files that
+/// are not listed
+const MAX_PUSH_COMMITS: usize = 2048;
+
+/// Cache key: installation, repository and path
+type ConfigKey = (Option<u64>, String, String);
+
+/// Configuration files read by [`Context::repo_config`], `None` for missing
+/// files
+///
+/// Shared by the contexts of all deliveries of a dispatcher. Holds at most
+/// [`REPO_CONFIG_CACHE_MAX_ENTRIES`] files, each for
+/// [`REPO_CONFIG_CACHE_TTL`]; when full, expired files are dropped, then the
+/// oldest.
+#[derive(Clone, Debug)]
+pub struct RepoConfigCache(Arc<ExpiringCache<ConfigKey, Option<String>>>);
+
+impl Default for RepoConfigCache {
+    fn default() -> Self {
+        Self::new(REPO_CONFIG_CACHE_MAX_ENTRIES, REPO_CONFIG_CACHE_TTL)
+    }
+}
+
+impl RepoConfigCache {
+    fn new(max_entries: usize, ttl: Duration) -> Self {
+        Self(Arc::new(ExpiringCache::new(max_entries, ttl)))
+    }
+
+    /// Forget the cached configuration files of the repository `full_name`,
+    /// of all installations
+    pub(crate) fn forget_repository(&self, full_name: &str) {
+        let full_name = full_name.to_lowercase();
+        self.0.retain(|(_, repository, _)| *repository != full_name);
+    }
+
+    /// Forget the cached configuration files of the repositories of the
+    /// account `login`, of all installations
+    pub(crate) fn forget_owner(&self, login: &str) {
+        let prefix = format!("{}/", login.to_lowercase());
+        self.0
+            .retain(|(_, repository, _)| !repository.starts_with(&prefix));
+    }
+
+    /// Forget the cached configuration files changed by the context's push
+    /// to the default branch, returning how many were forgotten
+    ///
+    /// Pushes that may change files they do not list, i.e. forced pushes,
+    /// pushes creating the branch and pushes with too many commits, forget
+    /// every file of the repository.
+    pub(crate) fn forget_pushed(&self, context: &Context) -> usize {
+        let Some(event) = context.event() else {
+            return 0;
+        };
+        let WebhookEventPayload::Push(push) = &event.specific else {
+            return 0;
+        };
+        let Some(repository) = &event.repository else {
+            return 0;
+        };
+        let Some(full_name) = &repository.full_name else {
+            return 0;
+        };
+        if let Some(branch) = &repository.default_branch {
+            if push.r#ref != format!("refs/heads/{}", branch) {
+                return 0;
+            }
+        }
+
+        let full_name = full_name.to_lowercase();
+        let everything = push.forced || push.created || push.commits.len() >= MAX_PUSH_COMMITS;
+        let changed = |path: &str| {
+            push.commits.iter().any(|commit| {
+                commit
+                    .added
+                    .iter()
+                    .chain(&commit.modified)
+                    .chain(&commit.removed)
+                    .any(|file| file == path)
+            })
+        };
+
+        self.0.retain(|(_, repository, path)| {
+            *repository != full_name || !(everything || changed(path))
+        })
+    }
+}
+
+impl Context {
+    /// Read the YAML configuration file at `path` in the event's repository
+    ///
+    /// The file is read from the default branch with the installation
+    /// client and cached for [`REPO_CONFIG_CACHE_TTL`], or until a push
+    /// changes it. Returns `None` if the file does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, the request fails, or the file cannot be
+    /// deserialized into `T`.
+    pub async fn repo_config<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>> {
+        let (owner, repo) = self.require_repository()?;
+        let path = path.trim_start_matches('/');
+        let key = (
+            self.installation_id,
+            format!("{}/{}", owner, repo).to_lowercase(),
+            path.to_string(),
+        );
+
+        let contents = match self.repo_config_cache.0.get(&key) {
+            Some(contents) => contents,
+            None => {
+                let client = self.require_installation_client().await?;
+                let repo_route = format!("/repos/{}/{}", path_segment(&owner), path_segment(&repo));
+                let contents = fetch_optional_file(&client, &repo_route, path).await?;
+                debug!("Read {} of {}/{}", path, owner, repo);
+                self.repo_config_cache.0.insert(key, contents.clone());
+                contents
+            }
+        };
+
+        contents
+            .map(|yaml| {
+                serde_yaml::from_str(&yaml)
+                    .map_err(|e| anyhow!("Invalid configuration in {}: {}", path, e))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::Dispatcher;
+    use crate::testing::{push_payload, repository, webhook_event, MockGitHub};
+    use axum::{routing::get, Json, Router};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Settings {
+        greeting: String,
+    }
+
+    fn push(repo: &str, modified: &[&str]) -> Value {
+        let mut payload = push_payload("abc123");
+        let mut repository = repository("octofer", repo);
+        repository["default_branch"] = json!("main");
+        payload["repository"] = repository;
+        payload["commits"] = json!([{
+            "id": "abc123",
+            "tree_id": "def456",
+            "distinct": true,
+            "message": "Update configuration",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "url": "https://github.com/octofer/app/commit/abc123",
+            "author": { "name": "octocat", "email": "octocat@github.com" },
+            "committer": { "name": "octocat", "email": "octocat@github.com" },
+            "added": [],
+            "modified": modified,
+            "removed": [],
+        }]);
+        payload
+    }
+
+    #[tokio::test]
+    async fn test_repo_config_is_refetched_after_a_push_changing_it() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/config-watched/contents/.github/octofer.yml",
+            get(|| async {
+                Json(json!({ "type": "file", "content": STANDARD.encode("greeting: hello") }))
+            }),
+        ))
+        .await;
+        let dispatcher = Dispatcher::new(Some(Arc::new(mock.client())));
+        dispatcher
+            .on(
+                "push",
+                |_context: Context, _extra: Arc<()>| async { Ok(()) },
+                Arc::new(()),
+            )
+            .await;
+        // Share the cache of the dispatcher's deliveries
+        let mut context = mock.context("push", push("config-watched", &[]));
+        context.repo_config_cache = dispatcher
+            .context(context.event.clone().unwrap())
+            .repo_config_cache;
+        let reads = || {
+            mock.requests()
+                .iter()
+                .filter(|request| request.path.ends_with("/contents/.github/octofer.yml"))
+                .count()
+        };
+        let expected = Some(Settings {
+            greeting: "hello".to_string(),
+        });
+
+        assert_eq!(
+            context.repo_config(".github/octofer.yml").await.unwrap(),
+            expected
+        );
+        assert_eq!(
+            context.repo_config("/.github/octofer.yml").await.unwrap(),
+            expected
+        );
+        assert_eq!(reads(), 1);
+
+        // Pushes not touching the file keep it cached
+        let unrelated = dispatcher.context(webhook_event(
+            "push",
+            push("config-watched", &["README.md"]),
+        ));
+        dispatcher.dispatch(unrelated).await.unwrap();
+        context
+            .repo_config::<Settings>(".github/octofer.yml")
+            .await
+            .unwrap();
+        assert_eq!(reads(), 1);
+
+        let changed = dispatcher.context(webhook_event(
+            "push",
+            push("config-watched", &[".github/octofer.yml"]),
+        ));
+        let report = dispatcher.dispatch(changed).await.unwrap();
+        // The app's own push handler still runs
+        assert_eq!(report.results.len(), 1);
+        assert_eq!(
+            context.repo_config(".github/octofer.yml").await.unwrap(),
+            expected
+        );
+        assert_eq!(reads(), 2);
+    }
+
+    #[test]
+    fn test_repo_config_cache_is_bounded() {
+        let key = |repo: &str| {
+            (
+                Some(1),
+                format!("octofer/{}", repo),
+                "config.yml".to_string(),
+            )
+        };
+
+        let cache = RepoConfigCache::new(2, REPO_CONFIG_CACHE_TTL);
+        for repo in ["a", "b", "c"] {
+            cache.0.insert(key(repo), None);
+        }
+        // The oldest file made room
+        assert_eq!(cache.0.len(), 2);
+        assert_eq!(cache.0.get(&key("a")), None);
+        assert_eq!(cache.0.get(&key("c")), Some(None));
+
+        // Expired files are dropped
+        let cache = RepoConfigCache::new(2, Duration::ZERO);
+        cache.0.insert(key("a"), None);
+        cache.0.insert(key("b"), None);
+        cache.0.insert(key("c"), None);
+        assert_eq!(cache.0.len(), 1);
+        assert_eq!(cache.0.get(&key("c")), None);
+        assert_eq!(cache.0.len(), 0);
+
+        // Caches of separate dispatchers are independent
+        let (first, second) = (Dispatcher::new(None), Dispatcher::new(None));
+        let event = webhook_event("push", push("config-separate", &[]));
+        first
+            .context(event.clone())
+            .repo_config_cache
+            .0
+            .insert(key("d"), None);
+        assert_eq!(first.context(event.clone()).repo_config_cache.0.len(), 1);
+        assert_eq!(second.context(event).repo_config_cache.0.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_missing_repo_config() {
+        use axum::{http::StatusCode, response::IntoResponse};
+
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/config-missing/contents/{*path}",
+            get(|| async {
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "message": "Not Found" })),
+                )
+                    .into_response()
+            }),
+        ))
+        .await;
+        let context = mock.context("push", push("config-missing", &[]));
+
+        assert_eq!(
+            context
+                .repo_config::<Settings>(".github/octofer.yml")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Repository settings helpers
+//!
+//! [`Context::apply_repo_settings`] brings the merge strategies, branch
+//! cleanup, default branch and topics of the event's repository in line with
+//! a [`RepoSettings`]. GitHub takes these from two endpoints, and refuses a
+//! whole update for one invalid value, e.g. a default branch that does not
+//! exist; the settings are sent together first, then one at a time if GitHub
+//! refused them, so each setting that can be applied is. Failures are
+//! collected per setting in the [`RepoSettingsOutcome`].
+//!
+//! Changing repository settings needs the `administration: write`
+//! permission; without it, settings fail with [`MissingPermission`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::repo_settings::RepoSettings;
+//! use octofer::helpers::repository::RepoChange;
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_repository(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if context.repo_change() != Some(RepoChange::Created) {
+//!             return Ok(());
+//!         }
+//!         let settings = RepoSettings {
+//!             allow_merge_commit: Some(false),
+//!             delete_branch_on_merge: Some(true),
+//!             topics: Some(vec!["managed".to_string()]),
+//!             ..Default::default()
+//!         };
+//!         let outcome = context.apply_repo_settings(&settings).await?;
+//!         for failure in &outcome.failures {
+//!             println!("Could not set {}: {:#}", failure.setting, failure.error);
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+//!
+//! [`MissingPermission`]: crate::helpers::installation::MissingPermission
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::debug;
+
+use crate::helpers::branch_protection::RepoAdmin;
+use crate::Context;
+
+/// Desired settings of a repository, see [`Context::apply_repo_settings`]
+///
+/// Unset settings are left as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoSettings {
+    /// Allow squash merges of pull requests
+    pub allow_squash_merge: Option<bool>,
+    /// Allow merge commits of pull requests
+    pub allow_merge_commit: Option<bool>,
+    /// Allow rebase merges of pull requests
+    pub allow_rebase_merge: Option<bool>,
+    /// Delete head branches once their pull request is merged
+    pub delete_branch_on_merge: Option<bool>,
+    /// Default branch, which must exist
+    pub default_branch: Option<String>,
+    /// Topics the repository must have, added to its current topics
+    pub topics: Option<Vec<String>>,
+}
+
+impl RepoSettings {
+    /// Settings set through the repository endpoint, by field name
+    fn repository_fields(&self) -> Vec<(&'static str, Value)> {
+        let flags = [
+            ("allow_squash_merge", self.allow_squash_merge),
+            ("allow_merge_commit", self.allow_merge_commit),
+            ("allow_rebase_merge", self.allow_rebase_merge),
+            ("delete_branch_on_merge", self.delete_branch_on_merge),
+        ];
+        let mut fields: Vec<_> = flags
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, Value::Bool(value?))))
+            .collect();
+        if let Some(branch) = &self.default_branch {
+            fields.push(("default_branch", json!(branch)));
+        }
+        fields
+    }
+}
+
+/// A setting that could not be applied
+#[derive(Debug)]
+pub struct SettingFailure {
+    /// Name of the setting, as a field of [`RepoSettings`]
+    pub setting: String,
+    /// Why it could not be applied
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for SettingFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:#}", self.setting, self.error)
+    }
+}
+
+/// Outcome of [`Context::apply_repo_settings`]
+#[derive(Debug, Default)]
+pub struct RepoSettingsOutcome {
+    /// Names of the settings applied, including those already set
+    pub applied: Vec<String>,
+    /// Settings that could not be applied
+    pub failures: Vec<SettingFailure>,
+}
+
+impl RepoSettingsOutcome {
+    /// Whether every setting was applied
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn record(&mut self, setting: &str, result: Result<()>) {
+        match result {
+            Ok(()) => self.applied.push(setting.to_string()),
+            Err(error) => self.failures.push(SettingFailure {
+                setting: setting.to_string(),
+                error,
+            }),
+        }
+    }
+}
+
+impl Context {
+    /// Apply `settings` to the event's repository
+    ///
+    /// Settings are applied independently: one failing, e.g. a default
+    /// branch that does not exist, does not keep the others from being
+    /// applied. Failures are returned in the outcome.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the event has no repository or no
+    /// installation client is available.
+    pub async fn apply_repo_settings(
+        &self,
+        settings: &RepoSettings,
+    ) -> Result<RepoSettingsOutcome> {
+        Ok(self.repo_admin().await?.apply_settings(settings).await)
+    }
+}
+
+impl RepoAdmin {
+    /// Apply `settings` to the repository, see [`Context::apply_repo_settings`]
+    pub(crate) async fn apply_settings(&self, settings: &RepoSettings) -> RepoSettingsOutcome {
+        let mut outcome = RepoSettingsOutcome::default();
+        let route = self.route();
+
+        let mut fields = settings.repository_fields();
+        if !fields.is_empty() {
+            let body: Map<String, Value> = fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect();
+            debug!("Updating the settings of {}", route);
+            match self.patch(&body).await {
+                Ok(()) => {
+                    for (name, _) in &fields {
+                        outcome.applied.push(name.to_string());
+                    }
+                }
+                Err(e) if fields.len() == 1 => outcome.record(fields[0].0, Err(e)),
+                Err(e) => {
+                    debug!("Applying the settings of {} one at a time: {:#}", route, e);
+                    // Allowed merge strategies first, so disabling the others
+                    // never leaves none allowed
+                    fields.sort_by_key(|(_, value)| *value != Value::Bool(true));
+                    for (name, value) in fields {
+                        let body = Map::from_iter([(name.to_string(), value)]);
+                        outcome.record(name, self.patch(&body).await);
+                    }
+                }
+            }
+        }
+
+        if let Some(topics) = &settings.topics {
+            outcome.record("topics", self.add_topics(topics).await);
+        }
+        outcome
+    }
+
+    async fn patch(&self, body: &Map<String, Value>) -> Result<()> {
+        self.client
+            .patch::<Value, _, _>(self.route(), Some(body))
+            .await
+            .map(drop)
+            .map_err(|e| self.error(e, &format!("update {}", self.route())))
+    }
+
+    /// Add the missing `topics` to the repository's topics
+    async fn add_topics(&self, topics: &[String]) -> Result<()> {
+        #[derive(Deserialize)]
+        struct Topics {
+            names: Vec<String>,
+        }
+
+        let route = format!("{}/topics", self.route());
+        let what = format!("update {}", route);
+        let mut current: Topics = self
+            .client
+            .get(&route, None::<&()>)
+            .await
+            .map_err(|e| self.error(e, &what))?;
+        let missing: Vec<_> = topics
+            .iter()
+            .map(|topic| topic.to_lowercase())
+            .filter(|topic| !current.names.contains(topic))
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        current.names.extend(missing);
+        let _: Value = self
+            .client
+            .put(&route, Some(&json!({ "names": current.names })))
+            .await
+            .map_err(|e| self.error(e, &what))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, MockGitHub};
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use axum::routing::{get, patch};
+    use axum::{Json, Router};
+
+    fn created_payload() -> Value {
+        json!({
+            "action": "created",
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/repos/octofer/app",
+                patch(|Json(body): Json<Value>| async move {
+                    if body.get("default_branch").is_some() {
+                        let message = json!({ "message": "Validation Failed" });
+                        return (StatusCode::UNPROCESSABLE_ENTITY, Json(message)).into_response();
+                    }
+                    Json(repository("octofer", "app")).into_response()
+                }),
+            )
+            .route(
+                "/repos/octofer/app/topics",
+                get(|| async { Json(json!({ "names": ["rust"] })) })
+                    .put(|Json(body): Json<Value>| async move { Json(body) }),
+            )
+    }
+
+    fn calls(mock: &MockGitHub) -> Vec<(String, Value)> {
+        mock.requests()
+            .into_iter()
+            .map(|r| (format!("{} {}", r.method, r.path), r.body))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_apply_repo_settings() {
+        let mock = MockGitHub::start(routes()).await;
+        let context = mock.context("repository", created_payload());
+        let settings = RepoSettings {
+            allow_squash_merge: Some(true),
+            allow_merge_commit: Some(false),
+            delete_branch_on_merge: Some(true),
+            topics: Some(vec!["Managed".to_string(), "rust".to_string()]),
+            ..Default::default()
+        };
+
+        let outcome = context.apply_repo_settings(&settings).await.unwrap();
+        assert!(outcome.is_success());
+        assert_eq!(
+            calls(&mock),
+            [
+                (
+                    "PATCH /repos/octofer/app".to_string(),
+                    json!({
+                        "allow_squash_merge": true,
+                        "allow_merge_commit": false,
+                        "delete_branch_on_merge": true,
+                    })
+                ),
+                ("GET /repos/octofer/app/topics".to_string(), Value::Null),
+                (
+                    "PUT /repos/octofer/app/topics".to_string(),
+                    json!({ "names": ["rust", "managed"] })
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_collected_per_setting() {
+        let mock = MockGitHub::start(routes()).await;
+        let context = mock.context("repository", created_payload());
+        let settings = RepoSettings {
+            allow_rebase_merge: Some(false),
+            allow_squash_merge: Some(true),
+            default_branch: Some("trunk".to_string()),
+            topics: Some(vec!["rust".to_string()]),
+            ..Default::default()
+        };
+
+        let outcome = context.apply_repo_settings(&settings).await.unwrap();
+        assert_eq!(
+            outcome.applied,
+            ["allow_squash_merge", "allow_rebase_merge", "topics"]
+        );
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].setting, "default_branch");
+        assert!(outcome.failures[0]
+            .to_string()
+            .starts_with("default_branch: Failed to update /repos/octofer/app"));
+
+        let calls = calls(&mock);
+        let bodies: Vec<_> = calls[1..4].iter().map(|(_, body)| body.clone()).collect();
+        assert_eq!(
+            bodies,
+            [
+                json!({ "allow_squash_merge": true }),
+                json!({ "allow_rebase_merge": false }),
+                json!({ "default_branch": "trunk" }),
+            ]
+        );
+        // The topics were already set
+        assert_eq!(calls.len(), 5);
+    }
+}
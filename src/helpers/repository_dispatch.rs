@@ -0,0 +1,305 @@
+//! Repository dispatch helpers
+//!
+//! `repository_dispatch` events are custom events triggered through the API,
+//! which makes them a convenient way for external systems to call into an
+//! app: the event type names the operation and the client payload carries its
+//! arguments. [`Context::client_payload`] deserializes the arguments into a
+//! typed value, and [`Context::send_repository_dispatch`] and
+//! [`GitHubClient::send_repository_dispatch`] trigger dispatches in other
+//! repositories.
+//!
+//! Dispatches are validated against GitHub's limits before they are sent: the
+//! event type may be at most [`MAX_DISPATCH_EVENT_TYPE_LENGTH`] characters and
+//! the client payload must be an object with at most
+//! [`MAX_CLIENT_PAYLOAD_PROPERTIES`] top-level properties, smaller than
+//! [`MAX_CLIENT_PAYLOAD_SIZE`] bytes once serialized.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::Context;
+//! use serde::Deserialize;
+//! use serde_json::json;
+//!
+//! #[derive(Deserialize)]
+//! struct Deploy {
+//!     environment: String,
+//!     version: String,
+//! }
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     let deploy: Deploy = context.client_payload()?;
+//!     // ... deploy, then notify the infrastructure repository
+//!     context
+//!         .send_repository_dispatch(
+//!             "acme",
+//!             "infrastructure",
+//!             "deployed",
+//!             &json!({ "environment": deploy.environment, "version": deploy.version }),
+//!         )
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::github::GitHubClient;
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Maximum length of a repository dispatch event type, in characters
+pub const MAX_DISPATCH_EVENT_TYPE_LENGTH: usize = 100;
+
+/// Maximum number of top-level properties of a client payload
+pub const MAX_CLIENT_PAYLOAD_PROPERTIES: usize = 10;
+
+/// Size a serialized client payload must stay below, in bytes
+pub const MAX_CLIENT_PAYLOAD_SIZE: usize = 64 * 1024;
+
+impl Context {
+    /// Deserialize the client payload of a `repository_dispatch` event
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not a `repository_dispatch` event, or
+    /// its client payload does not match `T`.
+    pub fn client_payload<T: DeserializeOwned>(&self) -> Result<T> {
+        let payload = match self.event.as_ref().map(|e| &e.specific) {
+            Some(WebhookEventPayload::RepositoryDispatch(payload)) => payload,
+            _ => {
+                return Err(anyhow!(
+                    "Event {} is not a repository_dispatch event",
+                    self.kind()
+                ))
+            }
+        };
+
+        serde_json::from_value(payload.client_payload.clone()).map_err(|e| {
+            anyhow!(
+                "Client payload of repository_dispatch event '{}' does not match {}: {}",
+                payload.action,
+                std::any::type_name::<T>(),
+                e
+            )
+        })
+    }
+
+    /// Trigger a `repository_dispatch` event of `event_type` in `owner/repo`
+    ///
+    /// The dispatch is sent with the event's installation client, so the app
+    /// must be installed on the target repository.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dispatch exceeds GitHub's limits, no
+    /// installation client is available, or the request fails.
+    pub async fn send_repository_dispatch(
+        &self,
+        owner: &str,
+        repo: &str,
+        event_type: &str,
+        client_payload: &impl Serialize,
+    ) -> Result<()> {
+        let client_payload = validate_dispatch(event_type, client_payload)?;
+        let client = self.require_installation_client().await?;
+        send(&client, owner, repo, event_type, client_payload).await
+    }
+}
+
+impl GitHubClient {
+    /// Trigger a `repository_dispatch` event of `event_type` in `owner/repo`
+    /// as installation `installation_id`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dispatch exceeds GitHub's limits, no client can
+    /// be created for the installation, or the request fails.
+    pub async fn send_repository_dispatch(
+        &self,
+        installation_id: u64,
+        owner: &str,
+        repo: &str,
+        event_type: &str,
+        client_payload: &impl Serialize,
+    ) -> Result<()> {
+        let client_payload = validate_dispatch(event_type, client_payload)?;
+        let client = self.installation_client(installation_id).await?;
+        send(&client, owner, repo, event_type, client_payload).await
+    }
+}
+
+/// Check a dispatch against GitHub's limits, returning the serialized payload
+fn validate_dispatch(event_type: &str, client_payload: &impl Serialize) -> Result<Value> {
+    if event_type.is_empty() {
+        return Err(anyhow!("Repository dispatch event type must not be empty"));
+    }
+    let length = event_type.chars().count();
+    if length > MAX_DISPATCH_EVENT_TYPE_LENGTH {
+        return Err(anyhow!(
+            "Repository dispatch event type is {} characters long; at most {} are allowed",
+            length,
+            MAX_DISPATCH_EVENT_TYPE_LENGTH
+        ));
+    }
+
+    let client_payload = serde_json::to_value(client_payload)
+        .map_err(|e| anyhow!("Failed to serialize client payload: {}", e))?;
+    let Some(properties) = client_payload.as_object() else {
+        return Err(anyhow!("Client payload must be a JSON object"));
+    };
+    if properties.len() > MAX_CLIENT_PAYLOAD_PROPERTIES {
+        return Err(anyhow!(
+            "Client payload has {} top-level properties; at most {} are allowed",
+            properties.len(),
+            MAX_CLIENT_PAYLOAD_PROPERTIES
+        ));
+    }
+    let size = client_payload.to_string().len();
+    if size >= MAX_CLIENT_PAYLOAD_SIZE {
+        return Err(anyhow!(
+            "Client payload is {} bytes; it must be smaller than {} bytes",
+            size,
+            MAX_CLIENT_PAYLOAD_SIZE
+        ));
+    }
+    Ok(client_payload)
+}
+
+async fn send(
+    client: &Octocrab,
+    owner: &str,
+    repo: &str,
+    event_type: &str,
+    client_payload: Value,
+) -> Result<()> {
+    debug!(
+        "Sending repository dispatch '{}' to {}/{}",
+        event_type, owner, repo
+    );
+    let response = client
+        ._post(
+            format!(
+                "/repos/{}/{}/dispatches",
+                path_segment(owner),
+                path_segment(repo)
+            ),
+            Some(&json!({ "event_type": event_type, "client_payload": client_payload })),
+        )
+        .await?;
+    octocrab::map_github_error(response).await.map_err(|e| {
+        anyhow!(
+            "Failed to send repository dispatch '{}' to {}/{}: {}",
+            event_type,
+            owner,
+            repo,
+            e
+        )
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issues_payload, repository, user, webhook_event, MockGitHub};
+    use axum::{http::StatusCode, routing::post, Router};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Deploy {
+        environment: String,
+        version: String,
+    }
+
+    fn dispatch_payload(client_payload: Value) -> Value {
+        json!({
+            "action": "deploy",
+            "branch": "main",
+            "client_payload": client_payload,
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    #[test]
+    fn test_client_payload() {
+        let context = Context::new(
+            Some(webhook_event(
+                "repository_dispatch",
+                dispatch_payload(json!({ "environment": "prod", "version": "1.2.3" })),
+            )),
+            None,
+        );
+        assert_eq!(
+            context.client_payload::<Deploy>().unwrap(),
+            Deploy {
+                environment: "prod".to_string(),
+                version: "1.2.3".to_string(),
+            }
+        );
+
+        let context = Context::new(
+            Some(webhook_event(
+                "repository_dispatch",
+                dispatch_payload(json!({ "environment": "prod" })),
+            )),
+            None,
+        );
+        let error = context.client_payload::<Deploy>().unwrap_err().to_string();
+        assert!(error.contains("'deploy'"), "{}", error);
+        assert!(error.contains("missing field `version`"), "{}", error);
+
+        let context = Context::new(
+            Some(webhook_event("issues", issues_payload("opened", 1))),
+            None,
+        );
+        assert!(context.client_payload::<Value>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_repository_dispatch_validates_before_sending() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/acme/infra/dispatches",
+            post(|| async { StatusCode::NO_CONTENT }),
+        ))
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 1));
+
+        context
+            .send_repository_dispatch("acme", "infra", "deployed", &json!({ "version": "1.2.3" }))
+            .await
+            .unwrap();
+
+        let too_long = "x".repeat(MAX_DISPATCH_EVENT_TYPE_LENGTH + 1);
+        let too_many: serde_json::Map<String, Value> = (0..=MAX_CLIENT_PAYLOAD_PROPERTIES)
+            .map(|i| (format!("key{}", i), json!(i)))
+            .collect();
+        let too_big = json!({ "blob": "x".repeat(MAX_CLIENT_PAYLOAD_SIZE) });
+        for (event_type, client_payload) in [
+            (too_long.as_str(), json!({})),
+            ("", json!({})),
+            ("deployed", json!(too_many)),
+            ("deployed", too_big),
+            ("deployed", json!([1, 2])),
+        ] {
+            assert!(context
+                .send_repository_dispatch("acme", "infra", event_type, &client_payload)
+                .await
+                .is_err());
+        }
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].body,
+            json!({ "event_type": "deployed", "client_payload": { "version": "1.2.3" } })
+        );
+    }
+}
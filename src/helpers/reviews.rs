@@ -0,0 +1,394 @@
+//! Pull request review helpers
+//!
+//! Request reviewers, approve, request changes and dismiss reviews on the
+//! pull request an event is about, and list its reviews.
+//!
+//! [`Context::approve`] refuses to approve pull requests opened by the app
+//! itself with [`SelfApproval`], before calling GitHub. Requesting a review
+//! from the author of the pull request fails with
+//! [`ReviewRequestedFromAuthor`], e.g. to fall back to other reviewers.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{helpers::reviews::ReviewRequestedFromAuthor, Context};
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     match context.request_reviewers(&["octocat"], &["maintainers"]).await {
+//!         Ok(_) => {}
+//!         Err(e) if e.is::<ReviewRequestedFromAuthor>() => {
+//!             context.request_reviewers(&[], &["maintainers"]).await?;
+//!         }
+//!         Err(e) => return Err(e),
+//!     }
+//!
+//!     // Dismiss approvals given before the latest push
+//!     for review in context.list_reviews().await? {
+//!         if review.state == Some(octocrab::models::pulls::ReviewState::Approved) {
+//!             context
+//!                 .dismiss_review(review.id.0, "New commits were pushed")
+//!                 .await?;
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::{anyhow, Result};
+use http::StatusCode;
+use octocrab::models::pulls::{PullRequest, Review};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::helpers::path_segment;
+use crate::Context;
+
+/// Maximum number of reviews per page
+const REVIEWS_PER_PAGE: usize = 100;
+
+/// Error returned when approving a pull request opened by the app itself
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Refusing to approve #{number}, opened by the app itself ({login})")]
+pub struct SelfApproval {
+    /// Number of the pull request
+    pub number: u64,
+    /// Login of the app, the author of the pull request
+    pub login: String,
+}
+
+/// Error returned when a review is requested from the author of the pull
+/// request
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Review cannot be requested from the author of #{number}")]
+pub struct ReviewRequestedFromAuthor {
+    /// Number of the pull request
+    pub number: u64,
+}
+
+impl Context {
+    /// Request reviews of the event's pull request from `users` and `teams`
+    ///
+    /// Teams are given by slug, without the organization.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReviewRequestedFromAuthor`] if `users` contains the author
+    /// of the pull request, or an error if the event is not about a pull
+    /// request, no installation client is available, or the request fails.
+    pub async fn request_reviewers(&self, users: &[&str], teams: &[&str]) -> Result<PullRequest> {
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+
+        debug!(
+            "Requesting reviews of #{} from {:?} and teams {:?}",
+            number, users, teams
+        );
+        let body = json!({ "reviewers": users, "team_reviewers": teams });
+        match client
+            .post(
+                self.pull_request_route(number, "requested_reviewers")?,
+                Some(&body),
+            )
+            .await
+        {
+            Ok(pull_request) => Ok(pull_request),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == StatusCode::UNPROCESSABLE_ENTITY
+                    && source
+                        .message
+                        .to_lowercase()
+                        .contains("from pull request author") =>
+            {
+                Err(ReviewRequestedFromAuthor { number }.into())
+            }
+            Err(e) => Err(anyhow!("Failed to request reviews of #{}: {}", number, e)),
+        }
+    }
+
+    /// Approve the event's pull request, with an optional `body`
+    ///
+    /// The author is compared to [`GitHubClient::app_login`](crate::github::GitHubClient::app_login);
+    /// if the app's slug is unknown, GitHub's own refusal surfaces as a
+    /// failed request instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SelfApproval`] if the pull request was opened by the app
+    /// itself, or an error if the event is not about a pull request, no
+    /// installation client is available, or the request fails.
+    pub async fn approve(&self, body: Option<&str>) -> Result<Review> {
+        let number = self.require_issue_number()?;
+        if let Some(login) = self.github_client.as_ref().and_then(|c| c.app_login()) {
+            if self
+                .pull_request_author()
+                .is_some_and(|author| author.eq_ignore_ascii_case(&login))
+            {
+                return Err(SelfApproval { number, login }.into());
+            }
+        }
+
+        let mut review = json!({ "event": "APPROVE" });
+        if let Some(body) = body {
+            review["body"] = json!(body);
+        }
+        self.submit_review(number, review, "approve").await
+    }
+
+    /// Request changes to the event's pull request, explained by `body`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about a pull request, no
+    /// installation client is available, or the request fails, e.g. because
+    /// the app opened the pull request.
+    pub async fn request_changes(&self, body: &str) -> Result<Review> {
+        let number = self.require_issue_number()?;
+        let review = json!({ "event": "REQUEST_CHANGES", "body": body });
+        self.submit_review(number, review, "request changes to")
+            .await
+    }
+
+    /// Dismiss review `review_id` of the event's pull request with `message`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about a pull request, no
+    /// installation client is available, or the request fails, e.g. because
+    /// the review is pending or not an approval or change request.
+    pub async fn dismiss_review(&self, review_id: u64, message: &str) -> Result<Review> {
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+
+        debug!("Dismissing review {} of #{}", review_id, number);
+        client
+            .put(
+                self.pull_request_route(number, &format!("reviews/{}/dismissals", review_id))?,
+                Some(&json!({ "message": message, "event": "DISMISS" })),
+            )
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to dismiss review {} of #{}: {}",
+                    review_id,
+                    number,
+                    e
+                )
+            })
+    }
+
+    /// List all reviews of the event's pull request, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is not about a pull request, no
+    /// installation client is available, or a request fails.
+    pub async fn list_reviews(&self) -> Result<Vec<Review>> {
+        let number = self.require_issue_number()?;
+        let client = self.require_installation_client().await?;
+        let route = self.pull_request_route(number, "reviews")?;
+
+        let mut reviews = Vec::new();
+        for page in 1u32.. {
+            let query = json!({ "per_page": REVIEWS_PER_PAGE, "page": page });
+            let page: Vec<Review> = client
+                .get(&route, Some(&query))
+                .await
+                .map_err(|e| anyhow!("Failed to list reviews of #{}: {}", number, e))?;
+            let count = page.len();
+            reviews.extend(page);
+            if count < REVIEWS_PER_PAGE {
+                break;
+            }
+        }
+        Ok(reviews)
+    }
+
+    /// Submit `review` of pull request `number`
+    async fn submit_review(&self, number: u64, review: Value, what: &str) -> Result<Review> {
+        let client = self.require_installation_client().await?;
+
+        debug!("Submitting review of #{}: {}", number, review["event"]);
+        client
+            .post(self.pull_request_route(number, "reviews")?, Some(&review))
+            .await
+            .map_err(|e| anyhow!("Failed to {} #{}: {}", what, number, e))
+    }
+
+    /// Get the login of the author of the event's pull request
+    ///
+    /// Comments on pull requests are `issue_comment` events, whose issue is
+    /// the pull request.
+    fn pull_request_author(&self) -> Option<&str> {
+        let user = match &self.event.as_ref()?.specific {
+            WebhookEventPayload::PullRequest(payload) => payload.pull_request.user.as_deref(),
+            WebhookEventPayload::PullRequestReview(payload) => payload.pull_request.user.as_deref(),
+            WebhookEventPayload::PullRequestReviewComment(payload) => {
+                payload.pull_request.user.as_deref()
+            }
+            WebhookEventPayload::PullRequestReviewThread(payload) => {
+                payload.pull_request.user.as_deref()
+            }
+            WebhookEventPayload::IssueComment(payload) => Some(&payload.issue.user),
+            _ => None,
+        };
+        user.map(|user| user.login.as_str())
+    }
+
+    /// Get the API route of `path` under pull request `number` of the
+    /// event's repository
+    fn pull_request_route(&self, number: u64, path: &str) -> Result<String> {
+        let (owner, repo) = self.require_repository()?;
+        Ok(format!(
+            "/repos/{}/{}/pulls/{}/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            number,
+            path
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        comment, issue, pull_request_payload, repository, user, MockGitHub, TEST_APP_SLUG,
+    };
+    use axum::{
+        routing::{post, put},
+        Json, Router,
+    };
+
+    fn review(id: u64, state: &str) -> Value {
+        json!({
+            "id": id,
+            "node_id": format!("PRR_{id}"),
+            "html_url": format!("https://github.com/octofer/app/pull/7#pullrequestreview-{id}"),
+            "user": user("reviewer"),
+            "body": "",
+            "state": state,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_reviews() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/pulls/7/reviews",
+                    post(|| async { Json(review(9, "APPROVED")) }).get(
+                        |axum::extract::Query(query): axum::extract::Query<Value>| async move {
+                            let reviews: Vec<Value> = if query["page"] == "1" {
+                                (0..100).map(|id| review(id, "COMMENTED")).collect()
+                            } else {
+                                vec![review(100, "APPROVED")]
+                            };
+                            Json(reviews)
+                        },
+                    ),
+                )
+                .route(
+                    "/repos/octofer/app/pulls/7/reviews/100/dismissals",
+                    put(|| async { Json(review(100, "DISMISSED")) }),
+                ),
+        )
+        .await;
+        let context = mock.context("pull_request", pull_request_payload("opened", 7, "abc"));
+
+        let approval = context.approve(Some("LGTM")).await.unwrap();
+        assert_eq!(approval.id.0, 9);
+        context.request_changes("Please add tests").await.unwrap();
+        let reviews = context.list_reviews().await.unwrap();
+        assert_eq!(reviews.len(), 101);
+        let dismissed = context.dismiss_review(100, "Stale").await.unwrap();
+        assert_eq!(
+            dismissed.state,
+            Some(octocrab::models::pulls::ReviewState::Dismissed)
+        );
+
+        let requests = mock.requests();
+        assert_eq!(
+            requests[0].body,
+            json!({ "event": "APPROVE", "body": "LGTM" })
+        );
+        assert_eq!(
+            requests[1].body,
+            json!({ "event": "REQUEST_CHANGES", "body": "Please add tests" })
+        );
+        assert_eq!(requests[3].query.as_deref(), Some("page=2&per_page=100"));
+        assert_eq!(requests[4].method, "PUT");
+        assert_eq!(
+            requests[4].body,
+            json!({ "message": "Stale", "event": "DISMISS" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approve_refuses_own_pull_request() {
+        let mock = MockGitHub::start(Router::new()).await;
+        let app = format!("{TEST_APP_SLUG}[bot]");
+
+        let mut payload = pull_request_payload("opened", 7, "abc");
+        payload["pull_request"]["user"] = user(&app);
+        let err = mock
+            .context("pull_request", payload)
+            .approve(None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SelfApproval>(),
+            Some(&SelfApproval {
+                number: 7,
+                login: app.clone()
+            })
+        );
+
+        let mut issue = issue("octofer", "app", 7);
+        issue["user"] = user(&app);
+        let comment = json!({
+            "action": "created",
+            "issue": issue,
+            "comment": comment(1, "maintainer", "/approve"),
+            "repository": repository("octofer", "app"),
+            "sender": user("maintainer"),
+        });
+        let err = mock
+            .context("issue_comment", comment)
+            .approve(None)
+            .await
+            .unwrap_err();
+        assert!(err.is::<SelfApproval>(), "{err}");
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_review_requested_from_author_is_typed() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/pulls/7/requested_reviewers",
+            post(|| async {
+                (
+                    axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(json!({
+                        "message": "Review cannot be requested from pull request author."
+                    })),
+                )
+            }),
+        ))
+        .await;
+        let context = mock.context("pull_request", pull_request_payload("opened", 7, "abc"));
+
+        let err = context
+            .request_reviewers(&["contributor"], &["maintainers"])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ReviewRequestedFromAuthor>(),
+            Some(&ReviewRequestedFromAuthor { number: 7 })
+        );
+        assert_eq!(
+            mock.requests()[0].body,
+            json!({ "reviewers": ["contributor"], "team_reviewers": ["maintainers"] })
+        );
+    }
+}
@@ -0,0 +1,506 @@
+//! Secret scanning alert helpers
+//!
+//! Typed access to the alert of `secret_scanning_alert` events, and helpers
+//! to remediate it: listing where the secret was found, opening a tracking
+//! issue and resolving the alert, e.g. as revoked once the secret was
+//! rotated.
+//!
+//! Tracking issues are opened in the repository configured with
+//! [`GitHubConfig::security_repository`](crate::config::GitHubConfig::security_repository)
+//! (`OCTOFER_SECURITY_REPOSITORY`), which may belong to another account than
+//! the alerting repository, or in the alerting repository if none is
+//! configured.
+//!
+//! Reading and resolving alerts requires the `secret_scanning_alerts`
+//! permission. Requests GitHub refuses for lack of it fail with
+//! [`MissingPermission`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::helpers::secret_scanning::SecretAlertResolution;
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_secret_scanning_alert(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         let Some(alert) = context.secret_scanning_alert() else {
+//!             return Ok(());
+//!         };
+//!         if alert.state == "open" {
+//!             let locations = context.list_secret_alert_locations().await?;
+//!             context
+//!                 .open_tracking_issue(
+//!                     &format!("Rotate leaked {}", alert.secret_type),
+//!                     &format!("Found in {} location(s)", locations.len()),
+//!                     &["security"],
+//!                 )
+//!                 .await?;
+//!         }
+//!         // Once rotation is confirmed
+//!         context
+//!             .resolve_secret_alert(SecretAlertResolution::Revoked, Some("Rotated"))
+//!             .await?;
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use http::StatusCode;
+use octocrab::models::issues::Issue;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::helpers::installation::MissingPermission;
+use crate::helpers::permissions::Permission;
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Name of the installation permission for secret scanning alerts
+pub const SECRET_SCANNING_PERMISSION: &str = "secret_scanning_alerts";
+
+/// Alert locations fetched per request
+const LOCATIONS_PER_PAGE: usize = 100;
+
+/// Reason a secret scanning alert is resolved with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretAlertResolution {
+    /// The detected string is not a secret
+    FalsePositive,
+    /// The secret will not be revoked
+    WontFix,
+    /// The secret was revoked
+    Revoked,
+    /// The secret is only used in tests
+    UsedInTests,
+}
+
+impl fmt::Display for SecretAlertResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::FalsePositive => "false_positive",
+            Self::WontFix => "wont_fix",
+            Self::Revoked => "revoked",
+            Self::UsedInTests => "used_in_tests",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Secret scanning alert, as sent in `secret_scanning_alert` events
+///
+/// Webhooks and the alert endpoints never include the secret itself.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SecretScanningAlert {
+    /// Number of the alert in its repository
+    pub number: u64,
+    /// Type of the secret (e.g. `github_personal_access_token`)
+    pub secret_type: String,
+    /// Human readable name of the secret type
+    #[serde(default)]
+    pub secret_type_display_name: Option<String>,
+    /// `open` or `resolved`
+    pub state: String,
+    /// Why the alert was resolved, if it is
+    #[serde(default)]
+    pub resolution: Option<SecretAlertResolution>,
+    /// Comment the alert was resolved with, if any
+    #[serde(default)]
+    pub resolution_comment: Option<String>,
+    /// URL of the alert on GitHub
+    #[serde(default)]
+    pub html_url: Option<String>,
+    /// API URL of the alert's locations
+    #[serde(default)]
+    pub locations_url: Option<String>,
+}
+
+/// Place a secret of an alert was found
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SecretAlertLocation {
+    /// Type of the location (e.g. `commit`, `issue_comment`)
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Details of the location, depending on its type (e.g. `path`,
+    /// `start_line` and `commit_sha` for commits)
+    #[serde(default)]
+    pub details: Value,
+}
+
+impl SecretAlertLocation {
+    /// Get the file path of `commit` and `wiki_commit` locations
+    pub fn path(&self) -> Option<&str> {
+        self.details["path"].as_str()
+    }
+}
+
+impl Context {
+    /// Get the alert of a `secret_scanning_alert` event
+    ///
+    /// Returns `None` for other events.
+    pub fn secret_scanning_alert(&self) -> Option<SecretScanningAlert> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::SecretScanningAlert(payload) => {
+                parse_payload_part("secret scanning alert", &payload.alert)
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve the event's secret scanning alert with `resolution`
+    ///
+    /// Returns the updated alert.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the installation may not write
+    /// secret scanning alerts, or an error if the event is not a
+    /// `secret_scanning_alert` event, no installation client is available, or
+    /// the request fails.
+    pub async fn resolve_secret_alert(
+        &self,
+        resolution: SecretAlertResolution,
+        comment: Option<&str>,
+    ) -> Result<SecretScanningAlert> {
+        let (route, client) = self.secret_alert_client().await?;
+
+        let mut body = json!({ "state": "resolved", "resolution": resolution });
+        if let Some(comment) = comment {
+            body["resolution_comment"] = json!(comment);
+        }
+        debug!(
+            "Resolving secret scanning alert {} as {}",
+            route, resolution
+        );
+        client
+            .patch(&route, Some(&body))
+            .await
+            .map_err(|e| self.secret_alert_error(e, Permission::Write, "resolve", &route))
+    }
+
+    /// List the places the secret of the event's secret scanning alert was
+    /// found, following all pages
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingPermission`] if the installation may not read secret
+    /// scanning alerts, or an error if the event is not a
+    /// `secret_scanning_alert` event, no installation client is available, or
+    /// a request fails.
+    pub async fn list_secret_alert_locations(&self) -> Result<Vec<SecretAlertLocation>> {
+        let (route, client) = self.secret_alert_client().await?;
+        let route = format!("{}/locations", route);
+
+        let mut locations = Vec::new();
+        for page in 1u32.. {
+            let batch: Vec<SecretAlertLocation> = client
+                .get(
+                    &route,
+                    Some(&json!({ "per_page": LOCATIONS_PER_PAGE, "page": page })),
+                )
+                .await
+                .map_err(|e| self.secret_alert_error(e, Permission::Read, "list", &route))?;
+
+            let done = batch.len() < LOCATIONS_PER_PAGE;
+            locations.extend(batch);
+            if done {
+                break;
+            }
+        }
+        Ok(locations)
+    }
+
+    /// Open an issue tracking a security alert
+    ///
+    /// The issue is opened in the configured
+    /// [security repository](crate::github::GitHubClient::security_repository),
+    /// with the client of the installation on its account, or in the event's
+    /// repository if none is configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no repository is configured and the event has
+    /// none, the app is not installed on the security repository, or a
+    /// request fails.
+    pub async fn open_tracking_issue(
+        &self,
+        title: &str,
+        body: &str,
+        labels: &[&str],
+    ) -> Result<Issue> {
+        let github = self
+            .github_client
+            .as_ref()
+            .ok_or_else(|| anyhow!("No GitHub client available"))?;
+        let (owner, repo) = match github.security_repository() {
+            Some(repository) => repository
+                .split_once('/')
+                .map(|(owner, repo)| (owner.to_string(), repo.to_string()))
+                .ok_or_else(|| anyhow!("Invalid security repository '{}'", repository))?,
+            None => self.require_repository()?,
+        };
+
+        // An installation belongs to one account, so repositories of the
+        // event's owner are reached with the event's installation
+        let same_account = self
+            .require_repository()
+            .is_ok_and(|(event_owner, _)| event_owner.eq_ignore_ascii_case(&owner));
+        let client = if same_account {
+            self.require_installation_client().await?
+        } else {
+            let installation_id = github.repository_installation_id(&owner, &repo).await?;
+            github
+                .handler_installation_client(
+                    installation_id,
+                    self.api_budget.clone(),
+                    self.audit_trail.clone(),
+                    self.dry_run.clone(),
+                )
+                .await?
+        };
+
+        let issue: Issue = client
+            .post(
+                format!(
+                    "/repos/{}/{}/issues",
+                    path_segment(&owner),
+                    path_segment(&repo)
+                ),
+                Some(&json!({ "title": title, "body": body, "labels": labels })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to open tracking issue in {}/{}: {}", owner, repo, e))?;
+        debug!("Opened tracking issue {}/{}#{}", owner, repo, issue.number);
+        Ok(issue)
+    }
+
+    /// Get the API route of the event's secret scanning alert and the
+    /// installation client
+    async fn secret_alert_client(&self) -> Result<(String, Octocrab)> {
+        let alert = self
+            .secret_scanning_alert()
+            .ok_or_else(|| anyhow!("Event is not a secret scanning alert event"))?;
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/secret-scanning/alerts/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            alert.number
+        );
+        Ok((route, client))
+    }
+
+    /// Turn a refused secret scanning request into [`MissingPermission`]
+    fn secret_alert_error(
+        &self,
+        error: octocrab::Error,
+        required: Permission,
+        what: &str,
+        route: &str,
+    ) -> anyhow::Error {
+        match &error {
+            octocrab::Error::GitHub { source, .. }
+                if source.status_code == StatusCode::FORBIDDEN =>
+            {
+                MissingPermission {
+                    installation_id: self.installation_id.unwrap_or_default(),
+                    permission: SECRET_SCANNING_PERMISSION.to_string(),
+                    required,
+                    granted: self
+                        .installation_access()
+                        .and_then(|access| access.permission(SECRET_SCANNING_PERMISSION)),
+                }
+                .into()
+            }
+            _ => anyhow!("Failed to {} {}: {}", what, route, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, webhook_event, MockGitHub};
+    use axum::extract::Query;
+    use axum::routing::{get, patch, post};
+    use axum::{Json, Router};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn alert_payload() -> Value {
+        json!({
+            "action": "created",
+            "alert": {
+                "number": 7,
+                "secret_type": "github_personal_access_token",
+                "secret_type_display_name": "GitHub Personal Access Token",
+                "state": "open",
+                "resolution": null,
+                "html_url": "https://github.com/octofer/app/security/secret-scanning/7",
+                "locations_url": "https://api.github.com/repos/octofer/app/secret-scanning/alerts/7/locations",
+                "created_at": "2024-01-01T00:00:00Z",
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    fn location(page: usize, index: usize) -> Value {
+        json!({
+            "type": "commit",
+            "details": { "path": format!("config/{}-{}.env", page, index), "start_line": 1 },
+        })
+    }
+
+    #[test]
+    fn test_secret_scanning_alert() {
+        let context = Context::new(
+            Some(webhook_event("secret_scanning_alert", alert_payload())),
+            None,
+        );
+        let alert = context.secret_scanning_alert().unwrap();
+        assert_eq!(alert.number, 7);
+        assert_eq!(alert.secret_type, "github_personal_access_token");
+        assert_eq!(alert.state, "open");
+        assert_eq!(alert.resolution, None);
+        assert!(alert
+            .locations_url
+            .unwrap()
+            .ends_with("/alerts/7/locations"));
+
+        let context = Context::new(
+            Some(webhook_event(
+                "issues",
+                crate::testing::issues_payload("opened", 1),
+            )),
+            None,
+        );
+        assert!(context.secret_scanning_alert().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_list_locations() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/secret-scanning/alerts/7",
+                    patch(|Json(body): Json<Value>| async move {
+                        let mut alert = alert_payload()["alert"].clone();
+                        alert["state"] = body["state"].clone();
+                        alert["resolution"] = body["resolution"].clone();
+                        alert["resolution_comment"] = body["resolution_comment"].clone();
+                        Json(alert)
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/secret-scanning/alerts/7/locations",
+                    get(|Query(query): Query<HashMap<String, String>>| async move {
+                        let page: usize = query["page"].parse().unwrap();
+                        let count = if page == 1 { LOCATIONS_PER_PAGE } else { 2 };
+                        Json((0..count).map(|i| location(page, i)).collect::<Vec<_>>())
+                    }),
+                ),
+        )
+        .await;
+        let context = mock.context("secret_scanning_alert", alert_payload());
+
+        let alert = context
+            .resolve_secret_alert(SecretAlertResolution::Revoked, Some("Rotated"))
+            .await
+            .unwrap();
+        assert_eq!(alert.state, "resolved");
+        assert_eq!(alert.resolution, Some(SecretAlertResolution::Revoked));
+        assert_eq!(alert.resolution_comment.as_deref(), Some("Rotated"));
+
+        let locations = context.list_secret_alert_locations().await.unwrap();
+        assert_eq!(locations.len(), LOCATIONS_PER_PAGE + 2);
+        assert_eq!(locations[0].kind, "commit");
+        assert_eq!(locations[101].path(), Some("config/2-1.env"));
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].method, "PATCH");
+        assert_eq!(
+            requests[0].body,
+            json!({ "state": "resolved", "resolution": "revoked", "resolution_comment": "Rotated" })
+        );
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].query.as_deref(), Some("page=2&per_page=100"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_permission_is_typed() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/secret-scanning/alerts/7",
+            patch(|| async {
+                (
+                    http::StatusCode::FORBIDDEN,
+                    Json(json!({ "message": "Resource not accessible by integration" })),
+                )
+            }),
+        ))
+        .await;
+        let context = mock.context("secret_scanning_alert", alert_payload());
+
+        let err = context
+            .resolve_secret_alert(SecretAlertResolution::FalsePositive, None)
+            .await
+            .unwrap_err();
+        let missing = err.downcast_ref::<MissingPermission>().unwrap();
+        assert_eq!(missing.permission, SECRET_SCANNING_PERMISSION);
+        assert_eq!(missing.required, Permission::Write);
+    }
+
+    #[tokio::test]
+    async fn test_tracking_issue_in_security_repository() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/security-org/alerts/installation",
+                    get(|| async { Json(json!({ "id": 2 })) }),
+                )
+                .route(
+                    "/repos/security-org/alerts/issues",
+                    post(|Json(body): Json<Value>| async move {
+                        let mut issue = crate::testing::issue("security-org", "alerts", 3);
+                        issue["title"] = body["title"].clone();
+                        Json(issue)
+                    }),
+                ),
+        )
+        .await;
+        mock.set_installations(&[1, 2]);
+        let client = mock
+            .client()
+            .with_security_repository("security-org/alerts");
+        let context = Context::with_github_client(
+            Some(webhook_event("secret_scanning_alert", alert_payload())),
+            Some(1),
+            Some(Arc::new(client)),
+        );
+
+        let issue = context
+            .open_tracking_issue("Rotate leaked token", "Found in config/.env", &["security"])
+            .await
+            .unwrap();
+        assert_eq!(issue.number, 3);
+        assert_eq!(issue.title, "Rotate leaked token");
+
+        let requests = mock.requests();
+        let issue_request = requests.last().unwrap();
+        assert_eq!(issue_request.body["labels"], json!(["security"]));
+        // The issue is created with a token of the security repository's installation
+        assert_eq!(issue_request.headers["authorization"], "Bearer ghs_test_2");
+    }
+}
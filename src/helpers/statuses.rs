@@ -0,0 +1,262 @@
+//! Commit status helpers
+//!
+//! Commit statuses are the predecessor of check runs: a state, a short
+//! description and a link, set on a commit under a context name. Many CI
+//! systems and branch protection rules still rely on them.
+//! [`Context::set_commit_status`] sets one on the event's
+//! [head commit](Context::head_sha) unless told otherwise, so the same handler
+//! can report on `push`, `pull_request` and `status` events.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::models::StatusState;
+//! use octofer::Context;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     context
+//!         .set_commit_status(
+//!             None,
+//!             StatusState::Pending,
+//!             "ci/legacy",
+//!             Some("Build queued"),
+//!             Some("https://ci.example.com/builds/42"),
+//!         )
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::{anyhow, Result};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde_json::json;
+use tracing::debug;
+
+use crate::github::models::{CommitStatus, Status, StatusState};
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Maximum length of a commit status description, in characters
+///
+/// [`Context::set_commit_status`] truncates longer descriptions; GitHub
+/// rejects them.
+pub const MAX_STATUS_DESCRIPTION_LENGTH: usize = 140;
+
+/// Parse a commit status state (`error`, `failure`, `pending` or `success`)
+///
+/// # Errors
+///
+/// Returns an error if `state` is not one of the states GitHub accepts.
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::github::models::StatusState;
+/// use octofer::helpers::statuses::parse_status_state;
+///
+/// assert_eq!(parse_status_state("success").unwrap(), StatusState::Success);
+/// assert!(parse_status_state("passed").is_err());
+/// ```
+pub fn parse_status_state(state: &str) -> Result<StatusState> {
+    serde_json::from_value(json!(state)).map_err(|_| {
+        anyhow!(
+            "Invalid commit status state '{}'; expected error, failure, pending or success",
+            state
+        )
+    })
+}
+
+impl Context {
+    /// Get the commit status of a `status` event
+    ///
+    /// Returns `None` for other events.
+    pub fn status(&self) -> Option<CommitStatus> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::Status(payload) => Some(CommitStatus {
+                sha: payload.sha.clone(),
+                state: parse_payload_part("commit status state", &json!(payload.state))?,
+                context: payload.context.clone(),
+                description: payload.description.clone(),
+                target_url: payload.target_url.as_ref().map(|url| url.to_string()),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Set a commit status named `context_name` on commit `sha`
+    ///
+    /// Without `sha`, the status is set on the event's
+    /// [head commit](Context::head_sha). Descriptions longer than
+    /// [`MAX_STATUS_DESCRIPTION_LENGTH`] are truncated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, `sha` is not given and
+    /// the event has no head commit, no installation client is available, or
+    /// the request fails.
+    pub async fn set_commit_status(
+        &self,
+        sha: Option<&str>,
+        state: StatusState,
+        context_name: &str,
+        description: Option<&str>,
+        target_url: Option<&str>,
+    ) -> Result<Status> {
+        let (owner, repo) = self.require_repository()?;
+        let sha = match sha {
+            Some(sha) => sha.to_string(),
+            None => self
+                .head_sha()
+                .ok_or_else(|| anyhow!("Event {} has no head commit", self.kind()))?,
+        };
+        let client = self.require_installation_client().await?;
+
+        let mut body = json!({ "state": state, "context": context_name });
+        if let Some(description) = description {
+            body["description"] = json!(truncate_description(description));
+        }
+        if let Some(target_url) = target_url {
+            body["target_url"] = json!(target_url);
+        }
+
+        debug!("Setting commit status '{}' on {}", context_name, sha);
+        client
+            .post(
+                format!(
+                    "/repos/{}/{}/statuses/{}",
+                    path_segment(&owner),
+                    path_segment(&repo),
+                    path_segment(&sha)
+                ),
+                Some(&body),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to set commit status '{}': {}", context_name, e))
+    }
+}
+
+/// Truncate `description` to [`MAX_STATUS_DESCRIPTION_LENGTH`] characters,
+/// ending truncated descriptions with an ellipsis
+fn truncate_description(description: &str) -> String {
+    if description.chars().count() <= MAX_STATUS_DESCRIPTION_LENGTH {
+        return description.to_string();
+    }
+    let mut truncated: String = description
+        .chars()
+        .take(MAX_STATUS_DESCRIPTION_LENGTH - 1)
+        .collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        pull_request_payload, push_payload, repository, user, webhook_event, MockGitHub,
+    };
+    use axum::{extract::Path, routing::post, Json, Router};
+    use serde_json::Value;
+
+    fn status_payload() -> Value {
+        json!({
+            "id": 1,
+            "sha": "5ca1ab1e",
+            "name": "octofer/app",
+            "context": "ci/jenkins",
+            "state": "failure",
+            "description": "2 tests failed",
+            "target_url": "https://ci.example.com/builds/7",
+            "avatar_url": null,
+            "branches": [],
+            "commit": {},
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "repository": repository("octofer", "app"),
+            "sender": user("ci-bot"),
+        })
+    }
+
+    #[test]
+    fn test_status_accessor_and_sha_defaults() {
+        let mock_context = |event, payload| Context::new(Some(webhook_event(event, payload)), None);
+
+        let context = mock_context("status", status_payload());
+        let status = context.status().unwrap();
+        assert_eq!(status.state, StatusState::Failure);
+        assert_eq!(status.context, "ci/jenkins");
+        assert_eq!(status.description.as_deref(), Some("2 tests failed"));
+        assert_eq!(
+            status.target_url.as_deref(),
+            Some("https://ci.example.com/builds/7")
+        );
+        assert_eq!(context.head_sha().as_deref(), Some("5ca1ab1e"));
+
+        let context = mock_context("push", push_payload("c0ffee"));
+        assert!(context.status().is_none());
+        assert_eq!(context.head_sha().as_deref(), Some("c0ffee"));
+
+        let context = mock_context(
+            "pull_request",
+            pull_request_payload("synchronize", 3, "beefcafe"),
+        );
+        assert_eq!(context.head_sha().as_deref(), Some("beefcafe"));
+
+        assert_eq!(parse_status_state("error").unwrap(), StatusState::Error);
+        assert!(parse_status_state("Success").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_set_commit_status_truncates_description() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/statuses/{sha}",
+            post(
+                |Path(_sha): Path<String>, Json(body): Json<Value>| async move {
+                    Json(json!({
+                        "id": 1,
+                        "state": body["state"],
+                        "context": body["context"],
+                        "description": body["description"],
+                    }))
+                },
+            ),
+        ))
+        .await;
+        let context = mock.context("push", push_payload("c0ffee"));
+
+        let long = "x".repeat(200);
+        let status = context
+            .set_commit_status(None, StatusState::Success, "ci/legacy", Some(&long), None)
+            .await
+            .unwrap();
+        assert_eq!(status.state, StatusState::Success);
+
+        let description = status.description.unwrap();
+        assert_eq!(description.chars().count(), MAX_STATUS_DESCRIPTION_LENGTH);
+        assert!(description.ends_with('…'));
+
+        context
+            .set_commit_status(
+                Some("abc123"),
+                StatusState::Pending,
+                "ci/legacy",
+                Some("Queued"),
+                Some("https://ci.example.com"),
+            )
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].path, "/repos/octofer/app/statuses/c0ffee");
+        assert_eq!(requests[1].path, "/repos/octofer/app/statuses/abc123");
+        assert_eq!(
+            requests[1].body,
+            json!({
+                "state": "pending",
+                "context": "ci/legacy",
+                "description": "Queued",
+                "target_url": "https://ci.example.com",
+            })
+        );
+    }
+}
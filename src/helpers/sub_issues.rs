@@ -0,0 +1,334 @@
+//! Sub-issue helpers
+//!
+//! Issues can be broken down into sub-issues, forming a hierarchy. Linking
+//! and unlinking sends `sub_issues` webhooks, which octocrab does not know
+//! yet: they are routed by their raw event name (see
+//! [`Octofer::on_sub_issues`](crate::Octofer::on_sub_issues)) and parsed with
+//! [`Context::sub_issues_event`].
+//!
+//! The REST endpoints identify sub-issues by their database ID rather than
+//! their number; the helpers here take numbers and resolve the IDs.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::github::models::SubIssuesAction;
+//! use octofer::Context;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     let Some(event) = context.sub_issues_event() else {
+//!         return Ok(());
+//!     };
+//!     if event.action == SubIssuesAction::SubIssueAdded {
+//!         let siblings = context.list_sub_issues(event.parent_issue.number).await?;
+//!         println!("#{} now has {} sub-issues", event.parent_issue.number, siblings.len());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::{anyhow, Result};
+use axum::http::{header::ACCEPT, request::Builder, Method};
+use octocrab::models::issues::Issue;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::github::models::SubIssuesEvent;
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Name of the webhook event sent when sub-issues are linked or unlinked
+pub const SUB_ISSUES_EVENT: &str = "sub_issues";
+
+/// Media type requested from the sub-issues endpoints
+pub const SUB_ISSUES_MEDIA_TYPE: &str = "application/vnd.github+json";
+
+impl Context {
+    /// Get the payload of a `sub_issues` event
+    ///
+    /// Returns `None` for other events.
+    pub fn sub_issues_event(&self) -> Option<SubIssuesEvent> {
+        let event = self.event.as_ref()?;
+        match &event.specific {
+            WebhookEventPayload::Unknown(payload) if self.kind() == SUB_ISSUES_EVENT => {
+                parse_payload_part("sub-issues event", payload)
+            }
+            _ => None,
+        }
+    }
+
+    /// Add issue `child_number` as a sub-issue of issue `parent_number`, both
+    /// in the event's repository
+    ///
+    /// Returns the parent issue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, or a request fails, e.g. because the child
+    /// already has a parent.
+    pub async fn add_sub_issue(&self, parent_number: u64, child_number: u64) -> Result<Issue> {
+        let (owner, repo, client) = self.sub_issues_client().await?;
+        let sub_issue_id = issue_id(&client, &owner, &repo, child_number).await?;
+
+        debug!(
+            "Adding #{} as sub-issue of #{}",
+            child_number, parent_number
+        );
+        send(
+            &client,
+            Method::POST,
+            format!(
+                "/repos/{}/{}/issues/{}/sub_issues",
+                path_segment(&owner),
+                path_segment(&repo),
+                parent_number
+            ),
+            Some(json!({ "sub_issue_id": sub_issue_id })),
+        )
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Failed to add #{} as sub-issue of #{}: {}",
+                child_number,
+                parent_number,
+                e
+            )
+        })
+    }
+
+    /// Remove sub-issue `child_number` from issue `parent_number`, both in the
+    /// event's repository
+    ///
+    /// Returns the parent issue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, or a request fails.
+    pub async fn remove_sub_issue(&self, parent_number: u64, child_number: u64) -> Result<Issue> {
+        let (owner, repo, client) = self.sub_issues_client().await?;
+        let sub_issue_id = issue_id(&client, &owner, &repo, child_number).await?;
+
+        debug!(
+            "Removing sub-issue #{} from #{}",
+            child_number, parent_number
+        );
+        send(
+            &client,
+            Method::DELETE,
+            format!(
+                "/repos/{}/{}/issues/{}/sub_issue",
+                path_segment(&owner),
+                path_segment(&repo),
+                parent_number
+            ),
+            Some(json!({ "sub_issue_id": sub_issue_id })),
+        )
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Failed to remove sub-issue #{} from #{}: {}",
+                child_number,
+                parent_number,
+                e
+            )
+        })
+    }
+
+    /// List the sub-issues of issue `parent_number` in the event's repository
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event has no repository, no installation
+    /// client is available, or a request fails.
+    pub async fn list_sub_issues(&self, parent_number: u64) -> Result<Vec<Issue>> {
+        let (owner, repo, client) = self.sub_issues_client().await?;
+
+        let mut sub_issues = Vec::new();
+        for page in 1u32.. {
+            let batch: Vec<Issue> = send(
+                &client,
+                Method::GET,
+                format!(
+                    "/repos/{}/{}/issues/{}/sub_issues?per_page=100&page={}",
+                    path_segment(&owner),
+                    path_segment(&repo),
+                    parent_number,
+                    page
+                ),
+                None,
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to list sub-issues of #{}: {}", parent_number, e))?;
+
+            let done = batch.len() < 100;
+            sub_issues.extend(batch);
+            if done {
+                break;
+            }
+        }
+        Ok(sub_issues)
+    }
+
+    async fn sub_issues_client(&self) -> Result<(String, String, Octocrab)> {
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        Ok((owner, repo, client))
+    }
+}
+
+/// Get the database ID of issue `number`
+async fn issue_id(client: &Octocrab, owner: &str, repo: &str, number: u64) -> Result<u64> {
+    let issue: Issue = client
+        .get(
+            format!(
+                "/repos/{}/{}/issues/{}",
+                path_segment(owner),
+                path_segment(repo),
+                number
+            ),
+            None::<&()>,
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to get issue #{}: {}", number, e))?;
+    Ok(issue.id.0)
+}
+
+/// Send a request to a sub-issues endpoint with its media type
+async fn send<R: DeserializeOwned>(
+    client: &Octocrab,
+    method: Method,
+    route: String,
+    body: Option<Value>,
+) -> Result<R> {
+    let builder = Builder::new()
+        .method(method)
+        .uri(route)
+        .header(ACCEPT, SUB_ISSUES_MEDIA_TYPE);
+    let request = client.build_request(builder, body.as_ref())?;
+    let response = octocrab::map_github_error(client.execute(request).await?).await?;
+    Ok(serde_json::from_str(
+        &client.body_to_string(response).await?,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::models::SubIssuesAction;
+    use crate::testing::live::LiveTestServer;
+    use crate::testing::{issue, issues_payload, repository, user, webhook_event, MockGitHub};
+    use axum::{
+        extract::Path,
+        routing::{delete, get, post},
+        Json, Router,
+    };
+    use std::sync::Arc;
+
+    fn sub_issues_payload() -> Value {
+        json!({
+            "action": "sub_issue_added",
+            "parent_issue_id": 1,
+            "parent_issue": issue("octofer", "app", 1),
+            "parent_issue_repo": repository("octofer", "app"),
+            "sub_issue_id": 2,
+            "sub_issue": issue("octofer", "app", 2),
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_sub_issues_events_are_routed_by_raw_name() {
+        let context = Context::new(
+            Some(webhook_event("sub_issues", sub_issues_payload())),
+            None,
+        );
+        let event = context.sub_issues_event().unwrap();
+        assert_eq!(event.action, SubIssuesAction::SubIssueAdded);
+        assert_eq!(event.parent_issue.number, 1);
+        assert_eq!(event.sub_issue_id, 2);
+
+        let context = Context::new(
+            Some(webhook_event("issues", issues_payload("opened", 1))),
+            None,
+        );
+        assert!(context.sub_issues_event().is_none());
+
+        let mut server = LiveTestServer::new();
+        server
+            .on_unchecked(
+                SUB_ISSUES_EVENT,
+                |_context, _extra| async { Ok(()) },
+                Arc::new(()),
+            )
+            .await;
+        let response = server
+            .send_webhook("sub_issues", &sub_issues_payload(), &server.secret())
+            .await;
+        assert!(response.status.is_success());
+        assert_eq!(response.invocations.len(), 1);
+        assert_eq!(
+            response.invocations[0]
+                .sub_issues_event()
+                .map(|e| e.sub_issue.number),
+            Some(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_link_unlink_and_list_sub_issues() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/issues/{number}",
+                    get(|Path(number): Path<u64>| async move {
+                        let mut issue = issue("octofer", "app", number);
+                        issue["id"] = json!(1000 + number);
+                        Json(issue)
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/issues/1/sub_issues",
+                    post(|| async { Json(issue("octofer", "app", 1)) })
+                        .get(|| async { Json(json!([issue("octofer", "app", 2)])) }),
+                )
+                .route(
+                    "/repos/octofer/app/issues/1/sub_issue",
+                    delete(|| async { Json(issue("octofer", "app", 1)) }),
+                ),
+        )
+        .await;
+        let context = mock.context("issues", issues_payload("opened", 1));
+
+        let parent = context.add_sub_issue(1, 2).await.unwrap();
+        assert_eq!(parent.number, 1);
+        context.remove_sub_issue(1, 2).await.unwrap();
+        let sub_issues = context.list_sub_issues(1).await.unwrap();
+        assert_eq!(sub_issues.len(), 1);
+
+        let requests = mock.requests();
+        let calls: Vec<_> = requests
+            .iter()
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "GET /repos/octofer/app/issues/2",
+                "POST /repos/octofer/app/issues/1/sub_issues",
+                "GET /repos/octofer/app/issues/2",
+                "DELETE /repos/octofer/app/issues/1/sub_issue",
+                "GET /repos/octofer/app/issues/1/sub_issues",
+            ]
+        );
+        assert_eq!(requests[1].body, json!({ "sub_issue_id": 1002 }));
+        assert_eq!(requests[3].body, json!({ "sub_issue_id": 1002 }));
+        assert_eq!(requests[1].headers[ACCEPT], SUB_ISSUES_MEDIA_TYPE);
+        assert_eq!(requests[4].query.as_deref(), Some("per_page=100&page=1"));
+    }
+}
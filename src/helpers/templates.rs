@@ -0,0 +1,175 @@
+//! Rendering of the app's templates with per-repository overrides
+//!
+//! [`Context::render`] renders one of the app's [`Templates`], unless the
+//! event's repository overrides it with a file in
+//! [`OVERRIDE_DIR`](crate::templates::OVERRIDE_DIR).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::Context;
+//! use serde_json::json;
+//!
+//! async fn handler(context: Context) -> anyhow::Result<()> {
+//!     let body = context.render("welcome", &json!({ "user": "octocat" })).await?;
+//!     context.upsert_comment("welcome", &body).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::Result;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::helpers::{fetch_optional_file, path_segment};
+use crate::templates::{render_source, TemplateError, Templates, OVERRIDE_DIR};
+use crate::Context;
+
+impl Context {
+    /// Render the template `name` with `data`
+    ///
+    /// `.github/octofer/templates/{name}.md` in the event's repository takes
+    /// precedence over the app's [templates](Context::templates). The app's
+    /// template is used if the event has no repository, no installation
+    /// client is available, the repository has no override, or the override
+    /// cannot be fetched or rendered.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`TemplateError`]: [`TemplateError::NotFound`] if neither the
+    /// repository nor the app has the template, or the error rendering the
+    /// app's template.
+    pub async fn render(&self, name: &str, data: &impl Serialize) -> Result<String> {
+        let data = serde_json::to_value(data).map_err(TemplateError::from)?;
+        if let Some(source) = self.template_override(name).await {
+            match render_source(name, &source, &data) {
+                Ok(rendered) => return Ok(rendered),
+                Err(e) => warn!("Ignoring the repository's override: {}", e),
+            }
+        }
+        Ok(self.templates.render(name, &data)?)
+    }
+
+    /// Get the templates rendered by [`Context::render`]
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    /// Fetch the repository's override of the template `name`, if any
+    async fn template_override(&self, name: &str) -> Option<String> {
+        let (owner, repo) = self.require_repository().ok()?;
+        let client = match self.installation_client().await {
+            Ok(client) => client?,
+            Err(e) => {
+                warn!("Cannot look up an override of template {}: {}", name, e);
+                return None;
+            }
+        };
+
+        let repo_route = format!("/repos/{}/{}", path_segment(&owner), path_segment(&repo));
+        let path = format!("{}/{}.md", OVERRIDE_DIR, name);
+        match fetch_optional_file(&client, &repo_route, &path).await {
+            Ok(Some(source)) => {
+                debug!("Using template {} of {}/{}", name, owner, repo);
+                Some(source)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Ignoring the override of template {}: {:?}", name, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issues_payload, webhook_event, MockGitHub};
+    use axum::{
+        extract::Path, http::StatusCode, response::IntoResponse, routing::get, Json, Router,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde_json::json;
+
+    fn overrides() -> Router {
+        Router::new().route(
+            "/repos/octofer/app/contents/{*path}",
+            get(|Path(path): Path<String>| async move {
+                match path.as_str() {
+                    ".github/octofer/templates/welcome.md" => Json(json!({
+                        "type": "file",
+                        "content": STANDARD.encode("Willkommen, @{{ user }}!"),
+                    }))
+                    .into_response(),
+                    ".github/octofer/templates/broken.md" => Json(json!({
+                        "type": "file",
+                        "content": STANDARD.encode("Hallo {{ missing }}"),
+                    }))
+                    .into_response(),
+                    _ => (
+                        StatusCode::NOT_FOUND,
+                        Json(json!({ "message": "Not Found", "documentation_url": "" })),
+                    )
+                        .into_response(),
+                }
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_repository_overrides_take_precedence() {
+        let mock = MockGitHub::start(overrides()).await;
+        let mut context = mock.context("issues", issues_payload("opened", 1));
+        context.templates = Templates::builtin().with("broken", "Hello {{ user }}");
+        let data = json!({ "user": "<b>octocat</b>", "days": 30 });
+
+        assert_eq!(
+            context.render("welcome", &data).await.unwrap(),
+            "Willkommen, @&lt;b&gt;octocat&lt;/b&gt;!"
+        );
+        // Without an override, and with one that fails to render, the app's
+        // template is used
+        assert!(context
+            .render("stale", &data)
+            .await
+            .unwrap()
+            .starts_with("This has had no activity for 30 days."));
+        assert_eq!(
+            context.render("broken", &data).await.unwrap(),
+            "Hello &lt;b&gt;octocat&lt;/b&gt;"
+        );
+
+        let err = context.render("unknown", &data).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TemplateError>(),
+            Some(TemplateError::NotFound(name)) if name == "unknown"
+        ));
+
+        let paths: Vec<String> = mock.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/repos/octofer/app/contents/.github/octofer/templates/welcome.md",
+                "/repos/octofer/app/contents/.github/octofer/templates/stale.md",
+                "/repos/octofer/app/contents/.github/octofer/templates/broken.md",
+                "/repos/octofer/app/contents/.github/octofer/templates/unknown.md",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_app_templates_without_installation_client() {
+        let context = Context::new(
+            Some(webhook_event("issues", issues_payload("opened", 1))),
+            None,
+        );
+        assert_eq!(
+            context
+                .render("welcome", &json!({ "user": "octocat" }))
+                .await
+                .unwrap(),
+            "Thanks for opening this, @octocat! A maintainer will take a look soon."
+        );
+    }
+}
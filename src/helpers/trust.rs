@@ -0,0 +1,251 @@
+//! Fork pull request safety
+//!
+//! Pull requests from forks run code written by anyone with a GitHub account.
+//! Handlers that build or run that code, or post secrets, must not act on them.
+//! [`Context::is_from_fork`] tells from the payload whether an event comes from
+//! a pull request whose head is in another repository, and
+//! [`Context::is_trusted`] additionally requires the sender to have write
+//! access. Handlers registered with
+//! [`trusted_only`](crate::core::HandlerRegistration::trusted_only) are skipped
+//! for untrusted events.
+//!
+//! Forks are detected for:
+//!
+//! - `pull_request`, `pull_request_review`, `pull_request_review_comment` and
+//!   `pull_request_review_thread` events, by comparing the head and base
+//!   repositories of the pull request. A deleted head repository counts as a
+//!   fork;
+//! - `check_suite` and `check_run` events, by comparing the head and base
+//!   repositories of the pull requests the checks belong to. GitHub leaves
+//!   these out for checks of forks, so an empty list is unknown;
+//! - `workflow_run` events, by comparing the head repository of the run with
+//!   the repository it ran in. This covers workflows triggered by
+//!   `pull_request` events from forks, which reach the app as runs.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_pull_request(
+//!     |_context: Context, _extra: Arc<()>| async move {
+//!         // ... deploy a preview with the app's credentials
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await
+//! .named("preview-deployer")
+//! .trusted_only();
+//! # }
+//! ```
+
+use anyhow::Result;
+use octocrab::models::pulls::PullRequest;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::Context;
+
+impl Context {
+    /// Check whether the event comes from a pull request opened from a fork
+    ///
+    /// Returns `None` if the event is not about a pull request, or the payload
+    /// does not tell. See the [module documentation](self) for the events
+    /// covered.
+    pub fn is_from_fork(&self) -> Option<bool> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::PullRequest(payload) => {
+                Some(pull_request_from_fork(&payload.pull_request))
+            }
+            WebhookEventPayload::PullRequestReview(payload) => {
+                Some(pull_request_from_fork(&payload.pull_request))
+            }
+            WebhookEventPayload::PullRequestReviewComment(payload) => {
+                Some(pull_request_from_fork(&payload.pull_request))
+            }
+            WebhookEventPayload::PullRequestReviewThread(payload) => {
+                Some(pull_request_from_fork(&payload.pull_request))
+            }
+            WebhookEventPayload::CheckSuite(payload) => {
+                check_pull_requests_from_fork(&payload.check_suite["pull_requests"])
+            }
+            WebhookEventPayload::CheckRun(payload) => {
+                check_pull_requests_from_fork(&payload.check_run["pull_requests"])
+            }
+            WebhookEventPayload::WorkflowRun(payload) => {
+                let run = &payload.workflow_run;
+                let head = run["head_repository"]["full_name"].as_str()?;
+                let base = run["repository"]["full_name"].as_str()?;
+                Some(!head.eq_ignore_ascii_case(base))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check whether the event may trigger privileged work
+    ///
+    /// An event is trusted if it is not [from a fork](Context::is_from_fork)
+    /// and its sender has at least write permission, looked up with
+    /// [`Context::sender_permission`]. Events from forks are untrusted without
+    /// a lookup, even if a maintainer sent them, since the code they are about
+    /// is not.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of [`Context::sender_permission`].
+    pub async fn is_trusted(&self) -> Result<bool> {
+        if self.is_from_fork() == Some(true) {
+            debug!("{} event comes from a fork", self.kind());
+            return Ok(false);
+        }
+        self.sender_can_write().await
+    }
+}
+
+fn pull_request_from_fork(pull_request: &PullRequest) -> bool {
+    let full_name = |repo: &Option<octocrab::models::Repository>| {
+        repo.as_ref()
+            .and_then(|repo| repo.full_name.clone())
+            .map(|name| name.to_lowercase())
+    };
+    match (
+        full_name(&pull_request.head.repo),
+        full_name(&pull_request.base.repo),
+    ) {
+        (Some(head), Some(base)) => head != base,
+        // The head repository of a pull request is only missing once it is
+        // deleted, which only forks can be without closing the pull request
+        (None, _) => true,
+        (Some(_), None) => false,
+    }
+}
+
+/// Check the `pull_requests` of a check suite or run, which only carry the
+/// ids and URLs of their repositories
+fn check_pull_requests_from_fork(pull_requests: &Value) -> Option<bool> {
+    let pull_requests = pull_requests.as_array().filter(|prs| !prs.is_empty())?;
+    Some(pull_requests.iter().any(|pr| {
+        let (head, base) = (&pr["head"]["repo"], &pr["base"]["repo"]);
+        match (head["id"].as_u64(), base["id"].as_u64()) {
+            (Some(head), Some(base)) => head != base,
+            _ => head["url"] != base["url"],
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pull_request_payload, repository, user, webhook_event, MockGitHub};
+    use axum::{routing::get, Json, Router};
+    use serde_json::json;
+
+    /// Payload of a pull request from `head_owner`'s copy of `octofer/app`
+    fn pull_request_from(head_owner: &str) -> Value {
+        let mut payload = pull_request_payload("opened", 5, "abc123");
+        payload["pull_request"]["head"]["repo"] = repository(head_owner, "app");
+        payload["pull_request"]["base"]["repo"] = repository("octofer", "app");
+        payload
+    }
+
+    fn check_run_payload(head_repo_id: u64) -> Value {
+        json!({
+            "action": "completed",
+            "check_run": {
+                "head_sha": "abc123",
+                "pull_requests": [{
+                    "number": 5,
+                    "head": { "ref": "feature", "sha": "abc123", "repo": { "id": head_repo_id } },
+                    "base": { "ref": "main", "sha": "base000", "repo": { "id": 1 } },
+                }],
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("contributor"),
+        })
+    }
+
+    #[test]
+    fn test_is_from_fork() {
+        let context = |event, payload| Context::new(Some(webhook_event(event, payload)), None);
+
+        assert_eq!(
+            context("pull_request", pull_request_from("contributor")).is_from_fork(),
+            Some(true)
+        );
+        assert_eq!(
+            context("pull_request", pull_request_from("Octofer")).is_from_fork(),
+            Some(false)
+        );
+        // A pull request whose fork was deleted
+        assert_eq!(
+            context("pull_request", pull_request_payload("closed", 5, "abc123")).is_from_fork(),
+            Some(true)
+        );
+
+        assert_eq!(
+            context("check_run", check_run_payload(2)).is_from_fork(),
+            Some(true)
+        );
+        assert_eq!(
+            context("check_run", check_run_payload(1)).is_from_fork(),
+            Some(false)
+        );
+        let mut checks_of_fork = check_run_payload(2);
+        checks_of_fork["check_run"]["pull_requests"] = json!([]);
+        assert_eq!(context("check_run", checks_of_fork).is_from_fork(), None);
+
+        let workflow_run = |head_owner: &str| {
+            json!({
+                "action": "completed",
+                "workflow_run": {
+                    "head_repository": { "full_name": format!("{head_owner}/app") },
+                    "repository": { "full_name": "octofer/app" },
+                },
+                "workflow": {},
+                "repository": repository("octofer", "app"),
+                "sender": user("contributor"),
+            })
+        };
+        assert_eq!(
+            context("workflow_run", workflow_run("contributor")).is_from_fork(),
+            Some(true)
+        );
+        assert_eq!(
+            context("workflow_run", workflow_run("octofer")).is_from_fork(),
+            Some(false)
+        );
+
+        assert_eq!(
+            context("push", crate::testing::push_payload("abc123")).is_from_fork(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_is_trusted() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/collaborators/{user}/permission",
+            get(|| async { Json(json!({ "permission": "write", "role_name": "write" })) }),
+        ))
+        .await;
+        let sent_by = |mut payload: Value, sender: &str| {
+            payload["sender"] = user(sender);
+            mock.context("pull_request", payload)
+        };
+
+        assert!(sent_by(pull_request_from("octofer"), "trusted-writer")
+            .is_trusted()
+            .await
+            .unwrap());
+        // Events from forks are not trusted, whoever sends them
+        assert!(!sent_by(pull_request_from("contributor"), "trusted-writer")
+            .is_trusted()
+            .await
+            .unwrap());
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
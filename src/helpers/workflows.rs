@@ -0,0 +1,684 @@
+//! Workflow helpers
+//!
+//! Typed accessors for `workflow_job` webhook payloads. GitHub sends a
+//! `workflow_job` event whenever a job is queued, starts running on a runner
+//! and completes, which is what self-hosted runner autoscalers react to; the
+//! job's labels tell which runner pool it needs.
+//!
+//! For `workflow_run` events, e.g. to notify pull requests of failed CI,
+//! [`Context::failed_jobs`] lists the run's failed jobs and steps, and
+//! [`Context::download_job_logs`] and [`Context::download_run_logs_zip`]
+//! download their logs. GitHub serves logs by redirecting to a short-lived
+//! URL of its blob storage; the redirect is followed without the
+//! installation token, which must not leave GitHub. Logs are read into memory
+//! up to [`MAX_JOB_LOG_BYTES`] and [`MAX_RUN_LOGS_BYTES`], beyond which they
+//! fail with [`LogsTooLarge`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_workflow_job_queued(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if let Some(job) = context.workflow_job() {
+//!             println!("Job {} needs a runner with {:?}", job.id, job.labels);
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await
+//! .high_priority();
+//! # }
+//! ```
+//!
+//! ```rust,no_run
+//! use octofer::helpers::workflows::log_tail;
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_workflow_run(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         for job in context.failed_jobs().await? {
+//!             let log = context.download_job_logs(job.id).await?;
+//!             println!("{} failed in {:?}:\n{}", job.name, job.failed_steps, log_tail(&log, 20));
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use http::StatusCode;
+use http_body_util::{BodyExt, Limited};
+use octocrab::models::webhook_events::payload::WorkflowJobWebhookEventAction;
+use octocrab::models::webhook_events::WebhookEventPayload;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::debug;
+
+use crate::github::models::{WorkflowJob, WorkflowRun};
+use crate::helpers::{parse_payload_part, path_segment};
+use crate::Context;
+
+/// Size up to which the log of a job is downloaded
+pub const MAX_JOB_LOG_BYTES: usize = 16 * 1024 * 1024;
+
+/// Size up to which the logs archive of a run is downloaded, and its files
+/// are extracted
+pub const MAX_RUN_LOGS_BYTES: usize = 64 * 1024 * 1024;
+
+/// Jobs fetched per request
+const JOBS_PER_PAGE: usize = 100;
+
+/// Logs larger than the limit they are read into memory up to
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{what} exceed the limit of {limit} bytes")]
+pub struct LogsTooLarge {
+    /// Logs that were downloaded or extracted
+    pub what: String,
+    /// Limit in bytes
+    pub limit: usize,
+}
+
+/// Failed job of a workflow run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobSummary {
+    /// ID of the job
+    pub id: u64,
+    /// Name of the job
+    pub name: String,
+    /// `failure` or `timed_out`
+    pub conclusion: String,
+    /// Names of the steps that failed, in run order
+    pub failed_steps: Vec<String>,
+    /// Web URL of the job
+    pub html_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JobsPage {
+    jobs: Vec<RawJob>,
+}
+
+#[derive(Deserialize)]
+struct RawJob {
+    id: u64,
+    name: String,
+    conclusion: Option<String>,
+    #[serde(default)]
+    steps: Vec<RawStep>,
+    html_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawStep {
+    name: String,
+    conclusion: Option<String>,
+}
+
+/// Whether a job or step concluded as failed
+fn failed(conclusion: Option<&str>) -> bool {
+    matches!(conclusion, Some("failure" | "timed_out"))
+}
+
+/// Get the last `lines` lines of `log`
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::helpers::workflows::log_tail;
+///
+/// assert_eq!(log_tail("a\nb\nc\n", 2), "b\nc\n");
+/// assert_eq!(log_tail("a\nb", 5), "a\nb");
+/// ```
+pub fn log_tail(log: &str, lines: usize) -> &str {
+    if lines == 0 {
+        return "";
+    }
+    let trimmed = log.strip_suffix('\n').unwrap_or(log);
+    match trimmed.rmatch_indices('\n').nth(lines - 1) {
+        Some((index, _)) => &log[index + 1..],
+        None => log,
+    }
+}
+
+impl Context {
+    /// Get the job of a `workflow_job` event
+    ///
+    /// Returns `None` for other events.
+    pub fn workflow_job(&self) -> Option<WorkflowJob> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::WorkflowJob(payload) => {
+                parse_payload_part("workflow job", &payload.workflow_job)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the action of a `workflow_job` event
+    ///
+    /// Returns `None` for other events.
+    pub fn workflow_job_action(&self) -> Option<WorkflowJobWebhookEventAction> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::WorkflowJob(payload) => Some(payload.action.clone()),
+            _ => None,
+        }
+    }
+
+    /// Get the run of a `workflow_run` event
+    ///
+    /// Returns `None` for other events.
+    pub fn workflow_run(&self) -> Option<WorkflowRun> {
+        match &self.event.as_ref()?.specific {
+            WebhookEventPayload::WorkflowRun(payload) => {
+                parse_payload_part("workflow run", &payload.workflow_run)
+            }
+            _ => None,
+        }
+    }
+
+    /// List the failed jobs of the event's workflow run, with their failed
+    /// steps
+    ///
+    /// Only the jobs of the run's latest attempt are listed. Jobs that
+    /// concluded with `failure` or `timed_out` count as failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event is neither a `workflow_run` nor a
+    /// `workflow_job` event, no installation client is available, or a
+    /// request fails.
+    pub async fn failed_jobs(&self) -> Result<Vec<JobSummary>> {
+        let run_id = self.require_run_id()?;
+        let (owner, repo) = self.require_repository()?;
+        let client = self.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/actions/runs/{}/jobs",
+            path_segment(&owner),
+            path_segment(&repo),
+            run_id
+        );
+
+        let mut failed_jobs = Vec::new();
+        for page in 1u32.. {
+            let query = json!({ "filter": "latest", "per_page": JOBS_PER_PAGE, "page": page });
+            let jobs: JobsPage = client
+                .get(&route, Some(&query))
+                .await
+                .map_err(|e| anyhow!("Failed to list jobs of run {}: {}", run_id, e))?;
+            let count = jobs.jobs.len();
+            failed_jobs.extend(
+                jobs.jobs
+                    .into_iter()
+                    .filter(|job| failed(job.conclusion.as_deref()))
+                    .map(|job| JobSummary {
+                        id: job.id,
+                        name: job.name,
+                        conclusion: job.conclusion.unwrap_or_default(),
+                        failed_steps: job
+                            .steps
+                            .into_iter()
+                            .filter(|step| failed(step.conclusion.as_deref()))
+                            .map(|step| step.name)
+                            .collect(),
+                        html_url: job.html_url,
+                    }),
+            );
+            if count < JOBS_PER_PAGE {
+                break;
+            }
+        }
+        Ok(failed_jobs)
+    }
+
+    /// Download the log of job `job_id` of the event's repository as text
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LogsTooLarge`] if the log exceeds [`MAX_JOB_LOG_BYTES`], or
+    /// an error if the event has no repository, no installation client is
+    /// available, or the download fails, e.g. because the log expired.
+    pub async fn download_job_logs(&self, job_id: u64) -> Result<String> {
+        let (owner, repo) = self.require_repository()?;
+        let route = format!(
+            "/repos/{}/{}/actions/jobs/{}/logs",
+            path_segment(&owner),
+            path_segment(&repo),
+            job_id
+        );
+        let what = format!("Logs of job {}", job_id);
+        let log = self.download_logs(&route, &what, MAX_JOB_LOG_BYTES).await?;
+        Ok(String::from_utf8_lossy(&log).into_owned())
+    }
+
+    /// Download the logs of the event's workflow run, as the path and text of
+    /// each file of the logs archive, in archive order
+    ///
+    /// The archive holds a file per job (e.g. `0_build.txt`), and a
+    /// directory per job with a file per step (e.g. `build/2_Run tests.txt`).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LogsTooLarge`] if the archive or its extracted files exceed
+    /// [`MAX_RUN_LOGS_BYTES`], or an error if the event is neither a
+    /// `workflow_run` nor a `workflow_job` event, no installation client is
+    /// available, the download fails, or the archive is invalid.
+    pub async fn download_run_logs_zip(&self) -> Result<Vec<(String, String)>> {
+        let run_id = self.require_run_id()?;
+        let (owner, repo) = self.require_repository()?;
+        let route = format!(
+            "/repos/{}/{}/actions/runs/{}/logs",
+            path_segment(&owner),
+            path_segment(&repo),
+            run_id
+        );
+        let what = format!("Logs of run {}", run_id);
+        let archive = self
+            .download_logs(&route, &what, MAX_RUN_LOGS_BYTES)
+            .await?;
+        unzip_logs(archive, &what, MAX_RUN_LOGS_BYTES)
+    }
+
+    /// ID of the run of a `workflow_run` or `workflow_job` event
+    fn require_run_id(&self) -> Result<u64> {
+        self.workflow_run()
+            .map(|run| run.id)
+            .or_else(|| self.workflow_job().map(|job| job.run_id))
+            .ok_or_else(|| anyhow!("Event is not a workflow run or workflow job event"))
+    }
+
+    /// Download the logs at `route`, following GitHub's redirect to its blob
+    /// storage
+    ///
+    /// The transport drops the `Authorization` header when a redirect leaves
+    /// the API's origin.
+    async fn download_logs(&self, route: &str, what: &str, limit: usize) -> Result<Bytes> {
+        let client = self.require_installation_client().await?;
+        debug!("Downloading {}", route);
+        let response = client._get(route).await?;
+        let response = octocrab::map_github_error(response)
+            .await
+            .map_err(|e| match e {
+                // Logs are kept for the repository's retention period
+                octocrab::Error::GitHub { source, .. }
+                    if source.status_code == StatusCode::GONE =>
+                {
+                    anyhow!("{} expired or were deleted", what)
+                }
+                e => anyhow!("Failed to download {}: {}", what.to_lowercase(), e),
+            })?;
+
+        match Limited::new(response.into_body(), limit).collect().await {
+            Ok(body) => Ok(body.to_bytes()),
+            Err(e) if e.is::<http_body_util::LengthLimitError>() => Err(LogsTooLarge {
+                what: what.to_string(),
+                limit,
+            }
+            .into()),
+            Err(e) => Err(anyhow!("Failed to download {}: {}", what.to_lowercase(), e)),
+        }
+    }
+}
+
+/// Extract the files of a logs archive, up to `limit` bytes in total
+///
+/// The size of every file is checked while reading it, not trusted from the
+/// archive's headers.
+fn unzip_logs(archive: Bytes, what: &str, limit: usize) -> Result<Vec<(String, String)>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(archive))
+        .map_err(|e| anyhow!("{} are not a valid archive: {}", what, e))?;
+    let too_large = || LogsTooLarge {
+        what: format!("Extracted {}", what.to_lowercase()),
+        limit,
+    };
+
+    let mut files = Vec::with_capacity(archive.len());
+    let mut remaining = limit;
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|e| anyhow!("Failed to extract {}: {}", what.to_lowercase(), e))?;
+        if file.is_dir() {
+            continue;
+        }
+        if file.size() > remaining as u64 {
+            return Err(too_large().into());
+        }
+        let name = file
+            .name()
+            .map_err(|e| anyhow!("Failed to extract {}: {}", what.to_lowercase(), e))?
+            .into_owned();
+        let mut content = Vec::new();
+        (&mut file)
+            .take(remaining as u64 + 1)
+            .read_to_end(&mut content)
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to extract {} from {}: {}",
+                    name,
+                    what.to_lowercase(),
+                    e
+                )
+            })?;
+        if content.len() > remaining {
+            return Err(too_large().into());
+        }
+        remaining -= content.len();
+        files.push((name, String::from_utf8_lossy(&content).into_owned()));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, webhook_event, MockGitHub};
+    use axum::http::header::{AUTHORIZATION, LOCATION};
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn run_payload() -> serde_json::Value {
+        json!({
+            "action": "completed",
+            "workflow_run": {
+                "id": 30433642,
+                "name": "CI",
+                "workflow_id": 159038,
+                "run_number": 562,
+                "run_attempt": 2,
+                "event": "pull_request",
+                "head_branch": "feature",
+                "head_sha": "abc123",
+                "status": "completed",
+                "conclusion": "failure",
+                "pull_requests": [{ "id": 1934, "number": 42, "url": "https://api.github.com/repos/octofer/app/pulls/42" }],
+                "html_url": "https://github.com/octofer/app/actions/runs/30433642",
+            },
+            "workflow": { "id": 159038, "name": "CI" },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        })
+    }
+
+    fn logs_zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .add_directory("build/", SimpleFileOptions::default())
+            .unwrap();
+        for (name, content) in files {
+            archive
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            archive.write_all(content.as_bytes()).unwrap();
+        }
+        archive.finish().unwrap().into_inner()
+    }
+
+    /// Start the blob storage GitHub redirects log downloads to, which
+    /// rejects requests carrying credentials like Azure's does
+    async fn blob_storage(job_log: String, run_logs: Vec<u8>) -> MockGitHub {
+        let reject_credentials =
+            |headers: &axum::http::HeaderMap| headers.contains_key(AUTHORIZATION);
+        MockGitHub::start(
+            Router::new()
+                .route(
+                    "/logs/job.txt",
+                    get(move |headers: axum::http::HeaderMap| async move {
+                        if reject_credentials(&headers) {
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+                        job_log.into_response()
+                    }),
+                )
+                .route(
+                    "/logs/run.zip",
+                    get(move |headers: axum::http::HeaderMap| async move {
+                        if reject_credentials(&headers) {
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+                        run_logs.into_response()
+                    }),
+                ),
+        )
+        .await
+    }
+
+    fn redirect(location: String) -> impl IntoResponse {
+        (StatusCode::FOUND, [(LOCATION, location)])
+    }
+
+    #[test]
+    fn test_workflow_job_accessor() {
+        let payload = json!({
+            "action": "completed",
+            "workflow_job": {
+                "id": 29679449,
+                "run_id": 1270817220,
+                "name": "build",
+                "workflow_name": "CI",
+                "head_sha": "abc123",
+                "status": "completed",
+                "conclusion": "success",
+                "labels": ["self-hosted", "linux", "gpu"],
+                "runner_id": 7,
+                "runner_name": "gpu-runner-7",
+                "runner_group_id": 2,
+                "runner_group_name": "gpu",
+                "html_url": "https://github.com/octofer/app/actions/runs/1270817220/job/29679449",
+                "steps": [],
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        });
+        let context = Context::new(Some(webhook_event("workflow_job", payload)), None);
+
+        let job = context.workflow_job().unwrap();
+        assert_eq!(job.run_id, 1270817220);
+        assert_eq!(job.labels, ["self-hosted", "linux", "gpu"]);
+        assert_eq!(job.runner_group_name.as_deref(), Some("gpu"));
+        assert_eq!(job.conclusion.as_deref(), Some("success"));
+        assert_eq!(
+            context.workflow_job_action(),
+            Some(WorkflowJobWebhookEventAction::Completed)
+        );
+    }
+
+    #[test]
+    fn test_workflow_run_accessor_and_log_tail() {
+        let context = Context::new(Some(webhook_event("workflow_run", run_payload())), None);
+        let run = context.workflow_run().unwrap();
+        assert_eq!(run.id, 30433642);
+        assert_eq!(run.run_attempt, 2);
+        assert_eq!(run.conclusion.as_deref(), Some("failure"));
+        assert_eq!(run.pull_requests[0].number, 42);
+        assert_eq!(context.require_run_id().unwrap(), 30433642);
+        assert!(context.workflow_job().is_none());
+
+        assert_eq!(log_tail("a\nb\nc", 2), "b\nc");
+        assert_eq!(log_tail("a\nb\nc\n", 1), "c\n");
+        assert_eq!(log_tail("a\nb\nc\n", 0), "");
+    }
+
+    #[tokio::test]
+    async fn test_download_logs_drops_credentials_on_redirect() {
+        let archive = logs_zip(&[
+            ("0_build.txt", "Run tests\nerror: 1 test failed\n"),
+            ("build/1_Set up job.txt", "Runner: ubuntu-latest\n"),
+        ]);
+        let blob = blob_storage("line 1\nline 2\nerror: boom\n".to_string(), archive).await;
+        let blob_uri = blob.uri();
+        let api = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/actions/jobs/7/logs",
+                    get({
+                        let blob_uri = blob_uri.clone();
+                        move || async move { redirect(format!("{blob_uri}/logs/job.txt?sig=abc")) }
+                    }),
+                )
+                .route(
+                    "/repos/octofer/app/actions/runs/30433642/logs",
+                    get(
+                        move || async move { redirect(format!("{blob_uri}/logs/run.zip?sig=abc")) },
+                    ),
+                )
+                // Some GitHub Enterprise Server versions serve logs directly
+                .route(
+                    "/repos/octofer/app/actions/jobs/8/logs",
+                    get(|| async { "plain text log\n" }),
+                ),
+        )
+        .await;
+        let context = api.context("workflow_run", run_payload());
+
+        let log = context.download_job_logs(7).await.unwrap();
+        assert_eq!(log_tail(&log, 1), "error: boom\n");
+        let files = context.download_run_logs_zip().await.unwrap();
+        assert_eq!(
+            files,
+            [
+                (
+                    "0_build.txt".to_string(),
+                    "Run tests\nerror: 1 test failed\n".to_string()
+                ),
+                (
+                    "build/1_Set up job.txt".to_string(),
+                    "Runner: ubuntu-latest\n".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            context.download_job_logs(8).await.unwrap(),
+            "plain text log\n"
+        );
+
+        // GitHub saw the installation token, the blob storage did not
+        let api_requests = api.requests();
+        assert_eq!(api_requests.len(), 3);
+        assert!(api_requests
+            .iter()
+            .all(|request| request.headers.contains_key(AUTHORIZATION)));
+        let blob_requests = blob.requests();
+        assert_eq!(blob_requests.len(), 2);
+        assert_eq!(blob_requests[0].query.as_deref(), Some("sig=abc"));
+        assert!(blob_requests
+            .iter()
+            .all(|request| !request.headers.contains_key(AUTHORIZATION)));
+    }
+
+    #[tokio::test]
+    async fn test_logs_size_limits() {
+        let big = "x".repeat(4096);
+        let archive = logs_zip(&[("0_build.txt", &big), ("1_test.txt", &big)]);
+        let blob = blob_storage(big.clone(), archive.clone()).await;
+        let blob_uri = blob.uri();
+        let api = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/repos/octofer/app/actions/jobs/7/logs",
+                    get(move || async move { redirect(format!("{blob_uri}/logs/job.txt")) }),
+                )
+                .route(
+                    "/repos/octofer/app/actions/jobs/9/logs",
+                    get(|| async {
+                        (
+                            StatusCode::GONE,
+                            Json(json!({ "message": "Logs have expired" })),
+                        )
+                    }),
+                ),
+        )
+        .await;
+        let context = api.context("workflow_run", run_payload());
+
+        let err = context
+            .download_logs(
+                "/repos/octofer/app/actions/jobs/7/logs",
+                "Logs of job 7",
+                1024,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LogsTooLarge>(),
+            Some(&LogsTooLarge {
+                what: "Logs of job 7".to_string(),
+                limit: 1024
+            })
+        );
+        let err = context.download_job_logs(9).await.unwrap_err();
+        assert_eq!(err.to_string(), "Logs of job 9 expired or were deleted");
+
+        // The compressed archive is small, its files are not
+        assert!(archive.len() < 4096);
+        assert_eq!(
+            unzip_logs(archive.clone().into(), "Logs of run 1", 8192)
+                .unwrap()
+                .len(),
+            2
+        );
+        let err = unzip_logs(archive.into(), "Logs of run 1", 6000).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LogsTooLarge>().map(|e| e.limit),
+            Some(6000)
+        );
+        assert!(unzip_logs(Bytes::from_static(b"not a zip"), "Logs of run 1", 6000).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_jobs() {
+        let api = MockGitHub::start(Router::new().route(
+            "/repos/octofer/app/actions/runs/30433642/jobs",
+            get(|| async {
+                Json(json!({
+                    "total_count": 3,
+                    "jobs": [
+                        {
+                            "id": 1, "name": "lint", "conclusion": "success",
+                            "steps": [{ "name": "Clippy", "conclusion": "success", "number": 1 }],
+                        },
+                        {
+                            "id": 2, "name": "test", "conclusion": "failure",
+                            "html_url": "https://github.com/octofer/app/actions/runs/30433642/job/2",
+                            "steps": [
+                                { "name": "Set up job", "conclusion": "success", "number": 1 },
+                                { "name": "Run tests", "conclusion": "failure", "number": 2 },
+                                { "name": "Upload coverage", "conclusion": "skipped", "number": 3 },
+                            ],
+                        },
+                        { "id": 3, "name": "e2e", "conclusion": "timed_out", "steps": [] },
+                    ],
+                }))
+            }),
+        ))
+        .await;
+        let context = api.context("workflow_run", run_payload());
+
+        let jobs = context.failed_jobs().await.unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].name, "test");
+        assert_eq!(jobs[0].failed_steps, ["Run tests"]);
+        assert_eq!(jobs[1].conclusion, "timed_out");
+        assert_eq!(
+            api.requests()[0].query.as_deref(),
+            Some("filter=latest&page=1&per_page=100")
+        );
+
+        let context = api.context("issues", crate::testing::issues_payload("opened", 1));
+        assert!(context.failed_jobs().await.is_err());
+    }
+}
@@ -17,7 +17,7 @@
 //!
 //! Octofer supports all major GitHub webhook events:
 //!
-//! - **Issues & PRs**: `on_issue()`, `on_issue_comment()`, `on_pull_request()`, 
+//! - **Issues & PRs**: `on_issue()`, `on_issue_comment()`, `on_pull_request()`,
 //!   `on_pull_request_review()`, `on_pull_request_review_comment()`, `on_pull_request_review_thread()`
 //! - **Repository**: `on_push()`, `on_create()`, `on_delete()`, `on_fork()`, `on_repository()`, etc.
 //! - **Workflows**: `on_workflow_run()`, `on_workflow_job()`, `on_workflow_dispatch()`, `on_status()`
@@ -32,8 +32,7 @@
 //! ### 1. Basic Example
 //!
 //! ```rust,no_run
-//! use octofer::{Octofer, Config};
-//! use std::sync::Arc;
+//! use octofer::prelude::*;
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
@@ -61,8 +60,7 @@
 //! ### 2. Full-Featured Example
 //!
 //! ```rust,no_run
-//! use octofer::{Octofer, Config, Context};
-//! use std::sync::Arc;
+//! use octofer::prelude::*;
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
@@ -150,7 +148,28 @@
 //! - [`core`] - Core types including [`Context`] and event handler traits  
 //! - [`github`] - GitHub API client with authentication and token management
 //! - [`events`] - Event handler registration methods
+//! - [`flags`] - Feature flags gating handlers, with a pluggable provider
+//! - [`groups`] - Named handler groups filtered by organization or installation
+//! - [`helpers`] - Typed payload accessors and helpers on [`Context`]
+//! - [`manifest`] - Events and permissions the app needs for its handlers
+//! - [`plugins`] - Ready-made automations such as label syncing
+//! - [`prelude`] - The types most handlers need, for a glob import
+//! - [`sampling`] - Sampling of the deliveries of high-volume events
+//! - [`secrets`] - Private key and webhook secret values, redacted from logs
+//! - [`sequence`] - Detection of webhook deliveries arriving out of order
+//! - [`staleness`] - Skipping handlers for deliveries of old events
+//! - [`state`] - Typed state shared by all handlers of the app
+//! - [`storage`] - Pluggable key-value storage for stateful features
+//! - [`stream`] - Deliveries as an async stream of events
+//! - [`tasks`] - Supervised background tasks with restart policies
+//! - [`templates`] - Named templates for bot-authored messages, overridable per repository
+//! - [`util`] - Canonical JSON and idempotency keys of deliveries
+//! - [`backfill`] - Running handlers for the existing issues and pull requests of a repository
+//! - [`archive`] - Archiving of webhook deliveries for replay and debugging
+//! - [`dispatch`] - Webhook verification and handler dispatch, usable without the server
+//! - [`error_log`] - Deduplication of repeated handler error logs
 //! - [`webhook`] - HTTP server for receiving webhook events
+//! - `testing` - Mock GitHub API and end-to-end webhook test harness (requires the `testing` feature)
 //!
 //! ## Error Handling
 //!
@@ -164,20 +183,49 @@
 //! and the GitHub client handles token caching and refresh automatically
 //! across threads.
 
+pub mod archive;
+pub mod backfill;
 pub mod config;
 pub mod core;
+pub mod dispatch;
+pub mod error_log;
 pub mod events;
+pub mod flags;
 pub mod github;
+pub mod groups;
+pub mod helpers;
+pub mod manifest;
+pub mod plugins;
+pub mod prelude;
+pub mod sampling;
+pub mod secrets;
+pub mod sequence;
+pub mod staleness;
+pub mod state;
+pub mod storage;
+pub mod stream;
+pub mod tasks;
+pub mod templates;
+pub mod util;
 pub mod webhook;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 pub use config::Config;
 pub use core::Context;
+pub use events::EventKind;
+/// The octocrab version Octofer is built with
+///
+/// Payload and model types in [`Context`] come from this version; use it,
+/// not a separately declared octocrab dependency, to name them.
 pub use octocrab;
 
 use octocrab::models::webhook_events::WebhookEventType;
 use serde::Serialize;
-use tracing::error;
+use tracing::{error, info, warn};
 
+use crate::github::{GitHubAuth, GitHubClient};
 use crate::webhook::WebhookServer;
 use anyhow::Result;
 
@@ -198,10 +246,12 @@ pub trait SerdeToString {
 impl SerdeToString for WebhookEventType {
     /// Convert webhook event type to string
     ///
-    /// Converts the WebhookEventType enum to its string representation.
+    /// Converts the WebhookEventType enum to its string representation, e.g.
+    /// `issue_comment`, matching the `X-GitHub-Event` header.
     /// If serialization fails, returns "undefined".
     fn to_string(&self) -> String {
         match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(s)) => s,
             Ok(v) => v.to_string(),
             Err(e) => {
                 error!("Cannot parse event kind: {:?}", e);
@@ -300,6 +350,8 @@ impl Octofer {
     /// This function will return an error if:
     /// - GitHub App authentication fails (invalid credentials)
     /// - The webhook server cannot be created
+    /// - Signature verification is disabled, or the default webhook secret is
+    ///   used without `allow_default_secret`, on a non-loopback host
     /// - Network issues prevent GitHub client setup
     ///
     /// # Examples
@@ -320,19 +372,143 @@ impl Octofer {
     /// # }
     /// ```
     pub async fn new(config: Config) -> Result<Self> {
-        let server = WebhookServer::new(
+        if config.github.disabled {
+            return Self::new_without_github(config).await;
+        }
+
+        let server = WebhookServer::with_hmac_config(
             config.server.host,
             config.server.port,
             config.github.clone(),
-            &config.webhook.secret,
-            &config.webhook.header_name,
+            config.webhook.hmac_config(),
         )
         .await?;
+        Ok(Self::with_server(config, server).await)
+    }
+
+    /// Create a new Octofer instance using a pre-built GitHub client
+    ///
+    /// Uses the server, webhook, logging and dispatch settings of `config`
+    /// like [`Octofer::new`], but handlers get `github_client` instead of a
+    /// client built from the GitHub App credentials of `config`; of the
+    /// GitHub settings, only
+    /// [`prewarm_installations`](config::GitHubConfig::prewarm_installations)
+    /// and [`redelivery`](config::GitHubConfig::redelivery) apply. Useful to
+    /// add middleware to every API request, see
+    /// [`GitHubClient::from_octocrab`](github::GitHubClient::from_octocrab),
+    /// or to point the app at a mock server in tests.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the webhook configuration is not safe for the
+    /// configured host (see
+    /// [`HmacConfig::validate`](github::middlewares::HmacConfig::validate)).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::github::{transport::ClientOptions, GitHubClient};
+    /// use octofer::octocrab::Octocrab;
+    /// use octofer::{Config, Octofer};
+    ///
+    /// # async fn example(app_client: Octocrab) -> anyhow::Result<()> {
+    /// let config = Config::from_env()?;
+    /// let client = GitHubClient::from_octocrab(app_client, ClientOptions::default()).await?;
+    ///
+    /// let app = Octofer::with_github_client(config, client).await?;
+    /// app.start().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_github_client(config: Config, github_client: GitHubClient) -> Result<Self> {
+        let mut server = WebhookServer::with_github_client(
+            config.server.host,
+            config.server.port,
+            github_client,
+            config.webhook.hmac_config(),
+        )?;
+        server.set_prewarm(config.github.prewarm_installations.clone());
+        server.set_redelivery(config.github.redelivery.clone());
+        Ok(Self::with_server(config, server).await)
+    }
+
+    /// Create a new Octofer instance without a GitHub client
+    ///
+    /// Uses the server, webhook, logging and dispatch settings of `config`
+    /// like [`Octofer::new`], but ignores its GitHub App credentials:
+    /// deliveries are verified and parsed as usual, while
+    /// [`Context::github`] returns `None` in handlers. [`Octofer::new`] takes
+    /// this path when [`GitHubConfig::disabled`](config::GitHubConfig::disabled)
+    /// is set, e.g. with `OCTOFER_GITHUB_DISABLED=true`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the webhook configuration is not safe for the
+    /// configured host (see
+    /// [`HmacConfig::validate`](github::middlewares::HmacConfig::validate)).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Config, Context, Octofer};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let mut config = Config::default();
+    /// config.webhook.secret = "webhook-secret".into();
+    ///
+    /// let mut app = Octofer::new_without_github(config).await?;
+    /// app.on_push(
+    ///     |context: Context, _extra: Arc<()>| async move {
+    ///         // Forward the event, e.g. to a message queue
+    ///         println!("{}", context.payload());
+    ///         Ok(())
+    ///     },
+    ///     Arc::new(()),
+    /// )
+    /// .await;
+    /// app.start().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn new_without_github(config: Config) -> Result<Self> {
+        let server = WebhookServer::without_github(
+            config.server.host,
+            config.server.port,
+            config.webhook.hmac_config(),
+        )?;
+        Ok(Self::with_server(config, server).await)
+    }
+
+    /// Apply the settings of `config` that are not handled by the server's
+    /// constructor
+    async fn with_server(config: Config, mut server: WebhookServer) -> Self {
+        server.set_dispatch_config(config.dispatch.clone()).await;
+        server.set_info_endpoint(config.server.info_endpoint);
+        server.set_tuning(config.server.tuning.clone());
+        server.set_forward_retry(config.webhook.forward_retry());
+        server.set_ack_deadline(config.webhook.ack_deadline());
+        if let Some(path) = &config.webhook.archive_path {
+            server.archive_deliveries(archive::JsonlArchive::new(path));
+        }
+        if let Some(path) = &config.github.audit_log_path {
+            server
+                .dispatcher()
+                .set_audit_sink(github::layers::JsonlAuditSink::new(path));
+        }
+        if let Some(dir) = &config.webhook.record_fixtures_dir {
+            if config.server.host.is_loopback() || config.webhook.record_fixtures_force {
+                server.record_fixtures(dir);
+            } else {
+                warn!(
+                    "Not recording fixtures on non-loopback address {}; \
+                     set OCTOFER_RECORD_FIXTURES_FORCE to record them to {} anyway",
+                    config.server.host, dir
+                );
+            }
+        }
 
-        Ok(Octofer {
-            config: config.clone(),
-            server,
-        })
+        Octofer { config, server }
     }
 
     /// Create a new Octofer instance with default configuration
@@ -417,6 +593,59 @@ impl Octofer {
         self.server.start().await
     }
 
+    /// Bind the application's address without serving requests yet
+    ///
+    /// Fails right away if the address cannot be bound. With
+    /// `server.port = 0` in the configuration, the operating system picks
+    /// a free port, reported by
+    /// [`BoundServer::local_addr`](webhook::BoundServer::local_addr). See
+    /// [`WebhookServer::bind`](webhook::WebhookServer::bind).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Config, Octofer};
+    ///
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let mut config = Config::from_env()?;
+    /// config.server.port = 0;
+    /// let app = Octofer::new(config).await?;
+    ///
+    /// let bound = app.bind().await?;
+    /// println!("Listening on {}", bound.local_addr());
+    /// bound.serve().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bind(&self) -> Result<webhook::BoundServer> {
+        self.server.bind().await
+    }
+
+    /// Start the application without processing deliveries yet
+    ///
+    /// Returns a [`Readiness`](webhook::Readiness) handle and the future
+    /// serving requests. Until [`Readiness::ready`](webhook::Readiness::ready)
+    /// is called, deliveries and health checks are answered with
+    /// `503 Service Unavailable`, so handlers can be registered through the
+    /// [`dispatcher`](Octofer::dispatcher) while the server already runs. See
+    /// the [`readiness`](webhook::readiness) module for an example.
+    pub fn start_paused(
+        &self,
+    ) -> (
+        webhook::Readiness,
+        impl std::future::Future<Output = Result<()>> + '_,
+    ) {
+        self.server.start_paused()
+    }
+
+    /// Get the dispatcher running registered handlers for incoming events
+    ///
+    /// Handlers registered on the dispatcher, or a clone of it, are shared
+    /// with this application, also while it is running.
+    pub fn dispatcher(&self) -> &dispatch::Dispatcher {
+        self.server.dispatcher()
+    }
+
     /// Get access to the configuration
     ///
     /// Returns a reference to the application configuration. This can be used
@@ -442,4 +671,368 @@ impl Octofer {
     pub fn config(&self) -> &Config {
         &self.config
     }
+
+    /// Register a hook invoked with the report of every webhook delivery
+    ///
+    /// Hooks run in background tasks after the handlers finished, and cannot
+    /// fail the delivery. See the [`report`](webhook::report) module for
+    /// details.
+    pub async fn on_delivery_complete<F, Fut>(&self, hook: F)
+    where
+        F: Fn(webhook::report::DeliveryReport) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.server.on_delivery_complete(hook).await;
+    }
+
+    /// Skip, or dispatch, deliveries of events caused by the app itself
+    ///
+    /// Overrides [`DispatchConfig::ignore_self`](config::DispatchConfig::ignore_self),
+    /// which is enabled by default, until the configuration is
+    /// [reloaded](Octofer::reload_config). To skip events of other bots too,
+    /// register handlers with
+    /// [`include_bots(false)`](core::HandlerRegistration::include_bots).
+    pub async fn ignore_self(&self, enabled: bool) {
+        let dispatcher = self.server.dispatcher();
+        let mut config = dispatcher.config().await;
+        config.ignore_self = enabled;
+        dispatcher.set_config(config).await;
+    }
+
+    /// Run every handler in dry-run mode, or only those registered with
+    /// [`dry_run`](core::HandlerRegistration::dry_run)
+    ///
+    /// Handlers in dry-run mode run, but their GitHub API writes are recorded
+    /// in the [delivery report](webhook::report::DeliveryReport::intended_actions)
+    /// instead of being sent. Overrides
+    /// [`DispatchConfig::dry_run`](config::DispatchConfig::dry_run) until the
+    /// configuration is [reloaded](Octofer::reload_config). See the
+    /// [`dry_run`](github::layers::dry_run) module.
+    pub async fn dry_run(&self, enabled: bool) {
+        let dispatcher = self.server.dispatcher();
+        let mut config = dispatcher.config().await;
+        config.dry_run = enabled;
+        dispatcher.set_config(config).await;
+    }
+
+    /// Set the responses synthesized for the writes of handlers in dry-run
+    /// mode
+    ///
+    /// Defaults to [`DryRunResponses::builtin`](github::layers::DryRunResponses::builtin).
+    pub fn set_dry_run_responses(&self, responses: github::layers::DryRunResponses) {
+        self.server.dispatcher().set_dry_run_responses(responses);
+    }
+
+    /// Set the templates handlers render with [`Context::render`]
+    ///
+    /// Defaults to [`Templates::builtin`](templates::Templates::builtin). See
+    /// the [`templates`] module.
+    pub fn set_templates(&self, templates: templates::Templates) {
+        self.server.dispatcher().set_templates(templates);
+    }
+
+    /// Set the provider evaluating the flags of handlers registered
+    /// [behind a flag](core::HandlerRegistration::behind_flag)
+    ///
+    /// Replaces the flags configured with `OCTOFER_FEATURE_FLAGS`. See the
+    /// [`flags`] module.
+    pub fn set_feature_flags<F: flags::FeatureFlags>(&self, flags: F) {
+        self.server.dispatcher().set_feature_flags(flags);
+    }
+
+    /// Store `value` as the app's state of its type, returned by
+    /// [`Context::state`] in every handler
+    ///
+    /// Replaces the state of the same type, if any. Values are shared by all
+    /// handlers; see the [`state`] module for changing them.
+    pub fn state<T: Send + Sync + 'static>(&self, value: T) -> std::sync::Arc<T> {
+        self.server.dispatcher().state().insert(value)
+    }
+
+    /// Store `value` behind a lock as the app's mutable state of its type,
+    /// returned by [`Context::state_mut`] in every handler
+    pub fn state_mut<T: Send + 'static>(&self, value: T) -> std::sync::Arc<tokio::sync::Mutex<T>> {
+        self.state(tokio::sync::Mutex::new(value))
+    }
+
+    /// Open a stream of the contexts of the app's deliveries, buffering up
+    /// to `buffer` of them and dropping the oldest when full
+    ///
+    /// Registered handlers still run; the stream ends when the server shuts
+    /// down. See the [`stream`] module.
+    pub fn event_stream(&self, buffer: usize) -> stream::EventStream {
+        self.event_stream_with(buffer, stream::Overflow::DropOldest)
+    }
+
+    /// Open a stream of the contexts of the app's deliveries, buffering up
+    /// to `buffer` of them and dropping contexts by `overflow` when full
+    pub fn event_stream_with(
+        &self,
+        buffer: usize,
+        overflow: stream::Overflow,
+    ) -> stream::EventStream {
+        self.server.dispatcher().event_stream(buffer, overflow)
+    }
+
+    /// Set the storage of the app's stateful features, e.g. backfill cursors
+    ///
+    /// Defaults to an [`InMemoryStore`](storage::InMemoryStore). See the
+    /// [`storage`] module.
+    pub fn set_store<S: storage::Store>(&self, store: S) {
+        self.server.dispatcher().set_store(store);
+    }
+
+    /// Get the storage of the app's stateful features
+    ///
+    /// The returned store follows later calls to [`Octofer::set_store`].
+    pub fn store(&self) -> std::sync::Arc<dyn storage::Store> {
+        self.server.dispatcher().store()
+    }
+
+    /// Set the sink receiving the audit records of the handlers' GitHub API
+    /// writes
+    ///
+    /// Replaces the JSONL file configured with `OCTOFER_AUDIT_LOG_PATH`, if
+    /// any. See the [`audit`](github::layers::audit) module.
+    pub fn set_audit_sink<S: github::layers::AuditSink>(&self, sink: S) {
+        self.server.dispatcher().set_audit_sink(sink);
+    }
+
+    /// Receive a plain, non-App webhook at `path`, verified with `secret`
+    ///
+    /// Deliveries go to the app's handlers, without an installation. See the
+    /// [`plain`](webhook::plain) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` does not start with `/` or is already
+    /// served, or `secret` is not safe for the server's host.
+    pub fn add_plain_webhook(&mut self, path: &str, secret: &str) -> Result<()> {
+        self.server.add_plain_webhook(path, secret)
+    }
+
+    /// Serve `method_router` at `path` next to the webhook
+    ///
+    /// The route's handlers receive a [`RouteState`](webhook::RouteState)
+    /// with the app's GitHub client and configuration. See the
+    /// [`routes`](webhook::routes) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` does not start with `/` or is served by
+    /// the server itself.
+    pub fn route(
+        &mut self,
+        path: &str,
+        method_router: axum::routing::MethodRouter<webhook::RouteState>,
+    ) -> Result<()> {
+        let state = self.route_state();
+        self.server.route(path, method_router, state)
+    }
+
+    /// Serve the routes of `router` under `path` next to the webhook
+    ///
+    /// Like [`Octofer::route`], for a whole router; see the
+    /// [`routes`](webhook::routes) module for an example.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` does not start with `/`, is `/`, or
+    /// contains a path served by the server itself.
+    pub fn nest(&mut self, path: &str, router: axum::Router<webhook::RouteState>) -> Result<()> {
+        let state = self.route_state();
+        self.server.nest(path, router, state)
+    }
+
+    /// Get the state passed to custom routes
+    pub fn route_state(&self) -> webhook::RouteState {
+        webhook::RouteState::new(self.server.github_client().cloned(), self.config.clone())
+    }
+
+    /// Forward successfully handled deliveries of `events` to `target_url`
+    ///
+    /// Each delivery is re-POSTed with an `X-Octofer-Signature-256` header
+    /// computed with `signing_secret`, and its GitHub headers as
+    /// `X-Forwarded-GitHub-*`. Failed requests are retried as configured with
+    /// `OCTOFER_FORWARD_RETRIES`, without affecting the response to GitHub.
+    /// See the [`forward`](webhook::forward) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an event name is invalid, `target_url` is not an
+    /// `http` or `https` URL, or `signing_secret` is empty.
+    pub fn forward_events(
+        &self,
+        events: &[&str],
+        target_url: &str,
+        signing_secret: &str,
+    ) -> Result<()> {
+        self.server
+            .forward_events(events, target_url, signing_secret)
+    }
+
+    /// Run the task created by `factory` in the background, restarting it
+    /// with backoff when it fails
+    ///
+    /// `factory` is called again for every restart. See the [`tasks`]
+    /// module.
+    pub fn spawn_supervised<F, Fut>(&self, name: &str, factory: F)
+    where
+        F: Fn(tasks::TaskShutdown) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.spawn_supervised_with(name, tasks::TaskOptions::default(), factory);
+    }
+
+    /// Run the task created by `factory` in the background, supervised as
+    /// set by `options`
+    ///
+    /// See the [`tasks`] module.
+    pub fn spawn_supervised_with<F, Fut>(&self, name: &str, options: tasks::TaskOptions, factory: F)
+    where
+        F: Fn(tasks::TaskShutdown) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.server.tasks().spawn(name, options, factory);
+    }
+
+    /// Get the supervisor of the app's background tasks
+    pub fn tasks(&self) -> &tasks::TaskSupervisor {
+        self.server.tasks()
+    }
+
+    /// Stop the app's background tasks, waiting up to `timeout` for them to
+    /// return
+    ///
+    /// Returns the names of the tasks aborted after `timeout`. See
+    /// [`TaskSupervisor::shutdown`](tasks::TaskSupervisor::shutdown).
+    pub async fn shutdown_tasks(&self, timeout: std::time::Duration) -> Vec<String> {
+        self.server.tasks().shutdown(timeout).await
+    }
+
+    /// Write every verified delivery to `archive` once it was processed
+    ///
+    /// Replaces the archive configured with `OCTOFER_ARCHIVE_PATH`, if any.
+    /// See the [`archive`] module for details.
+    pub fn archive_deliveries<A: archive::DeliveryArchive>(&self, archive: A) {
+        self.server.archive_deliveries(archive);
+    }
+
+    /// Describe the registered handlers, dispatch configuration and handler
+    /// groups of this app
+    ///
+    /// See the [`info`](webhook::info) module.
+    pub async fn registration_summary(&self) -> webhook::RegistrationSummary {
+        self.server.registration_summary().await
+    }
+
+    /// Compare the app's webhook settings with the registered handlers
+    ///
+    /// Reports events with handlers the app is not subscribed to, and the
+    /// other differences described in the [`drift`](webhook::drift) module.
+    /// Useful in CI to assert that the app's subscriptions match its code.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the webhook settings cannot be requested, or the
+    /// app runs without a GitHub client and received no `ping` yet.
+    pub async fn check_subscription_drift(&self) -> Result<webhook::SubscriptionDrift> {
+        self.server.check_subscription_drift().await
+    }
+
+    /// Get the events and permissions the GitHub App needs for the
+    /// registered handlers
+    ///
+    /// Serializes to the `default_events` and `default_permissions` of an
+    /// app manifest. See the [`manifest`] module.
+    pub async fn required_manifest(&self) -> manifest::AppManifestRequirements {
+        self.server.dispatcher().required_manifest().await
+    }
+
+    /// Apply a new configuration without restarting the server
+    ///
+    /// Safe to call while [`Octofer::start`] is running; deliveries in flight
+    /// are not interrupted. The following settings take effect immediately:
+    ///
+    /// - Webhook secret, signature header and verification flags (see
+    ///   [`WebhookServer::set_hmac_config`])
+    /// - Log level (see [`LoggingConfig::reload`](config::LoggingConfig::reload))
+    /// - Dispatch configuration
+    /// - Whether the [registration summary](webhook::info) is served
+    /// - How failed [forwarding](webhook::forward) requests are retried
+    /// - The [acknowledgement deadline](webhook::deadline)
+    /// - The GitHub App credentials (see
+    ///   [`GitHubClient::reload_credentials`](github::GitHubClient::reload_credentials)),
+    ///   unless the client was injected with [`Octofer::with_github_client`]
+    ///
+    /// Changes to the server address still require a restart and are only
+    /// logged. [`Octofer::config`] keeps
+    /// returning the configuration the app was created with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new log level is invalid, the new private key
+    /// is not a valid RSA PEM key, or the new webhook configuration is not
+    /// safe for the server's host (see
+    /// [`HmacConfig::validate`](github::middlewares::HmacConfig::validate));
+    /// nothing is applied in that case.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{Config, Octofer};
+    ///
+    /// # async fn example(app: Octofer) -> anyhow::Result<()> {
+    /// let mut config = app.config().clone();
+    /// config.webhook.secret = "rotated-secret".into();
+    /// config.logging.level = "debug".to_string();
+    ///
+    /// app.reload_config(&config).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reload_config(&self, new: &Config) -> Result<()> {
+        new.webhook.hmac_config().validate(self.server.host)?;
+        let credentials = GitHubAuth::from_config(&new.github);
+        let client = self
+            .dispatcher()
+            .github_client()
+            .filter(|client| client.has_credentials() && !client.uses_credentials(&credentials));
+        if client.is_some() {
+            credentials.encoding_key()?;
+        }
+        new.logging.filter()?;
+
+        // Credentials are the only setting that can still fail to apply, so
+        // they go first and a failure leaves everything else unchanged
+        if let Some(client) = client {
+            client.reload_credentials(credentials).await?;
+        }
+        new.logging.reload()?;
+        self.server.set_hmac_config(new.webhook.hmac_config())?;
+        self.server.set_dispatch_config(new.dispatch.clone()).await;
+        self.server.set_info_endpoint(new.server.info_endpoint);
+        self.server.set_forward_retry(new.webhook.forward_retry());
+        self.server.set_ack_deadline(new.webhook.ack_deadline());
+
+        if new.server.host != self.config.server.host || new.server.port != self.config.server.port
+        {
+            warn!("Changing the server address requires a restart");
+        }
+        if new.server.tuning != self.config.server.tuning {
+            warn!("Changing the connection tuning requires a restart");
+        }
+        if new.webhook.archive_path != self.config.webhook.archive_path {
+            warn!("Changing the archive path requires a restart");
+        }
+        if new.github.audit_log_path != self.config.github.audit_log_path {
+            warn!("Changing the audit log path requires a restart");
+        }
+        if new.webhook.record_fixtures_dir != self.config.webhook.record_fixtures_dir {
+            warn!("Changing the fixture recording directory requires a restart");
+        }
+
+        info!("Configuration reloaded");
+        Ok(())
+    }
 }
@@ -0,0 +1,533 @@
+//! GitHub App manifest requirements of the registered handlers
+//!
+//! A GitHub App only receives the events it subscribes to, and each event
+//! needs a permission to subscribe to it; the helpers handlers call need
+//! permissions of their own. [`AppManifestRequirements`], obtained with
+//! [`Octofer::required_manifest`](crate::Octofer::required_manifest),
+//! derives the minimal `default_events` and `default_permissions` of the
+//! [app manifest](https://docs.github.com/en/apps/sharing-github-apps/registering-a-github-app-from-a-manifest)
+//! from the registered handlers:
+//!
+//! - the event type of every handler, except the events GitHub delivers to
+//!   every app, with the permission the event requires ([`EVENT_PERMISSIONS`]);
+//! - the requirements handlers declare with
+//!   [`HandlerRegistration::requires_permissions`](crate::core::HandlerRegistration::requires_permissions),
+//!   either permissions (`issues:write`) or names of [`Context`](crate::Context)
+//!   helpers, looked up in [`HELPER_PERMISSIONS`].
+//!
+//! `metadata: read` is always required. Requirements that could not be
+//! resolved, e.g. a misspelled helper, are listed in
+//! [`AppManifestRequirements::unresolved`] rather than dropped.
+//!
+//! The requirements serialize to the manifest's JSON format. Compared with
+//! the app's configuration on GitHub, permissions the app lacks are reported
+//! by [subscription drift](crate::webhook::drift) checks.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut app = Octofer::new_default();
+//! app.on_issue(
+//!     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+//!     Arc::new(()),
+//! )
+//! .await
+//! .requires_permissions(&["upsert_comment", "checks:write"]);
+//!
+//! let manifest = app.required_manifest().await;
+//! assert_eq!(manifest.default_events, ["issues"]);
+//! assert_eq!(
+//!     serde_json::to_value(&manifest.default_permissions).unwrap(),
+//!     serde_json::json!({ "checks": "write", "issues": "write", "metadata": "read" })
+//! );
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::helpers::permissions::Permission;
+use crate::webhook::drift::ALWAYS_DELIVERED_EVENTS;
+
+/// Permission every GitHub App has, to read repository metadata
+pub const METADATA_PERMISSION: &str = "metadata";
+
+/// Permission an app needs to subscribe to each event
+///
+/// Events delivered to every app ([`ALWAYS_DELIVERED_EVENTS`]) need none.
+pub const EVENT_PERMISSIONS: &[(&str, &str, Permission)] = &[
+    ("branch_protection_rule", "administration", Permission::Read),
+    ("check_run", "checks", Permission::Read),
+    ("check_suite", "checks", Permission::Read),
+    ("code_scanning_alert", "security_events", Permission::Read),
+    ("commit_comment", "contents", Permission::Read),
+    ("create", "contents", Permission::Read),
+    ("delete", "contents", Permission::Read),
+    ("dependabot_alert", "vulnerability_alerts", Permission::Read),
+    ("deploy_key", "administration", Permission::Read),
+    ("deployment", "deployments", Permission::Read),
+    ("deployment_status", "deployments", Permission::Read),
+    ("discussion", "discussions", Permission::Read),
+    ("discussion_comment", "discussions", Permission::Read),
+    ("fork", "contents", Permission::Read),
+    ("gollum", "contents", Permission::Read),
+    ("issue_comment", "issues", Permission::Read),
+    ("issues", "issues", Permission::Read),
+    ("label", "metadata", Permission::Read),
+    ("member", "metadata", Permission::Read),
+    ("membership", "members", Permission::Read),
+    ("merge_group", "merge_queues", Permission::Read),
+    ("milestone", "issues", Permission::Read),
+    ("organization", "members", Permission::Read),
+    ("package", "packages", Permission::Read),
+    ("page_build", "pages", Permission::Read),
+    (
+        "projects_v2_item",
+        "organization_projects",
+        Permission::Read,
+    ),
+    ("public", "metadata", Permission::Read),
+    ("pull_request", "pull_requests", Permission::Read),
+    ("pull_request_review", "pull_requests", Permission::Read),
+    (
+        "pull_request_review_comment",
+        "pull_requests",
+        Permission::Read,
+    ),
+    (
+        "pull_request_review_thread",
+        "pull_requests",
+        Permission::Read,
+    ),
+    ("push", "contents", Permission::Read),
+    ("registry_package", "packages", Permission::Read),
+    ("release", "contents", Permission::Read),
+    ("repository", "metadata", Permission::Read),
+    ("repository_dispatch", "contents", Permission::Read),
+    ("repository_ruleset", "administration", Permission::Read),
+    (
+        "secret_scanning_alert",
+        "secret_scanning_alerts",
+        Permission::Read,
+    ),
+    (
+        "secret_scanning_alert_location",
+        "secret_scanning_alerts",
+        Permission::Read,
+    ),
+    ("star", "metadata", Permission::Read),
+    ("status", "statuses", Permission::Read),
+    ("sub_issues", "issues", Permission::Read),
+    ("team", "members", Permission::Read),
+    ("team_add", "members", Permission::Read),
+    ("watch", "metadata", Permission::Read),
+    ("workflow_dispatch", "actions", Permission::Read),
+    ("workflow_job", "actions", Permission::Read),
+    ("workflow_run", "actions", Permission::Read),
+];
+
+/// Permissions the [`Context`](crate::Context) and
+/// [`GitHubClient`](crate::github::GitHubClient) helpers need, by helper name
+pub const HELPER_PERMISSIONS: &[(&str, &[(&str, Permission)])] = &[
+    ("add_assignees", &[("issues", Permission::Write)]),
+    (
+        "add_discussion_label",
+        &[("discussions", Permission::Write)],
+    ),
+    ("add_sub_issue", &[("issues", Permission::Write)]),
+    (
+        "alerts_summary_for_installation",
+        &[("vulnerability_alerts", Permission::Read)],
+    ),
+    (
+        "apply_branch_protection",
+        &[("administration", Permission::Write)],
+    ),
+    (
+        "apply_repo_settings",
+        &[("administration", Permission::Write)],
+    ),
+    ("approve", &[("pull_requests", Permission::Write)]),
+    (
+        "associated_pull_requests",
+        &[("pull_requests", Permission::Read)],
+    ),
+    (
+        "associated_pull_requests_are_drafts",
+        &[("pull_requests", Permission::Read)],
+    ),
+    ("changed_files", &[("pull_requests", Permission::Read)]),
+    ("close_as_duplicate", &[("issues", Permission::Write)]),
+    ("close_issue", &[("issues", Permission::Write)]),
+    ("compare", &[("contents", Permission::Read)]),
+    (
+        "convert_issue_to_discussion",
+        &[
+            ("discussions", Permission::Write),
+            ("issues", Permission::Write),
+        ],
+    ),
+    ("create_check_run", &[("checks", Permission::Write)]),
+    (
+        "dismiss_dependabot_alert",
+        &[("vulnerability_alerts", Permission::Write)],
+    ),
+    ("dismiss_review", &[("pull_requests", Permission::Write)]),
+    ("download_job_logs", &[("actions", Permission::Read)]),
+    ("download_run_logs_zip", &[("actions", Permission::Read)]),
+    ("edit_issue", &[("issues", Permission::Write)]),
+    (
+        "ensure_check_exists",
+        &[
+            ("checks", Permission::Write),
+            ("contents", Permission::Read),
+        ],
+    ),
+    ("failed_jobs", &[("actions", Permission::Read)]),
+    ("fetch_wiki_page", &[("contents", Permission::Read)]),
+    ("get_file", &[("contents", Permission::Read)]),
+    ("get_file_uncached", &[("contents", Permission::Read)]),
+    ("is_trusted", &[("metadata", Permission::Read)]),
+    (
+        "list_dependabot_alerts",
+        &[("vulnerability_alerts", Permission::Read)],
+    ),
+    (
+        "list_merged_prs_between",
+        &[
+            ("contents", Permission::Read),
+            ("pull_requests", Permission::Read),
+        ],
+    ),
+    ("list_org_members", &[("members", Permission::Read)]),
+    ("list_reviews", &[("pull_requests", Permission::Read)]),
+    (
+        "list_secret_alert_locations",
+        &[("secret_scanning_alerts", Permission::Read)],
+    ),
+    ("list_sub_issues", &[("issues", Permission::Read)]),
+    ("list_team_members", &[("members", Permission::Read)]),
+    ("lock_discussion", &[("discussions", Permission::Write)]),
+    ("lock_issue", &[("issues", Permission::Write)]),
+    (
+        "mark_comment_as_answer",
+        &[("discussions", Permission::Write)],
+    ),
+    ("open_tracking_issue", &[("issues", Permission::Write)]),
+    (
+        "propagate_label_change_to",
+        &[("issues", Permission::Write)],
+    ),
+    ("push_changed_files", &[("contents", Permission::Read)]),
+    ("queue_comment", &[("issues", Permission::Write)]),
+    ("remove_assignees", &[("issues", Permission::Write)]),
+    ("remove_sub_issue", &[("issues", Permission::Write)]),
+    ("render", &[("contents", Permission::Read)]),
+    ("reopen_issue", &[("issues", Permission::Write)]),
+    ("repo_config", &[("contents", Permission::Read)]),
+    ("request_changes", &[("pull_requests", Permission::Write)]),
+    ("request_reviewers", &[("pull_requests", Permission::Write)]),
+    (
+        "require_status_check",
+        &[("administration", Permission::Write)],
+    ),
+    ("resolve_commit", &[("contents", Permission::Read)]),
+    (
+        "resolve_secret_alert",
+        &[("secret_scanning_alerts", Permission::Write)],
+    ),
+    ("search_code", &[("contents", Permission::Read)]),
+    ("search_issues", &[("issues", Permission::Read)]),
+    ("search_issues_in_repo", &[("issues", Permission::Read)]),
+    ("search_similar_issues", &[("issues", Permission::Read)]),
+    (
+        "send_repository_dispatch",
+        &[("contents", Permission::Write)],
+    ),
+    ("sender_can_write", &[("metadata", Permission::Read)]),
+    ("sender_permission", &[("metadata", Permission::Read)]),
+    ("set_commit_status", &[("statuses", Permission::Write)]),
+    ("transfer_issue", &[("issues", Permission::Write)]),
+    ("unlock_issue", &[("issues", Permission::Write)]),
+    ("update_release_notes", &[("contents", Permission::Write)]),
+    ("upload_release_asset", &[("contents", Permission::Write)]),
+    (
+        "upload_release_asset_stream",
+        &[("contents", Permission::Write)],
+    ),
+    ("upsert_comment", &[("issues", Permission::Write)]),
+];
+
+/// Events and permissions a GitHub App needs for its registered handlers
+///
+/// See the [module documentation](self). Serializes to the
+/// `default_events` and `default_permissions` of an app manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AppManifestRequirements {
+    /// Events to subscribe to, sorted
+    pub default_events: Vec<String>,
+    /// Permissions to request, with the highest level required
+    pub default_permissions: BTreeMap<String, Permission>,
+    /// Event types and declared requirements that could not be resolved to
+    /// permissions
+    #[serde(skip)]
+    pub unresolved: Vec<String>,
+}
+
+impl AppManifestRequirements {
+    /// Derive the requirements of handlers, given as their event type and
+    /// the requirements they declared
+    pub fn from_handlers<'a, I>(handlers: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a [String])>,
+    {
+        let mut requirements = Self::default();
+        requirements.require(METADATA_PERMISSION, Permission::Read);
+
+        for (event, declared) in handlers {
+            if !ALWAYS_DELIVERED_EVENTS.contains(&event)
+                && !requirements.default_events.iter().any(|e| e == event)
+            {
+                match event_permission(event) {
+                    Some((name, level)) => {
+                        requirements.default_events.push(event.to_string());
+                        requirements.require(name, level);
+                    }
+                    None => requirements.unresolve(format!("event {}", event)),
+                }
+            }
+            for requirement in declared {
+                match resolve_requirement(requirement) {
+                    Some(permissions) => {
+                        for (name, level) in permissions {
+                            requirements.require(&name, level);
+                        }
+                    }
+                    None => requirements.unresolve(requirement.clone()),
+                }
+            }
+        }
+
+        requirements.default_events.sort();
+        requirements.unresolved.sort();
+        requirements
+    }
+
+    /// Require `name` at `level`, keeping any higher level already required
+    pub fn require(&mut self, name: &str, level: Permission) {
+        let required = self
+            .default_permissions
+            .entry(name.to_string())
+            .or_insert(level);
+        *required = (*required).max(level);
+    }
+
+    /// Get the required permissions that `granted` lacks or grants at a
+    /// lower level
+    pub fn missing_permissions(
+        &self,
+        granted: &BTreeMap<String, Permission>,
+    ) -> Vec<MissingAppPermission> {
+        self.default_permissions
+            .iter()
+            .filter_map(|(name, &required)| {
+                let granted = granted.get(name).copied();
+                granted
+                    .is_none_or(|granted| granted < required)
+                    .then(|| MissingAppPermission {
+                        permission: name.clone(),
+                        required,
+                        granted,
+                    })
+            })
+            .collect()
+    }
+
+    fn unresolve(&mut self, requirement: String) {
+        if !self.unresolved.contains(&requirement) {
+            self.unresolved.push(requirement);
+        }
+    }
+}
+
+/// A permission the handlers need that the app does not request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissingAppPermission {
+    /// Name of the permission, e.g. `checks`
+    pub permission: String,
+    /// Level the handlers need
+    pub required: Permission,
+    /// Level the app requests, if any
+    pub granted: Option<Permission>,
+}
+
+/// Get the permission needed to subscribe to `event`
+pub fn event_permission(event: &str) -> Option<(&'static str, Permission)> {
+    EVENT_PERMISSIONS
+        .iter()
+        .find(|(name, _, _)| *name == event)
+        .map(|&(_, permission, level)| (permission, level))
+}
+
+/// Get the permissions of a requirement declared by a handler
+///
+/// `requirement` is either a permission and its level, `issues:write`, or
+/// the name of a helper in [`HELPER_PERMISSIONS`]. Returns `None` for
+/// unknown helpers and levels other than `read`, `write` and `admin`.
+pub fn resolve_requirement(requirement: &str) -> Option<Vec<(String, Permission)>> {
+    let requirement = requirement.trim();
+    if let Some((name, level)) = requirement.split_once(':') {
+        let level = Permission::from_name(level.trim()).filter(|level| {
+            matches!(
+                level,
+                Permission::Read | Permission::Write | Permission::Admin
+            )
+        })?;
+        let name = name.trim();
+        return (!name.is_empty()).then(|| vec![(name.to_string(), level)]);
+    }
+
+    HELPER_PERMISSIONS
+        .iter()
+        .find(|(helper, _)| *helper == requirement)
+        .map(|(_, permissions)| {
+            permissions
+                .iter()
+                .map(|&(name, level)| (name.to_string(), level))
+                .collect()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables_are_sorted_and_use_app_levels() {
+        for table in [
+            EVENT_PERMISSIONS
+                .iter()
+                .map(|(event, _, _)| *event)
+                .collect::<Vec<_>>(),
+            HELPER_PERMISSIONS
+                .iter()
+                .map(|(helper, _)| *helper)
+                .collect(),
+        ] {
+            let mut sorted = table.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(table, sorted);
+        }
+
+        let levels = EVENT_PERMISSIONS.iter().map(|&(_, _, level)| level).chain(
+            HELPER_PERMISSIONS
+                .iter()
+                .flat_map(|(_, permissions)| permissions.iter().map(|&(_, level)| level)),
+        );
+        for level in levels {
+            assert!(matches!(
+                level,
+                Permission::Read | Permission::Write | Permission::Admin
+            ));
+        }
+        for event in ALWAYS_DELIVERED_EVENTS {
+            assert!(event_permission(event).is_none());
+        }
+    }
+
+    #[test]
+    fn test_helper_permissions() {
+        let resolve = |requirement| resolve_requirement(requirement).unwrap();
+        assert_eq!(
+            resolve("upsert_comment"),
+            [("issues".to_string(), Permission::Write)]
+        );
+        assert_eq!(
+            resolve("create_check_run"),
+            [("checks".to_string(), Permission::Write)]
+        );
+        assert_eq!(
+            resolve("apply_repo_settings"),
+            [("administration".to_string(), Permission::Write)]
+        );
+        assert_eq!(
+            resolve("convert_issue_to_discussion"),
+            [
+                ("discussions".to_string(), Permission::Write),
+                ("issues".to_string(), Permission::Write)
+            ]
+        );
+        assert_eq!(
+            resolve(" pages : admin "),
+            [("pages".to_string(), Permission::Admin)]
+        );
+
+        assert!(resolve_requirement("upsert_comments").is_none());
+        assert!(resolve_requirement("issues:maintain").is_none());
+        assert!(resolve_requirement(":write").is_none());
+    }
+
+    #[test]
+    fn test_requirements_from_handlers() {
+        let declared = [
+            vec!["upsert_comment".to_string(), "checks:write".to_string()],
+            vec!["get_file".to_string(), "issues:read".to_string()],
+            vec!["no_such_helper".to_string()],
+        ];
+        let requirements = AppManifestRequirements::from_handlers([
+            ("issues", declared[0].as_slice()),
+            ("pull_request", declared[1].as_slice()),
+            ("issues", declared[2].as_slice()),
+            ("installation", &[][..]),
+            ("brand_new_event", &[][..]),
+        ]);
+
+        assert_eq!(requirements.default_events, ["issues", "pull_request"]);
+        assert_eq!(
+            serde_json::to_value(&requirements).unwrap(),
+            serde_json::json!({
+                "default_events": ["issues", "pull_request"],
+                "default_permissions": {
+                    "checks": "write",
+                    "contents": "read",
+                    "issues": "write",
+                    "metadata": "read",
+                    "pull_requests": "read",
+                },
+            })
+        );
+        assert_eq!(
+            requirements.unresolved,
+            ["event brand_new_event", "no_such_helper"]
+        );
+
+        let granted = BTreeMap::from([
+            ("issues".to_string(), Permission::Read),
+            ("metadata".to_string(), Permission::Read),
+            ("contents".to_string(), Permission::Write),
+            ("pull_requests".to_string(), Permission::Write),
+        ]);
+        assert_eq!(
+            requirements.missing_permissions(&granted),
+            [
+                MissingAppPermission {
+                    permission: "checks".to_string(),
+                    required: Permission::Write,
+                    granted: None,
+                },
+                MissingAppPermission {
+                    permission: "issues".to_string(),
+                    required: Permission::Write,
+                    granted: Some(Permission::Read),
+                },
+            ]
+        );
+    }
+}
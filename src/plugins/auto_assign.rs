@@ -0,0 +1,619 @@
+//! Automatic assignment of new issues and pull requests
+//!
+//! The auto-assign plugin assigns every opened issue and pull request to a
+//! member of a roster, picked with an [`AssignStrategy`]:
+//!
+//! - [`AssignStrategy::RoundRobin`] takes turns, with a counter per repository
+//!   kept in a [`RotationStore`] (by default the app's
+//!   [store](crate::storage), in the [`STORE_NAMESPACE`] namespace);
+//! - [`AssignStrategy::LoadBalanced`] picks the member with the fewest open
+//!   issues and pull requests assigned in the repository, counted with the
+//!   search API and cached for [`LOAD_CACHE_TTL`].
+//!
+//! Draft pull requests, issues and pull requests opened by bots, and those
+//! opened with assignees are left alone. Repositories can override the roster
+//! and strategy, or opt out, with a YAML file (`.github/auto_assign.yml` by
+//! default):
+//!
+//! ```yaml
+//! enabled: true
+//! roster: [alice, bob]
+//! strategy: load_balanced
+//! ```
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::plugins::auto_assign::{self, AssignStrategy, AutoAssignConfig};
+//! use octofer::Octofer;
+//!
+//! # async fn example(mut app: Octofer) {
+//! auto_assign::register(
+//!     &mut app,
+//!     AutoAssignConfig::new(["alice", "bob", "carol"], AssignStrategy::RoundRobin),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context as _, Result};
+use futures::future::BoxFuture;
+use octocrab::models::webhook_events::payload::{
+    IssuesWebhookEventAction, PullRequestWebhookEventAction,
+};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::core::HandlerRegistration;
+use crate::helpers::{fetch_optional_file, path_segment};
+use crate::storage::Namespace;
+use crate::{Context, Octofer};
+
+/// Default location of the per-repository override file
+pub const DEFAULT_CONFIG_PATH: &str = ".github/auto_assign.yml";
+
+/// Namespace of the round-robin turns in the app's
+/// [`Store`](crate::storage::Store)
+pub const STORE_NAMESPACE: &str = "auto_assign";
+
+/// How long the open assignment counts of [`AssignStrategy::LoadBalanced`]
+/// are reused
+pub const LOAD_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// How the plugin picks the member of the roster to assign
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssignStrategy {
+    /// Take turns, in roster order
+    #[default]
+    RoundRobin,
+    /// Pick the member with the fewest open assigned issues and pull
+    /// requests, the first in roster order on ties
+    LoadBalanced,
+}
+
+impl fmt::Display for AssignStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::RoundRobin => "round_robin",
+            Self::LoadBalanced => "load_balanced",
+        })
+    }
+}
+
+/// Storage of the round-robin turns, keyed by repository (`owner/name`)
+///
+/// Implement it to keep turns across restarts, e.g. in a database.
+pub trait RotationStore: Send + Sync {
+    /// Return the turn of repository `key`, starting at 0, and advance it
+    ///
+    /// Concurrent calls for the same key must return distinct turns.
+    fn next_turn(&self, key: &str) -> BoxFuture<'_, Result<u64>>;
+}
+
+impl RotationStore for Namespace {
+    fn next_turn(&self, key: &str) -> BoxFuture<'_, Result<u64>> {
+        let key = key.to_string();
+        Box::pin(async move { Ok(self.increment(&key).await? - 1) })
+    }
+}
+
+/// [`RotationStore`] keeping turns in memory
+///
+/// Turns restart from the first roster member on restart.
+#[derive(Debug, Default)]
+pub struct InMemoryRotationStore {
+    turns: Mutex<HashMap<String, u64>>,
+}
+
+impl InMemoryRotationStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RotationStore for InMemoryRotationStore {
+    fn next_turn(&self, key: &str) -> BoxFuture<'_, Result<u64>> {
+        let mut turns = self.turns.lock().unwrap();
+        let turn = turns.entry(key.to_string()).or_default();
+        let current = *turn;
+        *turn += 1;
+        Box::pin(async move { Ok(current) })
+    }
+}
+
+/// Configuration of the auto-assign plugin
+#[derive(Clone)]
+pub struct AutoAssignConfig {
+    /// Logins of the users to assign
+    pub roster: Vec<String>,
+    /// How the assignee is picked
+    pub strategy: AssignStrategy,
+    /// Storage of the round-robin turns (`None` for the app's store, or
+    /// memory for assigners created with [`AutoAssigner::new`])
+    pub store: Option<Arc<dyn RotationStore>>,
+    /// Path of the per-repository override file
+    pub path: String,
+}
+
+impl AutoAssignConfig {
+    /// Assign the users of `roster` with `strategy`, keeping turns in the
+    /// app's store
+    pub fn new<I, S>(roster: I, strategy: AssignStrategy) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            roster: roster.into_iter().map(Into::into).collect(),
+            strategy,
+            ..Self::default()
+        }
+    }
+
+    /// Keep the round-robin turns in `store`
+    pub fn with_store(mut self, store: Arc<dyn RotationStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+}
+
+impl Default for AutoAssignConfig {
+    fn default() -> Self {
+        Self {
+            roster: Vec::new(),
+            strategy: AssignStrategy::default(),
+            store: None,
+            path: DEFAULT_CONFIG_PATH.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for AutoAssignConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoAssignConfig")
+            .field("roster", &self.roster)
+            .field("strategy", &self.strategy)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Contents of the per-repository override file
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RepoOverride {
+    /// Whether to assign issues and pull requests of the repository
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// Roster replacing the configured one
+    #[serde(default)]
+    pub roster: Option<Vec<String>>,
+    /// Strategy replacing the configured one
+    #[serde(default)]
+    pub strategy: Option<AssignStrategy>,
+}
+
+impl RepoOverride {
+    /// Parse an override file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the YAML is invalid.
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        serde_yaml::from_str(yaml).context("Invalid auto-assign configuration")
+    }
+}
+
+/// Picks and assigns the assignees of new issues and pull requests
+///
+/// [`register`] runs an `AutoAssigner` for `issues.opened` and
+/// `pull_request.opened` events; use it directly to assign from other
+/// handlers.
+pub struct AutoAssigner {
+    config: AutoAssignConfig,
+    /// Storage of the round-robin turns
+    store: Arc<dyn RotationStore>,
+    /// Open assignment counts by repository and login, with when they were
+    /// counted
+    loads: Mutex<HashMap<(String, String), (u64, Instant)>>,
+}
+
+impl fmt::Debug for AutoAssigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoAssigner")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AutoAssigner {
+    /// Create an assigner with `config`, keeping turns in memory unless
+    /// `config` has a store
+    pub fn new(config: AutoAssignConfig) -> Self {
+        let store = config
+            .store
+            .clone()
+            .unwrap_or_else(|| Arc::new(InMemoryRotationStore::new()));
+        Self {
+            config,
+            store,
+            loads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Assign the issue or pull request opened by the context's event
+    ///
+    /// Returns the assigned login, or `None` if the event is not about an
+    /// opened issue or pull request, it is skipped (draft, opened by a bot or
+    /// with assignees), the repository opted out, or the roster is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the override file cannot be fetched or parsed, no
+    /// installation client is available, or a request fails.
+    pub async fn assign(&self, context: &Context) -> Result<Option<String>> {
+        if !should_assign(context) {
+            return Ok(None);
+        }
+        let (owner, repo) = context.require_repository()?;
+        let client = context.require_installation_client().await?;
+
+        let Some(assignee) = self.pick(&client, &owner, &repo).await? else {
+            return Ok(None);
+        };
+        info!("{}/{}: assigning {}", owner, repo, assignee);
+        context.add_assignees(&[&assignee]).await?;
+        Ok(Some(assignee))
+    }
+
+    /// Pick the member of the roster to assign in `owner/repo`
+    ///
+    /// Applies the repository's override file, and advances the round-robin
+    /// turn of the repository.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the override file cannot be fetched or parsed, or
+    /// the turn cannot be advanced.
+    pub async fn pick(&self, client: &Octocrab, owner: &str, repo: &str) -> Result<Option<String>> {
+        let repo_route = format!("/repos/{}/{}", path_segment(owner), path_segment(repo));
+        let repo_override =
+            match fetch_optional_file(client, &repo_route, &self.config.path).await? {
+                Some(yaml) => RepoOverride::from_yaml(&yaml)
+                    .with_context(|| format!("in {} of {}/{}", self.config.path, owner, repo))?,
+                None => RepoOverride::default(),
+            };
+        if repo_override.enabled == Some(false) {
+            debug!("Auto-assign is disabled in {}/{}", owner, repo);
+            return Ok(None);
+        }
+        let roster = repo_override.roster.as_ref().unwrap_or(&self.config.roster);
+        if roster.is_empty() {
+            return Ok(None);
+        }
+
+        let key = format!("{}/{}", owner, repo);
+        let strategy = repo_override.strategy.unwrap_or(self.config.strategy);
+        let assignee = match strategy {
+            AssignStrategy::RoundRobin => self.next_in_turn(&key, roster).await?,
+            AssignStrategy::LoadBalanced => match self.least_loaded(client, &key, roster).await {
+                Ok(assignee) => assignee,
+                Err(e) => {
+                    warn!("Falling back to round-robin in {}: {:#}", key, e);
+                    self.next_in_turn(&key, roster).await?
+                }
+            },
+        };
+        Ok(Some(assignee))
+    }
+
+    async fn next_in_turn(&self, key: &str, roster: &[String]) -> Result<String> {
+        let turn = self.store.next_turn(key).await?;
+        Ok(roster[(turn % roster.len() as u64) as usize].clone())
+    }
+
+    /// Pick the roster member with the fewest open assignments, counting the
+    /// new one in the cache
+    async fn least_loaded(
+        &self,
+        client: &Octocrab,
+        key: &str,
+        roster: &[String],
+    ) -> Result<String> {
+        let mut best: Option<(u64, &String)> = None;
+        for login in roster {
+            let load = self.load(client, key, login).await?;
+            if best.is_none_or(|(fewest, _)| load < fewest) {
+                best = Some((load, login));
+            }
+        }
+        let (_, login) = best.expect("roster is not empty");
+        if let Some((load, _)) = self
+            .loads
+            .lock()
+            .unwrap()
+            .get_mut(&(key.to_string(), login.clone()))
+        {
+            *load += 1;
+        }
+        Ok(login.clone())
+    }
+
+    /// Number of open issues and pull requests assigned to `login` in
+    /// repository `key`
+    async fn load(&self, client: &Octocrab, key: &str, login: &str) -> Result<u64> {
+        let cache_key = (key.to_string(), login.to_string());
+        if let Some((load, counted_at)) = self.loads.lock().unwrap().get(&cache_key) {
+            if counted_at.elapsed() < LOAD_CACHE_TTL {
+                return Ok(*load);
+            }
+        }
+
+        let query = format!("repo:{} is:open assignee:{}", key, login);
+        let results: Value = client
+            .get(
+                "/search/issues",
+                Some(&json!({ "q": query, "per_page": 1 })),
+            )
+            .await
+            .with_context(|| format!("Failed to count the assignments of {}", login))?;
+        let load = results["total_count"].as_u64().unwrap_or_default();
+        self.loads
+            .lock()
+            .unwrap()
+            .insert(cache_key, (load, Instant::now()));
+        Ok(load)
+    }
+}
+
+/// Register handlers assigning opened issues and pull requests
+///
+/// Returns the registrations of the `issues` and `pull_request` handlers.
+pub async fn register(
+    app: &mut Octofer,
+    mut config: AutoAssignConfig,
+) -> (HandlerRegistration, HandlerRegistration) {
+    if config.store.is_none() {
+        config.store = Some(Arc::new(Namespace::new(app.store(), STORE_NAMESPACE)));
+    }
+    let assigner = Arc::new(AutoAssigner::new(config));
+    let issues = app
+        .on_issue(
+            |context: Context, assigner: Arc<AutoAssigner>| async move {
+                assigner.assign(&context).await?;
+                Ok(())
+            },
+            assigner.clone(),
+        )
+        .await;
+    let pull_requests = app
+        .on_pull_request(
+            |context: Context, assigner: Arc<AutoAssigner>| async move {
+                assigner.assign(&context).await?;
+                Ok(())
+            },
+            assigner,
+        )
+        .await;
+    (issues, pull_requests)
+}
+
+/// Whether the context's event opened an issue or a ready pull request,
+/// without assignees, by someone other than a bot
+fn should_assign(context: &Context) -> bool {
+    let Some(event) = context.event() else {
+        return false;
+    };
+    let author = match &event.specific {
+        WebhookEventPayload::Issues(payload)
+            if payload.action == IssuesWebhookEventAction::Opened =>
+        {
+            Some(&payload.issue.user)
+        }
+        WebhookEventPayload::PullRequest(payload)
+            if payload.action == PullRequestWebhookEventAction::Opened =>
+        {
+            if payload.pull_request.draft == Some(true) {
+                return false;
+            }
+            payload.pull_request.user.as_deref()
+        }
+        _ => return false,
+    };
+    let by_bot =
+        author.is_some_and(|author| author.r#type == "Bot" || author.login.ends_with("[bot]"));
+    !by_bot && context.assignees().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issues_payload, pull_request_payload, user, MockGitHub};
+    use axum::extract::{Path, Query};
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    fn not_found() -> axum::response::Response {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "message": "Not Found" })),
+        )
+            .into_response()
+    }
+
+    /// Routes assigning issues, in repositories without override file
+    fn issue_route() -> Router {
+        Router::new()
+            .route(
+                "/repos/octofer/{repo}/issues/{number}/assignees",
+                post(|Path((repo, number)): Path<(String, u64)>| async move {
+                    Json(crate::testing::issue("octofer", &repo, number))
+                }),
+            )
+            .route(
+                "/repos/octofer/app/contents/.github/auto_assign.yml",
+                get(|| async { not_found() }),
+            )
+    }
+
+    fn assigned(mock: &MockGitHub) -> Vec<Value> {
+        mock.requests()
+            .into_iter()
+            .filter(|request| request.path.ends_with("/assignees"))
+            .map(|request| request.body["assignees"][0].clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_rotation_persists_across_events() {
+        let mock = MockGitHub::start(issue_route()).await;
+        let store = Arc::new(InMemoryRotationStore::new());
+        let config = AutoAssignConfig::new(["alice", "bob", "carol"], AssignStrategy::RoundRobin)
+            .with_store(store.clone());
+        let assigner = AutoAssigner::new(config.clone());
+
+        for number in 1..=4 {
+            let context = mock.context("issues", issues_payload("opened", number));
+            assigner.assign(&context).await.unwrap();
+        }
+        // A new assigner with the same store continues the rotation
+        let context = mock.context("issues", issues_payload("opened", 5));
+        let assignee = AutoAssigner::new(config).assign(&context).await.unwrap();
+        assert_eq!(assignee.as_deref(), Some("bob"));
+        assert_eq!(assigned(&mock), ["alice", "bob", "carol", "alice", "bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_skips_drafts_bots_assigned_and_other_actions() {
+        let mock = MockGitHub::start(issue_route()).await;
+        let assigner =
+            AutoAssigner::new(AutoAssignConfig::new(["alice"], AssignStrategy::RoundRobin));
+
+        let mut draft = pull_request_payload("opened", 1, "abc");
+        draft["pull_request"]["draft"] = json!(true);
+        let mut by_bot = issues_payload("opened", 2);
+        let mut bot = user("dependabot[bot]");
+        bot["type"] = json!("Bot");
+        by_bot["issue"]["user"] = bot;
+        let mut with_assignee = issues_payload("opened", 3);
+        with_assignee["issue"]["assignees"] = json!([user("octocat")]);
+
+        for (event, payload) in [
+            ("pull_request", draft),
+            ("issues", by_bot),
+            ("issues", with_assignee),
+            ("issues", issues_payload("edited", 4)),
+        ] {
+            let context = mock.context(event, payload);
+            assert_eq!(assigner.assign(&context).await.unwrap(), None);
+        }
+        assert!(mock.requests().is_empty());
+
+        let context = mock.context("pull_request", pull_request_payload("opened", 5, "abc"));
+        assert_eq!(
+            assigner.assign(&context).await.unwrap().as_deref(),
+            Some("alice")
+        );
+        assert_eq!(
+            mock.requests().last().unwrap().path,
+            "/repos/octofer/app/issues/5/assignees"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_balanced_picks_least_loaded_with_cache() {
+        let mock = MockGitHub::start(issue_route().route(
+            "/search/issues",
+            get(|Query(query): Query<HashMap<String, String>>| async move {
+                let q = &query["q"];
+                let total = if q.ends_with("assignee:alice") {
+                    3
+                } else if q.ends_with("assignee:bob") {
+                    1
+                } else {
+                    2
+                };
+                Json(json!({ "total_count": total, "incomplete_results": false, "items": [] }))
+            }),
+        ))
+        .await;
+        let assigner = AutoAssigner::new(AutoAssignConfig::new(
+            ["alice", "bob", "carol"],
+            AssignStrategy::LoadBalanced,
+        ));
+
+        let mut picked = Vec::new();
+        for number in 1..=3 {
+            let context = mock.context("issues", issues_payload("opened", number));
+            picked.push(assigner.assign(&context).await.unwrap().unwrap());
+        }
+        // bob has the fewest (1), then ties with carol at 2 and comes first
+        // in the roster, then carol has the fewest
+        assert_eq!(picked, ["bob", "bob", "carol"]);
+
+        let searches: Vec<_> = mock
+            .requests()
+            .into_iter()
+            .filter(|request| request.path == "/search/issues")
+            .collect();
+        assert_eq!(searches.len(), 3, "counts are cached across events");
+        assert!(searches[0]
+            .query
+            .as_deref()
+            .unwrap()
+            .contains("q=repo%3Aoctofer%2Fapp+is%3Aopen+assignee%3Aalice"));
+    }
+
+    #[tokio::test]
+    async fn test_repository_override() {
+        let mock = MockGitHub::start(Router::new().route(
+            "/repos/octofer/{repo}/contents/.github/auto_assign.yml",
+            get(|Path(repo): Path<String>| async move {
+                let yaml = match repo.as_str() {
+                    "app" => "roster: [dave]\n",
+                    "quiet" => "enabled: false\n",
+                    _ => return not_found(),
+                };
+                Json(json!({ "encoding": "base64", "content": STANDARD.encode(yaml) }))
+                    .into_response()
+            }),
+        ))
+        .await;
+        let assigner =
+            AutoAssigner::new(AutoAssignConfig::new(["alice"], AssignStrategy::RoundRobin));
+        let client = mock.client().installation_client(1).await.unwrap();
+
+        assert_eq!(
+            assigner
+                .pick(&client, "octofer", "app")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("dave")
+        );
+        assert_eq!(
+            assigner.pick(&client, "octofer", "quiet").await.unwrap(),
+            None
+        );
+        assert_eq!(
+            assigner
+                .pick(&client, "octofer", "other")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("alice")
+        );
+    }
+}
@@ -0,0 +1,538 @@
+//! Declarative repository label management
+//!
+//! The label-sync plugin keeps a repository's labels in line with a YAML file
+//! checked into the repository (`.github/labels.yml` by default):
+//!
+//! ```yaml
+//! prune: true
+//! labels:
+//!   - name: bug
+//!     color: "d73a4a"
+//!     description: Something isn't working
+//!   - name: enhancement
+//!     color: "#a2eeef"
+//!     aliases: [feature, feature-request]
+//! ```
+//!
+//! Reconciling creates missing labels, updates colors and descriptions,
+//! renames labels listed under `aliases` (keeping them on existing issues),
+//! and, when `prune` is set, deletes labels not in the file.
+//!
+//! [`register`] reconciles whenever a push to the default branch touches the
+//! file. Octofer has no scheduler, so to also reconcile periodically call
+//! [`reconcile`] from your own timer, e.g. with [`tokio::time::interval`] and
+//! an installation client from
+//! [`GitHubClient::installation_client`](crate::github::GitHubClient::installation_client).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::plugins::label_sync::{self, LabelSyncOptions};
+//! use octofer::Octofer;
+//!
+//! # async fn example(mut app: Octofer) {
+//! label_sync::register(&mut app, LabelSyncOptions::default()).await;
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{bail, Context as _, Result};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::core::HandlerRegistration;
+use crate::helpers::{fetch_file, path_segment};
+use crate::{Context, Octofer};
+
+/// Default location of the label configuration file
+pub const DEFAULT_CONFIG_PATH: &str = ".github/labels.yml";
+
+/// Options for the label-sync plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelSyncOptions {
+    /// Path of the configuration file in the repository
+    pub path: String,
+    /// Log the planned changes without applying them
+    pub dry_run: bool,
+}
+
+impl Default for LabelSyncOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_CONFIG_PATH.to_string(),
+            dry_run: false,
+        }
+    }
+}
+
+/// Contents of the label configuration file
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LabelConfig {
+    /// Delete labels that are not listed in the file
+    #[serde(default)]
+    pub prune: bool,
+    /// Desired labels
+    #[serde(default)]
+    pub labels: Vec<LabelSpec>,
+}
+
+/// A desired label
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelSpec {
+    /// Label name
+    pub name: String,
+    /// Hex color, with or without a leading `#`
+    pub color: String,
+    /// Label description
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Previous names of the label; an existing label with one of these names
+    /// is renamed instead of creating a new one
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+/// An existing repository label
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ExistingLabel {
+    /// Label name
+    pub name: String,
+    /// Hex color without a leading `#`
+    pub color: String,
+    /// Label description
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A change needed to bring the repository's labels in line with the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelChange {
+    /// Create a new label
+    Create(LabelSpec),
+    /// Update the color or description of the existing label `name`
+    Update {
+        /// Current name of the label
+        name: String,
+        /// Desired label
+        spec: LabelSpec,
+    },
+    /// Rename the existing label `from`, which matched an alias
+    Rename {
+        /// Current name of the label
+        from: String,
+        /// Desired label
+        spec: LabelSpec,
+    },
+    /// Delete a label not present in the config (only when pruning)
+    Delete(String),
+}
+
+impl fmt::Display for LabelChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelChange::Create(spec) => write!(f, "create '{}' (#{})", spec.name, spec.color),
+            LabelChange::Update { name, spec } => {
+                write!(f, "update '{}' (#{})", name, spec.color)
+            }
+            LabelChange::Rename { from, spec } => {
+                write!(f, "rename '{}' to '{}' (#{})", from, spec.name, spec.color)
+            }
+            LabelChange::Delete(name) => write!(f, "delete '{}'", name),
+        }
+    }
+}
+
+impl LabelConfig {
+    /// Parse and validate a label configuration file
+    ///
+    /// Colors are normalized to lowercase hex without a leading `#`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the YAML is invalid, a color is not a 6-digit hex
+    /// value, or a name or alias is listed more than once.
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let mut config: LabelConfig =
+            serde_yaml::from_str(yaml).context("Invalid label configuration")?;
+
+        let mut seen = Vec::new();
+        for spec in &mut config.labels {
+            let color = spec.color.trim_start_matches('#').to_ascii_lowercase();
+            if color.len() != 6 || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("Invalid color '{}' for label '{}'", spec.color, spec.name);
+            }
+            spec.color = color;
+
+            for name in std::iter::once(&spec.name).chain(&spec.aliases) {
+                let name = name.to_lowercase();
+                if seen.contains(&name) {
+                    bail!("Label '{}' is listed more than once", name);
+                }
+                seen.push(name);
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Compute the changes needed to go from `current` to `desired`
+///
+/// Labels are matched by name, then by alias, ignoring case. Deletions are
+/// ordered last, after all creates, updates and renames.
+pub fn plan(current: &[ExistingLabel], desired: &LabelConfig) -> Vec<LabelChange> {
+    let mut claimed = vec![false; current.len()];
+    let mut changes = Vec::new();
+
+    let find = |name: &str, claimed: &[bool]| {
+        current
+            .iter()
+            .enumerate()
+            .position(|(i, label)| !claimed[i] && label.name.eq_ignore_ascii_case(name))
+    };
+
+    for spec in &desired.labels {
+        if let Some(i) = find(&spec.name, &claimed) {
+            claimed[i] = true;
+            if needs_update(&current[i], spec) {
+                changes.push(LabelChange::Update {
+                    name: current[i].name.clone(),
+                    spec: spec.clone(),
+                });
+            }
+        } else if let Some(i) = spec.aliases.iter().find_map(|a| find(a, &claimed)) {
+            claimed[i] = true;
+            changes.push(LabelChange::Rename {
+                from: current[i].name.clone(),
+                spec: spec.clone(),
+            });
+        } else {
+            changes.push(LabelChange::Create(spec.clone()));
+        }
+    }
+
+    if desired.prune {
+        changes.extend(
+            current
+                .iter()
+                .zip(&claimed)
+                .filter(|(_, claimed)| !**claimed)
+                .map(|(label, _)| LabelChange::Delete(label.name.clone())),
+        );
+    }
+
+    changes
+}
+
+fn needs_update(current: &ExistingLabel, spec: &LabelSpec) -> bool {
+    current.name != spec.name
+        || !current.color.eq_ignore_ascii_case(&spec.color)
+        || current.description.as_deref().unwrap_or_default()
+            != spec.description.as_deref().unwrap_or_default()
+}
+
+/// Reconcile the labels of `owner/repo` with its configuration file
+///
+/// Returns the planned changes. Unless `options.dry_run` is set, the changes
+/// are applied in order, stopping at the first failed request.
+///
+/// # Errors
+///
+/// Returns an error if the configuration file cannot be fetched or parsed, or
+/// an API request fails.
+pub async fn reconcile(
+    client: &Octocrab,
+    owner: &str,
+    repo: &str,
+    options: &LabelSyncOptions,
+) -> Result<Vec<LabelChange>> {
+    let repo_route = format!("/repos/{}/{}", path_segment(owner), path_segment(repo));
+
+    let config = LabelConfig::from_yaml(&fetch_file(client, &repo_route, &options.path).await?)?;
+    let current = list_labels(client, &repo_route).await?;
+    let changes = plan(&current, &config);
+
+    if changes.is_empty() {
+        debug!("Labels of {}/{} are up to date", owner, repo);
+        return Ok(changes);
+    }
+
+    for change in &changes {
+        if options.dry_run {
+            info!("[dry run] {}/{}: would {}", owner, repo, change);
+            continue;
+        }
+
+        info!("{}/{}: {}", owner, repo, change);
+        apply(client, &repo_route, change)
+            .await
+            .with_context(|| format!("Failed to {} in {}/{}", change, owner, repo))?;
+    }
+
+    Ok(changes)
+}
+
+/// Register a push handler reconciling labels when the config file changes
+///
+/// Only pushes to the repository's default branch that add or modify
+/// `options.path` trigger a reconcile.
+pub async fn register(app: &mut Octofer, options: LabelSyncOptions) -> HandlerRegistration {
+    app.on_push(
+        |context: Context, options: Arc<LabelSyncOptions>| async move {
+            if !push_touches(&context, &options.path) {
+                return Ok(());
+            }
+
+            let (owner, repo) = context.require_repository()?;
+            let client = context.require_installation_client().await?;
+            reconcile(&client, &owner, &repo, &options).await?;
+            Ok(())
+        },
+        Arc::new(options),
+    )
+    .await
+}
+
+/// Whether the context's push event targets the default branch and adds or
+/// modifies `path`
+fn push_touches(context: &Context, path: &str) -> bool {
+    let Some(event) = context.event() else {
+        return false;
+    };
+    let WebhookEventPayload::Push(push) = &event.specific else {
+        return false;
+    };
+
+    let default_branch = event
+        .repository
+        .as_ref()
+        .and_then(|r| r.default_branch.as_deref());
+    if let Some(branch) = default_branch {
+        if push.r#ref != format!("refs/heads/{}", branch) {
+            return false;
+        }
+    }
+
+    push.commits.iter().any(|commit| {
+        commit
+            .added
+            .iter()
+            .chain(&commit.modified)
+            .any(|file| file == path)
+    })
+}
+
+async fn list_labels(client: &Octocrab, repo_route: &str) -> Result<Vec<ExistingLabel>> {
+    let mut labels = Vec::new();
+    for page in 1u32.. {
+        let batch: Vec<ExistingLabel> = client
+            .get(
+                format!("{}/labels", repo_route),
+                Some(&json!({ "per_page": 100, "page": page })),
+            )
+            .await
+            .context("Failed to list labels")?;
+
+        let done = batch.len() < 100;
+        labels.extend(batch);
+        if done {
+            break;
+        }
+    }
+    Ok(labels)
+}
+
+/// Apply `change` to the labels of the repository at `repo_route`
+pub(crate) async fn apply(client: &Octocrab, repo_route: &str, change: &LabelChange) -> Result<()> {
+    let label_route = |name: &str| format!("{}/labels/{}", repo_route, path_segment(name));
+    let body = |spec: &LabelSpec| {
+        json!({
+            "color": spec.color,
+            "description": spec.description.as_deref().unwrap_or_default(),
+        })
+    };
+
+    match change {
+        LabelChange::Create(spec) => {
+            let mut body = body(spec);
+            body["name"] = json!(spec.name);
+            let _: Value = client
+                .post(format!("{}/labels", repo_route), Some(&body))
+                .await?;
+        }
+        LabelChange::Update { name: from, spec } | LabelChange::Rename { from, spec } => {
+            let mut body = body(spec);
+            body["new_name"] = json!(spec.name);
+            let _: Value = client.patch(label_route(from), Some(&body)).await?;
+        }
+        LabelChange::Delete(name) => {
+            let response = client._delete(label_route(name), None::<&()>).await?;
+            octocrab::map_github_error(response).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockGitHub;
+    use axum::{
+        routing::{delete, get, patch},
+        Json, Router,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    const CONFIG: &str = r##"
+prune: true
+labels:
+  - name: bug
+    color: "#D73A4A"
+    description: Something isn't working
+  - name: enhancement
+    color: a2eeef
+    aliases: [feature]
+  - name: good first issue
+    color: 7057ff
+"##;
+
+    fn existing(name: &str, color: &str, description: Option<&str>) -> ExistingLabel {
+        ExistingLabel {
+            name: name.to_string(),
+            color: color.to_string(),
+            description: description.map(str::to_string),
+        }
+    }
+
+    fn label_json(label: &ExistingLabel) -> Value {
+        json!({ "name": label.name, "color": label.color, "description": label.description })
+    }
+
+    fn mock_routes() -> Router {
+        let labels = [
+            existing("Bug", "d73a4a", Some("Something isn't working")),
+            existing("feature", "cccccc", None),
+            existing("wontfix", "ffffff", None),
+        ];
+        Router::new()
+            .route(
+                "/repos/octofer/app/contents/{*path}",
+                get(|| async {
+                    Json(json!({ "type": "file", "content": STANDARD.encode(CONFIG) }))
+                }),
+            )
+            .route(
+                "/repos/octofer/app/labels",
+                get(move || {
+                    let labels = labels.iter().map(label_json).collect::<Vec<_>>();
+                    async move { Json(Value::Array(labels)) }
+                })
+                .post(|Json(body): Json<Value>| async move { Json(body) }),
+            )
+            .route(
+                "/repos/octofer/app/labels/{name}",
+                patch(|Json(body): Json<Value>| async move { Json(body) })
+                    .merge(delete(|| async { axum::http::StatusCode::NO_CONTENT })),
+            )
+    }
+
+    #[test]
+    fn test_parse_normalizes_colors() {
+        let config = LabelConfig::from_yaml(CONFIG).unwrap();
+        assert!(config.prune);
+        assert_eq!(config.labels[0].color, "d73a4a");
+        assert_eq!(config.labels[1].aliases, vec!["feature"]);
+
+        assert!(LabelConfig::from_yaml("labels: [{ name: x, color: red }]").is_err());
+        assert!(LabelConfig::from_yaml(
+            "labels: [{ name: x, color: '000000' }, { name: X, color: '000000' }]"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut config = LabelConfig::from_yaml(CONFIG).unwrap();
+        let current = vec![
+            existing("bug", "d73a4a", Some("Something isn't working")),
+            existing("feature", "cccccc", None),
+            existing("wontfix", "ffffff", None),
+        ];
+
+        let changes = plan(&current, &config);
+        assert_eq!(
+            changes,
+            vec![
+                LabelChange::Rename {
+                    from: "feature".to_string(),
+                    spec: config.labels[1].clone(),
+                },
+                LabelChange::Create(config.labels[2].clone()),
+                LabelChange::Delete("wontfix".to_string()),
+            ]
+        );
+
+        config.prune = false;
+        assert!(!plan(&current, &config)
+            .iter()
+            .any(|c| matches!(c, LabelChange::Delete(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_applies_changes() {
+        let mock = MockGitHub::start(mock_routes()).await;
+        let client = mock.client().installation_client(1).await.unwrap();
+
+        let changes = reconcile(&client, "octofer", "app", &LabelSyncOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 4);
+
+        let calls = mock
+            .requests()
+            .into_iter()
+            .map(|r| (r.method.to_string(), r.path, r.body))
+            .collect::<Vec<_>>();
+        let paths = calls
+            .iter()
+            .map(|(method, path, _)| format!("{} {}", method, path))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "GET /repos/octofer/app/contents/.github/labels.yml",
+                "GET /repos/octofer/app/labels",
+                "PATCH /repos/octofer/app/labels/Bug",
+                "PATCH /repos/octofer/app/labels/feature",
+                "POST /repos/octofer/app/labels",
+                "DELETE /repos/octofer/app/labels/wontfix",
+            ]
+        );
+        assert_eq!(calls[2].2["new_name"], "bug");
+        assert_eq!(calls[3].2["new_name"], "enhancement");
+        assert_eq!(calls[3].2["color"], "a2eeef");
+        assert_eq!(calls[4].2["name"], "good first issue");
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_dry_run_does_not_mutate() {
+        let mock = MockGitHub::start(mock_routes()).await;
+        let client = mock.client().installation_client(1).await.unwrap();
+        let options = LabelSyncOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let changes = reconcile(&client, "octofer", "app", &options)
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 4);
+        assert!(mock.requests().iter().all(|r| r.method == "GET"));
+    }
+}
@@ -0,0 +1,13 @@
+//! Ready-made plugins built on top of the event handlers
+//!
+//! Plugins bundle configuration parsing, handler registration and the GitHub
+//! API calls for a common automation task. Each plugin exposes a `register`
+//! function taking the [`Octofer`](crate::Octofer) app, and the underlying
+//! operations as plain functions so they can also be run outside of a webhook
+//! handler.
+
+pub mod auto_assign;
+pub mod label_sync;
+pub mod onboarding;
+pub mod repo_policy;
+pub mod spam_guard;
@@ -0,0 +1,502 @@
+//! Onboarding issue for new installations
+//!
+//! The onboarding plugin opens an issue explaining how to configure the app
+//! when it is installed, in the repositories picked by an
+//! [`OnboardingTarget`] among those the installation can access:
+//!
+//! - on `installation.created`, the repositories are listed with
+//!   `GET /installation/repositories`;
+//! - on `installation_repositories.added`, they are the added ones, so an
+//!   installation created without access to the target gets its issue once
+//!   access is granted.
+//!
+//! The body is a [template](crate::templates) rendered with:
+//!
+//! - `account`: login of the account the app is installed on;
+//! - `installation_id`: ID of the installation;
+//! - `repository.name` and `repository.full_name`: the issue's repository.
+//!
+//! Each account is onboarded once: opened issues are recorded in the app's
+//! [store](crate::storage), in the [`STORE_NAMESPACE`] namespace, for
+//! [`OnboardingConfig::reinstall_window`]. Uninstalling and reinstalling the
+//! app within the window does not open another issue. With
+//! [`OnboardingTarget::AllRepos`], each repository is onboarded once.
+//! Archived repositories and repositories without issues are skipped.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::plugins::onboarding::{self, OnboardingConfig, OnboardingTarget};
+//! use octofer::Octofer;
+//!
+//! # async fn example(mut app: Octofer) {
+//! onboarding::register(
+//!     &mut app,
+//!     OnboardingConfig::new(
+//!         "Welcome to Octofer",
+//!         "Thanks for installing the app on {{ account }}! Add a `.github/octofer.yml` to configure it.",
+//!         OnboardingTarget::NamedRepo(".github".to_string()),
+//!     ),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use octocrab::models::issues::Issue;
+use octocrab::models::webhook_events::payload::{
+    InstallationRepositoriesWebhookEventAction, InstallationWebhookEventAction,
+};
+use octocrab::models::webhook_events::{EventInstallation, WebhookEventPayload};
+use octocrab::models::{InstallationRepositories, Repository};
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::core::HandlerRegistration;
+use crate::helpers::path_segment;
+use crate::storage::Namespace;
+use crate::templates::render_source;
+use crate::{Context, Octofer};
+
+/// Namespace of the opened onboarding issues in the app's
+/// [`Store`](crate::storage::Store)
+pub const STORE_NAMESPACE: &str = "onboarding";
+
+/// Default of [`OnboardingConfig::reinstall_window`], 30 days
+pub const DEFAULT_REINSTALL_WINDOW: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Repositories listed per page of `GET /installation/repositories`
+const REPOSITORIES_PER_PAGE: usize = 100;
+
+/// Repositories of an installation receiving the onboarding issue
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OnboardingTarget {
+    /// The first accessible repository, in the order GitHub lists them
+    #[default]
+    FirstRepo,
+    /// The repository with this name, or full name (`owner/name`)
+    NamedRepo(String),
+    /// Every accessible repository
+    AllRepos,
+}
+
+/// Configuration of the onboarding plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnboardingConfig {
+    /// Title of the issue
+    pub title: String,
+    /// Template of the body of the issue, see the [module
+    /// documentation](self) for its data
+    pub body_template: String,
+    /// Repositories receiving the issue
+    pub target: OnboardingTarget,
+    /// How long onboarded accounts (or repositories, for
+    /// [`OnboardingTarget::AllRepos`]) are remembered
+    pub reinstall_window: Duration,
+}
+
+impl OnboardingConfig {
+    /// Open an issue titled `title` with the rendered `body_template` in the
+    /// `target` repositories
+    pub fn new(
+        title: impl Into<String>,
+        body_template: impl Into<String>,
+        target: OnboardingTarget,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            body_template: body_template.into(),
+            target,
+            ..Self::default()
+        }
+    }
+
+    /// Remember onboarded accounts for `window`
+    pub fn with_reinstall_window(mut self, window: Duration) -> Self {
+        self.reinstall_window = window;
+        self
+    }
+}
+
+impl Default for OnboardingConfig {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            body_template: String::new(),
+            target: OnboardingTarget::default(),
+            reinstall_window: DEFAULT_REINSTALL_WINDOW,
+        }
+    }
+}
+
+/// An onboarding issue opened by the plugin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OnboardingIssue {
+    /// Full name (`owner/name`) of the issue's repository
+    pub repository: String,
+    /// Number of the issue
+    pub number: u64,
+}
+
+/// Open the onboarding issues of the context's `installation.created` or
+/// `installation_repositories.added` event
+///
+/// Returns the opened issues, none for other events and already onboarded
+/// accounts or repositories.
+///
+/// # Errors
+///
+/// Returns an error if the repositories cannot be listed, the body cannot be
+/// rendered, or an issue cannot be opened or recorded in `store`.
+pub async fn onboard(
+    context: &Context,
+    config: &OnboardingConfig,
+    store: &Namespace,
+) -> Result<Vec<OnboardingIssue>> {
+    let Some(event) = context.event() else {
+        return Ok(Vec::new());
+    };
+    let candidates = match &event.specific {
+        WebhookEventPayload::Installation(payload)
+            if payload.action == InstallationWebhookEventAction::Created =>
+        {
+            let client = context.require_installation_client().await?;
+            accessible_repositories(&client).await?
+        }
+        WebhookEventPayload::InstallationRepositories(payload)
+            if payload.action == InstallationRepositoriesWebhookEventAction::Added =>
+        {
+            context.affected_repositories()?
+        }
+        _ => return Ok(Vec::new()),
+    };
+    let Some(EventInstallation::Full(installation)) = &event.installation else {
+        return Err(anyhow!("Event has no installation account"));
+    };
+    let account = &installation.account;
+
+    let mut opened = Vec::new();
+    for repository in select_targets(&config.target, &candidates) {
+        let key = match config.target {
+            OnboardingTarget::AllRepos => format!("{}/{}", account.id, repository.id),
+            _ => account.id.to_string(),
+        };
+        if let Some(issue) = store.get_json::<OnboardingIssue>(&key).await? {
+            debug!(
+                "{} was already onboarded in {}#{}",
+                account.login, issue.repository, issue.number
+            );
+            continue;
+        }
+
+        let full_name = repository
+            .full_name
+            .clone()
+            .unwrap_or_else(|| format!("{}/{}", account.login, repository.name));
+        let data = json!({
+            "account": account.login,
+            "installation_id": installation.id.0,
+            "repository": { "name": repository.name, "full_name": full_name },
+        });
+        let body = render_source("onboarding", &config.body_template, &data)?;
+
+        let client = context.require_installation_client().await?;
+        let route = format!(
+            "/repos/{}/{}/issues",
+            path_segment(&account.login),
+            path_segment(&repository.name)
+        );
+        let issue: Issue = client
+            .post(route, Some(&json!({ "title": config.title, "body": body })))
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to open the onboarding issue in {}: {}",
+                    full_name,
+                    e
+                )
+            })?;
+        info!("Opened onboarding issue {}#{}", full_name, issue.number);
+
+        let issue = OnboardingIssue {
+            repository: full_name,
+            number: issue.number,
+        };
+        store
+            .put_json(&key, &issue, Some(config.reinstall_window))
+            .await?;
+        opened.push(issue);
+    }
+    Ok(opened)
+}
+
+/// Pick the repositories receiving the onboarding issue among `candidates`
+///
+/// Archived repositories and repositories with issues disabled are skipped.
+pub fn select_targets<'a>(
+    target: &OnboardingTarget,
+    candidates: &'a [Repository],
+) -> Vec<&'a Repository> {
+    let mut eligible = candidates.iter().filter(|repository| {
+        repository.archived != Some(true) && repository.has_issues != Some(false)
+    });
+    match target {
+        OnboardingTarget::FirstRepo => eligible.next().into_iter().collect(),
+        OnboardingTarget::NamedRepo(name) => eligible
+            .find(|repository| {
+                repository.name.eq_ignore_ascii_case(name)
+                    || repository
+                        .full_name
+                        .as_ref()
+                        .is_some_and(|full_name| full_name.eq_ignore_ascii_case(name))
+            })
+            .into_iter()
+            .collect(),
+        OnboardingTarget::AllRepos => eligible.collect(),
+    }
+}
+
+/// Register handlers opening the onboarding issues of new installations
+///
+/// Returns the registrations of the `installation` and
+/// `installation_repositories` handlers.
+pub async fn register(
+    app: &mut Octofer,
+    config: OnboardingConfig,
+) -> (HandlerRegistration, HandlerRegistration) {
+    let onboarding = Arc::new((config, Namespace::new(app.store(), STORE_NAMESPACE)));
+    let installations = app
+        .on_installation(
+            |context: Context, onboarding: Arc<(OnboardingConfig, Namespace)>| async move {
+                let (config, store) = &*onboarding;
+                onboard(&context, config, store).await?;
+                Ok(())
+            },
+            onboarding.clone(),
+        )
+        .await;
+    let repositories = app
+        .on_installation_repositories(
+            |context: Context, onboarding: Arc<(OnboardingConfig, Namespace)>| async move {
+                let (config, store) = &*onboarding;
+                onboard(&context, config, store).await?;
+                Ok(())
+            },
+            onboarding,
+        )
+        .await;
+    (installations, repositories)
+}
+
+/// List the repositories the installation of `client` can access
+async fn accessible_repositories(client: &Octocrab) -> Result<Vec<Repository>> {
+    let mut repositories = Vec::new();
+    for page in 1.. {
+        let listed: InstallationRepositories = client
+            .get(
+                "/installation/repositories",
+                Some(&json!({ "per_page": REPOSITORIES_PER_PAGE, "page": page })),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to list the installation's repositories: {}", e))?;
+        let count = listed.repositories.len();
+        repositories.extend(listed.repositories);
+        if count < REPOSITORIES_PER_PAGE {
+            break;
+        }
+    }
+    Ok(repositories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemoryStore;
+    use crate::testing::{repository, user, MockGitHub};
+    use axum::http::Method;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::Value;
+
+    fn repositories(names: &[&str]) -> Vec<Value> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let mut repository = repository("octofer-org", name);
+                repository["id"] = json!(i + 1);
+                repository["archived"] = json!(name.starts_with("old-"));
+                repository
+            })
+            .collect()
+    }
+
+    fn installation_payload(action: &str, installation_id: u64) -> Value {
+        json!({
+            "action": action,
+            "installation": {
+                "id": installation_id,
+                "account": user("octofer-org"),
+                "permissions": {},
+                "events": [],
+            },
+            "repositories": [],
+            "requester": null,
+            "sender": user("octocat"),
+        })
+    }
+
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/installation/repositories",
+                get(|| async {
+                    let repositories = repositories(&["old-site", "api", "docs"]);
+                    Json(json!({ "total_count": repositories.len(), "repositories": repositories }))
+                }),
+            )
+            .route(
+                "/repos/octofer-org/{repo}/issues",
+                post(|| async { Json(crate::testing::issue("octofer-org", "api", 7)) }),
+            )
+    }
+
+    fn issue_requests(mock: &MockGitHub) -> Vec<(String, Value)> {
+        mock.requests()
+            .into_iter()
+            .filter(|r| r.method == Method::POST)
+            .map(|r| (r.path, r.body))
+            .collect()
+    }
+
+    #[test]
+    fn test_select_targets() {
+        let candidates: Vec<Repository> = repositories(&["old-site", "api", "docs"])
+            .into_iter()
+            .map(|repository| serde_json::from_value(repository).unwrap())
+            .collect();
+        let names = |target| {
+            select_targets(&target, &candidates)
+                .into_iter()
+                .map(|repository| repository.name.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(OnboardingTarget::FirstRepo), ["api"]);
+        assert_eq!(names(OnboardingTarget::AllRepos), ["api", "docs"]);
+        assert_eq!(
+            names(OnboardingTarget::NamedRepo("octofer-org/docs".to_string())),
+            ["docs"]
+        );
+        assert_eq!(
+            names(OnboardingTarget::NamedRepo("DOCS".to_string())),
+            ["docs"]
+        );
+        assert!(names(OnboardingTarget::NamedRepo("old-site".to_string())).is_empty());
+        assert!(select_targets(&OnboardingTarget::FirstRepo, &[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_onboarding_issue_is_opened_once() {
+        let mock = MockGitHub::start(routes()).await;
+        let store = Namespace::new(Arc::new(InMemoryStore::new()), STORE_NAMESPACE);
+        let config = OnboardingConfig::new(
+            "Welcome",
+            "Hello {{ account }}, this is {{ repository.full_name }}",
+            OnboardingTarget::FirstRepo,
+        );
+
+        let created = mock.context("installation", installation_payload("created", 1));
+        let opened = onboard(&created, &config, &store).await.unwrap();
+        assert_eq!(
+            opened,
+            [OnboardingIssue {
+                repository: "octofer-org/api".to_string(),
+                number: 7,
+            }]
+        );
+        assert_eq!(
+            issue_requests(&mock),
+            [(
+                "/repos/octofer-org/api/issues".to_string(),
+                json!({
+                    "title": "Welcome",
+                    "body": "Hello octofer-org, this is octofer-org/api",
+                })
+            )]
+        );
+
+        // Redeliveries and other actions open nothing
+        assert!(onboard(&created, &config, &store).await.unwrap().is_empty());
+        let deleted = mock.context("installation", installation_payload("deleted", 1));
+        assert!(onboard(&deleted, &config, &store).await.unwrap().is_empty());
+        assert_eq!(issue_requests(&mock).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reinstall_within_window_is_not_onboarded_again() {
+        let mock = MockGitHub::start(routes()).await;
+        let store = Namespace::new(Arc::new(InMemoryStore::new()), STORE_NAMESPACE);
+        let config = OnboardingConfig::new("Welcome", "Hi", OnboardingTarget::AllRepos)
+            .with_reinstall_window(Duration::from_millis(200));
+
+        let install = mock.context("installation", installation_payload("created", 1));
+        assert_eq!(onboard(&install, &config, &store).await.unwrap().len(), 2);
+
+        // A reinstall gets a new installation ID, but the same account
+        let reinstall = mock.context("installation", installation_payload("created", 2));
+        assert!(onboard(&reinstall, &config, &store)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(issue_requests(&mock).len(), 2);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(onboard(&reinstall, &config, &store).await.unwrap().len(), 2);
+        assert_eq!(issue_requests(&mock).len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_named_repository_added_later() {
+        let mock = MockGitHub::start(routes()).await;
+        let mut app = Octofer::new_default();
+        register(
+            &mut app,
+            OnboardingConfig::new(
+                "Welcome",
+                "Hi",
+                OnboardingTarget::NamedRepo(".github".to_string()),
+            ),
+        )
+        .await;
+        let dispatcher = app.dispatcher();
+
+        // The target is not accessible yet
+        let created = mock.context("installation", installation_payload("created", 1));
+        dispatcher.dispatch(created).await.unwrap();
+        assert!(issue_requests(&mock).is_empty());
+
+        let mut added = installation_payload("added", 1);
+        added["repositories_added"] = json!([{
+            "id": 9,
+            "node_id": "R_9",
+            "name": ".github",
+            "full_name": "octofer-org/.github",
+            "private": false,
+        }]);
+        added["repositories_removed"] = json!([]);
+        added["repository_selection"] = json!("selected");
+        let added = mock.context("installation_repositories", added);
+        dispatcher.dispatch(added.clone()).await.unwrap();
+        dispatcher.dispatch(added).await.unwrap();
+
+        let requests = issue_requests(&mock);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].0, "/repos/octofer-org/.github/issues");
+    }
+}
@@ -0,0 +1,396 @@
+//! Organization-wide repository policy
+//!
+//! The repo-policy plugin enforces repository settings and default branch
+//! protection across an organization, as described by a YAML file in the
+//! organization's `.github` repository (`repo-policy.yml` by default):
+//!
+//! ```yaml
+//! settings:
+//!   allow_merge_commit: false
+//!   allow_squash_merge: true
+//!   delete_branch_on_merge: true
+//!   topics: [managed]
+//! protection:
+//!   required_status_checks: [ci]
+//!   required_approving_review_count: 1
+//! ```
+//!
+//! `settings` are [`RepoSettings`] and `protection` a [`ProtectionSpec`],
+//! applied to the repository's default branch unless `protection.branch` is
+//! set. Enforcing applies every setting it can, see
+//! [`Context::apply_repo_settings`], and fails listing the settings that
+//! could not be applied.
+//!
+//! [`register`] enforces the policy on each repository when it is created.
+//! Octofer has no scheduler, so to also enforce it nightly, e.g. to undo
+//! manual changes, call [`reconcile_all`] from your own timer, e.g. with
+//! [`tokio::time::interval`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::plugins::repo_policy::{self, RepoPolicyOptions};
+//! use octofer::Octofer;
+//!
+//! # async fn example(mut app: Octofer) {
+//! repo_policy::register(&mut app, RepoPolicyOptions::default()).await;
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _, Result};
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::core::HandlerRegistration;
+use crate::github::batch::IterationSummary;
+use crate::github::GitHubClient;
+use crate::helpers::branch_protection::{ProtectionSpec, RepoAdmin};
+use crate::helpers::repo_settings::{RepoSettings, RepoSettingsOutcome, SettingFailure};
+use crate::helpers::repository::RepoChange;
+use crate::helpers::{fetch_optional_file, path_segment};
+use crate::{Context, Octofer};
+
+/// Default repository holding the policy file, in the organization
+pub const DEFAULT_POLICY_REPOSITORY: &str = ".github";
+
+/// Default location of the policy file in its repository
+pub const DEFAULT_POLICY_PATH: &str = "repo-policy.yml";
+
+/// Options for the repo-policy plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoPolicyOptions {
+    /// Repository of the organization holding the policy file
+    pub repository: String,
+    /// Path of the policy file in the repository
+    pub path: String,
+}
+
+impl Default for RepoPolicyOptions {
+    fn default() -> Self {
+        Self {
+            repository: DEFAULT_POLICY_REPOSITORY.to_string(),
+            path: DEFAULT_POLICY_PATH.to_string(),
+        }
+    }
+}
+
+/// Contents of the policy file
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoPolicy {
+    /// Settings of every repository
+    pub settings: RepoSettings,
+    /// Protection of a branch of every repository
+    pub protection: Option<BranchPolicy>,
+}
+
+/// Protection of a branch required by the policy
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BranchPolicy {
+    /// Branch to protect; the repository's default branch if unset
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Protection of the branch
+    #[serde(flatten)]
+    pub spec: ProtectionSpec,
+}
+
+impl RepoPolicy {
+    /// Parse a policy file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the YAML is invalid.
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        serde_yaml::from_str(yaml).context("Invalid repository policy")
+    }
+}
+
+/// Get the policy of the account `owner`, or `None` if it has no policy file
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be fetched or parsed.
+pub async fn load_policy(
+    client: &Octocrab,
+    owner: &str,
+    options: &RepoPolicyOptions,
+) -> Result<Option<RepoPolicy>> {
+    let repo_route = format!(
+        "/repos/{}/{}",
+        path_segment(owner),
+        path_segment(&options.repository)
+    );
+    fetch_optional_file(client, &repo_route, &options.path)
+        .await?
+        .map(|yaml| RepoPolicy::from_yaml(&yaml))
+        .transpose()
+}
+
+/// Enforce the policy of the repository's owner on the context's repository
+///
+/// Returns `None` if the owner has no policy file.
+///
+/// # Errors
+///
+/// Returns an error if the event has no repository, the policy cannot be
+/// loaded, or a setting or the branch protection could not be applied.
+pub async fn enforce(
+    context: &Context,
+    options: &RepoPolicyOptions,
+) -> Result<Option<RepoSettingsOutcome>> {
+    let admin = context.repo_admin().await?;
+    let default_branch = context
+        .event()
+        .as_ref()
+        .and_then(|event| event.repository.as_ref())
+        .and_then(|repository| repository.default_branch.clone());
+    enforce_with(&admin, default_branch, options).await
+}
+
+/// Enforce the policy of each repository's owner on every repository
+/// accessible to the app, at most `concurrency` at a time
+///
+/// Repositories whose owner has no policy file are left as they are. Failures
+/// are collected per repository in the summary.
+///
+/// # Errors
+///
+/// Returns an error only if the installations cannot be listed.
+pub async fn reconcile_all(
+    github: &GitHubClient,
+    concurrency: usize,
+    options: &RepoPolicyOptions,
+) -> Result<IterationSummary> {
+    github
+        .for_each_repository(concurrency, |handle| async move {
+            let admin = RepoAdmin {
+                client: handle.client,
+                installation_id: handle.installation.id.0,
+                granted: None,
+                owner: handle.owner,
+                repo: handle.repository.name,
+            };
+            enforce_with(&admin, handle.repository.default_branch, options)
+                .await
+                .map(drop)
+        })
+        .await
+}
+
+/// Register a repository handler enforcing the policy on created repositories
+pub async fn register(app: &mut Octofer, options: RepoPolicyOptions) -> HandlerRegistration {
+    app.on_repository(
+        |context: Context, options: Arc<RepoPolicyOptions>| async move {
+            if context.repo_change() != Some(RepoChange::Created) {
+                return Ok(());
+            }
+            enforce(&context, &options).await?;
+            Ok(())
+        },
+        Arc::new(options),
+    )
+    .await
+}
+
+async fn enforce_with(
+    admin: &RepoAdmin,
+    default_branch: Option<String>,
+    options: &RepoPolicyOptions,
+) -> Result<Option<RepoSettingsOutcome>> {
+    let Some(policy) = load_policy(&admin.client, &admin.owner, options).await? else {
+        debug!("{} has no repository policy", admin.owner);
+        return Ok(None);
+    };
+
+    let mut outcome = admin.apply_settings(&policy.settings).await;
+
+    if let Some(protection) = &policy.protection {
+        let result = async {
+            let branch = match (&protection.branch, &policy.settings.default_branch) {
+                (Some(branch), _) => branch.clone(),
+                (None, Some(branch)) if outcome.applied.iter().any(|s| s == "default_branch") => {
+                    branch.clone()
+                }
+                _ => match default_branch {
+                    Some(branch) => branch,
+                    None => current_default_branch(admin).await?,
+                },
+            };
+            admin.apply_protection(&branch, &protection.spec).await?;
+            Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => outcome.applied.push("branch_protection".to_string()),
+            Err(error) => outcome.failures.push(SettingFailure {
+                setting: "branch_protection".to_string(),
+                error,
+            }),
+        }
+    }
+
+    let repository = format!("{}/{}", admin.owner, admin.repo);
+    if !outcome.is_success() {
+        let failures: Vec<_> = outcome.failures.iter().map(|f| f.to_string()).collect();
+        return Err(anyhow!(
+            "Failed to enforce the repository policy on {}: {}",
+            repository,
+            failures.join("; ")
+        ));
+    }
+    info!("Enforced the repository policy on {}", repository);
+    Ok(Some(outcome))
+}
+
+async fn current_default_branch(admin: &RepoAdmin) -> Result<String> {
+    let repository: Value = admin
+        .client
+        .get(admin.route(), None::<&()>)
+        .await
+        .map_err(|e| anyhow!("Failed to get {}: {}", admin.route(), e))?;
+    repository["default_branch"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Repository {} has no default branch", admin.route()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, MockGitHub};
+    use axum::http::StatusCode;
+    use axum::routing::{get, patch};
+    use axum::{Json, Router};
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde_json::json;
+
+    const POLICY: &str = r#"
+settings:
+  allow_merge_commit: false
+  delete_branch_on_merge: true
+  topics: [managed]
+protection:
+  required_status_checks: [ci]
+"#;
+
+    fn created_payload() -> Value {
+        let mut repository = repository("octofer", "app");
+        repository["default_branch"] = json!("main");
+        json!({
+            "action": "created",
+            "repository": repository,
+            "sender": user("octocat"),
+        })
+    }
+
+    fn routes(policy: &'static str) -> Router {
+        Router::new()
+            .route(
+                "/repos/octofer/.github/contents/repo-policy.yml",
+                get(move || async move {
+                    Json(json!({ "encoding": "base64", "content": STANDARD.encode(policy) }))
+                }),
+            )
+            .route(
+                "/repos/octofer/app",
+                patch(|| async { Json(repository("octofer", "app")) }),
+            )
+            .route(
+                "/repos/octofer/app/topics",
+                get(|| async { Json(json!({ "names": [] })) })
+                    .put(|Json(body): Json<Value>| async move { Json(body) }),
+            )
+            .route(
+                "/repos/octofer/app/branches/main/protection",
+                get(|| async {
+                    let message = json!({ "message": "Branch not protected" });
+                    (StatusCode::NOT_FOUND, Json(message))
+                })
+                .put(|Json(body): Json<Value>| async move { Json(body) }),
+            )
+    }
+
+    #[test]
+    fn test_policy_from_yaml() {
+        let policy = RepoPolicy::from_yaml(POLICY).unwrap();
+        assert_eq!(policy.settings.allow_merge_commit, Some(false));
+        assert_eq!(policy.settings.topics, Some(vec!["managed".to_string()]));
+        let protection = policy.protection.unwrap();
+        assert_eq!(protection.branch, None);
+        assert_eq!(protection.spec.required_status_checks, ["ci"]);
+
+        assert!(RepoPolicy::from_yaml("settings: [").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_enforce_on_created_repository() {
+        let mock = MockGitHub::start(routes(POLICY)).await;
+        let context = mock.context("repository", created_payload());
+
+        let outcome = enforce(&context, &RepoPolicyOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            outcome.applied,
+            [
+                "allow_merge_commit",
+                "delete_branch_on_merge",
+                "topics",
+                "branch_protection"
+            ]
+        );
+
+        let requests = mock.requests();
+        let calls: Vec<_> = requests
+            .iter()
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "GET /repos/octofer/.github/contents/repo-policy.yml",
+                "PATCH /repos/octofer/app",
+                "GET /repos/octofer/app/topics",
+                "PUT /repos/octofer/app/topics",
+                "GET /repos/octofer/app/branches/main/protection",
+                "PUT /repos/octofer/app/branches/main/protection",
+            ]
+        );
+        assert_eq!(
+            requests[1].body,
+            json!({ "allow_merge_commit": false, "delete_branch_on_merge": true })
+        );
+        assert_eq!(requests[3].body, json!({ "names": ["managed"] }));
+        assert_eq!(
+            requests[5].body["required_status_checks"]["checks"],
+            json!([{ "context": "ci" }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enforce_without_policy() {
+        let routes = Router::new().route(
+            "/repos/octofer/.github/contents/repo-policy.yml",
+            get(|| async {
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "message": "Not Found" })),
+                )
+            }),
+        );
+        let mock = MockGitHub::start(routes).await;
+        let context = mock.context("repository", created_payload());
+
+        let outcome = enforce(&context, &RepoPolicyOptions::default())
+            .await
+            .unwrap();
+        assert!(outcome.is_none());
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
@@ -0,0 +1,769 @@
+//! Spam heuristics for new issues, pull requests and comments
+//!
+//! The spam guard scores every opened issue and pull request, and every
+//! created issue comment, by adding up the weights of the [`SpamSignal`]s it
+//! raises:
+//!
+//! - a body that is too short once its links are removed;
+//! - too many links, or links to a blocked domain (or one of its
+//!   subdomains);
+//! - an author account younger than the configured minimum, looked up with
+//!   the installation client and cached for [`ACCOUNT_CACHE_TTL`], weighted
+//!   more if it is also the author's first interaction with the repository.
+//!
+//! When the score reaches the threshold, the configured [`SpamAction`]s are
+//! taken: labelling, closing with a comment and locking issues and pull
+//! requests, or locking the conversation of comments. The closing comment is
+//! the built-in `spam_closed` [template](crate::templates), which
+//! repositories can override. Without actions the
+//! guard only reports. Owners, members and collaborators of the repository
+//! are never acted on: their author association exempts them outright, and
+//! the sender's [permission](crate::Context::sender_permission) is checked
+//! before acting on anyone else.
+//!
+//! Every [`SpamVerdict`] is added to the delivery's report as the
+//! [`NOTE_KEY`] note, to tune the heuristics from real deliveries.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::plugins::spam_guard::{self, SpamAction, SpamGuardConfig};
+//! use octofer::Octofer;
+//!
+//! # async fn example(mut app: Octofer) {
+//! let config = SpamGuardConfig {
+//!     blocked_domains: vec!["casino.example".to_string()],
+//!     actions: vec![SpamAction::Label, SpamAction::Close],
+//!     ..SpamGuardConfig::default()
+//! };
+//! spam_guard::register(&mut app, config).await;
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context as _, Result};
+use chrono::{DateTime, Utc};
+use octocrab::models::webhook_events::payload::{
+    IssueCommentWebhookEventAction, IssuesWebhookEventAction, PullRequestWebhookEventAction,
+};
+use octocrab::models::webhook_events::WebhookEventPayload;
+use octocrab::models::AuthorAssociation;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::core::HandlerRegistration;
+use crate::helpers::issue_state::{close_issue_at, lock_issue_at, CloseReason, LockReason};
+use crate::helpers::path_segment;
+use crate::helpers::permissions::Permission;
+use crate::{Context, Octofer};
+
+/// Key of the report note holding the [`SpamVerdict`]
+pub const NOTE_KEY: &str = "spam_guard";
+
+/// How long looked up account creation dates are reused
+pub const ACCOUNT_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Author associations exempt from the guard
+const EXEMPT_ASSOCIATIONS: &[&str] = &["OWNER", "MEMBER", "COLLABORATOR"];
+
+/// Author associations of users who never contributed to the repository
+const FIRST_INTERACTIONS: &[&str] = &["NONE", "FIRST_TIMER", "FIRST_TIME_CONTRIBUTOR"];
+
+/// What the guard does with spam
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamAction {
+    /// Apply the configured label to the issue or pull request
+    Label,
+    /// Comment with the configured message and close the issue or pull
+    /// request
+    Close,
+    /// Lock the conversation of the issue or pull request, or the one the
+    /// comment was posted in
+    Lock,
+}
+
+impl fmt::Display for SpamAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Label => "label",
+            Self::Close => "close",
+            Self::Lock => "lock",
+        })
+    }
+}
+
+/// Heuristic raised by a body or its author
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamSignal {
+    /// The body has fewer characters than the minimum once its links are
+    /// removed
+    ShortBody,
+    /// The body has more links than the maximum
+    ManyLinks,
+    /// The body links to a blocked domain
+    BlockedDomain(String),
+    /// The author's account is younger than the minimum
+    NewAccount,
+    /// The author's account is new and it is their first interaction with
+    /// the repository
+    NewAccountFirstInteraction,
+}
+
+impl SpamSignal {
+    /// Weight of the signal in the score
+    pub fn weight(&self) -> u32 {
+        match self {
+            Self::ShortBody => 1,
+            Self::ManyLinks => 2,
+            Self::BlockedDomain(_) => 3,
+            Self::NewAccount => 1,
+            Self::NewAccountFirstInteraction => 2,
+        }
+    }
+}
+
+/// Decision of the guard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamDecision {
+    /// The author is an owner, member or collaborator of the repository
+    Exempt,
+    /// The score is below the threshold
+    Legitimate,
+    /// The score reached the threshold
+    Spam,
+}
+
+/// Outcome of checking an issue, pull request or comment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpamVerdict {
+    /// Sum of the weights of the signals
+    pub score: u32,
+    /// Signals raised
+    pub signals: Vec<SpamSignal>,
+    /// What the guard decided
+    pub decision: SpamDecision,
+    /// Actions taken, empty unless the decision is [`SpamDecision::Spam`]
+    pub actions: Vec<SpamAction>,
+}
+
+/// Configuration of the spam guard
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpamGuardConfig {
+    /// Fewest characters of a body, links excluded; 0 disables the signal
+    pub min_body_length: usize,
+    /// Most links in a body
+    pub max_links: usize,
+    /// Domains whose links are spam, subdomains included
+    pub blocked_domains: Vec<String>,
+    /// Minimum age of the author's account, in days; 0 disables the lookup
+    pub min_account_age_days: u64,
+    /// Score from which a body is spam
+    pub threshold: u32,
+    /// Actions taken with spam, none to only report
+    pub actions: Vec<SpamAction>,
+    /// Label applied by [`SpamAction::Label`]
+    pub label: String,
+    /// [Template](crate::templates) of the comment posted by
+    /// [`SpamAction::Close`], rendered with the author (`user`) and `number`
+    /// of the issue or pull request
+    pub close_template: String,
+}
+
+impl SpamGuardConfig {
+    /// Score with the default heuristics and only report the verdicts
+    pub fn report_only() -> Self {
+        Self {
+            actions: Vec::new(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for SpamGuardConfig {
+    fn default() -> Self {
+        Self {
+            min_body_length: 20,
+            max_links: 3,
+            blocked_domains: Vec::new(),
+            min_account_age_days: 7,
+            threshold: 3,
+            actions: vec![SpamAction::Label],
+            label: "spam".to_string(),
+            close_template: "spam_closed".to_string(),
+        }
+    }
+}
+
+/// What the guard checks: the body and author of an opened issue or pull
+/// request, or of a created comment
+struct Subject<'a> {
+    body: &'a str,
+    author: &'a str,
+    association: String,
+    /// Number of the issue or pull request
+    number: u64,
+    comment: bool,
+}
+
+impl<'a> Subject<'a> {
+    fn of(context: &'a Context) -> Option<Self> {
+        let association = |association: &AuthorAssociation| {
+            serde_json::to_value(association)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_default()
+        };
+        match &context.event().as_ref()?.specific {
+            WebhookEventPayload::Issues(payload)
+                if payload.action == IssuesWebhookEventAction::Opened =>
+            {
+                Some(Self {
+                    body: payload.issue.body.as_deref().unwrap_or_default(),
+                    author: &payload.issue.user.login,
+                    association: payload.issue.author_association.clone(),
+                    number: payload.issue.number,
+                    comment: false,
+                })
+            }
+            WebhookEventPayload::PullRequest(payload)
+                if payload.action == PullRequestWebhookEventAction::Opened =>
+            {
+                let pull_request = &payload.pull_request;
+                Some(Self {
+                    body: pull_request.body.as_deref().unwrap_or_default(),
+                    author: &pull_request.user.as_ref()?.login,
+                    association: pull_request
+                        .author_association
+                        .as_ref()
+                        .map(association)
+                        .unwrap_or_default(),
+                    number: payload.number,
+                    comment: false,
+                })
+            }
+            WebhookEventPayload::IssueComment(payload)
+                if payload.action == IssueCommentWebhookEventAction::Created =>
+            {
+                Some(Self {
+                    body: payload.comment.body.as_deref().unwrap_or_default(),
+                    author: &payload.comment.user.login,
+                    association: association(&payload.comment.author_association),
+                    number: payload.issue.number,
+                    comment: true,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Scores new issues, pull requests and comments and acts on spam
+///
+/// [`register`] runs a `SpamGuard` for `issues.opened`,
+/// `pull_request.opened` and `issue_comment.created` events; use it directly
+/// to check from other handlers.
+#[derive(Debug)]
+pub struct SpamGuard {
+    config: SpamGuardConfig,
+    /// Creation dates of accounts by login, with when they were looked up
+    accounts: Mutex<HashMap<String, (DateTime<Utc>, Instant)>>,
+}
+
+impl SpamGuard {
+    /// Create a guard with `config`
+    pub fn new(config: SpamGuardConfig) -> Self {
+        Self {
+            config,
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check the issue, pull request or comment of the context's event,
+    /// acting on it if it is spam
+    ///
+    /// The verdict is added to the delivery's report as the [`NOTE_KEY`]
+    /// note. Returns `None` if the event did not open an issue or pull
+    /// request nor create a comment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no installation client is available, or a request
+    /// fails.
+    pub async fn check(&self, context: &Context) -> Result<Option<SpamVerdict>> {
+        let Some(subject) = Subject::of(context) else {
+            return Ok(None);
+        };
+        let verdict = self.judge(context, &subject).await?;
+        context.note(NOTE_KEY, &verdict);
+        Ok(Some(verdict))
+    }
+
+    async fn judge(&self, context: &Context, subject: &Subject<'_>) -> Result<SpamVerdict> {
+        let exempt = |signals| SpamVerdict {
+            score: 0,
+            signals,
+            decision: SpamDecision::Exempt,
+            actions: Vec::new(),
+        };
+        if EXEMPT_ASSOCIATIONS.contains(&subject.association.as_str()) {
+            return Ok(exempt(Vec::new()));
+        }
+
+        let mut signals = self.content_signals(subject.body);
+        if self.config.min_account_age_days > 0 {
+            let created_at = self.account_created_at(context, subject.author).await?;
+            let min_age = chrono::Duration::days(self.config.min_account_age_days as i64);
+            if Utc::now() - created_at < min_age {
+                signals.push(SpamSignal::NewAccount);
+                if FIRST_INTERACTIONS.contains(&subject.association.as_str()) {
+                    signals.push(SpamSignal::NewAccountFirstInteraction);
+                }
+            }
+        }
+        let score = signals.iter().map(SpamSignal::weight).sum();
+        if score < self.config.threshold {
+            return Ok(SpamVerdict {
+                score,
+                signals,
+                decision: SpamDecision::Legitimate,
+                actions: Vec::new(),
+            });
+        }
+
+        // Collaborators with read access look like anyone else on public
+        // repositories, hence the association check above
+        if context.sender_permission().await? > Permission::Read {
+            return Ok(exempt(signals));
+        }
+
+        let actions: Vec<_> = self
+            .config
+            .actions
+            .iter()
+            .copied()
+            .filter(|action| !subject.comment || *action == SpamAction::Lock)
+            .collect();
+        self.act(context, subject, &actions).await?;
+        Ok(SpamVerdict {
+            score,
+            signals,
+            decision: SpamDecision::Spam,
+            actions,
+        })
+    }
+
+    /// Signals raised by `body` alone
+    pub fn content_signals(&self, body: &str) -> Vec<SpamSignal> {
+        let links = links(body);
+        let mut signals = Vec::new();
+
+        let text: usize = body
+            .split_whitespace()
+            .filter(|word| !links.iter().any(|(link, _)| word.contains(link)))
+            .map(|word| word.chars().count())
+            .sum();
+        if text < self.config.min_body_length {
+            signals.push(SpamSignal::ShortBody);
+        }
+        if links.len() > self.config.max_links {
+            signals.push(SpamSignal::ManyLinks);
+        }
+        for (_, host) in &links {
+            let blocked = self.config.blocked_domains.iter().find(|domain| {
+                let domain = domain.to_lowercase();
+                *host == domain || host.ends_with(&format!(".{}", domain))
+            });
+            if let Some(domain) = blocked {
+                let signal = SpamSignal::BlockedDomain(domain.clone());
+                if !signals.contains(&signal) {
+                    signals.push(signal);
+                }
+            }
+        }
+        signals
+    }
+
+    /// Creation date of the account `login`
+    async fn account_created_at(&self, context: &Context, login: &str) -> Result<DateTime<Utc>> {
+        let key = login.to_lowercase();
+        if let Some((created_at, fetched_at)) = self.accounts.lock().unwrap().get(&key) {
+            if fetched_at.elapsed() < ACCOUNT_CACHE_TTL {
+                return Ok(*created_at);
+            }
+        }
+
+        let client = context.require_installation_client().await?;
+        let account: Value = client
+            .get(format!("/users/{}", path_segment(login)), None::<&()>)
+            .await
+            .map_err(|e| anyhow!("Failed to get account of {}: {}", login, e))?;
+        let created_at = account["created_at"]
+            .as_str()
+            .and_then(|created_at| created_at.parse().ok())
+            .with_context(|| format!("Account of {} has no creation date", login))?;
+        self.accounts
+            .lock()
+            .unwrap()
+            .insert(key, (created_at, Instant::now()));
+        Ok(created_at)
+    }
+
+    async fn act(
+        &self,
+        context: &Context,
+        subject: &Subject<'_>,
+        actions: &[SpamAction],
+    ) -> Result<()> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+        let (owner, repo) = context.require_repository()?;
+        let client = context.require_installation_client().await?;
+        let issue_route = format!(
+            "/repos/{}/{}/issues/{}",
+            path_segment(&owner),
+            path_segment(&repo),
+            subject.number
+        );
+        info!(
+            "{}/{}#{}: taking spam actions {:?}",
+            owner, repo, subject.number, actions
+        );
+
+        for action in actions {
+            match action {
+                SpamAction::Label => {
+                    let _: Value = client
+                        .post(
+                            format!("{}/labels", issue_route),
+                            Some(&json!({ "labels": [self.config.label] })),
+                        )
+                        .await
+                        .map_err(|e| anyhow!("Failed to label #{}: {}", subject.number, e))?;
+                }
+                SpamAction::Close => {
+                    let body = context
+                        .render(
+                            &self.config.close_template,
+                            &json!({ "user": subject.author, "number": subject.number }),
+                        )
+                        .await?;
+                    let _: Value = client
+                        .post(
+                            format!("{}/comments", issue_route),
+                            Some(&json!({ "body": body })),
+                        )
+                        .await
+                        .map_err(|e| anyhow!("Failed to comment on #{}: {}", subject.number, e))?;
+                    close_issue_at(
+                        &client,
+                        &issue_route,
+                        subject.number,
+                        CloseReason::NotPlanned,
+                    )
+                    .await?;
+                }
+                SpamAction::Lock => {
+                    // Already locked conversations need no action
+                    lock_issue_at(&client, &issue_route, subject.number, LockReason::Spam).await?;
+                }
+            }
+            debug!("Took spam action {} on #{}", action, subject.number);
+        }
+        Ok(())
+    }
+}
+
+/// Links of `body`, with their lowercase host
+fn links(body: &str) -> Vec<(&str, String)> {
+    body.match_indices("http")
+        .filter_map(|(start, _)| {
+            let candidate = &body[start..];
+            let end = candidate
+                .find(|c: char| c.is_whitespace() || matches!(c, ')' | ']' | '>' | '"' | '\''))
+                .unwrap_or(candidate.len());
+            let link = &candidate[..end];
+            if !link.starts_with("http://") && !link.starts_with("https://") {
+                return None;
+            }
+            let host = url::Url::parse(link).ok()?.host_str()?.to_lowercase();
+            Some((link, host))
+        })
+        .collect()
+}
+
+/// Register handlers checking opened issues and pull requests and created
+/// comments
+///
+/// Returns the registrations of the `issues`, `pull_request` and
+/// `issue_comment` handlers.
+pub async fn register(
+    app: &mut Octofer,
+    config: SpamGuardConfig,
+) -> (
+    HandlerRegistration,
+    HandlerRegistration,
+    HandlerRegistration,
+) {
+    let guard = Arc::new(SpamGuard::new(config));
+    let check = |context: Context, guard: Arc<SpamGuard>| async move {
+        guard.check(&context).await?;
+        Ok(())
+    };
+    let issues = app.on_issue(check, guard.clone()).await;
+    let pull_requests = app.on_pull_request(check, guard.clone()).await;
+    let comments = app.on_issue_comment(check, guard).await;
+    (issues, pull_requests, comments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::Dispatcher;
+    use crate::templates::Templates;
+    use crate::testing::{
+        comment, issue, issues_payload, webhook_event, MockGitHub, TEST_INSTALLATION_ID,
+    };
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::routing::{get, post, put};
+    use axum::{Json, Router};
+
+    /// Routes of an app where `newcomer` and `spammer` signed up yesterday and `maintainer`
+    /// has write access to `octofer/app`
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/users/{login}",
+                get(|Path(login): Path<String>| async move {
+                    let created_at = match login.as_str() {
+                        "newcomer" | "spammer" => Utc::now() - chrono::Duration::days(1),
+                        _ => "2015-01-01T00:00:00Z".parse().unwrap(),
+                    };
+                    Json(json!({ "login": login, "created_at": created_at }))
+                }),
+            )
+            .route(
+                "/repos/octofer/app/collaborators/{login}/permission",
+                get(|Path(login): Path<String>| async move {
+                    let permission = if login == "maintainer" {
+                        "write"
+                    } else {
+                        "read"
+                    };
+                    Json(json!({ "permission": permission }))
+                }),
+            )
+            .route(
+                "/repos/octofer/app/issues/{number}",
+                axum::routing::patch(|Path(number): Path<u64>| async move {
+                    Json(issue("octofer", "app", number))
+                }),
+            )
+            .route(
+                "/repos/octofer/app/issues/{number}/labels",
+                post(|| async { Json(json!([])) }),
+            )
+            .route(
+                "/repos/octofer/app/issues/{number}/comments",
+                post(|| async { Json(comment(1, "octofer-test[bot]", "closed")) }),
+            )
+            .route(
+                "/repos/octofer/app/issues/{number}/lock",
+                put(|| async { StatusCode::NO_CONTENT }),
+            )
+    }
+
+    fn opened_by(login: &str, association: &str, body: &str) -> Value {
+        let mut payload = issues_payload("opened", 1);
+        payload["issue"]["user"]["login"] = json!(login);
+        payload["issue"]["author_association"] = json!(association);
+        payload["issue"]["body"] = json!(body);
+        payload["sender"]["login"] = json!(login);
+        payload
+    }
+
+    fn calls(mock: &MockGitHub) -> Vec<String> {
+        mock.requests()
+            .into_iter()
+            .map(|request| format!("{} {}", request.method, request.path))
+            .collect()
+    }
+
+    #[test]
+    fn test_content_signals() {
+        let guard = SpamGuard::new(SpamGuardConfig {
+            blocked_domains: vec!["Casino.example".to_string()],
+            ..SpamGuardConfig::default()
+        });
+
+        assert_eq!(
+            guard.content_signals(
+                "The app crashes on startup when the config file is missing, see the log below."
+            ),
+            []
+        );
+        assert_eq!(
+            guard.content_signals("Win now https://win.casino.example/x"),
+            [
+                SpamSignal::ShortBody,
+                SpamSignal::BlockedDomain("Casino.example".to_string())
+            ]
+        );
+        let many = "Great resources for everyone to read: [a](https://a.example) \
+                    [b](https://b.example) [c](https://c.example) <http://d.example/>";
+        assert_eq!(guard.content_signals(many), [SpamSignal::ManyLinks]);
+    }
+
+    #[tokio::test]
+    async fn test_spam_is_labelled_closed_and_reported() {
+        let mock = MockGitHub::start(routes()).await;
+        let guard = Arc::new(SpamGuard::new(SpamGuardConfig {
+            blocked_domains: vec!["casino.example".to_string()],
+            actions: vec![SpamAction::Label, SpamAction::Close],
+            ..SpamGuardConfig::default()
+        }));
+        let dispatcher = Dispatcher::new(Some(Arc::new(mock.client())));
+        dispatcher.set_templates(
+            Templates::builtin().with("spam_closed", "Closed #{{ number }} of @{{ user }} as spam"),
+        );
+        dispatcher
+            .on(
+                "issues",
+                |context: Context, guard: Arc<SpamGuard>| async move {
+                    guard.check(&context).await?;
+                    Ok(())
+                },
+                guard,
+            )
+            .await;
+
+        let mut payload = opened_by("spammer", "FIRST_TIMER", "Best odds https://casino.example");
+        payload["installation"] = json!({ "id": TEST_INSTALLATION_ID, "node_id": "I_1" });
+        let report = dispatcher
+            .dispatch(dispatcher.context(webhook_event("issues", payload)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            calls(&mock),
+            [
+                "GET /users/spammer",
+                "GET /repos/octofer/app/collaborators/spammer/permission",
+                "POST /repos/octofer/app/issues/1/labels",
+                "GET /repos/octofer/app/contents/.github/octofer/templates/spam_closed.md",
+                "POST /repos/octofer/app/issues/1/comments",
+                "PATCH /repos/octofer/app/issues/1",
+            ]
+        );
+        let requests = mock.requests();
+        assert_eq!(requests[2].body["labels"], json!(["spam"]));
+        assert_eq!(requests[4].body["body"], "Closed #1 of @spammer as spam");
+        assert_eq!(requests[5].body["state"], "closed");
+
+        assert_eq!(report.notes.len(), 1);
+        let note = &report.notes[0];
+        assert_eq!(note.key, NOTE_KEY);
+        let verdict: SpamVerdict = serde_json::from_value(note.value.clone()).unwrap();
+        assert_eq!(verdict.decision, SpamDecision::Spam);
+        assert_eq!(verdict.score, 7);
+        assert_eq!(verdict.actions, [SpamAction::Label, SpamAction::Close]);
+    }
+
+    #[tokio::test]
+    async fn test_legitimate_and_exempt_authors_are_left_alone() {
+        let mock = MockGitHub::start(routes()).await;
+        let guard = SpamGuard::new(SpamGuardConfig {
+            actions: vec![SpamAction::Label, SpamAction::Close, SpamAction::Lock],
+            ..SpamGuardConfig::default()
+        });
+
+        // An established account with a detailed report
+        let context = mock.context(
+            "issues",
+            opened_by(
+                "veteran",
+                "CONTRIBUTOR",
+                "Parsing fails for configs with trailing commas, see https://docs.example/config",
+            ),
+        );
+        let verdict = guard.check(&context).await.unwrap().unwrap();
+        assert_eq!(verdict.decision, SpamDecision::Legitimate);
+        assert_eq!(verdict.score, 0);
+
+        // Members are exempt without any lookup
+        let context = mock.context("issues", opened_by("teammate", "MEMBER", "wip"));
+        let verdict = guard.check(&context).await.unwrap().unwrap();
+        assert_eq!(verdict.decision, SpamDecision::Exempt);
+
+        // Spammy, but the sender has write access
+        let context = mock.context(
+            "issues",
+            opened_by(
+                "maintainer",
+                "CONTRIBUTOR",
+                "https://a.example https://b.example https://c.example https://d.example",
+            ),
+        );
+        let verdict = guard.check(&context).await.unwrap().unwrap();
+        assert_eq!(verdict.decision, SpamDecision::Exempt);
+        assert_eq!(
+            verdict.signals,
+            [SpamSignal::ShortBody, SpamSignal::ManyLinks]
+        );
+
+        assert_eq!(
+            calls(&mock),
+            [
+                "GET /users/veteran",
+                "GET /users/maintainer",
+                "GET /repos/octofer/app/collaborators/maintainer/permission",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spam_comment_locks_the_conversation() {
+        let mock = MockGitHub::start(routes()).await;
+        let guard = SpamGuard::new(SpamGuardConfig {
+            threshold: 2,
+            actions: vec![SpamAction::Label, SpamAction::Lock],
+            ..SpamGuardConfig::default()
+        });
+        let payload = json!({
+            "action": "created",
+            "issue": issue("octofer", "app", 3),
+            "comment": comment(7, "newcomer", "Thanks! Also see https://promo.example"),
+            "repository": crate::testing::repository("octofer", "app"),
+            "sender": crate::testing::user("newcomer"),
+        });
+        let context = mock.context("issue_comment", payload);
+
+        let verdict = guard.check(&context).await.unwrap().unwrap();
+        assert_eq!(verdict.decision, SpamDecision::Spam);
+        // Labels are for issues and pull requests, not comments
+        assert_eq!(verdict.actions, [SpamAction::Lock]);
+        let lock = mock.requests().pop().unwrap();
+        assert_eq!(lock.path, "/repos/octofer/app/issues/3/lock");
+        assert_eq!(lock.body["lock_reason"], "spam");
+
+        // The account age is cached
+        guard.check(&context).await.unwrap();
+        assert_eq!(
+            calls(&mock)
+                .iter()
+                .filter(|call| *call == "GET /users/newcomer")
+                .count(),
+            1
+        );
+    }
+}
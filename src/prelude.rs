@@ -0,0 +1,131 @@
+//! Types needed by most handlers, importable at once
+//!
+//! ```rust,no_run
+//! use octofer::prelude::*;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut app = Octofer::new(Config::from_env()?).await?;
+//! app.on_issue(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         println!("{} event", context.kind());
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! app.start().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The prelude only grows with types most handlers use; anything else is
+//! imported from its module.
+
+/// The app: registers handlers and runs the webhook server
+///
+/// ```rust,no_run
+/// use octofer::prelude::*;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let app = Octofer::new(Config::default()).await?;
+/// app.start().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub use crate::Octofer;
+
+/// Configuration of the app, usually loaded from the environment
+///
+/// ```rust
+/// use octofer::prelude::*;
+///
+/// let config = Config::default();
+/// assert_eq!(config.server.port, octofer::config::DEFAULT_PORT);
+/// ```
+pub use crate::config::Config;
+
+/// Event, clients and helpers handed to every handler
+///
+/// ```rust
+/// use octofer::prelude::*;
+///
+/// let context = Context::new(None, Some(42));
+/// assert_eq!(context.installation_id(), Some(42));
+/// ```
+pub use crate::core::Context;
+
+/// Options of a registered handler, returned by the `on_*` methods
+///
+/// ```rust,no_run
+/// use octofer::prelude::*;
+///
+/// # async fn example(mut app: Octofer) {
+/// let _registration: HandlerRegistration = app
+///     .on_push(|_context: Context, _extra: Arc<()>| async { Ok(()) }, Arc::new(()))
+///     .await
+///     .named("push-logger");
+/// # }
+/// ```
+pub use crate::core::HandlerRegistration;
+
+/// How a handler's failure affects its delivery
+///
+/// ```rust,no_run
+/// use octofer::prelude::*;
+///
+/// # async fn example(mut app: Octofer) {
+/// app.on_push(|_context: Context, _extra: Arc<()>| async { Ok(()) }, Arc::new(()))
+///     .await
+///     .error_policy(ErrorPolicy::Isolate);
+/// # }
+/// ```
+pub use crate::core::ErrorPolicy;
+
+/// Name of a webhook event, as returned by [`Context::kind`]
+///
+/// ```rust
+/// use octofer::prelude::*;
+///
+/// assert_eq!(EventKind::new("issues"), "issues");
+/// ```
+pub use crate::events::EventKind;
+
+/// Client of the GitHub App, as returned by [`Context::github`]
+///
+/// ```rust,no_run
+/// use octofer::prelude::*;
+///
+/// # async fn example(context: Context) -> anyhow::Result<()> {
+/// if let Some(client) = context.github() {
+///     let client: &GitHubClient = client;
+///     println!("{} installations", client.get_installations().await?.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub use crate::github::GitHubClient;
+
+/// Event-specific part of a webhook payload, to match on
+///
+/// ```rust
+/// use octofer::prelude::*;
+///
+/// fn action(context: &Context) -> Option<String> {
+///     match &context.event.as_ref()?.specific {
+///         WebhookEventPayload::Issues(payload) => Some(format!("{:?}", payload.action)),
+///         _ => None,
+///     }
+/// }
+/// assert_eq!(action(&Context::new(None, None)), None);
+/// ```
+pub use octocrab::models::webhook_events::WebhookEventPayload;
+
+/// Shared pointer wrapping the extra data of every handler
+///
+/// ```rust
+/// use octofer::prelude::*;
+///
+/// let extra: Arc<()> = Arc::new(());
+/// assert_eq!(Arc::strong_count(&extra), 1);
+/// ```
+pub use std::sync::Arc;
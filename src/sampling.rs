@@ -0,0 +1,168 @@
+//! Delivery sampling for high-volume events
+//!
+//! Handlers registered with
+//! [`sample_rate`](crate::core::HandlerRegistration::sample_rate) only run
+//! for a share of the deliveries of their event, e.g. analytics handlers
+//! that need a statistical sample of `push` events rather than every one.
+//! Operators can sample whole event types for every handler with the
+//! [`SampleRates`] of
+//! [`DispatchConfig::sample_rates`](crate::config::DispatchConfig::sample_rates),
+//! loaded from `OCTOFER_SAMPLE_RATES`:
+//!
+//! ```bash
+//! # Handle 10% of push and 50% of workflow_job deliveries
+//! export OCTOFER_SAMPLE_RATES="push=0.1,workflow_job=0.5"
+//! ```
+//!
+//! A handler with its own rate, for an event type with a rate, runs at the
+//! lower of the two.
+//!
+//! Whether a delivery is sampled is decided from a hash of its delivery ID,
+//! so a redelivery of the same delivery is decided the same way, and every
+//! handler of the same rate sees the same sample. Deliveries without an ID
+//! are never sampled out. Sampled out deliveries are still verified and
+//! parsed, so built-in tracking such as configuration cache eviction keeps
+//! working, and are acknowledged with `200 OK`; the skipped handlers are
+//! listed in the
+//! [`sampled_out`](crate::dispatch::DispatchReport::sampled_out) handlers of
+//! the delivery's report.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Share of the deliveries of each event type handled
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::sampling::SampleRates;
+///
+/// let rates = SampleRates::parse("push=0.1, workflow_job=0.5");
+/// assert_eq!(rates.rate("push"), Some(0.1));
+/// assert_eq!(rates.rate("issues"), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SampleRates {
+    /// Rates by event type
+    rates: BTreeMap<String, f64>,
+}
+
+impl SampleRates {
+    /// Create rates handling every delivery
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse rates from `event=rate` entries separated by `,`
+    ///
+    /// Rates are clamped to `0.0..=1.0`. Entries without a valid rate are
+    /// ignored.
+    pub fn parse(spec: &str) -> Self {
+        spec.split(',')
+            .filter_map(|entry| {
+                let (event, rate) = entry.split_once('=')?;
+                let rate = rate.trim().parse().ok()?;
+                Some((event.trim(), rate))
+            })
+            .filter(|(event, _)| !event.is_empty())
+            .fold(Self::new(), |rates, (event, rate)| rates.with(event, rate))
+    }
+
+    /// Handle a share `rate` of the deliveries of `event`, clamped to
+    /// `0.0..=1.0`
+    pub fn with(mut self, event: impl Into<String>, rate: f64) -> Self {
+        self.rates.insert(event.into(), clamp(rate));
+        self
+    }
+
+    /// Rate of `event`, if it is sampled
+    pub fn rate(&self, event: &str) -> Option<f64> {
+        self.rates.get(event).copied()
+    }
+
+    /// Whether no event type is sampled
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+}
+
+/// Clamp `rate` to `0.0..=1.0`, mapping `NaN` to `1.0`
+pub(crate) fn clamp(rate: f64) -> f64 {
+    if rate.is_nan() {
+        1.0
+    } else {
+        rate.clamp(0.0, 1.0)
+    }
+}
+
+/// Whether the delivery `delivery_id` is in a sample of a share `rate` of
+/// all deliveries
+///
+/// The decision only depends on the delivery ID and the rate. Deliveries
+/// without an ID are always in the sample.
+///
+/// # Examples
+///
+/// ```rust
+/// use octofer::sampling::is_sampled;
+///
+/// let id = Some("72d3162e-cc78-11e3-81ab-4c9367dc0958");
+/// assert!(is_sampled(id, 1.0));
+/// assert!(!is_sampled(id, 0.0));
+/// assert_eq!(is_sampled(id, 0.1), is_sampled(id, 0.1));
+/// ```
+pub fn is_sampled(delivery_id: Option<&str>, rate: f64) -> bool {
+    match delivery_id {
+        Some(id) => position(id) < rate,
+        None => true,
+    }
+}
+
+/// Position of a delivery in `0.0..1.0`, from a hash of its ID
+fn position(delivery_id: &str) -> f64 {
+    let digest = Sha256::digest(delivery_id.as_bytes());
+    let bits = u64::from_be_bytes(digest[..8].try_into().expect("digest has 8 bytes"));
+    // The top 53 bits, which an f64 represents exactly
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let rates = SampleRates::parse(" push = 0.1 ,workflow_job=2,issues=x,=0.5,check_run=-1");
+        assert_eq!(rates.rate("push"), Some(0.1));
+        assert_eq!(rates.rate("workflow_job"), Some(1.0));
+        assert_eq!(rates.rate("check_run"), Some(0.0));
+        assert_eq!(rates.rate("issues"), None);
+        assert!(SampleRates::parse("").is_empty());
+    }
+
+    #[test]
+    fn test_sampling_is_deterministic_and_near_the_rate() {
+        let ids: Vec<_> = (0..10_000)
+            .map(|i| format!("00000000-0000-0000-0000-{:012}", i))
+            .collect();
+
+        for id in &ids[..100] {
+            let decision = is_sampled(Some(id), 0.1);
+            assert!((0..10).all(|_| is_sampled(Some(id), 0.1) == decision));
+            // Deliveries sampled at a rate are sampled at any higher rate
+            assert!(!decision || is_sampled(Some(id), 0.5));
+        }
+
+        for rate in [0.01, 0.1, 0.5, 0.9] {
+            let sampled = ids.iter().filter(|id| is_sampled(Some(id), rate)).count();
+            let share = sampled as f64 / ids.len() as f64;
+            assert!((share - rate).abs() < 0.02, "{} sampled at {}", share, rate);
+        }
+        assert!(ids.iter().all(|id| is_sampled(Some(id), 1.0)));
+        assert!(!ids.iter().any(|id| is_sampled(Some(id), 0.0)));
+        assert!(is_sampled(None, 0.0));
+    }
+}
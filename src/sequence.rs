@@ -0,0 +1,305 @@
+//! Detection of webhook deliveries arriving out of order
+//!
+//! GitHub does not guarantee that deliveries arrive in the order the events
+//! happened, so a handler may see an issue's `closed` event before its
+//! `opened` event. With [`DispatchConfig::sequence_tracking`] enabled, the
+//! dispatcher remembers the latest `updated_at` timestamp seen for every issue
+//! and pull request, and flags deliveries carrying an older timestamp through
+//! [`Context::out_of_order_hint`].
+//!
+//! Deliveries are not delayed or reordered; handlers decide how to react to a
+//! hint, e.g. by re-fetching the current state from the API instead of
+//! trusting the payload. Timestamps have a resolution of one second, so events
+//! happening within the same second are never flagged.
+//!
+//! The tracker keeps at most [`DispatchConfig::sequence_cache_size`] subjects;
+//! the subjects tracked the longest are evicted first.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_issue(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if let Some(hint) = context.out_of_order_hint() {
+//!             println!(
+//!                 "Stale delivery: updated at {}, but already saw {}",
+//!                 hint.timestamp, hint.latest_seen
+//!             );
+//!             return Ok(());
+//!         }
+//!         // ... handle the event
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use octocrab::models::webhook_events::WebhookEventPayload;
+
+use crate::config::DEFAULT_SEQUENCE_CACHE_SIZE;
+use crate::Context;
+
+#[cfg(doc)]
+use crate::config::DispatchConfig;
+
+/// Hint that a delivery is older than one already seen for the same subject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfOrderHint {
+    /// `updated_at` timestamp of the delivery's issue or pull request
+    pub timestamp: DateTime<Utc>,
+    /// Latest timestamp seen so far for the same issue or pull request
+    pub latest_seen: DateTime<Utc>,
+}
+
+/// Issue or pull request whose deliveries are tracked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SequenceKey {
+    /// Installation the delivery belongs to
+    pub installation_id: Option<u64>,
+    /// ID of the repository
+    pub repository_id: u64,
+    /// Issue or pull request number
+    pub number: u64,
+}
+
+impl SequenceKey {
+    /// Get the tracked subject of an event and its `updated_at` timestamp
+    ///
+    /// Returns `None` for events that are not about an issue or pull request.
+    pub fn of(context: &Context) -> Option<(Self, DateTime<Utc>)> {
+        let event = context.event().as_ref()?;
+        let repository_id = event.repository.as_ref()?.id.0;
+
+        let (number, timestamp) = match &event.specific {
+            WebhookEventPayload::Issues(payload) => {
+                (payload.issue.number, payload.issue.updated_at)
+            }
+            WebhookEventPayload::IssueComment(payload) => {
+                (payload.issue.number, payload.issue.updated_at)
+            }
+            WebhookEventPayload::PullRequest(payload) => {
+                (payload.number, payload.pull_request.updated_at?)
+            }
+            WebhookEventPayload::PullRequestReview(payload) => (
+                payload.pull_request.number,
+                payload.pull_request.updated_at?,
+            ),
+            WebhookEventPayload::PullRequestReviewComment(payload) => (
+                payload.pull_request.number,
+                payload.pull_request.updated_at?,
+            ),
+            _ => return None,
+        };
+
+        let key = Self {
+            installation_id: context.installation_id(),
+            repository_id,
+            number,
+        };
+        Some((key, timestamp))
+    }
+}
+
+/// Bounded cache of the latest timestamp seen per issue or pull request
+#[derive(Debug)]
+pub struct SequenceTracker {
+    inner: Mutex<TrackerState>,
+}
+
+#[derive(Debug)]
+struct TrackerState {
+    capacity: usize,
+    latest: HashMap<SequenceKey, DateTime<Utc>>,
+    /// Tracked keys, in the order they were first seen
+    order: VecDeque<SequenceKey>,
+}
+
+impl SequenceTracker {
+    /// Create a tracker remembering at most `capacity` subjects
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(TrackerState {
+                capacity,
+                latest: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Change the number of subjects remembered, evicting the oldest if needed
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.inner.lock().unwrap();
+        state.capacity = capacity;
+        state.evict();
+    }
+
+    /// Number of subjects currently tracked
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().latest.len()
+    }
+
+    /// Whether no subject is tracked
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record a delivery for `key` with the given timestamp
+    ///
+    /// Returns a hint if a later timestamp was already seen for `key`. The
+    /// latest timestamp is only ever moved forward.
+    pub fn observe(&self, key: SequenceKey, timestamp: DateTime<Utc>) -> Option<OutOfOrderHint> {
+        let mut state = self.inner.lock().unwrap();
+
+        match state.latest.get_mut(&key) {
+            Some(latest) if *latest > timestamp => {
+                return Some(OutOfOrderHint {
+                    timestamp,
+                    latest_seen: *latest,
+                })
+            }
+            Some(latest) => *latest = timestamp,
+            None => {
+                state.latest.insert(key, timestamp);
+                state.order.push_back(key);
+                state.evict();
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for SequenceTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEQUENCE_CACHE_SIZE)
+    }
+}
+
+impl TrackerState {
+    fn evict(&mut self) {
+        while self.latest.len() > self.capacity {
+            let Some(key) = self.order.pop_front() else {
+                break;
+            };
+            self.latest.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DispatchConfig;
+    use crate::dispatch::Dispatcher;
+    use crate::testing::{issues_payload, webhook_event};
+    use crate::SerdeToString;
+    use octocrab::models::webhook_events::WebhookEventType;
+    use std::sync::Arc;
+
+    fn issue_event(action: &str, number: u64, updated_at: &str) -> Context {
+        let mut payload = issues_payload(action, number);
+        payload["issue"]["updated_at"] = updated_at.into();
+        Context::new(Some(webhook_event("issues", payload)), Some(1))
+    }
+
+    fn timestamp(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_shuffled_deliveries_are_flagged() {
+        let dispatcher = Dispatcher::new(None);
+        dispatcher
+            .set_config(DispatchConfig {
+                sequence_tracking: true,
+                ..Default::default()
+            })
+            .await;
+
+        let hints = Arc::new(Mutex::new(Vec::new()));
+        dispatcher
+            .on(
+                WebhookEventType::Issues.to_string(),
+                |context: Context, hints: Arc<Mutex<Vec<_>>>| async move {
+                    hints.lock().unwrap().push(context.out_of_order_hint());
+                    Ok(())
+                },
+                hints.clone(),
+            )
+            .await;
+
+        // Delivered: closed, opened, then an event of another issue, edited
+        let deliveries = [
+            issue_event("closed", 1, "2024-01-01T00:00:03Z"),
+            issue_event("opened", 1, "2024-01-01T00:00:01Z"),
+            issue_event("opened", 2, "2024-01-01T00:00:02Z"),
+            issue_event("edited", 1, "2024-01-01T00:00:02Z"),
+        ];
+        let mut flagged = Vec::new();
+        for context in deliveries {
+            flagged.push(dispatcher.dispatch(context).await.unwrap().out_of_order);
+        }
+
+        assert_eq!(flagged, [false, true, false, true]);
+        let hints = hints.lock().unwrap();
+        assert_eq!(hints[0], None);
+        assert_eq!(
+            hints[1],
+            Some(OutOfOrderHint {
+                timestamp: timestamp("2024-01-01T00:00:01Z"),
+                latest_seen: timestamp("2024-01-01T00:00:03Z"),
+            })
+        );
+        assert_eq!(hints[2], None);
+        assert_eq!(
+            hints[3].map(|hint| hint.latest_seen),
+            Some(timestamp("2024-01-01T00:00:03Z"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tracking_is_disabled_by_default() {
+        let dispatcher = Dispatcher::new(None);
+        for context in [
+            issue_event("closed", 1, "2024-01-01T00:00:03Z"),
+            issue_event("opened", 1, "2024-01-01T00:00:01Z"),
+        ] {
+            assert!(!dispatcher.dispatch(context).await.unwrap().out_of_order);
+        }
+    }
+
+    #[test]
+    fn test_tracker_is_bounded() {
+        let tracker = SequenceTracker::new(2);
+        let key = |number| SequenceKey {
+            installation_id: Some(1),
+            repository_id: 1,
+            number,
+        };
+        let later = timestamp("2024-01-01T00:00:02Z");
+        let earlier = timestamp("2024-01-01T00:00:01Z");
+
+        assert_eq!(tracker.observe(key(1), later), None);
+        assert_eq!(tracker.observe(key(2), later), None);
+        assert!(tracker.observe(key(1), earlier).is_some());
+
+        // A third subject evicts the first one, which is forgotten
+        assert_eq!(tracker.observe(key(3), later), None);
+        assert_eq!(tracker.len(), 2);
+        assert_eq!(tracker.observe(key(1), earlier), None);
+        assert!(tracker.observe(key(3), earlier).is_some());
+
+        tracker.set_capacity(1);
+        assert_eq!(tracker.len(), 1);
+    }
+}
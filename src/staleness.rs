@@ -0,0 +1,203 @@
+//! Staleness gate for deliveries of old events
+//!
+//! Deliveries can arrive long after their event happened: GitHub retries
+//! deliveries after an outage, and [redelivering](crate::github::redelivery)
+//! failed deliveries replays events of hours ago. Handlers acting on such
+//! deliveries act on stale state, e.g. by commenting on an issue closed since.
+//! With [`DispatchConfig::max_event_age_secs`] set, loaded from
+//! `OCTOFER_MAX_EVENT_AGE`, deliveries of events older than the limit skip
+//! their handlers:
+//!
+//! ```bash
+//! # Skip handlers for events that happened more than 6 hours ago
+//! export OCTOFER_MAX_EVENT_AGE=6h
+//! ```
+//!
+//! GitHub does not send the time of a delivery in its headers, so the time of
+//! an event is read from its payload by [`event_timestamp`]: the time its
+//! subject, e.g. the issue or the workflow run, was last updated. Events
+//! without a timestamp, such as `installation` events, are never stale.
+//!
+//! Stale deliveries are still verified and parsed, so built-in tracking such
+//! as configuration cache eviction keeps working, and are acknowledged with
+//! `200 OK` and reported as [`stale`](crate::dispatch::DispatchReport::stale).
+//! Handlers registered with
+//! [`allow_stale`](crate::core::HandlerRegistration::allow_stale), e.g.
+//! audit or archival handlers, run regardless.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::Context;
+
+#[cfg(doc)]
+use crate::config::DispatchConfig;
+
+/// Subjects of the events, by payload field, with their timestamp fields
+/// from the most to the least specific
+///
+/// Comments and reviews come first, as their events also carry the issue or
+/// pull request they belong to.
+const SUBJECTS: &[(&str, &[&str])] = &[
+    ("comment", &["updated_at", "created_at"]),
+    ("review", &["submitted_at"]),
+    ("workflow_run", &["updated_at", "created_at"]),
+    (
+        "workflow_job",
+        &["completed_at", "started_at", "created_at"],
+    ),
+    ("check_run", &["completed_at", "started_at"]),
+    ("check_suite", &["updated_at", "created_at"]),
+    ("deployment_status", &["updated_at", "created_at"]),
+    ("deployment", &["updated_at", "created_at"]),
+    ("release", &["published_at", "created_at"]),
+    ("discussion", &["updated_at", "created_at"]),
+    ("pull_request", &["updated_at", "created_at"]),
+    ("issue", &["updated_at", "created_at"]),
+];
+
+/// Get the time the event of a delivery happened
+///
+/// Push events are timed by their head commit, or by the time of the push to
+/// the repository when they have none, e.g. for deleted branches. Other
+/// events are timed by the last update of their subject: the comment of
+/// comment events, the review of review events, the run of `workflow_run`
+/// events, the issue of `issues` events, and so on.
+///
+/// Returns `None` for events without a known subject or timestamp.
+pub fn event_timestamp(context: &Context) -> Option<DateTime<Utc>> {
+    let event = context.event().as_ref()?;
+    // Payloads serialize tagged by their event type
+    let tagged = serde_json::to_value(&event.specific).ok()?;
+    let payload = tagged.as_object()?.values().next()?;
+
+    if let Some(commit) = payload.get("head_commit") {
+        let pushed_at = event.repository.as_ref().and_then(|repo| repo.pushed_at);
+        return timestamp(&commit["timestamp"]).or(pushed_at);
+    }
+    subject_timestamp(payload)
+}
+
+/// Get how long ago the event of a delivery happened, see
+/// [`event_timestamp`]
+///
+/// Returns `None` for events without a timestamp, and zero for events
+/// timestamped after `now`.
+pub fn event_age(context: &Context, now: DateTime<Utc>) -> Option<Duration> {
+    let timestamp = event_timestamp(context)?;
+    Some((now - timestamp).to_std().unwrap_or_default())
+}
+
+/// Get the time the subject of an event was last updated from its payload
+fn subject_timestamp(payload: &Value) -> Option<DateTime<Utc>> {
+    let (subject, fields) = SUBJECTS
+        .iter()
+        .find(|(subject, _)| payload[subject].is_object())?;
+    fields
+        .iter()
+        .find_map(|field| timestamp(&payload[subject][field]))
+}
+
+/// Parse an RFC 3339 timestamp
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let timestamp = DateTime::parse_from_rfc3339(value.as_str()?).ok()?;
+    Some(timestamp.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{comment, issues_payload, push_payload, repository, user, webhook_event};
+    use serde_json::json;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn context(event: &str, payload: Value) -> Context {
+        Context::new(Some(webhook_event(event, payload)), None)
+    }
+
+    #[test]
+    fn test_issue_events_are_timed_by_their_subject() {
+        let mut payload = issues_payload("labeled", 1);
+        payload["issue"]["updated_at"] = json!("2024-03-01T12:00:00Z");
+        assert_eq!(
+            event_timestamp(&context("issues", payload.clone())),
+            Some(at("2024-03-01T12:00:00Z"))
+        );
+
+        payload["comment"] = comment(7, "octocat", "Hello");
+        payload["comment"]["updated_at"] = json!("2024-03-02T08:30:00Z");
+        payload["action"] = json!("created");
+        assert_eq!(
+            event_timestamp(&context("issue_comment", payload)),
+            Some(at("2024-03-02T08:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_push_events_are_timed_by_their_head_commit() {
+        let after = "a".repeat(40);
+        let mut payload = push_payload(&after);
+        payload["head_commit"] = json!({
+            "id": after,
+            "tree_id": "b".repeat(40),
+            "distinct": true,
+            "message": "Fix the build",
+            "timestamp": "2024-03-01T14:00:00+02:00",
+            "url": format!("https://github.com/octofer/app/commit/{after}"),
+            "author": { "name": "octocat", "email": "octocat@github.com" },
+            "committer": { "name": "octocat", "email": "octocat@github.com" },
+            "added": [],
+            "removed": [],
+            "modified": ["src/lib.rs"],
+        });
+        assert_eq!(
+            event_timestamp(&context("push", payload)),
+            Some(at("2024-03-01T12:00:00Z"))
+        );
+
+        // Branch deletions have no head commit
+        let mut payload = push_payload(&"0".repeat(40));
+        payload["deleted"] = json!(true);
+        payload["repository"]["pushed_at"] = json!("2024-03-01T12:00:00Z");
+        assert_eq!(
+            event_timestamp(&context("push", payload)),
+            Some(at("2024-03-01T12:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_workflow_run_events_are_timed_by_their_run() {
+        let payload = json!({
+            "action": "completed",
+            "workflow": { "id": 3, "name": "CI" },
+            "workflow_run": {
+                "id": 30,
+                "name": "CI",
+                "status": "completed",
+                "conclusion": "failure",
+                "created_at": "2024-03-01T11:00:00Z",
+                "updated_at": "2024-03-01T11:20:00Z",
+            },
+            "repository": repository("octofer", "app"),
+            "sender": user("octocat"),
+        });
+        let run = context("workflow_run", payload);
+        assert_eq!(event_timestamp(&run), Some(at("2024-03-01T11:20:00Z")));
+        assert_eq!(
+            event_age(&run, at("2024-03-01T17:20:00Z")),
+            Some(Duration::from_secs(6 * 3600))
+        );
+        assert_eq!(
+            event_age(&run, at("2024-03-01T11:00:00Z")),
+            Some(Duration::ZERO)
+        );
+
+        let ping = json!({ "zen": "Design for failure.", "hook_id": 1 });
+        assert_eq!(event_timestamp(&context("ping", ping)), None);
+    }
+}
@@ -0,0 +1,186 @@
+//! Shared state of the app's handlers
+//!
+//! [`Octofer::state`] stores a value of the app, one per type, which every
+//! handler gets with [`Context::state`], whichever module or plugin
+//! registered it. Unlike the `extra` value of each registration, state does
+//! not need to be threaded through every registration. Values are shared,
+//! not copied, so handlers that change state use interior mutability: an
+//! atomic, a [`std::sync::Mutex`] held across no `.await`, or the
+//! [`tokio::sync::Mutex`] stored by [`Octofer::state_mut`] and returned by
+//! [`Context::state_mut`].
+//!
+//! State lives in memory and is lost on restart; state that must survive it
+//! belongs in the app's [`Store`](crate::storage::Store).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Context, Octofer};
+//! use std::collections::HashMap;
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::sync::Arc;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.state(AtomicU64::new(0));
+//! app.state_mut(HashMap::<String, u64>::new());
+//!
+//! app.on_issue(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         context.state::<AtomicU64>()?.fetch_add(1, Ordering::Relaxed);
+//!
+//!         let payload = context.payload();
+//!         let sender = payload["sender"]["login"].as_str().unwrap_or("unknown");
+//!         let openers = context.state_mut::<HashMap<String, u64>>()?;
+//!         *openers.lock().await.entry(sender.to_string()).or_default() += 1;
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # }
+//! ```
+//!
+//! [`Octofer::state`]: crate::Octofer::state
+//! [`Octofer::state_mut`]: crate::Octofer::state_mut
+
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::Mutex;
+
+use crate::Context;
+
+/// Values shared by the app's handlers, one per type
+///
+/// Clones share the same values.
+#[derive(Clone, Default)]
+pub struct AppState {
+    values: Arc<RwLock<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>,
+}
+
+impl AppState {
+    /// Create empty state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `value`, replacing the value of its type if there is one, and
+    /// return it
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) -> Arc<T> {
+        let value = Arc::new(value);
+        self.values
+            .write()
+            .expect("state lock poisoned")
+            .insert(TypeId::of::<T>(), value.clone());
+        value
+    }
+
+    /// Get the value of type `T`, if stored
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let value = self
+            .values
+            .read()
+            .expect("state lock poisoned")
+            .get(&TypeId::of::<T>())?
+            .clone();
+        value.downcast().ok()
+    }
+
+    /// Whether a value of type `T` is stored
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values
+            .read()
+            .expect("state lock poisoned")
+            .contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl fmt::Debug for AppState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values = self.values.read().expect("state lock poisoned").len();
+        f.debug_struct("AppState").field("values", &values).finish()
+    }
+}
+
+/// Error of [`Context::state`] when no value of the requested type is stored
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("No state of type {type_name} was stored with Octofer::state")]
+pub struct MissingState {
+    /// Name of the requested type
+    pub type_name: &'static str,
+}
+
+impl Context {
+    /// Get the app's state of type `T`, see the [`state`](crate::state)
+    /// module
+    ///
+    /// The value is shared by all handlers; change it through interior
+    /// mutability, e.g. with [`Context::state_mut`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingState`] if no value of type `T` was stored with
+    /// [`Octofer::state`](crate::Octofer::state).
+    pub fn state<T: Send + Sync + 'static>(&self) -> Result<Arc<T>> {
+        self.app_state.get::<T>().ok_or_else(|| {
+            anyhow!(MissingState {
+                type_name: type_name::<T>(),
+            })
+        })
+    }
+
+    /// Get the app's mutable state of type `T`, stored with
+    /// [`Octofer::state_mut`](crate::Octofer::state_mut)
+    ///
+    /// The lock is shared by all handlers; hold it only as long as needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MissingState`] if no value of type `T` was stored with
+    /// [`Octofer::state_mut`](crate::Octofer::state_mut).
+    pub fn state_mut<T: Send + 'static>(&self) -> Result<Arc<Mutex<T>>> {
+        self.state::<Mutex<T>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_values_are_stored_by_type() {
+        let state = AppState::new();
+        state.insert(AtomicU64::new(1));
+        state.insert("app".to_string());
+        assert!(state.contains::<String>());
+        assert!(!state.contains::<u64>());
+        assert_eq!(state.get::<String>().as_deref(), Some(&"app".to_string()));
+
+        let clone = state.clone();
+        clone
+            .get::<AtomicU64>()
+            .unwrap()
+            .fetch_add(1, Ordering::Relaxed);
+        assert_eq!(state.get::<AtomicU64>().unwrap().load(Ordering::Relaxed), 2);
+
+        state.insert(AtomicU64::new(5));
+        assert_eq!(clone.get::<AtomicU64>().unwrap().load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_missing_state() {
+        let context = Context::new(None, None);
+        let error = context.state::<AtomicU64>().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<MissingState>(),
+            Some(&MissingState {
+                type_name: "core::sync::atomic::AtomicU64"
+            })
+        );
+        assert!(context.state_mut::<Vec<u64>>().is_err());
+    }
+}
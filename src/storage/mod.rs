@@ -0,0 +1,409 @@
+//! Pluggable key-value storage for stateful features
+//!
+//! Features keeping state, e.g. the cursors of [backfills](crate::backfill)
+//! and the round-robin turns of [auto-assign](crate::plugins::auto_assign),
+//! store it in the app's [`Store`], set with
+//! [`Octofer::set_store`](crate::Octofer::set_store). Each feature works in
+//! its own [`Namespace`], so keys of different features never collide.
+//!
+//! The default [`InMemoryStore`] loses its entries on restart. With the
+//! `sqlite` feature, [`SqliteStore`](sqlite::SqliteStore) keeps them in a
+//! SQLite database file; other backends, e.g. Redis, implement [`Store`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::storage::{InMemoryStore, Namespace};
+//! use octofer::Octofer;
+//! use std::time::Duration;
+//!
+//! # async fn example(app: Octofer) -> anyhow::Result<()> {
+//! app.set_store(InMemoryStore::new());
+//!
+//! let greetings = Namespace::new(app.store(), "greetings");
+//! greetings
+//!     .put_json("octocat", &"hello", Some(Duration::from_secs(3600)))
+//!     .await?;
+//! let greeting: Option<String> = greetings.get_json("octocat").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Separator between the namespace of a [`Namespace`] and its keys
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+/// Entries listed by [`Store::list`], as keys and values
+pub type Entries = Vec<(String, Vec<u8>)>;
+
+/// Key-value storage shared by the app's stateful features
+///
+/// Values are bytes, and entries with a TTL expire once it elapsed: they are
+/// no longer returned by [`get`](Store::get) and [`list`](Store::list), nor
+/// counted by [`increment`](Store::increment). Implementations must be safe
+/// to use from concurrent deliveries.
+pub trait Store: Send + Sync + 'static {
+    /// Get the value of `key`, if set and not expired
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>>;
+
+    /// Set `key` to `value`, expiring after `ttl` if set
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Remove `key`, returning whether it was set and not expired
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>>;
+
+    /// List the entries whose key starts with `prefix`, ordered by key
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Entries>>;
+
+    /// Add one to the counter at `key`, starting from 0, and return its new
+    /// value
+    ///
+    /// Counters are stored as decimal text without a TTL. Concurrent calls
+    /// for the same key must return distinct values.
+    fn increment<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<u64>>;
+}
+
+/// Parse the value of a counter
+pub(crate) fn parse_counter(key: &str, value: &[u8]) -> Result<u64> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| anyhow!("Value of {} is not a counter", key))
+}
+
+/// Keys of one feature in a [`Store`]
+///
+/// Keys are prefixed with the namespace and [`NAMESPACE_SEPARATOR`], and
+/// listed without it.
+#[derive(Clone)]
+pub struct Namespace {
+    store: Arc<dyn Store>,
+    prefix: String,
+}
+
+impl Namespace {
+    /// Get the keys of `namespace` in `store`
+    pub fn new(store: Arc<dyn Store>, namespace: &str) -> Self {
+        Self {
+            store,
+            prefix: format!("{}{}", namespace, NAMESPACE_SEPARATOR),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Get the value of `key`, see [`Store::get`]
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.store.get(&self.key(key)).await
+    }
+
+    /// Set `key` to `value`, see [`Store::put`]
+    pub async fn put(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<()> {
+        self.store.put(&self.key(key), value, ttl).await
+    }
+
+    /// Remove `key`, see [`Store::delete`]
+    pub async fn delete(&self, key: &str) -> Result<bool> {
+        self.store.delete(&self.key(key)).await
+    }
+
+    /// List the entries whose key starts with `prefix`, with the keys
+    /// relative to the namespace, see [`Store::list`]
+    pub async fn list(&self, prefix: &str) -> Result<Entries> {
+        let entries = self.store.list(&self.key(prefix)).await?;
+        Ok(entries
+            .into_iter()
+            .map(|(key, value)| (key[self.prefix.len()..].to_string(), value))
+            .collect())
+    }
+
+    /// Add one to the counter at `key`, see [`Store::increment`]
+    pub async fn increment(&self, key: &str) -> Result<u64> {
+        self.store.increment(&self.key(key)).await
+    }
+
+    /// Get the value of `key` deserialized from JSON
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails or the value is not JSON of `T`.
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.get(key)
+            .await?
+            .map(|value| {
+                serde_json::from_slice(&value)
+                    .map_err(|e| anyhow!("Invalid value of {}: {}", self.key(key), e))
+            })
+            .transpose()
+    }
+
+    /// Set `key` to `value` serialized as JSON
+    pub async fn put_json<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        self.put(key, serde_json::to_vec(value)?, ttl).await
+    }
+}
+
+impl fmt::Debug for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Namespace")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle on the app's store, forwarding to the store currently set
+#[derive(Clone)]
+pub(crate) struct SharedStore {
+    current: Arc<RwLock<Arc<dyn Store>>>,
+}
+
+impl SharedStore {
+    /// Replace the store handles forward to
+    pub(crate) fn set(&self, store: Arc<dyn Store>) {
+        *self.current.write().unwrap() = store;
+    }
+
+    fn current(&self) -> Arc<dyn Store> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+impl Default for SharedStore {
+    fn default() -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(InMemoryStore::new()))),
+        }
+    }
+}
+
+impl fmt::Debug for SharedStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedStore").finish_non_exhaustive()
+    }
+}
+
+impl Store for SharedStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>> {
+        let store = self.current();
+        Box::pin(async move { store.get(key).await })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let store = self.current();
+        Box::pin(async move { store.put(key, value, ttl).await })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        let store = self.current();
+        Box::pin(async move { store.delete(key).await })
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Entries>> {
+        let store = self.current();
+        Box::pin(async move { store.list(prefix).await })
+    }
+
+    fn increment<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<u64>> {
+        let store = self.current();
+        Box::pin(async move { store.increment(key).await })
+    }
+}
+
+/// Value and expiry of an entry of an [`InMemoryStore`]
+type Entry = (Vec<u8>, Option<Instant>);
+
+/// [`Store`] keeping entries in memory
+///
+/// Entries are lost on restart. Expired entries are dropped when they are
+/// next read or listed.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    entries: Mutex<BTreeMap<String, Entry>>,
+}
+
+impl InMemoryStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn is_live((_, expires_at): &Entry) -> bool {
+    expires_at.is_none_or(|expires_at| Instant::now() < expires_at)
+}
+
+impl Store for InMemoryStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>> {
+        let mut entries = self.entries.lock().unwrap();
+        let value = match entries.get(key) {
+            Some(entry) if is_live(entry) => Some(entry.0.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let expires_at = ttl.map(|ttl| Instant::now() + ttl);
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (value, expires_at));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        let removed = self.entries.lock().unwrap().remove(key);
+        Box::pin(async move { Ok(removed.as_ref().is_some_and(is_live)) })
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Entries>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|key, entry| !key.starts_with(prefix) || is_live(entry));
+        let listed = entries
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, (value, _))| (key.clone(), value.clone()))
+            .collect();
+        Box::pin(async move { Ok(listed) })
+    }
+
+    fn increment<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<u64>> {
+        let mut entries = self.entries.lock().unwrap();
+        let current = match entries.get(key) {
+            Some(entry) if is_live(entry) => parse_counter(key, &entry.0),
+            _ => Ok(0),
+        };
+        let result = current.map(|current| {
+            let next = current + 1;
+            entries.insert(key.to_string(), (next.to_string().into_bytes(), None));
+            next
+        });
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Check the semantics every [`Store`] shares
+    pub(crate) async fn check_store(store: Arc<dyn Store>) {
+        store.put("a:1", b"one".to_vec(), None).await.unwrap();
+        store.put("a:2", b"two".to_vec(), None).await.unwrap();
+        store.put("ab:1", b"other".to_vec(), None).await.unwrap();
+        store
+            .put("a:3", b"short".to_vec(), Some(Duration::from_millis(50)))
+            .await
+            .unwrap();
+
+        assert_eq!(store.get("a:1").await.unwrap(), Some(b"one".to_vec()));
+        assert_eq!(store.get("a:missing").await.unwrap(), None);
+        let keys = |entries: Entries| entries.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(keys(store.list("a:").await.unwrap()), ["a:1", "a:2", "a:3"]);
+
+        // Overwriting replaces the value and TTL
+        store.put("a:1", b"uno".to_vec(), None).await.unwrap();
+        assert_eq!(store.get("a:1").await.unwrap(), Some(b"uno".to_vec()));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(store.get("a:3").await.unwrap(), None);
+        assert_eq!(keys(store.list("a:").await.unwrap()), ["a:1", "a:2"]);
+        assert_eq!(keys(store.list("a").await.unwrap()), ["a:1", "a:2", "ab:1"]);
+        assert!(!store.delete("a:3").await.unwrap());
+
+        assert!(store.delete("a:2").await.unwrap());
+        assert!(!store.delete("a:2").await.unwrap());
+        assert_eq!(store.get("a:2").await.unwrap(), None);
+
+        assert_eq!(store.increment("counter").await.unwrap(), 1);
+        assert_eq!(store.increment("counter").await.unwrap(), 2);
+        assert!(store.increment("a:1").await.is_err());
+
+        // Keys with characters special to patterns are listed literally
+        store.put("100%_done", b"x".to_vec(), None).await.unwrap();
+        store.put("100xdone", b"y".to_vec(), None).await.unwrap();
+        assert_eq!(keys(store.list("100%_").await.unwrap()), ["100%_done"]);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        check_store(Arc::new(InMemoryStore::new())).await;
+    }
+
+    #[tokio::test]
+    async fn test_namespaces_are_separate() {
+        let store: Arc<dyn Store> = Arc::new(InMemoryStore::new());
+        let backfill = Namespace::new(store.clone(), "backfill");
+        let turns = Namespace::new(store.clone(), "turns");
+
+        backfill
+            .put_json("octofer/app", &42u64, None)
+            .await
+            .unwrap();
+        turns.increment("octofer/app").await.unwrap();
+
+        assert_eq!(
+            backfill.get_json::<u64>("octofer/app").await.unwrap(),
+            Some(42)
+        );
+        assert_eq!(
+            backfill.list("").await.unwrap(),
+            [("octofer/app".to_string(), b"42".to_vec())]
+        );
+        assert_eq!(
+            store.get("turns:octofer/app").await.unwrap(),
+            Some(b"1".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_store_follows_the_store_set() {
+        let shared = SharedStore::default();
+        let handle: Arc<dyn Store> = Arc::new(shared.clone());
+        handle.put("key", b"old".to_vec(), None).await.unwrap();
+
+        let replacement = Arc::new(InMemoryStore::new());
+        shared.set(replacement.clone());
+        assert_eq!(handle.get("key").await.unwrap(), None);
+        handle.put("key", b"new".to_vec(), None).await.unwrap();
+        assert_eq!(replacement.get("key").await.unwrap(), Some(b"new".to_vec()));
+    }
+}
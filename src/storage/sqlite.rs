@@ -0,0 +1,243 @@
+//! SQLite-backed [`Store`]
+//!
+//! Enabled with the `sqlite` feature. Entries are kept in the
+//! `octofer_store` table of a database file, created if missing, so state
+//! survives restarts and can be shared by the processes of one host.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::storage::sqlite::SqliteStore;
+//! use octofer::Octofer;
+//!
+//! # fn example(app: Octofer) -> anyhow::Result<()> {
+//! app.set_store(SqliteStore::open("/var/lib/my-app/state.db")?);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use super::{parse_counter, Entries, Store};
+
+/// Create the table of the entries, with expiry times in Unix milliseconds
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS octofer_store (
+    key TEXT PRIMARY KEY NOT NULL,
+    value BLOB NOT NULL,
+    expires_at INTEGER
+)";
+
+/// Condition of entries that did not expire at `?2`
+const LIVE: &str = "(expires_at IS NULL OR expires_at > ?2)";
+
+/// [`Store`] keeping entries in a SQLite database
+///
+/// Expired entries are not returned, and removed by
+/// [`SqliteStore::purge_expired`].
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Open the database at `path`, creating it if missing
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or its table
+    /// cannot be created.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let connection = Connection::open(path)
+            .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+        Self::with_connection(connection)
+    }
+
+    /// Open a database living in memory, e.g. for tests
+    pub fn open_in_memory() -> Result<Self> {
+        let connection = Connection::open_in_memory()
+            .map_err(|e| anyhow!("Failed to open in-memory database: {}", e))?;
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection
+            .execute(SCHEMA, [])
+            .map_err(|e| anyhow!("Failed to create the store table: {}", e))?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Remove the expired entries, returning how many were removed
+    pub async fn purge_expired(&self) -> Result<usize> {
+        self.run(|connection| {
+            connection.execute(
+                "DELETE FROM octofer_store WHERE expires_at <= ?1",
+                params![now()],
+            )
+        })
+        .await
+    }
+
+    /// Run `query` on the connection without blocking the runtime
+    fn run<'a, T, F>(&self, query: F) -> BoxFuture<'a, Result<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut connection = connection.lock().unwrap();
+                query(&mut connection)
+            })
+            .await
+            .map_err(|e| anyhow!("Store query panicked: {}", e))?
+            .map_err(|e| anyhow!("Store query failed: {}", e))
+        })
+    }
+}
+
+/// Current time in Unix milliseconds
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as i64)
+}
+
+impl Store for SqliteStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>> {
+        let key = key.to_string();
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    &format!("SELECT value FROM octofer_store WHERE key = ?1 AND {LIVE}"),
+                    params![key, now()],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let key = key.to_string();
+        let expires_at = ttl.map(|ttl| now().saturating_add(ttl.as_millis() as i64));
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO octofer_store (key, value, expires_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (key) DO UPDATE SET value = ?2, expires_at = ?3",
+                    params![key, value, expires_at],
+                )
+                .map(drop)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool>> {
+        let key = key.to_string();
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let live = transaction.execute(
+                &format!("DELETE FROM octofer_store WHERE key = ?1 AND {LIVE}"),
+                params![key, now()],
+            )?;
+            transaction.execute("DELETE FROM octofer_store WHERE key = ?1", params![key])?;
+            transaction.commit()?;
+            Ok(live > 0)
+        })
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Entries>> {
+        let prefix = prefix.to_string();
+        self.run(move |connection| {
+            // `substr` rather than `LIKE`, which would treat `%` and `_` in
+            // the prefix as wildcards and ignore case
+            let mut statement = connection.prepare(&format!(
+                "SELECT key, value FROM octofer_store
+                 WHERE substr(key, 1, length(?1)) = ?1 AND {LIVE}
+                 ORDER BY key"
+            ))?;
+            let rows = statement
+                .query_map(params![prefix, now()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+    }
+
+    fn increment<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<u64>> {
+        let counted_key = key.to_string();
+        // `None` if the value is not a counter
+        let next = self.run(move |connection| {
+            // Held until the update, so other processes cannot count
+            // in between
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let current: Option<Vec<u8>> = transaction
+                .query_row(
+                    &format!("SELECT value FROM octofer_store WHERE key = ?1 AND {LIVE}"),
+                    params![counted_key, now()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let next = match current {
+                Some(value) => match parse_counter(&counted_key, &value) {
+                    Ok(current) => current + 1,
+                    Err(_) => return Ok(None),
+                },
+                None => 1,
+            };
+            transaction.execute(
+                "INSERT INTO octofer_store (key, value, expires_at) VALUES (?1, ?2, NULL)
+                         ON CONFLICT (key) DO UPDATE SET value = ?2, expires_at = NULL",
+                params![counted_key, next.to_string().into_bytes()],
+            )?;
+            transaction.commit()?;
+            Ok(Some(next))
+        });
+        Box::pin(async move {
+            next.await?
+                .ok_or_else(|| anyhow!("Value of {} is not a counter", key))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::check_store;
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        check_store(Arc::new(SqliteStore::open_in_memory().unwrap())).await;
+    }
+
+    #[tokio::test]
+    async fn test_entries_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("octofer-store-{}.db", std::process::id()));
+        let store = SqliteStore::open(&path).unwrap();
+        store.put("kept", b"1".to_vec(), None).await.unwrap();
+        store
+            .put("expiring", b"2".to_vec(), Some(Duration::from_millis(10)))
+            .await
+            .unwrap();
+        drop(store);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.get("kept").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.purge_expired().await.unwrap(), 1);
+        assert_eq!(store.list("").await.unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+//! Deliveries as an async stream of events
+//!
+//! Instead of, or alongside, registering handlers, an app can consume the
+//! contexts of its deliveries from an [`EventStream`] and route them itself.
+//! Every delivery verified and parsed by the server is published to every
+//! open stream, before its handlers run; deliveries skipped for every handler,
+//! because [the app caused them](crate::config::DispatchConfig::ignore_self)
+//! or they are [stale](crate::staleness), are not published.
+//!
+//! Streams buffer a bounded number of contexts. When a consumer falls behind
+//! and its buffer is full, the [`Overflow`] policy of the stream decides which
+//! context is dropped; drops are counted by [`EventStream::dropped`] and never
+//! hold up deliveries. Streams end once the server shuts down and the
+//! buffered contexts are consumed.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use futures::StreamExt;
+//! use octofer::Octofer;
+//!
+//! # async fn example(app: Octofer) -> anyhow::Result<()> {
+//! let mut events = app.event_stream(100);
+//! tokio::spawn(async move {
+//!     while let Some(context) = events.next().await {
+//!         match context.kind().as_str() {
+//!             "issues" => println!("Issue event {:?}", context.delivery_id()),
+//!             "push" => println!("Push event {:?}", context.delivery_id()),
+//!             _ => {}
+//!         }
+//!     }
+//!     println!("Server stopped, {} event(s) dropped", events.dropped());
+//! });
+//!
+//! app.start().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context as TaskContext, Poll};
+
+use futures::task::AtomicWaker;
+use futures::Stream;
+use tracing::debug;
+
+use crate::Context;
+
+/// Which context a full [`EventStream`] drops
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the oldest buffered context, keeping the latest deliveries
+    #[default]
+    DropOldest,
+    /// Drop the new context, keeping the buffered deliveries
+    DropNewest,
+}
+
+/// Buffer shared by a stream and its publisher
+#[derive(Debug)]
+struct Buffer {
+    queue: Mutex<VecDeque<Context>>,
+    capacity: usize,
+    overflow: Overflow,
+    dropped: AtomicU64,
+    closed: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Buffer {
+    /// Buffer `context`, dropping one if full
+    fn push(&self, context: Context) {
+        {
+            let mut queue = self.queue.lock().expect("stream lock poisoned");
+            if queue.len() >= self.capacity {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "Event stream full, dropping the {} context",
+                    match self.overflow {
+                        Overflow::DropOldest => "oldest",
+                        Overflow::DropNewest => "newest",
+                    }
+                );
+                match self.overflow {
+                    Overflow::DropOldest => {
+                        queue.pop_front();
+                    }
+                    Overflow::DropNewest => return,
+                }
+            }
+            queue.push_back(context);
+        }
+        self.waker.wake();
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.waker.wake();
+    }
+}
+
+/// Stream of the contexts of an app's deliveries, see the
+/// [`stream`](crate::stream) module
+///
+/// Created by [`Octofer::event_stream`](crate::Octofer::event_stream) or
+/// [`Dispatcher::event_stream`](crate::dispatch::Dispatcher::event_stream).
+/// Dropping the stream unsubscribes it.
+#[derive(Debug)]
+pub struct EventStream {
+    buffer: Arc<Buffer>,
+}
+
+impl EventStream {
+    /// Number of contexts dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.buffer.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of contexts buffered, not consumed yet
+    pub fn len(&self) -> usize {
+        self.buffer
+            .queue
+            .lock()
+            .expect("stream lock poisoned")
+            .len()
+    }
+
+    /// Whether no context is buffered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Context;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Context>> {
+        let buffer = &self.buffer;
+        // Registered before checking, so a push in between wakes the task
+        buffer.waker.register(cx.waker());
+        if let Some(context) = buffer
+            .queue
+            .lock()
+            .expect("stream lock poisoned")
+            .pop_front()
+        {
+            return Poll::Ready(Some(context));
+        }
+        if buffer.closed.load(Ordering::SeqCst) {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    }
+}
+
+/// Open event streams of a dispatcher
+///
+/// Cloning is cheap; clones share the same streams, which end once the last
+/// clone is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct EventStreams {
+    subscribers: Arc<Subscribers>,
+}
+
+#[derive(Debug, Default)]
+struct Subscribers {
+    buffers: Mutex<Vec<Weak<Buffer>>>,
+}
+
+impl Subscribers {
+    fn close(&self) {
+        let buffers = std::mem::take(&mut *self.buffers.lock().expect("streams lock poisoned"));
+        for buffer in buffers.iter().filter_map(Weak::upgrade) {
+            buffer.close();
+        }
+    }
+}
+
+impl Drop for Subscribers {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl EventStreams {
+    /// Open a stream buffering up to `buffer` contexts, at least one
+    pub fn subscribe(&self, buffer: usize, overflow: Overflow) -> EventStream {
+        let buffer = Arc::new(Buffer {
+            queue: Mutex::new(VecDeque::new()),
+            capacity: buffer.max(1),
+            overflow,
+            dropped: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        });
+        self.buffers().push(Arc::downgrade(&buffer));
+        EventStream { buffer }
+    }
+
+    /// Publish `context` to every open stream
+    pub fn publish(&self, context: &Context) {
+        self.buffers().retain(|buffer| match buffer.upgrade() {
+            Some(buffer) => {
+                buffer.push(context.clone());
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// End every open stream once its buffered contexts are consumed
+    ///
+    /// Streams opened afterwards stay open.
+    pub fn close(&self) {
+        self.subscribers.close();
+    }
+
+    /// Get a guard ending every stream open when it is dropped, e.g. when a
+    /// server future is cancelled
+    pub(crate) fn close_on_drop(&self) -> CloseOnDrop {
+        CloseOnDrop(self.clone())
+    }
+
+    /// Number of open streams
+    pub fn len(&self) -> usize {
+        let mut buffers = self.buffers();
+        buffers.retain(|buffer| buffer.strong_count() > 0);
+        buffers.len()
+    }
+
+    /// Whether no stream is open
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn buffers(&self) -> std::sync::MutexGuard<'_, Vec<Weak<Buffer>>> {
+        self.subscribers
+            .buffers
+            .lock()
+            .expect("streams lock poisoned")
+    }
+}
+
+/// Guard returned by [`EventStreams::close_on_drop`]
+pub(crate) struct CloseOnDrop(EventStreams);
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn delivery(id: u32) -> Context {
+        let mut context = Context::new(None, None);
+        context.delivery_id = Some(format!("delivery-{}", id));
+        context
+    }
+
+    async fn ids(stream: &mut EventStream) -> Vec<String> {
+        let mut ids = Vec::new();
+        while !stream.is_empty() {
+            let context = stream.next().await.unwrap();
+            ids.push(context.delivery_id().unwrap().to_string());
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_full_streams_drop_by_their_policy() {
+        let streams = EventStreams::default();
+        let mut oldest = streams.subscribe(2, Overflow::DropOldest);
+        let mut newest = streams.subscribe(2, Overflow::DropNewest);
+        for id in 1..=3 {
+            streams.publish(&delivery(id));
+        }
+
+        assert_eq!(ids(&mut oldest).await, ["delivery-2", "delivery-3"]);
+        assert_eq!(ids(&mut newest).await, ["delivery-1", "delivery-2"]);
+        assert_eq!((oldest.dropped(), newest.dropped()), (1, 1));
+
+        drop(newest);
+        assert_eq!(streams.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_closed_streams_end_after_their_buffer() {
+        let streams = EventStreams::default();
+        let mut stream = streams.subscribe(10, Overflow::default());
+        let waiting = tokio::spawn(async move {
+            let mut ids = Vec::new();
+            while let Some(context) = stream.next().await {
+                ids.push(context.delivery_id().unwrap().to_string());
+            }
+            ids
+        });
+
+        streams.publish(&delivery(1));
+        streams.close();
+        streams.publish(&delivery(2));
+        assert_eq!(waiting.await.unwrap(), ["delivery-1"]);
+        assert!(streams.is_empty());
+
+        // Streams also end with their dispatcher
+        let mut stream = streams.subscribe(10, Overflow::default());
+        drop(streams);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_and_handlers_observe_the_same_delivery() {
+        use crate::testing::issues_payload;
+        use crate::testing::live::LiveTestServer;
+
+        let mut server = LiveTestServer::new();
+        server
+            .on("issues", |_context, _extra| async { Ok(()) }, Arc::new(()))
+            .await;
+        let mut stream = server
+            .server()
+            .dispatcher()
+            .event_stream(10, Overflow::DropOldest);
+
+        let response = server
+            .send_webhook("issues", &issues_payload("opened", 1), &server.secret())
+            .await;
+        assert!(response.status.is_success());
+        let streamed = stream.next().await.unwrap();
+        assert_eq!(streamed.kind().as_str(), "issues");
+        assert_eq!(
+            streamed.delivery_id(),
+            response.invocations[0].delivery_id()
+        );
+
+        // Deliveries of events without handlers are streamed too
+        let ping = serde_json::json!({ "zen": "Approachable is better.", "hook_id": 1 });
+        server.send_webhook("ping", &ping, &server.secret()).await;
+        assert_eq!(stream.next().await.unwrap().kind().as_str(), "ping");
+        assert_eq!(stream.dropped(), 0);
+    }
+}
@@ -0,0 +1,541 @@
+//! Supervised background tasks
+//!
+//! Features that work in the background, e.g. keeping installation tokens
+//! warm or writing the delivery archive, run as tasks of the app's
+//! [`TaskSupervisor`] rather than as bare Tokio tasks. Plugins and apps add
+//! their own with [`Octofer::spawn_supervised`](crate::Octofer::spawn_supervised).
+//!
+//! A task is created by a factory, called again for every restart, and
+//! returns `anyhow::Result<()>`. When it fails, returning an error or
+//! panicking, its [`RestartPolicy`] decides whether it runs again:
+//!
+//! - [`RestartPolicy::Backoff`], the default, restarts failed tasks after a
+//!   delay doubling up to a maximum, and lets tasks that return `Ok` finish;
+//! - [`RestartPolicy::Always`] also restarts tasks that return `Ok`, after
+//!   [`ALWAYS_RESTART_DELAY`];
+//! - [`RestartPolicy::Never`] runs the task once.
+//!
+//! Each task's status and restart count are listed in
+//! [`TaskSupervisor::summary`], which is part of the
+//! [registration summary](crate::webhook::info). Once a task marked as
+//! [critical](TaskOptions::critical) failed for good, `/health` responds
+//! with `503 Service Unavailable`.
+//!
+//! On shutdown, [`TaskSupervisor::shutdown`] signals every task through its
+//! [`TaskShutdown`] and waits for them to return, aborting the tasks still
+//! running after the timeout.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::tasks::{RestartPolicy, TaskOptions, DEFAULT_SHUTDOWN_TIMEOUT};
+//! use octofer::{Config, Octofer};
+//! use std::time::Duration;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let app = Octofer::new(Config::from_env()?).await?;
+//! app.spawn_supervised_with(
+//!     "nightly-sync",
+//!     TaskOptions {
+//!         critical: true,
+//!         ..TaskOptions::default()
+//!     },
+//!     |mut shutdown| async move {
+//!         let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+//!         loop {
+//!             tokio::select! {
+//!                 _ = shutdown.cancelled() => return Ok(()),
+//!                 _ = interval.tick() => println!("Syncing"),
+//!             }
+//!         }
+//!     },
+//! );
+//!
+//! tokio::select! {
+//!     result = app.start() => result?,
+//!     _ = tokio::signal::ctrl_c() => {}
+//! }
+//! app.shutdown_tasks(DEFAULT_SHUTDOWN_TIMEOUT).await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinHandle};
+use tracing::{debug, error, info, warn};
+
+/// Delay before restarting a task with [`RestartPolicy::Always`]
+pub const ALWAYS_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Time [`TaskSupervisor::shutdown`] is usually given to stop the tasks
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// When a task is run again after it ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Restart the task whenever it ends, after [`ALWAYS_RESTART_DELAY`]
+    Always,
+    /// Restart the task when it fails, after `initial` and then twice as
+    /// long as before, up to `max`
+    ///
+    /// The delay starts over once a run lasted at least `max`.
+    Backoff {
+        /// Delay before the first restart
+        initial: Duration,
+        /// Longest delay between restarts
+        max: Duration,
+    },
+    /// Never restart the task
+    Never,
+}
+
+impl Default for RestartPolicy {
+    /// Backoff from 1 second up to 1 minute
+    fn default() -> Self {
+        Self::Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+        }
+    }
+}
+
+/// How a task is supervised
+#[derive(Debug, Clone, Default)]
+pub struct TaskOptions {
+    /// When the task is run again after it ended
+    pub restart: RestartPolicy,
+    /// Whether the app is unhealthy once the task failed for good
+    pub critical: bool,
+    /// Number of restarts after which a failing task is given up (`None`
+    /// for no limit)
+    pub max_restarts: Option<u32>,
+}
+
+/// State of a supervised task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    /// The task is running
+    Running,
+    /// The task ended and waits to be restarted
+    Restarting,
+    /// The task returned `Ok` and is not restarted
+    Finished,
+    /// The task failed and is not restarted
+    Failed,
+    /// The task was stopped, or aborted on shutdown
+    Stopped,
+}
+
+/// Status of one supervised task, see [`TaskSupervisor::summary`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskSummary {
+    /// Name of the task
+    pub name: String,
+    /// Current state of the task
+    pub status: TaskStatus,
+    /// Whether the app is unhealthy once the task failed
+    pub critical: bool,
+    /// Number of times the task was restarted
+    pub restarts: u32,
+    /// Error or panic message of the last failed run, if any
+    pub last_error: Option<String>,
+}
+
+/// Signal asking a task to return, passed to its factory
+#[derive(Debug, Clone)]
+pub struct TaskShutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl TaskShutdown {
+    /// Whether the task was asked to return
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Wait until the task is asked to return
+    pub async fn cancelled(&mut self) {
+        // The sender is only dropped once the task is no longer supervised
+        let _ = self.receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// Supervisor of an app's background tasks, see the
+/// [module documentation](self)
+///
+/// Cloning is cheap; clones supervise the same tasks.
+#[derive(Clone, Default)]
+pub struct TaskSupervisor {
+    inner: Arc<Mutex<Tasks>>,
+}
+
+#[derive(Default)]
+struct Tasks {
+    running: BTreeMap<String, Task>,
+    /// Replaced or stopped tasks that were signalled but may still run
+    retired: Vec<JoinHandle<()>>,
+    shutting_down: bool,
+}
+
+struct Task {
+    state: Arc<Mutex<TaskSummary>>,
+    stop: watch::Sender<bool>,
+    /// Loop supervising the task, taken on shutdown
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Aborts a task when dropped, so aborting its supervising loop also aborts
+/// the run in progress
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl TaskSupervisor {
+    /// Run the task created by `factory` under supervision, as `name`
+    ///
+    /// A task already running as `name` is [stopped](TaskSupervisor::stop)
+    /// and replaced. Once the supervisor [shuts down](TaskSupervisor::shutdown),
+    /// no task is started anymore.
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn spawn<F, Fut>(&self, name: &str, options: TaskOptions, factory: F)
+    where
+        F: Fn(TaskShutdown) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let mut tasks = self.inner.lock().expect("task supervisor lock poisoned");
+        if tasks.shutting_down {
+            warn!("Not starting background task {}: shutting down", name);
+            return;
+        }
+
+        let state = Arc::new(Mutex::new(TaskSummary {
+            name: name.to_string(),
+            status: TaskStatus::Running,
+            critical: options.critical,
+            restarts: 0,
+            last_error: None,
+        }));
+        let (stop, receiver) = watch::channel(false);
+        let handle = tokio::spawn(supervise(
+            name.to_string(),
+            options,
+            factory,
+            state.clone(),
+            receiver,
+        ));
+        debug!("Started background task {}", name);
+
+        let task = Task {
+            state,
+            stop,
+            handle: Some(handle),
+        };
+        if let Some(replaced) = tasks.running.insert(name.to_string(), task) {
+            replaced.stop.send_replace(true);
+            tasks.retired.extend(replaced.handle);
+        }
+    }
+
+    /// Ask the task running as `name` to return, without restarting it
+    ///
+    /// Returns whether such a task was running.
+    pub fn stop(&self, name: &str) -> bool {
+        let mut tasks = self.inner.lock().expect("task supervisor lock poisoned");
+        let Some(task) = tasks.running.remove(name) else {
+            return false;
+        };
+        task.stop.send_replace(true);
+        tasks.retired.extend(task.handle);
+        true
+    }
+
+    /// Get the status of every supervised task, sorted by name
+    pub fn summary(&self) -> Vec<TaskSummary> {
+        let tasks = self.inner.lock().expect("task supervisor lock poisoned");
+        tasks
+            .running
+            .values()
+            .map(|task| task.state.lock().expect("task state lock poisoned").clone())
+            .collect()
+    }
+
+    /// Whether no [critical](TaskOptions::critical) task failed for good
+    pub fn is_healthy(&self) -> bool {
+        self.summary()
+            .iter()
+            .all(|task| !task.critical || task.status != TaskStatus::Failed)
+    }
+
+    /// Ask every task to return, and wait up to `timeout` for them
+    ///
+    /// Tasks are not restarted anymore, and tasks still running after
+    /// `timeout` are aborted. Returns the names of the aborted tasks.
+    pub async fn shutdown(&self, timeout: Duration) -> Vec<String> {
+        let (mut running, retired) = {
+            let mut tasks = self.inner.lock().expect("task supervisor lock poisoned");
+            tasks.shutting_down = true;
+            for task in tasks.running.values() {
+                task.stop.send_replace(true);
+            }
+            let running: Vec<_> = tasks
+                .running
+                .iter_mut()
+                .filter_map(|(name, task)| {
+                    let handle = task.handle.take()?;
+                    Some((name.clone(), handle, task.state.clone()))
+                })
+                .collect();
+            (running, std::mem::take(&mut tasks.retired))
+        };
+        info!("Stopping {} background task(s)", running.len());
+
+        let mut retired = retired;
+        let drained = tokio::time::timeout(timeout, async {
+            for (_, handle, _) in running.iter_mut() {
+                let _ = handle.await;
+            }
+            for handle in retired.iter_mut() {
+                let _ = handle.await;
+            }
+        })
+        .await
+        .is_ok();
+        if drained {
+            return Vec::new();
+        }
+
+        let mut aborted = Vec::new();
+        for (name, handle, state) in running {
+            if !handle.is_finished() {
+                handle.abort();
+                update(&state, |task| task.status = TaskStatus::Stopped);
+                warn!("Aborted background task {} after {:?}", name, timeout);
+                aborted.push(name);
+            }
+        }
+        for handle in retired {
+            handle.abort();
+        }
+        aborted
+    }
+}
+
+/// Run the tasks created by `factory` until the policy stops restarting
+/// them or the task is stopped
+async fn supervise<F, Fut>(
+    name: String,
+    options: TaskOptions,
+    factory: F,
+    state: Arc<Mutex<TaskSummary>>,
+    mut stop: watch::Receiver<bool>,
+) where
+    F: Fn(TaskShutdown) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut delay = match options.restart {
+        RestartPolicy::Backoff { initial, .. } => initial,
+        _ => ALWAYS_RESTART_DELAY,
+    };
+
+    loop {
+        update(&state, |task| task.status = TaskStatus::Running);
+        let started = Instant::now();
+        let run = tokio::spawn(factory(TaskShutdown {
+            receiver: stop.clone(),
+        }));
+        let _abort = AbortOnDrop(run.abort_handle());
+        let failure = match run.await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{:#}", e)),
+            Err(e) if e.is_panic() => Some(format!("panicked: {}", panic_message(e.into_panic()))),
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(failure) = &failure {
+            error!("Background task {} failed: {}", name, failure);
+            update(&state, |task| task.last_error = Some(failure.clone()));
+        }
+
+        let restarts = state.lock().expect("task state lock poisoned").restarts;
+        let done = match (options.restart, &failure) {
+            _ if *stop.borrow() => Some(TaskStatus::Stopped),
+            (RestartPolicy::Never | RestartPolicy::Backoff { .. }, None) => {
+                Some(TaskStatus::Finished)
+            }
+            (RestartPolicy::Never, Some(_)) => Some(TaskStatus::Failed),
+            _ if options.max_restarts.is_some_and(|max| restarts >= max) => {
+                Some(TaskStatus::Failed)
+            }
+            _ => None,
+        };
+        if let Some(status) = done {
+            debug!("Background task {} ended: {:?}", name, status);
+            update(&state, |task| task.status = status);
+            return;
+        }
+
+        if let RestartPolicy::Backoff { initial, max } = options.restart {
+            if started.elapsed() >= max {
+                delay = initial;
+            }
+        }
+        update(&state, |task| {
+            task.status = TaskStatus::Restarting;
+            task.restarts += 1;
+        });
+        warn!("Restarting background task {} in {:?}", name, delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = stop.wait_for(|stopped| *stopped) => {
+                update(&state, |task| task.status = TaskStatus::Stopped);
+                return;
+            }
+        }
+        if let RestartPolicy::Backoff { max, .. } = options.restart {
+            delay = (delay * 2).min(max);
+        }
+    }
+}
+
+/// Apply `change` to the summary of a task
+fn update(state: &Mutex<TaskSummary>, change: impl FnOnce(&mut TaskSummary)) {
+    change(&mut state.lock().expect("task state lock poisoned"));
+}
+
+/// Get the message of a panic's payload
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_panicking_task_is_restarted_with_backoff() {
+        let supervisor = TaskSupervisor::default();
+        let runs = Arc::new(AtomicU32::new(0));
+        let started = Instant::now();
+        let counted = runs.clone();
+        supervisor.spawn(
+            "flaky",
+            TaskOptions {
+                restart: RestartPolicy::Backoff {
+                    initial: Duration::from_millis(20),
+                    max: Duration::from_secs(1),
+                },
+                critical: true,
+                max_restarts: None,
+            },
+            move |_| {
+                let run = counted.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if run < 2 {
+                        panic!("run {} failed", run);
+                    }
+                    Ok(())
+                }
+            },
+        );
+
+        while supervisor.summary()[0].status != TaskStatus::Finished {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        // Waited 20 then 40 milliseconds between the runs
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        assert_eq!(
+            supervisor.summary(),
+            [TaskSummary {
+                name: "flaky".to_string(),
+                status: TaskStatus::Finished,
+                critical: true,
+                restarts: 2,
+                last_error: Some("panicked: run 1 failed".to_string()),
+            }]
+        );
+        assert!(supervisor.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_failed_critical_task_is_unhealthy() {
+        let supervisor = TaskSupervisor::default();
+        supervisor.spawn(
+            "once",
+            TaskOptions {
+                restart: RestartPolicy::Backoff {
+                    initial: Duration::from_millis(1),
+                    max: Duration::from_millis(10),
+                },
+                critical: true,
+                max_restarts: Some(1),
+            },
+            |_| async { Err(anyhow::anyhow!("sync broke")) },
+        );
+
+        while supervisor.summary()[0].status != TaskStatus::Failed {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(supervisor.summary()[0].restarts, 1);
+        assert!(!supervisor.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_tasks_within_timeout() {
+        let supervisor = TaskSupervisor::default();
+        let flushed = Arc::new(AtomicU32::new(0));
+        let counted = flushed.clone();
+        supervisor.spawn(
+            "cooperative",
+            TaskOptions::default(),
+            move |mut shutdown| {
+                let flushed = counted.clone();
+                async move {
+                    shutdown.cancelled().await;
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    flushed.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            },
+        );
+        supervisor.spawn("stubborn", TaskOptions::default(), |_| {
+            std::future::pending::<Result<()>>()
+        });
+        tokio::task::yield_now().await;
+
+        let started = Instant::now();
+        let aborted = supervisor.shutdown(Duration::from_millis(200)).await;
+        assert_eq!(aborted, ["stubborn"]);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(flushed.load(Ordering::SeqCst), 1);
+        let statuses: Vec<_> = supervisor
+            .summary()
+            .iter()
+            .map(|task| task.status)
+            .collect();
+        assert_eq!(statuses, [TaskStatus::Stopped, TaskStatus::Stopped]);
+
+        // Nothing starts after shutdown
+        supervisor.spawn("late", TaskOptions::default(), |_| async { Ok(()) });
+        assert_eq!(supervisor.summary().len(), 2);
+    }
+}
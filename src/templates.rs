@@ -0,0 +1,288 @@
+//! Named templates for bot-authored messages
+//!
+//! [`Templates`] maps names to Markdown templates. The app provides them with
+//! [`Octofer::set_templates`](crate::Octofer::set_templates), starting from
+//! [`Templates::builtin`], and handlers render them with
+//! [`Context::render`](crate::Context::render). Repositories can override any
+//! template by committing `.github/octofer/templates/{name}.md` (see
+//! [`OVERRIDE_DIR`]), e.g. to translate the bot's messages.
+//!
+//! # Syntax
+//!
+//! - `{{ path }}` is replaced by the value at `path` in the data, a
+//!   dot-separated sequence of object keys and array indices
+//!   (`{{ issue.user.login }}`). Values are HTML-escaped, so user-supplied
+//!   text cannot inject markup into the rendered Markdown.
+//! - `{{{ path }}}` inserts the value as is, for trusted Markdown.
+//!
+//! Strings are inserted without quotes, other values as JSON. Rendering fails
+//! with a [`TemplateError`] if a value is missing or `null`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use octofer::templates::Templates;
+//! use serde_json::json;
+//!
+//! let templates = Templates::builtin().with("greeting", "Hello, **{{ name }}**!");
+//! let rendered = templates.render("greeting", &json!({ "name": "<octocat>" }))?;
+//! assert_eq!(rendered, "Hello, **&lt;octocat&gt;**!");
+//! # Ok::<(), octofer::templates::TemplateError>(())
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use serde_json::Value;
+
+/// Directory of a repository holding template overrides, one `{name}.md`
+/// file per template
+pub const OVERRIDE_DIR: &str = ".github/octofer/templates";
+
+/// Templates of [`Templates::builtin`]
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "welcome",
+        "Thanks for opening this, @{{ user }}! A maintainer will take a look soon.",
+    ),
+    (
+        "stale",
+        "This has had no activity for {{ days }} days. It will be closed soon unless there is new activity.",
+    ),
+    (
+        "spam_closed",
+        "This was automatically closed as spam. If this is a mistake, please reach out to the maintainers.",
+    ),
+];
+
+/// Error rendering a template
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum TemplateError {
+    /// No template with this name is registered
+    #[error("Template {0} not found")]
+    NotFound(String),
+    /// A placeholder is not closed
+    #[error("Unclosed placeholder at byte {position} of template {template}")]
+    Unclosed {
+        /// Name of the template
+        template: String,
+        /// Byte offset of the placeholder
+        position: usize,
+    },
+    /// A placeholder refers to a value that is missing or `null`
+    #[error("Template {template} refers to missing value {path}")]
+    MissingValue {
+        /// Name of the template
+        template: String,
+        /// Path of the value
+        path: String,
+    },
+    /// The data could not be serialized
+    #[error("Failed to serialize template data: {0}")]
+    Data(#[from] serde_json::Error),
+}
+
+/// Named Markdown templates, see the [module documentation](self)
+///
+/// Cloning is cheap; clones share the templates until one is modified.
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+    templates: Arc<HashMap<String, String>>,
+}
+
+impl Templates {
+    /// Create an empty set of templates
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the templates provided by Octofer
+    ///
+    /// - `welcome` - Greets the author (`user`) of a new issue or pull request
+    /// - `stale` - Warns that an item inactive for `days` days will be closed
+    /// - `spam_closed` - Explains why the spam guard closed an issue or pull
+    ///   request (`number`) opened by `user`
+    pub fn builtin() -> Self {
+        static BUILTIN: OnceLock<Templates> = OnceLock::new();
+        BUILTIN
+            .get_or_init(|| {
+                BUILTIN_TEMPLATES
+                    .iter()
+                    .fold(Self::new(), |templates, (name, source)| {
+                        templates.with(*name, *source)
+                    })
+            })
+            .clone()
+    }
+
+    /// Add or replace the template `name`
+    pub fn with(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.insert(name, source);
+        self
+    }
+
+    /// Add or replace the template `name`
+    pub fn insert(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        Arc::make_mut(&mut self.templates).insert(name.into(), source.into());
+    }
+
+    /// Get the source of the template `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(String::as_str)
+    }
+
+    /// Names of the templates, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Render the template `name` with `data`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TemplateError::NotFound`] if there is no such template, or
+    /// the error of [`render_source`].
+    pub fn render(
+        &self,
+        name: &str,
+        data: &impl serde::Serialize,
+    ) -> Result<String, TemplateError> {
+        let source = self
+            .get(name)
+            .ok_or_else(|| TemplateError::NotFound(name.to_string()))?;
+        render_source(name, source, &serde_json::to_value(data)?)
+    }
+}
+
+/// Render the template `source`, named `name` in errors, with `data`
+///
+/// # Errors
+///
+/// Returns an error if a placeholder is not closed or refers to a missing
+/// value.
+pub fn render_source(name: &str, source: &str, data: &Value) -> Result<String, TemplateError> {
+    let mut rendered = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let position = source.len() - rest.len() + start;
+        let (raw, open, close) = if rest[start..].starts_with("{{{") {
+            (true, "{{{", "}}}")
+        } else {
+            (false, "{{", "}}")
+        };
+
+        let after = &rest[start + open.len()..];
+        let end = after.find(close).ok_or_else(|| TemplateError::Unclosed {
+            template: name.to_string(),
+            position,
+        })?;
+        let path = after[..end].trim();
+        let value = lookup(data, path).ok_or_else(|| TemplateError::MissingValue {
+            template: name.to_string(),
+            path: path.to_string(),
+        })?;
+
+        let text = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        if raw {
+            rendered.push_str(&text);
+        } else {
+            rendered.push_str(&escape_html(&text));
+        }
+        rest = &after[end + close.len()..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Look up the non-null value at the dot-separated `path`
+fn lookup<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(data, |value, key| match value {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => value.get(key),
+        })
+        .filter(|value| !value.is_null())
+}
+
+/// Escape the characters with a meaning in HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_values_are_escaped_unless_raw() {
+        let templates = Templates::new()
+            .with(
+                "escaped",
+                "**{{ title }}** by {{issue.user.login}} ({{ labels.0 }})",
+            )
+            .with("raw", "{{{ body }}}");
+        let data = json!({
+            "title": "<script>alert('x')</script> & more",
+            "issue": { "user": { "login": "octocat" } },
+            "labels": [42],
+            "body": "<details>trusted</details>",
+        });
+
+        assert_eq!(
+            templates.render("escaped", &data).unwrap(),
+            "**&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; more** by octocat (42)"
+        );
+        assert_eq!(
+            templates.render("raw", &data).unwrap(),
+            "<details>trusted</details>"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let templates = Templates::builtin().with("broken", "Hello {{ name");
+
+        assert!(matches!(
+            templates.render("missing", &json!({})),
+            Err(TemplateError::NotFound(name)) if name == "missing"
+        ));
+        assert!(matches!(
+            templates.render("broken", &json!({ "name": "x" })),
+            Err(TemplateError::Unclosed { position: 6, .. })
+        ));
+        assert!(matches!(
+            templates.render("welcome", &json!({ "user": null })),
+            Err(TemplateError::MissingValue { path, .. }) if path == "user"
+        ));
+        assert_eq!(
+            templates
+                .render("welcome", &json!({ "user": "octocat" }))
+                .unwrap(),
+            "Thanks for opening this, @octocat! A maintainer will take a look soon."
+        );
+        assert_eq!(
+            templates.names(),
+            ["broken", "spam_closed", "stale", "welcome"]
+        );
+        assert!(Templates::builtin().get("broken").is_none());
+    }
+}
@@ -0,0 +1,62 @@
+//! Recorded webhook deliveries as test fixtures
+//!
+//! Fixtures are written by [`FixtureRecorder`](crate::archive::FixtureRecorder)
+//! when `OCTOFER_RECORD_FIXTURES_DIR` is set. Copy the ones worth keeping to
+//! [`FIXTURES_DIR`] in the crate under test and load them by name with
+//! [`MockWebhookEvent::from_fixture`](crate::testing::MockWebhookEvent::from_fixture):
+//!
+//! ```rust,no_run
+//! use octocrab::models::webhook_events::WebhookEventType;
+//! use octofer::testing::MockWebhookEvent;
+//!
+//! # fn example() -> anyhow::Result<()> {
+//! let event = MockWebhookEvent::from_fixture("issues.opened.72d3162e")?;
+//! assert_eq!(event.kind, WebhookEventType::Issues);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::archive::ArchivedDelivery;
+
+/// Directory fixtures are looked up in by name, relative to the crate root
+pub const FIXTURES_DIR: &str = "tests/fixtures";
+
+/// Load a fixture file written by the fixture recorder
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or is not a recorded delivery.
+pub fn load(path: impl AsRef<Path>) -> Result<ArchivedDelivery> {
+    let path = path.as_ref();
+    let contents = std::fs::read(path)
+        .map_err(|e| anyhow!("Failed to read fixture {}: {}", path.display(), e))?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| anyhow!("Invalid fixture {}: {}", path.display(), e))
+}
+
+/// Resolve the path of the fixture `name`
+///
+/// Existing paths are used as is. Otherwise `name` is looked up in
+/// [`FIXTURES_DIR`] of the crate being tested (`CARGO_MANIFEST_DIR`, or the
+/// working directory outside of Cargo), with `.json` added if missing.
+pub fn path(name: &str) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.exists() {
+        return path;
+    }
+
+    let file_name = if name.ends_with(".json") {
+        name.to_string()
+    } else {
+        format!("{name}.json")
+    };
+    std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(FIXTURES_DIR)
+        .join(file_name)
+}
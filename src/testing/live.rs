@@ -0,0 +1,294 @@
+//! End-to-end test harness for webhook deliveries
+//!
+//! [`LiveTestServer`] sends signed deliveries through the real router of a
+//! [`WebhookServer`]: HMAC verification, event parsing, dispatch and the
+//! handlers, up to the response status. The contexts handlers were invoked
+//! with are captured so tests can assert on what ran.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use octofer::testing::live::LiveTestServer;
+//! use serde_json::json;
+//! use std::sync::Arc;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut server = LiveTestServer::new();
+//! server
+//!     .on("ping", |_context, _extra| async { Ok(()) }, Arc::new(()))
+//!     .await;
+//!
+//! let payload = json!({ "zen": "Keep it logically awesome.", "hook_id": 1 });
+//! let response = server
+//!     .send_webhook("ping", &payload, &server.secret())
+//!     .await;
+//! assert!(response.status.is_success());
+//! assert_eq!(response.invocations.len(), 1);
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    http::{Request, StatusCode},
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+use crate::core::HandlerSource;
+use crate::dispatch::{normalize_event_name, normalize_unchecked};
+use crate::github::middlewares::GITHUB_EVENT_HEADER;
+use crate::testing::sign;
+use crate::webhook::report::DELIVERY_ID_HEADER;
+use crate::webhook::WebhookServer;
+use crate::Context;
+
+/// Response to a delivery sent with [`LiveTestServer::send_webhook`]
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    /// HTTP status of the response
+    pub status: StatusCode,
+    /// Body of the response
+    pub body: Bytes,
+    /// Contexts of the handlers invoked for the delivery, in the order they ran
+    pub invocations: Vec<Context>,
+}
+
+/// A [`WebhookServer`] driven through its HTTP router
+///
+/// Deliveries are sent to the router directly, without binding a port.
+/// Handlers registered with [`LiveTestServer::on`] record their contexts;
+/// send deliveries one at a time to tell them apart.
+pub struct LiveTestServer {
+    server: WebhookServer,
+    invocations: Arc<Mutex<Vec<Context>>>,
+    deliveries: AtomicU64,
+}
+
+impl Default for LiveTestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveTestServer {
+    /// Create a harness around [`WebhookServer::new_default`]
+    pub fn new() -> Self {
+        Self::with_server(WebhookServer::new_default())
+    }
+
+    /// Create a harness around an existing server
+    pub fn with_server(server: WebhookServer) -> Self {
+        Self {
+            server,
+            invocations: Arc::default(),
+            deliveries: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the server, e.g. to change its configuration
+    pub fn server(&self) -> &WebhookServer {
+        &self.server
+    }
+
+    /// Get the server mutably, e.g. to register handlers whose invocations
+    /// are not captured
+    pub fn server_mut(&mut self) -> &mut WebhookServer {
+        &mut self.server
+    }
+
+    /// Get the webhook secret the server currently accepts
+    pub fn secret(&self) -> String {
+        self.server.hmac_config().secret.expose().to_string()
+    }
+
+    /// Register a handler for `event` whose invocations are captured
+    ///
+    /// # Panics
+    ///
+    /// Panics if `event` is not a known webhook event type, like
+    /// [`WebhookServer::on`].
+    #[track_caller]
+    pub fn on<F, Fut, E>(
+        &mut self,
+        event: &str,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = ()>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let source = HandlerSource::of::<F>();
+        let event = match normalize_event_name(event) {
+            Ok(event) => event,
+            Err(e) => panic!("{}", e),
+        };
+        let handler = self.capture(handler);
+        let registration = self.server.register(event, handler, extra, source);
+        async move {
+            registration.await;
+        }
+    }
+
+    /// Register a handler for `event` whose invocations are captured, without
+    /// checking that the event type is known
+    ///
+    /// See [`WebhookServer::on_unchecked`].
+    #[track_caller]
+    pub fn on_unchecked<F, Fut, E>(
+        &mut self,
+        event: &str,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl std::future::Future<Output = ()>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let source = HandlerSource::of::<F>();
+        let event = match normalize_unchecked(event) {
+            Ok(event) => event,
+            Err(e) => panic!("{}", e),
+        };
+        let handler = self.capture(handler);
+        let registration = self.server.register(event, handler, extra, source);
+        async move {
+            registration.await;
+        }
+    }
+
+    /// Wrap `handler` to record the contexts it is invoked with
+    fn capture<F, Fut, E>(
+        &self,
+        handler: F,
+    ) -> impl Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        E: Send + Sync + 'static,
+    {
+        let invocations = self.invocations.clone();
+        move |context: Context, extra: Arc<E>| {
+            invocations.lock().unwrap().push(context.clone());
+            handler(context, extra)
+        }
+    }
+
+    /// Send a delivery of `event_type` with `payload`, signed with `secret`
+    ///
+    /// The request carries the headers GitHub sends: the event type, the
+    /// signature, a unique delivery ID and the JSON content type.
+    pub async fn send_webhook(
+        &self,
+        event_type: &str,
+        payload: &Value,
+        secret: &str,
+    ) -> TestResponse {
+        let body = serde_json::to_vec(payload).expect("payload is not serializable");
+        let delivery = self.deliveries.fetch_add(1, Ordering::SeqCst) + 1;
+        let request = Request::post("/webhook")
+            .header(GITHUB_EVENT_HEADER, event_type)
+            .header(self.server.hmac_config().header_name, sign(secret, &body))
+            .header(DELIVERY_ID_HEADER, format!("test-delivery-{delivery}"))
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .expect("invalid webhook request");
+
+        self.invocations.lock().unwrap().clear();
+        let router = self.server.router().expect("router not initialized");
+        let response = router.oneshot(request).await.expect("router is infallible");
+
+        TestResponse {
+            status: response.status(),
+            body: to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap_or_default(),
+            invocations: std::mem::take(&mut *self.invocations.lock().unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{issues_payload, TEST_INSTALLATION_ID};
+    use octocrab::models::webhook_events::{
+        payload::IssuesWebhookEventAction, WebhookEventPayload,
+    };
+    use serde_json::json;
+
+    async fn server() -> LiveTestServer {
+        let mut server = LiveTestServer::new();
+        server
+            .on("issues", |_context, _extra| async { Ok(()) }, Arc::new(()))
+            .await;
+        server
+            .on(
+                "issues",
+                |context: Context, _extra| async move {
+                    match context.event().as_ref().map(|e| &e.specific) {
+                        Some(WebhookEventPayload::Issues(payload))
+                            if payload.action == IssuesWebhookEventAction::Deleted =>
+                        {
+                            Err(anyhow::anyhow!("cannot handle deletions"))
+                        }
+                        _ => Ok(()),
+                    }
+                },
+                Arc::new(()),
+            )
+            .await;
+        server
+    }
+
+    fn payload(action: &str) -> Value {
+        let mut payload = issues_payload(action, 7);
+        payload["installation"] = json!({ "id": TEST_INSTALLATION_ID, "node_id": "I_1" });
+        payload
+    }
+
+    #[tokio::test]
+    async fn test_valid_delivery_dispatches() {
+        let server = server().await;
+
+        let response = server
+            .send_webhook("issues", &payload("opened"), &server.secret())
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.invocations.len(), 2);
+        assert_eq!(response.invocations[0].kind(), "issues");
+        assert_eq!(
+            response.invocations[0].installation_id(),
+            Some(TEST_INSTALLATION_ID)
+        );
+        assert_eq!(response.invocations[1].require_issue_number().unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_bad_signature_is_rejected_before_dispatch() {
+        let server = server().await;
+
+        let response = server
+            .send_webhook("issues", &payload("opened"), "wrong-secret")
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        assert!(response.invocations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handler_error_returns_500() {
+        let server = server().await;
+
+        let response = server
+            .send_webhook("issues", &payload("deleted"), &server.secret())
+            .await;
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.invocations.len(), 2);
+    }
+}
@@ -0,0 +1,445 @@
+//! Test utilities: a mock GitHub API server and fixture builders
+//!
+//! [`MockGitHub`] serves the app endpoints needed to obtain installation
+//! tokens, plus any extra routes a test adds, and records every request it
+//! receives so tests can assert on outgoing API calls.
+//!
+//! [`live::LiveTestServer`] drives deliveries through the real webhook
+//! router, from signature verification to the response status.
+//!
+//! [`MockWebhookEvent::from_fixture`] builds events from deliveries recorded
+//! with the [`fixtures`] recorder, to turn real traffic into tests.
+//!
+//! This module is available to applications with the `testing` feature:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! octofer = { version = "0.1", features = ["testing"] }
+//! ```
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Path, Request, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use octocrab::models::webhook_events::WebhookEvent;
+use serde_json::{json, Value};
+
+use crate::github::{transport::Transport, GitHubAuth, GitHubClient};
+use crate::Context;
+
+pub mod fixtures;
+pub mod live;
+
+/// RSA private key used to sign app JWTs in tests
+pub const TEST_PRIVATE_KEY: &[u8] = include_bytes!("fixtures/test-private-key.pem");
+
+/// Installation ID served by [`MockGitHub`]
+pub const TEST_INSTALLATION_ID: u64 = 1;
+
+/// Slug of the app of [`MockGitHub::client`]; its bot user is `octofer-test[bot]`
+pub const TEST_APP_SLUG: &str = "octofer-test";
+
+/// A request received by [`MockGitHub`]
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    /// HTTP method
+    pub method: Method,
+    /// Request path
+    pub path: String,
+    /// Query string, if any
+    pub query: Option<String>,
+    /// Request headers
+    pub headers: HeaderMap,
+    /// Request body parsed as JSON (`Null` if empty or not JSON)
+    pub body: Value,
+}
+
+/// A mock GitHub API server listening on a random local port
+pub struct MockGitHub {
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    state: Arc<MockState>,
+}
+
+/// State of the app endpoints served by [`MockGitHub`]
+struct MockState {
+    addr: SocketAddr,
+    /// IDs of the installations listed by `GET /app/installations`
+    installations: Mutex<Vec<u64>>,
+    /// Error response returned for installation token requests, if any
+    token_error: Mutex<Option<(StatusCode, String)>>,
+}
+
+impl MockGitHub {
+    /// Start the mock server with additional `routes`
+    pub async fn start(routes: Router) -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock server");
+        let addr = listener.local_addr().expect("mock server has no address");
+        let state = Arc::new(MockState {
+            addr,
+            installations: Mutex::new(vec![TEST_INSTALLATION_ID]),
+            token_error: Mutex::new(None),
+        });
+
+        let app_routes = Router::new()
+            .route("/app/installations", get(installations))
+            .route(
+                "/app/installations/{id}/access_tokens",
+                post(installation_token),
+            )
+            .with_state(state.clone());
+        let router = app_routes
+            .merge(routes)
+            .layer(middleware::from_fn_with_state(requests.clone(), record));
+
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.ok();
+        });
+
+        Self { requests, state }
+    }
+
+    /// Set the IDs of the installations of the app
+    ///
+    /// Installation [`TEST_INSTALLATION_ID`] gets the token `ghs_test`, any
+    /// other installation `ghs_test_{id}`.
+    pub fn set_installations(&self, ids: &[u64]) {
+        *self.state.installations.lock().unwrap() = ids.to_vec();
+    }
+
+    /// Make installation token requests fail with `status` and `message`
+    pub fn fail_token_requests(&self, status: StatusCode, message: &str) {
+        *self.state.token_error.lock().unwrap() = Some((status, message.to_string()));
+    }
+
+    /// Answer installation token requests with tokens again after
+    /// [`MockGitHub::fail_token_requests`]
+    pub fn accept_token_requests(&self) {
+        *self.state.token_error.lock().unwrap() = None;
+    }
+
+    /// Base URI of the mock server
+    pub fn uri(&self) -> String {
+        format!("http://{}", self.state.addr)
+    }
+
+    /// Requests received so far, excluding the token exchange and lookups
+    /// of the repositories accessible to installations
+    ///
+    /// Installation clients look up their repositories on the first `404`
+    /// or `403`, see [`access`](crate::github::layers::access).
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| !r.path.starts_with("/app/") && r.path != "/installation/repositories")
+            .cloned()
+            .collect()
+    }
+
+    /// Number of installation token requests received so far
+    pub fn token_requests(&self) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.method == Method::POST && r.path.ends_with("/access_tokens"))
+            .count()
+    }
+
+    /// App credentials accepted by the mock server
+    pub fn auth(&self) -> GitHubAuth {
+        GitHubAuth {
+            app_id: 1,
+            private_key: TEST_PRIVATE_KEY.to_vec().into(),
+        }
+    }
+
+    /// Transport targeting the mock server
+    pub fn transport(&self) -> Transport {
+        Transport::new()
+            .unwrap()
+            .with_base_uri(self.uri().parse().unwrap())
+    }
+
+    /// Create a GitHub client talking to the mock server
+    pub fn client(&self) -> GitHubClient {
+        GitHubClient::with_transport(self.auth(), self.transport())
+            .unwrap()
+            .with_app_slug(TEST_APP_SLUG)
+    }
+
+    /// Create a context for a webhook event whose API calls go to the mock server
+    pub fn context(&self, event: &str, payload: Value) -> Context {
+        Context::with_github_client(
+            Some(webhook_event(event, payload)),
+            Some(TEST_INSTALLATION_ID),
+            Some(Arc::new(self.client())),
+        )
+    }
+}
+
+async fn record(
+    State(requests): State<Arc<Mutex<Vec<RecordedRequest>>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = req.into_parts();
+    let bytes = to_bytes(body, usize::MAX).await.unwrap_or_default();
+
+    requests.lock().unwrap().push(RecordedRequest {
+        method: parts.method.clone(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(str::to_string),
+        headers: parts.headers.clone(),
+        body: serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    });
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+async fn installations(State(state): State<Arc<MockState>>) -> Json<Value> {
+    let addr = state.addr;
+    let installations = state
+        .installations
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|id| {
+            json!({
+                "id": id,
+                "account": user(&format!("account-{id}")),
+                "access_tokens_url": format!("http://{addr}/app/installations/{id}/access_tokens"),
+                "permissions": {},
+                "events": [],
+            })
+        })
+        .collect();
+
+    Json(Value::Array(installations))
+}
+
+async fn installation_token(State(state): State<Arc<MockState>>, Path(id): Path<u64>) -> Response {
+    if let Some((status, message)) = state.token_error.lock().unwrap().clone() {
+        return (status, Json(json!({ "message": message }))).into_response();
+    }
+
+    let token = if id == TEST_INSTALLATION_ID {
+        "ghs_test".to_string()
+    } else {
+        format!("ghs_test_{id}")
+    };
+    Json(json!({
+        "token": token,
+        "expires_at": "2099-01-01T00:00:00Z",
+        "permissions": {},
+    }))
+    .into_response()
+}
+
+/// Compute the `X-Hub-Signature-256` header value of a delivery
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    use hmac::Mac;
+
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Parse a webhook event from its name and JSON payload
+pub fn webhook_event(event: &str, payload: Value) -> WebhookEvent {
+    WebhookEvent::try_from_header_and_body(event, &serde_json::to_vec(&payload).unwrap())
+        .expect("invalid webhook fixture")
+}
+
+/// Webhook events built from recorded fixtures
+pub struct MockWebhookEvent;
+
+impl MockWebhookEvent {
+    /// Build the event of the fixture `name`
+    ///
+    /// See [`fixtures::path`] for how `name` is resolved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fixture cannot be loaded or its payload is not
+    /// a valid event.
+    pub fn from_fixture(name: &str) -> anyhow::Result<WebhookEvent> {
+        let path = fixtures::path(name);
+        let delivery = fixtures::load(&path)?;
+        let body = serde_json::to_vec(&delivery.payload)?;
+        WebhookEvent::try_from_header_and_body(&delivery.event, &body)
+            .map_err(|e| anyhow::anyhow!("Invalid event in fixture {}: {}", path.display(), e))
+    }
+}
+
+/// JSON of a GitHub user
+pub fn user(login: &str) -> Value {
+    let url = format!("https://api.github.com/users/{login}");
+    json!({
+        "login": login,
+        "id": 1,
+        "node_id": "U_1",
+        "avatar_url": "https://avatars.githubusercontent.com/u/1",
+        "gravatar_id": "",
+        "url": url,
+        "html_url": format!("https://github.com/{login}"),
+        "followers_url": format!("{url}/followers"),
+        "following_url": format!("{url}/following"),
+        "gists_url": format!("{url}/gists"),
+        "starred_url": format!("{url}/starred"),
+        "subscriptions_url": format!("{url}/subscriptions"),
+        "organizations_url": format!("{url}/orgs"),
+        "repos_url": format!("{url}/repos"),
+        "events_url": format!("{url}/events"),
+        "received_events_url": format!("{url}/received_events"),
+        "type": "User",
+        "site_admin": false,
+    })
+}
+
+/// JSON of a repository `owner/name`
+pub fn repository(owner: &str, name: &str) -> Value {
+    json!({
+        "id": 1,
+        "node_id": "R_1",
+        "name": name,
+        "full_name": format!("{owner}/{name}"),
+        "owner": user(owner),
+        "url": format!("https://api.github.com/repos/{owner}/{name}"),
+        "html_url": format!("https://github.com/{owner}/{name}"),
+        "private": false,
+    })
+}
+
+/// JSON of an organization
+pub fn organization(login: &str) -> Value {
+    let url = format!("https://api.github.com/orgs/{login}");
+    json!({
+        "login": login,
+        "id": 1,
+        "node_id": "O_1",
+        "url": url,
+        "repos_url": format!("{url}/repos"),
+        "events_url": format!("{url}/events"),
+        "hooks_url": format!("{url}/hooks"),
+        "issues_url": format!("{url}/issues"),
+        "members_url": format!("{url}/members{{/member}}"),
+        "public_members_url": format!("{url}/public_members{{/member}}"),
+        "avatar_url": "https://avatars.githubusercontent.com/u/1",
+    })
+}
+
+/// JSON of issue `number` in `owner/name`
+pub fn issue(owner: &str, name: &str, number: u64) -> Value {
+    let url = format!("https://api.github.com/repos/{owner}/{name}/issues/{number}");
+    json!({
+        "id": number,
+        "node_id": format!("I_{number}"),
+        "url": url,
+        "repository_url": format!("https://api.github.com/repos/{owner}/{name}"),
+        "labels_url": format!("{url}/labels{{/name}}"),
+        "comments_url": format!("{url}/comments"),
+        "events_url": format!("{url}/events"),
+        "html_url": format!("https://github.com/{owner}/{name}/issues/{number}"),
+        "number": number,
+        "state": "open",
+        "state_reason": null,
+        "title": format!("Issue {number}"),
+        "body": null,
+        "user": user("reporter"),
+        "labels": [],
+        "assignees": [],
+        "author_association": "NONE",
+        "locked": false,
+        "comments": 0,
+        "created_at": "2024-01-01T00:00:00Z",
+        "updated_at": "2024-01-01T00:00:00Z",
+    })
+}
+
+/// Payload of an `issues` event with `action` for issue `number` in `octofer/app`
+pub fn issues_payload(action: &str, number: u64) -> Value {
+    json!({
+        "action": action,
+        "issue": issue("octofer", "app", number),
+        "repository": repository("octofer", "app"),
+        "sender": user("reporter"),
+    })
+}
+
+/// JSON of pull request `number` in `owner/name` with head commit `head_sha`
+pub fn pull_request(owner: &str, name: &str, number: u64, head_sha: &str) -> Value {
+    json!({
+        "id": number,
+        "node_id": format!("PR_{number}"),
+        "url": format!("https://api.github.com/repos/{owner}/{name}/pulls/{number}"),
+        "html_url": format!("https://github.com/{owner}/{name}/pull/{number}"),
+        "number": number,
+        "state": "open",
+        "title": format!("Pull request {number}"),
+        "body": null,
+        "user": user("contributor"),
+        "head": { "label": format!("{owner}:feature"), "ref": "feature", "sha": head_sha },
+        "base": { "label": format!("{owner}:main"), "ref": "main", "sha": "base000" },
+        "draft": false,
+        "created_at": "2024-01-01T00:00:00Z",
+        "updated_at": "2024-01-01T00:00:00Z",
+    })
+}
+
+/// Payload of a `pull_request` event with `action` for pull request `number`
+/// in `octofer/app`, whose head commit is `head_sha`
+pub fn pull_request_payload(action: &str, number: u64, head_sha: &str) -> Value {
+    json!({
+        "action": action,
+        "number": number,
+        "pull_request": pull_request("octofer", "app", number, head_sha),
+        "repository": repository("octofer", "app"),
+        "sender": user("contributor"),
+    })
+}
+
+/// Payload of a `push` event to `main` in `octofer/app` whose new head is `after`
+pub fn push_payload(after: &str) -> Value {
+    json!({
+        "ref": "refs/heads/main",
+        "before": "0000000000000000000000000000000000000000",
+        "after": after,
+        "created": false,
+        "deleted": false,
+        "forced": false,
+        "compare": format!("https://github.com/octofer/app/compare/{after}"),
+        "commits": [],
+        "head_commit": null,
+        "pusher": { "name": "octocat", "email": "octocat@github.com" },
+        "repository": repository("octofer", "app"),
+        "sender": user("octocat"),
+    })
+}
+
+/// JSON of an issue comment `id` by `login`
+pub fn comment(id: u64, login: &str, body: &str) -> Value {
+    json!({
+        "id": id,
+        "node_id": format!("IC_{id}"),
+        "url": format!("https://api.github.com/repos/octofer/app/issues/comments/{id}"),
+        "html_url": format!("https://github.com/octofer/app/issues/1#issuecomment-{id}"),
+        "body": body,
+        "author_association": "NONE",
+        "user": user(login),
+        "created_at": "2024-01-01T00:00:00Z",
+    })
+}
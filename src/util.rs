@@ -0,0 +1,315 @@
+//! Utilities for handlers
+//!
+//! [`canonical_json`] serializes JSON values deterministically, e.g. to hash
+//! or compare payloads. [`Context::idempotency_key`] and
+//! [`Context::delivery_scoped_key`] build on it to derive stable keys of
+//! deliveries, for handlers remembering what they already processed.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use octofer::util::canonical_json;
+//! use serde_json::json;
+//!
+//! let a = json!({ "b": 1.0, "a": [true, null] });
+//! let b = json!({ "a": [true, null], "b": 1 });
+//! assert_eq!(canonical_json(&a), r#"{"a":[true,null],"b":1}"#);
+//! assert_eq!(canonical_json(&a), canonical_json(&b));
+//! ```
+
+use std::borrow::Cow;
+
+use serde_json::{Map, Number, Value};
+use sha2::{Digest, Sha256};
+
+use crate::Context;
+
+/// Serialize `value` as canonical JSON
+///
+/// The output has no whitespace, object keys sorted by their UTF-8 bytes, and
+/// normalized numbers: integral floats such as `1.0` or `1e3` are written as
+/// integers, `-0.0` as `0`, and other floats in their shortest form. Equal
+/// values thus serialize identically however their source was formatted.
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) | Value::String(_) => {
+            out.push_str(&value.to_string());
+        }
+        Value::Number(number) => out.push_str(&normalize_number(number).to_string()),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Write integral floats exactly representable as integers as integers
+fn normalize_number(number: &Number) -> Number {
+    match number.as_f64() {
+        Some(float) if number.is_f64() && float.fract() == 0.0 => {
+            // 2^63 and above do not fit an i64, and lie beyond the integers
+            // floats represent exactly anyway
+            if float.abs() < 9_223_372_036_854_775_808.0 {
+                Number::from(float as i64)
+            } else {
+                number.clone()
+            }
+        }
+        _ => number.clone(),
+    }
+}
+
+/// SHA-256 hex digest of the canonical JSON of `value`
+fn digest(value: &Value) -> String {
+    hex::encode(Sha256::digest(canonical_json(value).as_bytes()))
+}
+
+impl Context {
+    /// Compute a stable key of the delivery from the payload fields at the
+    /// JSON pointers `fields` (e.g. `/issue/number`, `/action`)
+    ///
+    /// Pointers address the webhook payload as GitHub sent it, kept in
+    /// [`Context::raw_payload`], including the fields octocrab does not
+    /// model. Missing fields count as `null`, and the order of `fields` does
+    /// not matter. The key is the SHA-256 hex digest of the
+    /// [`canonical_json`] of the fields, so the same logical payload yields
+    /// the same key across deliveries, processes and releases of Octofer.
+    ///
+    /// Contexts not parsed from a delivery, e.g. those of a
+    /// [backfill](crate::backfill), have no raw payload; their key is
+    /// computed from the payload octocrab parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::Context;
+    ///
+    /// # fn example(context: Context, processed: &mut std::collections::HashSet<String>) {
+    /// let key = context.idempotency_key(&["/repository/id", "/issue/number", "/action"]);
+    /// if !processed.insert(key) {
+    ///     return; // Already handled
+    /// }
+    /// # }
+    /// ```
+    pub fn idempotency_key(&self, fields: &[&str]) -> String {
+        let payload = self.webhook_payload();
+        let fields: Map<String, Value> = fields
+            .iter()
+            .map(|field| {
+                let value = payload.pointer(field).cloned().unwrap_or(Value::Null);
+                (field.to_string(), value)
+            })
+            .collect();
+        digest(&Value::Object(fields))
+    }
+
+    /// Compute a key of the delivery from its ID and whole payload
+    ///
+    /// Redeliveries of a delivery keep its ID and payload, and thus its key.
+    /// The payload is read like in [`Context::idempotency_key`].
+    /// Returns `None` if GitHub did not send a delivery ID.
+    pub fn delivery_scoped_key(&self) -> Option<String> {
+        let delivery_id = self.delivery_id()?;
+        Some(format!(
+            "{}:{}",
+            delivery_id,
+            digest(&self.webhook_payload())
+        ))
+    }
+
+    /// Get the payload as GitHub sent it, or else as parsed by octocrab with
+    /// its event-specific fields at the top level
+    ///
+    /// [`Context::payload`] nests them under the name of the payload type.
+    fn webhook_payload(&self) -> Cow<'_, Value> {
+        if let Some(payload) = &self.raw_payload {
+            return Cow::Borrowed(payload);
+        }
+        let Some(event) = &self.event else {
+            return Cow::Owned(Value::Null);
+        };
+        let Ok(Value::Object(mut payload)) = serde_json::to_value(event) else {
+            return Cow::Owned(Value::Null);
+        };
+        let specific = serde_json::to_value(&event.specific).ok();
+        if let Some(Value::Object(wrapper)) = specific {
+            for (name, fields) in wrapper {
+                payload.remove(&name);
+                if let Value::Object(fields) = fields {
+                    for (key, value) in fields {
+                        payload.entry(key).or_insert(value);
+                    }
+                }
+            }
+        }
+        Cow::Owned(Value::Object(payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{repository, user, webhook_event};
+    use serde_json::json;
+
+    const DELIVERY: &str = "72d3162e-cc78-11e3-81ab-4c9367dc0958";
+
+    fn context(kind: &str, payload: &str) -> Context {
+        let event = webhook_event(kind, serde_json::from_str(payload).unwrap());
+        let mut context = Context::new(Some(event), None);
+        context.delivery_id = Some(DELIVERY.to_string());
+        context
+    }
+
+    fn alert(alert: &str) -> String {
+        format!(
+            r#"{{"action": "created", "alert": {}, "repository": {}, "sender": {}}}"#,
+            alert,
+            repository("octofer", "app"),
+            user("octocat")
+        )
+    }
+
+    const ALERT: &str =
+        r#"{"number": 3, "cvss": {"score": 9.0, "vector": "AV:N"}, "ids": [1, 2.5]}"#;
+
+    const ALERT_SHUFFLED: &str =
+        r#"{"ids": [1.0, 25e-1], "cvss": {"vector": "AV:N", "score": 9}, "number": 3.0}"#;
+
+    #[test]
+    fn test_canonical_json() {
+        let value: Value = serde_json::from_str(
+            r#"{"z": {"b": 2, "a": [1.0, -0.0, 1e3, 0.5]}, "ä": "é\n", "a": null}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            canonical_json(&value),
+            r#"{"a":null,"z":{"a":[1,0,1000,0.5],"b":2},"ä":"é\n"}"#
+        );
+        assert_eq!(canonical_json(&json!(1.0e20)), "1e+20");
+        assert_eq!(canonical_json(&json!(-3.0)), "-3");
+    }
+
+    #[test]
+    fn test_idempotency_key_is_stable() {
+        let original = context("dependabot_alert", &alert(ALERT));
+        let shuffled = context("dependabot_alert", &alert(ALERT_SHUFFLED));
+
+        let fields = [
+            "/alert/number",
+            "/alert/cvss",
+            "/repository/full_name",
+            "/missing",
+        ];
+        let key = original.idempotency_key(&fields);
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, shuffled.idempotency_key(&fields));
+        assert_eq!(
+            key,
+            original.idempotency_key(&[
+                "/missing",
+                "/repository/full_name",
+                "/alert/cvss",
+                "/alert/number"
+            ])
+        );
+        // Keys depend on nothing but the fields, so they are the same across
+        // runs
+        assert_eq!(
+            key,
+            hex::encode(Sha256::digest(
+                concat!(
+                    r#"{"/alert/cvss":{"score":9,"vector":"AV:N"},"/alert/number":3,"#,
+                    r#""/missing":null,"/repository/full_name":"octofer/app"}"#
+                )
+                .as_bytes()
+            ))
+        );
+        assert_ne!(key, original.idempotency_key(&["/action"]));
+
+        assert_eq!(
+            original.idempotency_key(&["/alert/ids"]),
+            shuffled.idempotency_key(&["/alert/ids"])
+        );
+        assert_eq!(
+            original.delivery_scoped_key(),
+            shuffled.delivery_scoped_key()
+        );
+        assert!(original
+            .delivery_scoped_key()
+            .unwrap()
+            .starts_with(&format!("{DELIVERY}:")));
+        assert_eq!(Context::default().delivery_scoped_key(), None);
+    }
+
+    #[test]
+    fn test_keys_of_fields_octocrab_does_not_model() {
+        use crate::dispatch::Dispatcher;
+        use crate::github::middlewares::GITHUB_EVENT_HEADER;
+        use crate::webhook::report::DELIVERY_ID_HEADER;
+        use axum::http::HeaderMap;
+
+        let dispatcher = Dispatcher::new(None);
+        let parse = |issue_type: &str| {
+            let mut payload = crate::testing::issues_payload("opened", 7);
+            payload["issue"]["type"] = json!({ "id": 1, "name": issue_type });
+            let mut headers = HeaderMap::new();
+            headers.insert(GITHUB_EVENT_HEADER, "issues".parse().unwrap());
+            headers.insert(DELIVERY_ID_HEADER, DELIVERY.parse().unwrap());
+            dispatcher
+                .parse(&headers, &serde_json::to_vec(&payload).unwrap())
+                .unwrap()
+        };
+        let (bug, feature) = (parse("Bug"), parse("Feature"));
+
+        assert_eq!(
+            bug.idempotency_key(&["/issue/type/name"]),
+            digest(&json!({ "/issue/type/name": "Bug" }))
+        );
+        assert_ne!(
+            bug.idempotency_key(&["/issue/type/name"]),
+            feature.idempotency_key(&["/issue/type/name"])
+        );
+        assert_ne!(bug.delivery_scoped_key(), feature.delivery_scoped_key());
+        assert_eq!(
+            bug.idempotency_key(&["/issue/number"]),
+            feature.idempotency_key(&["/issue/number"])
+        );
+    }
+
+    #[test]
+    fn test_idempotency_key_of_typed_payloads() {
+        let payload = crate::testing::issues_payload("opened", 7);
+        let context = context("issues", &payload.to_string());
+        assert_eq!(
+            context.idempotency_key(&["/issue/number", "/action"]),
+            digest(&json!({ "/issue/number": 7, "/action": "opened" }))
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Acknowledging slow deliveries before all handlers completed
+//!
+//! GitHub considers a delivery failed if the response takes longer than 10
+//! seconds. With an acknowledgement deadline set, see
+//! [`WebhookConfig::ack_deadline_ms`](crate::config::WebhookConfig::ack_deadline_ms),
+//! handlers still run inline, but once the deadline passes the server
+//! responds with `202 Accepted` and lets the remaining handlers finish in a
+//! background task:
+//!
+//! - handlers run one after the other as usual, the handler running at the
+//!   deadline is not interrupted;
+//! - failures of handlers completing after the deadline cannot change the
+//!   response anymore; they are logged and reported;
+//! - the [`DeliveryReport`](crate::webhook::report::DeliveryReport) of the
+//!   delivery is built, archived and passed to the completion hooks once all
+//!   handlers completed. Its
+//!   [`acknowledged_handlers`](crate::webhook::report::DeliveryReport::acknowledged_handlers)
+//!   tells how many of them had completed before the response;
+//! - the background task runs in the request's tracing span, so its logs are
+//!   attributed to the delivery.
+//!
+//! A deadline of 8 seconds leaves room for network latency. Deliveries
+//! acknowledged with `202` count as successful for GitHub, so they are not
+//! redelivered automatically and their handlers do not run twice; a manual
+//! redelivery from the app's settings runs all handlers again.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span};
+
+use crate::dispatch::{DispatchReport, Dispatcher};
+use crate::Context;
+
+/// Deadline after which deliveries are acknowledged, shared by the routes of
+/// a server
+///
+/// Clones share the deadline. Disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct AckDeadline {
+    millis: Arc<AtomicU64>,
+}
+
+impl AckDeadline {
+    /// Get the deadline, `None` if deliveries are acknowledged only once all
+    /// handlers completed
+    pub fn get(&self) -> Option<Duration> {
+        match self.millis.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    /// Set the deadline, or disable it with `None`
+    pub fn set(&self, deadline: Option<Duration>) {
+        let millis = deadline.map_or(0, |d| d.as_millis().max(1) as u64);
+        self.millis.store(millis, Ordering::Relaxed);
+    }
+}
+
+/// Outcome of dispatching a delivery against a deadline
+pub(crate) enum Dispatched {
+    /// All handlers completed before the deadline
+    Completed(Box<DispatchReport>, Result<()>),
+    /// The deadline passed after `completed` handlers; the rest run in
+    /// `remainder`
+    Deferred {
+        completed: usize,
+        remainder: JoinHandle<(DispatchReport, Result<()>)>,
+    },
+}
+
+/// Dispatch `context`, giving up waiting for the handlers after `deadline`
+pub(crate) async fn dispatch(
+    dispatcher: &Dispatcher,
+    context: Context,
+    deadline: Duration,
+) -> Dispatched {
+    let completed = Arc::new(AtomicUsize::new(0));
+    let mut task = tokio::spawn({
+        let dispatcher = dispatcher.clone();
+        let completed = completed.clone();
+        async move { dispatcher.dispatch_tracked(context, &completed).await }
+            .instrument(Span::current())
+    });
+
+    tokio::select! {
+        joined = &mut task => {
+            let (report, result) = joined_outcome(joined);
+            Dispatched::Completed(Box::new(report), result)
+        }
+        _ = tokio::time::sleep(deadline) => Dispatched::Deferred {
+            completed: completed.load(Ordering::SeqCst),
+            remainder: task,
+        },
+    }
+}
+
+/// Get the outcome of a dispatch task, failing it if the task panicked
+pub(crate) fn joined_outcome(
+    joined: Result<(DispatchReport, Result<()>), tokio::task::JoinError>,
+) -> (DispatchReport, Result<()>) {
+    joined.unwrap_or_else(|e| {
+        (
+            DispatchReport::default(),
+            Err(anyhow!("Dispatch task failed: {}", e)),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_is_disabled_by_default() {
+        let deadline = AckDeadline::default();
+        assert_eq!(deadline.get(), None);
+
+        deadline.clone().set(Some(Duration::from_millis(8_000)));
+        assert_eq!(deadline.get(), Some(Duration::from_secs(8)));
+        deadline.set(None);
+        assert_eq!(deadline.get(), None);
+    }
+}
@@ -0,0 +1,467 @@
+//! Detection of drift between the app's webhook and its handlers
+//!
+//! The events a GitHub App receives are configured on GitHub, separately from
+//! the handlers the app registers, so the two can drift apart: a handler for
+//! `discussion` never runs if the app is not subscribed to discussion events.
+//! The hook's settings are observed in two ways:
+//!
+//! - every `ping` delivery carries the hook's settings, which are compared
+//!   with the registered handlers and logged as warnings on drift;
+//! - [`WebhookServer::check_subscription_drift`](crate::webhook::WebhookServer::check_subscription_drift)
+//!   requests them from the API (`GET /app/hook/config` and `GET /app`).
+//!
+//! Settings requested from the API also carry the app's permissions, which
+//! are compared with the permissions the handlers need, see the
+//! [`manifest`](crate::manifest) module.
+//!
+//! The latest observation is cached, and the drift against it is part of the
+//! [registration summary](crate::webhook::info). There is no built-in
+//! schedule; to check periodically, call `check_subscription_drift` from your
+//! own timer, e.g. with [`tokio::time::interval`].
+//!
+//! # Examples
+//!
+//! Asserting in CI that the app is subscribed to everything it handles:
+//!
+//! ```rust,no_run
+//! use octofer::{Config, Octofer};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut app = Octofer::new(Config::from_env()?).await?;
+//! // ... register handlers
+//! let drift = app.check_subscription_drift().await?;
+//! assert!(drift.is_empty(), "{:?}", drift.warnings());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::dispatch::Dispatcher;
+use crate::github::{self, GitHubClient};
+use crate::helpers::permissions::Permission;
+use crate::manifest::{AppManifestRequirements, MissingAppPermission};
+use crate::webhook::WebhookEventKind;
+
+/// Content type Octofer expects deliveries in
+pub const EXPECTED_CONTENT_TYPE: &str = "json";
+
+/// Events GitHub delivers to every app, without a subscription
+pub const ALWAYS_DELIVERED_EVENTS: &[&str] = &[
+    "github_app_authorization",
+    "installation",
+    "installation_repositories",
+    "installation_target",
+    "meta",
+    "ping",
+];
+
+/// Where [`HookSettings`] were observed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookSource {
+    /// The `hook` object of a `ping` delivery
+    Ping,
+    /// The app's hook configuration, requested from the API
+    Api,
+}
+
+/// Settings of the app's webhook, as configured on GitHub
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HookSettings {
+    /// Where the settings were observed
+    pub source: HookSource,
+    /// Content type of deliveries (`json` or `form`)
+    pub content_type: Option<String>,
+    /// Whether deliveries are signed with a secret
+    pub secret_configured: bool,
+    /// Subscribed events, sorted; `*` subscribes to all events
+    pub events: Vec<String>,
+    /// Permissions the app requests (`None` for `ping` deliveries, which do
+    /// not carry them)
+    pub permissions: Option<BTreeMap<String, Permission>>,
+}
+
+/// The `hook` object of a `ping` payload
+#[derive(Deserialize)]
+struct PingHook {
+    #[serde(default)]
+    events: Vec<String>,
+    #[serde(default)]
+    config: RawHookConfig,
+}
+
+/// Configuration of a hook, as in ping payloads and `GET /app/hook/config`
+#[derive(Default, Deserialize)]
+struct RawHookConfig {
+    content_type: Option<String>,
+    secret: Option<String>,
+}
+
+impl HookSettings {
+    /// Parse the hook settings of a `ping` payload
+    ///
+    /// Returns `None` if the payload has no `hook` object.
+    pub fn from_ping(payload: &Value) -> Option<Self> {
+        let hook: PingHook = serde_json::from_value(payload.get("hook")?.clone())
+            .map_err(|e| debug!("Failed to parse ping hook: {}", e))
+            .ok()?;
+        Some(Self::new(HookSource::Ping, hook.config, hook.events, None))
+    }
+
+    fn new(
+        source: HookSource,
+        config: RawHookConfig,
+        mut events: Vec<String>,
+        permissions: Option<BTreeMap<String, Permission>>,
+    ) -> Self {
+        events.sort();
+        events.dedup();
+        Self {
+            source,
+            content_type: config.content_type,
+            secret_configured: config.secret.is_some_and(|secret| !secret.is_empty()),
+            events,
+            permissions,
+        }
+    }
+}
+
+/// Differences between the app's webhook and its registered handlers
+///
+/// Obtained from [`WebhookServer::check_subscription_drift`](crate::webhook::WebhookServer::check_subscription_drift).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubscriptionDrift {
+    /// Where the compared hook settings were observed
+    pub source: HookSource,
+    /// Event types with handlers that the app is not subscribed to
+    pub unsubscribed: Vec<WebhookEventKind>,
+    /// Subscribed events without handlers
+    pub unhandled: Vec<String>,
+    /// Content type of deliveries, if it is not [`EXPECTED_CONTENT_TYPE`]
+    pub content_type: Option<String>,
+    /// Whether deliveries are sent without a secret, and so cannot be verified
+    pub secret_missing: bool,
+    /// Permissions the handlers need that the app does not request (only
+    /// compared when the settings carry the app's permissions)
+    pub missing_permissions: Vec<MissingAppPermission>,
+}
+
+impl SubscriptionDrift {
+    /// Compare hook settings with the event types that have handlers, and
+    /// the app's permissions with the ones they need
+    pub fn compare(
+        settings: &HookSettings,
+        handled: &[WebhookEventKind],
+        required: &AppManifestRequirements,
+    ) -> Self {
+        let all_events = settings.events.iter().any(|event| event == "*");
+        let unsubscribed = handled
+            .iter()
+            .filter(|event| !ALWAYS_DELIVERED_EVENTS.contains(&event.as_str()))
+            .filter(|event| !all_events && !settings.events.iter().any(|e| e == *event))
+            .cloned()
+            .collect();
+        let unhandled = settings
+            .events
+            .iter()
+            .filter(|event| *event != "*" && !handled.iter().any(|e| e == *event))
+            .cloned()
+            .collect();
+
+        Self {
+            source: settings.source,
+            unsubscribed,
+            unhandled,
+            content_type: settings
+                .content_type
+                .clone()
+                .filter(|content_type| content_type != EXPECTED_CONTENT_TYPE),
+            secret_missing: !settings.secret_configured,
+            missing_permissions: settings
+                .permissions
+                .as_ref()
+                .map(|granted| required.missing_permissions(granted))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Whether the hook matches the registered handlers
+    pub fn is_empty(&self) -> bool {
+        self.unsubscribed.is_empty()
+            && self.unhandled.is_empty()
+            && self.content_type.is_none()
+            && !self.secret_missing
+            && self.missing_permissions.is_empty()
+    }
+
+    /// Describe the drift, one message per difference
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings: Vec<String> = self
+            .unsubscribed
+            .iter()
+            .map(|event| {
+                format!(
+                    "handlers registered for {} but the app is not subscribed to {} events",
+                    event, event
+                )
+            })
+            .collect();
+        warnings.extend(self.unhandled.iter().map(|event| {
+            format!(
+                "subscribed to {} events but no handler is registered",
+                event
+            )
+        }));
+        if let Some(content_type) = &self.content_type {
+            warnings.push(format!(
+                "deliveries are sent as '{}' but Octofer expects '{}'",
+                content_type, EXPECTED_CONTENT_TYPE
+            ));
+        }
+        if self.secret_missing {
+            warnings
+                .push("the webhook has no secret, so deliveries cannot be verified".to_string());
+        }
+        warnings.extend(self.missing_permissions.iter().map(|missing| {
+            format!(
+                "handlers need '{}: {}' but the app requests {}",
+                missing.permission,
+                missing.required,
+                match missing.granted {
+                    Some(granted) => format!("'{}'", granted),
+                    None => "none".to_string(),
+                }
+            )
+        }));
+        warnings
+    }
+
+    fn log(&self) {
+        for warning in self.warnings() {
+            warn!("Webhook configuration drift: {}", warning);
+        }
+    }
+}
+
+/// Latest observed settings of the app's webhook
+#[derive(Debug, Clone, Default)]
+pub struct HookMonitor {
+    settings: Arc<Mutex<Option<HookSettings>>>,
+}
+
+impl HookMonitor {
+    /// Get the latest observed hook settings
+    pub fn settings(&self) -> Option<HookSettings> {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Compare the latest observed hook settings with the registered handlers
+    pub(crate) async fn drift(&self, dispatcher: &Dispatcher) -> Option<SubscriptionDrift> {
+        let settings = self.settings()?;
+        Some(SubscriptionDrift::compare(
+            &settings,
+            &handled_events(dispatcher).await,
+            &dispatcher.required_manifest().await,
+        ))
+    }
+
+    /// Cache the hook settings of a `ping` delivery and log any drift
+    pub(crate) async fn observe_ping(&self, dispatcher: &Dispatcher, body: &[u8]) {
+        let Some(settings) = serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|payload| HookSettings::from_ping(&payload))
+        else {
+            return;
+        };
+        *self.settings.lock().unwrap() = Some(settings);
+        if let Some(drift) = self.drift(dispatcher).await {
+            drift.log();
+        }
+    }
+
+    /// Compare the app's webhook with the registered handlers
+    ///
+    /// The settings are requested from the API if a GitHub client is
+    /// available, and otherwise taken from the latest `ping` delivery.
+    pub(crate) async fn check(&self, dispatcher: &Dispatcher) -> Result<SubscriptionDrift> {
+        if let Some(client) = dispatcher.github_client() {
+            let settings = client.hook_settings().await?;
+            *self.settings.lock().unwrap() = Some(settings);
+        }
+        let drift = self.drift(dispatcher).await.ok_or_else(|| {
+            anyhow!("No GitHub client is available and no ping delivery was received yet")
+        })?;
+        drift.log();
+        Ok(drift)
+    }
+}
+
+async fn handled_events(dispatcher: &Dispatcher) -> Vec<WebhookEventKind> {
+    dispatcher
+        .handler_names()
+        .await
+        .into_iter()
+        .map(|(event, _)| event)
+        .collect()
+}
+
+impl GitHubClient {
+    /// Get the settings of the app's webhook
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails.
+    pub async fn hook_settings(&self) -> Result<HookSettings, github::Error> {
+        let config: RawHookConfig = self
+            .app_client()
+            .get("/app/hook/config", None::<&()>)
+            .await?;
+        let app: Value = self.app_client().get("/app", None::<&()>).await?;
+        let events = app["events"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|event| event.as_str().map(str::to_string))
+            .collect();
+        let permissions = app["permissions"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, level)| {
+                Some((name.clone(), Permission::from_name(level.as_str()?)?))
+            })
+            .collect();
+        Ok(HookSettings::new(
+            HookSource::Api,
+            config,
+            events,
+            Some(permissions),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockGitHub;
+    use axum::{routing::get, Json, Router};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn handled() -> Vec<WebhookEventKind> {
+        ["discussion", "issues", "ping"]
+            .map(WebhookEventKind::from)
+            .to_vec()
+    }
+
+    #[test]
+    fn test_compare_ping_hook_with_handlers() {
+        let payload = json!({
+            "zen": "Keep it logically awesome.",
+            "hook_id": 1,
+            "hook": {
+                "type": "App",
+                "events": ["issues", "pull_request"],
+                "config": { "content_type": "form", "url": "https://example.com/webhook" },
+            },
+        });
+        let settings = HookSettings::from_ping(&payload).unwrap();
+        assert_eq!(settings.source, HookSource::Ping);
+        assert!(!settings.secret_configured);
+
+        let required = AppManifestRequirements::default();
+        let drift = SubscriptionDrift::compare(&settings, &handled(), &required);
+        assert_eq!(drift.unsubscribed, ["discussion"]);
+        assert_eq!(drift.unhandled, ["pull_request"]);
+        assert_eq!(drift.content_type.as_deref(), Some("form"));
+        assert!(drift.secret_missing);
+        assert_eq!(
+            drift.warnings()[0],
+            "handlers registered for discussion but the app is not subscribed to discussion events"
+        );
+        assert_eq!(drift.warnings().len(), 4);
+
+        let settings = HookSettings {
+            source: HookSource::Ping,
+            content_type: Some("json".to_string()),
+            secret_configured: true,
+            events: vec!["*".to_string()],
+            permissions: None,
+        };
+        assert!(SubscriptionDrift::compare(&settings, &handled(), &required).is_empty());
+        assert!(HookSettings::from_ping(&json!({ "zen": "Design for failure." })).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_check_fetches_hook_settings() {
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/app/hook/config",
+                    get(|| async {
+                        Json(json!({
+                            "content_type": "json",
+                            "insecure_ssl": "0",
+                            "secret": "********",
+                            "url": "https://example.com/webhook",
+                        }))
+                    }),
+                )
+                .route(
+                    "/app",
+                    get(|| async {
+                        Json(json!({
+                            "slug": "my-bot",
+                            "events": ["issues", "discussion"],
+                            "permissions": {
+                                "discussions": "read",
+                                "issues": "write",
+                                "metadata": "read",
+                            },
+                        }))
+                    }),
+                ),
+        )
+        .await;
+        let dispatcher = Dispatcher::new(Some(Arc::new(mock.client())));
+        for event in handled() {
+            dispatcher
+                .on(event, |_context, _extra| async { Ok(()) }, Arc::new(()))
+                .await;
+        }
+
+        let monitor = HookMonitor::default();
+        let drift = monitor.check(&dispatcher).await.unwrap();
+        assert_eq!(drift.source, HookSource::Api);
+        assert!(drift.is_empty(), "{:?}", drift);
+        assert_eq!(monitor.settings().unwrap().events, ["discussion", "issues"]);
+
+        dispatcher
+            .on("issues", |_context, _extra| async { Ok(()) }, Arc::new(()))
+            .await
+            .requires_permissions(&["upsert_comment", "create_check_run"]);
+        let drift = monitor.check(&dispatcher).await.unwrap();
+        assert_eq!(
+            drift.missing_permissions,
+            [MissingAppPermission {
+                permission: "checks".to_string(),
+                required: Permission::Write,
+                granted: None,
+            }]
+        );
+        assert_eq!(
+            drift.warnings(),
+            ["handlers need 'checks: write' but the app requests none"]
+        );
+
+        let without_github = Dispatcher::new(None);
+        assert!(HookMonitor::default().check(&without_github).await.is_err());
+    }
+}
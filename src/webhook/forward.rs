@@ -0,0 +1,473 @@
+//! Forwarding of webhook deliveries to downstream services
+//!
+//! Deliveries of selected events can be forwarded to internal services once
+//! the app's handlers processed them successfully, with
+//! [`Octofer::forward_events`](crate::Octofer::forward_events). The original
+//! payload is re-POSTed to each target with:
+//!
+//! - an [`FORWARD_SIGNATURE_HEADER`] computed from the payload with the
+//!   target's secret, in the `sha256=<hex>` format GitHub uses, so the target
+//!   can verify the delivery came from this app;
+//! - the `X-GitHub-*` headers of the delivery, renamed to
+//!   `X-Forwarded-GitHub-*` (e.g. `X-Forwarded-GitHub-Event`).
+//!
+//! Forwarding never fails or delays the response to GitHub. It runs in a
+//! background task after the response status is known; requests that fail
+//! with a connection error, a timeout, `429` or a `5xx` status are retried up
+//! to [`WebhookConfig::forward_retries`](crate::config::WebhookConfig::forward_retries)
+//! times with exponential backoff. The outcome of every target is logged and
+//! passed to [delivery hooks](crate::webhook::report) in
+//! [`DeliveryReport::forwards`](crate::webhook::report::DeliveryReport::forwards);
+//! the hooks of forwarded deliveries run once forwarding finished.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::Octofer;
+//!
+//! # fn example(app: Octofer) -> anyhow::Result<()> {
+//! app.forward_events(
+//!     &["push", "release"],
+//!     "https://deployer.internal/github",
+//!     "forwarding-secret",
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Verifying a forwarded delivery in the receiving service:
+//!
+//! ```rust
+//! use octofer::webhook::forward::sign_payload;
+//!
+//! fn is_authentic(signature: &str, body: &[u8]) -> bool {
+//!     // Use a constant-time comparison, e.g. `hmac::Mac::verify_slice`, in production
+//!     signature == sign_payload(body, "forwarding-secret")
+//! }
+//! ```
+
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use axum::body::Bytes;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode, Uri};
+use hmac::Mac;
+use http_body_util::Full;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::TokioExecutor;
+use tracing::{debug, info, warn};
+
+use crate::config::{DEFAULT_FORWARD_BACKOFF_MS, DEFAULT_FORWARD_RETRIES};
+use crate::dispatch::normalize_event_name;
+use crate::github::transport::DEFAULT_USER_AGENT;
+use crate::secrets::Secret;
+use crate::webhook::WebhookEventKind;
+
+/// Header carrying the signature of a forwarded payload
+pub const FORWARD_SIGNATURE_HEADER: &str = "X-Octofer-Signature-256";
+
+/// Prefix replacing `X-GitHub-` in the names of forwarded GitHub headers
+pub const FORWARDED_HEADER_PREFIX: &str = "X-Forwarded-GitHub-";
+
+/// Time a single forwarding attempt may take
+pub const FORWARD_TIMEOUT: Duration = Duration::from_secs(10);
+
+const GITHUB_HEADER_PREFIX: &str = "x-github-";
+
+type HttpClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// Compute the [`FORWARD_SIGNATURE_HEADER`] of `body` with `secret`
+///
+/// Returns `sha256=` followed by the hex-encoded HMAC-SHA256 of the body.
+pub fn sign_payload(body: &[u8], secret: &str) -> String {
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// A service deliveries of some events are forwarded to
+#[derive(Debug, Clone)]
+pub struct ForwardTarget {
+    /// Events forwarded to the target
+    pub events: Vec<WebhookEventKind>,
+    /// URL the deliveries are POSTed to
+    pub url: Uri,
+    secret: Secret,
+}
+
+impl ForwardTarget {
+    /// Forward deliveries of `events` to `url`, signed with `secret`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an event name is invalid, `url` is not an absolute
+    /// `http` or `https` URL, or `secret` is empty.
+    pub fn new(events: &[&str], url: &str, secret: &str) -> Result<Self> {
+        let events = events
+            .iter()
+            .map(|event| normalize_event_name(event))
+            .collect::<Result<Vec<_>, _>>()?;
+        let url: Uri = url
+            .parse()
+            .map_err(|e| anyhow!("Invalid forwarding URL {}: {}", url, e))?;
+        if !matches!(url.scheme_str(), Some("http" | "https")) || url.host().is_none() {
+            return Err(anyhow!("Forwarding URL must be an http(s) URL: {}", url));
+        }
+        if secret.is_empty() {
+            return Err(anyhow!("Forwarding secret must not be empty"));
+        }
+        Ok(Self {
+            events,
+            url,
+            secret: secret.into(),
+        })
+    }
+
+    /// Check whether deliveries of `event` are forwarded to the target
+    pub fn forwards(&self, event: &str) -> bool {
+        self.events.iter().any(|e| e == event)
+    }
+}
+
+/// Outcome of forwarding a delivery to one target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardResult {
+    /// URL of the target
+    pub target: String,
+    /// Number of requests sent
+    pub attempts: u32,
+    /// Status returned by the target to the last request, if any
+    pub status: Option<StatusCode>,
+    /// Error of the last attempt, if forwarding failed
+    pub error: Option<String>,
+}
+
+impl ForwardResult {
+    /// Check whether the target accepted the delivery
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// How often and how fast failed forwarding requests are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardRetry {
+    /// Retries after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub backoff: Duration,
+}
+
+impl Default for ForwardRetry {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_FORWARD_RETRIES,
+            backoff: Duration::from_millis(DEFAULT_FORWARD_BACKOFF_MS),
+        }
+    }
+}
+
+/// Targets deliveries are forwarded to
+///
+/// Cloning is cheap; clones share the same targets.
+#[derive(Clone, Default)]
+pub struct Forwarder {
+    targets: Arc<RwLock<Vec<Arc<ForwardTarget>>>>,
+    retry: Arc<RwLock<ForwardRetry>>,
+    client: Arc<OnceLock<Result<HttpClient, String>>>,
+}
+
+impl Forwarder {
+    /// Add a target
+    pub fn add(&self, target: ForwardTarget) {
+        info!(
+            "Forwarding {:?} deliveries to {}",
+            target.events, target.url
+        );
+        self.targets
+            .write()
+            .expect("forwarding targets lock poisoned")
+            .push(Arc::new(target));
+    }
+
+    /// Set how failed requests are retried
+    pub fn set_retry(&self, retry: ForwardRetry) {
+        *self.retry.write().expect("forwarding retry lock poisoned") = retry;
+    }
+
+    /// Get the targets deliveries of `event` are forwarded to
+    pub fn targets_for(&self, event: &str) -> Vec<Arc<ForwardTarget>> {
+        self.targets
+            .read()
+            .expect("forwarding targets lock poisoned")
+            .iter()
+            .filter(|target| target.forwards(event))
+            .cloned()
+            .collect()
+    }
+
+    /// Forward the delivery with `headers` and `body` to each of `targets`
+    ///
+    /// Targets are forwarded to concurrently. Returns their outcomes, in the
+    /// order of `targets`.
+    pub async fn forward(
+        &self,
+        targets: &[Arc<ForwardTarget>],
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Vec<ForwardResult> {
+        let retry = *self.retry.read().expect("forwarding retry lock poisoned");
+        futures::future::join_all(
+            targets
+                .iter()
+                .map(|target| self.forward_to(target, headers, body, retry)),
+        )
+        .await
+    }
+
+    async fn forward_to(
+        &self,
+        target: &ForwardTarget,
+        headers: &HeaderMap,
+        body: &Bytes,
+        retry: ForwardRetry,
+    ) -> ForwardResult {
+        let mut result = ForwardResult {
+            target: target.url.to_string(),
+            attempts: 0,
+            status: None,
+            error: None,
+        };
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(e) => {
+                result.error = Some(e);
+                return result;
+            }
+        };
+
+        let mut backoff = retry.backoff;
+        loop {
+            result.attempts += 1;
+            let (retryable, error) = match send(&client, target, headers, body).await {
+                Ok(status) if status.is_success() => {
+                    result.status = Some(status);
+                    result.error = None;
+                    break;
+                }
+                Ok(status) => {
+                    result.status = Some(status);
+                    let retryable =
+                        status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+                    (retryable, format!("Target responded with {}", status))
+                }
+                Err(e) => {
+                    result.status = None;
+                    (true, e.to_string())
+                }
+            };
+            result.error = Some(error);
+            if !retryable || result.attempts > retry.retries {
+                break;
+            }
+            debug!(
+                "Forwarding to {} failed (attempt {}), retrying in {:?}: {}",
+                target.url,
+                result.attempts,
+                backoff,
+                result.error.as_deref().unwrap_or_default()
+            );
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+
+        match &result.error {
+            None => debug!(
+                "Forwarded delivery to {} in {} attempt(s)",
+                target.url, result.attempts
+            ),
+            Some(e) => warn!(
+                "Failed to forward delivery to {} after {} attempt(s): {}",
+                target.url, result.attempts, e
+            ),
+        }
+        result
+    }
+
+    /// Get the HTTP client, built on first use
+    fn client(&self) -> Result<HttpClient, String> {
+        self.client
+            .get_or_init(|| {
+                let connector = HttpsConnectorBuilder::new()
+                    .with_native_roots()
+                    .map_err(|e| format!("Failed to load native TLS roots: {}", e))?
+                    .https_or_http()
+                    .enable_http1()
+                    .build();
+                Ok(Client::builder(TokioExecutor::new()).build(connector))
+            })
+            .clone()
+    }
+}
+
+/// Send one forwarding request, returning the target's response status
+async fn send(
+    client: &HttpClient,
+    target: &ForwardTarget,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> Result<StatusCode> {
+    let mut request = Request::post(target.url.clone())
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::USER_AGENT, DEFAULT_USER_AGENT)
+        .header(
+            FORWARD_SIGNATURE_HEADER,
+            sign_payload(body, target.secret.expose()),
+        )
+        .body(Full::new(body.clone()))?;
+    request.headers_mut().extend(forwarded_headers(headers));
+
+    let response = tokio::time::timeout(FORWARD_TIMEOUT, client.request(request))
+        .await
+        .map_err(|_| anyhow!("Timed out after {:?}", FORWARD_TIMEOUT))??;
+    Ok(response.status())
+}
+
+/// Rename the `X-GitHub-*` headers of a delivery to `X-Forwarded-GitHub-*`
+fn forwarded_headers(headers: &HeaderMap) -> Vec<(HeaderName, HeaderValue)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let suffix = name.as_str().strip_prefix(GITHUB_HEADER_PREFIX)?;
+            let name = format!("{}{}", FORWARDED_HEADER_PREFIX, suffix);
+            Some((HeaderName::from_bytes(name.as_bytes()).ok()?, value.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::middlewares::verify_hmac_sha256;
+    use axum::{routing::post, Router};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_forward_target_validation() {
+        let target =
+            ForwardTarget::new(&["Push", "issue_comment"], "http://ci.internal/hook", "s").unwrap();
+        assert_eq!(target.events, ["push", "issue_comment"]);
+        assert!(target.forwards("push"));
+        assert!(!target.forwards("issues"));
+
+        assert!(ForwardTarget::new(&["not an event"], "http://ci.internal", "s").is_err());
+        assert!(ForwardTarget::new(&["push"], "/relative", "s").is_err());
+        assert!(ForwardTarget::new(&["push"], "ftp://ci.internal", "s").is_err());
+        assert!(ForwardTarget::new(&["push"], "http://ci.internal", "").is_err());
+
+        let target = ForwardTarget::new(&["push"], "http://ci.internal", "s3cr3t").unwrap();
+        assert!(!format!("{:?}", target).contains("s3cr3t"));
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_deliveries_are_signed_and_retried() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new().route(
+            "/hook",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: Bytes| async move {
+                    let mut received = received.lock().unwrap();
+                    received.push((headers, body));
+                    // The first attempt fails
+                    if received.len() == 1 {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    } else {
+                        StatusCode::ACCEPTED
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let forwarder = Forwarder::default();
+        forwarder.set_retry(ForwardRetry {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        });
+        forwarder
+            .add(ForwardTarget::new(&["push"], &format!("http://{addr}/hook"), "s3cr3t").unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert("x-github-event", HeaderValue::from_static("push"));
+        headers.insert("x-github-delivery", HeaderValue::from_static("d-1"));
+        headers.insert("x-hub-signature-256", HeaderValue::from_static("sha256=00"));
+        let body = Bytes::from_static(br#"{"ref":"refs/heads/main"}"#);
+
+        assert!(forwarder.targets_for("issues").is_empty());
+        let results = forwarder
+            .forward(&forwarder.targets_for("push"), &headers, &body)
+            .await;
+        assert_eq!(
+            results,
+            [ForwardResult {
+                target: format!("http://{addr}/hook"),
+                attempts: 2,
+                status: Some(StatusCode::ACCEPTED),
+                error: None,
+            }]
+        );
+
+        let received = received.lock().unwrap();
+        let (headers, forwarded) = received.last().unwrap();
+        assert_eq!(forwarded, &body);
+        let signature = headers[FORWARD_SIGNATURE_HEADER].to_str().unwrap();
+        verify_hmac_sha256(signature, forwarded, "s3cr3t").unwrap();
+        assert!(verify_hmac_sha256(signature, forwarded, "other").is_err());
+        assert_eq!(headers["x-forwarded-github-event"], "push");
+        assert_eq!(headers["x-forwarded-github-delivery"], "d-1");
+        // GitHub's signature is only valid for GitHub's secret
+        assert!(!headers.contains_key("x-hub-signature-256"));
+    }
+
+    #[tokio::test]
+    async fn test_forwarding_gives_up_after_retries() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/down", post(|| async { StatusCode::BAD_GATEWAY }))
+            .route("/rejects", post(|| async { StatusCode::FORBIDDEN }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let forwarder = Forwarder::default();
+        forwarder.set_retry(ForwardRetry {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        });
+        for path in ["down", "rejects"] {
+            let url = format!("http://{addr}/{path}");
+            forwarder.add(ForwardTarget::new(&["push"], &url, "s3cr3t").unwrap());
+        }
+
+        let results = forwarder
+            .forward(
+                &forwarder.targets_for("push"),
+                &HeaderMap::new(),
+                &Bytes::new(),
+            )
+            .await;
+        assert_eq!(results[0].attempts, 3);
+        assert_eq!(results[0].status, Some(StatusCode::BAD_GATEWAY));
+        assert!(!results[0].is_success());
+        // Client errors are not retried
+        assert_eq!(results[1].attempts, 1);
+        assert_eq!(
+            results[1].error.as_deref(),
+            Some("Target responded with 403 Forbidden")
+        );
+    }
+}
@@ -4,14 +4,25 @@
 //! These handlers process incoming GitHub webhook events and route them
 //! to registered event handlers.
 
-use crate::core::Context;
-use crate::github::middlewares::GitHubEventExt;
-use crate::webhook::AppState;
+use crate::archive::ArchivedDelivery;
+use crate::dispatch::DispatchReport;
+use crate::github::middlewares::GITHUB_EVENT_HEADER;
+use crate::webhook::deadline::{self, Dispatched};
+use crate::webhook::info;
+use crate::webhook::report::{DeliveryReport, DELIVERY_ID_HEADER};
+use crate::webhook::{AppState, WebhookEventKind};
 use axum::{
-    extract::{Request, State},
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response, Result},
+    Json,
 };
-use tracing::{error, info};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{error, warn, Instrument, Span};
 
 /// Handle incoming webhook requests
 ///
@@ -21,18 +32,35 @@ use tracing::{error, info};
 ///
 /// # Request Processing Flow
 ///
-/// 1. **Extract Event Data** - Gets GitHub event information from request extensions
-///    (populated by the github_event_middleware)
-/// 2. **Create Context** - Creates a Context with event data and GitHub client
-/// 3. **Find Handlers** - Looks up registered handlers for this event type
-/// 4. **Execute Handlers** - Runs all handlers sequentially for this event
+/// 1. **Parse Event** - Parses the event from the `X-GitHub-Event` header and body
+///    into a Context with event data and GitHub client
+///    (the webhook settings of `ping` deliveries to the app's webhook are
+///    checked for [drift](crate::webhook::drift))
+/// 2. **Dispatch** - Runs all registered handlers for this event type through the
+///    [`Dispatcher`](crate::dispatch::Dispatcher), each with its own API budget,
+///    and logs a delivery summary
+/// 3. **Report** - Passes a [`DeliveryReport`] to the hooks registered with
+///    [`WebhookServer::on_delivery_complete`](crate::webhook::WebhookServer::on_delivery_complete),
+///    in background tasks, and queues the delivery for the
+///    [archive](crate::archive) and the fixture recorder, if enabled
+/// 4. **Forward** - Successfully handled deliveries of
+///    [forwarded](crate::webhook::forward) events are forwarded in a
+///    background task, before their report is passed to the hooks
 /// 5. **Return Response** - Returns appropriate HTTP status code
 ///
+/// With an [acknowledgement deadline](crate::webhook::deadline) set, a
+/// delivery whose handlers are still running at the deadline is acknowledged
+/// with `202 Accepted`; steps 3 and 4 follow once the remaining handlers
+/// completed in the background.
+///
 /// # Response Codes
 ///
 /// - `200 OK` - Event processed successfully (even if no handlers were registered)
-/// - `400 BAD REQUEST` - Request missing required GitHub event information
+/// - `202 ACCEPTED` - Handlers were still running at the acknowledgement deadline
+/// - `400 BAD REQUEST` - Missing event header or invalid event payload
 /// - `500 INTERNAL SERVER ERROR` - One or more handlers failed with an error
+/// - `503 SERVICE UNAVAILABLE` - The server is not [ready](crate::webhook::readiness)
+///   yet; the delivery is not processed
 ///
 /// # Error Handling
 ///
@@ -50,46 +78,196 @@ use tracing::{error, info};
 ///     .route("/webhook", post(handle_webhook))
 /// ```
 ///
-/// The handler expects the request to have been processed by middleware that:
-/// - Verifies the HMAC signature
-/// - Extracts GitHub event information
-/// - Populates request extensions with event data
-pub async fn handle_webhook(State(state): State<AppState>, req: Request) -> Result<Response> {
-    // Extract GitHub event information from the request extensions
-    let github_event_context = match req.github_event() {
-        Some(g) => g,
-        None => {
-            error!("Request does not contain GitHub event information!");
-            return Ok(axum::http::StatusCode::BAD_REQUEST.into_response());
+/// The handler expects the request to have been processed by middleware that
+/// verifies the HMAC signature.
+pub async fn handle_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response> {
+    if !state.readiness.is_ready() {
+        warn!(
+            "Server is not ready; rejecting delivery {}",
+            header(&headers, DELIVERY_ID_HEADER).unwrap_or_default()
+        );
+        return Ok(StatusCode::SERVICE_UNAVAILABLE.into_response());
+    }
+
+    let started = Instant::now();
+    let received_at = Utc::now();
+    let mut report = DeliveryReport {
+        delivery_id: header(&headers, DELIVERY_ID_HEADER),
+        event: header(&headers, GITHUB_EVENT_HEADER)
+            .map(WebhookEventKind::from)
+            .unwrap_or_default(),
+        action: serde_json::from_slice::<Action>(&body)
+            .ok()
+            .and_then(|a| a.action),
+        installation_id: None,
+        repository: None,
+        handlers: Vec::new(),
+        flags: Vec::new(),
+        sampled_out: Vec::new(),
+        stale: false,
+        comment_sections: Vec::new(),
+        notes: Vec::new(),
+        intended_actions: Vec::new(),
+        duration: Duration::ZERO,
+        status: StatusCode::OK,
+        forwards: Vec::new(),
+        acknowledged_handlers: None,
+        audited_calls: 0,
+    };
+
+    let status = match state.dispatcher.parse_from(&state.source, &headers, &body) {
+        Ok(ctx) => {
+            report.event = ctx.kind();
+            if report.event == PING_EVENT && state.source.is_app() {
+                state.hook.observe_ping(&state.dispatcher, &body).await;
+            }
+            report.installation_id = ctx.installation_id();
+            report.repository = ctx
+                .event()
+                .as_ref()
+                .and_then(|e| e.repository.as_ref())
+                .and_then(|r| r.full_name.clone());
+
+            let (dispatched, result) = match state.ack_deadline.get() {
+                None => state.dispatcher.dispatch_with_report(ctx).await,
+                Some(deadline) => {
+                    let remaining = deadline.saturating_sub(started.elapsed());
+                    match deadline::dispatch(&state.dispatcher, ctx, remaining).await {
+                        Dispatched::Completed(dispatched, result) => (*dispatched, result),
+                        Dispatched::Deferred {
+                            completed,
+                            remainder,
+                        } => {
+                            report.duration = started.elapsed();
+                            report.status = StatusCode::ACCEPTED;
+                            report.acknowledged_handlers = Some(completed);
+                            defer(state, headers, body, received_at, report, remainder);
+                            return Ok(StatusCode::ACCEPTED.into_response());
+                        }
+                    }
+                }
+            };
+            report.handlers = dispatched.results;
+            report.flags = dispatched.flags;
+            report.sampled_out = dispatched.sampled_out;
+            report.stale = dispatched.stale;
+            report.comment_sections = dispatched.comment_sections;
+            report.notes = dispatched.notes;
+            report.intended_actions = dispatched.intended_actions;
+            report.audited_calls = dispatched.audited_calls;
+            match result {
+                Ok(()) => StatusCode::OK,
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        }
+        Err(e) => {
+            error!("Invalid webhook delivery: {}", e);
+            StatusCode::BAD_REQUEST
         }
     };
 
-    let cloned_event = github_event_context.event.clone();
+    report.duration = started.elapsed();
+    report.status = status;
+    let handled = status == StatusCode::OK;
+    complete(state, headers, body, received_at, report, handled).await;
 
-    let ctx = Context::with_github_client(
-        Some(cloned_event),
-        github_event_context.installation_id.map(|id| id as u64),
-        state.github_client,
+    Ok(status.into_response())
+}
+
+/// Complete a delivery acknowledged at its deadline once its remaining
+/// handlers completed in `remainder`
+fn defer(
+    state: AppState,
+    headers: HeaderMap,
+    body: Bytes,
+    received_at: DateTime<Utc>,
+    mut report: DeliveryReport,
+    remainder: JoinHandle<(DispatchReport, anyhow::Result<()>)>,
+) {
+    warn!(
+        "Acknowledging {} delivery {} at the deadline, after {} handler(s)",
+        report.event,
+        report.delivery_id.as_deref().unwrap_or("without ID"),
+        report.acknowledged_handlers.unwrap_or_default()
     );
+    let task = async move {
+        let (dispatched, result) = deadline::joined_outcome(remainder.await);
+        if let Err(e) = &result {
+            error!("Delivery failed after its acknowledgement: {:#}", e);
+        }
+        report.handlers = dispatched.results;
+        report.flags = dispatched.flags;
+        report.sampled_out = dispatched.sampled_out;
+        report.stale = dispatched.stale;
+        report.comment_sections = dispatched.comment_sections;
+        report.notes = dispatched.notes;
+        report.intended_actions = dispatched.intended_actions;
+        report.audited_calls = dispatched.audited_calls;
+        let handled = result.is_ok();
+        complete(state, headers, body, received_at, report, handled).await;
+    };
+    tokio::spawn(task.instrument(Span::current()));
+}
 
-    // Get handlers for this event type
-    if let Some(event_handlers) = state.handlers.read().await.get(&ctx.kind()) {
-        for handler in event_handlers {
-            match handler(ctx.clone()).await {
-                Ok(_) => {
-                    info!("Handler executed successfully");
-                }
-                Err(e) => {
-                    error!("Handler failed with error: {:?}", e);
-                    return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR.into());
-                }
-            }
+/// Archive, forward and report a delivery whose handlers completed
+///
+/// `handled` tells whether the handlers succeeded, so the delivery is
+/// forwarded.
+async fn complete(
+    state: AppState,
+    headers: HeaderMap,
+    body: Bytes,
+    received_at: DateTime<Utc>,
+    mut report: DeliveryReport,
+    handled: bool,
+) {
+    let status = report.status;
+    if state.archive.is_enabled() || state.fixtures.is_enabled() {
+        let errors = report
+            .handlers
+            .iter()
+            .filter_map(|handler| handler.error.clone())
+            .collect();
+        let delivery = ArchivedDelivery::new(&headers, &body, received_at, status.as_u16(), errors);
+        if state.fixtures.is_enabled() {
+            state.fixtures.archive(delivery.clone());
         }
+        state.archive.archive(delivery);
+    }
+
+    let targets = if handled {
+        state.forwarder.targets_for(&report.event)
+    } else {
+        Vec::new()
+    };
+    if targets.is_empty() {
+        state.delivery_hooks.notify(report).await;
     } else {
-        info!("No handlers registered for event: {}", ctx.kind());
+        tokio::spawn(async move {
+            report.forwards = state.forwarder.forward(&targets, &headers, &body).await;
+            state.delivery_hooks.notify(report).await;
+        });
     }
+}
+
+/// Event whose payload carries the settings of the webhook
+const PING_EVENT: &str = "ping";
 
-    Ok(axum::http::StatusCode::OK.into_response())
+/// The `action` field of a webhook payload
+#[derive(Deserialize)]
+struct Action {
+    action: Option<String>,
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
 }
 
 /// Handle health check requests
@@ -100,7 +278,10 @@ pub async fn handle_webhook(State(state): State<AppState>, req: Request) -> Resu
 ///
 /// # Response
 ///
-/// Always returns `200 OK` with an empty body.
+/// Returns `200 OK` with an empty body, or `503 Service Unavailable` while the
+/// server is not [ready](crate::webhook::readiness), GitHub keeps rejecting
+/// the app's [credentials](crate::github::credentials), or a critical
+/// [background task](crate::tasks) failed.
 ///
 /// # Examples
 ///
@@ -128,6 +309,36 @@ pub async fn handle_webhook(State(state): State<AppState>, req: Request) -> Resu
 ///   initialDelaySeconds: 30
 ///   periodSeconds: 10
 /// ```
-pub async fn handle_health() -> Result<Response> {
-    Ok(axum::http::StatusCode::OK.into_response())
+pub async fn handle_health(State(state): State<AppState>) -> Result<Response> {
+    if !state.readiness.is_ready() {
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, "not ready").into_response());
+    }
+    if let Some(client) = state.dispatcher.github_client() {
+        if !client.credential_health().is_healthy() {
+            return Ok((StatusCode::SERVICE_UNAVAILABLE, "credentials unhealthy").into_response());
+        }
+    }
+    if !state.tasks.is_healthy() {
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, "background task failed").into_response());
+    }
+    Ok(StatusCode::OK.into_response())
+}
+
+/// Serve the registration summary
+///
+/// Responds with `404 Not Found` unless the endpoint is
+/// [enabled](crate::webhook::WebhookServer::set_info_endpoint), so an
+/// instance does not reveal what it handles by default.
+///
+/// # Examples
+///
+/// ```bash
+/// curl http://localhost:8000/_octofer/info
+/// # Returns: {"version":"0.1.0","app_id":123456,"events":[...],...}
+/// ```
+pub async fn handle_info(State(state): State<AppState>) -> Result<Response> {
+    if !state.info.is_enabled() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    Ok(Json(info::summarize(&state).await).into_response())
 }
@@ -0,0 +1,135 @@
+//! Introspection of a running webhook server
+//!
+//! [`WebhookServer::registration_summary`](crate::webhook::WebhookServer::registration_summary)
+//! describes what a server handles: the registered event types and handler
+//! names, the dispatch configuration, the filters of the handler groups, the
+//! [drift](crate::webhook::drift) from the app's webhook settings and the
+//! status of the [background tasks](crate::tasks).
+//! The same summary can be served as JSON at [`INFO_PATH`], which is disabled
+//! unless [`ServerConfig::info_endpoint`](crate::config::ServerConfig::info_endpoint)
+//! is set or [`WebhookServer::set_info_endpoint`](crate::webhook::WebhookServer::set_info_endpoint)
+//! is called.
+//!
+//! The summary only identifies the app by its ID; the webhook secret and the
+//! private key are never part of it.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use octofer::webhook::WebhookServer;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let server = WebhookServer::new_default();
+//! let summary = server.registration_summary().await;
+//! assert!(summary.events.is_empty());
+//! assert_eq!(summary.app_id, None);
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::config::DispatchConfig;
+use crate::groups::GroupInfo;
+use crate::tasks::TaskSummary;
+use crate::webhook::drift::SubscriptionDrift;
+use crate::webhook::{AppState, WebhookEventKind};
+
+/// Path the registration summary is served at, when enabled
+pub const INFO_PATH: &str = "/_octofer/info";
+
+/// What a webhook server handles, see the [module documentation](self)
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistrationSummary {
+    /// Version of the octofer crate
+    pub version: String,
+    /// ID of the GitHub App (`None` without a GitHub client)
+    pub app_id: Option<u64>,
+    /// Registered event types, sorted by event type
+    pub events: Vec<EventRegistrations>,
+    /// Configuration used when dispatching events to handlers
+    pub dispatch: DispatchConfig,
+    /// Handler groups and the filters in effect for them
+    pub groups: Vec<GroupInfo>,
+    /// Seconds since the server was created
+    pub uptime_secs: u64,
+    /// Drift between the app's webhook, as last observed, and the registered
+    /// handlers (`None` until the webhook's settings were observed)
+    pub drift: Option<SubscriptionDrift>,
+    /// Supervised background tasks, sorted by name
+    pub tasks: Vec<TaskSummary>,
+}
+
+/// Handlers registered for one event type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EventRegistrations {
+    /// Event type
+    pub event: WebhookEventKind,
+    /// Number of registered handlers
+    pub handlers: usize,
+    /// Names of the handlers, in the order they run
+    pub names: Vec<String>,
+}
+
+/// Whether the info endpoint is served, and since when the server is up
+#[derive(Debug, Clone)]
+pub struct InfoEndpoint {
+    enabled: Arc<AtomicBool>,
+    started: Instant,
+}
+
+impl Default for InfoEndpoint {
+    fn default() -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(false)),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl InfoEndpoint {
+    /// Whether the summary is served at [`INFO_PATH`]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Serve, or stop serving, the summary at [`INFO_PATH`]
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Time since the server was created
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Build the registration summary of a server's state
+pub(crate) async fn summarize(state: &AppState) -> RegistrationSummary {
+    let dispatcher = &state.dispatcher;
+    let events = dispatcher
+        .handler_names()
+        .await
+        .into_iter()
+        .map(|(event, names)| EventRegistrations {
+            event,
+            handlers: names.len(),
+            names,
+        })
+        .collect();
+
+    RegistrationSummary {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        app_id: dispatcher.github_client().map(|client| client.app_id()),
+        events,
+        dispatch: dispatcher.config().await,
+        groups: dispatcher.groups().await,
+        uptime_secs: state.info.uptime().as_secs(),
+        drift: state.hook.drift(dispatcher).await,
+        tasks: state.tasks.summary(),
+    }
+}
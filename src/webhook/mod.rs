@@ -9,6 +9,13 @@
 //! - [`WebhookServer`] - HTTP server for receiving webhook events
 //! - [`AppState`] - Shared application state containing handlers and GitHub client
 //! - [`handlers`] - Request handlers for webhook and health check endpoints
+//! - [`info`] - Summary of the registered handlers, optionally served over HTTP
+//! - [`report`] - Per-delivery reports passed to completion hooks
+//! - [`plain`] - Plain organization or enterprise webhooks next to the app's
+//! - [`forward`] - Signed forwarding of deliveries to downstream services
+//! - [`deadline`] - Acknowledging slow deliveries before all handlers completed
+//! - [`routes`] - Custom HTTP routes served next to the webhook
+//! - [`tuning`] - Keep-alive, HTTP/2, compression and listener settings
 //!
 //! # Architecture
 //!
@@ -33,7 +40,7 @@
 //!     config.server.host,
 //!     config.server.port,
 //!     config.github.clone(),
-//!     &config.webhook.secret,
+//!     config.webhook.secret.expose(),
 //!     &config.webhook.header_name,
 //! ).await?;
 //!
@@ -43,7 +50,21 @@
 //! # }
 //! ```
 
+pub mod deadline;
+pub mod drift;
+pub mod forward;
 pub mod handlers;
+pub mod info;
+pub mod plain;
+pub mod readiness;
+pub mod report;
+pub mod routes;
 pub mod server;
+pub mod tuning;
 
+pub use drift::SubscriptionDrift;
+pub use info::RegistrationSummary;
+pub use plain::WebhookSource;
+pub use readiness::Readiness;
+pub use routes::RouteState;
 pub use server::*;
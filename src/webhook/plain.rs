@@ -0,0 +1,67 @@
+//! Plain webhooks received next to the GitHub App's webhook
+//!
+//! Besides the app's webhook at `POST /webhook`, a server can receive plain
+//! organization, enterprise or repository webhooks, e.g. for audit log
+//! streaming, at further paths with
+//! [`WebhookServer::add_plain_webhook`](crate::webhook::WebhookServer::add_plain_webhook).
+//! Each has its own secret, and deliveries are parsed the same way as the
+//! app's, but they are not about an installation: their
+//! [`Context::installation_id`](crate::Context::installation_id) is always
+//! `None`, so handlers only get the [app client](crate::Context::github), if
+//! the server has one. Handlers can tell the sources apart with
+//! [`Context::source`](crate::Context::source).
+//!
+//! Plain deliveries go to the server's handlers, or to a separate
+//! [`Dispatcher`](crate::dispatch::Dispatcher) with
+//! [`WebhookServer::add_plain_webhook_with_dispatcher`](crate::webhook::WebhookServer::add_plain_webhook_with_dispatcher).
+//! Their `ping` deliveries are not checked for
+//! [drift](crate::webhook::drift), which is about the app's webhook.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Config, Context, Octofer};
+//! use octofer::webhook::WebhookSource;
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut app = Octofer::new(Config::from_env()?).await?;
+//! app.add_plain_webhook("/webhooks/org", "org-webhook-secret")?;
+//!
+//! app.on_team(
+//!     |context: Context, _extra: Arc<()>| async move {
+//!         if let WebhookSource::Plain { path } = context.source() {
+//!             println!("Team event from the webhook at {}", path);
+//!         }
+//!         Ok(())
+//!     },
+//!     Arc::new(()),
+//! )
+//! .await;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::Serialize;
+
+/// Webhook a delivery was received on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookSource {
+    /// The GitHub App's webhook
+    #[default]
+    App,
+    /// A plain webhook added with
+    /// [`WebhookServer::add_plain_webhook`](crate::webhook::WebhookServer::add_plain_webhook)
+    Plain {
+        /// Path the webhook is received at
+        path: String,
+    },
+}
+
+impl WebhookSource {
+    /// Whether the delivery was sent to the GitHub App's webhook
+    pub fn is_app(&self) -> bool {
+        matches!(self, Self::App)
+    }
+}
@@ -0,0 +1,72 @@
+//! Readiness of a webhook server to process deliveries
+//!
+//! A server normally processes deliveries as soon as it is started. Apps that
+//! register handlers only after starting, e.g. once dynamic configuration was
+//! fetched, can start it paused with
+//! [`WebhookServer::start_paused`](crate::webhook::WebhookServer::start_paused)
+//! instead. Until [`Readiness::ready`] is called:
+//!
+//! - `POST /webhook` responds with `503 Service Unavailable` without running
+//!   any handler, so deliveries are not lost to an empty handler registry;
+//! - `GET /health` responds with `503 Service Unavailable`, so load balancers
+//!   do not route traffic to the instance yet.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::{Config, Context, Octofer};
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let app = Octofer::new(Config::from_env()?).await?;
+//! let dispatcher = app.dispatcher().clone();
+//!
+//! let (readiness, serving) = app.start_paused();
+//! let registration = async move {
+//!     // ... fetch dynamic configuration, then register handlers
+//!     dispatcher
+//!         .on("issues", |_context: Context, _extra: Arc<()>| async { Ok(()) }, Arc::new(()))
+//!         .await;
+//!     readiness.ready();
+//!     Ok(())
+//! };
+//! tokio::try_join!(serving, registration)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Switch between processing deliveries and rejecting them as not ready
+///
+/// Clones share the switch.
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    ready: Arc<AtomicBool>,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self {
+            ready: Arc::new(AtomicBool::new(true)),
+        }
+    }
+}
+
+impl Readiness {
+    /// Whether deliveries are processed
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// Start processing deliveries
+    pub fn ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    /// Reject deliveries as not ready until [`Readiness::ready`] is called
+    pub fn pause(&self) {
+        self.ready.store(false, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,150 @@
+//! Per-delivery reports and completion hooks
+//!
+//! After every webhook delivery, the server builds a [`DeliveryReport`] with
+//! the outcome of each handler and passes it to the hooks registered with
+//! [`WebhookServer::on_delivery_complete`](crate::webhook::WebhookServer::on_delivery_complete),
+//! e.g. to ship delivery outcomes to a data warehouse.
+//!
+//! Hooks run in background tasks once the response status is known, so they
+//! never delay the response to GitHub. A failing or panicking hook cannot fail
+//! the delivery; its error is logged.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use octofer::Octofer;
+//!
+//! # async fn example(mut app: Octofer) {
+//! app.on_delivery_complete(|report| async move {
+//!     println!(
+//!         "{} {:?}: {} handler(s) in {:?}, status {}",
+//!         report.event,
+//!         report.action,
+//!         report.handlers.len(),
+//!         report.duration,
+//!         report.status
+//!     );
+//!     Ok(())
+//! })
+//! .await;
+//! # }
+//! ```
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::http::StatusCode;
+use futures::FutureExt;
+use tokio::sync::RwLock;
+use tracing::error;
+
+use crate::dispatch::HandlerResult;
+use crate::flags::FlagEvaluation;
+use crate::github::layers::IntendedAction;
+use crate::helpers::comments::QueuedSectionResult;
+use crate::helpers::notes::ReportNote;
+use crate::webhook::forward::ForwardResult;
+use crate::webhook::WebhookEventKind;
+
+/// Header carrying the unique ID of a webhook delivery
+pub const DELIVERY_ID_HEADER: &str = "X-GitHub-Delivery";
+
+/// Outcome of a single webhook delivery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryReport {
+    /// Unique ID of the delivery (`X-GitHub-Delivery` header), if sent
+    pub delivery_id: Option<String>,
+    /// Event type of the delivery
+    pub event: WebhookEventKind,
+    /// Action of the event (e.g. `opened`), if it has one
+    pub action: Option<String>,
+    /// Installation the delivery belongs to
+    pub installation_id: Option<u64>,
+    /// Full name of the event's repository (e.g. `octocat/hello-world`)
+    pub repository: Option<String>,
+    /// Results of the handlers that ran, in the order they ran
+    pub handlers: Vec<HandlerResult>,
+    /// Evaluations of the flags of handlers registered
+    /// [behind a flag](crate::core::HandlerRegistration::behind_flag)
+    pub flags: Vec<FlagEvaluation>,
+    /// Names of the handlers skipped because the delivery was not in their
+    /// [sample](crate::sampling)
+    pub sampled_out: Vec<String>,
+    /// Whether handlers not allowing it were skipped because the event is
+    /// [stale](crate::staleness)
+    pub stale: bool,
+    /// Outcome of the comment sections queued by the handlers
+    pub comment_sections: Vec<QueuedSectionResult>,
+    /// Notes the handlers added with [`Context::note`](crate::Context::note)
+    pub notes: Vec<ReportNote>,
+    /// Writes intercepted from the handlers in dry-run mode, see the
+    /// [`dry_run`](crate::github::layers::dry_run) module
+    pub intended_actions: Vec<IntendedAction>,
+    /// Time from receiving the delivery to responding
+    pub duration: Duration,
+    /// HTTP status returned to GitHub
+    pub status: StatusCode,
+    /// Outcome of [forwarding](crate::webhook::forward) the delivery, per
+    /// target; empty if it was not forwarded
+    pub forwards: Vec<ForwardResult>,
+    /// Number of `handlers` that had completed when the delivery was
+    /// acknowledged at its [deadline](crate::webhook::deadline); `None` if
+    /// the response waited for all handlers
+    pub acknowledged_handlers: Option<usize>,
+    /// Number of mutating GitHub API requests performed by the handlers, see
+    /// the [`audit`](crate::github::layers::audit) module
+    pub audited_calls: usize,
+}
+
+impl DeliveryReport {
+    /// Get the results of the handlers that completed after the delivery was
+    /// acknowledged at its deadline
+    pub fn handlers_after_ack(&self) -> &[HandlerResult] {
+        match self.acknowledged_handlers {
+            Some(completed) => self.handlers.get(completed..).unwrap_or_default(),
+            None => &[],
+        }
+    }
+}
+
+type DeliveryHook =
+    Arc<dyn Fn(DeliveryReport) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+/// Hooks invoked with the report of every delivery
+///
+/// Cloning is cheap; clones share the same hooks.
+#[derive(Clone, Default)]
+pub struct DeliveryHooks {
+    hooks: Arc<RwLock<Vec<DeliveryHook>>>,
+}
+
+impl DeliveryHooks {
+    /// Add a hook
+    pub async fn add<F, Fut>(&self, hook: F)
+    where
+        F: Fn(DeliveryReport) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let hook: DeliveryHook = Arc::new(move |report| Box::pin(hook(report)));
+        self.hooks.write().await.push(hook);
+    }
+
+    /// Run every hook with `report` in its own background task
+    pub async fn notify(&self, report: DeliveryReport) {
+        for hook in self.hooks.read().await.iter().cloned() {
+            let report = report.clone();
+            tokio::spawn(async move {
+                let delivery = report.delivery_id.clone().unwrap_or_default();
+                match AssertUnwindSafe(hook(report)).catch_unwind().await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => error!("Delivery hook failed for {}: {:?}", delivery, e),
+                    Err(_) => error!("Delivery hook panicked for {}", delivery),
+                }
+            });
+        }
+    }
+}
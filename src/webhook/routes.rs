@@ -0,0 +1,76 @@
+//! Custom HTTP routes served next to the webhook
+//!
+//! Apps can serve their own endpoints, e.g. an OAuth callback or a small
+//! admin API, on the webhook server's address with
+//! [`Octofer::route`](crate::Octofer::route) and
+//! [`Octofer::nest`](crate::Octofer::nest). Their handlers receive a
+//! [`RouteState`] with the app's GitHub client and configuration.
+//!
+//! Custom routes are traced like the built-in routes, but are not covered by
+//! the permissive CORS layer of `/health` and `/webhook`; add a
+//! `tower_http::cors::CorsLayer` to the nested router if browsers call them.
+//! They are not authenticated either: unlike `/webhook`, requests to them are
+//! not verified with the webhook secret. Middleware added with
+//! [`WebhookServer::add_middleware`](crate::webhook::WebhookServer::add_middleware)
+//! wraps the routes mounted before it was added, so mount custom routes
+//! first for it to apply to them too.
+//!
+//! Routes must be mounted before the server is started. Paths served by the
+//! server itself (`/webhook`, `/health`, the
+//! [info endpoint](crate::webhook::info) and plain webhooks) cannot be
+//! overridden.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+//! use octofer::webhook::RouteState;
+//! use octofer::{Config, Octofer};
+//!
+//! async fn installations(State(state): State<RouteState>) -> Result<Json<Vec<u64>>, StatusCode> {
+//!     let client = state.github.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+//!     let installations = client
+//!         .get_installations()
+//!         .await
+//!         .map_err(|_| StatusCode::BAD_GATEWAY)?;
+//!     Ok(Json(installations.iter().map(|i| i.id.0).collect()))
+//! }
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut app = Octofer::new(Config::from_env()?).await?;
+//! app.nest(
+//!     "/admin",
+//!     Router::new().route("/installations", get(installations)),
+//! )?;
+//! app.start().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use crate::github::GitHubClient;
+use crate::Config;
+
+/// State passed to the handlers of custom routes
+///
+/// Cloning is cheap.
+#[derive(Clone, Default)]
+pub struct RouteState {
+    /// GitHub client shared with the event handlers, if the app has one
+    pub github: Option<Arc<GitHubClient>>,
+    /// Configuration the app was created with
+    ///
+    /// Includes the app's secrets; do not return it from routes as is.
+    pub config: Arc<Config>,
+}
+
+impl RouteState {
+    /// Create the state of custom routes
+    pub fn new(github: Option<Arc<GitHubClient>>, config: Config) -> Self {
+        Self {
+            github,
+            config: Arc::new(config),
+        }
+    }
+}
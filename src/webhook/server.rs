@@ -6,37 +6,89 @@
 use anyhow::Result;
 use axum::extract::Request;
 use axum::response::IntoResponse;
-use axum::routing::{get, post, Route};
+use axum::routing::{get, post, MethodRouter, Route};
 use axum::{middleware, Router};
 use std::convert::Infallible;
-use std::{collections::HashMap, net::Ipv4Addr, sync::Arc};
-use tokio::sync::RwLock;
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tower::{Layer, Service};
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
 use tower_http::trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer};
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 
-use crate::config::{GitHubConfig, DEFAULT_HOST_ADDR, DEFAULT_PORT};
-use crate::core::{Context, EventHandlerFn};
+use crate::archive::{DeliveryArchive, DeliveryArchiver, FixtureRecorder};
+use crate::config::{
+    DispatchConfig, GitHubConfig, PrewarmInstallations, DEFAULT_HOST_ADDR, DEFAULT_PORT,
+    WEBHOOK_HEADER_NAME,
+};
+use crate::core::{Context, HandlerRegistration, HandlerSource};
+use crate::dispatch::{normalize_event_name, normalize_unchecked, registration_or_log, Dispatcher};
 use crate::github::{
-    middlewares::{github_event_middleware, verify_hmac_middleware, HmacConfig},
-    GitHubAuth, GitHubClient,
+    middlewares::{verify_hmac_middleware, HmacConfig, InsecureHmacConfig, SharedHmacConfig},
+    redelivery::RedeliveryOptions,
+    GitHubClient,
 };
+use crate::groups::GroupFilter;
+use crate::tasks::{RestartPolicy, TaskOptions, TaskSupervisor};
 
+use super::deadline::AckDeadline;
+use super::drift::{HookMonitor, SubscriptionDrift};
+use super::forward::{ForwardRetry, ForwardTarget, Forwarder};
 use super::handlers;
+use super::info::{self, InfoEndpoint, RegistrationSummary, INFO_PATH};
+use super::plain::WebhookSource;
+use super::readiness::Readiness;
+use super::report::{DeliveryHooks, DeliveryReport};
+use super::routes::RouteState;
+use super::tuning::{self, CompressionSwitch, ServerTuning};
+
+/// Name of the background task writing the delivery archive
+const ARCHIVE_TASK: &str = "delivery-archive";
+
+/// Name of the background task recording deliveries as fixtures
+const FIXTURES_TASK: &str = "fixture-recorder";
+
+/// Name of the background task keeping installation tokens fresh
+const KEEP_WARM_TASK: &str = "keep-warm";
 
-/// Type alias for webhook event kinds (event type strings)
-pub type WebhookEventKind = String;
+/// Name of the background task redelivering failed deliveries at startup
+const REDELIVERY_TASK: &str = "redeliver-failed";
+
+/// Type alias for webhook event kinds, kept for compatibility; prefer
+/// [`EventKind`](crate::EventKind)
+pub type WebhookEventKind = crate::events::EventKind;
 
 /// Application state shared across handlers
 ///
 /// This struct contains the shared state that all webhook handlers can access,
-/// including registered event handlers and the GitHub API client.
+/// i.e. the [`Dispatcher`] holding registered event handlers and the GitHub API client.
 #[derive(Clone, Default)]
 pub struct AppState {
-    /// Event handlers mapped by event type (e.g., "issues", "pull_request")
-    pub handlers: Arc<RwLock<HashMap<WebhookEventKind, Vec<EventHandlerFn>>>>,
-    /// GitHub client for API operations (if available)
-    pub github_client: Option<Arc<GitHubClient>>,
+    /// Dispatcher running registered handlers for incoming events
+    pub dispatcher: Dispatcher,
+    /// Hooks invoked with the report of every delivery
+    pub delivery_hooks: DeliveryHooks,
+    /// Archive verified deliveries are written to, if enabled
+    pub archive: DeliveryArchiver,
+    /// Recorder writing verified deliveries as test fixtures, if enabled
+    pub fixtures: DeliveryArchiver,
+    /// Switch of the registration summary endpoint and the server's start time
+    pub info: InfoEndpoint,
+    /// Latest observed settings of the app's webhook
+    pub hook: HookMonitor,
+    /// Whether deliveries are processed or rejected as not ready
+    pub readiness: Readiness,
+    /// Webhook the routes of this state receive deliveries on
+    pub source: WebhookSource,
+    /// Downstream services deliveries are forwarded to
+    pub forwarder: Forwarder,
+    /// Deadline after which deliveries are acknowledged, if set
+    pub ack_deadline: AckDeadline,
+    /// Supervisor of the app's background tasks
+    pub tasks: TaskSupervisor,
 }
 
 /// Webhook server for handling GitHub webhook events
@@ -67,7 +119,7 @@ pub struct AppState {
 ///     config.server.host,
 ///     config.server.port,
 ///     config.github.clone(),
-///     &config.webhook.secret,
+///     config.webhook.secret.expose(),
 ///     &config.webhook.header_name,
 /// ).await?;
 ///
@@ -96,6 +148,20 @@ pub struct WebhookServer {
     pub port: u16,
     /// Axum router
     router: Option<Router>,
+    /// Handler group new handlers are registered in, if any
+    group: Option<Arc<GroupFilter>>,
+    /// HMAC configuration read by the router on every request
+    hmac: SharedHmacConfig,
+    /// Paths of the plain webhooks added to the router
+    plain_webhooks: Vec<String>,
+    /// Installations whose clients are created before serving, if any
+    prewarm: Option<PrewarmInstallations>,
+    /// Failed deliveries redelivered once serving, if any
+    redelivery: Option<RedeliveryOptions>,
+    /// Connection settings applied when binding
+    tuning: ServerTuning,
+    /// Whether the health and info endpoints compress their responses
+    compression: CompressionSwitch,
 }
 
 impl Default for WebhookServer {
@@ -121,7 +187,9 @@ impl WebhookServer {
     /// # Returns
     ///
     /// Returns `Ok(WebhookServer)` if the server was created successfully,
-    /// or `Err` if GitHub client creation failed.
+    /// or `Err` if GitHub client creation failed, or `secret` is the default
+    /// development secret and `host` is not a loopback address (see
+    /// [`HmacConfig::validate`]).
     ///
     /// # Examples
     ///
@@ -136,7 +204,7 @@ impl WebhookServer {
     ///     Ipv4Addr::new(0, 0, 0, 0),  // Bind to all interfaces
     ///     3000,                       // Port 3000
     ///     config.github,
-    ///     &config.webhook.secret,
+    ///     config.webhook.secret.expose(),
     ///     &config.webhook.header_name,
     /// ).await?;
     ///
@@ -151,23 +219,95 @@ impl WebhookServer {
         secret: &str,
         hmac_header: &str,
     ) -> Result<Self> {
-        let auth = GitHubAuth::from_config(&github_config);
-        let github_client = Arc::new(GitHubClient::new(auth).await?);
-
-        let state = AppState {
-            handlers: Arc::new(RwLock::new(HashMap::new())),
-            github_client: Some(github_client),
-        };
+        Self::with_hmac_config(
+            host,
+            port,
+            github_config,
+            HmacConfig::new(secret.into(), hmac_header.into()),
+        )
+        .await
+    }
 
-        let hmac_config = HmacConfig::new(secret.into(), hmac_header.into());
-        let router = create_router(state.clone(), hmac_config);
+    /// Create a new webhook server verifying deliveries with `hmac_config`
+    ///
+    /// Like [`WebhookServer::new`], but also allows disabling verification
+    /// or accepting the default secret, e.g. with the configuration from
+    /// [`WebhookConfig::hmac_config`](crate::config::WebhookConfig::hmac_config).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if GitHub client creation failed, or `hmac_config` is
+    /// not safe for `host` (see [`HmacConfig::validate`]).
+    pub async fn with_hmac_config(
+        host: Ipv4Addr,
+        port: u16,
+        github_config: GitHubConfig,
+        hmac_config: HmacConfig,
+    ) -> Result<Self> {
+        hmac_config.validate(host)?;
+        let github_client = Arc::new(GitHubClient::from_config(&github_config).await?);
+        let mut server = Self::build(host, port, Some(github_client), hmac_config);
+        server.prewarm = github_config.prewarm_installations;
+        server.redelivery = github_config.redelivery;
+        Ok(server)
+    }
 
-        Ok(Self {
-            state,
+    /// Create a new webhook server using a pre-built GitHub client
+    ///
+    /// Like [`WebhookServer::with_hmac_config`], with handlers using
+    /// `github_client` instead of a client built from the GitHub
+    /// configuration, e.g. one created with
+    /// [`GitHubClient::from_octocrab`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `hmac_config` is not safe for `host` (see
+    /// [`HmacConfig::validate`]).
+    pub fn with_github_client(
+        host: Ipv4Addr,
+        port: u16,
+        github_client: GitHubClient,
+        hmac_config: HmacConfig,
+    ) -> Result<Self> {
+        hmac_config.validate(host)?;
+        Ok(Self::build(
             host,
             port,
-            router: Some(router),
-        })
+            Some(Arc::new(github_client)),
+            hmac_config,
+        ))
+    }
+
+    /// Create a new webhook server without a GitHub client
+    ///
+    /// Deliveries are verified and parsed like with [`WebhookServer::new`],
+    /// but handlers get no API access: [`Context::github`] returns `None`.
+    /// Useful for apps that only route webhooks, e.g. into a message queue,
+    /// and have no GitHub App credentials.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `hmac_config` is not safe for `host` (see
+    /// [`HmacConfig::validate`]).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use octofer::github::middlewares::HmacConfig;
+    /// use octofer::webhook::WebhookServer;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = WebhookServer::without_github(
+    ///     Ipv4Addr::new(0, 0, 0, 0),
+    ///     3000,
+    ///     HmacConfig::new("webhook-secret".to_string(), "X-Hub-Signature-256".to_string()),
+    /// )?;
+    /// assert!(server.github_client().is_none());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn without_github(host: Ipv4Addr, port: u16, hmac_config: HmacConfig) -> Result<Self> {
+        hmac_config.validate(host)?;
+        Ok(Self::build(host, port, None, hmac_config))
     }
 
     /// Create a new webhook server with default configuration
@@ -192,18 +332,46 @@ impl WebhookServer {
     /// assert_eq!(server.port, 8000);
     /// ```
     pub fn new_default() -> Self {
+        Self::build(DEFAULT_HOST_ADDR, DEFAULT_PORT, None, HmacConfig::default())
+    }
+
+    fn build(
+        host: Ipv4Addr,
+        port: u16,
+        github_client: Option<Arc<GitHubClient>>,
+        hmac_config: HmacConfig,
+    ) -> Self {
+        let tasks = TaskSupervisor::default();
         let state = AppState {
-            handlers: Arc::new(RwLock::new(HashMap::new())),
-            github_client: None,
+            dispatcher: Dispatcher::new(github_client),
+            delivery_hooks: DeliveryHooks::default(),
+            archive: DeliveryArchiver::supervised(tasks.clone(), ARCHIVE_TASK),
+            fixtures: DeliveryArchiver::supervised(tasks.clone(), FIXTURES_TASK),
+            info: InfoEndpoint::default(),
+            hook: HookMonitor::default(),
+            readiness: Readiness::default(),
+            source: WebhookSource::App,
+            forwarder: Forwarder::default(),
+            ack_deadline: AckDeadline::default(),
+            tasks,
         };
 
-        let router = create_router(state.clone(), HmacConfig::default());
+        let hmac = SharedHmacConfig::new(hmac_config);
+        let compression = CompressionSwitch::default();
+        let router = create_router(state.clone(), hmac.clone(), &compression);
 
         Self {
             state,
-            host: DEFAULT_HOST_ADDR,
-            port: DEFAULT_PORT,
+            host,
+            port,
             router: Some(router),
+            group: None,
+            hmac,
+            plain_webhooks: Vec::new(),
+            prewarm: None,
+            redelivery: None,
+            tuning: ServerTuning::default(),
+            compression,
         }
     }
 
@@ -211,6 +379,7 @@ impl WebhookServer {
     ///
     /// Starts the HTTP server and begins listening for webhook requests.
     /// This method will block until the server is stopped or an error occurs.
+    /// It is [`WebhookServer::bind`] followed by [`BoundServer::serve`].
     ///
     /// The server provides two endpoints:
     /// - `POST /webhook` - Receives GitHub webhook events
@@ -219,7 +388,9 @@ impl WebhookServer {
     /// # Returns
     ///
     /// Returns `Ok(())` if the server stops gracefully, or `Err` if there's
-    /// an error starting the server or binding to the specified address.
+    /// an error starting the server or binding to the specified address, or
+    /// the HMAC configuration is not safe for the host (see
+    /// [`HmacConfig::validate`]).
     ///
     /// # Examples
     ///
@@ -235,16 +406,91 @@ impl WebhookServer {
     /// # }
     /// ```
     pub async fn start(&self) -> Result<()> {
-        let listener = tokio::net::TcpListener::bind((self.host, self.port)).await?;
-        info!("Webhook server started on {}:{}", self.host, self.port);
+        self.bind().await?.serve().await
+    }
+
+    /// Bind the webhook server's address without serving requests yet
+    ///
+    /// Binding fails right away if the address is in use, and with port `0`
+    /// the operating system picks a free port, which
+    /// [`BoundServer::local_addr`] reports. Requests are only accepted once
+    /// [`BoundServer::serve`] runs.
+    ///
+    /// Handlers registered after binding, through the
+    /// [`dispatcher`](WebhookServer::dispatcher), still receive deliveries;
+    /// routes must be added before.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address cannot be bound, or the HMAC
+    /// configuration is not safe for the host (see [`HmacConfig::validate`]).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::webhook::WebhookServer;
+    ///
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let mut server = WebhookServer::new_default();
+    /// server.port = 0;
+    ///
+    /// let bound = server.bind().await?;
+    /// println!("Listening on {}", bound.local_addr());
+    /// bound.serve().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bind(&self) -> Result<BoundServer> {
+        let hmac = self.hmac.get();
+        hmac.validate(self.host)?;
 
         let router = self
             .router
             .clone()
             .ok_or(anyhow::anyhow!("Cannot initialize router"))?;
+        let listener = tuning::bind((self.host, self.port).into(), &self.tuning)?;
+        let local_addr = listener.local_addr()?;
+        if hmac.disabled {
+            warn!(
+                "HMAC verification is DISABLED: any request to {} is accepted as a delivery",
+                local_addr
+            );
+        }
 
-        axum::serve(listener, router).await?;
-        Ok(())
+        Ok(BoundServer {
+            listener,
+            local_addr,
+            router,
+            dispatcher: self.state.dispatcher.clone(),
+            tasks: self.state.tasks.clone(),
+            prewarm: self.prewarm.clone(),
+            redelivery: self.redelivery.clone(),
+            tuning: self.tuning.clone(),
+        })
+    }
+
+    /// Start the webhook server without processing deliveries yet
+    ///
+    /// Returns the server's [`Readiness`] and the future serving requests,
+    /// which behaves like [`WebhookServer::start`]. Deliveries and health
+    /// checks are answered with `503 Service Unavailable` until
+    /// [`Readiness::ready`] is called. See the
+    /// [`readiness`](crate::webhook::readiness) module.
+    pub fn start_paused(&self) -> (Readiness, impl Future<Output = Result<()>> + '_) {
+        self.state.readiness.pause();
+        (self.state.readiness.clone(), self.start())
+    }
+
+    /// Get the switch between processing deliveries and rejecting them as
+    /// not ready
+    pub fn readiness(&self) -> &Readiness {
+        &self.state.readiness
+    }
+
+    /// Get the supervisor of the server's background tasks, see the
+    /// [`tasks`](crate::tasks) module
+    pub fn tasks(&self) -> &TaskSupervisor {
+        &self.state.tasks
     }
 
     /// Register an event handler for a specific event type
@@ -259,6 +505,22 @@ impl WebhookServer {
     /// * `handler` - Async function to handle the event
     /// * `extra` - Additional data to pass to the handler (shared across all calls)
     ///
+    /// # Returns
+    ///
+    /// Returns a [`HandlerRegistration`] that can be used to tune how the
+    /// handler is dispatched, e.g. to override its API budget, or to
+    /// [name](HandlerRegistration::named) it. Unnamed handlers appear in logs
+    /// and delivery reports under their type name and the location of this
+    /// call.
+    ///
+    /// # Invalid Event Names
+    ///
+    /// If `event` is empty or not a known event name, e.g. the typo
+    /// `"issue"`, the error is logged and the handler is not registered.
+    /// Names are case-insensitive. Use [`WebhookServer::try_on`] to fail at
+    /// startup instead, or [`WebhookServer::on_unchecked`] for custom event
+    /// names.
+    ///
     /// # Handler Signature
     ///
     /// The handler function must have the signature:
@@ -295,29 +557,455 @@ impl WebhookServer {
     ///     },
     ///     app_name,
     /// ).await;
+    ///
+    /// // Allow a heavy handler more API requests per invocation
+    /// server.on(
+    ///     "push",
+    ///     |_context: Context, _extra: Arc<()>| async move { Ok(()) },
+    ///     Arc::new(()),
+    /// ).await.api_budget(500);
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn on<F, Fut, E>(&mut self, event: impl Into<String>, handler: F, extra: Arc<E>)
+    #[track_caller]
+    pub fn on<F, Fut, E>(
+        &mut self,
+        event: impl Into<String>,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl Future<Output = HandlerRegistration>
     where
         F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
-        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
         E: Send + Sync + 'static,
     {
-        let event = event.into();
-        let boxed_handler: EventHandlerFn = Box::new(move |context| {
-            // Clone the extra data for this handler call
-            let extra = extra.clone();
-            Box::pin(handler(context, extra))
-        });
+        let source = HandlerSource::of::<F>();
+        let registration = normalize_event_name(&event.into())
+            .map(|event| self.register(event, handler, extra, source));
+        registration_or_log(registration, source)
+    }
+
+    /// Register an event handler, failing if the event name is unknown
+    ///
+    /// Same as [`WebhookServer::on`], but returns the error instead of
+    /// logging it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InvalidEventName`](crate::dispatch::InvalidEventName)
+    /// error if `event` is empty or not a known event name.
+    #[track_caller]
+    pub fn try_on<F, Fut, E>(
+        &mut self,
+        event: impl Into<String>,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl Future<Output = Result<HandlerRegistration>>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let source = HandlerSource::of::<F>();
+        let registration = normalize_event_name(&event.into())
+            .map(|event| self.register(event, handler, extra, source));
+        async move { Ok(registration?.await) }
+    }
+
+    /// Register an event handler without checking that the event name is known
+    ///
+    /// An escape hatch for event types newer than the ones known to octocrab.
+    /// The name is still trimmed and lowercased.
+    ///
+    /// If `event` is empty, the error is logged and the handler is not
+    /// registered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{webhook::WebhookServer, Context};
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(mut server: WebhookServer) {
+    /// server
+    ///     .on_unchecked(
+    ///         "brand_new_event",
+    ///         |context: Context, _extra: Arc<()>| async move {
+    ///             println!("Payload: {}", context.payload());
+    ///             Ok(())
+    ///         },
+    ///         Arc::new(()),
+    ///     )
+    ///     .await;
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn on_unchecked<F, Fut, E>(
+        &mut self,
+        event: impl Into<String>,
+        handler: F,
+        extra: Arc<E>,
+    ) -> impl Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let source = HandlerSource::of::<F>();
+        let registration = normalize_unchecked(&event.into())
+            .map(|event| self.register(event, handler, extra, source));
+        registration_or_log(registration, source)
+    }
 
+    /// Register an event handler under an already normalized event name, in
+    /// the current handler group
+    ///
+    /// Used by wrappers that adapt a handler before registering it, so it is
+    /// still reported under the `source` of the original handler.
+    pub(crate) fn register<F, Fut, E>(
+        &mut self,
+        event: WebhookEventKind,
+        handler: F,
+        extra: Arc<E>,
+        source: HandlerSource,
+    ) -> impl Future<Output = HandlerRegistration>
+    where
+        F: Fn(Context, Arc<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
         self.state
-            .handlers
-            .write()
-            .await
-            .entry(event)
-            .or_default()
-            .push(boxed_handler);
+            .dispatcher
+            .register(event, self.group.clone(), handler, extra, source)
+    }
+
+    /// Set the handler group that subsequently registered handlers belong to
+    ///
+    /// Used by [`HandlerGroup`](crate::groups::HandlerGroup); pass `None` to
+    /// register handlers outside of any group again.
+    pub fn set_handler_group(&mut self, group: Option<Arc<GroupFilter>>) {
+        self.group = group;
+    }
+
+    /// Set the configuration used when dispatching events to handlers
+    ///
+    /// Applies to all deliveries received after the call, including those of
+    /// an already running server.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use octofer::{config::DispatchConfig, webhook::WebhookServer};
+    ///
+    /// # async fn example() {
+    /// let server = WebhookServer::new_default();
+    /// server
+    ///     .set_dispatch_config(DispatchConfig {
+    ///         api_budget: Some(50),
+    ///         ..Default::default()
+    ///     })
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn set_dispatch_config(&self, config: DispatchConfig) {
+        self.state.dispatcher.set_config(config).await;
+    }
+
+    /// Register a hook invoked with the report of every webhook delivery
+    ///
+    /// See the [`report`](crate::webhook::report) module for details.
+    pub async fn on_delivery_complete<F, Fut>(&self, hook: F)
+    where
+        F: Fn(DeliveryReport) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.state.delivery_hooks.add(hook).await;
+    }
+
+    /// Forward successfully handled deliveries of `events` to `target_url`
+    ///
+    /// Deliveries are signed with `signing_secret`. See the
+    /// [`forward`](crate::webhook::forward) module for details.
+    ///
+    /// # Errors
+    ///
+    /// See [`ForwardTarget::new`].
+    pub fn forward_events(
+        &self,
+        events: &[&str],
+        target_url: &str,
+        signing_secret: &str,
+    ) -> Result<()> {
+        let target = ForwardTarget::new(events, target_url, signing_secret)?;
+        self.state.forwarder.add(target);
+        Ok(())
+    }
+
+    /// Create the clients of `installations` before serving requests, and
+    /// keep their tokens fresh while serving
+    ///
+    /// Defaults to
+    /// [`GitHubConfig::prewarm_installations`](crate::config::GitHubConfig::prewarm_installations).
+    /// Takes effect the next time the server is bound. Failures to warm an
+    /// installation are logged and do not prevent the server from starting.
+    pub fn set_prewarm(&mut self, installations: Option<PrewarmInstallations>) {
+        self.prewarm = installations;
+    }
+
+    /// Redeliver the webhook deliveries that failed recently, e.g. while the
+    /// app was down, once the server serves requests
+    ///
+    /// Defaults to
+    /// [`GitHubConfig::redelivery`](crate::config::GitHubConfig::redelivery).
+    /// Takes effect the next time the server is bound. Redeliveries are
+    /// requested in a background task, see
+    /// [`GitHubClient::redeliver_failed`]; failures are logged.
+    pub fn set_redelivery(&mut self, options: Option<RedeliveryOptions>) {
+        self.redelivery = options;
+    }
+
+    /// Set the keep-alive, HTTP/2, compression and listener settings of the
+    /// server's connections
+    ///
+    /// Compression takes effect for the next request, the other settings the
+    /// next time the server is bound. See the
+    /// [`tuning`](crate::webhook::tuning) module.
+    pub fn set_tuning(&mut self, tuning: ServerTuning) {
+        self.compression.set(tuning.compression);
+        self.tuning = tuning;
+    }
+
+    /// Get the connection settings of the server
+    pub fn tuning(&self) -> &ServerTuning {
+        &self.tuning
+    }
+
+    /// Set how failed forwarding requests are retried
+    ///
+    /// Takes effect for the next delivery.
+    pub fn set_forward_retry(&self, retry: ForwardRetry) {
+        self.state.forwarder.set_retry(retry);
+    }
+
+    /// Acknowledge deliveries after `deadline` while their remaining handlers
+    /// keep running, or only once all handlers completed with `None`
+    ///
+    /// Takes effect for the next delivery. See the
+    /// [`deadline`](crate::webhook::deadline) module for details.
+    pub fn set_ack_deadline(&self, deadline: Option<Duration>) {
+        self.state.ack_deadline.set(deadline);
+    }
+
+    /// Write every verified delivery to `archive` once it was processed
+    ///
+    /// Replaces the previous archive. See the [`archive`](crate::archive)
+    /// module for details.
+    pub fn archive_deliveries<A: DeliveryArchive>(&self, archive: A) {
+        self.state.archive.set(archive);
+        info!("Archiving webhook deliveries");
+    }
+
+    /// Write every verified delivery to `dir` as a redacted test fixture
+    ///
+    /// Independent of [`WebhookServer::archive_deliveries`]. See
+    /// [`FixtureRecorder`] for the file layout; meant for development only.
+    pub fn record_fixtures(&self, dir: impl Into<PathBuf>) {
+        let dir = dir.into();
+        info!(
+            "Recording webhook deliveries as fixtures in {}",
+            dir.display()
+        );
+        self.state.fixtures.set(FixtureRecorder::new(dir));
+    }
+
+    /// Get the HMAC configuration used to verify webhook deliveries
+    pub fn hmac_config(&self) -> HmacConfig {
+        self.hmac.get()
+    }
+
+    /// Replace the HMAC configuration used to verify webhook deliveries
+    ///
+    /// Safe to call while the server is running: the next delivery is
+    /// verified with the new secret and header name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, keeping the current configuration, if `config` is not
+    /// safe for the server's host (see [`HmacConfig::validate`]).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use octofer::github::middlewares::HmacConfig;
+    /// use octofer::webhook::WebhookServer;
+    ///
+    /// let server = WebhookServer::new_default();
+    /// server.set_hmac_config(HmacConfig::new(
+    ///     "rotated-secret".to_string(),
+    ///     "X-Hub-Signature-256".to_string(),
+    /// ))
+    /// .unwrap();
+    /// assert_eq!(server.hmac_config().secret.expose(), "rotated-secret");
+    /// ```
+    pub fn set_hmac_config(&self, config: HmacConfig) -> Result<(), InsecureHmacConfig> {
+        config.validate(self.host)?;
+        self.hmac.set(config);
+        info!("Webhook HMAC configuration updated");
+        Ok(())
+    }
+
+    /// Get a clone of the router serving the webhook and health endpoints
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn router(&self) -> Option<Router> {
+        self.router.clone()
+    }
+
+    /// Receive a plain, non-App webhook at `path`, verified with `secret`
+    ///
+    /// Deliveries go to the handlers of this server, without an installation.
+    /// See the [`plain`](crate::webhook::plain) module. Middleware added with
+    /// [`WebhookServer::add_middleware`] afterwards applies to the route too,
+    /// middleware added before does not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` does not start with `/` or is already
+    /// served, or `secret` is not safe for the server's host (see
+    /// [`HmacConfig::validate`]).
+    pub fn add_plain_webhook(&mut self, path: &str, secret: &str) -> Result<()> {
+        let dispatcher = self.state.dispatcher.clone();
+        self.add_plain_webhook_with_dispatcher(path, secret, dispatcher)
+    }
+
+    /// Receive a plain, non-App webhook at `path`, verified with `secret`,
+    /// and dispatch its deliveries with `dispatcher`
+    ///
+    /// Like [`WebhookServer::add_plain_webhook`], but with a separate handler
+    /// registry. Use [`Dispatcher::new`] with the server's
+    /// [GitHub client](WebhookServer::github_client) to give its handlers
+    /// the app client.
+    ///
+    /// # Errors
+    ///
+    /// See [`WebhookServer::add_plain_webhook`].
+    pub fn add_plain_webhook_with_dispatcher(
+        &mut self,
+        path: &str,
+        secret: &str,
+        dispatcher: Dispatcher,
+    ) -> Result<()> {
+        if !path.starts_with('/') {
+            return Err(anyhow::anyhow!(
+                "Webhook path must start with '/': {}",
+                path
+            ));
+        }
+        let served = ["/webhook", "/health", INFO_PATH];
+        if served.contains(&path) || self.plain_webhooks.iter().any(|p| p == path) {
+            return Err(anyhow::anyhow!("Path {} is already served", path));
+        }
+        let hmac_config = HmacConfig::new(secret.to_string(), WEBHOOK_HEADER_NAME.to_string());
+        hmac_config.validate(self.host)?;
+        let Some(router) = self.router.take() else {
+            return Err(anyhow::anyhow!("Router not initialized"));
+        };
+
+        let state = AppState {
+            dispatcher,
+            source: WebhookSource::Plain {
+                path: path.to_string(),
+            },
+            ..self.state.clone()
+        };
+        let route = Router::new()
+            .route(
+                path,
+                post(handlers::handle_webhook).layer(middleware::from_fn_with_state(
+                    SharedHmacConfig::new(hmac_config),
+                    verify_hmac_middleware,
+                )),
+            )
+            .layer(trace_layer())
+            .with_state(state);
+        self.router = Some(router.merge(route));
+        self.plain_webhooks.push(path.to_string());
+        info!("Receiving plain webhook deliveries at {}", path);
+        Ok(())
+    }
+
+    /// Serve `method_router` at `path`, with `state`
+    ///
+    /// See the [`routes`](crate::webhook::routes) module for how custom
+    /// routes relate to the server's own routes and layers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` does not start with `/` or is served by
+    /// the server itself.
+    pub fn route(
+        &mut self,
+        path: &str,
+        method_router: MethodRouter<RouteState>,
+        state: RouteState,
+    ) -> Result<()> {
+        self.check_custom_path(path, false)?;
+        let route = Router::new().route(path, method_router);
+        self.merge_custom(route, state)?;
+        info!("Serving custom route {}", path);
+        Ok(())
+    }
+
+    /// Serve the routes of `router` under `path`, with `state`
+    ///
+    /// A route `/installations` of `router` nested at `/admin` is served at
+    /// `/admin/installations`. See the [`routes`](crate::webhook::routes)
+    /// module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` does not start with `/`, is `/`, or
+    /// contains a path served by the server itself.
+    pub fn nest(
+        &mut self,
+        path: &str,
+        router: Router<RouteState>,
+        state: RouteState,
+    ) -> Result<()> {
+        if path.trim_end_matches('/').is_empty() {
+            return Err(anyhow::anyhow!("Cannot nest routes at the root path"));
+        }
+        self.check_custom_path(path, true)?;
+        self.merge_custom(Router::new().nest(path, router), state)?;
+        info!("Serving custom routes under {}", path);
+        Ok(())
+    }
+
+    /// Check that custom routes at, or `nested` under, `path` do not shadow
+    /// the server's routes
+    fn check_custom_path(&self, path: &str, nested: bool) -> Result<()> {
+        if !path.starts_with('/') {
+            return Err(anyhow::anyhow!("Route path must start with '/': {}", path));
+        }
+        let prefix = path.trim_end_matches('/');
+        let served = ["/webhook", "/health", INFO_PATH]
+            .into_iter()
+            .chain(self.plain_webhooks.iter().map(String::as_str));
+        for served in served {
+            let shadowed = served == path
+                || (nested && (served == prefix || served.starts_with(&format!("{}/", prefix))));
+            if shadowed {
+                return Err(anyhow::anyhow!("Path {} is served by the server", served));
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_custom(&mut self, router: Router<RouteState>, state: RouteState) -> Result<()> {
+        let Some(current) = self.router.take() else {
+            return Err(anyhow::anyhow!("Router not initialized"));
+        };
+        self.router = Some(current.merge(router.layer(trace_layer()).with_state(state)));
+        Ok(())
     }
 
     pub fn add_middleware<T>(&mut self, layer: T) -> Result<()>
@@ -358,7 +1046,7 @@ impl WebhookServer {
     ///     config.server.host,
     ///     config.server.port,
     ///     config.github,
-    ///     &config.webhook.secret,
+    ///     config.webhook.secret.expose(),
     ///     &config.webhook.header_name,
     /// ).await?;
     ///
@@ -372,7 +1060,47 @@ impl WebhookServer {
     /// # }
     /// ```
     pub fn github_client(&self) -> Option<&Arc<GitHubClient>> {
-        self.state.github_client.as_ref()
+        self.state.dispatcher.github_client()
+    }
+
+    /// Describe the registered handlers, dispatch configuration and handler
+    /// groups of this server
+    ///
+    /// See the [`info`](crate::webhook::info) module.
+    pub async fn registration_summary(&self) -> RegistrationSummary {
+        info::summarize(&self.state).await
+    }
+
+    /// Compare the app's webhook with the registered handlers
+    ///
+    /// The webhook's settings are requested from the API if a GitHub client
+    /// is available, and otherwise taken from the latest `ping` delivery.
+    /// Differences are logged as warnings. See the
+    /// [`drift`](crate::webhook::drift) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, or there is no GitHub client and
+    /// no `ping` delivery was received yet.
+    pub async fn check_subscription_drift(&self) -> Result<SubscriptionDrift> {
+        self.state.hook.check(&self.state.dispatcher).await
+    }
+
+    /// Serve, or stop serving, the registration summary at
+    /// [`INFO_PATH`](crate::webhook::info::INFO_PATH)
+    pub fn set_info_endpoint(&self, enabled: bool) {
+        self.state.info.set_enabled(enabled);
+        if enabled {
+            info!("Serving the registration summary at {}", INFO_PATH);
+        }
+    }
+
+    /// Get the dispatcher running registered handlers for incoming events
+    ///
+    /// The dispatcher shares its handler registry with this server, so it can
+    /// be used to process deliveries that arrive through other channels.
+    pub fn dispatcher(&self) -> &Dispatcher {
+        &self.state.dispatcher
     }
 }
 
@@ -392,34 +1120,952 @@ impl WebhookServer {
 /// # Endpoints
 ///
 /// - `GET /health` - Health check endpoint (no authentication required)
+/// - `GET /_octofer/info` - Registration summary (`404` unless enabled)
 /// - `POST /webhook` - Webhook endpoint (requires valid HMAC signature)
-fn create_router(state: AppState, hmac_config: HmacConfig) -> Router {
+///
+/// The health and info endpoints compress their responses while
+/// `compression` is on.
+fn create_router(
+    state: AppState,
+    hmac_config: SharedHmacConfig,
+    compression: &CompressionSwitch,
+) -> Router {
     let cors_layer = tower_http::cors::CorsLayer::new()
         .allow_origin(tower_http::cors::Any)
         .allow_methods(tower_http::cors::Any)
         .allow_headers(tower_http::cors::Any);
 
-    let trace_layer = TraceLayer::new_for_http()
+    Router::new()
+        .route(
+            "/health",
+            get(handlers::handle_health).layer(compression.layer()),
+        )
+        .route(
+            INFO_PATH,
+            get(handlers::handle_info).layer(compression.layer()),
+        )
+        .route(
+            "/webhook",
+            post(handlers::handle_webhook).layer(middleware::from_fn_with_state(
+                hmac_config,
+                verify_hmac_middleware,
+            )),
+        )
+        .layer(trace_layer())
+        .layer(cors_layer)
+        .with_state(state)
+}
+
+/// Layer logging all requests and responses
+fn trace_layer() -> TraceLayer<SharedClassifier<ServerErrorsAsFailures>> {
+    TraceLayer::new_for_http()
         .make_span_with(DefaultMakeSpan::new().include_headers(true))
         .on_request(DefaultOnRequest::new().level(Level::INFO))
         .on_response(
             DefaultOnResponse::new()
                 .level(Level::INFO)
                 .latency_unit(tower_http::LatencyUnit::Micros),
+        )
+}
+
+/// Webhook server bound to its address, see [`WebhookServer::bind`]
+pub struct BoundServer {
+    listener: tokio::net::TcpListener,
+    local_addr: SocketAddr,
+    router: Router,
+    dispatcher: Dispatcher,
+    tasks: TaskSupervisor,
+    prewarm: Option<PrewarmInstallations>,
+    redelivery: Option<RedeliveryOptions>,
+    tuning: ServerTuning,
+}
+
+impl BoundServer {
+    /// Get the address the server is bound to, with the port picked by the
+    /// operating system if port `0` was requested
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Serve requests until the server is stopped or an error occurs
+    ///
+    /// Installation clients to pre-warm (see [`WebhookServer::set_prewarm`])
+    /// are created first, and failed deliveries to redeliver (see
+    /// [`WebhookServer::set_redelivery`]) are redelivered in the background
+    /// while serving. Both run as [supervised tasks](crate::tasks).
+    /// [Event streams](crate::stream) end once serving stops. See
+    /// [`WebhookServer::start`].
+    pub async fn serve(self) -> Result<()> {
+        // Event streams end when serving stops, even if this future is dropped
+        let _streams = self.dispatcher.event_streams().close_on_drop();
+        if let (Some(installations), Some(client)) =
+            (&self.prewarm, self.dispatcher.github_client())
+        {
+            prewarm(client, installations).await;
+            spawn_keep_warm(&self.tasks, client.clone(), installations.clone());
+        }
+
+        info!("Webhook server started on {}", self.local_addr);
+        self.dispatcher.log_handlers().await;
+
+        // Redelivered events reach the listener, which already accepts
+        // connections
+        if let (Some(options), Some(client)) = (self.redelivery, self.dispatcher.github_client()) {
+            spawn_redelivery(&self.tasks, client.clone(), options);
+        }
+
+        tuning::serve(self.listener, self.router, self.tuning).await;
+        self.tasks.stop(KEEP_WARM_TASK);
+        self.tasks.stop(REDELIVERY_TASK);
+        Ok(())
+    }
+}
+
+/// Create the clients of `installations`, logging the outcome
+async fn prewarm(client: &GitHubClient, installations: &PrewarmInstallations) {
+    match client.prewarm_installations(installations).await {
+        Ok(summary) => info!(
+            "Pre-warmed {} installation client(s), {} suspended, {} failed",
+            summary.succeeded,
+            summary.skipped,
+            summary.failures.len()
+        ),
+        Err(e) => warn!("Failed to list installations to pre-warm: {}", e),
+    }
+}
+
+/// Keep the clients of `installations` warm in a supervised task
+fn spawn_keep_warm(
+    tasks: &TaskSupervisor,
+    client: Arc<GitHubClient>,
+    installations: PrewarmInstallations,
+) {
+    tasks.spawn(
+        KEEP_WARM_TASK,
+        TaskOptions::default(),
+        move |mut shutdown| {
+            let (client, installations) = (client.clone(), installations.clone());
+            async move {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = client.refresh_warm(&installations) => {}
+                }
+                Ok(())
+            }
+        },
+    );
+}
+
+/// Redeliver the failed deliveries selected by `options` once, in a
+/// supervised task
+fn spawn_redelivery(tasks: &TaskSupervisor, client: Arc<GitHubClient>, options: RedeliveryOptions) {
+    let once = TaskOptions {
+        restart: RestartPolicy::Never,
+        ..TaskOptions::default()
+    };
+    tasks.spawn(REDELIVERY_TASK, once, move |mut shutdown| {
+        let (client, options) = (client.clone(), options.clone());
+        async move {
+            tokio::select! {
+                _ = shutdown.cancelled() => {}
+                _ = redeliver(client, options) => {}
+            }
+            Ok(())
+        }
+    });
+}
+
+/// Redeliver the failed deliveries selected by `options`, logging the outcome
+async fn redeliver(client: Arc<GitHubClient>, options: RedeliveryOptions) {
+    match client.redeliver_failed(&options).await {
+        Ok(summary) => info!(
+            "Redelivered {} failed delivery(ies), {} left out, {} failed",
+            summary.redelivered,
+            summary.skipped,
+            summary.failures.len()
+        ),
+        Err(e) => warn!("Failed to list deliveries to redeliver: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WEBHOOK_HEADER_NAME;
+    use crate::github::middlewares::GITHUB_EVENT_HEADER;
+    use crate::testing::sign;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    async fn deliver(server: &WebhookServer, secret: &str) -> StatusCode {
+        let body = serde_json::to_vec(&serde_json::json!({
+            "zen": "Keep it logically awesome.",
+            "hook_id": 1,
+            "hook": {
+                "type": "App",
+                "id": 1,
+                "name": "web",
+                "active": true,
+                "events": ["issues"],
+                "config": {
+                    "content_type": "json",
+                    "secret": "********",
+                    "url": "https://example.com/webhook",
+                },
+            },
+        }))
+        .unwrap();
+        let request = Request::post("/webhook")
+            .header(GITHUB_EVENT_HEADER, "ping")
+            .header(WEBHOOK_HEADER_NAME, sign(secret, &body))
+            .body(Body::from(body))
+            .unwrap();
+
+        let router = server.router().unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_rotated_secret_is_honored_by_next_request() {
+        let server = WebhookServer::new_default();
+        let old_secret = server.hmac_config().secret.expose().to_string();
+        assert_eq!(deliver(&server, &old_secret).await, StatusCode::OK);
+
+        server
+            .set_hmac_config(HmacConfig::new(
+                "rotated-secret".to_string(),
+                WEBHOOK_HEADER_NAME.to_string(),
+            ))
+            .unwrap();
+
+        assert_eq!(deliver(&server, "rotated-secret").await, StatusCode::OK);
+        assert_eq!(
+            deliver(&server, &old_secret).await,
+            StatusCode::UNAUTHORIZED
         );
+    }
 
-    Router::new()
-        .route("/health", get(handlers::handle_health))
-        .route(
-            "/webhook",
-            post(handlers::handle_webhook)
-                .layer(middleware::from_fn_with_state(
-                    hmac_config,
-                    verify_hmac_middleware,
-                ))
-                .layer(middleware::from_fn(github_event_middleware)),
+    #[tokio::test]
+    async fn test_disabled_hmac_only_on_loopback() {
+        let mut server = WebhookServer::new_default();
+        let disabled = HmacConfig {
+            disabled: true,
+            ..server.hmac_config()
+        };
+        server.set_hmac_config(disabled.clone()).unwrap();
+        assert_eq!(deliver(&server, "any-secret").await, StatusCode::OK);
+
+        // Moving to a public address after disabling verification
+        server.host = Ipv4Addr::UNSPECIFIED;
+        let err = server.start().await.unwrap_err();
+        assert!(err.downcast_ref::<InsecureHmacConfig>().is_some());
+        assert_eq!(
+            server.set_hmac_config(disabled.clone()),
+            Err(InsecureHmacConfig::VerificationDisabled {
+                host: Ipv4Addr::UNSPECIFIED
+            })
+        );
+
+        let err = WebhookServer::with_hmac_config(
+            Ipv4Addr::UNSPECIFIED,
+            DEFAULT_PORT,
+            GitHubConfig::default(),
+            disabled,
         )
-        .layer(trace_layer)
-        .layer(cors_layer)
-        .with_state(state)
+        .await
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("OCTOFER_DISABLE_HMAC"));
+    }
+
+    #[tokio::test]
+    async fn test_default_secret_requires_opt_in_on_public_host() {
+        let err = WebhookServer::new(
+            Ipv4Addr::UNSPECIFIED,
+            DEFAULT_PORT,
+            GitHubConfig::default(),
+            crate::config::WEBHOOK_SECRET,
+            WEBHOOK_HEADER_NAME,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.downcast_ref::<InsecureHmacConfig>(),
+            Some(&InsecureHmacConfig::DefaultSecret {
+                host: Ipv4Addr::UNSPECIFIED
+            })
+        );
+
+        // With the opt-in, validation passes and client creation is reached
+        let err = WebhookServer::with_hmac_config(
+            Ipv4Addr::UNSPECIFIED,
+            DEFAULT_PORT,
+            GitHubConfig::default(),
+            HmacConfig {
+                allow_default_secret: true,
+                ..HmacConfig::default()
+            },
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(err.downcast_ref::<InsecureHmacConfig>().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_with_invalid_key_applies_nothing() {
+        use crate::testing::MockGitHub;
+
+        let mock = MockGitHub::start(Router::new()).await;
+        let mut config = crate::Config::default();
+        config.webhook.secret = "old-secret".into();
+        let app = crate::Octofer::with_github_client(config.clone(), mock.client())
+            .await
+            .unwrap();
+
+        let mut new = config;
+        new.webhook.secret = "new-secret".into();
+        new.dispatch.api_budget = Some(7);
+        new.github.app_id += 1;
+        new.github.private_key = b"not a key".to_vec().into();
+        assert!(app.reload_config(&new).await.is_err());
+
+        assert_eq!(app.server.hmac_config().secret.expose(), "old-secret");
+        assert_eq!(deliver(&app.server, "old-secret").await, StatusCode::OK);
+        assert_eq!(
+            app.server.dispatcher().config().await.api_budget,
+            crate::config::DispatchConfig::default().api_budget
+        );
+        assert!(app
+            .server
+            .github_client()
+            .unwrap()
+            .uses_credentials(&mock.auth()));
+    }
+
+    #[tokio::test]
+    async fn test_octofer_without_github_verifies_and_parses() {
+        let mut config = crate::Config::default();
+        config.github.disabled = true;
+        config.server.port = 3001;
+        config.webhook.secret = "router-secret".into();
+        let mut app = crate::Octofer::new(config).await.unwrap();
+        assert!(app.server.github_client().is_none());
+        assert_eq!(app.server.port, 3001);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        app.server
+            .on(
+                "ping",
+                move |context: Context, _extra: Arc<()>| {
+                    let tx = tx.clone();
+                    async move {
+                        tx.send((context.kind(), context.github().is_none()))?;
+                        Ok(())
+                    }
+                },
+                Arc::new(()),
+            )
+            .await;
+
+        assert_eq!(deliver(&app.server, "router-secret").await, StatusCode::OK);
+        assert_eq!(rx.recv().await, Some(("ping".into(), true)));
+        assert_eq!(
+            deliver(&app.server, "wrong-secret").await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_injected_client_serves_deliveries() {
+        use crate::github::transport::ClientOptions;
+        use crate::testing::{issues_payload, live::LiveTestServer, MockGitHub, TEST_APP_SLUG};
+        use axum::{routing::get, Json, Router};
+        use serde_json::json;
+
+        let mock = MockGitHub::start(
+            Router::new()
+                .route(
+                    "/app",
+                    get(|| async { Json(json!({ "id": 1, "slug": TEST_APP_SLUG })) }),
+                )
+                .route(
+                    "/repos/octofer/app",
+                    get(|| async { Json(json!({ "full_name": "octofer/app" })) }),
+                ),
+        )
+        .await;
+        let app_client = octocrab::Octocrab::builder()
+            .base_uri(mock.uri())
+            .unwrap()
+            .add_header("x-org-policy".parse().unwrap(), "enforced".to_string())
+            .app(1.into(), mock.auth().encoding_key().unwrap())
+            .build()
+            .unwrap();
+        let options = ClientOptions {
+            base_uri: mock.uri().parse().unwrap(),
+            user_agent: "injected-test".to_string(),
+            ..Default::default()
+        };
+        let client = GitHubClient::from_octocrab(app_client, options)
+            .await
+            .unwrap();
+        assert_eq!(client.app_id(), 1);
+        assert_eq!(client.app_slug(), Some(TEST_APP_SLUG));
+
+        let app = crate::Octofer::with_github_client(crate::Config::default(), client)
+            .await
+            .unwrap();
+        let mut server = LiveTestServer::with_server(app.server);
+        server
+            .on(
+                "issues",
+                |context: Context, _extra: Arc<()>| async move {
+                    let client = context.require_installation_client().await?;
+                    let _: serde_json::Value =
+                        client.get("/repos/octofer/app", None::<&()>).await?;
+                    Ok(())
+                },
+                Arc::new(()),
+            )
+            .await;
+
+        let mut payload = issues_payload("opened", 1);
+        payload["installation"] = json!({ "id": 1, "node_id": "I_1" });
+        let response = server
+            .send_webhook("issues", &payload, &server.secret())
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let requests = mock.requests();
+        let request = requests
+            .iter()
+            .find(|request| request.path == "/repos/octofer/app")
+            .expect("the handler's request reached the mock");
+        assert_eq!(request.headers["x-org-policy"], "enforced");
+        assert_eq!(request.headers["authorization"], "Bearer ghs_test");
+        assert!(request
+            .headers
+            .get_all("user-agent")
+            .iter()
+            .any(|value| value == "injected-test"));
+        assert_eq!(mock.token_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_info_endpoint_is_opt_in() {
+        let mut server = WebhookServer::new_default();
+        server
+            .set_hmac_config(HmacConfig::new(
+                "super-secret-value".to_string(),
+                WEBHOOK_HEADER_NAME.to_string(),
+            ))
+            .unwrap();
+        server
+            .on("ping", |_context, _extra| async { Ok(()) }, Arc::new(()))
+            .await
+            .named("pinger");
+
+        let get_info = || async {
+            let request = Request::get(INFO_PATH).body(Body::empty()).unwrap();
+            let response = server.router().unwrap().oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, body)
+        };
+
+        assert_eq!(get_info().await.0, StatusCode::NOT_FOUND);
+
+        server.set_info_endpoint(true);
+        let (status, body) = get_info().await;
+        assert_eq!(status, StatusCode::OK);
+        let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["events"][0]["event"], "ping");
+        assert_eq!(info["events"][0]["handlers"], 1);
+        assert_eq!(info["events"][0]["names"][0], "pinger");
+        assert!(!String::from_utf8_lossy(&body).contains("super-secret-value"));
+
+        let summary = server.registration_summary().await;
+        assert_eq!(summary.events[0].names, ["pinger"]);
+        assert_eq!(summary.drift, None);
+
+        // A ping reveals the webhook's settings
+        let secret = server.hmac_config().secret.expose().to_string();
+        assert_eq!(deliver(&server, &secret).await, StatusCode::OK);
+        let (_, body) = get_info().await;
+        let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(info["drift"]["source"], "ping");
+        assert_eq!(info["drift"]["unsubscribed"], serde_json::json!([]));
+        assert_eq!(info["drift"]["unhandled"], serde_json::json!(["issues"]));
+        assert_eq!(info["drift"]["secret_missing"], false);
+    }
+
+    #[tokio::test]
+    async fn test_installations_are_prewarmed_before_serving() {
+        use hyper_util::client::legacy::Client;
+        use hyper_util::rt::TokioExecutor;
+
+        let mock = crate::testing::MockGitHub::start(Router::new()).await;
+        mock.set_installations(&[1, 2, 3]);
+        let client = Arc::new(mock.client());
+        let mut server =
+            WebhookServer::build(Ipv4Addr::LOCALHOST, 0, Some(client), HmacConfig::default());
+        // Installation 99 does not exist and fails to warm
+        server.set_prewarm(Some(PrewarmInstallations::Ids(vec![1, 2, 3, 99])));
+
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addr();
+        tokio::spawn(bound.serve());
+
+        // The listener accepts connections while the clients are warmed, and
+        // the request is answered once the server serves
+        let client = Client::builder(TokioExecutor::new()).build_http::<axum::body::Body>();
+        let response = client
+            .get(format!("http://{addr}/health").parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(mock.token_requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_rejected_credentials() {
+        let mock = crate::testing::MockGitHub::start(Router::new()).await;
+        mock.fail_token_requests(StatusCode::UNAUTHORIZED, "Bad credentials");
+        let client = Arc::new(mock.client().with_credential_failure_threshold(1));
+        let server = WebhookServer::build(
+            Ipv4Addr::LOCALHOST,
+            0,
+            Some(client.clone()),
+            HmacConfig::default(),
+        );
+        let health = || async {
+            let request = Request::get("/health").body(Body::empty()).unwrap();
+            let router = server.router().unwrap();
+            router.oneshot(request).await.unwrap().status()
+        };
+        assert_eq!(health().await, StatusCode::OK);
+
+        client.installation_client(1).await.unwrap_err();
+        assert_eq!(health().await, StatusCode::SERVICE_UNAVAILABLE);
+
+        mock.accept_token_requests();
+        client.reload_credentials(mock.auth()).await.unwrap();
+        assert_eq!(health().await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_failed_critical_task() {
+        use crate::tasks::TaskStatus;
+
+        let server = WebhookServer::new_default();
+        let health = || async {
+            let request = Request::get("/health").body(Body::empty()).unwrap();
+            let router = server.router().unwrap();
+            router.oneshot(request).await.unwrap().status()
+        };
+        let options = TaskOptions {
+            restart: RestartPolicy::Never,
+            critical: true,
+            max_restarts: None,
+        };
+        server
+            .tasks()
+            .spawn("sync", options, |_| async { anyhow::bail!("sync broke") });
+        while server.tasks().summary()[0].status != TaskStatus::Failed {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(health().await, StatusCode::SERVICE_UNAVAILABLE);
+        let summary = server.registration_summary().await;
+        assert_eq!(summary.tasks[0].name, "sync");
+        assert_eq!(summary.tasks[0].last_error.as_deref(), Some("sync broke"));
+    }
+
+    #[tokio::test]
+    async fn test_bound_server_reports_ephemeral_port() {
+        use http_body_util::Full;
+        use hyper_util::client::legacy::Client;
+        use hyper_util::rt::TokioExecutor;
+
+        let mut server = WebhookServer::new_default();
+        server.port = 0;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        server
+            .on(
+                "ping",
+                move |_context, _extra| {
+                    let tx = tx.clone();
+                    async move {
+                        tx.send(())?;
+                        Ok(())
+                    }
+                },
+                Arc::new(()),
+            )
+            .await;
+        let secret = server.hmac_config().secret.expose().to_string();
+
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addr();
+        assert_eq!(addr.ip(), Ipv4Addr::LOCALHOST);
+        assert_ne!(addr.port(), 0);
+        tokio::spawn(bound.serve());
+
+        let body = br#"{"zen":"Keep it logically awesome.","hook_id":1}"#;
+        let request = Request::post(format!("http://{addr}/webhook"))
+            .header(GITHUB_EVENT_HEADER, "ping")
+            .header(WEBHOOK_HEADER_NAME, sign(&secret, body))
+            .body(Full::new(axum::body::Bytes::from_static(body)))
+            .unwrap();
+        let client = Client::builder(TokioExecutor::new()).build_http();
+        let response = client.request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(rx.try_recv(), Ok(()));
+
+        // The port is taken until the bound server is dropped
+        let mut taken = WebhookServer::new_default();
+        taken.port = addr.port();
+        assert!(taken.bind().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paused_server_rejects_deliveries_until_ready() {
+        let mut server = WebhookServer::new_default();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        server
+            .on(
+                "ping",
+                move |_context, _extra| {
+                    let tx = tx.clone();
+                    async move {
+                        tx.send(())?;
+                        Ok(())
+                    }
+                },
+                Arc::new(()),
+            )
+            .await;
+        let health = || async {
+            let request = Request::get("/health").body(Body::empty()).unwrap();
+            let router = server.router().unwrap();
+            router.oneshot(request).await.unwrap().status()
+        };
+        let secret = server.hmac_config().secret.expose().to_string();
+
+        // The serving future is never polled, so nothing is bound
+        let (readiness, serving) = server.start_paused();
+        drop(serving);
+        assert!(!server.readiness().is_ready());
+        assert_eq!(health().await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            deliver(&server, &secret).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert!(rx.try_recv().is_err());
+
+        readiness.ready();
+        assert_eq!(health().await, StatusCode::OK);
+        assert_eq!(deliver(&server, &secret).await, StatusCode::OK);
+        assert_eq!(rx.try_recv(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_delivery_hooks_receive_reports() {
+        let mut server = WebhookServer::new_default();
+        server
+            .on("ping", |_context, _extra| async { Ok(()) }, Arc::new(()))
+            .await;
+        server
+            .on(
+                "ping",
+                |_context, _extra| async { Err(anyhow::anyhow!("boom")) },
+                Arc::new(()),
+            )
+            .await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        server
+            .on_delivery_complete(move |report| {
+                let tx = tx.clone();
+                async move {
+                    tx.send(report)?;
+                    Ok(())
+                }
+            })
+            .await;
+        server
+            .on_delivery_complete(|_report| async { Err(anyhow::anyhow!("hook failed")) })
+            .await;
+
+        let secret = server.hmac_config().secret.expose().to_string();
+        assert_eq!(
+            deliver(&server, &secret).await,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+
+        let report = rx.recv().await.unwrap();
+        assert_eq!(report.event, "ping");
+        assert_eq!(report.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(report.handlers.len(), 2);
+        assert!(report.handlers[0].is_success());
+        assert_eq!(report.handlers[1].index, 1);
+        assert_eq!(report.handlers[1].error.as_deref(), Some("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_error_policies_decide_the_status() {
+        use crate::core::ErrorPolicy;
+
+        let fail = |_context: Context, _extra: Arc<()>| async { Err(anyhow::anyhow!("boom")) };
+        let succeed = |_context: Context, _extra: Arc<()>| async { Ok(()) };
+
+        let mut server = WebhookServer::new_default();
+        let secret = server.hmac_config().secret.expose().to_string();
+        server
+            .on("ping", fail, Arc::new(()))
+            .await
+            .error_policy(ErrorPolicy::Ignore);
+        server.on("ping", succeed, Arc::new(())).await;
+        assert_eq!(deliver(&server, &secret).await, StatusCode::OK);
+
+        server.on("ping", fail, Arc::new(())).await;
+        assert_eq!(
+            deliver(&server, &secret).await,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_deliveries_are_acknowledged_at_the_deadline() {
+        let mut server = WebhookServer::new_default();
+        let secret = server.hmac_config().secret.expose().to_string();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        server
+            .on_delivery_complete(move |report| {
+                let tx = tx.clone();
+                async move {
+                    tx.send(report)?;
+                    Ok(())
+                }
+            })
+            .await;
+        for delay in [0, 200] {
+            server
+                .on(
+                    "ping",
+                    move |_context: Context, _extra: Arc<()>| async move {
+                        tokio::time::sleep(Duration::from_millis(delay)).await;
+                        Ok(())
+                    },
+                    Arc::new(()),
+                )
+                .await
+                .named(format!("sleep-{delay}"));
+        }
+
+        // Fast deliveries are answered as usual
+        server.set_ack_deadline(Some(Duration::from_secs(5)));
+        assert_eq!(deliver(&server, &secret).await, StatusCode::OK);
+        assert_eq!(rx.recv().await.unwrap().acknowledged_handlers, None);
+
+        server.set_ack_deadline(Some(Duration::from_millis(50)));
+        assert_eq!(deliver(&server, &secret).await, StatusCode::ACCEPTED);
+        let report = rx.recv().await.unwrap();
+        assert_eq!(report.status, StatusCode::ACCEPTED);
+        assert_eq!(report.acknowledged_handlers, Some(1));
+        assert_eq!(report.handlers.len(), 2);
+        assert_eq!(report.handlers_after_ack()[0].name, "sleep-200");
+    }
+
+    #[tokio::test]
+    async fn test_forwarding_failures_are_reported_not_returned() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver = Router::new().route(
+            "/hook",
+            post(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+        );
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let server = WebhookServer::new_default();
+        server
+            .forward_events(&["ping"], &format!("http://{addr}/hook"), "s3cr3t")
+            .unwrap();
+        server.set_forward_retry(ForwardRetry {
+            retries: 1,
+            backoff: std::time::Duration::from_millis(1),
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        server
+            .on_delivery_complete(move |report| {
+                let tx = tx.clone();
+                async move {
+                    tx.send(report)?;
+                    Ok(())
+                }
+            })
+            .await;
+
+        let secret = server.hmac_config().secret.expose().to_string();
+        assert_eq!(deliver(&server, &secret).await, StatusCode::OK);
+
+        let report = rx.recv().await.unwrap();
+        assert_eq!(report.status, StatusCode::OK);
+        assert_eq!(report.forwards.len(), 1);
+        assert_eq!(report.forwards[0].attempts, 2);
+        assert_eq!(
+            report.forwards[0].status,
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert!(!report.forwards[0].is_success());
+    }
+
+    #[tokio::test]
+    async fn test_custom_routes_share_the_server() {
+        use axum::extract::State;
+
+        let mut server = WebhookServer::new_default();
+        let mut config = crate::Config::default();
+        config.server.port = 3000;
+        let state = RouteState::new(None, config);
+
+        server
+            .route(
+                "/oauth/callback",
+                get(|| async { "signed in" }),
+                state.clone(),
+            )
+            .unwrap();
+        server
+            .nest(
+                "/admin",
+                Router::new().route(
+                    "/port",
+                    get(|State(state): State<RouteState>| async move {
+                        format!("{}:{}", state.config.server.port, state.github.is_none())
+                    }),
+                ),
+                state.clone(),
+            )
+            .unwrap();
+
+        let empty = || get(|| async { "" });
+        assert!(server.route("/health", empty(), state.clone()).is_err());
+        assert!(server.route("relative", empty(), state.clone()).is_err());
+        assert!(server.nest("/", Router::new(), state.clone()).is_err());
+        assert!(server.nest("/_octofer", Router::new(), state).is_err());
+
+        let get_body = |path: &'static str| {
+            let router = server.router().unwrap();
+            async move {
+                let response = router
+                    .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+        assert_eq!(get_body("/oauth/callback").await, "signed in");
+        assert_eq!(get_body("/admin/port").await, "3000:true");
+
+        // The built-in routes are unaffected
+        let secret = server.hmac_config().secret.expose().to_string();
+        assert_eq!(deliver(&server, &secret).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_plain_webhooks_are_routed_with_their_own_secret() {
+        let mut server = WebhookServer::new_default();
+        let app_secret = server.hmac_config().secret.expose().to_string();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        server
+            .on(
+                "issues",
+                |context: Context, tx: Arc<tokio::sync::mpsc::UnboundedSender<_>>| async move {
+                    tx.send((context.source().clone(), context.installation_id()))?;
+                    Ok(())
+                },
+                Arc::new(tx),
+            )
+            .await;
+
+        let audit = Dispatcher::new(None);
+        let (audit_tx, mut audit_rx) = tokio::sync::mpsc::unbounded_channel();
+        audit
+            .on(
+                "issues",
+                |context: Context, tx: Arc<tokio::sync::mpsc::UnboundedSender<_>>| async move {
+                    tx.send(context.source().clone())?;
+                    Ok(())
+                },
+                Arc::new(audit_tx),
+            )
+            .await;
+
+        server
+            .add_plain_webhook("/webhooks/org", "org-secret")
+            .unwrap();
+        server
+            .add_plain_webhook_with_dispatcher("/webhooks/audit", "audit-secret", audit)
+            .unwrap();
+        assert!(server.add_plain_webhook("/webhooks/org", "other").is_err());
+        assert!(server.add_plain_webhook("/webhook", "other").is_err());
+        assert!(server
+            .add_plain_webhook("webhooks/relative", "other")
+            .is_err());
+
+        let deliver = |path: &'static str, secret: &str| {
+            let mut payload = crate::testing::issues_payload("opened", 1);
+            payload["installation"] = serde_json::json!({ "id": 1, "node_id": "MDIz" });
+            let body = serde_json::to_vec(&payload).unwrap();
+            let request = Request::post(path)
+                .header(GITHUB_EVENT_HEADER, "issues")
+                .header(WEBHOOK_HEADER_NAME, sign(secret, &body))
+                .body(Body::from(body))
+                .unwrap();
+            let router = server.router().unwrap();
+            async move { router.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(deliver("/webhook", &app_secret).await, StatusCode::OK);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            (
+                WebhookSource::App,
+                Some(crate::testing::TEST_INSTALLATION_ID)
+            )
+        );
+
+        assert_eq!(
+            deliver("/webhooks/org", &app_secret).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(deliver("/webhooks/org", "org-secret").await, StatusCode::OK);
+        let plain = WebhookSource::Plain {
+            path: "/webhooks/org".to_string(),
+        };
+        assert_eq!(rx.try_recv().unwrap(), (plain, None));
+
+        assert_eq!(
+            deliver("/webhooks/audit", "audit-secret").await,
+            StatusCode::OK
+        );
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            audit_rx.try_recv().unwrap(),
+            WebhookSource::Plain {
+                path: "/webhooks/audit".to_string(),
+            }
+        );
+    }
 }
@@ -0,0 +1,434 @@
+//! Connection tuning of the webhook server
+//!
+//! High delivery rates, e.g. during organization-wide migrations, make the
+//! way connections are accepted and kept matter. [`ServerTuning`], set with
+//! [`ServerConfig::tuning`](crate::config::ServerConfig::tuning) or
+//! [`WebhookServer::set_tuning`](crate::webhook::WebhookServer::set_tuning),
+//! controls:
+//!
+//! - how long an idle HTTP/1 connection is kept open, and after how many
+//!   requests it is closed, so load balancers can rebalance long-lived
+//!   connections;
+//! - whether HTTP/2 without TLS (h2c, with prior knowledge) is accepted next
+//!   to HTTP/1, for proxies terminating TLS upstream;
+//! - whether the health and registration summary endpoints compress their
+//!   responses with gzip; webhook responses are too small to benefit and are
+//!   never compressed, nor are responses under 32 bytes;
+//! - the listen backlog and `TCP_NODELAY` of the listener's connections.
+//!
+//! The defaults keep the behavior of a server without tuning: HTTP/1 only,
+//! no idle timeout or request limit, no compression, a backlog of
+//! [`DEFAULT_TCP_BACKLOG`] and Nagle's algorithm enabled.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use octofer::config::ServerConfig;
+//! use octofer::webhook::tuning::ServerTuning;
+//!
+//! let config = ServerConfig {
+//!     tuning: ServerTuning {
+//!         keep_alive_timeout_secs: Some(75),
+//!         max_requests_per_connection: Some(1_000),
+//!         http2: true,
+//!         tcp_nodelay: true,
+//!         ..ServerTuning::default()
+//!     },
+//!     ..ServerConfig::default()
+//! };
+//! ```
+
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::Request;
+use axum::Router;
+use http::header::CONNECTION;
+use http::{HeaderValue, Version};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tower::ServiceExt;
+use tower_http::compression::predicate::{DefaultPredicate, Predicate};
+use tower_http::compression::CompressionLayer;
+use tracing::{debug, error};
+
+/// Listen backlog of the webhook server's socket, as without tuning
+pub const DEFAULT_TCP_BACKLOG: u32 = 1024;
+
+/// Connection settings of the webhook server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerTuning {
+    /// Seconds an idle HTTP/1 connection waits for its next request before
+    /// it is closed (`None` keeps it open until the client closes it)
+    pub keep_alive_timeout_secs: Option<u64>,
+    /// Requests served on an HTTP/1 connection before it is closed
+    /// (`None` for no limit)
+    pub max_requests_per_connection: Option<u64>,
+    /// Accept HTTP/2 without TLS (h2c) next to HTTP/1
+    pub http2: bool,
+    /// Compress the responses of the health and registration summary
+    /// endpoints with gzip, for clients accepting it
+    pub compression: bool,
+    /// Maximum length of the queue of connections not accepted yet
+    pub tcp_backlog: u32,
+    /// Set `TCP_NODELAY` on accepted connections, disabling Nagle's
+    /// algorithm
+    pub tcp_nodelay: bool,
+}
+
+impl Default for ServerTuning {
+    fn default() -> Self {
+        Self {
+            keep_alive_timeout_secs: None,
+            max_requests_per_connection: None,
+            http2: false,
+            compression: false,
+            tcp_backlog: DEFAULT_TCP_BACKLOG,
+            tcp_nodelay: false,
+        }
+    }
+}
+
+impl ServerTuning {
+    /// Get the idle timeout of HTTP/1 connections, if any
+    pub fn keep_alive_timeout(&self) -> Option<Duration> {
+        self.keep_alive_timeout_secs.map(Duration::from_secs)
+    }
+}
+
+/// Whether the health and info endpoints compress their responses, shared by
+/// the router and the server
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompressionSwitch {
+    enabled: Arc<AtomicBool>,
+}
+
+impl CompressionSwitch {
+    pub(crate) fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Layer compressing responses while the switch is on
+    pub(crate) fn layer(&self) -> CompressionLayer<impl Predicate> {
+        CompressionLayer::new()
+            .no_br()
+            .no_deflate()
+            .no_zstd()
+            .compress_when(self.clone().and(DefaultPredicate::new()))
+    }
+}
+
+impl Predicate for CompressionSwitch {
+    fn should_compress<B>(&self, _response: &http::Response<B>) -> bool
+    where
+        B: http_body::Body,
+    {
+        self.enabled.load(Ordering::Relaxed)
+    }
+}
+
+/// Bind a listener to `addr` with the backlog of `tuning`
+pub(crate) fn bind(addr: SocketAddr, tuning: &ServerTuning) -> io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    // Like `TcpListener::bind`, so restarts do not wait for old connections
+    #[cfg(not(windows))]
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(tuning.tcp_backlog)
+}
+
+/// Serve `router` on the connections of `listener` until accepting fails
+/// unrecoverably
+pub(crate) async fn serve(listener: TcpListener, router: Router, tuning: ServerTuning) {
+    let tuning = Arc::new(tuning);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                handle_accept_error(e).await;
+                continue;
+            }
+        };
+        if tuning.tcp_nodelay {
+            if let Err(e) = stream.set_nodelay(true) {
+                debug!("Failed to set TCP_NODELAY: {}", e);
+            }
+        }
+        tokio::spawn(serve_connection(stream, router.clone(), tuning.clone()));
+    }
+}
+
+async fn serve_connection(stream: TcpStream, router: Router, tuning: Arc<ServerTuning>) {
+    let max_requests = tuning.max_requests_per_connection;
+    let served = Arc::new(AtomicU64::new(0));
+    let service = hyper::service::service_fn(move |request: Request<Incoming>| {
+        let router = router.clone();
+        let last = max_requests
+            .is_some_and(|max| served.fetch_add(1, Ordering::Relaxed) + 1 >= max)
+            && request.version() < Version::HTTP_2;
+        async move {
+            let mut response = router.oneshot(request.map(axum::body::Body::new)).await?;
+            if last {
+                response
+                    .headers_mut()
+                    .insert(CONNECTION, HeaderValue::from_static("close"));
+            }
+            Ok::<_, Infallible>(response)
+        }
+    });
+
+    // The timeout for reading a request's headers starts when the connection
+    // waits for its next request. Without a timeout, no timer is set so
+    // hyper's default timeout stays off.
+    let io = TokioIo::new(stream);
+    let timeout = tuning.keep_alive_timeout();
+    let result = if tuning.http2 {
+        let mut builder = Builder::new(TokioExecutor::new());
+        if let Some(timeout) = timeout {
+            builder
+                .http1()
+                .timer(TokioTimer::new())
+                .header_read_timeout(timeout);
+        }
+        builder.serve_connection_with_upgrades(io, service).await
+    } else {
+        let mut builder = http1::Builder::new();
+        if let Some(timeout) = timeout {
+            builder
+                .timer(TokioTimer::new())
+                .header_read_timeout(timeout);
+        }
+        builder
+            .serve_connection(io, service)
+            .with_upgrades()
+            .await
+            .map_err(Into::into)
+    };
+    if let Err(e) = result {
+        debug!("Connection closed with an error: {}", e);
+    }
+}
+
+/// Wait after a failed accept, unless only the connection failed
+async fn handle_accept_error(e: io::Error) {
+    if matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    ) {
+        return;
+    }
+    // e.g. too many open files: accepting again right away fails too
+    error!("Failed to accept a connection: {}", e);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::middlewares::GITHUB_EVENT_HEADER;
+    use crate::testing::live::LiveTestServer;
+    use crate::testing::sign;
+    use crate::webhook::info::INFO_PATH;
+    use crate::webhook::report::DELIVERY_ID_HEADER;
+    use crate::webhook::WebhookServer;
+    use crate::Context;
+    use http::StatusCode;
+    use http_body_util::{BodyExt, Full};
+    use hyper_util::client::legacy::Client;
+    use std::time::Instant;
+
+    async fn start(server: &mut WebhookServer, tuning: ServerTuning) -> SocketAddr {
+        server.port = 0;
+        server.set_tuning(tuning);
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addr();
+        tokio::spawn(bound.serve());
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_signed_deliveries() {
+        const DELIVERIES: usize = 1000;
+
+        let mut live = LiveTestServer::new();
+        let handled = Arc::new(AtomicU64::new(0));
+        live.server_mut()
+            .on(
+                "ping",
+                |_context: Context, handled: Arc<AtomicU64>| async move {
+                    handled.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                },
+                handled.clone(),
+            )
+            .await;
+        let secret = live.secret();
+        let header = live.server().hmac_config().header_name;
+        let addr = start(
+            live.server_mut(),
+            ServerTuning {
+                keep_alive_timeout_secs: Some(5),
+                max_requests_per_connection: Some(50),
+                tcp_nodelay: true,
+                ..ServerTuning::default()
+            },
+        )
+        .await;
+
+        let client = Client::builder(TokioExecutor::new()).build_http::<Full<bytes::Bytes>>();
+        let body =
+            bytes::Bytes::from_static(br#"{"zen":"Keep it logically awesome.","hook_id":1}"#);
+        let deliveries = (0..DELIVERIES).map(|delivery| {
+            let request = http::Request::post(format!("http://{addr}/webhook"))
+                .header(GITHUB_EVENT_HEADER, "ping")
+                .header(&header, sign(&secret, &body))
+                .header(DELIVERY_ID_HEADER, format!("load-{delivery}"))
+                .header("content-type", "application/json")
+                .body(Full::new(body.clone()))
+                .unwrap();
+            let client = client.clone();
+            tokio::spawn(async move {
+                let started = Instant::now();
+                let status = client.request(request).await.map(|r| r.status());
+                (status, started.elapsed())
+            })
+        });
+
+        let mut latencies = Vec::with_capacity(DELIVERIES);
+        for result in futures::future::join_all(deliveries).await {
+            let (status, latency) = result.unwrap();
+            assert_eq!(status.unwrap(), StatusCode::OK);
+            latencies.push(latency);
+        }
+        latencies.sort();
+        let p99 = latencies[DELIVERIES * 99 / 100];
+        assert!(p99 < Duration::from_secs(5), "p99 latency of {:?}", p99);
+        assert_eq!(handled.load(Ordering::Relaxed), DELIVERIES as u64);
+    }
+
+    #[tokio::test]
+    async fn test_connections_close_after_max_requests() {
+        let mut server = WebhookServer::new_default();
+        let get = |addr: SocketAddr| async move {
+            let client = Client::builder(TokioExecutor::new()).build_http::<Full<bytes::Bytes>>();
+            let uri = format!("http://{addr}/health").parse().unwrap();
+            client.get(uri).await.unwrap()
+        };
+
+        let addr = start(&mut server, ServerTuning::default()).await;
+        assert_eq!(get(addr).await.headers().get(CONNECTION), None);
+
+        let addr = start(
+            &mut server,
+            ServerTuning {
+                max_requests_per_connection: Some(1),
+                ..ServerTuning::default()
+            },
+        )
+        .await;
+        let response = get(addr).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONNECTION], "close");
+    }
+
+    #[tokio::test]
+    async fn test_idle_connections_time_out() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut server = WebhookServer::new_default();
+        let addr = start(
+            &mut server,
+            ServerTuning {
+                keep_alive_timeout_secs: Some(1),
+                ..ServerTuning::default()
+            },
+        )
+        .await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = vec![0; 1024];
+        let read = stream.read(&mut response).await.unwrap();
+        assert!(response[..read].starts_with(b"HTTP/1.1 200 OK"));
+
+        // The idle connection is closed without a response
+        let closed = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut response));
+        assert!(matches!(closed.await, Ok(Ok(0)) | Ok(Err(_))));
+    }
+
+    #[tokio::test]
+    async fn test_compression_only_applies_to_introspection_endpoints() {
+        let mut server = WebhookServer::new_default();
+        server.set_info_endpoint(true);
+        let addr = start(&mut server, ServerTuning::default()).await;
+        let client = Client::builder(TokioExecutor::new()).build_http::<Full<bytes::Bytes>>();
+        let encoding = |method: &str, path: &str| {
+            let request = http::Request::builder()
+                .method(method)
+                .uri(format!("http://{addr}{path}"))
+                .header(http::header::ACCEPT_ENCODING, "gzip")
+                .body(Full::default())
+                .unwrap();
+            let client = client.clone();
+            async move {
+                let response = client.request(request).await.unwrap();
+                let encoding = response
+                    .headers()
+                    .get(http::header::CONTENT_ENCODING)
+                    .cloned();
+                response.into_body().collect().await.unwrap();
+                encoding
+            }
+        };
+
+        assert_eq!(encoding("GET", INFO_PATH).await, None);
+
+        server.set_tuning(ServerTuning {
+            compression: true,
+            ..ServerTuning::default()
+        });
+        assert_eq!(encoding("GET", INFO_PATH).await.unwrap(), "gzip");
+        assert_eq!(encoding("POST", "/webhook").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_h2c_is_opt_in() {
+        let mut server = WebhookServer::new_default();
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<Full<bytes::Bytes>>();
+        let get = |addr: SocketAddr| client.get(format!("http://{addr}/health").parse().unwrap());
+
+        let addr = start(&mut server, ServerTuning::default()).await;
+        get(addr).await.unwrap_err();
+
+        let addr = start(
+            &mut server,
+            ServerTuning {
+                http2: true,
+                ..ServerTuning::default()
+            },
+        )
+        .await;
+        let response = get(addr).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), Version::HTTP_2);
+    }
+}